@@ -180,7 +180,7 @@ impl ApiServer {
         // Load seccomp filters on the API thread.
         // Execution panics if filters cannot be loaded, use --no-seccomp if skipping filters
         // altogether is the desired behaviour.
-        if let Err(e) = seccompiler::apply_filter(seccomp_filter) {
+        if let Err(e) = vmm::seccomp_filters::apply_filter(seccomp_filter) {
             panic!(
                 "Failed to set the requested seccomp filters on the API thread: Error: {:?}",
                 e
@@ -241,7 +241,7 @@ impl ApiServer {
             Ok(ParsedRequest::Sync(vmm_action)) => {
                 self.serve_vmm_action_request(vmm_action, request_processing_start_us)
             }
-            Ok(ParsedRequest::GetMMDS) => self.get_mmds(),
+            Ok(ParsedRequest::GetMMDS(path)) => self.get_mmds(path),
             Ok(ParsedRequest::PatchMMDS(value)) => self.patch_mmds(value),
             Ok(ParsedRequest::PutMMDS(value)) => self.put_mmds(value),
             Ok(ParsedRequest::ShutdownInternal) => {
@@ -296,14 +296,27 @@ impl ApiServer {
         response
     }
 
-    fn get_mmds(&self) -> Response {
-        ApiServer::json_response(
-            StatusCode::OK,
-            self.mmds_info
-                .lock()
-                .expect("Failed to acquire lock on MMDS info")
-                .get_data_str(),
-        )
+    fn get_mmds(&self, path: Option<String>) -> Response {
+        let mmds = self
+            .mmds_info
+            .lock()
+            .expect("Failed to acquire lock on MMDS info");
+        let path = match path {
+            Some(path) => path,
+            None => return ApiServer::json_response(StatusCode::OK, mmds.get_data_str()),
+        };
+        match mmds.get_value(path.clone(), data_store::OutputFormat::Json) {
+            Ok(value) => ApiServer::json_response(StatusCode::OK, value),
+            Err(data_store::Error::NotFound) => ApiServer::json_response(
+                StatusCode::NotFound,
+                ApiServer::json_fault_message(format!("No MMDS data at {}.", path)),
+            ),
+            // The JSON output supports all the values.
+            Err(e) => ApiServer::json_response(
+                StatusCode::BadRequest,
+                ApiServer::json_fault_message(e.to_string()),
+            ),
+        }
     }
 
     fn patch_mmds(&self, value: serde_json::Value) -> Response {
@@ -318,7 +331,9 @@ impl ApiServer {
             Err(e) => match e {
                 data_store::Error::NotFound => unreachable!(),
                 data_store::Error::UnsupportedValueType => unreachable!(),
-                data_store::Error::NotInitialized => ApiServer::json_response(
+                data_store::Error::NonObjectPatch
+                | data_store::Error::NonObjectRoot
+                | data_store::Error::NotInitialized => ApiServer::json_response(
                     StatusCode::BadRequest,
                     ApiServer::json_fault_message(e.to_string()),
                 ),
@@ -419,7 +434,8 @@ mod tests {
                 StartMicrovmError::MissingKernelConfig,
             ))))
             .unwrap();
-        let response = api_server.serve_vmm_action_request(Box::new(VmmAction::StartMicroVm), 0);
+        let response = api_server
+            .serve_vmm_action_request(Box::new(VmmAction::StartMicroVm(Default::default())), 0);
         assert_eq!(response.status(), StatusCode::BadRequest);
 
         let start_time_us = utils::time::get_time_us(ClockType::Monotonic);
@@ -438,8 +454,11 @@ mod tests {
             Box::new(VmmAction::CreateSnapshot(CreateSnapshotParams {
                 snapshot_type: SnapshotType::Diff,
                 snapshot_path: PathBuf::new(),
+                snapshot_fd: None,
                 mem_file_path: PathBuf::new(),
+                mem_file_fd: None,
                 version: None,
+                live: false,
             })),
             start_time_us,
         );
@@ -452,8 +471,11 @@ mod tests {
             Box::new(VmmAction::CreateSnapshot(CreateSnapshotParams {
                 snapshot_type: SnapshotType::Diff,
                 snapshot_path: PathBuf::new(),
+                snapshot_fd: None,
                 mem_file_path: PathBuf::new(),
+                mem_file_fd: None,
                 version: None,
+                live: false,
             })),
             start_time_us,
         );
@@ -476,10 +498,37 @@ mod tests {
             to_vmm_fd,
         );
 
-        let response = api_server.get_mmds();
+        let response = api_server.get_mmds(None);
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[test]
+    fn test_get_mmds_path() {
+        let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        let (api_request_sender, _from_api) = channel();
+        let (_to_api, vmm_response_receiver) = channel();
+        let mmds_info = Arc::new(Mutex::new(Mmds::default()));
+
+        let api_server = ApiServer::new(
+            mmds_info,
+            api_request_sender,
+            vmm_response_receiver,
+            to_vmm_fd,
+        );
+        let data = json!({"latest": {"meta-data": {"instance-id": "i-1234"}}});
+        api_server.put_mmds(data);
+
+        let response = api_server.get_mmds(Some("/latest/meta-data".to_string()));
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = api_server.get_mmds(Some("/latest/meta-data/instance-id".to_string()));
+        assert_eq!(response.status(), StatusCode::OK);
+        let value = String::from_utf8(response.body().unwrap().body).unwrap();
+        assert_eq!(value, "\"i-1234\"");
+
+        let response = api_server.get_mmds(Some("/latest/user-data".to_string()));
+        assert_eq!(response.status(), StatusCode::NotFound);
+    }
+
     #[test]
     fn test_put_mmds() {
         let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
@@ -518,10 +567,25 @@ mod tests {
         let response = api_server.put_mmds(serde_json::Value::String("string".to_string()));
         assert_eq!(response.status(), StatusCode::NoContent);
 
+        // A data store which isn't an object can't be patched.
+        let response = api_server.patch_mmds(json!({ "key": "value" }));
+        assert_eq!(response.status(), StatusCode::BadRequest);
+
+        let response = api_server.put_mmds(json!({ "key": "value" }));
+        assert_eq!(response.status(), StatusCode::NoContent);
+
+        // The patch has to be an object.
         let response = api_server.patch_mmds(serde_json::Value::String(
             "{ \"key\" : \"value\" }".to_string(),
         ));
+        assert_eq!(response.status(), StatusCode::BadRequest);
+
+        let response = api_server.patch_mmds(json!({ "key": null, "other": 1 }));
         assert_eq!(response.status(), StatusCode::NoContent);
+        assert_eq!(
+            api_server.mmds_info.lock().unwrap().get_data_str(),
+            r#"{"other":1}"#
+        );
     }
 
     #[test]
@@ -7,18 +7,25 @@ use serde_json::Value;
 use super::VmmData;
 use crate::request::actions::parse_put_actions;
 use crate::request::balloon::{parse_get_balloon, parse_patch_balloon, parse_put_balloon};
-use crate::request::boot_source::parse_put_boot_source;
+use crate::request::boot_source::{parse_patch_boot_source, parse_put_boot_source};
+use crate::request::devices::{parse_get_devices, parse_put_devices};
 use crate::request::drive::{parse_patch_drive, parse_put_drive};
-use crate::request::instance_info::parse_get_instance_info;
+use crate::request::fs::parse_put_fs;
+use crate::request::instance_info::{parse_get_instance_info, parse_patch_instance_info};
 use crate::request::logger::parse_put_logger;
 use crate::request::machine_configuration::{
     parse_get_machine_config, parse_patch_machine_config, parse_put_machine_config,
 };
+use crate::request::memory_dump::parse_put_memory_dump;
 use crate::request::metrics::parse_put_metrics;
 use crate::request::mmds::{parse_get_mmds, parse_patch_mmds, parse_put_mmds};
 use crate::request::net::{parse_patch_net, parse_put_net};
+use crate::request::recent_errors::parse_get_recent_errors;
+use crate::request::serial_log::parse_get_serial_log;
 use crate::request::snapshot::parse_patch_vm_state;
 use crate::request::snapshot::parse_put_snapshot;
+use crate::request::vcpu::parse_get_vcpu;
+use crate::request::vm_config::parse_put_vm_config;
 use crate::request::vsock::parse_put_vsock;
 use crate::ApiServer;
 use micro_http::{Body, Method, Request, Response, StatusCode, Version};
@@ -27,7 +34,8 @@ use logger::{error, info};
 use vmm::rpc_interface::{VmmAction, VmmActionError};
 
 pub(crate) enum ParsedRequest {
-    GetMMDS,
+    /// Gets the MMDS data, or the subtree at the JSON pointer, if any.
+    GetMMDS(Option<String>),
     PatchMMDS(Value),
     PutMMDS(Value),
     Sync(Box<VmmAction>),
@@ -59,18 +67,25 @@ impl ParsedRequest {
         match (request.method(), path, request.body.as_ref()) {
             (Method::Get, "", None) => parse_get_instance_info(),
             (Method::Get, "balloon", None) => parse_get_balloon(path_tokens.get(1)),
+            (Method::Get, "devices", None) => parse_get_devices(),
             (Method::Get, "vm", None) if path_tokens.get(1) == Some(&"config") => {
                 Ok(ParsedRequest::new_sync(VmmAction::GetFullVmConfig))
             }
             (Method::Get, "machine-config", None) => parse_get_machine_config(),
-            (Method::Get, "mmds", None) => parse_get_mmds(),
+            (Method::Get, "mmds", None) => parse_get_mmds(&path_tokens[1..]),
+            (Method::Get, "recent-errors", None) => parse_get_recent_errors(),
+            (Method::Get, "serial-log", None) => parse_get_serial_log(),
+            (Method::Get, "vcpus", None) => parse_get_vcpu(path_tokens.get(1)),
             (Method::Get, _, Some(_)) => method_to_error(Method::Get),
             (Method::Put, "actions", Some(body)) => parse_put_actions(body),
             (Method::Put, "balloon", Some(body)) => parse_put_balloon(body),
             (Method::Put, "boot-source", Some(body)) => parse_put_boot_source(body),
+            (Method::Put, "devices", Some(body)) => parse_put_devices(body, path_tokens.get(1)),
             (Method::Put, "drives", Some(body)) => parse_put_drive(body, path_tokens.get(1)),
+            (Method::Put, "fs", Some(body)) => parse_put_fs(body, path_tokens.get(1)),
             (Method::Put, "logger", Some(body)) => parse_put_logger(body),
             (Method::Put, "machine-config", Some(body)) => parse_put_machine_config(body),
+            (Method::Put, "memory-dump", Some(body)) => parse_put_memory_dump(body),
             (Method::Put, "metrics", Some(body)) => parse_put_metrics(body),
             (Method::Put, "mmds", Some(body)) => parse_put_mmds(body, path_tokens.get(1)),
             (Method::Put, "network-interfaces", Some(body)) => {
@@ -78,9 +93,12 @@ impl ParsedRequest {
             }
             (Method::Put, "shutdown-internal", None) => Ok(ParsedRequest::ShutdownInternal),
             (Method::Put, "snapshot", Some(body)) => parse_put_snapshot(body, path_tokens.get(1)),
+            (Method::Put, "vm", Some(body)) => parse_put_vm_config(body, path_tokens.get(1)),
             (Method::Put, "vsock", Some(body)) => parse_put_vsock(body),
             (Method::Put, _, None) => method_to_error(Method::Put),
+            (Method::Patch, "", Some(body)) => parse_patch_instance_info(body),
             (Method::Patch, "balloon", Some(body)) => parse_patch_balloon(body, path_tokens.get(1)),
+            (Method::Patch, "boot-source", Some(body)) => parse_patch_boot_source(body),
             (Method::Patch, "drives", Some(body)) => parse_patch_drive(body, path_tokens.get(1)),
             (Method::Patch, "machine-config", Some(body)) => parse_patch_machine_config(body),
             (Method::Patch, "mmds", Some(body)) => parse_patch_mmds(body),
@@ -121,8 +139,17 @@ impl ParsedRequest {
                     Self::success_response_with_data(balloon_config)
                 }
                 VmmData::BalloonStats(stats) => Self::success_response_with_data(stats),
+                VmmData::GuestMemoryStats(stats) => Self::success_response_with_data(stats),
+                VmmData::ConfigurationProblems(problems) => {
+                    Self::success_response_with_data(problems)
+                }
+                VmmData::Devices(devices) => Self::success_response_with_data(devices),
                 VmmData::InstanceInformation(info) => Self::success_response_with_data(info),
                 VmmData::FullVmConfig(config) => Self::success_response_with_data(config),
+                VmmData::RecentErrors(errors) => Self::success_response_with_data(errors),
+                VmmData::SerialLog(output) => Self::success_response_with_data(output),
+                VmmData::SnapshotInfo(info) => Self::success_response_with_data(info),
+                VmmData::VcpuState(registers) => Self::success_response_with_data(registers),
             },
             Err(vmm_action_error) => {
                 error!(
@@ -268,9 +295,10 @@ pub(crate) mod tests {
 
     use micro_http::HttpConnection;
     use vmm::builder::StartMicrovmError;
+    use vmm::recent_errors::RecentError;
     use vmm::resources::VmmConfig;
     use vmm::rpc_interface::VmmActionError;
-    use vmm::vmm_config::balloon::{BalloonDeviceConfig, BalloonStats};
+    use vmm::vmm_config::balloon::{BalloonDeviceConfig, BalloonStats, GuestMemoryStats};
     use vmm::vmm_config::instance_info::InstanceInfo;
     use vmm::vmm_config::machine_config::VmConfig;
 
@@ -280,7 +308,9 @@ pub(crate) mod tests {
                 (&ParsedRequest::Sync(ref sync_req), &ParsedRequest::Sync(ref other_sync_req)) => {
                     sync_req == other_sync_req
                 }
-                (&ParsedRequest::GetMMDS, &ParsedRequest::GetMMDS) => true,
+                (&ParsedRequest::GetMMDS(ref path), &ParsedRequest::GetMMDS(ref other_path)) => {
+                    path == other_path
+                }
                 (&ParsedRequest::PutMMDS(ref val), &ParsedRequest::PutMMDS(ref other_val)) => {
                     val == other_val
                 }
@@ -504,16 +534,37 @@ pub(crate) mod tests {
                 VmmData::BalloonStats(stats) => {
                     http_response(&serde_json::to_string(stats).unwrap(), 200)
                 }
+                VmmData::ConfigurationProblems(problems) => {
+                    http_response(&serde_json::to_string(problems).unwrap(), 200)
+                }
+                VmmData::Devices(devices) => {
+                    http_response(&serde_json::to_string(devices).unwrap(), 200)
+                }
                 VmmData::Empty => http_response("", 204),
                 VmmData::FullVmConfig(cfg) => {
                     http_response(&serde_json::to_string(cfg).unwrap(), 200)
                 }
+                VmmData::GuestMemoryStats(stats) => {
+                    http_response(&serde_json::to_string(stats).unwrap(), 200)
+                }
                 VmmData::MachineConfiguration(cfg) => {
                     http_response(&serde_json::to_string(cfg).unwrap(), 200)
                 }
                 VmmData::InstanceInformation(info) => {
                     http_response(&serde_json::to_string(info).unwrap(), 200)
                 }
+                VmmData::RecentErrors(errors) => {
+                    http_response(&serde_json::to_string(errors).unwrap(), 200)
+                }
+                VmmData::SerialLog(output) => {
+                    http_response(&serde_json::to_string(output).unwrap(), 200)
+                }
+                VmmData::SnapshotInfo(info) => {
+                    http_response(&serde_json::to_string(info).unwrap(), 200)
+                }
+                VmmData::VcpuState(registers) => {
+                    http_response(&serde_json::to_string(registers).unwrap(), 200)
+                }
             };
             let response = ParsedRequest::convert_to_response(&data);
             assert!(response.write_all(&mut buf).is_ok());
@@ -526,10 +577,25 @@ pub(crate) mod tests {
             swap_out: Some(1),
             ..Default::default()
         }));
+        verify_ok_response_with(VmmData::ConfigurationProblems(vec![
+            "Cannot start microvm without kernel configuration.".to_string(),
+        ]));
+        verify_ok_response_with(VmmData::Devices(Vec::new()));
         verify_ok_response_with(VmmData::Empty);
         verify_ok_response_with(VmmData::FullVmConfig(VmmConfig::default()));
+        verify_ok_response_with(VmmData::GuestMemoryStats(GuestMemoryStats {
+            free_bytes: Some(1),
+            ..Default::default()
+        }));
         verify_ok_response_with(VmmData::MachineConfiguration(VmConfig::default()));
         verify_ok_response_with(VmmData::InstanceInformation(InstanceInfo::default()));
+        verify_ok_response_with(VmmData::RecentErrors(vec![RecentError {
+            timestamp_us: 1,
+            message: "error".to_string(),
+        }]));
+        verify_ok_response_with(VmmData::SerialLog("serial output".to_string()));
+        verify_ok_response_with(VmmData::SnapshotInfo(Default::default()));
+        verify_ok_response_with(VmmData::VcpuState(Default::default()));
 
         // Error.
         let error = VmmActionError::StartMicrovm(StartMicrovmError::MissingKernelConfig);
@@ -599,6 +665,64 @@ pub(crate) mod tests {
             .unwrap();
         assert!(connection.try_read().is_ok());
         let req = connection.pop_parsed_request().unwrap();
+        assert!(ParsedRequest::try_from_request(&req).unwrap() == ParsedRequest::GetMMDS(None));
+
+        sender
+            .write_all(http_request("GET", "/mmds/latest/meta-data", None).as_bytes())
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(
+            ParsedRequest::try_from_request(&req).unwrap()
+                == ParsedRequest::GetMMDS(Some("/latest/meta-data".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_try_from_get_devices() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(http_request("GET", "/devices", None).as_bytes())
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(ParsedRequest::try_from_request(&req).is_ok());
+    }
+
+    #[test]
+    fn test_try_from_get_recent_errors() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(http_request("GET", "/recent-errors", None).as_bytes())
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(ParsedRequest::try_from_request(&req).is_ok());
+    }
+
+    #[test]
+    fn test_try_from_get_serial_log() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(http_request("GET", "/serial-log", None).as_bytes())
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(ParsedRequest::try_from_request(&req).is_ok());
+    }
+
+    #[test]
+    fn test_try_from_get_vcpu() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(http_request("GET", "/vcpus/0", None).as_bytes())
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
         assert!(ParsedRequest::try_from_request(&req).is_ok());
     }
 
@@ -682,6 +806,24 @@ pub(crate) mod tests {
         assert!(ParsedRequest::try_from_request(&req).is_ok());
     }
 
+    #[test]
+    fn test_try_from_put_fs() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \
+            \"fs_id\": \"string\", \
+            \"shared_dir\": \"string\", \
+            \"tag\": \"string\", \
+            \"is_read_only\": true \
+        }";
+        sender
+            .write_all(http_request("PUT", "/fs/string", Some(&body)).as_bytes())
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(ParsedRequest::try_from_request(&req).is_ok());
+    }
+
     #[test]
     fn test_try_from_put_logger() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -852,6 +994,38 @@ pub(crate) mod tests {
         assert!(ParsedRequest::try_from_request(&req).is_ok());
     }
 
+    #[test]
+    fn test_try_from_put_memory_dump() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \
+            \"mem_file_path\": \"foo\", \
+            \"pause_vm\": true \
+        }";
+        sender
+            .write_all(http_request("PUT", "/memory-dump", Some(&body)).as_bytes())
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(ParsedRequest::try_from_request(&req).is_ok());
+    }
+
+    #[test]
+    fn test_try_from_put_vm_config() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \
+            \"boot-source\": { \"kernel_image_path\": \"string\" }, \
+            \"drives\": [] \
+        }";
+        sender
+            .write_all(http_request("PUT", "/vm/config", Some(&body)).as_bytes())
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(ParsedRequest::try_from_request(&req).is_ok());
+    }
+
     #[test]
     fn test_try_from_put_vsock() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -869,6 +1043,19 @@ pub(crate) mod tests {
         assert!(ParsedRequest::try_from_request(&req).is_ok());
     }
 
+    #[test]
+    fn test_try_from_patch_instance_info() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"id\": \"string\" }";
+        sender
+            .write_all(http_request("PATCH", "/", Some(&body)).as_bytes())
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(ParsedRequest::try_from_request(&req).is_ok());
+    }
+
     #[test]
     fn test_try_from_patch_balloon() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -891,6 +1078,21 @@ pub(crate) mod tests {
         assert!(ParsedRequest::try_from_request(&req).is_ok());
     }
 
+    #[test]
+    fn test_try_from_patch_boot_source() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \
+            \"kernel_image_path\": \"string\" \
+        }";
+        sender
+            .write_all(http_request("PATCH", "/boot-source", Some(&body)).as_bytes())
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(ParsedRequest::try_from_request(&req).is_ok());
+    }
+
     #[test]
     fn test_try_from_patch_drives() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
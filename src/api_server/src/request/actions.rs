@@ -3,21 +3,22 @@
 
 use super::super::VmmAction;
 use crate::parsed_request::{Error, ParsedRequest};
-use crate::request::Body;
-#[cfg(target_arch = "aarch64")]
-use crate::request::StatusCode;
+use crate::request::{Body, StatusCode};
 use logger::{IncMetric, METRICS};
+use vmm::vmm_config::instance_info::StartMicroVmConfig;
 
 use serde::{Deserialize, Serialize};
 
 // The names of the members from this enum must precisely correspond (as a string) to the possible
 // values of "action_type" from the json request body. This is useful to get a strongly typed
 // struct from the Serde deserialization process.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
 enum ActionType {
     FlushMetrics,
     InstanceStart,
+    ReopenLogFiles,
     SendCtrlAltDel,
+    ValidateConfiguration,
 }
 
 // The model of the json body from a sync request. We use Serde to transform each associated
@@ -26,6 +27,8 @@ enum ActionType {
 #[serde(deny_unknown_fields)]
 struct ActionBody {
     action_type: ActionType,
+    // Only valid for `InstanceStart`.
+    start_paused: Option<bool>,
 }
 
 pub(crate) fn parse_put_actions(body: &Body) -> Result<ParsedRequest, Error> {
@@ -35,9 +38,22 @@ pub(crate) fn parse_put_actions(body: &Body) -> Result<ParsedRequest, Error> {
         Error::SerdeJson(e)
     })?;
 
+    if action_body.start_paused.is_some() && action_body.action_type != ActionType::InstanceStart {
+        METRICS.put_api_requests.actions_fails.inc();
+        return Err(Error::Generic(
+            StatusCode::BadRequest,
+            "start_paused is only supported by InstanceStart.".to_string(),
+        ));
+    }
+
     match action_body.action_type {
         ActionType::FlushMetrics => Ok(ParsedRequest::new_sync(VmmAction::FlushMetrics)),
-        ActionType::InstanceStart => Ok(ParsedRequest::new_sync(VmmAction::StartMicroVm)),
+        ActionType::InstanceStart => Ok(ParsedRequest::new_sync(VmmAction::StartMicroVm(
+            StartMicroVmConfig {
+                start_paused: action_body.start_paused.unwrap_or(false),
+            },
+        ))),
+        ActionType::ReopenLogFiles => Ok(ParsedRequest::new_sync(VmmAction::ReopenLogFiles)),
         ActionType::SendCtrlAltDel => {
             // SendCtrlAltDel not supported on aarch64.
             #[cfg(target_arch = "aarch64")]
@@ -49,6 +65,9 @@ pub(crate) fn parse_put_actions(body: &Body) -> Result<ParsedRequest, Error> {
             #[cfg(target_arch = "x86_64")]
             Ok(ParsedRequest::new_sync(VmmAction::SendCtrlAltDel))
         }
+        ActionType::ValidateConfiguration => {
+            Ok(ParsedRequest::new_sync(VmmAction::ValidateConfiguration))
+        }
     }
 }
 
@@ -65,10 +84,33 @@ mod tests {
                 "action_type": "InstanceStart"
             }"#;
 
-            let req: ParsedRequest = ParsedRequest::new_sync(VmmAction::StartMicroVm);
+            let req: ParsedRequest =
+                ParsedRequest::new_sync(VmmAction::StartMicroVm(StartMicroVmConfig::default()));
+            let result = parse_put_actions(&Body::new(json));
+            assert!(result.is_ok());
+            assert!(result.unwrap().eq(&req));
+        }
+
+        {
+            let json = r#"{
+                "action_type": "InstanceStart",
+                "start_paused": true
+            }"#;
+
+            let req: ParsedRequest =
+                ParsedRequest::new_sync(VmmAction::StartMicroVm(StartMicroVmConfig {
+                    start_paused: true,
+                }));
             let result = parse_put_actions(&Body::new(json));
             assert!(result.is_ok());
             assert!(result.unwrap().eq(&req));
+
+            // Only the InstanceStart action can start the microVM paused.
+            let json = r#"{
+                "action_type": "FlushMetrics",
+                "start_paused": true
+            }"#;
+            assert!(parse_put_actions(&Body::new(json)).is_err());
         }
 
         #[cfg(target_arch = "x86_64")]
@@ -103,5 +145,27 @@ mod tests {
             assert!(result.is_ok());
             assert!(result.unwrap().eq(&req));
         }
+
+        {
+            let json = r#"{
+                "action_type": "ReopenLogFiles"
+            }"#;
+
+            let req: ParsedRequest = ParsedRequest::new_sync(VmmAction::ReopenLogFiles);
+            let result = parse_put_actions(&Body::new(json));
+            assert!(result.is_ok());
+            assert!(result.unwrap().eq(&req));
+        }
+
+        {
+            let json = r#"{
+                "action_type": "ValidateConfiguration"
+            }"#;
+
+            let req: ParsedRequest = ParsedRequest::new_sync(VmmAction::ValidateConfiguration);
+            let result = parse_put_actions(&Body::new(json));
+            assert!(result.is_ok());
+            assert!(result.unwrap().eq(&req));
+        }
     }
 }
@@ -13,6 +13,7 @@ pub(crate) fn parse_get_balloon(path_second_token: Option<&&str>) -> Result<Pars
     match path_second_token {
         Some(stats_path) => match *stats_path {
             "statistics" => Ok(ParsedRequest::new_sync(VmmAction::GetBalloonStats)),
+            "memory-stats" => Ok(ParsedRequest::new_sync(VmmAction::GetGuestMemoryStats)),
             _ => Err(Error::Generic(
                 StatusCode::BadRequest,
                 format!("Unrecognized GET request path `{}`.", *stats_path),
@@ -61,6 +62,11 @@ mod tests {
         assert!(parse_get_balloon(Some(&"unrelated")).is_err());
 
         assert!(parse_get_balloon(Some(&"statistics")).is_ok());
+
+        match vmm_action_from_request(parse_get_balloon(Some(&"memory-stats")).unwrap()) {
+            VmmAction::GetGuestMemoryStats => (),
+            _ => panic!("Test failed: Invalid request"),
+        }
     }
 
     #[test]
@@ -165,5 +171,13 @@ mod tests {
                 "stats_polling_interval_s": 0
             }"#;
         assert!(parse_put_balloon(&Body::new(body)).is_ok());
+
+        // PUT with free page reporting enabled.
+        let body = r#"{
+                "amount_mib": 1000,
+                "deflate_on_oom": true,
+                "free_page_reporting": true
+            }"#;
+        assert!(parse_put_balloon(&Body::new(body)).is_ok());
     }
 }
@@ -2,10 +2,10 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::super::VmmAction;
-use crate::parsed_request::{Error, ParsedRequest};
-use crate::request::Body;
+use crate::parsed_request::{method_to_error, Error, ParsedRequest};
+use crate::request::{Body, Method};
 use logger::{IncMetric, METRICS};
-use vmm::vmm_config::boot_source::BootSourceConfig;
+use vmm::vmm_config::boot_source::{BootSourceConfig, BootSourceUpdateConfig};
 
 pub(crate) fn parse_put_boot_source(body: &Body) -> Result<ParsedRequest, Error> {
     METRICS.put_api_requests.boot_source_count.inc();
@@ -17,6 +17,23 @@ pub(crate) fn parse_put_boot_source(body: &Body) -> Result<ParsedRequest, Error>
     )))
 }
 
+pub(crate) fn parse_patch_boot_source(body: &Body) -> Result<ParsedRequest, Error> {
+    METRICS.patch_api_requests.boot_source_count.inc();
+    let update_cfg = serde_json::from_slice::<BootSourceUpdateConfig>(body.raw()).map_err(|e| {
+        METRICS.patch_api_requests.boot_source_fails.inc();
+        Error::SerdeJson(e)
+    })?;
+
+    if update_cfg.kernel_image_path.is_none() && update_cfg.boot_args.is_none() {
+        METRICS.patch_api_requests.boot_source_fails.inc();
+        return method_to_error(Method::Patch);
+    }
+
+    Ok(ParsedRequest::new_sync(VmmAction::UpdateBootSource(
+        update_cfg,
+    )))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -28,12 +45,14 @@ mod tests {
         let body = r#"{
                 "kernel_image_path": "/foo/bar",
                 "initrd_path": "/bar/foo",
-                "boot_args": "foobar"
+                "boot_args": "foobar",
+                "rng_seed": false
               }"#;
         let same_body = BootSourceConfig {
             kernel_image_path: String::from("/foo/bar"),
             initrd_path: Some(String::from("/bar/foo")),
             boot_args: Some(String::from("foobar")),
+            rng_seed: Some(false),
         };
         let result = parse_put_boot_source(&Body::new(body));
         assert!(result.is_ok());
@@ -41,4 +60,41 @@ mod tests {
 
         assert!(parsed_req == ParsedRequest::new_sync(VmmAction::ConfigureBootSource(same_body)));
     }
+
+    #[test]
+    fn test_parse_patch_boot_request() {
+        assert!(parse_patch_boot_source(&Body::new("invalid_payload")).is_err());
+
+        // At least one field must be updated.
+        assert!(parse_patch_boot_source(&Body::new("{}")).is_err());
+
+        // The initrd can't be updated.
+        let body = r#"{
+                "initrd_path": "/bar/foo"
+              }"#;
+        assert!(parse_patch_boot_source(&Body::new(body)).is_err());
+
+        let body = r#"{
+                "kernel_image_path": "/foo/bar"
+              }"#;
+        let expected_cfg = BootSourceUpdateConfig {
+            kernel_image_path: Some(String::from("/foo/bar")),
+            boot_args: None,
+        };
+        let parsed_req =
+            parse_patch_boot_source(&Body::new(body)).unwrap_or_else(|_e| panic!("Failed test."));
+        assert!(parsed_req == ParsedRequest::new_sync(VmmAction::UpdateBootSource(expected_cfg)));
+
+        let body = r#"{
+                "kernel_image_path": "/foo/bar",
+                "boot_args": "foobar"
+              }"#;
+        let expected_cfg = BootSourceUpdateConfig {
+            kernel_image_path: Some(String::from("/foo/bar")),
+            boot_args: Some(String::from("foobar")),
+        };
+        let parsed_req =
+            parse_patch_boot_source(&Body::new(body)).unwrap_or_else(|_e| panic!("Failed test."));
+        assert!(parsed_req == ParsedRequest::new_sync(VmmAction::UpdateBootSource(expected_cfg)));
+    }
 }
@@ -0,0 +1,80 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::parsed_request::{Error, ParsedRequest};
+use crate::request::{Body, Method};
+use logger::{IncMetric, METRICS};
+use vmm::rpc_interface::VmmAction;
+use vmm::vmm_config::devices::DeviceResetConfig;
+
+pub(crate) fn parse_get_devices() -> Result<ParsedRequest, Error> {
+    METRICS.get_api_requests.devices_count.inc();
+    Ok(ParsedRequest::new_sync(VmmAction::GetDevices))
+}
+
+pub(crate) fn parse_put_devices(
+    body: &Body,
+    operation_from_path: Option<&&str>,
+) -> Result<ParsedRequest, Error> {
+    match operation_from_path {
+        Some(&"reset") => {
+            METRICS.put_api_requests.device_reset_count.inc();
+            let config =
+                serde_json::from_slice::<DeviceResetConfig>(body.raw()).map_err(|err| {
+                    METRICS.put_api_requests.device_reset_fails.inc();
+                    Error::SerdeJson(err)
+                })?;
+            Ok(ParsedRequest::new_sync(VmmAction::ResetDevice(config)))
+        }
+        Some(operation) => Err(Error::InvalidPathMethod(
+            format!("/devices/{}", operation),
+            Method::Put,
+        )),
+        None => Err(Error::InvalidPathMethod(
+            "/devices".to_string(),
+            Method::Put,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsed_request::tests::vmm_action_from_request;
+    use vmm::vmm_config::devices::ResettableDeviceType;
+
+    #[test]
+    fn test_parse_get_devices_request() {
+        match parse_get_devices() {
+            Ok(ParsedRequest::Sync(action)) if *action == VmmAction::GetDevices => {}
+            _ => panic!("Test failed."),
+        }
+    }
+
+    #[test]
+    fn test_parse_put_devices_request() {
+        let body = r#"{
+            "device_type": "block",
+            "id": "rootfs"
+        }"#;
+        match vmm_action_from_request(parse_put_devices(&Body::new(body), Some(&"reset")).unwrap())
+        {
+            VmmAction::ResetDevice(config) => assert_eq!(
+                config,
+                DeviceResetConfig {
+                    device_type: ResettableDeviceType::Block,
+                    id: "rootfs".to_string(),
+                }
+            ),
+            _ => panic!("Test failed."),
+        }
+
+        let body = r#"{
+            "device_type": "balloon",
+            "id": "balloon"
+        }"#;
+        assert!(parse_put_devices(&Body::new(body), Some(&"reset")).is_err());
+        assert!(parse_put_devices(&Body::new("{}"), Some(&"unplug")).is_err());
+        assert!(parse_put_devices(&Body::new("{}"), None).is_err());
+    }
+}
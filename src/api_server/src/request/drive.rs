@@ -243,6 +243,11 @@ mod tests {
                 "partuuid": "string",
                 "is_read_only": true,
                 "cache_type": "Unsafe",
+                "open_retry": {
+                    "max_retries": 5,
+                    "initial_backoff_ms": 100
+                },
+                "access_pattern": "Sequential",
                 "rate_limiter": {
                     "bandwidth": {
                         "size": 0,
@@ -0,0 +1,76 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::super::VmmAction;
+use crate::parsed_request::{checked_id, Error, ParsedRequest};
+use crate::request::{Body, StatusCode};
+use logger::{IncMetric, METRICS};
+use vmm::vmm_config::fs::FsDeviceConfig;
+
+pub(crate) fn parse_put_fs(
+    body: &Body,
+    id_from_path: Option<&&str>,
+) -> Result<ParsedRequest, Error> {
+    METRICS.put_api_requests.fs_count.inc();
+    let id = if let Some(id) = id_from_path {
+        checked_id(id)?
+    } else {
+        METRICS.put_api_requests.fs_fails.inc();
+        return Err(Error::EmptyID);
+    };
+
+    let fs_cfg = serde_json::from_slice::<FsDeviceConfig>(body.raw()).map_err(|e| {
+        METRICS.put_api_requests.fs_fails.inc();
+        Error::SerdeJson(e)
+    })?;
+
+    if id != fs_cfg.fs_id {
+        METRICS.put_api_requests.fs_fails.inc();
+        Err(Error::Generic(
+            StatusCode::BadRequest,
+            "The id from the path does not match the id from the body!".to_string(),
+        ))
+    } else {
+        Ok(ParsedRequest::new_sync(VmmAction::InsertFsDevice(fs_cfg)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsed_request::tests::vmm_action_from_request;
+
+    #[test]
+    fn test_parse_put_fs_request() {
+        assert!(parse_put_fs(&Body::new("invalid_payload"), None).is_err());
+        assert!(parse_put_fs(&Body::new("invalid_payload"), Some(&"id")).is_err());
+
+        let body = r#"{
+                "fs_id": "foo",
+                "shared_dir": "/srv/shared",
+                "tag": "shared"
+              }"#;
+        // The id from the path differs from the id from the body.
+        assert!(parse_put_fs(&Body::new(body), Some(&"bar")).is_err());
+
+        match vmm_action_from_request(parse_put_fs(&Body::new(body), Some(&"foo")).unwrap()) {
+            VmmAction::InsertFsDevice(cfg) => {
+                assert_eq!(cfg.fs_id, "foo");
+                assert_eq!(cfg.shared_dir, "/srv/shared");
+                assert_eq!(cfg.tag, "shared");
+                // The device is read-write by default.
+                assert!(!cfg.is_read_only);
+            }
+            _ => panic!("Test failed: Invalid parameters"),
+        };
+
+        // Unknown fields are refused.
+        let body = r#"{
+                "fs_id": "foo",
+                "shared_dir": "/srv/shared",
+                "tag": "shared",
+                "cache": "always"
+              }"#;
+        assert!(parse_put_fs(&Body::new(body), Some(&"foo")).is_err());
+    }
+}
@@ -2,14 +2,25 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::parsed_request::{Error, ParsedRequest};
+use crate::request::Body;
 use logger::{IncMetric, METRICS};
 use vmm::rpc_interface::VmmAction;
+use vmm::vmm_config::instance_info::InstanceInfoUpdate;
 
 pub(crate) fn parse_get_instance_info() -> Result<ParsedRequest, Error> {
     METRICS.get_api_requests.instance_info_count.inc();
     Ok(ParsedRequest::new_sync(VmmAction::GetVmInstanceInfo))
 }
 
+pub(crate) fn parse_patch_instance_info(body: &Body) -> Result<ParsedRequest, Error> {
+    METRICS.patch_api_requests.instance_info_count.inc();
+    let update = serde_json::from_slice::<InstanceInfoUpdate>(body.raw()).map_err(|e| {
+        METRICS.patch_api_requests.instance_info_fails.inc();
+        Error::SerdeJson(e)
+    })?;
+    Ok(ParsedRequest::new_sync(VmmAction::SetInstanceId(update.id)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -21,4 +32,24 @@ mod tests {
             _ => panic!("Test failed."),
         }
     }
+
+    #[test]
+    fn test_parse_patch_instance_info_request() {
+        let body = r#"{
+                "id": "new-id"
+              }"#;
+        match parse_patch_instance_info(&Body::new(body)) {
+            Ok(ParsedRequest::Sync(action))
+                if *action == VmmAction::SetInstanceId("new-id".to_string()) => {}
+            _ => panic!("Test failed."),
+        }
+
+        // Only the ID can be changed.
+        let body = r#"{
+                "id": "new-id",
+                "state": "Running"
+              }"#;
+        assert!(parse_patch_instance_info(&Body::new(body)).is_err());
+        assert!(parse_patch_instance_info(&Body::new("invalid_payload")).is_err());
+    }
 }
@@ -49,6 +49,21 @@ pub(crate) fn parse_patch_machine_config(body: &Body) -> Result<ParsedRequest, E
         && vm_config.mem_size_mib.is_none()
         && vm_config.cpu_template.is_none()
         && vm_config.ht_enabled.is_none()
+        && vm_config.vcpu_affinity.is_none()
+        && vm_config.mem_regions.is_none()
+        && vm_config.allow_mem_overcommit.is_none()
+        && vm_config.mem_backing_file.is_none()
+        && vm_config.on_reboot.is_none()
+        && vm_config.reset_action.is_none()
+        && vm_config.panic_action.is_none()
+        && vm_config.rtc_base_time.is_none()
+        && vm_config.max_descriptors_per_event.is_none()
+        && vm_config.max_devices_per_irq.is_none()
+        && vm_config.interrupt_controller.is_none()
+        && vm_config.smbios.is_none()
+        && vm_config.shutdown_grace_period_ms.is_none()
+        && vm_config.guest_snapshot.is_none()
+        && vm_config.zero_mem_on_shutdown.is_none()
     {
         return method_to_error(Method::Patch);
     }
@@ -75,6 +90,7 @@ fn check_unsupported_fields(_vm_config: &VmConfig) -> Result<(), Error> {
 mod tests {
     use super::*;
     use crate::parsed_request::tests::vmm_action_from_request;
+    use vmm::vmm_config::machine_config::MemoryRegionConfig;
 
     #[test]
     fn test_parse_get_machine_config_request() {
@@ -120,6 +136,21 @@ mod tests {
             ht_enabled: Some(true),
             cpu_template: None,
             track_dirty_pages: true,
+            vcpu_affinity: None,
+            mem_regions: None,
+            allow_mem_overcommit: None,
+            mem_backing_file: None,
+            on_reboot: None,
+            reset_action: None,
+            panic_action: None,
+            rtc_base_time: None,
+            max_descriptors_per_event: None,
+            max_devices_per_irq: None,
+            interrupt_controller: None,
+            smbios: None,
+            shutdown_grace_period_ms: None,
+            guest_snapshot: None,
+            zero_mem_on_shutdown: None,
         };
 
         match vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()) {
@@ -145,6 +176,21 @@ mod tests {
                 ht_enabled: Some(true),
                 cpu_template: Some(CpuFeaturesTemplate::T2),
                 track_dirty_pages: true,
+                vcpu_affinity: None,
+                mem_regions: None,
+                allow_mem_overcommit: None,
+                mem_backing_file: None,
+                on_reboot: None,
+                reset_action: None,
+                panic_action: None,
+                rtc_base_time: None,
+                max_descriptors_per_event: None,
+                max_devices_per_irq: None,
+                interrupt_controller: None,
+                smbios: None,
+                shutdown_grace_period_ms: None,
+                guest_snapshot: None,
+                zero_mem_on_shutdown: None,
             };
 
             match vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()) {
@@ -157,6 +203,102 @@ mod tests {
         {
             assert!(parse_put_machine_config(&Body::new(body)).is_err());
         }
+
+        // 5. Test case for the vcpu affinity.
+        let body = r#"{
+                "vcpu_count": 2,
+                "mem_size_mib": 1024,
+                "ht_enabled": false,
+                "vcpu_affinity": {"0": [2, 3], "1": [4]}
+              }"#;
+        let mut vcpu_affinity = std::collections::BTreeMap::new();
+        vcpu_affinity.insert(0, vec![2, 3]);
+        vcpu_affinity.insert(1, vec![4]);
+        let expected_config = VmConfig {
+            vcpu_count: Some(2),
+            mem_size_mib: Some(1024),
+            ht_enabled: Some(false),
+            cpu_template: None,
+            track_dirty_pages: false,
+            vcpu_affinity: Some(vcpu_affinity),
+            mem_regions: None,
+            allow_mem_overcommit: None,
+            mem_backing_file: None,
+            on_reboot: None,
+            reset_action: None,
+            panic_action: None,
+            rtc_base_time: None,
+            max_descriptors_per_event: None,
+            max_devices_per_irq: None,
+            interrupt_controller: None,
+            smbios: None,
+            shutdown_grace_period_ms: None,
+            guest_snapshot: None,
+            zero_mem_on_shutdown: None,
+        };
+
+        match vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()) {
+            VmmAction::SetVmConfiguration(config) => assert_eq!(config, expected_config),
+            _ => panic!("Test failed."),
+        }
+
+        // 6. Test case for the memory layout.
+        let body = r#"{
+                "vcpu_count": 2,
+                "mem_size_mib": 1024,
+                "ht_enabled": false,
+                "mem_regions": [
+                    {"guest_addr": 0, "size_mib": 512},
+                    {"guest_addr": 4294967296, "size_mib": 512, "numa_node": 1}
+                ]
+              }"#;
+        let expected_config = VmConfig {
+            vcpu_count: Some(2),
+            mem_size_mib: Some(1024),
+            ht_enabled: Some(false),
+            cpu_template: None,
+            track_dirty_pages: false,
+            vcpu_affinity: None,
+            mem_regions: Some(vec![
+                MemoryRegionConfig {
+                    guest_addr: 0,
+                    size_mib: 512,
+                    numa_node: 0,
+                },
+                MemoryRegionConfig {
+                    guest_addr: 1 << 32,
+                    size_mib: 512,
+                    numa_node: 1,
+                },
+            ]),
+            allow_mem_overcommit: None,
+            mem_backing_file: None,
+            on_reboot: None,
+            reset_action: None,
+            panic_action: None,
+            rtc_base_time: None,
+            max_descriptors_per_event: None,
+            max_devices_per_irq: None,
+            interrupt_controller: None,
+            smbios: None,
+            shutdown_grace_period_ms: None,
+            guest_snapshot: None,
+            zero_mem_on_shutdown: None,
+        };
+
+        match vmm_action_from_request(parse_put_machine_config(&Body::new(body)).unwrap()) {
+            VmmAction::SetVmConfiguration(config) => assert_eq!(config, expected_config),
+            _ => panic!("Test failed."),
+        }
+
+        // Unknown fields of the memory regions are rejected.
+        let body = r#"{
+                "vcpu_count": 2,
+                "mem_size_mib": 1024,
+                "ht_enabled": false,
+                "mem_regions": [{"guest_addr": 0, "size_mib": 1024, "node": 1}]
+              }"#;
+        assert!(parse_put_machine_config(&Body::new(body)).is_err());
     }
 
     #[test]
@@ -191,5 +333,17 @@ mod tests {
                 "ht_enabled": false
               }"#;
         assert!(parse_patch_machine_config(&Body::new(body)).is_ok());
+        let body = r#"{
+                "vcpu_affinity": {"0": [1]}
+              }"#;
+        assert!(parse_patch_machine_config(&Body::new(body)).is_ok());
+        let body = r#"{
+                "shutdown_grace_period_ms": 5000
+              }"#;
+        assert!(parse_patch_machine_config(&Body::new(body)).is_ok());
+        let body = r#"{
+                "zero_mem_on_shutdown": true
+              }"#;
+        assert!(parse_patch_machine_config(&Body::new(body)).is_ok());
     }
 }
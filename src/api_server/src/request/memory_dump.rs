@@ -0,0 +1,54 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::super::VmmAction;
+use crate::parsed_request::{Error, ParsedRequest};
+use crate::request::Body;
+use vmm::vmm_config::memory_dump::DumpGuestMemoryParams;
+
+pub(crate) fn parse_put_memory_dump(body: &Body) -> Result<ParsedRequest, Error> {
+    Ok(ParsedRequest::new_sync(VmmAction::DumpGuestMemory(
+        serde_json::from_slice::<DumpGuestMemoryParams>(body.raw()).map_err(Error::SerdeJson)?,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsed_request::tests::vmm_action_from_request;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_put_memory_dump_request() {
+        let body = r#"{
+                "mem_file_path": "foo"
+              }"#;
+        let expected_params = DumpGuestMemoryParams {
+            mem_file_path: PathBuf::from("foo"),
+            pause_vm: false,
+        };
+        match vmm_action_from_request(parse_put_memory_dump(&Body::new(body)).unwrap()) {
+            VmmAction::DumpGuestMemory(params) => assert_eq!(params, expected_params),
+            _ => panic!("Test failed."),
+        }
+
+        let body = r#"{
+                "mem_file_path": "foo",
+                "pause_vm": true
+              }"#;
+        let expected_params = DumpGuestMemoryParams {
+            mem_file_path: PathBuf::from("foo"),
+            pause_vm: true,
+        };
+        match vmm_action_from_request(parse_put_memory_dump(&Body::new(body)).unwrap()) {
+            VmmAction::DumpGuestMemory(params) => assert_eq!(params, expected_params),
+            _ => panic!("Test failed."),
+        }
+
+        let body = r#"{
+                "mem_file_path": "foo",
+                "invalid_field": true
+              }"#;
+        assert!(parse_put_memory_dump(&Body::new(body)).is_err());
+    }
+}
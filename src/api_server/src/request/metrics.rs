@@ -23,6 +23,7 @@ mod tests {
 
     use super::*;
     use crate::parsed_request::tests::vmm_action_from_request;
+    use logger::MetricsFormat;
 
     #[test]
     fn test_parse_put_metrics_request() {
@@ -32,12 +33,27 @@ mod tests {
 
         let expected_cfg = MetricsConfig {
             metrics_path: PathBuf::from("metrics"),
+            format: MetricsFormat::Json,
         };
         match vmm_action_from_request(parse_put_metrics(&Body::new(body)).unwrap()) {
             VmmAction::ConfigureMetrics(cfg) => assert_eq!(cfg, expected_cfg),
             _ => panic!("Test failed."),
         }
 
+        let body = r#"{
+                "metrics_path": "metrics",
+                "format": "OpenMetrics"
+              }"#;
+        match vmm_action_from_request(parse_put_metrics(&Body::new(body)).unwrap()) {
+            VmmAction::ConfigureMetrics(cfg) => assert_eq!(cfg.format, MetricsFormat::OpenMetrics),
+            _ => panic!("Test failed."),
+        }
+        let body = r#"{
+                "metrics_path": "metrics",
+                "format": "Text"
+              }"#;
+        assert!(parse_put_metrics(&Body::new(body)).is_err());
+
         let invalid_body = r#"{
                 "invalid_field": "metrics"
               }"#;
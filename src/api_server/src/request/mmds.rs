@@ -7,9 +7,17 @@ use logger::{IncMetric, METRICS};
 use micro_http::StatusCode;
 use vmm::rpc_interface::VmmAction::SetMmdsConfiguration;
 
-pub(crate) fn parse_get_mmds() -> Result<ParsedRequest, Error> {
+// The path tokens following `mmds` select a subtree of the MMDS data, the whole data is
+// returned without them.
+pub(crate) fn parse_get_mmds(path_tokens: &[&str]) -> Result<ParsedRequest, Error> {
     METRICS.get_api_requests.mmds_count.inc();
-    Ok(ParsedRequest::GetMMDS)
+    if path_tokens.is_empty() {
+        return Ok(ParsedRequest::GetMMDS(None));
+    }
+    Ok(ParsedRequest::GetMMDS(Some(format!(
+        "/{}",
+        path_tokens.join("/")
+    ))))
 }
 
 pub(crate) fn parse_put_mmds(
@@ -56,8 +64,19 @@ mod tests {
 
     #[test]
     fn test_parse_get_mmds_request() {
-        assert!(parse_get_mmds().is_ok());
+        assert!(parse_get_mmds(&[]).is_ok());
         assert!(METRICS.get_api_requests.mmds_count.count() > 0);
+
+        match parse_get_mmds(&[]) {
+            Ok(ParsedRequest::GetMMDS(None)) => (),
+            _ => panic!("Expected a request for the whole MMDS data."),
+        }
+        match parse_get_mmds(&["latest", "meta-data", "instance-id"]) {
+            Ok(ParsedRequest::GetMMDS(Some(path))) => {
+                assert_eq!(path, "/latest/meta-data/instance-id")
+            }
+            _ => panic!("Expected a request for a subtree of the MMDS data."),
+        }
     }
 
     #[test]
@@ -82,6 +101,28 @@ mod tests {
               }"#;
         assert!(parse_put_mmds(&Body::new(body), Some(&path)).is_err());
 
+        let body = r#"{
+                "ipv4_address": "169.254.170.2",
+                "ipv6_address": "fe80::a9fe:a9fe:a9fe:a9fe"
+              }"#;
+        assert!(parse_put_mmds(&Body::new(body), Some(&path)).is_ok());
+
+        let body = r#"{
+                "ipv6_address": "169.254.170.2"
+              }"#;
+        assert!(parse_put_mmds(&Body::new(body), Some(&path)).is_err());
+
+        let body = r#"{
+                "version": "V2",
+                "ipv4_address": "169.254.170.2"
+              }"#;
+        assert!(parse_put_mmds(&Body::new(body), Some(&path)).is_ok());
+
+        let body = r#"{
+                "version": "V3"
+              }"#;
+        assert!(parse_put_mmds(&Body::new(body), Some(&path)).is_err());
+
         // Equivalent to reset the mmds configuration.
         let empty_body = r#"{}"#;
         assert!(parse_put_mmds(&Body::new(empty_body), Some(&path)).is_ok());
@@ -4,14 +4,21 @@
 pub mod actions;
 pub mod balloon;
 pub mod boot_source;
+pub mod devices;
 pub mod drive;
+pub mod fs;
 pub mod instance_info;
 pub mod logger;
 pub mod machine_configuration;
+pub mod memory_dump;
 pub mod metrics;
 pub mod mmds;
 pub mod net;
+pub mod recent_errors;
+pub mod serial_log;
 pub mod snapshot;
+pub mod vcpu;
+pub mod vm_config;
 pub mod vsock;
 pub use micro_http::{
     Body, HttpServer, Method, Request, RequestError, Response, StatusCode, Version,
@@ -108,6 +108,67 @@ mod tests {
         }"#;
 
         assert!(parse_put_net(&Body::new(body), Some(&"foo")).is_err());
+
+        // 5. Offloading is enabled by default and can be turned off.
+        let body = r#"{
+                "iface_id": "foo",
+                "host_dev_name": "bar"
+              }"#;
+        match vmm_action_from_request(parse_put_net(&Body::new(body), Some(&"foo")).unwrap()) {
+            VmmAction::InsertNetworkDevice(netif) => assert!(netif.enable_offload),
+            _ => panic!("Test failed."),
+        }
+        let body = r#"{
+                "iface_id": "foo",
+                "host_dev_name": "bar",
+                "enable_offload": false
+              }"#;
+        match vmm_action_from_request(parse_put_net(&Body::new(body), Some(&"foo")).unwrap()) {
+            VmmAction::InsertNetworkDevice(netif) => assert!(!netif.enable_offload),
+            _ => panic!("Test failed."),
+        }
+
+        // 6. The MTU is optional and must fit in 16 bits.
+        let body = r#"{
+                "iface_id": "foo",
+                "host_dev_name": "bar",
+                "mtu": 9000
+              }"#;
+        match vmm_action_from_request(parse_put_net(&Body::new(body), Some(&"foo")).unwrap()) {
+            VmmAction::InsertNetworkDevice(netif) => assert_eq!(netif.mtu, Some(9000)),
+            _ => panic!("Test failed."),
+        }
+        let body = r#"{
+                "iface_id": "foo",
+                "host_dev_name": "bar",
+                "mtu": 65536
+              }"#;
+        assert!(parse_put_net(&Body::new(body), Some(&"foo")).is_err());
+
+        // 7. The MMDS can be restricted to some guest addresses, given as IP prefixes.
+        let body = r#"{
+                "iface_id": "foo",
+                "host_dev_name": "bar",
+                "allow_mmds_requests": true,
+                "mmds_allowed_sources": ["10.0.0.2", "fd00::/8"]
+              }"#;
+        match vmm_action_from_request(parse_put_net(&Body::new(body), Some(&"foo")).unwrap()) {
+            VmmAction::InsertNetworkDevice(netif) => {
+                let sources: Vec<String> = netif
+                    .mmds_allowed_sources
+                    .iter()
+                    .map(|prefix| prefix.to_string())
+                    .collect();
+                assert_eq!(sources, vec!["10.0.0.2/32", "fd00::/8"]);
+            }
+            _ => panic!("Test failed."),
+        }
+        let body = r#"{
+                "iface_id": "foo",
+                "host_dev_name": "bar",
+                "mmds_allowed_sources": ["10.0.0.0/33"]
+              }"#;
+        assert!(parse_put_net(&Body::new(body), Some(&"foo")).is_err());
     }
 
     #[test]
@@ -149,5 +210,17 @@ mod tests {
             }
         }"#;
         assert!(parse_patch_net(&Body::new(body), Some(&"foo")).is_err());
+
+        // 5. Update of the packet capture.
+        let body = r#"{
+                "iface_id": "foo",
+                "pcap_path": "/tmp/foo.pcap"
+        }"#;
+        match vmm_action_from_request(parse_patch_net(&Body::new(body), Some(&"foo")).unwrap()) {
+            VmmAction::UpdateNetworkInterface(netif) => {
+                assert_eq!(netif.pcap_path, Some("/tmp/foo.pcap".to_string()))
+            }
+            _ => panic!("Test failed."),
+        }
     }
 }
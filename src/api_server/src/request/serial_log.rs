@@ -0,0 +1,24 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::parsed_request::{Error, ParsedRequest};
+use logger::{IncMetric, METRICS};
+use vmm::rpc_interface::VmmAction;
+
+pub(crate) fn parse_get_serial_log() -> Result<ParsedRequest, Error> {
+    METRICS.get_api_requests.serial_log_count.inc();
+    Ok(ParsedRequest::new_sync(VmmAction::GetSerialLog))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_get_serial_log_request() {
+        match parse_get_serial_log() {
+            Ok(ParsedRequest::Sync(action)) if *action == VmmAction::GetSerialLog => {}
+            _ => panic!("Test failed."),
+        }
+    }
+}
@@ -1,11 +1,16 @@
 // Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::os::unix::io::RawFd;
+use std::path::Path;
+
 use super::super::VmmAction;
 use crate::parsed_request::{Error, ParsedRequest};
 use crate::request::Body;
 use crate::request::{Method, StatusCode};
-use vmm::vmm_config::snapshot::{CreateSnapshotParams, LoadSnapshotParams};
+use vmm::vmm_config::snapshot::{
+    CreateSnapshotParams, DirtyPageTrackingConfig, InspectSnapshotParams, LoadSnapshotParams,
+};
 use vmm::vmm_config::snapshot::{Vm, VmState};
 
 pub(crate) fn parse_put_snapshot(
@@ -14,14 +19,29 @@ pub(crate) fn parse_put_snapshot(
 ) -> Result<ParsedRequest, Error> {
     match request_type_from_path {
         Some(&request_type) => match request_type {
-            "create" => Ok(ParsedRequest::new_sync(VmmAction::CreateSnapshot(
-                serde_json::from_slice::<CreateSnapshotParams>(body.raw())
-                    .map_err(Error::SerdeJson)?,
+            "create" => {
+                let params = serde_json::from_slice::<CreateSnapshotParams>(body.raw())
+                    .map_err(Error::SerdeJson)?;
+                check_snapshot_file("snapshot", &params.snapshot_path, params.snapshot_fd)?;
+                check_snapshot_file("mem_file", &params.mem_file_path, params.mem_file_fd)?;
+                Ok(ParsedRequest::new_sync(VmmAction::CreateSnapshot(params)))
+            }
+            "dirty-page-tracking" => Ok(ParsedRequest::new_sync(VmmAction::SetDirtyPageTracking(
+                serde_json::from_slice::<DirtyPageTrackingConfig>(body.raw())
+                    .map_err(Error::SerdeJson)?
+                    .enabled,
             ))),
-            "load" => Ok(ParsedRequest::new_sync(VmmAction::LoadSnapshot(
-                serde_json::from_slice::<LoadSnapshotParams>(body.raw())
+            "inspect" => Ok(ParsedRequest::new_sync(VmmAction::InspectSnapshot(
+                serde_json::from_slice::<InspectSnapshotParams>(body.raw())
                     .map_err(Error::SerdeJson)?,
             ))),
+            "load" => {
+                let params = serde_json::from_slice::<LoadSnapshotParams>(body.raw())
+                    .map_err(Error::SerdeJson)?;
+                check_snapshot_file("snapshot", &params.snapshot_path, params.snapshot_fd)?;
+                check_snapshot_file("mem_file", &params.mem_file_path, params.mem_file_fd)?;
+                Ok(ParsedRequest::new_sync(VmmAction::LoadSnapshot(params)))
+            }
             _ => Err(Error::InvalidPathMethod(
                 format!("/snapshot/{}", request_type),
                 Method::Put,
@@ -34,6 +54,17 @@ pub(crate) fn parse_put_snapshot(
     }
 }
 
+// Each snapshot file is given either by its path or by an inherited file descriptor.
+fn check_snapshot_file(name: &str, path: &Path, fd: Option<RawFd>) -> Result<(), Error> {
+    if path.as_os_str().is_empty() == fd.is_none() {
+        return Err(Error::Generic(
+            StatusCode::BadRequest,
+            format!("Exactly one of {}_path and {}_fd must be set.", name, name),
+        ));
+    }
+    Ok(())
+}
+
 pub(crate) fn parse_patch_vm_state(body: &Body) -> Result<ParsedRequest, Error> {
     let vm = serde_json::from_slice::<Vm>(body.raw()).map_err(Error::SerdeJson)?;
 
@@ -50,8 +81,9 @@ mod tests {
 
     #[test]
     fn test_parse_put_snapshot() {
+        use std::collections::HashMap;
         use std::path::PathBuf;
-        use vmm::vmm_config::snapshot::SnapshotType;
+        use vmm::vmm_config::snapshot::{MemoryMapping, SnapshotType};
 
         let mut body = r#"{
                 "snapshot_type": "Diff",
@@ -63,8 +95,11 @@ mod tests {
         let mut expected_cfg = CreateSnapshotParams {
             snapshot_type: SnapshotType::Diff,
             snapshot_path: PathBuf::from("foo"),
+            snapshot_fd: None,
             mem_file_path: PathBuf::from("bar"),
+            mem_file_fd: None,
             version: Some(String::from("0.23.0")),
+            live: false,
         };
 
         match vmm_action_from_request(
@@ -82,8 +117,11 @@ mod tests {
         expected_cfg = CreateSnapshotParams {
             snapshot_type: SnapshotType::Full,
             snapshot_path: PathBuf::from("foo"),
+            snapshot_fd: None,
             mem_file_path: PathBuf::from("bar"),
+            mem_file_fd: None,
             version: None,
+            live: false,
         };
 
         match vmm_action_from_request(
@@ -107,9 +145,17 @@ mod tests {
 
         let mut expected_cfg = LoadSnapshotParams {
             snapshot_path: PathBuf::from("foo"),
+            snapshot_fd: None,
             mem_file_path: PathBuf::from("bar"),
+            mem_file_fd: None,
             enable_diff_snapshots: false,
             resume_vm: false,
+            rebase_clock: false,
+            require_tsc_scaling: false,
+            verify_mem_checksum: false,
+            drive_paths: HashMap::new(),
+            vcpu_count: None,
+            mem_mapping: MemoryMapping::Private,
         };
         match vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some(&"load")).unwrap())
         {
@@ -125,9 +171,17 @@ mod tests {
 
         expected_cfg = LoadSnapshotParams {
             snapshot_path: PathBuf::from("foo"),
+            snapshot_fd: None,
             mem_file_path: PathBuf::from("bar"),
+            mem_file_fd: None,
             enable_diff_snapshots: true,
             resume_vm: false,
+            rebase_clock: false,
+            require_tsc_scaling: false,
+            verify_mem_checksum: false,
+            drive_paths: HashMap::new(),
+            vcpu_count: None,
+            mem_mapping: MemoryMapping::Private,
         };
 
         match vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some(&"load")).unwrap())
@@ -144,9 +198,46 @@ mod tests {
 
         expected_cfg = LoadSnapshotParams {
             snapshot_path: PathBuf::from("foo"),
+            snapshot_fd: None,
+            mem_file_path: PathBuf::from("bar"),
+            mem_file_fd: None,
+            enable_diff_snapshots: false,
+            resume_vm: true,
+            rebase_clock: false,
+            require_tsc_scaling: false,
+            verify_mem_checksum: false,
+            drive_paths: HashMap::new(),
+            vcpu_count: None,
+            mem_mapping: MemoryMapping::Private,
+        };
+
+        match vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some(&"load")).unwrap())
+        {
+            VmmAction::LoadSnapshot(cfg) => assert_eq!(cfg, expected_cfg),
+            _ => panic!("Test failed."),
+        }
+
+        body = r#"{
+                "snapshot_path": "foo",
+                "mem_file_path": "bar",
+                "resume_vm": true,
+                "rebase_clock": true,
+                "require_tsc_scaling": true
+              }"#;
+
+        expected_cfg = LoadSnapshotParams {
+            snapshot_path: PathBuf::from("foo"),
+            snapshot_fd: None,
             mem_file_path: PathBuf::from("bar"),
+            mem_file_fd: None,
             enable_diff_snapshots: false,
             resume_vm: true,
+            rebase_clock: true,
+            require_tsc_scaling: true,
+            verify_mem_checksum: false,
+            drive_paths: HashMap::new(),
+            vcpu_count: None,
+            mem_mapping: MemoryMapping::Private,
         };
 
         match vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some(&"load")).unwrap())
@@ -155,6 +246,194 @@ mod tests {
             _ => panic!("Test failed."),
         }
 
+        body = r#"{
+                "snapshot_path": "foo",
+                "mem_file_path": "bar",
+                "verify_mem_checksum": true
+              }"#;
+
+        expected_cfg = LoadSnapshotParams {
+            snapshot_path: PathBuf::from("foo"),
+            snapshot_fd: None,
+            mem_file_path: PathBuf::from("bar"),
+            mem_file_fd: None,
+            enable_diff_snapshots: false,
+            resume_vm: false,
+            rebase_clock: false,
+            require_tsc_scaling: false,
+            verify_mem_checksum: true,
+            drive_paths: HashMap::new(),
+            vcpu_count: None,
+            mem_mapping: MemoryMapping::Private,
+        };
+
+        match vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some(&"load")).unwrap())
+        {
+            VmmAction::LoadSnapshot(cfg) => assert_eq!(cfg, expected_cfg),
+            _ => panic!("Test failed."),
+        }
+
+        // The snapshot files can be passed as inherited file descriptors instead of paths.
+        body = r#"{
+                "snapshot_fd": 10,
+                "mem_file_fd": 11
+              }"#;
+        match vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some(&"load")).unwrap())
+        {
+            VmmAction::LoadSnapshot(cfg) => {
+                assert_eq!(cfg.snapshot_path, PathBuf::new());
+                assert_eq!(cfg.snapshot_fd, Some(10));
+                assert_eq!(cfg.mem_file_path, PathBuf::new());
+                assert_eq!(cfg.mem_file_fd, Some(11));
+            }
+            _ => panic!("Test failed."),
+        }
+        match vmm_action_from_request(
+            parse_put_snapshot(&Body::new(body), Some(&"create")).unwrap(),
+        ) {
+            VmmAction::CreateSnapshot(cfg) => {
+                assert_eq!(cfg.snapshot_fd, Some(10));
+                assert_eq!(cfg.mem_file_fd, Some(11));
+            }
+            _ => panic!("Test failed."),
+        }
+
+        // Each file needs exactly one of its path and file descriptor.
+        for body in &[
+            r#"{ "snapshot_path": "foo", "snapshot_fd": 10, "mem_file_path": "bar" }"#,
+            r#"{ "snapshot_path": "foo", "mem_file_path": "bar", "mem_file_fd": 11 }"#,
+            r#"{ "mem_file_path": "bar" }"#,
+            r#"{ "snapshot_fd": 10 }"#,
+        ] {
+            assert!(parse_put_snapshot(&Body::new(*body), Some(&"load")).is_err());
+            assert!(parse_put_snapshot(&Body::new(*body), Some(&"create")).is_err());
+        }
+
+        body = r#"{
+                "snapshot_path": "foo",
+                "mem_file_path": "bar",
+                "drive_paths": {
+                    "rootfs": "/new/rootfs.ext4"
+                }
+              }"#;
+
+        expected_cfg = LoadSnapshotParams {
+            snapshot_path: PathBuf::from("foo"),
+            snapshot_fd: None,
+            mem_file_path: PathBuf::from("bar"),
+            mem_file_fd: None,
+            enable_diff_snapshots: false,
+            resume_vm: false,
+            rebase_clock: false,
+            require_tsc_scaling: false,
+            verify_mem_checksum: false,
+            drive_paths: HashMap::new(),
+            vcpu_count: None,
+            mem_mapping: MemoryMapping::Private,
+        };
+        expected_cfg
+            .drive_paths
+            .insert("rootfs".to_string(), "/new/rootfs.ext4".to_string());
+
+        match vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some(&"load")).unwrap())
+        {
+            VmmAction::LoadSnapshot(cfg) => assert_eq!(cfg, expected_cfg),
+            _ => panic!("Test failed."),
+        }
+
+        body = r#"{
+                "snapshot_path": "foo",
+                "mem_file_path": "bar",
+                "vcpu_count": 2
+              }"#;
+
+        expected_cfg = LoadSnapshotParams {
+            snapshot_path: PathBuf::from("foo"),
+            snapshot_fd: None,
+            mem_file_path: PathBuf::from("bar"),
+            mem_file_fd: None,
+            enable_diff_snapshots: false,
+            resume_vm: false,
+            rebase_clock: false,
+            require_tsc_scaling: false,
+            verify_mem_checksum: false,
+            drive_paths: HashMap::new(),
+            vcpu_count: Some(2),
+            mem_mapping: MemoryMapping::Private,
+        };
+
+        match vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some(&"load")).unwrap())
+        {
+            VmmAction::LoadSnapshot(cfg) => assert_eq!(cfg, expected_cfg),
+            _ => panic!("Test failed."),
+        }
+
+        body = r#"{
+                "snapshot_path": "foo",
+                "mem_file_path": "bar",
+                "mem_mapping": "Shared"
+              }"#;
+
+        expected_cfg = LoadSnapshotParams {
+            snapshot_path: PathBuf::from("foo"),
+            snapshot_fd: None,
+            mem_file_path: PathBuf::from("bar"),
+            mem_file_fd: None,
+            enable_diff_snapshots: false,
+            resume_vm: false,
+            rebase_clock: false,
+            require_tsc_scaling: false,
+            verify_mem_checksum: false,
+            drive_paths: HashMap::new(),
+            vcpu_count: None,
+            mem_mapping: MemoryMapping::Shared,
+        };
+
+        match vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some(&"load")).unwrap())
+        {
+            VmmAction::LoadSnapshot(cfg) => assert_eq!(cfg, expected_cfg),
+            _ => panic!("Test failed."),
+        }
+
+        body = r#"{
+                "snapshot_path": "foo",
+                "mem_file_path": "bar",
+                "mem_mapping": "Invalid"
+              }"#;
+        assert!(parse_put_snapshot(&Body::new(body), Some(&"load")).is_err());
+
+        body = r#"{
+                "snapshot_path": "foo"
+              }"#;
+        match vmm_action_from_request(
+            parse_put_snapshot(&Body::new(body), Some(&"inspect")).unwrap(),
+        ) {
+            VmmAction::InspectSnapshot(params) => assert_eq!(
+                params,
+                InspectSnapshotParams {
+                    snapshot_path: PathBuf::from("foo"),
+                }
+            ),
+            _ => panic!("Test failed."),
+        }
+        // Only the snapshot file is needed.
+        body = r#"{
+                "snapshot_path": "foo",
+                "mem_file_path": "bar"
+              }"#;
+        assert!(parse_put_snapshot(&Body::new(body), Some(&"inspect")).is_err());
+
+        body = r#"{
+                "enabled": false
+              }"#;
+        match vmm_action_from_request(
+            parse_put_snapshot(&Body::new(body), Some(&"dirty-page-tracking")).unwrap(),
+        ) {
+            VmmAction::SetDirtyPageTracking(enabled) => assert!(!enabled),
+            _ => panic!("Test failed."),
+        }
+        assert!(parse_put_snapshot(&Body::new("{}"), Some(&"dirty-page-tracking")).is_err());
+
         assert!(parse_put_snapshot(&Body::new(body), Some(&"invalid")).is_err());
         assert!(parse_put_snapshot(&Body::new(body), None).is_err());
     }
@@ -0,0 +1,39 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::parsed_request::{Error, ParsedRequest};
+use logger::{IncMetric, METRICS};
+use micro_http::StatusCode;
+use vmm::rpc_interface::VmmAction;
+
+pub(crate) fn parse_get_vcpu(id_from_path: Option<&&str>) -> Result<ParsedRequest, Error> {
+    METRICS.get_api_requests.vcpu_count.inc();
+    let id = match id_from_path {
+        Some(id) => id,
+        None => return Err(Error::EmptyID),
+    };
+    let vcpu_id = id.parse::<u8>().map_err(|_| {
+        Error::Generic(
+            StatusCode::BadRequest,
+            format!("Invalid vCPU index `{}`.", id),
+        )
+    })?;
+    Ok(ParsedRequest::new_sync(VmmAction::GetVcpuState(vcpu_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_get_vcpu_request() {
+        match parse_get_vcpu(Some(&"1")) {
+            Ok(ParsedRequest::Sync(action)) if *action == VmmAction::GetVcpuState(1) => {}
+            _ => panic!("Test failed."),
+        }
+
+        assert!(parse_get_vcpu(None).is_err());
+        assert!(parse_get_vcpu(Some(&"foo")).is_err());
+        assert!(parse_get_vcpu(Some(&"256")).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::parsed_request::{Error, ParsedRequest};
+use crate::request::{Body, StatusCode};
+use logger::{IncMetric, METRICS};
+use vmm::resources::VmmConfig;
+use vmm::rpc_interface::VmmAction;
+
+pub(crate) fn parse_put_vm_config(
+    body: &Body,
+    path_second_token: Option<&&str>,
+) -> Result<ParsedRequest, Error> {
+    METRICS.put_api_requests.vm_config_count.inc();
+    if path_second_token != Some(&"config") {
+        METRICS.put_api_requests.vm_config_fails.inc();
+        return Err(Error::Generic(
+            StatusCode::BadRequest,
+            format!(
+                "Unrecognized PUT request path `{}`.",
+                path_second_token.unwrap_or(&"")
+            ),
+        ));
+    }
+
+    Ok(ParsedRequest::new_sync(VmmAction::SetFullVmConfig(
+        serde_json::from_slice::<VmmConfig>(body.raw()).map_err(|e| {
+            METRICS.put_api_requests.vm_config_fails.inc();
+            Error::SerdeJson(e)
+        })?,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsed_request::tests::vmm_action_from_request;
+
+    #[test]
+    fn test_parse_put_vm_config_request() {
+        let body = r#"{
+                "boot-source": {
+                    "kernel_image_path": "vmlinux.bin"
+                },
+                "drives": [
+                    {
+                        "drive_id": "rootfs",
+                        "path_on_host": "rootfs.ext4",
+                        "is_root_device": true,
+                        "is_read_only": false
+                    }
+                ],
+                "machine-config": {
+                    "vcpu_count": 2,
+                    "mem_size_mib": 1024,
+                    "ht_enabled": false
+                }
+              }"#;
+        let expected_config = serde_json::from_str::<VmmConfig>(body).unwrap();
+        assert!(
+            vmm_action_from_request(
+                parse_put_vm_config(&Body::new(body), Some(&"config")).unwrap()
+            ) == VmmAction::SetFullVmConfig(expected_config)
+        );
+
+        assert!(parse_put_vm_config(&Body::new(body), None).is_err());
+        assert!(parse_put_vm_config(&Body::new(body), Some(&"state")).is_err());
+        assert!(parse_put_vm_config(&Body::new("invalid_payload"), Some(&"config")).is_err());
+        // The boot source section is mandatory.
+        assert!(parse_put_vm_config(&Body::new(r#"{ "drives": [] }"#), Some(&"config")).is_err());
+    }
+}
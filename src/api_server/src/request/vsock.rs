@@ -25,6 +25,27 @@ mod tests {
               }"#;
         assert!(parse_put_vsock(&Body::new(body)).is_ok());
 
+        let body = r#"{
+                "vsock_id": "foo",
+                "guest_cid": 42,
+                "uds_path": "vsock.sock",
+                "peer_allowlist": {
+                    "uids": [1000],
+                    "gids": [100]
+                }
+              }"#;
+        assert!(parse_put_vsock(&Body::new(body)).is_ok());
+
+        let body = r#"{
+                "vsock_id": "foo",
+                "guest_cid": 42,
+                "uds_path": "vsock.sock",
+                "peer_allowlist": {
+                    "pids": [1]
+                }
+              }"#;
+        assert!(parse_put_vsock(&Body::new(body)).is_err());
+
         let body = r#"{
                 "vsock_id": "foo",
                 "guest_cid": 42,
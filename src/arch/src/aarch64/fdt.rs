@@ -15,13 +15,15 @@ use std::{io, result};
 use libfdt_bindings::*;
 
 use super::super::DeviceType;
-use super::super::InitrdConfig;
+use super::super::{InitrdConfig, SystemInfo};
 use super::cache_info::{read_cache_config, CacheEntry};
 use super::get_fdt_addr;
 use super::gic::GICDevice;
 use super::layout::FDT_MAX_SIZE;
 use crate::aarch64::fdt::Error::CstringFDTTransform;
-use vm_memory::{Address, Bytes, GuestAddress, GuestMemory, GuestMemoryError, GuestMemoryMmap};
+use vm_memory::{
+    Address, Bytes, GuestAddress, GuestMemory, GuestMemoryError, GuestMemoryMmap, GuestMemoryRegion,
+};
 
 // This is a value for uniquely identifying the FDT node declaring the interrupt controller.
 const GIC_PHANDLE: u32 = 1;
@@ -85,6 +87,9 @@ pub fn create_fdt<T: DeviceInfoForFDT + Clone + Debug, S: std::hash::BuildHasher
     device_info: &HashMap<(DeviceType, String), T, S>,
     gic_device: &dyn GICDevice,
     initrd: &Option<InitrdConfig>,
+    numa_nodes: Option<&[u32]>,
+    system_info: Option<&SystemInfo>,
+    rng_seed: Option<&[u8]>,
 ) -> Result<Vec<u8>> {
     // Allocate stuff necessary for storing the blob.
     let mut fdt = vec![0; FDT_MAX_SIZE];
@@ -98,6 +103,9 @@ pub fn create_fdt<T: DeviceInfoForFDT + Clone + Debug, S: std::hash::BuildHasher
     // Header or the root node as per above mentioned documentation.
     append_begin_node(&mut fdt, "")?;
     append_property_string(&mut fdt, "compatible", "linux,dummy-virt")?;
+    if let Some(system_info) = system_info {
+        create_system_info_properties(&mut fdt, system_info)?;
+    }
     // For info on #address-cells and size-cells read "Note about cells and address representation"
     // from the above mentioned txt file.
     append_property_u32(&mut fdt, "#address-cells", ADDRESS_CELLS)?;
@@ -106,8 +114,11 @@ pub fn create_fdt<T: DeviceInfoForFDT + Clone + Debug, S: std::hash::BuildHasher
     // containing description of the interrupt controller for this VM.
     append_property_u32(&mut fdt, "interrupt-parent", GIC_PHANDLE)?;
     create_cpu_nodes(&mut fdt, &vcpu_mpidr)?;
-    create_memory_node(&mut fdt, guest_mem)?;
-    create_chosen_node(&mut fdt, cmdline, initrd)?;
+    match numa_nodes {
+        Some(numa_nodes) => create_numa_memory_nodes(&mut fdt, guest_mem, numa_nodes)?,
+        None => create_memory_node(&mut fdt, guest_mem)?,
+    }
+    create_chosen_node(&mut fdt, cmdline, initrd, rng_seed)?;
     create_gic_node(&mut fdt, gic_device)?;
     create_timer_node(&mut fdt)?;
     create_clock_node(&mut fdt)?;
@@ -422,6 +433,28 @@ fn create_cpu_nodes(fdt: &mut Vec<u8>, vcpu_mpidr: &[u64]) -> Result<()> {
     Ok(())
 }
 
+// The identity of the system is exposed through the "model" and "serial-number" properties of
+// the root node, see the Devicetree Specification.
+fn create_system_info_properties(fdt: &mut Vec<u8>, system_info: &SystemInfo) -> Result<()> {
+    // The recommended format of the model is "manufacturer,model".
+    let model = [
+        system_info.manufacturer.as_str(),
+        system_info.product_name.as_str(),
+    ]
+    .iter()
+    .filter(|s| !s.is_empty())
+    .cloned()
+    .collect::<Vec<&str>>()
+    .join(",");
+    if !model.is_empty() {
+        append_property_string(fdt, "model", &model)?;
+    }
+    if !system_info.serial_number.is_empty() {
+        append_property_string(fdt, "serial-number", &system_info.serial_number)?;
+    }
+    Ok(())
+}
+
 fn create_memory_node(fdt: &mut Vec<u8>, guest_mem: &GuestMemoryMmap) -> Result<()> {
     let mem_size = guest_mem.last_addr().raw_value() - super::layout::DRAM_MEM_START + 1;
     // See https://github.com/torvalds/linux/blob/master/Documentation/devicetree/booting-without-of.txt#L960
@@ -435,10 +468,34 @@ fn create_memory_node(fdt: &mut Vec<u8>, guest_mem: &GuestMemoryMmap) -> Result<
     Ok(())
 }
 
+// Describes each guest memory region in its own memory node, along with the NUMA node it
+// belongs to. See https://www.kernel.org/doc/Documentation/devicetree/bindings/numa.txt
+fn create_numa_memory_nodes(
+    fdt: &mut Vec<u8>,
+    guest_mem: &GuestMemoryMmap,
+    numa_nodes: &[u32],
+) -> Result<()> {
+    guest_mem.with_regions(|index, region| {
+        let start = region.start_addr().raw_value();
+        let mem_reg_prop = generate_prop64(&[start, region.len()]);
+
+        append_begin_node(fdt, &format!("memory@{:x}", start))?;
+        append_property_string(fdt, "device_type", "memory")?;
+        append_property(fdt, "reg", &mem_reg_prop)?;
+        append_property_u32(
+            fdt,
+            "numa-node-id",
+            numa_nodes.get(index).copied().unwrap_or(0),
+        )?;
+        append_end_node(fdt)
+    })
+}
+
 fn create_chosen_node(
     fdt: &mut Vec<u8>,
     cmdline: &CStr,
     initrd: &Option<InitrdConfig>,
+    rng_seed: Option<&[u8]>,
 ) -> Result<()> {
     append_begin_node(fdt, "chosen")?;
     append_property_cstring(fdt, "bootargs", cmdline)?;
@@ -455,6 +512,10 @@ fn create_chosen_node(
             initrd_config.address.raw_value() + initrd_config.size as u64,
         )?;
     }
+    // The guest kernel wipes the seed from the FDT once it is read.
+    if let Some(rng_seed) = rng_seed {
+        append_property(fdt, "rng-seed", rng_seed)?;
+    }
 
     append_end_node(fdt)?;
 
@@ -595,6 +656,20 @@ fn create_rtc_node<T: DeviceInfoForFDT + Clone + Debug>(
     Ok(())
 }
 
+fn create_pvpanic_node<T: DeviceInfoForFDT + Clone + Debug>(
+    fdt: &mut Vec<u8>,
+    dev_info: &T,
+) -> Result<()> {
+    let compatible = b"qemu,pvpanic-mmio\0";
+    let pvpanic_reg_prop = generate_prop64(&[dev_info.addr(), dev_info.length()]);
+    append_begin_node(fdt, &format!("pvpanic@{:x}", dev_info.addr()))?;
+    append_property(fdt, "compatible", compatible)?;
+    append_property(fdt, "reg", &pvpanic_reg_prop)?;
+    append_end_node(fdt)?;
+
+    Ok(())
+}
+
 fn create_devices_node<T: DeviceInfoForFDT + Clone + Debug, S: std::hash::BuildHasher>(
     fdt: &mut Vec<u8>,
     dev_info: &HashMap<(DeviceType, String), T, S>,
@@ -605,6 +680,9 @@ fn create_devices_node<T: DeviceInfoForFDT + Clone + Debug, S: std::hash::BuildH
     for ((device_type, _device_id), info) in dev_info {
         match device_type {
             DeviceType::BootTimer => (), // since it's not a real device
+            DeviceType::PvPanic => create_pvpanic_node(fdt, info)?,
+            // The guest has no driver for it, its address is reported by `GET /devices`.
+            DeviceType::SnapshotTrigger => (),
             DeviceType::Rtc => create_rtc_node(fdt, info)?,
             DeviceType::Serial => create_serial_node(fdt, info)?,
             DeviceType::Virtio(_) => {
@@ -678,6 +756,13 @@ mod tests {
                     irq: 3,
                 },
             ),
+            (
+                (DeviceType::PvPanic, DeviceType::PvPanic.to_string()),
+                MMIODeviceInfo {
+                    addr: 3 * LEN,
+                    irq: 0,
+                },
+            ),
         ]
         .iter()
         .cloned()
@@ -692,10 +777,45 @@ mod tests {
             &dev_info,
             gic.as_ref(),
             &None,
+            None,
+            Some(&SystemInfo {
+                manufacturer: "Firecracker".to_string(),
+                product_name: "microVM".to_string(),
+                serial_number: "fc-0123".to_string(),
+                uuid: [0; 16],
+            }),
+            Some(&[0xa5; 32]),
         )
         .is_ok())
     }
 
+    #[test]
+    fn test_create_fdt_with_numa_nodes() {
+        let mem = GuestMemoryMmap::from_ranges(&[
+            (GuestAddress(layout::DRAM_MEM_START), 0x1000_0000),
+            (
+                GuestAddress(layout::DRAM_MEM_START + 0x2000_0000),
+                0x1000_0000,
+            ),
+        ])
+        .expect("Cannot initialize memory");
+        let kvm = Kvm::new().unwrap();
+        let vm = kvm.create_vm().unwrap();
+        let gic = create_gic(&vm, 1, None).unwrap();
+        assert!(create_fdt(
+            &mem,
+            vec![0],
+            &CString::new("console=tty0").unwrap(),
+            &HashMap::<(DeviceType, std::string::String), MMIODeviceInfo>::new(),
+            gic.as_ref(),
+            &None,
+            Some(&[0, 1]),
+            None,
+            None,
+        )
+        .is_ok());
+    }
+
     #[test]
     fn test_create_fdt() {
         let regions = arch_memory_regions(layout::FDT_MAX_SIZE + 0x1000);
@@ -717,6 +837,9 @@ mod tests {
             &HashMap::<(DeviceType, std::string::String), MMIODeviceInfo>::new(),
             gic.as_ref(),
             &None,
+            None,
+            None,
+            None,
         )
         .unwrap();
 
@@ -781,6 +904,9 @@ mod tests {
             &HashMap::<(DeviceType, std::string::String), MMIODeviceInfo>::new(),
             gic.as_ref(),
             &Some(initrd),
+            None,
+            None,
+            None,
         )
         .unwrap();
 
@@ -39,6 +39,21 @@ pub fn arch_memory_regions(size: usize) -> Vec<(GuestAddress, usize)> {
     vec![(GuestAddress(layout::DRAM_MEM_START), dram_size)]
 }
 
+/// Checks that guest memory regions, sorted by address and not overlapping, can be used as
+/// the guest memory. The first region has to start at the beginning of the DRAM, where the
+/// kernel is loaded, and all regions have to fit in the DRAM.
+pub fn is_valid_memory_layout(regions: &[(GuestAddress, usize)]) -> bool {
+    match regions.first() {
+        Some((start, _)) if start.raw_value() == layout::DRAM_MEM_START => (),
+        _ => return false,
+    }
+    regions.iter().all(|(start, size)| {
+        start.checked_add(*size as u64).map_or(false, |end| {
+            end.raw_value() <= layout::DRAM_MEM_START + layout::DRAM_MEM_MAX_SIZE
+        })
+    })
+}
+
 /// Configures the system and should be called once per vm before starting vcpu threads.
 /// For aarch64, we only setup the FDT.
 ///
@@ -50,6 +65,9 @@ pub fn arch_memory_regions(size: usize) -> Vec<(GuestAddress, usize)> {
 /// * `device_info` - A hashmap containing the attached devices for building FDT device nodes.
 /// * `gic_device` - The GIC device.
 /// * `initrd` - Information about an optional initrd.
+/// * `numa_nodes` - The NUMA node of each guest memory region, if any.
+/// * `system_info` - Identity of the system, exposed through the FDT root node if provided.
+/// * `rng_seed` - Random seed for the kernel CRNG, passed through the FDT if provided.
 pub fn configure_system<T: DeviceInfoForFDT + Clone + Debug, S: std::hash::BuildHasher>(
     guest_mem: &GuestMemoryMmap,
     cmdline_cstring: &CStr,
@@ -57,6 +75,9 @@ pub fn configure_system<T: DeviceInfoForFDT + Clone + Debug, S: std::hash::Build
     device_info: &HashMap<(DeviceType, String), T, S>,
     gic_device: &dyn GICDevice,
     initrd: &Option<super::InitrdConfig>,
+    numa_nodes: Option<&[u32]>,
+    system_info: Option<&super::SystemInfo>,
+    rng_seed: Option<&[u8]>,
 ) -> super::Result<()> {
     fdt::create_fdt(
         guest_mem,
@@ -65,6 +86,9 @@ pub fn configure_system<T: DeviceInfoForFDT + Clone + Debug, S: std::hash::Build
         device_info,
         gic_device,
         initrd,
+        numa_nodes,
+        system_info,
+        rng_seed,
     )
     .map_err(Error::SetupFDT)?;
     Ok(())
@@ -126,6 +150,30 @@ mod tests {
         assert_eq!(super::layout::DRAM_MEM_MAX_SIZE, regions[0].1 as u64);
     }
 
+    #[test]
+    fn test_is_valid_memory_layout() {
+        assert!(is_valid_memory_layout(&arch_memory_regions(1usize << 29)));
+        assert!(is_valid_memory_layout(&[
+            (GuestAddress(layout::DRAM_MEM_START), 1 << 29),
+            (GuestAddress(layout::DRAM_MEM_START + (1 << 30)), 1 << 29),
+        ]));
+
+        assert!(!is_valid_memory_layout(&[]));
+        // The first region must start at the beginning of the DRAM.
+        assert!(!is_valid_memory_layout(&[(
+            GuestAddress(layout::DRAM_MEM_START + (1 << 30)),
+            1 << 29
+        )]));
+        // All regions must fit in the DRAM.
+        assert!(!is_valid_memory_layout(&[
+            (GuestAddress(layout::DRAM_MEM_START), 1 << 29),
+            (
+                GuestAddress(layout::DRAM_MEM_START + layout::DRAM_MEM_MAX_SIZE),
+                1 << 29
+            ),
+        ]));
+    }
+
     #[test]
     fn test_get_fdt_addr() {
         let regions = arch_memory_regions(layout::FDT_MAX_SIZE - 0x1000);
@@ -17,7 +17,8 @@ pub mod aarch64;
 #[cfg(target_arch = "aarch64")]
 pub use aarch64::{
     arch_memory_regions, configure_system, get_kernel_start, initrd_load_addr,
-    layout::CMDLINE_MAX_SIZE, layout::IRQ_BASE, layout::IRQ_MAX, regs, Error, MMIO_MEM_START,
+    is_valid_memory_layout, layout::CMDLINE_MAX_SIZE, layout::IRQ_BASE, layout::IRQ_MAX, regs,
+    Error, MMIO_MEM_START,
 };
 
 /// Module for x86_64 related functionality.
@@ -27,7 +28,8 @@ pub mod x86_64;
 #[cfg(target_arch = "x86_64")]
 pub use crate::x86_64::{
     arch_memory_regions, configure_system, get_kernel_start, initrd_load_addr,
-    layout::CMDLINE_MAX_SIZE, layout::IRQ_BASE, layout::IRQ_MAX, Error, MMIO_MEM_START,
+    is_valid_memory_layout, layout::CMDLINE_MAX_SIZE, layout::IRQ_BASE, layout::IRQ_MAX, Error,
+    MMIO_MEM_START,
 };
 
 /// Type for returning public functions outcome.
@@ -46,6 +48,10 @@ pub enum DeviceType {
     Rtc,
     /// Device Type: BootTimer.
     BootTimer,
+    /// Device Type: PvPanic.
+    PvPanic,
+    /// Device Type: SnapshotTrigger.
+    SnapshotTrigger,
 }
 
 /// Type for passing information about the initrd in the guest memory.
@@ -56,6 +62,23 @@ pub struct InitrdConfig {
     pub size: usize,
 }
 
+/// Identity of the system exposed to the guest, through the SMBIOS tables on x86_64 and
+/// through the root node of the FDT on aarch64.
+///
+/// The strings can't hold NUL bytes, an empty string is not exposed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SystemInfo {
+    /// Manufacturer of the system.
+    pub manufacturer: String,
+    /// Product name of the system.
+    pub product_name: String,
+    /// Serial number of the system.
+    pub serial_number: String,
+    /// UUID of the system, in the byte order of its string representation. Only exposed on
+    /// x86_64, the FDT has no property for it.
+    pub uuid: [u8; 16],
+}
+
 /// Default (smallest) memory page size for the supported architectures.
 pub const PAGE_SIZE: usize = 4096;
 
@@ -15,6 +15,9 @@ pub const CMDLINE_START: u64 = 0x20000;
 /// Kernel command line start address maximum size.
 pub const CMDLINE_MAX_SIZE: usize = 0x10000;
 
+/// Address of the setup_data list, right after the kernel command line.
+pub const SETUP_DATA_START: u64 = CMDLINE_START + CMDLINE_MAX_SIZE as u64;
+
 /// Start of the high memory.
 pub const HIMEM_START: u64 = 0x0010_0000; //1 MB.
 
@@ -27,5 +30,8 @@ pub const IRQ_MAX: u32 = 23;
 /// Address for the TSS setup.
 pub const KVM_TSS_ADDRESS: u64 = 0xfffb_d000;
 
+/// The SMBIOS entry point, in the BIOS area scanned by the guest kernel.
+pub const SMBIOS_START: u64 = 0xf0000;
+
 /// The 'zero page', a.k.a linux kernel bootparams.
 pub const ZERO_PAGE_START: u64 = 0x7000;
@@ -15,8 +15,9 @@ mod mptable;
 pub mod msr;
 /// Logic for configuring x86_64 registers.
 pub mod regs;
+mod smbios;
 
-use crate::InitrdConfig;
+use crate::{InitrdConfig, SystemInfo};
 use arch_gen::x86::bootparam::{boot_params, E820_RAM};
 use vm_memory::{
     Address, ByteValued, Bytes, GuestAddress, GuestMemory, GuestMemoryMmap, GuestMemoryRegion,
@@ -40,12 +41,31 @@ pub enum Error {
     E820Configuration,
     /// Error writing MP table to memory.
     MpTableSetup(mptable::Error),
+    /// Error writing the setup_data list to memory.
+    SetupDataSetup,
+    /// Error writing the SMBIOS tables to memory.
+    SmbiosSetup(smbios::Error),
     /// Error writing the zero page of guest memory.
     ZeroPageSetup,
     /// Failed to compute initrd address.
     InitrdAddress,
 }
 
+// The header of a setup_data entry, which is followed by `len` bytes of data.
+#[repr(C, packed)]
+#[derive(Copy, Clone, Default)]
+struct SetupDataHeader {
+    next: u64,
+    type_: u32,
+    len: u32,
+}
+
+// It is safe to initialize SetupDataHeader which is a series of ints.
+unsafe impl ByteValued for SetupDataHeader {}
+
+// The type of the setup_data entry holding a random seed for the kernel CRNG.
+const SETUP_RNG_SEED: u32 = 9;
+
 // Where BIOS/VGA magic would live on a real PC.
 const EBDA_START: u64 = 0x9fc00;
 const FIRST_ADDR_PAST_32BITS: u64 = 1 << 32;
@@ -71,6 +91,24 @@ pub fn arch_memory_regions(size: usize) -> Vec<(GuestAddress, usize)> {
     }
 }
 
+/// Checks that guest memory regions, sorted by address and not overlapping, can be used as
+/// the guest memory. The first region has to start at address 0, where the boot structures are
+/// written, and no region can overlap the carve out at the end of 32bit address space.
+pub fn is_valid_memory_layout(regions: &[(GuestAddress, usize)]) -> bool {
+    match regions.first() {
+        Some((start, _)) if start.raw_value() == 0 => (),
+        _ => return false,
+    }
+    regions
+        .iter()
+        .all(|(start, size)| match start.checked_add(*size as u64) {
+            Some(end) => {
+                end.raw_value() <= MMIO_MEM_START || *start >= GuestAddress(FIRST_ADDR_PAST_32BITS)
+            }
+            None => false,
+        })
+}
+
 /// Returns the memory address where the kernel could be loaded.
 pub fn get_kernel_start() -> u64 {
     layout::HIMEM_START
@@ -101,24 +139,28 @@ pub fn initrd_load_addr(guest_mem: &GuestMemoryMmap, initrd_size: usize) -> supe
 /// * `cmdline_size` - Size of the kernel command line in bytes including the null terminator.
 /// * `initrd` - Information about where the ramdisk image was loaded in the `guest_mem`.
 /// * `num_cpus` - Number of virtual CPUs the guest will have.
+/// * `system_info` - Identity of the system, written in the SMBIOS tables if provided.
+/// * `rng_seed` - Random seed for the kernel CRNG, passed through the setup_data if provided.
 pub fn configure_system(
     guest_mem: &GuestMemoryMmap,
     cmdline_addr: GuestAddress,
     cmdline_size: usize,
     initrd: &Option<InitrdConfig>,
     num_cpus: u8,
+    system_info: Option<&SystemInfo>,
+    rng_seed: Option<&[u8]>,
 ) -> super::Result<()> {
     const KERNEL_BOOT_FLAG_MAGIC: u16 = 0xaa55;
     const KERNEL_HDR_MAGIC: u32 = 0x5372_6448;
     const KERNEL_LOADER_OTHER: u8 = 0xff;
     const KERNEL_MIN_ALIGNMENT_BYTES: u32 = 0x0100_0000; // Must be non-zero.
-    let first_addr_past_32bits = GuestAddress(FIRST_ADDR_PAST_32BITS);
-    let end_32bit_gap_start = GuestAddress(MMIO_MEM_START);
-
     let himem_start = GuestAddress(layout::HIMEM_START);
 
     // Note that this puts the mptable at the last 1k of Linux's 640k base RAM
     mptable::setup_mptable(guest_mem, num_cpus).map_err(Error::MpTableSetup)?;
+    if let Some(system_info) = system_info {
+        smbios::setup_smbios(guest_mem, system_info).map_err(Error::SmbiosSetup)?;
+    }
 
     let mut params: BootParamsWrapper = BootParamsWrapper(boot_params::default());
 
@@ -132,40 +174,29 @@ pub fn configure_system(
         params.0.hdr.ramdisk_image = initrd_config.address.raw_value() as u32;
         params.0.hdr.ramdisk_size = initrd_config.size as u32;
     }
+    if let Some(rng_seed) = rng_seed {
+        params.0.hdr.setup_data = setup_rng_seed(guest_mem, rng_seed)?.raw_value();
+    }
 
     add_e820_entry(&mut params.0, 0, EBDA_START, E820_RAM)?;
 
-    let last_addr = guest_mem.last_addr();
-    if last_addr < end_32bit_gap_start {
-        add_e820_entry(
-            &mut params.0,
-            himem_start.raw_value() as u64,
-            // it's safe to use unchecked_offset_from because
-            // mem_end > himem_start
-            last_addr.unchecked_offset_from(himem_start) as u64 + 1,
-            E820_RAM,
-        )?;
-    } else {
+    // Besides the memory below `himem_start`, each guest memory region is usable RAM, so
+    // that holes in the guest memory layout are not reported to the guest.
+    guest_mem.with_regions(|_, region| {
+        let start = std::cmp::max(region.start_addr(), himem_start);
+        let last_addr = region.last_addr();
+        if last_addr < start {
+            return Ok(());
+        }
         add_e820_entry(
             &mut params.0,
-            himem_start.raw_value(),
+            start.raw_value(),
             // it's safe to use unchecked_offset_from because
-            // end_32bit_gap_start > himem_start
-            end_32bit_gap_start.unchecked_offset_from(himem_start),
+            // last_addr >= start
+            last_addr.unchecked_offset_from(start) + 1,
             E820_RAM,
-        )?;
-
-        if last_addr > first_addr_past_32bits {
-            add_e820_entry(
-                &mut params.0,
-                first_addr_past_32bits.raw_value(),
-                // it's safe to use unchecked_offset_from because
-                // mem_end > first_addr_past_32bits
-                last_addr.unchecked_offset_from(first_addr_past_32bits) + 1,
-                E820_RAM,
-            )?;
-        }
-    }
+        )
+    })?;
 
     let zero_page_addr = GuestAddress(layout::ZERO_PAGE_START);
     guest_mem
@@ -175,6 +206,27 @@ pub fn configure_system(
     Ok(())
 }
 
+// Writes a setup_data list holding `rng_seed` and returns its address. The guest kernel wipes
+// the seed from its memory once it is read.
+fn setup_rng_seed(guest_mem: &GuestMemoryMmap, rng_seed: &[u8]) -> super::Result<GuestAddress> {
+    let setup_data_addr = GuestAddress(layout::SETUP_DATA_START);
+    let header = SetupDataHeader {
+        next: 0,
+        type_: SETUP_RNG_SEED,
+        len: rng_seed.len() as u32,
+    };
+    guest_mem
+        .write_obj(header, setup_data_addr)
+        .map_err(|_| Error::SetupDataSetup)?;
+    guest_mem
+        .write_slice(
+            rng_seed,
+            setup_data_addr.unchecked_add(std::mem::size_of::<SetupDataHeader>() as u64),
+        )
+        .map_err(|_| Error::SetupDataSetup)?;
+    Ok(setup_data_addr)
+}
+
 /// Add an e820 region to the e820 map.
 /// Returns Ok(()) if successful, or an error if there is no space left in the map.
 fn add_e820_entry(
@@ -220,7 +272,7 @@ mod tests {
     fn test_system_configuration() {
         let no_vcpus = 4;
         let gm = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), 0x10000)]).unwrap();
-        let config_err = configure_system(&gm, GuestAddress(0), 0, &None, 1);
+        let config_err = configure_system(&gm, GuestAddress(0), 0, &None, 1, None, None);
         assert!(config_err.is_err());
         assert_eq!(
             config_err.unwrap_err(),
@@ -231,19 +283,104 @@ mod tests {
         let mem_size = 128 << 20;
         let arch_mem_regions = arch_memory_regions(mem_size);
         let gm = GuestMemoryMmap::from_ranges(&arch_mem_regions).unwrap();
-        configure_system(&gm, GuestAddress(0), 0, &None, no_vcpus).unwrap();
+        configure_system(&gm, GuestAddress(0), 0, &None, no_vcpus, None, None).unwrap();
+
+        // The SMBIOS tables are only written when the system identity is provided.
+        let mut anchor = [0u8; 5];
+        gm.read_slice(&mut anchor, GuestAddress(layout::SMBIOS_START))
+            .unwrap();
+        assert_eq!(anchor, [0u8; 5]);
+        let system_info = SystemInfo::default();
+        configure_system(
+            &gm,
+            GuestAddress(0),
+            0,
+            &None,
+            no_vcpus,
+            Some(&system_info),
+            None,
+        )
+        .unwrap();
+        gm.read_slice(&mut anchor, GuestAddress(layout::SMBIOS_START))
+            .unwrap();
+        assert_eq!(&anchor, b"_SM3_");
+
+        // The random seed is only passed when provided.
+        let params: BootParamsWrapper = gm.read_obj(GuestAddress(layout::ZERO_PAGE_START)).unwrap();
+        assert_eq!({ params.0.hdr.setup_data }, 0);
+        let rng_seed = [0xa5u8; 32];
+        configure_system(
+            &gm,
+            GuestAddress(0),
+            0,
+            &None,
+            no_vcpus,
+            None,
+            Some(&rng_seed),
+        )
+        .unwrap();
+        let params: BootParamsWrapper = gm.read_obj(GuestAddress(layout::ZERO_PAGE_START)).unwrap();
+        assert_eq!({ params.0.hdr.setup_data }, layout::SETUP_DATA_START);
+        let header: SetupDataHeader = gm.read_obj(GuestAddress(layout::SETUP_DATA_START)).unwrap();
+        assert_eq!(
+            ({ header.next }, { header.type_ }, { header.len }),
+            (0, SETUP_RNG_SEED, 32)
+        );
+        let mut seed = [0u8; 32];
+        gm.read_slice(&mut seed, GuestAddress(layout::SETUP_DATA_START + 16))
+            .unwrap();
+        assert_eq!(seed, rng_seed);
 
         // Now assigning some memory that is equal to the start of the 32bit memory hole.
         let mem_size = 3328 << 20;
         let arch_mem_regions = arch_memory_regions(mem_size);
         let gm = GuestMemoryMmap::from_ranges(&arch_mem_regions).unwrap();
-        configure_system(&gm, GuestAddress(0), 0, &None, no_vcpus).unwrap();
+        configure_system(&gm, GuestAddress(0), 0, &None, no_vcpus, None, None).unwrap();
 
         // Now assigning some memory that falls after the 32bit memory hole.
         let mem_size = 3330 << 20;
         let arch_mem_regions = arch_memory_regions(mem_size);
         let gm = GuestMemoryMmap::from_ranges(&arch_mem_regions).unwrap();
-        configure_system(&gm, GuestAddress(0), 0, &None, no_vcpus).unwrap();
+        configure_system(&gm, GuestAddress(0), 0, &None, no_vcpus, None, None).unwrap();
+
+        // Now assigning a sparse memory layout, whose holes are not reported as RAM.
+        let gm = GuestMemoryMmap::from_ranges(&[
+            (GuestAddress(0), 64 << 20),
+            (GuestAddress(128 << 20), 64 << 20),
+        ])
+        .unwrap();
+        configure_system(&gm, GuestAddress(0), 0, &None, no_vcpus, None, None).unwrap();
+        let params: BootParamsWrapper = gm.read_obj(GuestAddress(layout::ZERO_PAGE_START)).unwrap();
+        assert_eq!(params.0.e820_entries, 3);
+        let e820_map = params.0.e820_map;
+        assert_eq!(
+            (e820_map[1].addr, e820_map[1].size),
+            (layout::HIMEM_START, (64 << 20) - layout::HIMEM_START)
+        );
+        assert_eq!((e820_map[2].addr, e820_map[2].size), (128 << 20, 64 << 20));
+    }
+
+    #[test]
+    fn test_is_valid_memory_layout() {
+        assert!(is_valid_memory_layout(&arch_memory_regions(128 << 20)));
+        assert!(is_valid_memory_layout(&arch_memory_regions(4 << 30)));
+        assert!(is_valid_memory_layout(&[
+            (GuestAddress(0), 64 << 20),
+            (GuestAddress(FIRST_ADDR_PAST_32BITS), 64 << 20),
+            (GuestAddress(FIRST_ADDR_PAST_32BITS + (1 << 30)), 64 << 20),
+        ]));
+
+        assert!(!is_valid_memory_layout(&[]));
+        // The first region must start at 0.
+        assert!(!is_valid_memory_layout(&[(
+            GuestAddress(1 << 20),
+            64 << 20
+        )]));
+        // No region can overlap the MMIO gap.
+        assert!(!is_valid_memory_layout(&[
+            (GuestAddress(0), 64 << 20),
+            (GuestAddress(MMIO_MEM_START - (1 << 20)), 2 << 20),
+        ]));
     }
 
     #[test]
@@ -0,0 +1,307 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! SMBIOS tables, through which the guest reads the identity of the system (DMI).
+//!
+//! The SMBIOS 3.0 entry point is written at the start of the BIOS area, where the guest kernel
+//! scans for it, followed by the structure table. The table holds the BIOS information (type 0),
+//! the system information (type 1) and the end-of-table (type 127) structures.
+
+use std::mem;
+use std::result;
+
+use vm_memory::{Address, ByteValued, Bytes, GuestAddress, GuestMemoryMmap};
+
+use super::layout::SMBIOS_START;
+use crate::SystemInfo;
+
+const SM3_MAGIC_IDENT: [u8; 5] = *b"_SM3_";
+const SMBIOS_MAJOR_VERSION: u8 = 3;
+const SMBIOS_MINOR_VERSION: u8 = 2;
+const ENTRY_POINT_REVISION: u8 = 1;
+// The structure table follows the entry point, at a 16 bytes aligned address.
+const STRUCTURE_TABLE_OFFSET: u64 = 0x20;
+
+const BIOS_INFORMATION: u8 = 0;
+const SYSTEM_INFORMATION: u8 = 1;
+const END_OF_TABLE: u8 = 127;
+
+const BIOS_VENDOR: &str = "Firecracker";
+// The BIOS characteristics are not supported.
+const BIOS_CHARACTERISTICS_NOT_SUPPORTED: u64 = 1 << 3;
+// The SMBIOS table describes a virtual machine.
+const BIOS_CHARACTERISTICS_EXT2_VIRTUAL_MACHINE: u8 = 1 << 4;
+// The value of the BIOS and embedded controller release fields which are not supported.
+const RELEASE_NOT_SUPPORTED: u8 = 0xff;
+// The system is powered on by the VMM, as if through its power switch.
+const WAKE_UP_TYPE_POWER_SWITCH: u8 = 6;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The SMBIOS tables are larger than the BIOS area.
+    TooLarge,
+    /// Failure to write the SMBIOS entry point.
+    WriteEntryPoint,
+    /// Failure to write the SMBIOS structure table.
+    WriteStructureTable,
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Default)]
+struct Smbios3EntryPoint {
+    anchor: [u8; 5],
+    checksum: u8,
+    length: u8,
+    major_version: u8,
+    minor_version: u8,
+    docrev: u8,
+    revision: u8,
+    reserved: u8,
+    max_size: u32,
+    table_address: u64,
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Default)]
+struct StructureHeader {
+    type_: u8,
+    length: u8,
+    handle: u16,
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Default)]
+struct BiosInformation {
+    header: StructureHeader,
+    vendor: u8,
+    version: u8,
+    start_segment: u16,
+    release_date: u8,
+    rom_size: u8,
+    characteristics: u64,
+    characteristics_ext1: u8,
+    characteristics_ext2: u8,
+    bios_major_release: u8,
+    bios_minor_release: u8,
+    ec_major_release: u8,
+    ec_minor_release: u8,
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy, Default)]
+struct SystemInformation {
+    header: StructureHeader,
+    manufacturer: u8,
+    product_name: u8,
+    version: u8,
+    serial_number: u8,
+    uuid: [u8; 16],
+    wake_up_type: u8,
+    sku_number: u8,
+    family: u8,
+}
+
+// These structures are only data, reading them from data is a safe initialization.
+unsafe impl ByteValued for Smbios3EntryPoint {}
+unsafe impl ByteValued for StructureHeader {}
+unsafe impl ByteValued for BiosInformation {}
+unsafe impl ByteValued for SystemInformation {}
+
+// The strings following a structure, which refers to them by their 1-based index.
+#[derive(Default)]
+struct StringSet(Vec<u8>, u8);
+
+impl StringSet {
+    // Returns the index of `s`, 0 for an empty string, which is not stored.
+    fn add(&mut self, s: &str) -> u8 {
+        if s.is_empty() {
+            return 0;
+        }
+        self.0.extend_from_slice(s.as_bytes());
+        self.0.push(0);
+        self.1 += 1;
+        self.1
+    }
+
+    // The set is terminated by an additional NUL byte, or two of them if it is empty.
+    fn into_bytes(mut self) -> Vec<u8> {
+        if self.0.is_empty() {
+            self.0.push(0);
+        }
+        self.0.push(0);
+        self.0
+    }
+}
+
+fn compute_checksum(bytes: &[u8]) -> u8 {
+    let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    (!sum).wrapping_add(1)
+}
+
+// SMBIOS stores the first three fields of the UUID as little endian.
+fn smbios_uuid(uuid: &[u8; 16]) -> [u8; 16] {
+    let mut smbios_uuid = *uuid;
+    smbios_uuid[0..4].reverse();
+    smbios_uuid[4..6].reverse();
+    smbios_uuid[6..8].reverse();
+    smbios_uuid
+}
+
+fn structure_table(system_info: &SystemInfo) -> Vec<u8> {
+    let mut table = Vec::new();
+
+    let mut strings = StringSet::default();
+    let bios_information = BiosInformation {
+        header: StructureHeader {
+            type_: BIOS_INFORMATION,
+            length: mem::size_of::<BiosInformation>() as u8,
+            handle: 0,
+        },
+        vendor: strings.add(BIOS_VENDOR),
+        characteristics: BIOS_CHARACTERISTICS_NOT_SUPPORTED,
+        characteristics_ext2: BIOS_CHARACTERISTICS_EXT2_VIRTUAL_MACHINE,
+        bios_major_release: RELEASE_NOT_SUPPORTED,
+        bios_minor_release: RELEASE_NOT_SUPPORTED,
+        ec_major_release: RELEASE_NOT_SUPPORTED,
+        ec_minor_release: RELEASE_NOT_SUPPORTED,
+        ..Default::default()
+    };
+    table.extend_from_slice(bios_information.as_slice());
+    table.extend(strings.into_bytes());
+
+    let mut strings = StringSet::default();
+    let system_information = SystemInformation {
+        header: StructureHeader {
+            type_: SYSTEM_INFORMATION,
+            length: mem::size_of::<SystemInformation>() as u8,
+            handle: 1,
+        },
+        manufacturer: strings.add(&system_info.manufacturer),
+        product_name: strings.add(&system_info.product_name),
+        serial_number: strings.add(&system_info.serial_number),
+        uuid: smbios_uuid(&system_info.uuid),
+        wake_up_type: WAKE_UP_TYPE_POWER_SWITCH,
+        ..Default::default()
+    };
+    table.extend_from_slice(system_information.as_slice());
+    table.extend(strings.into_bytes());
+
+    let end_of_table = StructureHeader {
+        type_: END_OF_TABLE,
+        length: mem::size_of::<StructureHeader>() as u8,
+        handle: 2,
+    };
+    table.extend_from_slice(end_of_table.as_slice());
+    table.extend(StringSet::default().into_bytes());
+
+    table
+}
+
+/// Writes the SMBIOS tables describing `system_info` in the BIOS area of the guest memory.
+pub fn setup_smbios(mem: &GuestMemoryMmap, system_info: &SystemInfo) -> Result<()> {
+    let table = structure_table(system_info);
+    let table_address = GuestAddress(SMBIOS_START).unchecked_add(STRUCTURE_TABLE_OFFSET);
+    // The BIOS area ends at 1 MiB.
+    if table_address.raw_value() + table.len() as u64 > super::layout::HIMEM_START {
+        return Err(Error::TooLarge);
+    }
+    mem.write_slice(&table, table_address)
+        .map_err(|_| Error::WriteStructureTable)?;
+
+    let mut entry_point = Smbios3EntryPoint {
+        anchor: SM3_MAGIC_IDENT,
+        length: mem::size_of::<Smbios3EntryPoint>() as u8,
+        major_version: SMBIOS_MAJOR_VERSION,
+        minor_version: SMBIOS_MINOR_VERSION,
+        revision: ENTRY_POINT_REVISION,
+        max_size: table.len() as u32,
+        table_address: table_address.raw_value(),
+        ..Default::default()
+    };
+    entry_point.checksum = compute_checksum(entry_point.as_slice());
+    mem.write_obj(entry_point, GuestAddress(SMBIOS_START))
+        .map_err(|_| Error::WriteEntryPoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn system_info() -> SystemInfo {
+        SystemInfo {
+            manufacturer: "Firecracker".to_string(),
+            product_name: "microVM".to_string(),
+            serial_number: String::new(),
+            uuid: [
+                0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0, 0x01, 0x23, 0x45, 0x67, 0x89, 0xab,
+                0xcd, 0xef,
+            ],
+        }
+    }
+
+    #[test]
+    fn test_struct_sizes() {
+        assert_eq!(mem::size_of::<Smbios3EntryPoint>(), 24);
+        assert_eq!(mem::size_of::<BiosInformation>(), 24);
+        assert_eq!(mem::size_of::<SystemInformation>(), 27);
+    }
+
+    #[test]
+    fn test_setup_smbios() {
+        let mem = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), 1 << 20)]).unwrap();
+        setup_smbios(&mem, &system_info()).unwrap();
+
+        let entry_point: Smbios3EntryPoint = mem.read_obj(GuestAddress(SMBIOS_START)).unwrap();
+        assert_eq!(entry_point.anchor, SM3_MAGIC_IDENT);
+        assert_eq!(compute_checksum(entry_point.as_slice()), 0);
+
+        let table_address = entry_point.table_address;
+        let mut table = vec![0u8; entry_point.max_size as usize];
+        mem.read_slice(&mut table, GuestAddress(table_address))
+            .unwrap();
+        assert_eq!(table, structure_table(&system_info()));
+
+        // The BIOS information, with its single string.
+        let bios_len = mem::size_of::<BiosInformation>();
+        assert_eq!(table[0], BIOS_INFORMATION);
+        assert_eq!(&table[bios_len..bios_len + 13], b"Firecracker\0\0");
+
+        // The system information, the empty serial number isn't stored.
+        let system = &table[bios_len + 13..];
+        let system_len = mem::size_of::<SystemInformation>();
+        let system_information = SystemInformation::from_slice(&system[..system_len]).unwrap();
+        assert_eq!(system_information.header.type_, SYSTEM_INFORMATION);
+        assert_eq!(system_information.manufacturer, 1);
+        assert_eq!(system_information.product_name, 2);
+        assert_eq!(system_information.serial_number, 0);
+        assert_eq!(
+            system_information.uuid[..8],
+            [0x78, 0x56, 0x34, 0x12, 0xbc, 0x9a, 0xf0, 0xde]
+        );
+        assert_eq!(system_information.uuid[8..], system_info().uuid[8..]);
+        assert_eq!(
+            &system[system_len..system_len + 21],
+            b"Firecracker\0microVM\0\0"
+        );
+
+        // The end of the table, without strings.
+        assert_eq!(&system[system_len + 21..], &[END_OF_TABLE, 4, 2, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_setup_smbios_too_large() {
+        let mem = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), 1 << 20)]).unwrap();
+        let mut system_info = system_info();
+        system_info.product_name = "x".repeat(0x10000);
+        assert_eq!(setup_smbios(&mem, &system_info), Err(Error::TooLarge));
+
+        // The guest memory has to hold the BIOS area.
+        let mem = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), 0x1000)]).unwrap();
+        assert_eq!(
+            setup_smbios(&mem, &system_info()),
+            Err(Error::WriteStructureTable)
+        );
+    }
+}
@@ -49,6 +49,8 @@ pub mod leaf_0x1 {
         // PDCM = Perfmon and Debug Capability
         pub const PDCM_BITINDEX: u32 = 15;
         // 18 = DCA Direct Cache Access (prefetch data from a memory mapped device)
+        // X2APIC = the local APIC supports the x2APIC mode
+        pub const X2APIC_BITINDEX: u32 = 21;
         pub const MOVBE_BITINDEX: u32 = 22;
         pub const TSC_DEADLINE_TIMER_BITINDEX: u32 = 24;
         pub const OSXSAVE_BITINDEX: u32 = 27;
@@ -253,6 +255,32 @@ pub mod leaf_0xd {
     }
 }
 
+// Processor Frequency Information
+pub mod leaf_0x16 {
+    pub const LEAF_NUM: u32 = 0x16;
+
+    pub mod eax {
+        use crate::bit_helper::BitRange;
+
+        // The processor base frequency, in MHz
+        pub const BASE_FREQUENCY_BITRANGE: BitRange = bit_range!(15, 0);
+    }
+
+    pub mod ebx {
+        use crate::bit_helper::BitRange;
+
+        // The maximum frequency of the processor, in MHz
+        pub const MAX_FREQUENCY_BITRANGE: BitRange = bit_range!(15, 0);
+    }
+
+    pub mod ecx {
+        use crate::bit_helper::BitRange;
+
+        // The bus (reference) frequency, in MHz
+        pub const BUS_FREQUENCY_BITRANGE: BitRange = bit_range!(15, 0);
+    }
+}
+
 pub mod leaf_0x80000000 {
     pub const LEAF_NUM: u32 = 0x8000_0000;
 
@@ -67,3 +67,56 @@ pub fn filter_cpuid(kvm_cpuid: &mut CpuId, vm_spec: &VmSpec) -> Result<(), Error
 
     Ok(())
 }
+
+/// Exposes the x2APIC mode of the local APIC to the guest, or hides it so that the guest uses
+/// the local APIC in xAPIC mode. The x2APIC mode can only be exposed if `kvm_cpuid`, as
+/// supported by KVM, has it.
+pub fn set_x2apic(kvm_cpuid: &mut CpuId, enabled: bool) -> Result<(), Error> {
+    use crate::bit_helper::BitHelper;
+    use crate::cpu_leaf::leaf_0x1;
+
+    for entry in kvm_cpuid
+        .as_mut_slice()
+        .iter_mut()
+        .filter(|entry| entry.function == leaf_0x1::LEAF_NUM)
+    {
+        if enabled && !entry.ecx.read_bit(leaf_0x1::ecx::X2APIC_BITINDEX) {
+            return Err(Error::X2ApicNotSupported);
+        }
+        entry.ecx.write_bit(leaf_0x1::ecx::X2APIC_BITINDEX, enabled);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bit_helper::BitHelper;
+    use crate::cpu_leaf::leaf_0x1;
+
+    #[test]
+    fn test_set_x2apic() {
+        let mut cpuid = CpuId::new(1).unwrap();
+        cpuid.as_mut_slice()[0].function = leaf_0x1::LEAF_NUM;
+        let x2apic = |cpuid: &CpuId| {
+            cpuid.as_slice()[0]
+                .ecx
+                .read_bit(leaf_0x1::ecx::X2APIC_BITINDEX)
+        };
+
+        // The x2APIC mode can't be exposed if KVM doesn't support it.
+        assert!(matches!(
+            set_x2apic(&mut cpuid, true),
+            Err(Error::X2ApicNotSupported)
+        ));
+
+        cpuid.as_mut_slice()[0]
+            .ecx
+            .write_bit(leaf_0x1::ecx::X2APIC_BITINDEX, true);
+        set_x2apic(&mut cpuid, true).unwrap();
+        assert!(x2apic(&cpuid));
+        set_x2apic(&mut cpuid, false).unwrap();
+        assert!(!x2apic(&cpuid));
+    }
+}
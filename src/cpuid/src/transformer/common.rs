@@ -99,6 +99,46 @@ pub fn update_cache_parameters_entry(
     Ok(())
 }
 
+/// Copies the processor frequencies of the host to the guest, so that guest software which relies
+/// on them, e.g. for calibrating the TSC, doesn't see zeros and fall back to slow calibration
+/// loops. The entry is left as is if the host doesn't report them.
+pub fn update_processor_frequency_entry(
+    entry: &mut kvm_cpuid_entry2,
+    _vm_spec: &VmSpec,
+) -> Result<(), Error> {
+    use crate::cpu_leaf::leaf_0x16::*;
+
+    let host_entry = match get_cpuid(LEAF_NUM, 0) {
+        Ok(host_entry) => host_entry,
+        Err(_) => return Ok(()),
+    };
+
+    entry.eax = 0;
+    entry.eax.write_bits_in_range(
+        &eax::BASE_FREQUENCY_BITRANGE,
+        host_entry
+            .eax
+            .read_bits_in_range(&eax::BASE_FREQUENCY_BITRANGE),
+    );
+    entry.ebx = 0;
+    entry.ebx.write_bits_in_range(
+        &ebx::MAX_FREQUENCY_BITRANGE,
+        host_entry
+            .ebx
+            .read_bits_in_range(&ebx::MAX_FREQUENCY_BITRANGE),
+    );
+    entry.ecx = 0;
+    entry.ecx.write_bits_in_range(
+        &ecx::BUS_FREQUENCY_BITRANGE,
+        host_entry
+            .ecx
+            .read_bits_in_range(&ecx::BUS_FREQUENCY_BITRANGE),
+    );
+    entry.edx = 0;
+
+    Ok(())
+}
+
 /// Replaces the `cpuid` entries corresponding to `function` with the entries from the host's cpuid.
 pub fn use_host_cpuid_function(
     cpuid: &mut CpuId,
@@ -254,6 +294,52 @@ mod tests {
         check_update_cache_parameters_entry(2, true, 3, 1);
     }
 
+    #[test]
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn test_update_processor_frequency_entry() {
+        use crate::cpu_leaf::leaf_0x16::*;
+
+        let vm_spec = VmSpec::new(0, 1, false).expect("Error creating vm_spec");
+        let mut entry = &mut kvm_cpuid_entry2 {
+            function: LEAF_NUM,
+            index: 0,
+            flags: 0,
+            eax: 0,
+            ebx: 0,
+            ecx: 0,
+            edx: 0xffff_ffff,
+            padding: [0, 0, 0],
+        };
+
+        assert!(update_processor_frequency_entry(&mut entry, &vm_spec).is_ok());
+
+        match get_cpuid(LEAF_NUM, 0) {
+            Ok(host_entry) => {
+                assert_eq!(
+                    entry.eax,
+                    host_entry
+                        .eax
+                        .read_bits_in_range(&eax::BASE_FREQUENCY_BITRANGE)
+                );
+                assert_eq!(
+                    entry.ebx,
+                    host_entry
+                        .ebx
+                        .read_bits_in_range(&ebx::MAX_FREQUENCY_BITRANGE)
+                );
+                assert_eq!(
+                    entry.ecx,
+                    host_entry
+                        .ecx
+                        .read_bits_in_range(&ecx::BUS_FREQUENCY_BITRANGE)
+                );
+                assert_eq!(entry.edx, 0);
+            }
+            // The entry is left as is on hosts which don't report the frequencies.
+            Err(_) => assert_eq!(entry.edx, 0xffff_ffff),
+        }
+    }
+
     #[test]
     #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
     fn test_use_host_cpuid_function_with_count() {
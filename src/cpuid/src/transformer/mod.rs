@@ -64,6 +64,8 @@ pub enum Error {
     InvalidVendor,
     /// The maximum number of addressable logical CPUs cannot be stored in an `u8`.
     VcpuCountOverflow,
+    /// KVM doesn't support the x2APIC mode of the local APIC.
+    X2ApicNotSupported,
 }
 
 pub type EntryTransformerFn =
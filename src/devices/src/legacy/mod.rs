@@ -6,12 +6,18 @@
 // found in the THIRD-PARTY file.
 
 mod i8042;
+mod pvpanic;
 #[cfg(target_arch = "aarch64")]
 mod rtc_pl031;
 mod serial;
+mod serial_log;
+mod snapshot_trigger;
 
 pub use self::i8042::Error as I8042DeviceError;
 pub use self::i8042::I8042Device;
+pub use self::pvpanic::{PvPanic, PVPANIC_CRASH_LOADED, PVPANIC_PANICKED};
 #[cfg(target_arch = "aarch64")]
-pub use self::rtc_pl031::RTCDevice;
+pub use self::rtc_pl031::{RTCDevice, RTC_LOAD_REGISTER_OFFSET};
 pub use self::serial::{ReadableFd, Serial};
+pub use self::serial_log::{SerialLog, SerialLogWriter};
+pub use self::snapshot_trigger::{SnapshotTrigger, SNAPSHOT_TRIGGER_REQUEST};
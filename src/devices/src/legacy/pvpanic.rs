@@ -0,0 +1,81 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::bus::BusDevice;
+use logger::{error, warn, IncMetric, METRICS};
+use utils::eventfd::EventFd;
+
+/// The guest kernel panicked.
+pub const PVPANIC_PANICKED: u8 = 1 << 0;
+/// The guest kernel panicked and a crash kernel is loaded to handle it.
+pub const PVPANIC_CRASH_LOADED: u8 = 1 << 1;
+
+/// The pvpanic device, through which the guest reports its kernel panics.
+///
+/// The guest reads the events the device supports from its single byte register and writes
+/// the event it hits. A panic is signaled on `panic_evt`, the crash kernel being loaded is only
+/// logged since the guest keeps running to dump its memory.
+pub struct PvPanic {
+    panic_evt: EventFd,
+}
+
+impl PvPanic {
+    /// Creates a device signaling the guest panics on `panic_evt`.
+    pub fn new(panic_evt: EventFd) -> PvPanic {
+        PvPanic { panic_evt }
+    }
+}
+
+impl BusDevice for PvPanic {
+    fn read(&mut self, offset: u64, data: &mut [u8]) {
+        if data.len() != 1 || offset != 0 {
+            return;
+        }
+        data[0] = PVPANIC_PANICKED | PVPANIC_CRASH_LOADED;
+    }
+
+    fn write(&mut self, offset: u64, data: &[u8]) {
+        if data.len() != 1 || offset != 0 {
+            return;
+        }
+        if data[0] & PVPANIC_PANICKED != 0 {
+            METRICS.vmm.guest_panics.inc();
+            if let Err(e) = self.panic_evt.write(1) {
+                error!("Failed to signal the guest panic: {:?}", e);
+            }
+        } else if data[0] & PVPANIC_CRASH_LOADED != 0 {
+            METRICS.vmm.guest_panics.inc();
+            warn!("The guest kernel panicked, its crash kernel is loaded.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pvpanic() {
+        let panic_evt = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        let mut pvpanic = PvPanic::new(panic_evt.try_clone().unwrap());
+
+        let mut data = [0u8];
+        pvpanic.read(0, &mut data);
+        assert_eq!(data[0], PVPANIC_PANICKED | PVPANIC_CRASH_LOADED);
+
+        // Accesses wider than a byte or outside the register are ignored.
+        pvpanic.write(0, &[PVPANIC_PANICKED, 0]);
+        pvpanic.write(1, &[PVPANIC_PANICKED]);
+        assert!(panic_evt.read().is_err());
+
+        // The crash kernel handles the panic, the guest keeps running.
+        let panics = METRICS.vmm.guest_panics.count();
+        pvpanic.write(0, &[PVPANIC_CRASH_LOADED]);
+        assert!(panic_evt.read().is_err());
+        assert_eq!(METRICS.vmm.guest_panics.count(), panics + 1);
+
+        pvpanic.write(0, &[PVPANIC_PANICKED]);
+        assert_eq!(panic_evt.read().unwrap(), 1);
+        assert_eq!(METRICS.vmm.guest_panics.count(), panics + 2);
+    }
+}
@@ -9,6 +9,9 @@ use crate::BusDevice;
 
 pub type RTCDevice = vm_superio::RTC<Arc<RTCDeviceMetrics>>;
 
+/// Offset of the load register (RTCLR), which sets the time the RTC reads.
+pub const RTC_LOAD_REGISTER_OFFSET: u16 = 0x008;
+
 // Implements Bus functions for AMBA PL031 RTC device
 #[cfg(target_arch = "aarch64")]
 impl BusDevice for RTCDevice {
@@ -0,0 +1,131 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+/// Bounded in-memory log of the latest guest serial output.
+///
+/// Clones share the same buffer, so the log can be read while the serial device writes to it.
+#[derive(Clone)]
+pub struct SerialLog {
+    buffer: Arc<Mutex<VecDeque<u8>>>,
+    capacity: usize,
+}
+
+impl SerialLog {
+    /// Creates a log which keeps the last `capacity` bytes of output.
+    pub fn new(capacity: usize) -> Self {
+        SerialLog {
+            buffer: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Appends `bytes` to the log, dropping the oldest output if the log is full.
+    fn record(&self, bytes: &[u8]) {
+        let mut buffer = self.buffer.lock().expect("Poisoned lock");
+        let bytes = &bytes[bytes.len().saturating_sub(self.capacity)..];
+        let overflow = (buffer.len() + bytes.len()).saturating_sub(self.capacity);
+        buffer.drain(..overflow);
+        buffer.extend(bytes);
+    }
+
+    /// Provides the output currently held by the log, oldest first.
+    pub fn contents(&self) -> Vec<u8> {
+        self.buffer
+            .lock()
+            .expect("Poisoned lock")
+            .iter()
+            .copied()
+            .collect()
+    }
+}
+
+/// Writes the guest serial output to another `Write` object, keeping a copy in a `SerialLog`.
+pub struct SerialLogWriter {
+    out: Box<dyn io::Write + Send>,
+    log: SerialLog,
+}
+
+impl SerialLogWriter {
+    /// Creates a writer which forwards the output to `out` and records it in `log`.
+    pub fn new(out: Box<dyn io::Write + Send>, log: SerialLog) -> Self {
+        SerialLogWriter { out, log }
+    }
+}
+
+impl io::Write for SerialLogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let res = self.out.write(buf);
+        // The output is recorded even if `out` failed, so that the log is still available when
+        // the host end of the console is gone. The result is returned unchanged.
+        match res {
+            Ok(count) => self.log.record(&buf[..count]),
+            Err(_) => self.log.record(buf),
+        }
+        res
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    struct FailingWriter;
+
+    impl io::Write for FailingWriter {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(io::ErrorKind::BrokenPipe))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_serial_log() {
+        let log = SerialLog::new(8);
+        assert!(log.contents().is_empty());
+
+        log.record(b"abc");
+        assert_eq!(log.contents(), b"abc");
+
+        // The oldest output is dropped when the log is full.
+        log.record(b"defghi");
+        assert_eq!(log.contents(), b"bcdefghi");
+
+        // Only the tail of an output larger than the log is kept.
+        log.record(b"0123456789");
+        assert_eq!(log.contents(), b"23456789");
+
+        // A log with no capacity keeps nothing.
+        let log = SerialLog::new(0);
+        log.record(b"abc");
+        assert!(log.contents().is_empty());
+    }
+
+    #[test]
+    fn test_serial_log_writer() {
+        let log = SerialLog::new(16);
+        let mut writer = SerialLogWriter::new(Box::new(io::sink()), log.clone());
+        writer.write_all(b"hello").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(log.contents(), b"hello");
+
+        // Errors of the underlying writer are returned, but the output is still recorded.
+        let mut writer = SerialLogWriter::new(Box::new(FailingWriter), log.clone());
+        assert_eq!(
+            writer.write(b"!").unwrap_err().kind(),
+            io::ErrorKind::BrokenPipe
+        );
+        assert_eq!(log.contents(), b"hello!");
+    }
+}
@@ -0,0 +1,57 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::bus::BusDevice;
+use logger::error;
+use utils::eventfd::EventFd;
+
+/// The value the guest writes to ask for a snapshot.
+pub const SNAPSHOT_TRIGGER_REQUEST: u8 = 1;
+
+/// The device through which the guest asks for a snapshot of the microVM.
+///
+/// The guest writes `SNAPSHOT_TRIGGER_REQUEST` to its single byte register, which is signaled
+/// on `trigger_evt`. The snapshot itself is created by the VMM, at the paths configured on the
+/// host, so the guest can only choose when it happens.
+pub struct SnapshotTrigger {
+    trigger_evt: EventFd,
+}
+
+impl SnapshotTrigger {
+    /// Creates a device signaling the snapshot requests of the guest on `trigger_evt`.
+    pub fn new(trigger_evt: EventFd) -> SnapshotTrigger {
+        SnapshotTrigger { trigger_evt }
+    }
+}
+
+impl BusDevice for SnapshotTrigger {
+    fn write(&mut self, offset: u64, data: &[u8]) {
+        if data.len() != 1 || offset != 0 || data[0] != SNAPSHOT_TRIGGER_REQUEST {
+            return;
+        }
+        if let Err(e) = self.trigger_evt.write(1) {
+            error!("Failed to signal the guest snapshot request: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_trigger() {
+        let trigger_evt = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        let mut trigger = SnapshotTrigger::new(trigger_evt.try_clone().unwrap());
+
+        // Accesses wider than a byte, outside the register or of another value are ignored.
+        trigger.write(0, &[SNAPSHOT_TRIGGER_REQUEST, 0]);
+        trigger.write(1, &[SNAPSHOT_TRIGGER_REQUEST]);
+        trigger.write(0, &[0]);
+        assert!(trigger_evt.read().is_err());
+
+        trigger.write(0, &[SNAPSHOT_TRIGGER_REQUEST]);
+        trigger.write(0, &[SNAPSHOT_TRIGGER_REQUEST]);
+        assert_eq!(trigger_evt.read().unwrap(), 2);
+    }
+}
@@ -29,6 +29,16 @@ pub(crate) fn report_balloon_event_fail(err: virtio::balloon::Error) {
     METRICS.balloon.event_fails.inc();
 }
 
+pub(crate) fn report_console_event_fail(err: virtio::console::Error) {
+    error!("{:?}", err);
+    METRICS.console.event_fails.inc();
+}
+
+pub(crate) fn report_fs_event_fail(err: virtio::fs::Error) {
+    error!("{:?}", err);
+    METRICS.fs.event_fails.inc();
+}
+
 #[derive(Debug)]
 pub enum Error {
     /// Failed to read from the TAP device.
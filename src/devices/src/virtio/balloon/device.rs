@@ -11,7 +11,7 @@ use std::time::Duration;
 
 use ::timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
 
-use ::logger::{error, IncMetric, METRICS};
+use ::logger::{error, IncMetric, SharedIncMetric, METRICS};
 use ::utils::eventfd::EventFd;
 use ::virtio_gen::virtio_blk::*;
 use ::vm_memory::{Address, ByteValued, Bytes, GuestAddress, GuestMemoryMmap};
@@ -21,7 +21,7 @@ use super::{
     super::{
         ActivateResult, DeviceState, Queue, VirtioDevice, TYPE_BALLOON, VIRTIO_MMIO_INT_VRING,
     },
-    utils::{compact_page_frame_numbers, remove_range},
+    utils::{compact_page_frame_numbers, free_range, remove_range},
     BALLOON_DEV_ID,
 };
 
@@ -79,6 +79,7 @@ pub struct BalloonConfig {
     pub amount_mib: u32,
     pub deflate_on_oom: bool,
     pub stats_polling_interval_s: u16,
+    pub free_page_reporting: bool,
 }
 
 // BalloonStats holds statistics returned from the stats_queue.
@@ -145,6 +146,7 @@ pub struct Balloon {
     // Transport related fields.
     pub(crate) queues: Vec<Queue>,
     pub(crate) interrupt_status: Arc<AtomicUsize>,
+    pub(crate) irq_counter: Arc<SharedIncMetric>,
     pub(crate) interrupt_evt: EventFd,
     pub(crate) queue_evts: [EventFd; NUM_QUEUES],
     pub(crate) device_state: DeviceState,
@@ -166,6 +168,7 @@ impl Balloon {
         amount_mib: u32,
         deflate_on_oom: bool,
         stats_polling_interval_s: u16,
+        free_page_reporting: bool,
         restored: bool,
     ) -> Result<Balloon, BalloonError> {
         let mut avail_features = 1u64 << VIRTIO_F_VERSION_1;
@@ -178,16 +181,24 @@ impl Balloon {
             avail_features |= 1u64 << VIRTIO_BALLOON_F_STATS_VQ;
         }
 
+        if free_page_reporting {
+            avail_features |= 1u64 << VIRTIO_BALLOON_F_REPORTING;
+        }
+
         let queue_evts = [
             EventFd::new(libc::EFD_NONBLOCK).map_err(BalloonError::EventFd)?,
             EventFd::new(libc::EFD_NONBLOCK).map_err(BalloonError::EventFd)?,
             EventFd::new(libc::EFD_NONBLOCK).map_err(BalloonError::EventFd)?,
+            EventFd::new(libc::EFD_NONBLOCK).map_err(BalloonError::EventFd)?,
         ];
 
         let mut queues: Vec<Queue> = QUEUE_SIZES.iter().map(|&s| Queue::new(s)).collect();
 
-        // The VirtIO specification states that the statistics queue should
-        // not be present at all if the statistics are not enabled.
+        // The VirtIO specification states that the statistics and the free page
+        // reporting queues should not be present at all if they are not enabled.
+        if !free_page_reporting {
+            let _ = queues.remove(REPORTING_INDEX);
+        }
         if stats_polling_interval_s == 0 {
             let _ = queues.remove(STATS_INDEX);
         }
@@ -203,6 +214,7 @@ impl Balloon {
                 actual_pages: 0,
             },
             interrupt_status: Arc::new(AtomicUsize::new(0)),
+            irq_counter: Arc::default(),
             interrupt_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(BalloonError::EventFd)?,
             queue_evts,
             queues,
@@ -238,6 +250,13 @@ impl Balloon {
         self.process_stats_queue()
     }
 
+    pub(crate) fn process_reporting_queue_event(&mut self) -> Result<(), BalloonError> {
+        self.queue_evts[self.reporting_index()]
+            .read()
+            .map_err(BalloonError::EventFd)?;
+        self.process_reporting_queue()
+    }
+
     pub(crate) fn process_stats_timer_event(&mut self) -> Result<(), BalloonError> {
         self.stats_timer.read();
         self.trigger_stats_update()
@@ -391,6 +410,46 @@ impl Balloon {
         Ok(())
     }
 
+    pub(crate) fn process_reporting_queue(&mut self) -> Result<(), BalloonError> {
+        let mem = mem_of_active_device!(self.device_state);
+        METRICS.balloon.free_page_report_count.inc();
+
+        let reporting_index = self.reporting_index();
+        let queue = &mut self.queues[reporting_index];
+        let mut needs_interrupt = false;
+
+        while let Some(head) = queue.pop(&mem) {
+            let head_index = head.index;
+            // Each descriptor of the chain holds a range of free guest memory.
+            let mut next_desc = Some(head);
+            while let Some(desc) = next_desc {
+                match free_range(&mem, (desc.addr, u64::from(desc.len)), self.restored) {
+                    Ok(()) => METRICS
+                        .balloon
+                        .free_page_report_freed
+                        .add(desc.len as usize),
+                    Err(e) => {
+                        error!("Error freeing reported memory range: {:?}", e);
+                        METRICS.balloon.free_page_report_fails.inc();
+                    }
+                }
+                next_desc = desc.next_descriptor();
+            }
+
+            // The reported pages are given back to the driver.
+            queue
+                .add_used(&mem, head_index, 0)
+                .map_err(BalloonError::Queue)?;
+            needs_interrupt = true;
+        }
+
+        if needs_interrupt {
+            self.signal_used_queue()
+        } else {
+            Ok(())
+        }
+    }
+
     pub(crate) fn signal_used_queue(&self) -> Result<(), BalloonError> {
         self.interrupt_status
             .fetch_or(VIRTIO_MMIO_INT_VRING as usize, Ordering::SeqCst);
@@ -399,6 +458,7 @@ impl Balloon {
             error!("Failed to signal used queue: {:?}", e);
             BalloonError::FailedSignalingUsedQueue(e)
         })?;
+        self.irq_counter.inc();
         Ok(())
     }
 
@@ -406,6 +466,9 @@ impl Balloon {
     pub fn process_virtio_queues(&mut self) {
         let _ = self.process_inflate();
         let _ = self.process_deflate_queue();
+        if self.free_page_reporting() {
+            let _ = self.process_reporting_queue();
+        }
     }
 
     pub fn id(&self) -> &str {
@@ -474,6 +537,10 @@ impl Balloon {
         self.avail_features & (1u64 << VIRTIO_BALLOON_F_DEFLATE_ON_OOM) != 0
     }
 
+    pub fn free_page_reporting(&self) -> bool {
+        self.avail_features & (1u64 << VIRTIO_BALLOON_F_REPORTING) != 0
+    }
+
     pub fn stats_polling_interval_s(&self) -> u16 {
         self.stats_polling_interval_s
     }
@@ -495,6 +562,7 @@ impl Balloon {
             amount_mib: self.size_mb(),
             deflate_on_oom: self.deflate_on_oom(),
             stats_polling_interval_s: self.stats_polling_interval_s(),
+            free_page_reporting: self.free_page_reporting(),
         }
     }
 
@@ -502,6 +570,16 @@ impl Balloon {
         self.stats_polling_interval_s > 0
     }
 
+    // The free page reporting queue comes right after the statistics queue,
+    // which is not present if the statistics are disabled.
+    pub(crate) fn reporting_index(&self) -> usize {
+        if self.stats_enabled() {
+            REPORTING_INDEX
+        } else {
+            STATS_INDEX
+        }
+    }
+
     pub(crate) fn set_stats_desc_index(&mut self, stats_desc_index: Option<u16>) {
         self.stats_desc_index = stats_desc_index;
     }
@@ -532,6 +610,14 @@ impl VirtioDevice for Balloon {
         self.interrupt_status.clone()
     }
 
+    fn irq_counter(&self) -> &SharedIncMetric {
+        &self.irq_counter
+    }
+
+    fn set_irq_counter(&mut self, irq_counter: Arc<SharedIncMetric>) {
+        self.irq_counter = irq_counter;
+    }
+
     fn avail_features(&self) -> u64 {
         self.avail_features
     }
@@ -694,36 +780,54 @@ pub(crate) mod tests {
         // Test all feature combinations.
         for deflate_on_oom in vec![true, false].iter() {
             for stats_interval in vec![0, 1].iter() {
-                let mut balloon = Balloon::new(0, *deflate_on_oom, *stats_interval, false).unwrap();
-                assert_eq!(balloon.device_type(), TYPE_BALLOON);
-
-                let features: u64 = (1u64 << VIRTIO_F_VERSION_1)
-                    | ((if *deflate_on_oom { 1 } else { 0 }) << VIRTIO_BALLOON_F_DEFLATE_ON_OOM)
-                    | ((*stats_interval as u64) << VIRTIO_BALLOON_F_STATS_VQ);
+                for free_page_reporting in vec![true, false].iter() {
+                    let mut balloon = Balloon::new(
+                        0,
+                        *deflate_on_oom,
+                        *stats_interval,
+                        *free_page_reporting,
+                        false,
+                    )
+                    .unwrap();
+                    assert_eq!(balloon.device_type(), TYPE_BALLOON);
+
+                    let features: u64 = (1u64 << VIRTIO_F_VERSION_1)
+                        | ((if *deflate_on_oom { 1 } else { 0 })
+                            << VIRTIO_BALLOON_F_DEFLATE_ON_OOM)
+                        | ((*stats_interval as u64) << VIRTIO_BALLOON_F_STATS_VQ)
+                        | ((if *free_page_reporting { 1 } else { 0 })
+                            << VIRTIO_BALLOON_F_REPORTING);
+
+                    assert_eq!(balloon.avail_features_by_page(0), features as u32);
+                    assert_eq!(balloon.avail_features_by_page(1), (features >> 32) as u32);
+                    for i in 2..10 {
+                        assert_eq!(balloon.avail_features_by_page(i), 0u32);
+                    }
 
-                assert_eq!(balloon.avail_features_by_page(0), features as u32);
-                assert_eq!(balloon.avail_features_by_page(1), (features >> 32) as u32);
-                for i in 2..10 {
-                    assert_eq!(balloon.avail_features_by_page(i), 0u32);
-                }
+                    for i in 0..10 {
+                        balloon.ack_features_by_page(i, u32::MAX);
+                    }
+                    // Only present features should be acknowledged.
+                    assert_eq!(balloon.acked_features, features);
 
-                for i in 0..10 {
-                    balloon.ack_features_by_page(i, u32::MAX);
+                    // Only the enabled queues should be present.
+                    let num_queues =
+                        2 + *stats_interval as usize + if *free_page_reporting { 1 } else { 0 };
+                    assert_eq!(balloon.queues().len(), num_queues);
                 }
-                // Only present features should be acknowledged.
-                assert_eq!(balloon.acked_features, features);
             }
         }
     }
 
     #[test]
     fn test_virtio_read_config() {
-        let balloon = Balloon::new(0x10, true, 0, false).unwrap();
+        let balloon = Balloon::new(0x10, true, 0, false, false).unwrap();
 
         let cfg = BalloonConfig {
             amount_mib: 16,
             deflate_on_oom: true,
             stats_polling_interval_s: 0,
+            free_page_reporting: false,
         };
         assert_eq!(balloon.config(), cfg);
 
@@ -749,7 +853,7 @@ pub(crate) mod tests {
 
     #[test]
     fn test_virtio_write_config() {
-        let mut balloon = Balloon::new(0, true, 0, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 0, false, false).unwrap();
 
         let expected_config_space: [u8; CONFIG_SPACE_SIZE] =
             [0x00, 0x50, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
@@ -769,7 +873,7 @@ pub(crate) mod tests {
 
     #[test]
     fn test_invalid_request() {
-        let mut balloon = Balloon::new(0, true, 0, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 0, false, false).unwrap();
         let mem = default_mem();
         // Only initialize the inflate queue to demonstrate invalid request handling.
         let infq = VirtQueue::new(GuestAddress(0), &mem, 16);
@@ -828,7 +932,7 @@ pub(crate) mod tests {
 
     #[test]
     fn test_inflate() {
-        let mut balloon = Balloon::new(0, true, 0, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 0, false, false).unwrap();
         let mem = default_mem();
         let infq = VirtQueue::new(GuestAddress(0), &mem, 16);
         balloon.set_queue(INFLATE_INDEX, infq.create_queue());
@@ -886,7 +990,7 @@ pub(crate) mod tests {
 
     #[test]
     fn test_deflate() {
-        let mut balloon = Balloon::new(0, true, 0, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 0, false, false).unwrap();
         let mem = default_mem();
         let defq = VirtQueue::new(GuestAddress(0), &mem, 16);
         balloon.set_queue(DEFLATE_INDEX, defq.create_queue());
@@ -922,7 +1026,7 @@ pub(crate) mod tests {
 
     #[test]
     fn test_stats() {
-        let mut balloon = Balloon::new(0, true, 1, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 1, false, false).unwrap();
         let mem = default_mem();
         let statsq = VirtQueue::new(GuestAddress(0), &mem, 16);
         balloon.set_queue(STATS_INDEX, statsq.create_queue());
@@ -1001,9 +1105,42 @@ pub(crate) mod tests {
         }
     }
 
+    #[test]
+    fn test_free_page_reporting() {
+        for stats_interval in vec![0, 1].iter() {
+            let mut balloon = Balloon::new(0, true, *stats_interval, true, false).unwrap();
+            // The reporting queue comes after the stats queue, if the latter is present.
+            let reporting_index = balloon.reporting_index();
+            assert_eq!(reporting_index, STATS_INDEX + *stats_interval as usize);
+
+            let mem = default_mem();
+            let repq = VirtQueue::new(GuestAddress(0), &mem, 16);
+            balloon.set_queue(reporting_index, repq.create_queue());
+            balloon.activate(mem).unwrap();
+
+            // Report a free page.
+            set_request(&repq, 0, 0x2000, 0x1000, 0);
+            check_metric_after_block!(METRICS.balloon.free_page_report_freed, 0x1000, {
+                balloon.queue_evts[reporting_index].write(1).unwrap();
+                balloon.process_reporting_queue_event().unwrap();
+            });
+            check_request_completion(&repq, 0);
+            assert_eq!(balloon.interrupt_evt.read().unwrap(), 1);
+
+            // A range which is not page aligned is not freed, but still given back.
+            set_request(&repq, 1, 0x2020, 0x1000, 0);
+            check_metric_after_block!(METRICS.balloon.free_page_report_fails, 1, {
+                balloon.queue_evts[reporting_index].write(1).unwrap();
+                balloon.process_reporting_queue_event().unwrap();
+            });
+            check_request_completion(&repq, 1);
+            assert_eq!(balloon.interrupt_evt.read().unwrap(), 1);
+        }
+    }
+
     #[test]
     fn test_process_balloon_queues() {
-        let mut balloon = Balloon::new(0x10, true, 0, false).unwrap();
+        let mut balloon = Balloon::new(0x10, true, 0, false, false).unwrap();
         let mem = default_mem();
         balloon.activate(mem).unwrap();
         balloon.process_virtio_queues()
@@ -1011,7 +1148,7 @@ pub(crate) mod tests {
 
     #[test]
     fn test_update_stats_interval() {
-        let mut balloon = Balloon::new(0, true, 0, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 0, false, false).unwrap();
         let mem = default_mem();
         balloon.activate(mem).unwrap();
         assert_eq!(
@@ -1020,7 +1157,7 @@ pub(crate) mod tests {
         );
         assert!(balloon.update_stats_polling_interval(0).is_ok());
 
-        let mut balloon = Balloon::new(0, true, 1, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 1, false, false).unwrap();
         let mem = default_mem();
         balloon.activate(mem).unwrap();
         assert_eq!(
@@ -1033,7 +1170,7 @@ pub(crate) mod tests {
 
     #[test]
     fn test_num_pages() {
-        let mut balloon = Balloon::new(0, true, 0, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 0, false, false).unwrap();
         // Assert that we can't update an inactive device.
         assert!(balloon.update_size(1).is_err());
         // Switch the state to active.
@@ -28,6 +28,12 @@ impl Balloon {
                 error!("Failed to register stats timerfd event: {}", e);
             }
         }
+        if self.free_page_reporting() {
+            let reporting_evt = &self.queue_evts[self.reporting_index()];
+            if let Err(e) = ops.add(Events::new(reporting_evt, EventSet::IN)) {
+                error!("Failed to register free page reporting queue event: {}", e);
+            }
+        }
     }
 
     fn register_activate_event(&self, ops: &mut EventOps) {
@@ -66,6 +72,7 @@ impl MutEventSubscriber for Balloon {
             let virtq_inflate_ev_fd = self.queue_evts[INFLATE_INDEX].as_raw_fd();
             let virtq_deflate_ev_fd = self.queue_evts[DEFLATE_INDEX].as_raw_fd();
             let virtq_stats_ev_fd = self.queue_evts[STATS_INDEX].as_raw_fd();
+            let virtq_reporting_ev_fd = self.queue_evts[self.reporting_index()].as_raw_fd();
             let stats_timer_fd = self.stats_timer.as_raw_fd();
             let activate_fd = self.activate_evt.as_raw_fd();
 
@@ -77,6 +84,11 @@ impl MutEventSubscriber for Balloon {
                 _ if source == virtq_deflate_ev_fd => self
                     .process_deflate_queue_event()
                     .unwrap_or_else(report_balloon_event_fail),
+                // Checked before the stats queue, whose event the reporting
+                // queue uses when the statistics are disabled.
+                _ if self.free_page_reporting() && source == virtq_reporting_ev_fd => self
+                    .process_reporting_queue_event()
+                    .unwrap_or_else(report_balloon_event_fail),
                 _ if source == virtq_stats_ev_fd => self
                     .process_stats_queue_event()
                     .unwrap_or_else(report_balloon_event_fail),
@@ -122,7 +134,7 @@ pub mod tests {
     #[test]
     fn test_event_handler() {
         let mut event_manager = EventManager::new().unwrap();
-        let mut balloon = Balloon::new(0, true, 10, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 10, false, false).unwrap();
         let mem = default_mem();
         let infq = VirtQueue::new(GuestAddress(0), &mem, 16);
         balloon.set_queue(INFLATE_INDEX, infq.create_queue());
@@ -19,8 +19,8 @@ pub use self::event_handler::*;
 pub const BALLOON_DEV_ID: &str = "balloon";
 pub const CONFIG_SPACE_SIZE: usize = 8;
 pub const QUEUE_SIZE: u16 = 256;
-pub const NUM_QUEUES: usize = 3;
-pub const QUEUE_SIZES: &[u16] = &[QUEUE_SIZE, QUEUE_SIZE, QUEUE_SIZE];
+pub const NUM_QUEUES: usize = 4;
+pub const QUEUE_SIZES: &[u16] = &[QUEUE_SIZE, QUEUE_SIZE, QUEUE_SIZE, QUEUE_SIZE];
 // Number of 4K pages in a MiB.
 pub const MIB_TO_4K_PAGES: u32 = 256;
 // The maximum number of pages that can be received in a single descriptor.
@@ -36,10 +36,14 @@ pub const INFLATE_INDEX: usize = 0;
 pub const DEFLATE_INDEX: usize = 1;
 // The index of the deflate queue from Balloon device queues/queues_evts vector.
 pub const STATS_INDEX: usize = 2;
+// The index of the free page reporting queue from Balloon device queues/queues_evts vector,
+// when the statistics are enabled. Otherwise, the reporting queue takes the stats queue index.
+pub const REPORTING_INDEX: usize = 3;
 
 // The feature bitmap for virtio balloon.
 const VIRTIO_BALLOON_F_STATS_VQ: u32 = 1; // Enable statistics.
 const VIRTIO_BALLOON_F_DEFLATE_ON_OOM: u32 = 2; // Deflate balloon on OOM.
+const VIRTIO_BALLOON_F_REPORTING: u32 = 5; // Free page reporting.
 
 // The statistics tags.
 const VIRTIO_BALLOON_S_SWAP_IN: u16 = 0;
@@ -114,16 +114,28 @@ impl Persist<'_> for Balloon {
         constructor_args: Self::ConstructorArgs,
         state: &Self::State,
     ) -> std::result::Result<Self, Self::Error> {
+        // The free page reporting state is part of the saved device features.
+        let free_page_reporting =
+            state.virtio_state.avail_features & (1u64 << VIRTIO_BALLOON_F_REPORTING) != 0;
         // We can safely create the balloon with arbitrary flags and
         // num_pages because we will overwrite them after.
-        let mut balloon = Balloon::new(0, false, state.stats_polling_interval_s, true)?;
+        let mut balloon = Balloon::new(
+            0,
+            false,
+            state.stats_polling_interval_s,
+            free_page_reporting,
+            true,
+        )?;
 
         let mut num_queues = NUM_QUEUES;
-        // As per the virtio 1.1 specification, the statistics queue
-        // should not exist if the statistics are not enabled.
+        // As per the virtio 1.1 specification, the statistics and the free page
+        // reporting queues should not exist if they are not enabled.
         if state.stats_polling_interval_s == 0 {
             num_queues -= 1;
         }
+        if !free_page_reporting {
+            num_queues -= 1;
+        }
         balloon.queues = state
             .virtio_state
             .build_queues_checked(&constructor_args.mem, TYPE_BALLOON, num_queues, QUEUE_SIZE)
@@ -175,7 +187,7 @@ mod tests {
         let version_map = VersionMap::new();
 
         // Create and save the balloon device.
-        let balloon = Balloon::new(0x42, false, 2, false).unwrap();
+        let balloon = Balloon::new(0x42, false, 2, false, false).unwrap();
 
         <Balloon as Persist>::save(&balloon)
             .serialize(&mut mem.as_mut_slice(), &version_map, 1)
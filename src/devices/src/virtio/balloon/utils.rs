@@ -7,6 +7,8 @@ use super::{RemoveRegionError, MAX_PAGE_COMPACT_BUFFER};
 use logger::error;
 use vm_memory::{GuestAddress, GuestMemory, GuestMemoryMmap, GuestMemoryRegion};
 
+const PAGE_SIZE: u64 = 4096;
+
 /// This takes a vector of page frame numbers, and compacts them
 /// into ranges of consecutive pages. The result is a vector
 /// of (start_page_frame_number, range_length) pairs.
@@ -116,6 +118,46 @@ pub(crate) fn remove_range(
     }
 }
 
+/// Frees a range of guest memory which the driver reported as unused. The pages are
+/// reclaimed lazily by the host, only under memory pressure.
+pub(crate) fn free_range(
+    guest_memory: &GuestMemoryMmap,
+    range: (GuestAddress, u64),
+    restored: bool,
+) -> std::result::Result<(), RemoveRegionError> {
+    let (guest_address, range_len) = range;
+
+    let region = guest_memory
+        .find_region(guest_address)
+        .ok_or(RemoveRegionError::RegionNotFound)?;
+    let range_end = guest_address
+        .0
+        .checked_add(range_len)
+        .ok_or(RemoveRegionError::MalformedRange)?;
+    if range_end > region.start_addr().0 + region.len()
+        || guest_address.0 % PAGE_SIZE != 0
+        || range_len % PAGE_SIZE != 0
+    {
+        return Err(RemoveRegionError::MalformedRange);
+    }
+
+    // `MADV_FREE` only works on private anonymous mappings, while the guest memory of
+    // a restored microVM is mmaped from file, so the range is removed instead.
+    if restored {
+        return remove_range(guest_memory, range, restored);
+    }
+
+    let phys_address = guest_memory
+        .get_host_address(guest_address)
+        .map_err(|_| RemoveRegionError::AddressTranslation)?;
+    let ret = unsafe { libc::madvise(phys_address as *mut _, range_len as usize, libc::MADV_FREE) };
+    if ret < 0 {
+        return Err(RemoveRegionError::MadviseFail(io::Error::last_os_error()));
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -264,6 +306,48 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_free_range() {
+        let page_size: usize = 0x1000;
+        let mem = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), 2 * page_size)]).unwrap();
+
+        for restored in [false, true].iter() {
+            assert!(free_range(&mem, (GuestAddress(0), page_size as u64), *restored).is_ok());
+            assert!(free_range(&mem, (GuestAddress(0), 2 * page_size as u64), *restored).is_ok());
+
+            // Malformed range: the len is too big.
+            assert_match!(
+                free_range(&mem, (GuestAddress(0), 0x10000), *restored).unwrap_err(),
+                RemoveRegionError::MalformedRange
+            );
+
+            // Malformed range: the range end overflows.
+            assert_match!(
+                free_range(&mem, (GuestAddress(page_size as u64), u64::MAX), *restored)
+                    .unwrap_err(),
+                RemoveRegionError::MalformedRange
+            );
+
+            // Malformed range: the guest address is not aligned to the page size.
+            assert_match!(
+                free_range(&mem, (GuestAddress(0x20), page_size as u64), *restored).unwrap_err(),
+                RemoveRegionError::MalformedRange
+            );
+
+            // Malformed range: the len is not a multiple of the page size.
+            assert_match!(
+                free_range(&mem, (GuestAddress(0), 0x20), *restored).unwrap_err(),
+                RemoveRegionError::MalformedRange
+            );
+
+            // Region not mapped.
+            assert_match!(
+                free_range(&mem, (GuestAddress(0x10000), 0x1000), *restored).unwrap_err(),
+                RemoveRegionError::RegionNotFound
+            );
+        }
+    }
+
     /// -------------------------------------
     /// BEGIN PROPERTY BASED TESTING
     use proptest::prelude::*;
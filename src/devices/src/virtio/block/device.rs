@@ -7,24 +7,32 @@
 
 use std::cmp;
 use std::convert::From;
+use std::ffi::CString;
 use std::fs::{File, OpenOptions};
 use std::io::{self, Seek, SeekFrom, Write};
 use std::os::linux::fs::MetadataExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
 use std::path::PathBuf;
 use std::result;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-use logger::{error, warn, IncMetric, METRICS};
+use logger::{error, warn, IncMetric, SharedIncMetric, METRICS};
 use rate_limiter::{BucketUpdate, RateLimiter, TokenType};
 use utils::eventfd::EventFd;
 use virtio_gen::virtio_blk::*;
 use vm_memory::{Bytes, GuestMemoryMmap};
 
 use super::{
-    super::{ActivateResult, DeviceState, Queue, VirtioDevice, TYPE_BLOCK, VIRTIO_MMIO_INT_VRING},
+    super::{
+        ActivateResult, DeviceState, InterruptCoalescer, Queue, VirtioDevice,
+        DEFAULT_MAX_DESCRIPTORS_PER_EVENT, TYPE_BLOCK, VIRTIO_MMIO_INT_VRING,
+    },
     request::*,
-    Error, CONFIG_SPACE_SIZE, QUEUE_SIZES, SECTOR_SHIFT, SECTOR_SIZE,
+    Error, CONFIG_SPACE_SIZE, MAX_WRITE_ZEROES_SECTORS, MAX_WRITE_ZEROES_SEG, QUEUE_SIZES,
+    SECTOR_SHIFT, SECTOR_SIZE, WRITE_ZEROES_CONFIG_OFFSET, WRITE_ZEROES_CONFIG_SPACE_SIZE,
 };
 
 use crate::virtio::VIRTIO_MMIO_INT_CONFIG;
@@ -50,9 +58,151 @@ impl Default for CacheType {
     }
 }
 
+/// Storage backing the disk of a block device.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum DiskBacking {
+    /// The disk is the host file found at the block device path.
+    File,
+    /// The disk is an anonymous memory region of the given size, in bytes, which starts zeroed.
+    /// The writes never reach a host file, and the content is discarded when the device is
+    /// dropped.
+    Memory(u64),
+}
+
+impl Default for DiskBacking {
+    fn default() -> DiskBacking {
+        DiskBacking::File
+    }
+}
+
+/// Hint about how the guest accesses the disk, given to the host kernel through
+/// `posix_fadvise` to tune the read-ahead of the backing file.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum AccessPattern {
+    /// No hint is given, the host kernel uses its default read-ahead.
+    None,
+    /// The disk is mostly read sequentially, the read-ahead window is enlarged.
+    Sequential,
+    /// The disk is read at random offsets, the read-ahead is disabled.
+    Random,
+}
+
+impl Default for AccessPattern {
+    fn default() -> AccessPattern {
+        AccessPattern::None
+    }
+}
+
+/// The maximum number of times opening a backing file can be retried.
+pub const MAX_OPEN_RETRIES: u32 = 10;
+/// Upper bound for the delay between two attempts to open a backing file, in milliseconds.
+pub const MAX_OPEN_RETRY_BACKOFF_MS: u64 = 1000;
+
+/// Configuration of the retries performed when opening the backing file fails with a
+/// transient error (e.g. `EBUSY` or `ESTALE` on network filesystems). Errors such as `ENOENT`
+/// or `EACCES` are never retried.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OpenRetryConfig {
+    /// Number of times opening the backing file is retried.
+    pub max_retries: u32,
+    /// Delay before the first retry, in milliseconds. It doubles with every subsequent retry,
+    /// up to `MAX_OPEN_RETRY_BACKOFF_MS`.
+    pub initial_backoff_ms: u64,
+}
+
+impl Default for OpenRetryConfig {
+    fn default() -> Self {
+        OpenRetryConfig {
+            max_retries: 3,
+            initial_backoff_ms: 10,
+        }
+    }
+}
+
+// Errors which may go away if opening the backing file is attempted again.
+fn is_transient_open_error(err: &io::Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(libc::EAGAIN)
+            | Some(libc::EBUSY)
+            | Some(libc::EINTR)
+            | Some(libc::ESTALE)
+            | Some(libc::ETIMEDOUT)
+    )
+}
+
+fn open_disk_image(
+    disk_image_path: &str,
+    is_disk_read_only: bool,
+    retry_config: OpenRetryConfig,
+) -> io::Result<File> {
+    let mut retries_left = retry_config.max_retries;
+    let mut backoff_ms = cmp::min(retry_config.initial_backoff_ms, MAX_OPEN_RETRY_BACKOFF_MS);
+
+    loop {
+        match OpenOptions::new()
+            .read(true)
+            .write(!is_disk_read_only)
+            .open(PathBuf::from(disk_image_path))
+        {
+            Err(err) if retries_left > 0 && is_transient_open_error(&err) => {
+                warn!(
+                    "Failed to open block device backing file {}: {}. Retrying in {} ms.",
+                    disk_image_path, err, backoff_ms
+                );
+                thread::sleep(Duration::from_millis(backoff_ms));
+                backoff_ms = cmp::min(backoff_ms.saturating_mul(2), MAX_OPEN_RETRY_BACKOFF_MS);
+                retries_left -= 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Passes the access pattern hint for the whole backing file to the host kernel.
+fn advise_disk_image(disk_image: &File, access_pattern: AccessPattern) -> io::Result<()> {
+    let advice = match access_pattern {
+        AccessPattern::None => return Ok(()),
+        AccessPattern::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+        AccessPattern::Random => libc::POSIX_FADV_RANDOM,
+    };
+    // Safe because the file descriptor is valid and we check the return value, which is the
+    // error number itself rather than -1.
+    let ret = unsafe { libc::posix_fadvise(disk_image.as_raw_fd(), 0, 0, advice) };
+    if ret != 0 {
+        return Err(io::Error::from_raw_os_error(ret));
+    }
+    Ok(())
+}
+
+// Creates an anonymous, memory backed file of `size` bytes, to be used as a disk image.
+fn create_memory_disk_image(size: u64) -> io::Result<File> {
+    // The name is only used for debugging, e.g. in /proc/self/fd.
+    let name = CString::new("fc_memory_disk").unwrap();
+    // Safe because the name is a valid C string and we check the return value.
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_memfd_create,
+            name.as_ptr(),
+            libc::MFD_CLOEXEC as libc::c_uint,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // Safe because the file descriptor was just created and nothing else owns it.
+    let disk_image = unsafe { File::from_raw_fd(fd as libc::c_int) };
+    disk_image.set_len(size)?;
+    Ok(disk_image)
+}
+
 /// Helper object for setting up all `Block` fields derived from its backing file.
 pub(crate) struct DiskProperties {
     cache_type: CacheType,
+    backing: DiskBacking,
+    open_retry: OpenRetryConfig,
+    access_pattern: AccessPattern,
     file_path: String,
     file: File,
     nsectors: u64,
@@ -62,13 +212,29 @@ pub(crate) struct DiskProperties {
 impl DiskProperties {
     pub fn new(
         disk_image_path: String,
+        backing: DiskBacking,
         is_disk_read_only: bool,
         cache_type: CacheType,
+        open_retry: OpenRetryConfig,
+        access_pattern: AccessPattern,
     ) -> io::Result<Self> {
-        let mut disk_image = OpenOptions::new()
-            .read(true)
-            .write(!is_disk_read_only)
-            .open(PathBuf::from(&disk_image_path))?;
+        let mut disk_image = match backing {
+            DiskBacking::File => {
+                let disk_image = open_disk_image(&disk_image_path, is_disk_read_only, open_retry)?;
+                // The hint only tunes the host page cache, the disk works without it.
+                if let Err(e) = advise_disk_image(&disk_image, access_pattern) {
+                    warn!(
+                        "Failed to set the access pattern of block device backing file {}: {}",
+                        disk_image_path, e
+                    );
+                }
+                disk_image
+            }
+            DiskBacking::Memory(size) => create_memory_disk_image(size)?,
+        };
+        // The metadata of a block special file, e.g. a raw partition or a LVM volume, reports a
+        // zero length, so the size is read by seeking to the end, which works for both. This is
+        // also how the size is read again when the backing file is updated.
         let disk_size = disk_image.seek(SeekFrom::End(0))? as u64;
 
         // We only support disk size, which uses the first two words of the configuration space.
@@ -83,6 +249,9 @@ impl DiskProperties {
 
         Ok(Self {
             cache_type,
+            backing,
+            open_retry,
+            access_pattern,
             nsectors: disk_size >> SECTOR_SHIFT,
             image_id: Self::build_disk_image_id(&disk_image),
             file_path: disk_image_path,
@@ -140,21 +309,90 @@ impl DiskProperties {
         &self.file_path
     }
 
+    pub fn backing(&self) -> DiskBacking {
+        self.backing
+    }
+
     /// Provides vec containing the virtio block configuration space
     /// buffer. The config space is populated with the disk size based
-    /// on the backing file size.
-    pub fn virtio_block_config_space(&self) -> Vec<u8> {
+    /// on the backing file size, followed by the write zeroes limits
+    /// if `write_zeroes` is set.
+    pub fn virtio_block_config_space(&self, write_zeroes: bool) -> Vec<u8> {
         // The config space is little endian.
-        let mut config = Vec::with_capacity(CONFIG_SPACE_SIZE);
+        let mut config = Vec::with_capacity(WRITE_ZEROES_CONFIG_SPACE_SIZE);
         for i in 0..CONFIG_SPACE_SIZE {
             config.push((self.nsectors >> (8 * i)) as u8);
         }
+        if write_zeroes {
+            // The fields in between belong to features which are not advertised.
+            config.resize(WRITE_ZEROES_CONFIG_OFFSET, 0);
+            config.extend_from_slice(&MAX_WRITE_ZEROES_SECTORS.to_le_bytes());
+            config.extend_from_slice(&MAX_WRITE_ZEROES_SEG.to_le_bytes());
+            // `write_zeroes_may_unmap`: the sectors are deallocated when the driver allows it.
+            config.push(1);
+            config.resize(WRITE_ZEROES_CONFIG_SPACE_SIZE, 0);
+        }
         config
     }
 
+    /// Zeroes `len` bytes of the backing file, starting at `offset`. If `unmap` is set, the
+    /// bytes are deallocated if the host file system supports it.
+    pub fn write_zeroes(&mut self, offset: u64, len: u64, unmap: bool) -> io::Result<()> {
+        const PUNCH_HOLE: libc::c_int = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+        const ZERO_RANGE: libc::c_int = libc::FALLOC_FL_ZERO_RANGE | libc::FALLOC_FL_KEEP_SIZE;
+
+        if unmap && self.fallocate(PUNCH_HOLE, offset, len).is_ok() {
+            return Ok(());
+        }
+        match self.fallocate(ZERO_RANGE, offset, len) {
+            // Not every file system, nor every block device, supports zeroing ranges.
+            Err(ref e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => {
+                self.write_zeroes_fallback(offset, len)
+            }
+            result => result,
+        }
+    }
+
+    fn fallocate(&self, mode: libc::c_int, offset: u64, len: u64) -> io::Result<()> {
+        // Safe because the file descriptor is valid and we check the return value.
+        let ret = unsafe {
+            libc::fallocate(
+                self.file.as_raw_fd(),
+                mode,
+                offset as libc::off_t,
+                len as libc::off_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn write_zeroes_fallback(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        const ZEROES_LEN: u64 = 64 * 1024;
+        let zeroes = [0u8; ZEROES_LEN as usize];
+        self.file.seek(SeekFrom::Start(offset))?;
+        let mut remaining = len;
+        while remaining > 0 {
+            let count = cmp::min(remaining, ZEROES_LEN);
+            self.file.write_all(&zeroes[..count as usize])?;
+            remaining -= count;
+        }
+        Ok(())
+    }
+
     pub fn cache_type(&self) -> CacheType {
         self.cache_type
     }
+
+    pub fn open_retry(&self) -> OpenRetryConfig {
+        self.open_retry
+    }
+
+    pub fn access_pattern(&self) -> AccessPattern {
+        self.access_pattern
+    }
 }
 
 impl Drop for DiskProperties {
@@ -192,7 +430,9 @@ pub struct Block {
     // Transport related fields.
     pub(crate) queues: Vec<Queue>,
     pub(crate) interrupt_status: Arc<AtomicUsize>,
+    pub(crate) irq_counter: Arc<SharedIncMetric>,
     pub(crate) interrupt_evt: EventFd,
+    pub(crate) irq_coalescer: InterruptCoalescer,
     pub(crate) queue_evts: [EventFd; 1],
     pub(crate) device_state: DeviceState,
 
@@ -200,28 +440,46 @@ pub struct Block {
     pub(crate) id: String,
     pub(crate) partuuid: Option<String>,
     pub(crate) root_device: bool,
+    pub(crate) boot_index: Option<u32>,
     pub(crate) rate_limiter: RateLimiter,
+    pub(crate) max_descriptors_per_event: u16,
 }
 
 impl Block {
     /// Create a new virtio block device that operates on the given file.
     ///
     /// The given file must be seekable and sizable.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: String,
         partuuid: Option<String>,
         cache_type: CacheType,
         disk_image_path: String,
+        backing: DiskBacking,
         is_disk_read_only: bool,
         is_disk_root: bool,
-        rate_limiter: RateLimiter,
+        boot_index: Option<u32>,
+        mut rate_limiter: RateLimiter,
+        open_retry: OpenRetryConfig,
+        access_pattern: AccessPattern,
     ) -> io::Result<Block> {
-        let disk_properties = DiskProperties::new(disk_image_path, is_disk_read_only, cache_type)?;
+        let disk_properties = DiskProperties::new(
+            disk_image_path,
+            backing,
+            is_disk_read_only,
+            cache_type,
+            open_retry,
+            access_pattern,
+        )?;
+
+        rate_limiter.set_metrics(METRICS.rate_limiters.get(&format!("block_{}", id)));
 
         let mut avail_features = (1u64 << VIRTIO_F_VERSION_1) | (1u64 << VIRTIO_BLK_F_FLUSH);
 
         if is_disk_read_only {
             avail_features |= 1u64 << VIRTIO_BLK_F_RO;
+        } else {
+            avail_features |= 1u64 << VIRTIO_BLK_F_WRITE_ZEROES;
         };
 
         let queue_evts = [EventFd::new(libc::EFD_NONBLOCK)?];
@@ -231,14 +489,18 @@ impl Block {
         Ok(Block {
             id,
             root_device: is_disk_root,
+            boot_index,
             partuuid,
             rate_limiter,
-            config_space: disk_properties.virtio_block_config_space(),
+            max_descriptors_per_event: DEFAULT_MAX_DESCRIPTORS_PER_EVENT,
+            config_space: disk_properties.virtio_block_config_space(!is_disk_read_only),
             disk: disk_properties,
             avail_features,
             acked_features: 0u64,
             interrupt_status: Arc::new(AtomicUsize::new(0)),
+            irq_counter: Arc::default(),
             interrupt_evt: EventFd::new(libc::EFD_NONBLOCK)?,
+            irq_coalescer: InterruptCoalescer::new(0)?,
             queue_evts,
             queues,
             device_state: DeviceState::Inactive,
@@ -274,6 +536,20 @@ impl Block {
         }
     }
 
+    pub(crate) fn process_irq_coalescer_event(&mut self) {
+        if self.irq_coalescer.event_handler() {
+            let _ = self.raise_used_queue_interrupt();
+        }
+    }
+
+    /// Raises the interrupt deferred by the interrupt coalescing, if any, e.g. before saving the
+    /// device state.
+    pub fn flush_coalesced_interrupt(&mut self) {
+        if self.irq_coalescer.flush() {
+            let _ = self.raise_used_queue_interrupt();
+        }
+    }
+
     pub fn process_queue(&mut self, queue_index: usize) -> bool {
         let mem = match self.device_state {
             DeviceState::Activated(ref mem) => mem,
@@ -282,8 +558,23 @@ impl Block {
         };
         let queue = &mut self.queues[queue_index];
         let mut used_any = false;
+        let mut processed: u16 = 0;
+        // Adjacent reads or writes are gathered, to be served by a single vectored IO.
+        let mut batch: Vec<(u16, Request)> = Vec::new();
         while let Some(head) = queue.pop(mem) {
-            let len = match Request::parse(&head, mem) {
+            if processed == self.max_descriptors_per_event {
+                // Leave the rest of the queue for the next event, so that a busy queue doesn't
+                // hold up the other devices and the API.
+                queue.undo_pop();
+                if let Err(e) = self.queue_evts[queue_index].write(1) {
+                    error!("Failed to signal queue event: {:?}", e);
+                    METRICS.block.event_fails.inc();
+                }
+                break;
+            }
+            processed += 1;
+
+            match Request::parse(&head, mem) {
                 Ok(request) => {
                     // If limiter.consume() fails it means there is no more TokenType::Ops
                     // budget and rate limiting is in effect.
@@ -314,38 +605,24 @@ impl Block {
                         }
                     }
 
-                    let status = Status::from_result(request.execute(&mut self.disk, mem));
-                    let virtio_blk_status = status.virtio_blk_status();
-                    let num_used_bytes = status.num_used_bytes();
-                    if let Status::Err(err_status) = status {
-                        METRICS.block.invalid_reqs_count.inc();
-                        error!(
-                            "Failed to execute {:?} virtio block request: {:?}",
-                            request.request_type, err_status
-                        );
-                    }
-
-                    if let Err(e) = mem.write_obj(virtio_blk_status, request.status_addr) {
-                        error!("Failed to write virtio block status: {:?}", e)
+                    if let Some((_, last_request)) = batch.last() {
+                        if batch.len() == MAX_COALESCED_REQUESTS
+                            || !last_request.is_followed_by(&request)
+                        {
+                            Self::execute_batch(&mut batch, &mut self.disk, mem, queue);
+                        }
                     }
-
-                    num_used_bytes
+                    batch.push((head.index, request));
                 }
                 Err(e) => {
                     error!("Failed to parse available descriptor chain: {:?}", e);
                     METRICS.block.execute_fails.inc();
-                    0
+                    Self::add_used(mem, queue, head.index, 0);
                 }
-            };
-
-            queue.add_used(mem, head.index, len).unwrap_or_else(|e| {
-                error!(
-                    "Failed to add available descriptor head {}: {}",
-                    head.index, e
-                )
-            });
+            }
             used_any = true;
         }
+        Self::execute_batch(&mut batch, &mut self.disk, mem, queue);
 
         if !used_any {
             METRICS.block.no_avail_buffer.inc();
@@ -354,7 +631,58 @@ impl Block {
         used_any
     }
 
-    pub(crate) fn signal_used_queue(&self) -> result::Result<(), DeviceError> {
+    // Executes the gathered requests, then writes their statuses and hands them back to the
+    // driver.
+    fn execute_batch(
+        batch: &mut Vec<(u16, Request)>,
+        disk: &mut DiskProperties,
+        mem: &GuestMemoryMmap,
+        queue: &mut Queue,
+    ) {
+        if batch.is_empty() {
+            return;
+        }
+        let (head_indexes, requests): (Vec<u16>, Vec<Request>) = batch.drain(..).unzip();
+        let results = Request::execute_batch(&requests, disk, mem);
+
+        for ((head_index, request), result) in head_indexes.into_iter().zip(requests).zip(results) {
+            let status = Status::from_result(result);
+            let virtio_blk_status = status.virtio_blk_status();
+            let num_used_bytes = status.num_used_bytes();
+            if let Status::Err(err_status) = status {
+                METRICS.block.invalid_reqs_count.inc();
+                error!(
+                    "Failed to execute {:?} virtio block request: {:?}",
+                    request.request_type, err_status
+                );
+            }
+
+            if let Err(e) = mem.write_obj(virtio_blk_status, request.status_addr) {
+                error!("Failed to write virtio block status: {:?}", e)
+            }
+
+            Self::add_used(mem, queue, head_index, num_used_bytes);
+        }
+    }
+
+    fn add_used(mem: &GuestMemoryMmap, queue: &mut Queue, head_index: u16, len: u32) {
+        queue.add_used(mem, head_index, len).unwrap_or_else(|e| {
+            error!(
+                "Failed to add available descriptor head {}: {}",
+                head_index, e
+            )
+        });
+    }
+
+    pub(crate) fn signal_used_queue(&mut self) -> result::Result<(), DeviceError> {
+        if !self.irq_coalescer.request() {
+            METRICS.block.coalesced_irq_count.inc();
+            return Ok(());
+        }
+        self.raise_used_queue_interrupt()
+    }
+
+    fn raise_used_queue_interrupt(&self) -> result::Result<(), DeviceError> {
         self.interrupt_status
             .fetch_or(VIRTIO_MMIO_INT_VRING as usize, Ordering::SeqCst);
 
@@ -363,20 +691,45 @@ impl Block {
             METRICS.block.event_fails.inc();
             DeviceError::FailedSignalingUsedQueue(e)
         })?;
+        self.irq_counter.inc();
         Ok(())
     }
 
     /// Update the backing file and the config space of the block device.
     pub fn update_disk_image(&mut self, disk_image_path: String) -> io::Result<()> {
-        let disk_properties =
-            DiskProperties::new(disk_image_path, self.is_read_only(), self.cache_type())?;
+        // A memory backed disk has a fixed size and no backing file to switch to.
+        if let DiskBacking::Memory(_) = self.backing() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The disk of the block device is memory backed.",
+            ));
+        }
+        // The update runs on the VMM thread, which must not sleep between the attempts to open
+        // the new backing file, so it is opened without retries. The retry configuration is
+        // kept for the device though.
+        let open_retry = self.open_retry();
+        let mut disk_properties = DiskProperties::new(
+            disk_image_path,
+            DiskBacking::File,
+            self.is_read_only(),
+            self.cache_type(),
+            OpenRetryConfig {
+                max_retries: 0,
+                ..open_retry
+            },
+            self.access_pattern(),
+        )?;
+        disk_properties.open_retry = open_retry;
         self.disk = disk_properties;
-        self.config_space = self.disk.virtio_block_config_space();
+        self.config_space = self
+            .disk
+            .virtio_block_config_space(self.supports_write_zeroes());
 
         // Kick the driver to pick up the changes.
         self.interrupt_status
             .fetch_or(VIRTIO_MMIO_INT_CONFIG as usize, Ordering::SeqCst);
         self.interrupt_evt.write(1).unwrap();
+        self.irq_counter.inc();
 
         METRICS.block.update_count.inc();
         Ok(())
@@ -387,6 +740,23 @@ impl Block {
         self.rate_limiter.update_buckets(bytes, ops);
     }
 
+    /// Sets the number of descriptor chains processed from the queue for one event.
+    pub fn set_max_descriptors_per_event(&mut self, max_descriptors_per_event: u16) {
+        self.max_descriptors_per_event = max_descriptors_per_event;
+    }
+
+    /// Returns the minimum time between two used queue interrupts, in microseconds.
+    pub fn interrupt_coalescing_us(&self) -> u64 {
+        self.irq_coalescer.interval_us()
+    }
+
+    /// Sets the minimum time between two used queue interrupts, in microseconds. The interrupts
+    /// requested sooner are merged and raised at the end of the interval. Every interrupt is
+    /// raised right away when this is 0, which is the default.
+    pub fn set_interrupt_coalescing_us(&mut self, interrupt_coalescing_us: u64) {
+        self.irq_coalescer.set_interval_us(interrupt_coalescing_us);
+    }
+
     /// Provides the ID of this block device.
     pub fn id(&self) -> &String {
         &self.id
@@ -397,6 +767,26 @@ impl Block {
         self.disk.file_path()
     }
 
+    /// Provides the storage backing the disk of this block device.
+    pub fn backing(&self) -> DiskBacking {
+        self.disk.backing()
+    }
+
+    /// Writes the data of the backing file out to the host storage.
+    pub fn flush_disk(&mut self) -> io::Result<()> {
+        let file = self.disk.file_mut();
+        file.flush()?;
+        file.sync_all()
+    }
+
+    /// Writes the data of the backing file out to the host storage, so that a snapshot of the
+    /// paused microVM captures a disk consistent with the guest memory.
+    pub fn sync_disk(&mut self) -> io::Result<()> {
+        self.flush_disk()?;
+        METRICS.block_snapshot_flushes.get(&self.id).inc();
+        Ok(())
+    }
+
     /// Provides the PARTUUID of this block device.
     pub fn partuuid(&self) -> Option<&String> {
         self.partuuid.as_ref()
@@ -407,16 +797,36 @@ impl Block {
         self.avail_features & (1u64 << VIRTIO_BLK_F_RO) != 0
     }
 
+    /// Specifies if this block device advertises the write zeroes requests.
+    pub fn supports_write_zeroes(&self) -> bool {
+        self.avail_features & (1u64 << VIRTIO_BLK_F_WRITE_ZEROES) != 0
+    }
+
     /// Specifies if this block device is read only.
     pub fn is_root_device(&self) -> bool {
         self.root_device
     }
 
+    /// Provides the boot index of this block device, if it is one of several root devices.
+    pub fn boot_index(&self) -> Option<u32> {
+        self.boot_index
+    }
+
     /// Specifies block device cache type.
     pub fn cache_type(&self) -> CacheType {
         self.disk.cache_type()
     }
 
+    /// Provides the retry configuration used when opening the backing file.
+    pub fn open_retry(&self) -> OpenRetryConfig {
+        self.disk.open_retry()
+    }
+
+    /// Provides the access pattern hint given for the backing file.
+    pub fn access_pattern(&self) -> AccessPattern {
+        self.disk.access_pattern()
+    }
+
     /// Provides non-mutable reference to this device's rate limiter.
     pub fn rate_limiter(&self) -> &RateLimiter {
         &self.rate_limiter
@@ -449,6 +859,14 @@ impl VirtioDevice for Block {
         self.interrupt_status.clone()
     }
 
+    fn irq_counter(&self) -> &SharedIncMetric {
+        &self.irq_counter
+    }
+
+    fn set_irq_counter(&mut self, irq_counter: Arc<SharedIncMetric>) {
+        self.irq_counter = irq_counter;
+    }
+
     fn avail_features(&self) -> u64 {
         self.avail_features
     }
@@ -496,18 +914,35 @@ impl VirtioDevice for Block {
 
     fn activate(&mut self, mem: GuestMemoryMmap) -> ActivateResult {
         if self.activate_evt.write(1).is_err() {
+            METRICS.block.activate_fails.inc();
             error!("Block: Cannot write to activate_evt");
             return Err(super::super::ActivateError::BadActivate);
         }
         self.device_state = DeviceState::Activated(mem);
         Ok(())
     }
+
+    fn reset(&mut self) -> Option<(EventFd, Vec<EventFd>)> {
+        // The requests are served synchronously, so none is left in flight. The transport resets
+        // the queues and the device is activated again once the driver sets them up.
+        let interrupt_evt = self.interrupt_evt.try_clone().ok()?;
+        let queue_evts = self
+            .queue_evts
+            .iter()
+            .map(EventFd::try_clone)
+            .collect::<io::Result<Vec<EventFd>>>()
+            .ok()?;
+        self.acked_features = 0;
+        self.device_state = DeviceState::Inactive;
+        Some((interrupt_evt, queue_evts))
+    }
 }
 
 #[cfg(test)]
 pub(crate) mod tests {
     use std::fs::metadata;
     use std::io::Read;
+    use std::mem;
     use std::os::unix::ffi::OsStrExt;
     use std::thread;
     use std::time::Duration;
@@ -533,24 +968,110 @@ pub(crate) mod tests {
 
         let disk_properties = DiskProperties::new(
             String::from(f.as_path().to_str().unwrap()),
+            DiskBacking::File,
             true,
             CacheType::Unsafe,
+            OpenRetryConfig::default(),
+            AccessPattern::default(),
         )
         .unwrap();
 
         assert_eq!(size, SECTOR_SIZE * num_sectors);
         assert_eq!(disk_properties.nsectors, num_sectors);
-        let cfg = disk_properties.virtio_block_config_space();
+        let cfg = disk_properties.virtio_block_config_space(false);
         assert_eq!(cfg.len(), CONFIG_SPACE_SIZE);
         for (i, byte) in cfg.iter().enumerate() {
             assert_eq!(*byte, (num_sectors >> (8 * i)) as u8);
         }
+        let cfg = disk_properties.virtio_block_config_space(true);
+        assert_eq!(cfg.len(), WRITE_ZEROES_CONFIG_SPACE_SIZE);
+        assert_eq!(cfg[..CONFIG_SPACE_SIZE], num_sectors.to_le_bytes());
+        let offset = WRITE_ZEROES_CONFIG_OFFSET;
+        assert_eq!(
+            cfg[offset..offset + 4],
+            MAX_WRITE_ZEROES_SECTORS.to_le_bytes()
+        );
+        assert_eq!(
+            cfg[offset + 4..offset + 8],
+            MAX_WRITE_ZEROES_SEG.to_le_bytes()
+        );
+        assert_eq!(cfg[offset + 8], 1);
         // Testing `backing_file.virtio_block_disk_image_id()` implies
         // duplicating that logic in tests, so skipping it.
 
-        assert!(
-            DiskProperties::new("invalid-disk-path".to_string(), true, CacheType::Unsafe).is_err()
-        );
+        assert!(DiskProperties::new(
+            "invalid-disk-path".to_string(),
+            DiskBacking::File,
+            true,
+            CacheType::Unsafe,
+            OpenRetryConfig::default(),
+            AccessPattern::default(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_access_pattern() {
+        let f = TempFile::new().unwrap();
+        f.as_file().set_len(0x1000).unwrap();
+        for access_pattern in &[
+            AccessPattern::None,
+            AccessPattern::Sequential,
+            AccessPattern::Random,
+        ] {
+            assert!(advise_disk_image(f.as_file(), *access_pattern).is_ok());
+            let disk_properties = DiskProperties::new(
+                String::from(f.as_path().to_str().unwrap()),
+                DiskBacking::File,
+                false,
+                CacheType::Unsafe,
+                OpenRetryConfig::default(),
+                *access_pattern,
+            )
+            .unwrap();
+            assert_eq!(disk_properties.access_pattern(), *access_pattern);
+        }
+
+        // The hint can't be given for a pipe, but it doesn't fail the disk.
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (read_end, _write_end) =
+            unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        let err = advise_disk_image(&read_end, AccessPattern::Random).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ESPIPE));
+    }
+
+    #[test]
+    fn test_open_disk_image_retry() {
+        for errno in &[
+            libc::EAGAIN,
+            libc::EBUSY,
+            libc::EINTR,
+            libc::ESTALE,
+            libc::ETIMEDOUT,
+        ] {
+            assert!(is_transient_open_error(&io::Error::from_raw_os_error(
+                *errno
+            )));
+        }
+        for errno in &[libc::ENOENT, libc::EACCES, libc::EISDIR] {
+            assert!(!is_transient_open_error(&io::Error::from_raw_os_error(
+                *errno
+            )));
+        }
+
+        // Permanent errors are returned right away, without any retry.
+        let retry_config = OpenRetryConfig {
+            max_retries: MAX_OPEN_RETRIES,
+            initial_backoff_ms: MAX_OPEN_RETRY_BACKOFF_MS,
+        };
+        let start = std::time::Instant::now();
+        let err = open_disk_image("invalid-disk-path", true, retry_config).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+        assert!(start.elapsed() < Duration::from_millis(MAX_OPEN_RETRY_BACKOFF_MS));
+
+        let f = TempFile::new().unwrap();
+        assert!(open_disk_image(f.as_path().to_str().unwrap(), false, retry_config).is_ok());
     }
 
     #[test]
@@ -559,7 +1080,9 @@ pub(crate) mod tests {
 
         assert_eq!(block.device_type(), TYPE_BLOCK);
 
-        let features: u64 = (1u64 << VIRTIO_F_VERSION_1) | (1u64 << VIRTIO_BLK_F_FLUSH);
+        let features: u64 = (1u64 << VIRTIO_F_VERSION_1)
+            | (1u64 << VIRTIO_BLK_F_FLUSH)
+            | (1u64 << VIRTIO_BLK_F_WRITE_ZEROES);
 
         assert_eq!(block.avail_features_by_page(0), features as u32);
         assert_eq!(block.avail_features_by_page(1), (features >> 32) as u32);
@@ -572,6 +1095,27 @@ pub(crate) mod tests {
             block.ack_features_by_page(i, u32::MAX);
         }
         assert_eq!(block.acked_features, features);
+
+        // Read-only devices don't advertise the write zeroes requests.
+        let f = TempFile::new().unwrap();
+        f.as_file().set_len(0x1000).unwrap();
+        let block = Block::new(
+            "ro".to_string(),
+            None,
+            CacheType::Unsafe,
+            f.as_path().to_str().unwrap().to_string(),
+            DiskBacking::File,
+            true,
+            false,
+            None,
+            RateLimiter::default(),
+            OpenRetryConfig::default(),
+            AccessPattern::default(),
+        )
+        .unwrap();
+        assert!(block.is_read_only());
+        assert!(!block.supports_write_zeroes());
+        assert_eq!(block.config_space.len(), CONFIG_SPACE_SIZE);
     }
 
     #[test]
@@ -591,7 +1135,10 @@ pub(crate) mod tests {
         let expected_config_space: [u8; CONFIG_SPACE_SIZE] =
             [0xd, 0xe, 0xa, 0xd, 0xb, 0xe, 0xe, 0xf];
         actual_config_space = expected_config_space;
-        block.read_config(CONFIG_SPACE_SIZE as u64 + 1, &mut actual_config_space);
+        block.read_config(
+            WRITE_ZEROES_CONFIG_SPACE_SIZE as u64 + 1,
+            &mut actual_config_space,
+        );
 
         // Validate read failed (the config space was not updated).
         assert_eq!(actual_config_space, expected_config_space);
@@ -619,10 +1166,31 @@ pub(crate) mod tests {
 
         // Invalid write.
         let new_config_space = [0xd, 0xe, 0xa, 0xd, 0xb, 0xe, 0xe, 0xf];
-        block.write_config(5, &new_config_space);
+        let offset = WRITE_ZEROES_CONFIG_SPACE_SIZE as u64 - 3;
+        block.write_config(offset, &new_config_space);
         // Make sure nothing got written.
         block.read_config(0, &mut actual_config_space);
         assert_eq!(actual_config_space, expected_config_space);
+        let mut config_space_end = [0xffu8; 3];
+        block.read_config(offset, &mut config_space_end);
+        assert_eq!(config_space_end, [0u8; 3]);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut block = default_block();
+        let mem = default_mem();
+        block.set_acked_features(1u64 << VIRTIO_F_VERSION_1);
+        block.activate(mem.clone()).unwrap();
+
+        let (_, queue_evts) = block.reset().unwrap();
+        assert_eq!(queue_evts.len(), 1);
+        assert!(!block.is_activated());
+        assert_eq!(block.acked_features(), 0);
+
+        // The device is activated again once the driver sets it up.
+        block.activate(mem).unwrap();
+        assert!(block.is_activated());
     }
 
     #[test]
@@ -1040,6 +1608,18 @@ pub(crate) mod tests {
         }
     }
 
+    #[test]
+    fn test_sync_disk() {
+        let mut block = default_block();
+        let flushes = METRICS.block_snapshot_flushes.get(block.id()).count();
+
+        block.sync_disk().unwrap();
+        assert_eq!(
+            METRICS.block_snapshot_flushes.get(block.id()).count(),
+            flushes + 1
+        );
+    }
+
     #[test]
     fn test_flush() {
         let mut block = default_block();
@@ -1084,6 +1664,175 @@ pub(crate) mod tests {
         }
     }
 
+    #[test]
+    fn test_coalesced_requests() {
+        let mut block = default_block();
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        set_queue(&mut block, 0, vq.create_queue());
+        block.activate(mem.clone()).unwrap();
+
+        // Sets up the request `i` of the queue, of `len` bytes starting at `sector`.
+        let set_request = |i: usize, request_type: u32, sector: u64, len: u32| {
+            let request_addr = 0x1000 + 0x100 * i as u64;
+            let data_addr = 0x4000 + 0x1000 * i as u64;
+            let status_addr = 0x3000 + 0x100 * i as u64;
+            let data_flags = if request_type == VIRTIO_BLK_T_IN {
+                VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE
+            } else {
+                VIRTQ_DESC_F_NEXT
+            };
+            let desc = (3 * i) as u16;
+            vq.avail.ring[i].set(desc);
+            vq.dtable[desc as usize].set(request_addr, 0x100, VIRTQ_DESC_F_NEXT, desc + 1);
+            vq.dtable[desc as usize + 1].set(data_addr, len, data_flags, desc + 2);
+            vq.dtable[desc as usize + 2].set(status_addr, 0x100, VIRTQ_DESC_F_WRITE, 0);
+            mem.write_obj(
+                RequestHeader::new(request_type, sector),
+                GuestAddress(request_addr),
+            )
+            .unwrap();
+        };
+        let data_addr = |i: usize| GuestAddress(0x4000 + 0x1000 * i as u64);
+        let status = |i: usize| {
+            mem.read_obj::<u8>(GuestAddress(0x3000 + 0x100 * i as u64))
+                .unwrap()
+        };
+        let rand_data = utils::rand::rand_alphanumerics(1024).as_bytes().to_vec();
+
+        // The first two writes are adjacent, the third one is not.
+        {
+            set_request(0, VIRTIO_BLK_T_OUT, 0, 512);
+            set_request(1, VIRTIO_BLK_T_OUT, 1, 512);
+            set_request(2, VIRTIO_BLK_T_OUT, 4, 512);
+            mem.write_slice(&rand_data[..512], data_addr(0)).unwrap();
+            mem.write_slice(&rand_data[512..], data_addr(1)).unwrap();
+            mem.write_slice(&rand_data[..512], data_addr(2)).unwrap();
+            vq.avail.idx.set(3);
+
+            let write_count = METRICS.block.write_count.count();
+            check_metric_after_block!(
+                &METRICS.block.coalesced_reqs_count,
+                2,
+                invoke_handler_for_queue_event(&mut block)
+            );
+            assert_eq!(METRICS.block.write_count.count(), write_count + 3);
+            assert_eq!(vq.used.idx.get(), 3);
+            for i in 0..3 {
+                assert_eq!(vq.used.ring[i].get().id, 3 * i as u32);
+                assert_eq!(vq.used.ring[i].get().len, 1);
+                assert_eq!(u32::from(status(i)), VIRTIO_BLK_S_OK);
+            }
+
+            let mut content = vec![0u8; 0x1000];
+            block.disk.file.seek(SeekFrom::Start(0)).unwrap();
+            block.disk.file.read_exact(&mut content).unwrap();
+            assert_eq!(&content[..1024], rand_data.as_slice());
+            assert_eq!(&content[2048..2560], &rand_data[..512]);
+        }
+
+        // Each of the adjacent reads gets its own status when the vectored IO comes short.
+        {
+            vq.used.idx.set(0);
+            set_queue(&mut block, 0, vq.create_queue());
+            set_request(0, VIRTIO_BLK_T_IN, 0, 512);
+            set_request(1, VIRTIO_BLK_T_IN, 1, 512);
+            vq.avail.idx.set(2);
+            // The disk ends in the middle of the second read.
+            block.disk.file.set_len(768).unwrap();
+
+            check_metric_after_block!(
+                &METRICS.block.coalesced_reqs_count,
+                1,
+                invoke_handler_for_queue_event(&mut block)
+            );
+            assert_eq!(vq.used.idx.get(), 2);
+            assert_eq!(vq.used.ring[0].get().len, 513);
+            assert_eq!(u32::from(status(0)), VIRTIO_BLK_S_OK);
+            assert_eq!(u32::from(status(1)), VIRTIO_BLK_S_IOERR);
+
+            let mut buf = [0u8; 512];
+            mem.read_slice(&mut buf, data_addr(0)).unwrap();
+            assert_eq!(&buf[..], &rand_data[..512]);
+        }
+    }
+
+    #[test]
+    fn test_max_descriptors_per_event() {
+        let mut block = default_block();
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        set_queue(&mut block, 0, vq.create_queue());
+        block.activate(mem.clone()).unwrap();
+        block.set_max_descriptors_per_event(2);
+
+        // Three flush requests, of a header and a status descriptor each.
+        for i in 0..3 {
+            let request_addr = 0x1000 + 0x100 * i as u64;
+            let status_addr = 0x3000 + 0x100 * i as u64;
+            let desc = (2 * i) as u16;
+            vq.avail.ring[i].set(desc);
+            vq.dtable[desc as usize].set(request_addr, 0x100, VIRTQ_DESC_F_NEXT, desc + 1);
+            vq.dtable[desc as usize + 1].set(status_addr, 0x100, VIRTQ_DESC_F_WRITE, 0);
+            mem.write_obj(
+                RequestHeader::new(VIRTIO_BLK_T_FLUSH, 0),
+                GuestAddress(request_addr),
+            )
+            .unwrap();
+        }
+        vq.avail.idx.set(3);
+
+        // Only two requests are processed, and the queue event is signaled for the last one.
+        invoke_handler_for_queue_event(&mut block);
+        assert_eq!(vq.used.idx.get(), 2);
+        assert_eq!(block.queue_evts[0].read().unwrap(), 1);
+
+        invoke_handler_for_queue_event(&mut block);
+        assert_eq!(vq.used.idx.get(), 3);
+        assert_eq!(vq.used.ring[2].get().id, 4);
+        assert!(block.queue_evts[0].read().is_err());
+    }
+
+    #[test]
+    fn test_interrupt_coalescing() {
+        let mut block = default_block();
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        set_queue(&mut block, 0, vq.create_queue());
+        block.activate(mem.clone()).unwrap();
+        initialize_virtqueue(&vq);
+        assert_eq!(block.interrupt_coalescing_us(), 0);
+        block.set_interrupt_coalescing_us(1_000_000);
+        assert_eq!(block.interrupt_coalescing_us(), 1_000_000);
+
+        let request_type_addr = GuestAddress(vq.dtable[0].addr.get());
+        vq.dtable[0].next.set(2);
+        mem.write_obj::<u32>(VIRTIO_BLK_T_FLUSH, request_type_addr)
+            .unwrap();
+
+        // The interrupt of the first request is raised right away.
+        invoke_handler_for_queue_event(&mut block);
+        assert_eq!(vq.used.idx.get(), 1);
+
+        // The interrupt of the second one is deferred.
+        vq.used.idx.set(0);
+        set_queue(&mut block, 0, vq.create_queue());
+        block.queue_evts[0].write(1).unwrap();
+        check_metric_after_block!(
+            &METRICS.block.coalesced_irq_count,
+            1,
+            block.process_queue_event()
+        );
+        assert_eq!(vq.used.idx.get(), 1);
+        assert!(block.interrupt_evt.read().is_err());
+
+        // Flushing raises the deferred interrupt.
+        block.flush_coalesced_interrupt();
+        assert_eq!(block.interrupt_evt.read().unwrap(), 1);
+        block.flush_coalesced_interrupt();
+        assert!(block.interrupt_evt.read().is_err());
+    }
+
     #[test]
     fn test_get_device_id() {
         let mut block = default_block();
@@ -1307,6 +2056,105 @@ pub(crate) mod tests {
         }
     }
 
+    #[test]
+    fn test_write_zeroes() {
+        let mut block = default_block();
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        set_queue(&mut block, 0, vq.create_queue());
+        block.activate(mem.clone()).unwrap();
+        initialize_virtqueue(&vq);
+
+        let request_type_addr = GuestAddress(vq.dtable[0].addr.get());
+        let data_addr = GuestAddress(vq.dtable[1].addr.get());
+        let status_addr = GuestAddress(vq.dtable[2].addr.get());
+        let request_header = RequestHeader::new(VIRTIO_BLK_T_WRITE_ZEROES, 0);
+        mem.write_obj::<RequestHeader>(request_header, request_type_addr)
+            .unwrap();
+        // Make data read only and as long as a segment.
+        vq.dtable[1].flags.set(VIRTQ_DESC_F_NEXT);
+        vq.dtable[1]
+            .len
+            .set(mem::size_of::<WriteZeroesSegment>() as u32);
+
+        // The whole disk is filled with 0xff, 8 sectors.
+        block.disk.file.seek(SeekFrom::Start(0)).unwrap();
+        block.disk.file.write_all(&[0xffu8; 0x1000]).unwrap();
+
+        let run_request = |block: &mut Block, segment: WriteZeroesSegment| {
+            vq.used.idx.set(0);
+            set_queue(block, 0, vq.create_queue());
+            mem.write_obj::<WriteZeroesSegment>(segment, data_addr)
+                .unwrap();
+            invoke_handler_for_queue_event(block);
+            assert_eq!(vq.used.idx.get(), 1);
+            assert_eq!(vq.used.ring[0].get().id, 0);
+            assert_eq!(vq.used.ring[0].get().len, 1);
+            mem.read_obj::<u32>(status_addr).unwrap()
+        };
+        let read_disk = |block: &mut Block| {
+            let mut buf = vec![0u8; 0x1000];
+            block.disk.file.seek(SeekFrom::Start(0)).unwrap();
+            block.disk.file.read_exact(&mut buf).unwrap();
+            buf
+        };
+
+        // Zero sectors 1 and 2.
+        check_metric_after_block!(
+            &METRICS.block.write_zeroes_count,
+            1,
+            assert_eq!(
+                run_request(&mut block, WriteZeroesSegment::new(1, 2, 0)),
+                VIRTIO_BLK_S_OK
+            )
+        );
+        let disk = read_disk(&mut block);
+        assert!(disk[..512].iter().all(|&byte| byte == 0xff));
+        assert!(disk[512..1536].iter().all(|&byte| byte == 0));
+        assert!(disk[1536..].iter().all(|&byte| byte == 0xff));
+
+        // Zero and deallocate the last sector.
+        assert_eq!(
+            run_request(
+                &mut block,
+                WriteZeroesSegment::new(7, 1, VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP)
+            ),
+            VIRTIO_BLK_S_OK
+        );
+        let disk = read_disk(&mut block);
+        assert!(disk[1536..3584].iter().all(|&byte| byte == 0xff));
+        assert!(disk[3584..].iter().all(|&byte| byte == 0));
+        // The disk keeps its size.
+        assert_eq!(block.disk.file.metadata().unwrap().len(), 0x1000);
+
+        // Unknown flags are not supported.
+        assert_eq!(
+            run_request(&mut block, WriteZeroesSegment::new(0, 1, 0x2)),
+            VIRTIO_BLK_S_UNSUPP
+        );
+        // The sectors must be within the disk.
+        assert_eq!(
+            run_request(&mut block, WriteZeroesSegment::new(7, 2, 0)),
+            VIRTIO_BLK_S_IOERR
+        );
+        assert_eq!(
+            run_request(&mut block, WriteZeroesSegment::new(u64::MAX, 1, 0)),
+            VIRTIO_BLK_S_IOERR
+        );
+        assert!(read_disk(&mut block)[..512]
+            .iter()
+            .all(|&byte| byte == 0xff));
+
+        // Only a single segment is supported.
+        vq.dtable[1]
+            .len
+            .set(2 * mem::size_of::<WriteZeroesSegment>() as u32);
+        assert_eq!(
+            run_request(&mut block, WriteZeroesSegment::new(0, 1, 0)),
+            VIRTIO_BLK_S_IOERR
+        );
+    }
+
     #[test]
     fn test_update_disk_image() {
         let mut block = default_block();
@@ -1325,5 +2173,57 @@ pub(crate) mod tests {
 
         assert_eq!(block.disk.file.metadata().unwrap().st_ino(), mdata.st_ino());
         assert_eq!(block.disk.image_id, id);
+        // The retry configuration is kept, even though the update itself doesn't retry.
+        assert_eq!(block.open_retry(), OpenRetryConfig::default());
+    }
+
+    #[test]
+    fn test_memory_backed_disk() {
+        let num_sectors = 4;
+        let mut block = Block::new(
+            "memory".to_string(),
+            None,
+            CacheType::Unsafe,
+            String::new(),
+            DiskBacking::Memory(SECTOR_SIZE * num_sectors),
+            false,
+            false,
+            None,
+            RateLimiter::default(),
+            OpenRetryConfig::default(),
+            AccessPattern::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            block.backing(),
+            DiskBacking::Memory(SECTOR_SIZE * num_sectors)
+        );
+        assert_eq!(block.disk.nsectors(), num_sectors);
+        assert_eq!(
+            block.config_space[..CONFIG_SPACE_SIZE],
+            num_sectors.to_le_bytes()
+        );
+
+        // The disk starts zeroed and keeps what is written to it.
+        let mut buf = [0xffu8; 16];
+        block.disk.file.seek(SeekFrom::Start(0)).unwrap();
+        block.disk.file.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0u8; 16]);
+        block.disk.file.seek(SeekFrom::Start(0)).unwrap();
+        block.disk.file.write_all(&[0xab; 16]).unwrap();
+        block.disk.file.seek(SeekFrom::Start(0)).unwrap();
+        block.disk.file.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0xab; 16]);
+
+        // There is no backing file to switch to.
+        let f = TempFile::new().unwrap();
+        assert_eq!(
+            block
+                .update_disk_image(String::from(f.as_path().to_str().unwrap()))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(block.disk.nsectors(), num_sectors);
     }
 }
@@ -17,6 +17,9 @@ impl Block {
         if let Err(e) = ops.add(Events::new(&self.rate_limiter, EventSet::IN)) {
             error!("Failed to register ratelimiter event: {}", e);
         }
+        if let Err(e) = ops.add(Events::new(&self.irq_coalescer, EventSet::IN)) {
+            error!("Failed to register interrupt coalescing event: {}", e);
+        }
     }
 
     fn register_activate_event(&self, ops: &mut EventOps) {
@@ -57,12 +60,14 @@ impl MutEventSubscriber for Block {
         if self.is_activated() {
             let queue_evt = self.queue_evts[0].as_raw_fd();
             let rate_limiter_evt = self.rate_limiter.as_raw_fd();
+            let irq_coalescer_evt = self.irq_coalescer.as_raw_fd();
             let activate_fd = self.activate_evt.as_raw_fd();
 
             // Looks better than C style if/else if/else.
             match source {
                 _ if queue_evt == source => self.process_queue_event(),
                 _ if rate_limiter_evt == source => self.process_rate_limiter_event(),
+                _ if irq_coalescer_evt == source => self.process_irq_coalescer_event(),
                 _ if activate_fd == source => self.process_activate_event(ops),
                 _ => warn!("Block: Spurious event received: {:?}", source),
             }
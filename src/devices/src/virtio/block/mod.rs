@@ -7,13 +7,22 @@ pub mod persist;
 pub mod request;
 pub mod test_utils;
 
-pub use self::device::{Block, CacheType};
+pub use self::device::{AccessPattern, Block, CacheType, DiskBacking, OpenRetryConfig};
 pub use self::event_handler::*;
 pub use self::request::*;
 
 use vm_memory::GuestMemoryError;
 
 pub const CONFIG_SPACE_SIZE: usize = 8;
+/// The size of the config space which holds the write zeroes limits, up to
+/// `write_zeroes_may_unmap` and its padding.
+pub const WRITE_ZEROES_CONFIG_SPACE_SIZE: usize = 60;
+/// The offset of `max_write_zeroes_sectors` in the config space.
+pub const WRITE_ZEROES_CONFIG_OFFSET: usize = 48;
+/// The maximum number of sectors zeroed by a single write zeroes segment.
+pub const MAX_WRITE_ZEROES_SECTORS: u32 = 0x3f_ffff;
+/// The maximum number of segments of a write zeroes request.
+pub const MAX_WRITE_ZEROES_SEG: u32 = 1;
 pub const SECTOR_SHIFT: u8 = 9;
 pub const SECTOR_SIZE: u64 = (0x01_u64) << SECTOR_SHIFT;
 pub const QUEUE_SIZE: u16 = 256;
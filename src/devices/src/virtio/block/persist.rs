@@ -3,11 +3,11 @@
 
 //! Defines the structures needed for saving/restoring block devices.
 
-use std::io::{self, Write};
+use std::io;
 use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
 
-use logger::{error, warn};
+use logger::warn;
 use rate_limiter::{persist::RateLimiterState, RateLimiter};
 use snapshot::Persist;
 use versionize::{VersionMap, Versionize, VersionizeError, VersionizeResult};
@@ -45,6 +45,59 @@ impl From<CacheTypeState> for CacheType {
     }
 }
 
+#[derive(Clone, Copy, Debug, Versionize, PartialEq)]
+// NOTICE: Any changes to this structure require a snapshot version bump.
+pub enum AccessPatternState {
+    None,
+    Sequential,
+    Random,
+}
+
+impl From<AccessPattern> for AccessPatternState {
+    fn from(access_pattern: AccessPattern) -> Self {
+        match access_pattern {
+            AccessPattern::None => AccessPatternState::None,
+            AccessPattern::Sequential => AccessPatternState::Sequential,
+            AccessPattern::Random => AccessPatternState::Random,
+        }
+    }
+}
+
+impl From<AccessPatternState> for AccessPattern {
+    fn from(access_pattern_state: AccessPatternState) -> Self {
+        match access_pattern_state {
+            AccessPatternState::None => AccessPattern::None,
+            AccessPatternState::Sequential => AccessPattern::Sequential,
+            AccessPatternState::Random => AccessPattern::Random,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Versionize, PartialEq)]
+// NOTICE: Any changes to this structure require a snapshot version bump.
+pub struct OpenRetryConfigState {
+    max_retries: u32,
+    initial_backoff_ms: u64,
+}
+
+impl From<OpenRetryConfig> for OpenRetryConfigState {
+    fn from(open_retry: OpenRetryConfig) -> Self {
+        OpenRetryConfigState {
+            max_retries: open_retry.max_retries,
+            initial_backoff_ms: open_retry.initial_backoff_ms,
+        }
+    }
+}
+
+impl From<OpenRetryConfigState> for OpenRetryConfig {
+    fn from(open_retry_state: OpenRetryConfigState) -> Self {
+        OpenRetryConfig {
+            max_retries: open_retry_state.max_retries,
+            initial_backoff_ms: open_retry_state.initial_backoff_ms,
+        }
+    }
+}
+
 #[derive(Clone, Versionize)]
 // NOTICE: Any changes to this structure require a snapshot version bump.
 pub struct BlockState {
@@ -60,9 +113,27 @@ pub struct BlockState {
     disk_path: String,
     virtio_state: VirtioDeviceState,
     rate_limiter_state: RateLimiterState,
+    #[version(start = 2, default_fn = "default_open_retry")]
+    open_retry: OpenRetryConfigState,
+    #[version(start = 2, default_fn = "default_interrupt_coalescing_us")]
+    interrupt_coalescing_us: u64,
+    #[version(start = 2)]
+    boot_index: Option<u32>,
+    #[version(start = 2, default_fn = "default_access_pattern")]
+    access_pattern: AccessPatternState,
 }
 
 impl BlockState {
+    /// Provides the path of the backing file of the drive.
+    pub fn disk_path(&self) -> &str {
+        &self.disk_path
+    }
+
+    /// Changes the path of the backing file which the drive is restored from.
+    pub fn set_disk_path(&mut self, disk_path: String) {
+        self.disk_path = disk_path;
+    }
+
     fn block_cache_type_ser(&mut self, target_version: u16) -> VersionizeResult<()> {
         if target_version < 3 && self.cache_type != CacheTypeState::Unsafe {
             warn!(
@@ -77,6 +148,18 @@ impl BlockState {
     fn default_cache_type_flush(_source_version: u16) -> CacheTypeState {
         CacheTypeState::Unsafe
     }
+
+    fn default_open_retry(_source_version: u16) -> OpenRetryConfigState {
+        OpenRetryConfigState::from(OpenRetryConfig::default())
+    }
+
+    fn default_interrupt_coalescing_us(_source_version: u16) -> u64 {
+        0
+    }
+
+    fn default_access_pattern(_source_version: u16) -> AccessPatternState {
+        AccessPatternState::None
+    }
 }
 
 pub struct BlockConstructorArgs {
@@ -89,14 +172,7 @@ impl Persist<'_> for Block {
     type Error = io::Error;
 
     fn save(&self) -> Self::State {
-        if let Err(e) = self.disk.file().flush() {
-            error!("Failed to flush block data on serialization. Error: {}", e);
-        }
-        // Sync data out to backing file on host.
-        if let Err(e) = self.disk.file().sync_all() {
-            error!("Failed to sync block data on serialization. Error: {}", e);
-        }
-        // Save device state.
+        // The backing file is synced by `sync_disk()` beforehand, which can fail the snapshot.
         BlockState {
             id: self.id.clone(),
             partuuid: self.partuuid.clone(),
@@ -105,6 +181,10 @@ impl Persist<'_> for Block {
             disk_path: self.disk.file_path().clone(),
             virtio_state: VirtioDeviceState::from_device(self),
             rate_limiter_state: self.rate_limiter.save(),
+            open_retry: OpenRetryConfigState::from(self.open_retry()),
+            interrupt_coalescing_us: self.interrupt_coalescing_us(),
+            boot_index: self.boot_index,
+            access_pattern: AccessPatternState::from(self.access_pattern()),
         }
     }
 
@@ -120,9 +200,14 @@ impl Persist<'_> for Block {
             state.partuuid.clone(),
             state.cache_type.into(),
             state.disk_path.clone(),
+            // The microVMs with memory backed drives can't be snapshotted.
+            DiskBacking::File,
             is_disk_read_only,
             state.root_device,
+            state.boot_index,
             rate_limiter,
+            state.open_retry.into(),
+            state.access_pattern.into(),
         )?;
 
         block.queues = state
@@ -132,6 +217,7 @@ impl Persist<'_> for Block {
         block.interrupt_status = Arc::new(AtomicUsize::new(state.virtio_state.interrupt_status));
         block.avail_features = state.virtio_state.avail_features;
         block.acked_features = state.virtio_state.acked_features;
+        block.set_interrupt_coalescing_us(state.interrupt_coalescing_us);
 
         if state.virtio_state.activated {
             block.device_state = DeviceState::Activated(constructor_args.mem);
@@ -168,6 +254,18 @@ mod tests {
         assert_eq!(CacheType::Writeback, CacheTypeState::Writeback.into());
     }
 
+    #[test]
+    fn test_access_pattern_state_from_into() {
+        for access_pattern in &[
+            AccessPattern::None,
+            AccessPattern::Sequential,
+            AccessPattern::Random,
+        ] {
+            let state = AccessPatternState::from(*access_pattern);
+            assert_eq!(AccessPattern::from(state), *access_pattern);
+        }
+    }
+
     #[test]
     fn test_default_cache_type_flush() {
         assert_eq!(
@@ -180,6 +278,66 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_config_persistence() {
+        let f = TempFile::new().unwrap();
+        f.as_file().set_len(0x1000).unwrap();
+
+        let open_retry = OpenRetryConfig {
+            max_retries: 5,
+            initial_backoff_ms: 100,
+        };
+        let mut block = Block::new(
+            "test".to_string(),
+            None,
+            CacheType::Unsafe,
+            f.as_path().to_str().unwrap().to_string(),
+            DiskBacking::File,
+            false,
+            false,
+            Some(2),
+            RateLimiter::default(),
+            open_retry,
+            AccessPattern::Random,
+        )
+        .unwrap();
+        block.set_interrupt_coalescing_us(1000);
+
+        let mut mem = vec![0; 4096];
+        let mut version_map = VersionMap::new();
+        version_map
+            .new_version()
+            .set_type_version(BlockState::type_id(), 2);
+
+        // The configuration is restored from the snapshot.
+        <Block as Persist>::save(&block)
+            .serialize(&mut mem.as_mut_slice(), &version_map, 2)
+            .unwrap();
+        let restored_block = Block::restore(
+            BlockConstructorArgs { mem: default_mem() },
+            &BlockState::deserialize(&mut mem.as_slice(), &version_map, 2).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(restored_block.open_retry(), open_retry);
+        assert_eq!(restored_block.interrupt_coalescing_us(), 1000);
+        assert_eq!(restored_block.boot_index(), Some(2));
+        assert_eq!(restored_block.access_pattern(), AccessPattern::Random);
+
+        // Older snapshots don't have it, so the defaults are used.
+        <Block as Persist>::save(&block)
+            .serialize(&mut mem.as_mut_slice(), &version_map, 1)
+            .unwrap();
+        let restored_block = Block::restore(
+            BlockConstructorArgs { mem: default_mem() },
+            &BlockState::deserialize(&mut mem.as_slice(), &version_map, 1).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(restored_block.open_retry(), OpenRetryConfig::default());
+        assert_eq!(restored_block.interrupt_coalescing_us(), 0);
+        assert_eq!(restored_block.boot_index(), None);
+        assert_eq!(restored_block.access_pattern(), AccessPattern::None);
+    }
+
     #[test]
     fn test_cache_semantic_ser() {
         // We create the backing file here so that it exists for the whole lifetime of the test.
@@ -192,9 +350,13 @@ mod tests {
             None,
             CacheType::Writeback,
             f.as_path().to_str().unwrap().to_string(),
+            DiskBacking::File,
             false,
             false,
+            None,
             RateLimiter::default(),
+            OpenRetryConfig::default(),
+            AccessPattern::default(),
         )
         .unwrap();
 
@@ -223,9 +385,13 @@ mod tests {
             None,
             CacheType::Unsafe,
             f.as_path().to_str().unwrap().to_string(),
+            DiskBacking::File,
             false,
             false,
+            None,
             RateLimiter::default(),
+            OpenRetryConfig::default(),
+            AccessPattern::default(),
         )
         .unwrap();
         let guest_mem = default_mem();
@@ -7,15 +7,23 @@
 
 use std::convert::From;
 use std::io::{self, Seek, SeekFrom, Write};
+use std::mem;
+use std::os::unix::io::AsRawFd;
 use std::result;
 
 use logger::{IncMetric, METRICS};
 use virtio_gen::virtio_blk::*;
-use vm_memory::{ByteValued, Bytes, GuestAddress, GuestMemoryError, GuestMemoryMmap};
+use vm_memory::{
+    Address, ByteValued, Bytes, GuestAddress, GuestMemory, GuestMemoryError, GuestMemoryMmap,
+    GuestMemoryRegion,
+};
 
 use super::super::DescriptorChain;
 use super::device::{CacheType, DiskProperties};
-use super::{Error, SECTOR_SHIFT, SECTOR_SIZE};
+use super::{Error, MAX_WRITE_ZEROES_SECTORS, MAX_WRITE_ZEROES_SEG, SECTOR_SHIFT, SECTOR_SIZE};
+
+/// The maximum number of adjacent requests which are served by a single vectored IO.
+pub(crate) const MAX_COALESCED_REQUESTS: usize = 64;
 
 #[derive(Debug)]
 pub enum IoErrStatus {
@@ -26,6 +34,7 @@ pub enum IoErrStatus {
     Seek(io::Error),
     SyncAll(io::Error),
     Write(GuestMemoryError),
+    WriteZeroes(io::Error),
 }
 
 #[derive(Debug)]
@@ -83,6 +92,7 @@ pub enum RequestType {
     Out,
     Flush,
     GetDeviceID,
+    WriteZeroes,
     Unsupported(u32),
 }
 
@@ -93,6 +103,7 @@ impl From<u32> for RequestType {
             VIRTIO_BLK_T_OUT => RequestType::Out,
             VIRTIO_BLK_T_FLUSH => RequestType::Flush,
             VIRTIO_BLK_T_GET_ID => RequestType::GetDeviceID,
+            VIRTIO_BLK_T_WRITE_ZEROES => RequestType::WriteZeroes,
             t => RequestType::Unsupported(t),
         }
     }
@@ -149,6 +160,34 @@ impl RequestHeader {
     }
 }
 
+/// A segment of a write zeroes request, which describes a range of sectors to zero.
+///
+/// The data of a write zeroes request is made of such segments, with the following fields:
+///   * sector: an u64 value representing the first sector to zero.
+///   * num_sectors: an u32 value representing the number of sectors to zero.
+///   * flags: an u32 value which may only have the `VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP` bit
+///     set, when the sectors may be deallocated.
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct WriteZeroesSegment {
+    sector: u64,
+    num_sectors: u32,
+    flags: u32,
+}
+
+// Safe because WriteZeroesSegment only contains plain data.
+unsafe impl ByteValued for WriteZeroesSegment {}
+
+impl WriteZeroesSegment {
+    pub fn new(sector: u64, num_sectors: u32, flags: u32) -> WriteZeroesSegment {
+        WriteZeroesSegment {
+            sector,
+            num_sectors,
+            flags,
+        }
+    }
+}
+
 impl Request {
     pub fn parse(
         avail_desc: &DescriptorChain,
@@ -186,7 +225,10 @@ impl Request {
                 .next_descriptor()
                 .ok_or(Error::DescriptorChainTooShort)?;
 
-            if data_desc.is_write_only() && req.request_type == RequestType::Out {
+            if data_desc.is_write_only()
+                && (req.request_type == RequestType::Out
+                    || req.request_type == RequestType::WriteZeroes)
+            {
                 return Err(Error::UnexpectedWriteOnlyDescriptor);
             }
             if !data_desc.is_write_only() && req.request_type == RequestType::In {
@@ -214,7 +256,20 @@ impl Request {
         Ok(req)
     }
 
-    fn execute_seek(&self, disk: &mut DiskProperties) -> result::Result<(), ErrStatus> {
+    /// Checks whether `next` reads or writes the sectors which follow the ones of this request,
+    /// in which case both of them can be served by a single vectored IO.
+    pub(crate) fn is_followed_by(&self, next: &Request) -> bool {
+        if self.request_type != next.request_type
+            || (self.request_type != RequestType::In && self.request_type != RequestType::Out)
+        {
+            return false;
+        }
+        self.sector
+            .checked_add(u64::from(self.data_len) >> SECTOR_SHIFT)
+            .map_or(false, |top_sector| top_sector == next.sector)
+    }
+
+    fn check_bounds(&self, disk: &DiskProperties) -> result::Result<(), ErrStatus> {
         // TODO: perform this logic at request parsing level in the future.
         // Check that the data length is a multiple of 512 as specified in the virtio standard.
         if u64::from(self.data_len) % SECTOR_SIZE != 0 {
@@ -234,6 +289,12 @@ impl Request {
             )));
         }
 
+        Ok(())
+    }
+
+    fn execute_seek(&self, disk: &mut DiskProperties) -> result::Result<(), ErrStatus> {
+        self.check_bounds(disk)?;
+
         disk.file_mut()
             .seek(SeekFrom::Start(self.sector << SECTOR_SHIFT))
             .map_err(|e| ErrStatus::IoErr(IoErrStatus::Seek(e)))?;
@@ -241,6 +302,59 @@ impl Request {
         Ok(())
     }
 
+    fn execute_write_zeroes(
+        &self,
+        disk: &mut DiskProperties,
+        mem: &GuestMemoryMmap,
+    ) -> result::Result<u32, ErrStatus> {
+        let segment_len = mem::size_of::<WriteZeroesSegment>() as u32;
+        if self.data_len == 0
+            || self.data_len % segment_len != 0
+            || self.data_len / segment_len > MAX_WRITE_ZEROES_SEG
+        {
+            return Err(ErrStatus::IoErr(IoErrStatus::BadRequest(
+                Error::InvalidDataLength,
+            )));
+        }
+
+        for index in 0..self.data_len / segment_len {
+            let segment_addr = self.data_addr.unchecked_add(u64::from(index * segment_len));
+            let segment: WriteZeroesSegment = mem
+                .read_obj(segment_addr)
+                .map_err(|e| ErrStatus::IoErr(IoErrStatus::BadRequest(Error::GuestMemory(e))))?;
+            if segment.flags & !VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP != 0 {
+                return Err(ErrStatus::Unsupported(VIRTIO_BLK_T_WRITE_ZEROES));
+            }
+            if segment.num_sectors > MAX_WRITE_ZEROES_SECTORS {
+                return Err(ErrStatus::IoErr(IoErrStatus::BadRequest(
+                    Error::InvalidDataLength,
+                )));
+            }
+            let top_sector = segment
+                .sector
+                .checked_add(u64::from(segment.num_sectors))
+                .ok_or(ErrStatus::IoErr(IoErrStatus::BadRequest(
+                    Error::InvalidOffset,
+                )))?;
+            if top_sector > disk.nsectors() {
+                return Err(ErrStatus::IoErr(IoErrStatus::BadRequest(
+                    Error::InvalidOffset,
+                )));
+            }
+
+            let unmap = segment.flags & VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP != 0;
+            disk.write_zeroes(
+                segment.sector << SECTOR_SHIFT,
+                u64::from(segment.num_sectors) << SECTOR_SHIFT,
+                unmap,
+            )
+            .map_err(|e| ErrStatus::IoErr(IoErrStatus::WriteZeroes(e)))?;
+        }
+
+        METRICS.block.write_zeroes_count.inc();
+        Ok(0)
+    }
+
     pub(crate) fn execute(
         &self,
         disk: &mut DiskProperties,
@@ -312,9 +426,103 @@ impl Request {
                     .map(|_| VIRTIO_BLK_ID_BYTES)
                     .map_err(|e| ErrStatus::IoErr(IoErrStatus::Write(e)))
             }
+            RequestType::WriteZeroes => self.execute_write_zeroes(disk, mem),
             RequestType::Unsupported(op) => Err(ErrStatus::Unsupported(op)),
         }
     }
+
+    /// Executes a batch of requests, in which each request is followed by the next one, and
+    /// returns the result of each of them.
+    ///
+    /// The batch is served by a single vectored IO. The requests which the vectored IO doesn't
+    /// fully transfer are executed one by one, so each of them still gets its own result.
+    pub(crate) fn execute_batch(
+        requests: &[Request],
+        disk: &mut DiskProperties,
+        mem: &GuestMemoryMmap,
+    ) -> Vec<result::Result<u32, ErrStatus>> {
+        let transferred = if requests.len() > 1 {
+            Self::execute_vectored(requests, disk, mem)
+        } else {
+            0
+        };
+        METRICS.block.coalesced_reqs_count.add(transferred);
+
+        requests
+            .iter()
+            .enumerate()
+            .map(|(i, request)| match request.request_type {
+                RequestType::In if i < transferred => Ok(request.data_len),
+                RequestType::Out if i < transferred => Ok(0),
+                _ => request.execute(disk, mem),
+            })
+            .collect()
+    }
+
+    // Serves the requests with a single `preadv` or `pwritev`, and returns the number of requests
+    // which were fully transferred, starting with the first one.
+    fn execute_vectored(
+        requests: &[Request],
+        disk: &mut DiskProperties,
+        mem: &GuestMemoryMmap,
+    ) -> usize {
+        let mut iovecs = Vec::with_capacity(requests.len());
+        for request in requests {
+            if request.check_bounds(disk).is_err() {
+                return 0;
+            }
+            // The data of each request must be contiguous in the host memory.
+            match mem.get_slice(request.data_addr, request.data_len as usize) {
+                Ok(slice) => iovecs.push(libc::iovec {
+                    iov_base: slice.as_ptr() as *mut libc::c_void,
+                    iov_len: slice.len(),
+                }),
+                Err(_) => return 0,
+            }
+        }
+
+        let fd = disk.file().as_raw_fd();
+        let offset = (requests[0].sector << SECTOR_SHIFT) as libc::off_t;
+        let iovcnt = iovecs.len() as libc::c_int;
+        // Safe because the iovecs point to guest memory which stays mapped for the whole call,
+        // and we check the return value.
+        let ret = unsafe {
+            if requests[0].request_type == RequestType::In {
+                libc::preadv(fd, iovecs.as_ptr(), iovcnt, offset)
+            } else {
+                libc::pwritev(fd, iovecs.as_ptr(), iovcnt, offset)
+            }
+        };
+        if ret < 0 {
+            // The requests are executed again one by one, which reports the error.
+            return 0;
+        }
+
+        let mut remaining = ret as usize;
+        let mut transferred = 0;
+        for request in requests {
+            let len = request.data_len as usize;
+            if remaining < len {
+                break;
+            }
+            remaining -= len;
+            transferred += 1;
+
+            if request.request_type == RequestType::In {
+                // The data was read straight into the guest memory, so it's not tracked yet.
+                if let Some(region) = mem.find_region(request.data_addr) {
+                    let offset = request.data_addr.unchecked_offset_from(region.start_addr());
+                    region.mark_dirty_pages(offset as usize, len);
+                }
+                METRICS.block.read_bytes.add(len);
+                METRICS.block.read_count.inc();
+            } else {
+                METRICS.block.write_bytes.add(len);
+                METRICS.block.write_count.inc();
+            }
+        }
+        transferred
+    }
 }
 
 #[cfg(test)]
@@ -337,6 +545,7 @@ mod tests {
             VIRTIO_BLK_T_OUT,
             VIRTIO_BLK_T_FLUSH,
             VIRTIO_BLK_T_GET_ID,
+            VIRTIO_BLK_T_WRITE_ZEROES,
         ];
 
         for request_type in supported_request_types {
@@ -354,6 +563,34 @@ mod tests {
         assert!(RequestHeader::read_from(&mem, GuestAddress(0x1000)).is_err());
     }
 
+    #[test]
+    fn test_is_followed_by() {
+        let request = |request_type, sector, data_len| Request {
+            request_type,
+            data_len,
+            status_addr: GuestAddress(0),
+            sector,
+            data_addr: GuestAddress(0),
+        };
+
+        let read = request(RequestType::In, 8, 1024);
+        assert!(read.is_followed_by(&request(RequestType::In, 10, 512)));
+        // The sectors are not adjacent.
+        assert!(!read.is_followed_by(&request(RequestType::In, 11, 512)));
+        assert!(!read.is_followed_by(&request(RequestType::In, 8, 512)));
+        // The request types differ.
+        assert!(!read.is_followed_by(&request(RequestType::Out, 10, 512)));
+
+        let write = request(RequestType::Out, 0, 512);
+        assert!(write.is_followed_by(&request(RequestType::Out, 1, 512)));
+        // Only data transfers are coalesced.
+        let flush = request(RequestType::Flush, 0, 0);
+        assert!(!flush.is_followed_by(&request(RequestType::Flush, 0, 0)));
+        // The sector overflows.
+        let last = request(RequestType::In, u64::MAX, 512);
+        assert!(!last.is_followed_by(&request(RequestType::In, 0, 512)));
+    }
+
     #[test]
     fn test_request_type_from() {
         assert_eq!(RequestType::from(VIRTIO_BLK_T_IN), RequestType::In);
@@ -363,6 +600,10 @@ mod tests {
             RequestType::from(VIRTIO_BLK_T_GET_ID),
             RequestType::GetDeviceID
         );
+        assert_eq!(
+            RequestType::from(VIRTIO_BLK_T_WRITE_ZEROES),
+            RequestType::WriteZeroes
+        );
         assert_eq!(RequestType::from(42), RequestType::Unsupported(42));
     }
 
@@ -494,6 +735,17 @@ mod tests {
             ));
         }
 
+        {
+            let mut q = vq.create_queue();
+            // Write only data for WRITE_ZEROES.
+            m.write_obj::<u32>(VIRTIO_BLK_T_WRITE_ZEROES, GuestAddress(0x1000))
+                .unwrap();
+            assert!(matches!(
+                Request::parse(&q.pop(m).unwrap(), m),
+                Err(Error::UnexpectedWriteOnlyDescriptor)
+            ));
+        }
+
         {
             let mut q = vq.create_queue();
             // Read only data for GetDeviceID.
@@ -603,6 +855,7 @@ mod tests {
             (u32, std::sync::Arc<fn() -> Self>),
             (u32, std::sync::Arc<fn() -> Self>),
             (u32, std::sync::Arc<fn() -> Self>),
+            (u32, std::sync::Arc<fn() -> Self>),
             (
                 u32,
                 std::sync::Arc<Map<<u32 as Arbitrary>::Strategy, fn(u32) -> Self>>,
@@ -617,13 +870,14 @@ mod tests {
                 (1u32, std::sync::Arc::new(|| RequestType::Out {})),
                 (1u32, std::sync::Arc::new(|| RequestType::Flush {})),
                 (1u32, std::sync::Arc::new(|| RequestType::GetDeviceID {})),
+                (1u32, std::sync::Arc::new(|| RequestType::WriteZeroes {})),
                 (
                     1u32,
                     std::sync::Arc::new(Strategy::prop_map(any::<u32>(), |id| {
                         // Random unsupported requests for our implementation start at
-                        // VIRTIO_BLK_T_GET_ID + 1 = 9.
-                        // This can be further refined to include unsupported requests ids < 9.
-                        RequestType::Unsupported(id.checked_add(9).unwrap_or(9))
+                        // VIRTIO_BLK_T_WRITE_ZEROES + 1 = 14.
+                        // This can be further refined to include unsupported requests ids < 14.
+                        RequestType::Unsupported(id.checked_add(14).unwrap_or(14))
                     })),
                 ),
             ))
@@ -642,6 +896,7 @@ mod tests {
                 RequestType::Out => VIRTIO_BLK_T_OUT,
                 RequestType::Flush => VIRTIO_BLK_T_FLUSH,
                 RequestType::GetDeviceID => VIRTIO_BLK_T_GET_ID,
+                RequestType::WriteZeroes => VIRTIO_BLK_T_WRITE_ZEROES,
                 RequestType::Unsupported(id) => id,
             }
         }
@@ -654,6 +909,7 @@ mod tests {
             RequestType::Out => VIRTQ_DESC_F_NEXT,
             RequestType::Flush => VIRTQ_DESC_F_NEXT,
             RequestType::GetDeviceID => VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE,
+            RequestType::WriteZeroes => VIRTQ_DESC_F_NEXT,
             RequestType::Unsupported(_) => VIRTQ_DESC_F_NEXT,
         }
     }
@@ -793,7 +1049,7 @@ mod tests {
 
                         return match request.request_type {
                             // Readonly buffer is writable.
-                            RequestType::Out => {
+                            RequestType::Out | RequestType::WriteZeroes => {
                                 data_desc_flags |= VIRTQ_DESC_F_WRITE;
                                 vq.dtable[DATA_DESCRIPTOR].flags.set(data_desc_flags);
                                 (Err(Error::UnexpectedWriteOnlyDescriptor), mem, q)
@@ -1,7 +1,7 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::virtio::{Block, CacheType, Queue};
+use crate::virtio::{AccessPattern, Block, CacheType, DiskBacking, OpenRetryConfig, Queue};
 use rate_limiter::RateLimiter;
 use utils::tempfile::TempFile;
 
@@ -26,9 +26,13 @@ pub fn default_block_with_path(path: String) -> Block {
         None,
         CacheType::Unsafe,
         path,
+        DiskBacking::File,
         false,
         false,
+        None,
         rate_limiter,
+        OpenRetryConfig::default(),
+        AccessPattern::default(),
     )
     .unwrap()
 }
@@ -0,0 +1,459 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::result::Result;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use logger::{error, IncMetric, SharedIncMetric, METRICS};
+use utils::eventfd::EventFd;
+use virtio_gen::virtio_blk::VIRTIO_F_VERSION_1;
+use vm_memory::{Bytes, GuestMemoryMmap};
+
+use super::super::{
+    ActivateError, ActivateResult, DeviceState, Queue, VirtioDevice, TYPE_CONSOLE,
+    VIRTIO_MMIO_INT_VRING,
+};
+use super::{
+    Error as ConsoleError, CONSOLE_DEV_ID, INPUT_BUFFER_SIZE, QUEUE_SIZES, RECEIVEQ_INDEX,
+    TRANSMITQ_INDEX,
+};
+use crate::legacy::ReadableFd;
+use crate::report_console_event_fail;
+
+macro_rules! mem_of_active_device {
+    ($state:expr) => {
+        match $state {
+            DeviceState::Activated(ref mem) => mem,
+            // This should never happen, it's been already validated in the event handler.
+            DeviceState::Inactive => unreachable!(),
+        }
+    };
+}
+
+/// Virtio console device with a single port, connecting the guest console to a host input and
+/// output the same way the legacy serial console is connected.
+///
+/// None of the optional features (console size, multiple ports, emergency write) are offered,
+/// so the device has no configuration space.
+pub struct Console {
+    // Virtio fields.
+    pub(crate) avail_features: u64,
+    pub(crate) acked_features: u64,
+    pub(crate) interrupt_status: Arc<AtomicUsize>,
+    pub(crate) irq_counter: Arc<SharedIncMetric>,
+    pub(crate) interrupt_evt: EventFd,
+
+    // Transport related fields.
+    pub(crate) queues: Vec<Queue>,
+    pub(crate) queue_evts: Vec<EventFd>,
+    pub(crate) device_state: DeviceState,
+    pub(crate) activate_evt: EventFd,
+
+    // Host side of the console.
+    input: Option<Box<dyn ReadableFd + Send>>,
+    output: Box<dyn io::Write + Send>,
+    // Host input which the guest has not received yet.
+    in_buffer: VecDeque<u8>,
+    // Whether reading the host input is paused until the guest makes room in `in_buffer`.
+    pub(crate) input_paused: bool,
+}
+
+impl Console {
+    /// Creates a console which sends `input` to the guest and writes the guest output to
+    /// `output`.
+    pub fn new(
+        input: Option<Box<dyn ReadableFd + Send>>,
+        output: Box<dyn io::Write + Send>,
+    ) -> Result<Console, ConsoleError> {
+        let queue_evts = vec![
+            EventFd::new(libc::EFD_NONBLOCK).map_err(ConsoleError::EventFd)?,
+            EventFd::new(libc::EFD_NONBLOCK).map_err(ConsoleError::EventFd)?,
+        ];
+        let queues: Vec<Queue> = QUEUE_SIZES.iter().map(|&s| Queue::new(s)).collect();
+
+        Ok(Console {
+            avail_features: 1u64 << VIRTIO_F_VERSION_1,
+            acked_features: 0u64,
+            interrupt_status: Arc::new(AtomicUsize::new(0)),
+            irq_counter: Arc::default(),
+            interrupt_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(ConsoleError::EventFd)?,
+            queues,
+            queue_evts,
+            device_state: DeviceState::Inactive,
+            activate_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(ConsoleError::EventFd)?,
+            input,
+            output,
+            in_buffer: VecDeque::with_capacity(INPUT_BUFFER_SIZE),
+            input_paused: false,
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        CONSOLE_DEV_ID
+    }
+
+    /// Provides the fd of the host input, if any.
+    pub(crate) fn input_fd(&self) -> Option<RawFd> {
+        self.input.as_ref().map(|input| input.as_raw_fd())
+    }
+
+    /// Whether `in_buffer` can take more host input.
+    pub(crate) fn has_input_room(&self) -> bool {
+        self.in_buffer.len() < INPUT_BUFFER_SIZE
+    }
+
+    pub(crate) fn signal_used_queue(&self) -> Result<(), ConsoleError> {
+        self.interrupt_status
+            .fetch_or(VIRTIO_MMIO_INT_VRING as usize, Ordering::SeqCst);
+
+        self.interrupt_evt.write(1).map_err(|e| {
+            error!("Failed to signal used queue: {:?}", e);
+            ConsoleError::FailedSignalingUsedQueue(e)
+        })?;
+        self.irq_counter.inc();
+        Ok(())
+    }
+
+    /// Reads the available host input into `in_buffer`. Returns the number of bytes read,
+    /// which is 0 once the host input reached EOF or while `in_buffer` is full.
+    pub(crate) fn read_input(&mut self) -> Result<usize, ConsoleError> {
+        let avail_cap = INPUT_BUFFER_SIZE.saturating_sub(self.in_buffer.len());
+        let input = match self.input.as_mut() {
+            Some(input) if avail_cap > 0 => input,
+            _ => return Ok(0),
+        };
+
+        let mut buf = vec![0u8; avail_cap];
+        let count = input.read(&mut buf).map_err(ConsoleError::ReadInput)?;
+        self.in_buffer.extend(&buf[..count]);
+        Ok(count)
+    }
+
+    /// Moves the buffered host input to the buffers made available by the guest on the
+    /// receive queue.
+    pub(crate) fn process_receive_queue(&mut self) -> Result<(), ConsoleError> {
+        let mem = mem_of_active_device!(self.device_state);
+        let mut used_any = false;
+
+        while !self.in_buffer.is_empty() {
+            let head = match self.queues[RECEIVEQ_INDEX].pop(mem) {
+                Some(head) => head,
+                None => break,
+            };
+            let head_index = head.index;
+            let mut written = 0;
+
+            let mut next_desc = Some(head);
+            while let Some(desc) = next_desc {
+                if !desc.is_write_only() || self.in_buffer.is_empty() {
+                    break;
+                }
+                let count = cmp::min(desc.len as usize, self.in_buffer.len());
+                let data: Vec<u8> = self.in_buffer.iter().take(count).copied().collect();
+                if let Err(e) = mem.write_slice(&data, desc.addr) {
+                    report_console_event_fail(ConsoleError::GuestMemory(e));
+                    break;
+                }
+                self.in_buffer.drain(..count);
+                written += count;
+                next_desc = desc.next_descriptor();
+            }
+
+            // The buffer is returned to the guest even if the chain was bad, so that the driver
+            // doesn't run out of buffers.
+            self.queues[RECEIVEQ_INDEX]
+                .add_used(mem, head_index, written as u32)
+                .map_err(ConsoleError::Queue)?;
+            used_any = true;
+            METRICS.console.rx_bytes_count.add(written);
+        }
+
+        if used_any {
+            self.signal_used_queue()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the guest output from the transmit queue to the host output.
+    pub(crate) fn process_transmit_queue(&mut self) -> Result<(), ConsoleError> {
+        let mem = mem_of_active_device!(self.device_state);
+        let mut used_any = false;
+
+        while let Some(head) = self.queues[TRANSMITQ_INDEX].pop(mem) {
+            let head_index = head.index;
+
+            let mut next_desc = Some(head);
+            while let Some(desc) = next_desc {
+                if desc.is_write_only() {
+                    break;
+                }
+                let mut data = vec![0u8; desc.len as usize];
+                if let Err(e) = mem.read_slice(&mut data, desc.addr) {
+                    report_console_event_fail(ConsoleError::GuestMemory(e));
+                    break;
+                }
+                Self::write_output(&mut self.output, &data);
+                next_desc = desc.next_descriptor();
+            }
+
+            self.queues[TRANSMITQ_INDEX]
+                .add_used(mem, head_index, 0)
+                .map_err(ConsoleError::Queue)?;
+            used_any = true;
+        }
+
+        if used_any {
+            self.signal_used_queue()?;
+        }
+        Ok(())
+    }
+
+    // Like the legacy serial console, the output which the host can't take right away is
+    // dropped instead of stalling the guest.
+    fn write_output(output: &mut Box<dyn io::Write + Send>, data: &[u8]) {
+        match output.write_all(data).and_then(|_| output.flush()) {
+            Ok(()) => METRICS.console.tx_bytes_count.add(data.len()),
+            Err(e) => {
+                error!("Failed to write the console output: {}", e);
+                METRICS.console.tx_dropped_bytes_count.add(data.len());
+            }
+        }
+    }
+}
+
+impl VirtioDevice for Console {
+    fn device_type(&self) -> u32 {
+        TYPE_CONSOLE
+    }
+
+    fn queues(&self) -> &[Queue] {
+        &self.queues
+    }
+
+    fn queues_mut(&mut self) -> &mut [Queue] {
+        &mut self.queues
+    }
+
+    fn queue_events(&self) -> &[EventFd] {
+        &self.queue_evts
+    }
+
+    fn interrupt_evt(&self) -> &EventFd {
+        &self.interrupt_evt
+    }
+
+    fn interrupt_status(&self) -> Arc<AtomicUsize> {
+        self.interrupt_status.clone()
+    }
+
+    fn irq_counter(&self) -> &SharedIncMetric {
+        &self.irq_counter
+    }
+
+    fn set_irq_counter(&mut self, irq_counter: Arc<SharedIncMetric>) {
+        self.irq_counter = irq_counter;
+    }
+
+    fn avail_features(&self) -> u64 {
+        self.avail_features
+    }
+
+    fn acked_features(&self) -> u64 {
+        self.acked_features
+    }
+
+    fn set_acked_features(&mut self, acked_features: u64) {
+        self.acked_features = acked_features;
+    }
+
+    fn read_config(&self, _offset: u64, _data: &mut [u8]) {
+        error!("The console device has no configuration space");
+    }
+
+    fn write_config(&mut self, _offset: u64, _data: &[u8]) {
+        error!("The console device has no configuration space");
+    }
+
+    fn is_activated(&self) -> bool {
+        match self.device_state {
+            DeviceState::Inactive => false,
+            DeviceState::Activated(_) => true,
+        }
+    }
+
+    fn activate(&mut self, mem: GuestMemoryMmap) -> ActivateResult {
+        self.device_state = DeviceState::Activated(mem);
+        if self.activate_evt.write(1).is_err() {
+            error!("Console: Cannot write to activate_evt");
+            METRICS.console.activate_fails.inc();
+            self.device_state = DeviceState::Inactive;
+            return Err(ActivateError::BadActivate);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::{Read, Write};
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::virtio::test_utils::{default_mem, VirtQueue};
+    use crate::virtio::{VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE};
+    use vm_memory::GuestAddress;
+
+    impl Console {
+        pub(crate) fn set_queue(&mut self, idx: usize, q: Queue) {
+            self.queues[idx] = q;
+        }
+    }
+
+    pub(crate) struct SharedBuffer(pub(crate) Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    pub(crate) struct TestInput(pub(crate) EventFd, pub(crate) io::Cursor<Vec<u8>>);
+
+    impl io::Read for TestInput {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.1.read(buf)
+        }
+    }
+
+    impl AsRawFd for TestInput {
+        fn as_raw_fd(&self) -> RawFd {
+            self.0.as_raw_fd()
+        }
+    }
+
+    impl ReadableFd for TestInput {}
+
+    // Creates a console with `input` as host input. The returned `EventFd` makes the host input
+    // readable from the point of view of the event loop.
+    pub(crate) fn default_console(input: &[u8]) -> (Console, EventFd, Arc<Mutex<Vec<u8>>>) {
+        let input_evt = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        let input = TestInput(
+            input_evt.try_clone().unwrap(),
+            io::Cursor::new(input.to_vec()),
+        );
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let console = Console::new(
+            Some(Box::new(input)),
+            Box::new(SharedBuffer(output.clone())),
+        )
+        .unwrap();
+        (console, input_evt, output)
+    }
+
+    #[test]
+    fn test_virtio_features() {
+        let (mut console, _, _) = default_console(b"");
+        assert_eq!(console.device_type(), TYPE_CONSOLE);
+        assert_eq!(console.id(), CONSOLE_DEV_ID);
+        assert_eq!(console.queues().len(), 2);
+
+        let features = 1u64 << VIRTIO_F_VERSION_1;
+        assert_eq!(console.avail_features_by_page(0), features as u32);
+        assert_eq!(console.avail_features_by_page(1), (features >> 32) as u32);
+        console.ack_features_by_page(0, features as u32);
+        console.ack_features_by_page(1, (features >> 32) as u32);
+        assert_eq!(console.acked_features(), features);
+
+        // There is no configuration space.
+        let mut data = [0xffu8; 4];
+        console.read_config(0, &mut data);
+        assert_eq!(data, [0xffu8; 4]);
+        console.write_config(0, &data);
+    }
+
+    #[test]
+    fn test_transmit() {
+        let mem = default_mem();
+        let txq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let (mut console, _, output) = default_console(b"");
+        console.set_queue(TRANSMITQ_INDEX, txq.create_queue());
+        console.activate(mem.clone()).unwrap();
+
+        // A chain of two readable descriptors.
+        mem.write_slice(b"hello ", GuestAddress(0x1000)).unwrap();
+        mem.write_slice(b"world", GuestAddress(0x2000)).unwrap();
+        txq.dtable[0].set(0x1000, 6, VIRTQ_DESC_F_NEXT, 1);
+        txq.dtable[1].set(0x2000, 5, 0, 0);
+        txq.avail.ring[0].set(0);
+        txq.avail.idx.set(1);
+
+        let tx_bytes = METRICS.console.tx_bytes_count.count();
+        console.process_transmit_queue().unwrap();
+        assert_eq!(txq.used.idx.get(), 1);
+        txq.check_used_elem(0, 0, 0);
+        assert_eq!(output.lock().unwrap().as_slice(), b"hello world");
+        assert_eq!(METRICS.console.tx_bytes_count.count(), tx_bytes + 11);
+        assert_eq!(console.interrupt_status().load(Ordering::SeqCst), 1);
+        assert_eq!(console.irq_counter().count(), 1);
+    }
+
+    #[test]
+    fn test_receive() {
+        let mem = default_mem();
+        let rxq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let (mut console, _, _) = default_console(b"abcdef");
+        console.set_queue(RECEIVEQ_INDEX, rxq.create_queue());
+        console.activate(mem.clone()).unwrap();
+
+        // Nothing happens without input.
+        console.process_receive_queue().unwrap();
+        assert_eq!(rxq.used.idx.get(), 0);
+
+        assert_eq!(console.read_input().unwrap(), 6);
+        assert_eq!(console.read_input().unwrap(), 0);
+
+        // Without guest buffers, the input stays buffered.
+        console.process_receive_queue().unwrap();
+        assert_eq!(rxq.used.idx.get(), 0);
+
+        // The input is split across the available buffers.
+        rxq.dtable[0].set(0x1000, 4, VIRTQ_DESC_F_WRITE, 0);
+        rxq.dtable[1].set(0x2000, 4, VIRTQ_DESC_F_WRITE, 0);
+        rxq.avail.ring[0].set(0);
+        rxq.avail.ring[1].set(1);
+        rxq.avail.idx.set(2);
+
+        let rx_bytes = METRICS.console.rx_bytes_count.count();
+        console.process_receive_queue().unwrap();
+        assert_eq!(rxq.used.idx.get(), 2);
+        rxq.check_used_elem(0, 0, 4);
+        rxq.check_used_elem(1, 1, 2);
+        assert_eq!(METRICS.console.rx_bytes_count.count(), rx_bytes + 6);
+
+        let mut data = [0u8; 6];
+        mem.read_slice(&mut data[..4], GuestAddress(0x1000))
+            .unwrap();
+        mem.read_slice(&mut data[4..], GuestAddress(0x2000))
+            .unwrap();
+        assert_eq!(&data, b"abcdef");
+    }
+
+    #[test]
+    fn test_input_buffer_limit() {
+        let (mut console, _, _) = default_console(&[b'x'; INPUT_BUFFER_SIZE + 1]);
+        assert!(console.has_input_room());
+        assert_eq!(console.read_input().unwrap(), INPUT_BUFFER_SIZE);
+        assert!(!console.has_input_room());
+        // No more input is read while the buffer is full.
+        assert_eq!(console.read_input().unwrap(), 0);
+    }
+}
@@ -0,0 +1,220 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::os::unix::io::AsRawFd;
+
+use event_manager::{EventOps, Events, MutEventSubscriber};
+use logger::{debug, error, warn, IncMetric, METRICS};
+use utils::epoll::EventSet;
+
+use crate::report_console_event_fail;
+use crate::virtio::{console::device::Console, VirtioDevice, RECEIVEQ_INDEX, TRANSMITQ_INDEX};
+
+impl Console {
+    fn register_runtime_events(&self, ops: &mut EventOps) {
+        if let Err(e) = ops.add(Events::new(&self.queue_evts[RECEIVEQ_INDEX], EventSet::IN)) {
+            error!("Failed to register console receive queue event: {}", e);
+        }
+        if let Err(e) = ops.add(Events::new(&self.queue_evts[TRANSMITQ_INDEX], EventSet::IN)) {
+            error!("Failed to register console transmit queue event: {}", e);
+        }
+        if !self.input_paused {
+            self.register_input(ops);
+        }
+    }
+
+    fn register_activate_event(&self, ops: &mut EventOps) {
+        if let Err(e) = ops.add(Events::new(&self.activate_evt, EventSet::IN)) {
+            error!("Failed to register activate event: {}", e);
+        }
+    }
+
+    fn register_input(&self, ops: &mut EventOps) {
+        if let Some(input_fd) = self.input_fd() {
+            if let Err(e) = ops.add(Events::new(&input_fd, EventSet::IN)) {
+                error!("Failed to register console input fd: {}", e);
+            }
+        }
+    }
+
+    fn unregister_input(&self, ops: &mut EventOps) {
+        if let Some(input_fd) = self.input_fd() {
+            if let Err(e) = ops.remove(Events::new(&input_fd, EventSet::IN)) {
+                error!("Failed to un-register console input fd: {}", e);
+            }
+        }
+    }
+
+    fn process_activate_event(&self, ops: &mut EventOps) {
+        debug!("console: activate event");
+        if let Err(e) = self.activate_evt.read() {
+            error!("Failed to consume console activate event: {:?}", e);
+        }
+        self.register_runtime_events(ops);
+        if let Err(e) = ops.remove(Events::new(&self.activate_evt, EventSet::IN)) {
+            error!("Failed to un-register activate event: {}", e);
+        }
+    }
+
+    fn process_receive_queue_event(&mut self, ops: &mut EventOps) {
+        if let Err(e) = self.queue_evts[RECEIVEQ_INDEX].read() {
+            error!("Failed to get console receive queue event: {:?}", e);
+            METRICS.console.event_fails.inc();
+        }
+        self.process_receive_queue()
+            .unwrap_or_else(report_console_event_fail);
+
+        // The guest made room for more input, so the host input can be read again.
+        if self.input_paused && self.has_input_room() {
+            self.input_paused = false;
+            self.register_input(ops);
+        }
+    }
+
+    fn process_transmit_queue_event(&mut self) {
+        if let Err(e) = self.queue_evts[TRANSMITQ_INDEX].read() {
+            error!("Failed to get console transmit queue event: {:?}", e);
+            METRICS.console.event_fails.inc();
+        }
+        self.process_transmit_queue()
+            .unwrap_or_else(report_console_event_fail);
+    }
+
+    fn process_input_event(&mut self, ops: &mut EventOps) {
+        match self.read_input() {
+            // EOF, the host input is gone.
+            Ok(0) => {
+                self.unregister_input(ops);
+                warn!("Detached the console input due to peer close.");
+            }
+            Ok(_) => (),
+            Err(e) => {
+                report_console_event_fail(e);
+                self.unregister_input(ops);
+                warn!("Detached the console input due to error.");
+                return;
+            }
+        }
+        self.process_receive_queue()
+            .unwrap_or_else(report_console_event_fail);
+
+        // Stop reading the host input until the guest consumes the buffered one, so that the
+        // input is never dropped.
+        if !self.has_input_room() {
+            self.input_paused = true;
+            self.unregister_input(ops);
+            METRICS.console.rx_paused_count.inc();
+        }
+    }
+}
+
+impl MutEventSubscriber for Console {
+    fn process(&mut self, event: Events, ops: &mut EventOps) {
+        let source = event.fd();
+        let event_set = event.event_set();
+        let supported_events = EventSet::IN | EventSet::HANG_UP | EventSet::ERROR;
+
+        if !supported_events.contains(event_set) {
+            warn!(
+                "Received unknown event: {:?} from source: {:?}",
+                event_set, source
+            );
+            return;
+        }
+
+        if self.is_activated() {
+            let receiveq_ev_fd = self.queue_evts[RECEIVEQ_INDEX].as_raw_fd();
+            let transmitq_ev_fd = self.queue_evts[TRANSMITQ_INDEX].as_raw_fd();
+            let input_fd = self.input_fd();
+            let activate_fd = self.activate_evt.as_raw_fd();
+
+            // Looks better than C style if/else if/else.
+            match source {
+                _ if source == receiveq_ev_fd => self.process_receive_queue_event(ops),
+                _ if source == transmitq_ev_fd => self.process_transmit_queue_event(),
+                _ if Some(source) == input_fd => self.process_input_event(ops),
+                _ if activate_fd == source => self.process_activate_event(ops),
+                _ => {
+                    warn!("Console: Spurious event received: {:?}", source);
+                }
+            };
+        } else {
+            warn!(
+                "Console: The device is not yet activated. Spurious event received: {:?}",
+                source
+            );
+        }
+    }
+
+    fn init(&mut self, ops: &mut EventOps) {
+        // The host input is only read once the device is activated, until then it stays
+        // buffered in the host.
+        if self.is_activated() {
+            self.register_runtime_events(ops);
+        } else {
+            self.register_activate_event(ops);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::virtio::console::device::tests::default_console;
+    use crate::virtio::console::INPUT_BUFFER_SIZE;
+    use crate::virtio::test_utils::{default_mem, VirtQueue};
+    use crate::virtio::VIRTQ_DESC_F_WRITE;
+    use event_manager::{EventManager, SubscriberOps};
+    use vm_memory::{Bytes, GuestAddress};
+
+    #[test]
+    fn test_event_handler() {
+        let mut event_manager = EventManager::new().unwrap();
+        let mem = default_mem();
+        let rxq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let (mut console, input_evt, _) = default_console(&[b'x'; INPUT_BUFFER_SIZE + 1]);
+        console.set_queue(RECEIVEQ_INDEX, rxq.create_queue());
+
+        let console = Arc::new(Mutex::new(console));
+        let _id = event_manager.add_subscriber(console.clone());
+
+        // The host input is not read before the device is activated.
+        input_evt.write(1).unwrap();
+        assert_eq!(event_manager.run_with_timeout(50).unwrap(), 0);
+
+        console.lock().unwrap().activate(mem.clone()).unwrap();
+        // Process the activate event.
+        assert_eq!(event_manager.run_with_timeout(50).unwrap(), 1);
+
+        // The guest has no receive buffers, so reading the input fills up the device buffer and
+        // pauses the host input.
+        let rx_paused = METRICS.console.rx_paused_count.count();
+        assert_eq!(event_manager.run_with_timeout(50).unwrap(), 1);
+        assert!(console.lock().unwrap().input_paused);
+        assert!(!console.lock().unwrap().has_input_room());
+        assert_eq!(METRICS.console.rx_paused_count.count(), rx_paused + 1);
+        // The host input stays readable, but it is not polled anymore.
+        assert_eq!(event_manager.run_with_timeout(50).unwrap(), 0);
+
+        // The guest makes a receive buffer available, which resumes the host input.
+        rxq.dtable[0].set(0x1000, 16, VIRTQ_DESC_F_WRITE, 0);
+        rxq.avail.ring[0].set(0);
+        rxq.avail.idx.set(1);
+        console.lock().unwrap().queue_evts[RECEIVEQ_INDEX]
+            .write(1)
+            .unwrap();
+        assert_eq!(event_manager.run_with_timeout(50).unwrap(), 1);
+        assert_eq!(rxq.used.idx.get(), 1);
+        rxq.check_used_elem(0, 0, 16);
+        let mut data = [0u8; 16];
+        mem.read_slice(&mut data, GuestAddress(0x1000)).unwrap();
+        assert_eq!(data, [b'x'; 16]);
+        assert!(!console.lock().unwrap().input_paused);
+
+        // The rest of the host input is read.
+        assert_eq!(event_manager.run_with_timeout(50).unwrap(), 1);
+        assert!(console.lock().unwrap().has_input_room());
+    }
+}
@@ -0,0 +1,39 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+pub mod device;
+pub mod event_handler;
+
+use vm_memory::GuestMemoryError;
+
+pub use self::device::Console;
+pub use self::event_handler::*;
+
+/// Device ID used in MMIO device identification.
+/// Because the console is unique per-vm, this ID can be hardcoded.
+pub const CONSOLE_DEV_ID: &str = "console";
+pub const QUEUE_SIZE: u16 = 256;
+pub const NUM_QUEUES: usize = 2;
+pub const QUEUE_SIZES: &[u16] = &[QUEUE_SIZE, QUEUE_SIZE];
+// The index of the receive queue, carrying the host input to the guest.
+pub const RECEIVEQ_INDEX: usize = 0;
+// The index of the transmit queue, carrying the guest output to the host.
+pub const TRANSMITQ_INDEX: usize = 1;
+// The maximum amount of host input buffered while the guest is not consuming it.
+pub const INPUT_BUFFER_SIZE: usize = 4096;
+
+#[derive(Debug)]
+pub enum Error {
+    /// EventFd error.
+    EventFd(std::io::Error),
+    /// Failed to signal the virtio used queue.
+    FailedSignalingUsedQueue(std::io::Error),
+    /// Guest gave us bad memory addresses.
+    GuestMemory(GuestMemoryError),
+    /// Error while processing the virt queues.
+    Queue(super::QueueError),
+    /// Failed to read the host input.
+    ReadInput(std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
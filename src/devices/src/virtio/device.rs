@@ -9,7 +9,7 @@ use std::sync::{atomic::AtomicUsize, Arc};
 
 use super::{ActivateResult, Queue};
 use crate::virtio::AsAny;
-use logger::warn;
+use logger::{warn, SharedIncMetric};
 use utils::eventfd::EventFd;
 use vm_memory::GuestMemoryMmap;
 
@@ -55,6 +55,13 @@ pub trait VirtioDevice: AsAny + Send {
     /// Returns the current device interrupt status.
     fn interrupt_status(&self) -> Arc<AtomicUsize>;
 
+    /// Returns the counter of the interrupts raised by the device.
+    fn irq_counter(&self) -> &SharedIncMetric;
+
+    /// Replaces the counter of the interrupts raised by the device, so that they are accounted
+    /// in the metrics of the device once it's registered.
+    fn set_irq_counter(&mut self, irq_counter: Arc<SharedIncMetric>);
+
     /// The set of feature bits shifted by `page * 32`.
     fn avail_features_by_page(&self, page: u32) -> u32 {
         let avail_features = self.avail_features();
@@ -0,0 +1,448 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::cmp;
+use std::io::Write;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::result::Result;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use logger::{error, IncMetric, SharedIncMetric, METRICS};
+use utils::eventfd::EventFd;
+use virtio_gen::virtio_blk::VIRTIO_F_VERSION_1;
+use vm_memory::{ByteValued, Bytes, GuestAddress, GuestMemoryMmap};
+
+use super::super::{
+    ActivateError, ActivateResult, DescriptorChain, DeviceState, Queue, VirtioDevice, TYPE_FS,
+    VIRTIO_MMIO_INT_VRING,
+};
+use super::fuse::{InHeader, WriteIn};
+use super::passthrough::{PassthroughFs, MAX_IO_SIZE};
+use super::{Error as FsError, FS_QUEUE_SIZES, FS_TAG_MAX_LEN};
+use crate::report_fs_event_fail;
+
+// The longest request accepted from the guest: a write of the maximum size, plus some room
+// for the names of the requests taking up to two of them.
+const MAX_REQUEST_LEN: usize = mem::size_of::<InHeader>()
+    + mem::size_of::<WriteIn>()
+    + MAX_IO_SIZE as usize
+    + 2 * (libc::NAME_MAX as usize + 1);
+
+macro_rules! mem_of_active_device {
+    ($state:expr) => {
+        match $state {
+            DeviceState::Activated(ref mem) => mem,
+            // This should never happen, it's been already validated in the event handler.
+            DeviceState::Inactive => unreachable!(),
+        }
+    };
+}
+
+// The layout of the virtio-fs device configuration.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct ConfigSpace {
+    pub tag: [u8; FS_TAG_MAX_LEN],
+    pub num_request_queues: u32,
+}
+
+impl Default for ConfigSpace {
+    fn default() -> ConfigSpace {
+        ConfigSpace {
+            tag: [0; FS_TAG_MAX_LEN],
+            num_request_queues: 0,
+        }
+    }
+}
+
+unsafe impl ByteValued for ConfigSpace {}
+
+/// Virtio-fs device sharing a host directory with the guest, which mounts it by its tag.
+///
+/// The requests are served by a single request queue, on the VMM thread. None of the optional
+/// features (notification queue, DAX window) are offered.
+pub struct Fs {
+    id: String,
+    shared_dir: PathBuf,
+
+    // Virtio fields.
+    pub(crate) avail_features: u64,
+    pub(crate) acked_features: u64,
+    pub(crate) config_space: ConfigSpace,
+    pub(crate) interrupt_status: Arc<AtomicUsize>,
+    pub(crate) irq_counter: Arc<SharedIncMetric>,
+    pub(crate) interrupt_evt: EventFd,
+
+    // Transport related fields.
+    pub(crate) queues: Vec<Queue>,
+    pub(crate) queue_evts: Vec<EventFd>,
+    pub(crate) device_state: DeviceState,
+    pub(crate) activate_evt: EventFd,
+
+    server: PassthroughFs,
+}
+
+impl Fs {
+    /// Creates a device sharing the directory at `shared_dir` under the name `tag`. In
+    /// read-only mode, the guest can't change anything in the directory.
+    pub fn new(id: String, shared_dir: &Path, tag: &str, read_only: bool) -> Result<Fs, FsError> {
+        if tag.is_empty() || tag.len() > FS_TAG_MAX_LEN {
+            return Err(FsError::InvalidTag(tag.to_string()));
+        }
+        let mut config_space = ConfigSpace {
+            num_request_queues: 1u32.to_le(),
+            ..Default::default()
+        };
+        config_space.tag[..tag.len()].copy_from_slice(tag.as_bytes());
+
+        let server = PassthroughFs::new(shared_dir, read_only).map_err(FsError::SharedDir)?;
+        let queue_evts = vec![
+            EventFd::new(libc::EFD_NONBLOCK).map_err(FsError::EventFd)?,
+            EventFd::new(libc::EFD_NONBLOCK).map_err(FsError::EventFd)?,
+        ];
+        let queues: Vec<Queue> = FS_QUEUE_SIZES.iter().map(|&s| Queue::new(s)).collect();
+
+        Ok(Fs {
+            id,
+            shared_dir: shared_dir.to_path_buf(),
+            avail_features: 1u64 << VIRTIO_F_VERSION_1,
+            acked_features: 0u64,
+            config_space,
+            interrupt_status: Arc::new(AtomicUsize::new(0)),
+            irq_counter: Arc::default(),
+            interrupt_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(FsError::EventFd)?,
+            queues,
+            queue_evts,
+            device_state: DeviceState::Inactive,
+            activate_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(FsError::EventFd)?,
+            server,
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Provides the path of the shared directory.
+    pub fn shared_dir(&self) -> &Path {
+        &self.shared_dir
+    }
+
+    /// Provides the tag through which the guest mounts the file system.
+    pub fn tag(&self) -> String {
+        let tag = &self.config_space.tag;
+        let len = tag.iter().position(|&b| b == 0).unwrap_or(tag.len());
+        String::from_utf8_lossy(&tag[..len]).into_owned()
+    }
+
+    /// Whether the guest can't change anything in the shared directory.
+    pub fn is_read_only(&self) -> bool {
+        self.server.is_read_only()
+    }
+
+    pub(crate) fn signal_used_queue(&self) -> Result<(), FsError> {
+        self.interrupt_status
+            .fetch_or(VIRTIO_MMIO_INT_VRING as usize, Ordering::SeqCst);
+
+        self.interrupt_evt.write(1).map_err(|e| {
+            error!("Failed to signal used queue: {:?}", e);
+            FsError::FailedSignalingUsedQueue(e)
+        })?;
+        self.irq_counter.inc();
+        Ok(())
+    }
+
+    // Splits a descriptor chain into the request, gathered from the device-readable
+    // descriptors, and the device-writable descriptors, which receive the reply.
+    fn parse_chain(
+        mem: &GuestMemoryMmap,
+        head: DescriptorChain,
+    ) -> Result<(Vec<u8>, Vec<(GuestAddress, usize)>), FsError> {
+        let mut request = Vec::new();
+        let mut reply_descs = Vec::new();
+
+        let mut next_desc = Some(head);
+        while let Some(desc) = next_desc {
+            if desc.is_write_only() {
+                reply_descs.push((desc.addr, desc.len as usize));
+            } else {
+                let len = request.len() + desc.len as usize;
+                if len > MAX_REQUEST_LEN {
+                    return Err(FsError::RequestTooLong(len));
+                }
+                let start = request.len();
+                request.resize(len, 0);
+                mem.read_slice(&mut request[start..], desc.addr)
+                    .map_err(FsError::GuestMemory)?;
+            }
+            next_desc = desc.next_descriptor();
+        }
+        Ok((request, reply_descs))
+    }
+
+    // Writes the reply to the device-writable descriptors and returns its length. A reply
+    // which doesn't fit is truncated.
+    fn write_reply(
+        mem: &GuestMemoryMmap,
+        mut reply: &[u8],
+        reply_descs: &[(GuestAddress, usize)],
+    ) -> Result<usize, FsError> {
+        let mut written = 0;
+        for &(addr, len) in reply_descs {
+            if reply.is_empty() {
+                break;
+            }
+            let count = cmp::min(len, reply.len());
+            mem.write_slice(&reply[..count], addr)
+                .map_err(FsError::GuestMemory)?;
+            reply = &reply[count..];
+            written += count;
+        }
+        Ok(written)
+    }
+
+    /// Serves the requests made available by the guest on the queue with the given index.
+    pub(crate) fn process_queue(&mut self, queue_index: usize) -> Result<(), FsError> {
+        let mem = mem_of_active_device!(self.device_state);
+        let mut used_any = false;
+
+        while let Some(head) = self.queues[queue_index].pop(mem) {
+            let head_index = head.index;
+            METRICS.fs.request_count.inc();
+
+            let server = &mut self.server;
+            let result = Self::parse_chain(mem, head).and_then(|(request, reply_descs)| {
+                // Some requests, like `FORGET`, have no reply.
+                match server.handle_message(&request) {
+                    Some(reply) => Self::write_reply(mem, &reply, &reply_descs),
+                    None => Ok(0),
+                }
+            });
+            let written = result.unwrap_or_else(|e| {
+                METRICS.fs.invalid_reqs_count.inc();
+                report_fs_event_fail(e);
+                0
+            });
+
+            // The request is returned to the guest even if it was bad, so that the driver
+            // doesn't run out of buffers.
+            self.queues[queue_index]
+                .add_used(mem, head_index, written as u32)
+                .map_err(FsError::Queue)?;
+            used_any = true;
+        }
+
+        if used_any {
+            self.signal_used_queue()?;
+        }
+        Ok(())
+    }
+}
+
+impl VirtioDevice for Fs {
+    fn device_type(&self) -> u32 {
+        TYPE_FS
+    }
+
+    fn queues(&self) -> &[Queue] {
+        &self.queues
+    }
+
+    fn queues_mut(&mut self) -> &mut [Queue] {
+        &mut self.queues
+    }
+
+    fn queue_events(&self) -> &[EventFd] {
+        &self.queue_evts
+    }
+
+    fn interrupt_evt(&self) -> &EventFd {
+        &self.interrupt_evt
+    }
+
+    fn interrupt_status(&self) -> Arc<AtomicUsize> {
+        self.interrupt_status.clone()
+    }
+
+    fn irq_counter(&self) -> &SharedIncMetric {
+        &self.irq_counter
+    }
+
+    fn set_irq_counter(&mut self, irq_counter: Arc<SharedIncMetric>) {
+        self.irq_counter = irq_counter;
+    }
+
+    fn avail_features(&self) -> u64 {
+        self.avail_features
+    }
+
+    fn acked_features(&self) -> u64 {
+        self.acked_features
+    }
+
+    fn set_acked_features(&mut self, acked_features: u64) {
+        self.acked_features = acked_features;
+    }
+
+    fn read_config(&self, offset: u64, mut data: &mut [u8]) {
+        let config_space_bytes = self.config_space.as_slice();
+        let config_len = config_space_bytes.len() as u64;
+        if offset >= config_len {
+            error!("Failed to read config space");
+            METRICS.fs.cfg_fails.inc();
+            return;
+        }
+        if let Some(end) = offset.checked_add(data.len() as u64) {
+            // This write can't fail, offset and end are checked against config_len.
+            data.write_all(
+                &config_space_bytes[offset as usize..cmp::min(end, config_len) as usize],
+            )
+            .unwrap();
+        }
+    }
+
+    fn write_config(&mut self, _offset: u64, _data: &[u8]) {
+        error!("The virtio-fs configuration space is read-only");
+        METRICS.fs.cfg_fails.inc();
+    }
+
+    fn is_activated(&self) -> bool {
+        match self.device_state {
+            DeviceState::Inactive => false,
+            DeviceState::Activated(_) => true,
+        }
+    }
+
+    fn activate(&mut self, mem: GuestMemoryMmap) -> ActivateResult {
+        self.device_state = DeviceState::Activated(mem);
+        if self.activate_evt.write(1).is_err() {
+            error!("Fs: Cannot write to activate_evt");
+            METRICS.fs.activate_fails.inc();
+            self.device_state = DeviceState::Inactive;
+            return Err(ActivateError::BadActivate);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::virtio::fs::fuse::{self, InitIn, InitOut, OutHeader};
+    use crate::virtio::fs::passthrough::tests::{parse_reply, request};
+    use crate::virtio::fs::REQUEST_QUEUE_INDEX;
+    use crate::virtio::test_utils::{default_mem, VirtQueue};
+    use crate::virtio::{VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE};
+    use utils::tempdir::TempDir;
+
+    impl Fs {
+        pub(crate) fn set_queue(&mut self, idx: usize, q: Queue) {
+            self.queues[idx] = q;
+        }
+    }
+
+    pub(crate) fn default_fs(dir: &TempDir) -> Fs {
+        Fs::new("fs0".to_string(), dir.as_path(), "shared", false).unwrap()
+    }
+
+    #[test]
+    fn test_new() {
+        let dir = TempDir::new().unwrap();
+        let mut fs = default_fs(&dir);
+        assert_eq!(fs.device_type(), TYPE_FS);
+        assert_eq!(fs.id(), "fs0");
+        assert_eq!(fs.tag(), "shared");
+        assert_eq!(fs.shared_dir(), dir.as_path());
+        assert!(!fs.is_read_only());
+        assert_eq!(fs.queues().len(), 2);
+
+        let features = 1u64 << VIRTIO_F_VERSION_1;
+        assert_eq!(fs.avail_features_by_page(0), features as u32);
+        fs.ack_features_by_page(0, features as u32);
+        assert_eq!(fs.acked_features(), features);
+
+        // The tag is followed by the number of request queues.
+        let mut data = [0u8; 40];
+        fs.read_config(0, &mut data);
+        assert_eq!(&data[..6], b"shared");
+        assert_eq!(&data[6..36], &[0u8; 30][..]);
+        assert_eq!(&data[36..], &1u32.to_le_bytes());
+        // The configuration space can't be written.
+        fs.write_config(0, b"other");
+        assert_eq!(fs.tag(), "shared");
+
+        for tag in &["", "a-tag-which-is-longer-than-36-characters"] {
+            match Fs::new("fs1".to_string(), dir.as_path(), tag, false) {
+                Err(FsError::InvalidTag(_)) => (),
+                _ => panic!("The tag {:?} should be invalid", tag),
+            }
+        }
+        match Fs::new("fs1".to_string(), Path::new("/nonexistent"), "tag", false) {
+            Err(FsError::SharedDir(_)) => (),
+            _ => panic!("The shared directory should not exist"),
+        }
+    }
+
+    #[test]
+    fn test_process_queue() {
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let dir = TempDir::new().unwrap();
+        let mut fs = default_fs(&dir);
+        fs.set_queue(REQUEST_QUEUE_INDEX, vq.create_queue());
+        fs.activate(mem.clone()).unwrap();
+
+        // An init request, followed by the buffer for the reply.
+        let arg = InitIn {
+            major: fuse::KERNEL_VERSION,
+            minor: fuse::KERNEL_MINOR_VERSION,
+            ..Default::default()
+        };
+        let init = request(fuse::INIT, 0, &[arg.as_slice()]);
+        mem.write_slice(&init, GuestAddress(0x1000)).unwrap();
+        let reply_len = mem::size_of::<OutHeader>() + mem::size_of::<InitOut>();
+        vq.dtable[0].set(0x1000, init.len() as u32, VIRTQ_DESC_F_NEXT, 1);
+        vq.dtable[1].set(0x2000, reply_len as u32, VIRTQ_DESC_F_WRITE, 0);
+        vq.avail.ring[0].set(0);
+        // A request which is too short for a header.
+        vq.dtable[2].set(0x3000, 4, VIRTQ_DESC_F_NEXT, 3);
+        vq.dtable[3].set(0x4000, 64, VIRTQ_DESC_F_WRITE, 0);
+        vq.avail.ring[1].set(2);
+        vq.avail.idx.set(2);
+
+        let requests = METRICS.fs.request_count.count();
+        fs.process_queue(REQUEST_QUEUE_INDEX).unwrap();
+        assert_eq!(METRICS.fs.request_count.count(), requests + 2);
+        assert_eq!(vq.used.idx.get(), 2);
+        vq.check_used_elem(0, 0, reply_len as u32);
+        vq.check_used_elem(1, 2, 0);
+        assert_eq!(fs.interrupt_status().load(Ordering::SeqCst), 1);
+
+        let mut reply = vec![0u8; reply_len];
+        mem.read_slice(&mut reply, GuestAddress(0x2000)).unwrap();
+        let (error, payload) = parse_reply(&reply);
+        assert_eq!(error, 0);
+        assert_eq!(payload.len(), mem::size_of::<InitOut>());
+    }
+
+    #[test]
+    fn test_request_too_long() {
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let dir = TempDir::new().unwrap();
+        let mut fs = default_fs(&dir);
+        fs.set_queue(REQUEST_QUEUE_INDEX, vq.create_queue());
+        fs.activate(mem.clone()).unwrap();
+
+        vq.dtable[0].set(0x1000, MAX_REQUEST_LEN as u32 + 1, 0, 0);
+        vq.avail.ring[0].set(0);
+        vq.avail.idx.set(1);
+
+        let invalid_reqs = METRICS.fs.invalid_reqs_count.count();
+        fs.process_queue(REQUEST_QUEUE_INDEX).unwrap();
+        assert_eq!(METRICS.fs.invalid_reqs_count.count(), invalid_reqs + 1);
+        vq.check_used_elem(0, 0, 0);
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::os::unix::io::AsRawFd;
+
+use event_manager::{EventOps, Events, MutEventSubscriber};
+use logger::{debug, error, warn, IncMetric, METRICS};
+use utils::epoll::EventSet;
+
+use crate::report_fs_event_fail;
+use crate::virtio::fs::{device::Fs, HIPRIO_QUEUE_INDEX, REQUEST_QUEUE_INDEX};
+use crate::virtio::VirtioDevice;
+
+impl Fs {
+    fn register_runtime_events(&self, ops: &mut EventOps) {
+        for queue_evt in self.queue_evts.iter() {
+            if let Err(e) = ops.add(Events::new(queue_evt, EventSet::IN)) {
+                error!("Failed to register fs queue event: {}", e);
+            }
+        }
+    }
+
+    fn register_activate_event(&self, ops: &mut EventOps) {
+        if let Err(e) = ops.add(Events::new(&self.activate_evt, EventSet::IN)) {
+            error!("Failed to register activate event: {}", e);
+        }
+    }
+
+    fn process_activate_event(&self, ops: &mut EventOps) {
+        debug!("fs: activate event");
+        if let Err(e) = self.activate_evt.read() {
+            error!("Failed to consume fs activate event: {:?}", e);
+        }
+        self.register_runtime_events(ops);
+        if let Err(e) = ops.remove(Events::new(&self.activate_evt, EventSet::IN)) {
+            error!("Failed to un-register activate event: {}", e);
+        }
+    }
+
+    fn process_queue_event(&mut self, queue_index: usize) {
+        METRICS.fs.queue_event_count.inc();
+        if let Err(e) = self.queue_evts[queue_index].read() {
+            error!("Failed to get fs queue event: {:?}", e);
+            METRICS.fs.event_fails.inc();
+        }
+        self.process_queue(queue_index)
+            .unwrap_or_else(report_fs_event_fail);
+    }
+}
+
+impl MutEventSubscriber for Fs {
+    fn process(&mut self, event: Events, ops: &mut EventOps) {
+        let source = event.fd();
+        let event_set = event.event_set();
+        let supported_events = EventSet::IN;
+
+        if !supported_events.contains(event_set) {
+            warn!(
+                "Received unknown event: {:?} from source: {:?}",
+                event_set, source
+            );
+            return;
+        }
+
+        if self.is_activated() {
+            let hiprio_ev_fd = self.queue_evts[HIPRIO_QUEUE_INDEX].as_raw_fd();
+            let request_ev_fd = self.queue_evts[REQUEST_QUEUE_INDEX].as_raw_fd();
+            let activate_fd = self.activate_evt.as_raw_fd();
+
+            // Looks better than C style if/else if/else.
+            match source {
+                _ if source == hiprio_ev_fd => self.process_queue_event(HIPRIO_QUEUE_INDEX),
+                _ if source == request_ev_fd => self.process_queue_event(REQUEST_QUEUE_INDEX),
+                _ if activate_fd == source => self.process_activate_event(ops),
+                _ => {
+                    warn!("Fs: Spurious event received: {:?}", source);
+                }
+            };
+        } else {
+            warn!(
+                "Fs: The device is not yet activated. Spurious event received: {:?}",
+                source
+            );
+        }
+    }
+
+    fn init(&mut self, ops: &mut EventOps) {
+        // The requests are only served once the device is activated.
+        if self.is_activated() {
+            self.register_runtime_events(ops);
+        } else {
+            self.register_activate_event(ops);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::virtio::fs::device::tests::default_fs;
+    use crate::virtio::test_utils::{default_mem, VirtQueue};
+    use event_manager::{EventManager, SubscriberOps};
+    use utils::tempdir::TempDir;
+    use vm_memory::GuestAddress;
+
+    #[test]
+    fn test_event_handler() {
+        let mut event_manager = EventManager::new().unwrap();
+        let mem = default_mem();
+        let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let dir = TempDir::new().unwrap();
+        let mut fs = default_fs(&dir);
+        fs.set_queue(HIPRIO_QUEUE_INDEX, vq.create_queue());
+
+        let fs = Arc::new(Mutex::new(fs));
+        let _id = event_manager.add_subscriber(fs.clone());
+
+        // The queue events are not handled before the device is activated.
+        fs.lock().unwrap().queue_evts[HIPRIO_QUEUE_INDEX]
+            .write(1)
+            .unwrap();
+        assert_eq!(event_manager.run_with_timeout(50).unwrap(), 0);
+
+        fs.lock().unwrap().activate(mem).unwrap();
+        // Process the activate event, then the pending queue event.
+        assert_eq!(event_manager.run_with_timeout(50).unwrap(), 1);
+        let queue_events = METRICS.fs.queue_event_count.count();
+        assert_eq!(event_manager.run_with_timeout(50).unwrap(), 1);
+        assert_eq!(METRICS.fs.queue_event_count.count(), queue_events + 1);
+        // There was no request on the queue.
+        assert_eq!(vq.used.idx.get(), 0);
+    }
+}
@@ -0,0 +1,45 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Implements a virtio-fs device, sharing a host directory with the guest.
+
+pub mod device;
+pub mod event_handler;
+pub mod fuse;
+pub mod passthrough;
+
+use vm_memory::GuestMemoryError;
+
+pub use self::device::Fs;
+pub use self::event_handler::*;
+
+pub const FS_QUEUE_SIZE: u16 = 256;
+pub const FS_NUM_QUEUES: usize = 2;
+pub const FS_QUEUE_SIZES: &[u16] = &[FS_QUEUE_SIZE, FS_QUEUE_SIZE];
+// The index of the high priority queue, carrying the requests which interrupt or forget other
+// requests.
+pub const HIPRIO_QUEUE_INDEX: usize = 0;
+// The index of the queue carrying all the other requests.
+pub const REQUEST_QUEUE_INDEX: usize = 1;
+/// The maximum length of the tag through which the guest mounts the file system.
+pub const FS_TAG_MAX_LEN: usize = 36;
+
+#[derive(Debug)]
+pub enum Error {
+    /// EventFd error.
+    EventFd(std::io::Error),
+    /// Failed to signal the virtio used queue.
+    FailedSignalingUsedQueue(std::io::Error),
+    /// Guest gave us bad memory addresses.
+    GuestMemory(GuestMemoryError),
+    /// The tag is empty or longer than `FS_TAG_MAX_LEN`.
+    InvalidTag(String),
+    /// Error while processing the virt queues.
+    Queue(super::QueueError),
+    /// The guest sent a request longer than the device accepts.
+    RequestTooLong(usize),
+    /// The shared directory can't be opened.
+    SharedDir(std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,1283 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! A FUSE server exposing a host directory to the guest.
+//!
+//! The guest can't reach anything outside the shared directory. Every node known to the guest
+//! is held open with an `O_PATH` file descriptor, and nodes are only ever resolved one name at
+//! a time, relative to the descriptor of their parent directory and without following
+//! symbolic links. Names which are not a single path component (`.`, `..`, or containing a
+//! `/`) are refused.
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::Path;
+
+use logger::{debug, warn};
+use vm_memory::ByteValued;
+
+use super::fuse::*;
+
+/// The largest read or write the guest can request, which is also the size of the biggest
+/// request the device accepts, besides the request headers.
+pub const MAX_IO_SIZE: u32 = 128 * 1024;
+// How long the guest can cache the names and the attributes of the nodes, in seconds.
+const ATTR_TTL_SECS: u64 = 1;
+// The open flags passed on to the host, any other flag requested by the guest is ignored.
+const OPEN_FLAGS_MASK: i32 = libc::O_ACCMODE
+    | libc::O_APPEND
+    | libc::O_TRUNC
+    | libc::O_EXCL
+    | libc::O_SYNC
+    | libc::O_DSYNC
+    | libc::O_NONBLOCK;
+// The most nodes and open files the guest can hold at once. Each of them keeps a descriptor
+// open in the Firecracker process, whose descriptor table the guest must not be able to fill.
+const MAX_INODES: usize = 512;
+const MAX_HANDLES: usize = 256;
+
+fn ebadf() -> io::Error {
+    io::Error::from_raw_os_error(libc::EBADF)
+}
+
+fn einval() -> io::Error {
+    io::Error::from_raw_os_error(libc::EINVAL)
+}
+
+fn erofs() -> io::Error {
+    io::Error::from_raw_os_error(libc::EROFS)
+}
+
+// Turns the return value of a libc call into a `Result`.
+fn check_retval<T: From<i8> + PartialEq>(ret: T) -> io::Result<T> {
+    if ret == T::from(-1) {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+// Checks that `name` is a single path component of the directory it is looked up in.
+fn validate_name(name: &CStr) -> io::Result<()> {
+    let bytes = name.to_bytes();
+    if bytes.is_empty() || bytes == b"." || bytes == b".." || bytes.contains(&b'/') {
+        return Err(einval());
+    }
+    Ok(())
+}
+
+fn openat(dir: RawFd, name: &CStr, flags: i32, mode: u32) -> io::Result<File> {
+    // Safe because the kernel only reads the nul terminated `name` and we check the return
+    // value, which is a new file descriptor owned by the returned `File`.
+    let fd =
+        check_retval(unsafe { libc::openat(dir, name.as_ptr(), flags | libc::O_CLOEXEC, mode) })?;
+    // Safe because `fd` was just opened and nothing else owns it.
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+fn stat(fd: RawFd) -> io::Result<libc::stat> {
+    // Safe because `libc::stat` is plain data, for which all zeroes is a valid value.
+    let mut st: libc::stat = unsafe { mem::zeroed() };
+    // Safe because the kernel only writes to `st`, which is big enough for a `libc::stat`, and
+    // we check the return value.
+    check_retval(unsafe {
+        libc::fstatat(
+            fd,
+            b"\0".as_ptr() as *const libc::c_char,
+            &mut st,
+            libc::AT_EMPTY_PATH | libc::AT_SYMLINK_NOFOLLOW,
+        )
+    })?;
+    Ok(st)
+}
+
+// Parses the arguments of a request.
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read_obj<T: ByteValued + Default>(&mut self) -> io::Result<T> {
+        let mut obj = T::default();
+        let len = obj.as_slice().len();
+        if self.buf.len() < len {
+            return Err(einval());
+        }
+        obj.as_mut_slice().copy_from_slice(&self.buf[..len]);
+        self.buf = &self.buf[len..];
+        Ok(obj)
+    }
+
+    fn read_name(&mut self) -> io::Result<CString> {
+        let end = self.buf.iter().position(|&b| b == 0).ok_or_else(einval)?;
+        let name = CString::new(&self.buf[..end]).map_err(|_| einval())?;
+        self.buf = &self.buf[end + 1..];
+        validate_name(&name)?;
+        Ok(name)
+    }
+
+    fn read_bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(einval());
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+}
+
+// A node of the shared directory known to the guest.
+struct Inode {
+    // An `O_PATH` descriptor of the node.
+    file: File,
+    // The device and inode numbers of the node on the host, which identify it.
+    key: (u64, u64),
+    // The type bits of the mode of the node.
+    file_type: u32,
+    // The directories and the names through which the node was looked up, which are needed to
+    // open a node which is not a directory. A node with hard links has several of them, any of
+    // which can be removed while the others still lead to it.
+    names: Vec<(u64, CString)>,
+    // The number of lookups of the node by the guest, which it drops through forget requests.
+    refcount: u64,
+}
+
+/// Serves the FUSE requests of the guest from a host directory.
+pub struct PassthroughFs {
+    read_only: bool,
+    inodes: BTreeMap<u64, Inode>,
+    inodes_by_key: BTreeMap<(u64, u64), u64>,
+    inodes_by_name: BTreeMap<(u64, CString), u64>,
+    next_inode: u64,
+    handles: BTreeMap<u64, File>,
+    next_handle: u64,
+}
+
+impl PassthroughFs {
+    /// Creates a server for the directory at `shared_dir`. In read-only mode, the guest can't
+    /// change anything in the directory.
+    pub fn new(shared_dir: &Path, read_only: bool) -> io::Result<PassthroughFs> {
+        let path = CString::new(shared_dir.as_os_str().as_bytes()).map_err(|_| einval())?;
+        let file = openat(libc::AT_FDCWD, &path, libc::O_PATH | libc::O_DIRECTORY, 0)?;
+        let st = stat(file.as_raw_fd())?;
+
+        let mut fs = PassthroughFs {
+            read_only,
+            inodes: BTreeMap::new(),
+            inodes_by_key: BTreeMap::new(),
+            inodes_by_name: BTreeMap::new(),
+            next_inode: ROOT_ID + 1,
+            handles: BTreeMap::new(),
+            next_handle: 0,
+        };
+        let key = (st.st_dev, st.st_ino);
+        fs.inodes_by_key.insert(key, ROOT_ID);
+        fs.inodes.insert(
+            ROOT_ID,
+            Inode {
+                file,
+                key,
+                file_type: libc::S_IFDIR,
+                names: Vec::new(),
+                // The root is never forgotten.
+                refcount: u64::MAX,
+            },
+        );
+        Ok(fs)
+    }
+
+    /// Whether the guest can't change anything in the shared directory.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Serves a request and returns the reply to it. There is no reply to the requests which
+    /// don't expect one and to the malformed requests.
+    pub fn handle_message(&mut self, request: &[u8]) -> Option<Vec<u8>> {
+        let mut reader = Reader { buf: request };
+        let header: InHeader = match reader.read_obj() {
+            Ok(header) => header,
+            Err(_) => {
+                warn!("virtio-fs: the request is too short for a header");
+                return None;
+            }
+        };
+
+        let result = match header.opcode {
+            // The requests without a reply.
+            FORGET => {
+                if let Ok(arg) = reader.read_obj::<ForgetIn>() {
+                    self.forget(header.nodeid, arg.nlookup);
+                }
+                return None;
+            }
+            BATCH_FORGET => {
+                self.batch_forget(&mut reader);
+                return None;
+            }
+            INIT => self.init(&mut reader),
+            DESTROY => {
+                self.destroy();
+                Ok(Vec::new())
+            }
+            LOOKUP => reader
+                .read_name()
+                .and_then(|name| self.lookup(header.nodeid, &name))
+                .map(|entry| entry.as_slice().to_vec()),
+            GETATTR => self.getattr(header.nodeid),
+            SETATTR => self.setattr(header.nodeid, &mut reader),
+            READLINK => self.readlink(header.nodeid),
+            MKDIR => self.mkdir(header.nodeid, &mut reader),
+            UNLINK => self.unlink(header.nodeid, &mut reader, 0),
+            RMDIR => self.unlink(header.nodeid, &mut reader, libc::AT_REMOVEDIR),
+            RENAME => self.rename(header.nodeid, &mut reader),
+            OPEN => self.open(header.nodeid, &mut reader),
+            OPENDIR => self.open_handle(header.nodeid, libc::O_RDONLY | libc::O_DIRECTORY),
+            CREATE => self.create(header.nodeid, &mut reader),
+            READ => self.read(&mut reader),
+            READDIR => self.readdir(&mut reader),
+            WRITE => self.write(&mut reader),
+            STATFS => self.statfs(),
+            FLUSH => self.handle_fd(&mut reader).map(|_| Vec::new()),
+            FSYNC | FSYNCDIR => self.fsync(&mut reader),
+            RELEASE | RELEASEDIR => self.release(&mut reader),
+            opcode => {
+                debug!("virtio-fs: unsupported request {}", opcode);
+                Err(io::Error::from_raw_os_error(libc::ENOSYS))
+            }
+        };
+
+        let (error, payload) = match result {
+            Ok(payload) => (0, payload),
+            Err(e) => (-e.raw_os_error().unwrap_or(libc::EIO), Vec::new()),
+        };
+        let out_header = OutHeader {
+            len: (mem::size_of::<OutHeader>() + payload.len()) as u32,
+            error,
+            unique: header.unique,
+        };
+        let mut reply = out_header.as_slice().to_vec();
+        reply.extend_from_slice(&payload);
+        Some(reply)
+    }
+
+    fn inode(&self, nodeid: u64) -> io::Result<&Inode> {
+        self.inodes.get(&nodeid).ok_or_else(ebadf)
+    }
+
+    fn inode_fd(&self, nodeid: u64) -> io::Result<RawFd> {
+        self.inode(nodeid).map(|inode| inode.file.as_raw_fd())
+    }
+
+    fn handle_fd(&self, reader: &mut Reader) -> io::Result<RawFd> {
+        // All the requests on a handle start with its ID.
+        let fh: u64 = reader.read_obj()?;
+        self.handles
+            .get(&fh)
+            .map(|file| file.as_raw_fd())
+            .ok_or_else(ebadf)
+    }
+
+    fn check_writable(&self) -> io::Result<()> {
+        if self.read_only {
+            return Err(erofs());
+        }
+        Ok(())
+    }
+
+    // Opens a regular file descriptor of a node, which is confined to the shared directory
+    // the same way as a lookup. Only directories and regular files can be opened, since opening
+    // a FIFO or a device could block or have side effects on the host.
+    fn open_inode(&self, nodeid: u64, flags: i32) -> io::Result<File> {
+        let inode = self.inode(nodeid)?;
+        match inode.file_type {
+            libc::S_IFDIR => {
+                return openat(
+                    inode.file.as_raw_fd(),
+                    CStr::from_bytes_with_nul(b".\0").unwrap(),
+                    flags,
+                    0,
+                )
+            }
+            libc::S_IFREG => (),
+            libc::S_IFLNK => return Err(io::Error::from_raw_os_error(libc::ELOOP)),
+            _ => return Err(io::Error::from_raw_os_error(libc::ENXIO)),
+        }
+
+        for (parent, name) in inode.names.iter() {
+            let file = match openat(self.inode_fd(*parent)?, name, flags | libc::O_NOFOLLOW, 0) {
+                Ok(file) => file,
+                Err(e) if e.raw_os_error() == Some(libc::ENOENT) => continue,
+                Err(e) => return Err(e),
+            };
+            // The name might have been reused for another file since the lookup.
+            let st = stat(file.as_raw_fd())?;
+            if (st.st_dev, st.st_ino) == inode.key {
+                return Ok(file);
+            }
+        }
+        Err(io::Error::from_raw_os_error(libc::ESTALE))
+    }
+
+    fn add_handle(&mut self, file: File) -> io::Result<u64> {
+        if self.handles.len() >= MAX_HANDLES {
+            return Err(io::Error::from_raw_os_error(libc::EMFILE));
+        }
+        let fh = self.next_handle;
+        self.next_handle += 1;
+        self.handles.insert(fh, file);
+        Ok(fh)
+    }
+
+    // Records that `name` in the directory `parent` leads to the node, which keeps the directory
+    // known for as long as the name is recorded.
+    fn add_name(&mut self, nodeid: u64, parent: u64, name: &CStr) -> io::Result<()> {
+        let key = (parent, name.to_owned());
+        let known = self.inodes_by_name.get(&key).copied();
+        if known == Some(nodeid) {
+            return Ok(());
+        }
+        self.inodes.get_mut(&parent).ok_or_else(ebadf)?.refcount += 1;
+        if known.is_some() {
+            self.remove_name(parent, name);
+        }
+        self.inodes
+            .get_mut(&nodeid)
+            .ok_or_else(ebadf)?
+            .names
+            .push(key.clone());
+        self.inodes_by_name.insert(key, nodeid);
+        Ok(())
+    }
+
+    // Forgets that `name` in the directory `parent` leads to a node, once it was removed or
+    // replaced.
+    fn remove_name(&mut self, parent: u64, name: &CStr) {
+        let key = (parent, name.to_owned());
+        if let Some(nodeid) = self.inodes_by_name.remove(&key) {
+            if let Some(inode) = self.inodes.get_mut(&nodeid) {
+                inode.names.retain(|known| *known != key);
+            }
+            self.forget(parent, 1);
+        }
+    }
+
+    fn init(&mut self, reader: &mut Reader) -> io::Result<Vec<u8>> {
+        let arg: InitIn = reader.read_obj()?;
+        if arg.major != KERNEL_VERSION {
+            warn!(
+                "virtio-fs: unsupported FUSE protocol version {}.{}",
+                arg.major, arg.minor
+            );
+            return Err(io::Error::from_raw_os_error(libc::EPROTO));
+        }
+        let out = InitOut {
+            major: KERNEL_VERSION,
+            minor: cmp::min(arg.minor, KERNEL_MINOR_VERSION),
+            max_readahead: arg.max_readahead,
+            flags: arg.flags & (ASYNC_READ | ATOMIC_O_TRUNC | BIG_WRITES),
+            max_write: MAX_IO_SIZE,
+            time_gran: 1,
+            ..Default::default()
+        };
+        Ok(out.as_slice().to_vec())
+    }
+
+    // Drops everything the guest opened or looked up.
+    fn destroy(&mut self) {
+        self.handles.clear();
+        self.inodes.retain(|&nodeid, _| nodeid == ROOT_ID);
+        self.inodes_by_key
+            .retain(|_, &mut nodeid| nodeid == ROOT_ID);
+        self.inodes_by_name.clear();
+    }
+
+    fn lookup(&mut self, parent: u64, name: &CStr) -> io::Result<EntryOut> {
+        let file = openat(
+            self.inode_fd(parent)?,
+            name,
+            libc::O_PATH | libc::O_NOFOLLOW,
+            0,
+        )?;
+        let st = stat(file.as_raw_fd())?;
+        let key = (st.st_dev, st.st_ino);
+
+        // A node looked up again, possibly through another hard link, keeps its descriptor.
+        let nodeid = match self.inodes_by_key.get(&key) {
+            Some(&nodeid) => {
+                let inode = self.inodes.get_mut(&nodeid).ok_or_else(ebadf)?;
+                inode.refcount = inode.refcount.saturating_add(1);
+                nodeid
+            }
+            None => {
+                if self.inodes.len() >= MAX_INODES {
+                    return Err(io::Error::from_raw_os_error(libc::ENFILE));
+                }
+                let nodeid = self.next_inode;
+                self.next_inode += 1;
+                self.inodes_by_key.insert(key, nodeid);
+                self.inodes.insert(
+                    nodeid,
+                    Inode {
+                        file,
+                        key,
+                        file_type: st.st_mode & libc::S_IFMT,
+                        names: Vec::new(),
+                        refcount: 1,
+                    },
+                );
+                nodeid
+            }
+        };
+        // A node keeps its parent known, so that it can be opened through it.
+        self.add_name(nodeid, parent, name)?;
+
+        Ok(EntryOut {
+            nodeid,
+            generation: 0,
+            entry_valid: ATTR_TTL_SECS,
+            attr_valid: ATTR_TTL_SECS,
+            entry_valid_nsec: 0,
+            attr_valid_nsec: 0,
+            attr: Attr::from(st),
+        })
+    }
+
+    fn forget(&mut self, nodeid: u64, nlookup: u64) {
+        let mut pending = vec![(nodeid, nlookup)];
+        while let Some((nodeid, nlookup)) = pending.pop() {
+            let inode = match self.inodes.get_mut(&nodeid) {
+                Some(inode) => inode,
+                None => continue,
+            };
+            inode.refcount = inode.refcount.saturating_sub(nlookup);
+            if inode.refcount == 0 && nodeid != ROOT_ID {
+                if let Some(inode) = self.inodes.remove(&nodeid) {
+                    self.inodes_by_key.remove(&inode.key);
+                    for key in inode.names {
+                        self.inodes_by_name.remove(&key);
+                        pending.push((key.0, 1));
+                    }
+                }
+            }
+        }
+    }
+
+    fn batch_forget(&mut self, reader: &mut Reader) {
+        let count = match reader.read_obj::<BatchForgetIn>() {
+            Ok(arg) => arg.count,
+            Err(_) => return,
+        };
+        for _ in 0..count {
+            match reader.read_obj::<ForgetOne>() {
+                Ok(one) => self.forget(one.nodeid, one.nlookup),
+                Err(_) => return,
+            }
+        }
+    }
+
+    fn attr_out(st: libc::stat) -> Vec<u8> {
+        AttrOut {
+            attr_valid: ATTR_TTL_SECS,
+            attr_valid_nsec: 0,
+            dummy: 0,
+            attr: Attr::from(st),
+        }
+        .as_slice()
+        .to_vec()
+    }
+
+    fn getattr(&self, nodeid: u64) -> io::Result<Vec<u8>> {
+        stat(self.inode_fd(nodeid)?).map(Self::attr_out)
+    }
+
+    fn setattr(&mut self, nodeid: u64, reader: &mut Reader) -> io::Result<Vec<u8>> {
+        self.check_writable()?;
+        let arg: SetattrIn = reader.read_obj()?;
+
+        // The attributes are set through a regular file descriptor, since the `O_PATH` one
+        // can't be used for that and the ones taking a name would follow symbolic links.
+        let opened;
+        let fd = match self.handles.get(&arg.fh) {
+            Some(file) if arg.valid & FATTR_FH != 0 => file.as_raw_fd(),
+            _ => {
+                let flags = if arg.valid & FATTR_SIZE != 0 {
+                    libc::O_WRONLY
+                } else {
+                    libc::O_RDONLY
+                };
+                opened = self.open_inode(nodeid, flags | libc::O_NONBLOCK)?;
+                opened.as_raw_fd()
+            }
+        };
+
+        // Safe because these calls don't access memory of the process and we check their
+        // return values.
+        if arg.valid & FATTR_MODE != 0 {
+            check_retval(unsafe { libc::fchmod(fd, arg.mode) })?;
+        }
+        if arg.valid & (FATTR_UID | FATTR_GID) != 0 {
+            let uid = if arg.valid & FATTR_UID != 0 {
+                arg.uid
+            } else {
+                u32::MAX
+            };
+            let gid = if arg.valid & FATTR_GID != 0 {
+                arg.gid
+            } else {
+                u32::MAX
+            };
+            check_retval(unsafe { libc::fchown(fd, uid, gid) })?;
+        }
+        if arg.valid & FATTR_SIZE != 0 {
+            check_retval(unsafe { libc::ftruncate(fd, arg.size as libc::off_t) })?;
+        }
+        if arg.valid & (FATTR_ATIME | FATTR_MTIME) != 0 {
+            let time = |set, now, secs, nsecs| {
+                let mut ts: libc::timespec = unsafe { mem::zeroed() };
+                if arg.valid & now != 0 {
+                    ts.tv_nsec = libc::UTIME_NOW;
+                } else if arg.valid & set != 0 {
+                    ts.tv_sec = secs as libc::time_t;
+                    ts.tv_nsec = nsecs as libc::c_long;
+                } else {
+                    ts.tv_nsec = libc::UTIME_OMIT;
+                }
+                ts
+            };
+            let times = [
+                time(FATTR_ATIME, FATTR_ATIME_NOW, arg.atime, arg.atimensec),
+                time(FATTR_MTIME, FATTR_MTIME_NOW, arg.mtime, arg.mtimensec),
+            ];
+            // Safe because the kernel only reads the two elements of `times`.
+            check_retval(unsafe { libc::futimens(fd, times.as_ptr()) })?;
+        }
+
+        stat(fd).map(Self::attr_out)
+    }
+
+    fn readlink(&self, nodeid: u64) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; libc::PATH_MAX as usize];
+        // Safe because the kernel writes at most `buf.len()` bytes to `buf` and we check the
+        // return value.
+        let len = check_retval(unsafe {
+            libc::readlinkat(
+                self.inode_fd(nodeid)?,
+                b"\0".as_ptr() as *const libc::c_char,
+                buf.as_mut_ptr() as *mut libc::c_char,
+                buf.len(),
+            )
+        })?;
+        buf.truncate(len as usize);
+        Ok(buf)
+    }
+
+    fn mkdir(&mut self, parent: u64, reader: &mut Reader) -> io::Result<Vec<u8>> {
+        self.check_writable()?;
+        let arg: MkdirIn = reader.read_obj()?;
+        let name = reader.read_name()?;
+        // Safe because the kernel only reads the nul terminated `name` and we check the return
+        // value.
+        check_retval(unsafe {
+            libc::mkdirat(self.inode_fd(parent)?, name.as_ptr(), arg.mode & !arg.umask)
+        })?;
+        self.lookup(parent, &name)
+            .map(|entry| entry.as_slice().to_vec())
+    }
+
+    fn unlink(&mut self, parent: u64, reader: &mut Reader, flags: i32) -> io::Result<Vec<u8>> {
+        self.check_writable()?;
+        let name = reader.read_name()?;
+        // Safe because the kernel only reads the nul terminated `name` and we check the return
+        // value.
+        check_retval(unsafe { libc::unlinkat(self.inode_fd(parent)?, name.as_ptr(), flags) })?;
+        self.remove_name(parent, &name);
+        Ok(Vec::new())
+    }
+
+    fn rename(&mut self, olddir: u64, reader: &mut Reader) -> io::Result<Vec<u8>> {
+        self.check_writable()?;
+        let arg: RenameIn = reader.read_obj()?;
+        let oldname = reader.read_name()?;
+        let newname = reader.read_name()?;
+        let newdir_fd = self.inode_fd(arg.newdir)?;
+        // Safe because the kernel only reads the nul terminated names and we check the return
+        // value.
+        check_retval(unsafe {
+            libc::renameat(
+                self.inode_fd(olddir)?,
+                oldname.as_ptr(),
+                newdir_fd,
+                newname.as_ptr(),
+            )
+        })?;
+
+        // The renamed node is opened through its new name from now on, and the node the new
+        // name led to, if any, can't be opened through it anymore. Renaming a hard link onto
+        // another link of the same node changes nothing.
+        let renamed = self.inodes_by_name.get(&(olddir, oldname.clone())).copied();
+        let replaced = self
+            .inodes_by_name
+            .get(&(arg.newdir, newname.clone()))
+            .copied();
+        match renamed {
+            Some(nodeid) if renamed != replaced => {
+                self.add_name(nodeid, arg.newdir, &newname)?;
+                self.remove_name(olddir, &oldname);
+            }
+            Some(_) => (),
+            None => self.remove_name(arg.newdir, &newname),
+        }
+        Ok(Vec::new())
+    }
+
+    fn open_flags(&self, flags: u32) -> io::Result<i32> {
+        let flags = flags as i32 & OPEN_FLAGS_MASK;
+        if self.read_only
+            && (flags & libc::O_ACCMODE != libc::O_RDONLY || flags & libc::O_TRUNC != 0)
+        {
+            return Err(erofs());
+        }
+        Ok(flags)
+    }
+
+    fn open_handle(&mut self, nodeid: u64, flags: i32) -> io::Result<Vec<u8>> {
+        let file = self.open_inode(nodeid, flags)?;
+        let out = OpenOut {
+            fh: self.add_handle(file)?,
+            open_flags: 0,
+            padding: 0,
+        };
+        Ok(out.as_slice().to_vec())
+    }
+
+    fn open(&mut self, nodeid: u64, reader: &mut Reader) -> io::Result<Vec<u8>> {
+        let arg: OpenIn = reader.read_obj()?;
+        let flags = self.open_flags(arg.flags)?;
+        self.open_handle(nodeid, flags)
+    }
+
+    fn create(&mut self, parent: u64, reader: &mut Reader) -> io::Result<Vec<u8>> {
+        self.check_writable()?;
+        let arg: CreateIn = reader.read_obj()?;
+        let name = reader.read_name()?;
+        let flags = self.open_flags(arg.flags)?;
+        // Nothing gets created on the host if the file can't be handed to the guest.
+        if self.handles.len() >= MAX_HANDLES {
+            return Err(io::Error::from_raw_os_error(libc::EMFILE));
+        }
+        let file = openat(
+            self.inode_fd(parent)?,
+            &name,
+            flags | libc::O_CREAT | libc::O_NOFOLLOW,
+            arg.mode & !arg.umask,
+        )?;
+        let entry = self.lookup(parent, &name)?;
+        let out = OpenOut {
+            fh: self.add_handle(file)?,
+            open_flags: 0,
+            padding: 0,
+        };
+
+        let mut payload = entry.as_slice().to_vec();
+        payload.extend_from_slice(out.as_slice());
+        Ok(payload)
+    }
+
+    fn read(&self, reader: &mut Reader) -> io::Result<Vec<u8>> {
+        let arg: ReadIn = reader.read_obj()?;
+        let file = self.handles.get(&arg.fh).ok_or_else(ebadf)?;
+        let mut buf = vec![0u8; cmp::min(arg.size, MAX_IO_SIZE) as usize];
+        let len = file.read_at(&mut buf, arg.offset)?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    fn write(&self, reader: &mut Reader) -> io::Result<Vec<u8>> {
+        self.check_writable()?;
+        let arg: WriteIn = reader.read_obj()?;
+        let data = reader.read_bytes(arg.size as usize)?;
+        let file = self.handles.get(&arg.fh).ok_or_else(ebadf)?;
+        let len = file.write_at(data, arg.offset)?;
+        let out = WriteOut {
+            size: len as u32,
+            padding: 0,
+        };
+        Ok(out.as_slice().to_vec())
+    }
+
+    fn readdir(&self, reader: &mut Reader) -> io::Result<Vec<u8>> {
+        let arg: ReadIn = reader.read_obj()?;
+        let fd = self
+            .handles
+            .get(&arg.fh)
+            .map(|file| file.as_raw_fd())
+            .ok_or_else(ebadf)?;
+        let size = cmp::min(arg.size, MAX_IO_SIZE) as usize;
+
+        // The offsets of the entries are the ones of the host, so the listing resumes from
+        // the requested one every time.
+        // Safe because this call doesn't access memory of the process and we check the return
+        // value.
+        check_retval(unsafe { libc::lseek(fd, arg.offset as libc::off_t, libc::SEEK_SET) })?;
+        let mut buf = vec![0u8; size];
+        // Safe because the kernel writes at most `buf.len()` bytes to `buf` and we check the
+        // return value.
+        let len = check_retval(unsafe {
+            libc::syscall(libc::SYS_getdents64, fd, buf.as_mut_ptr(), buf.len())
+        })? as usize;
+
+        // The layout of `struct linux_dirent64`: the inode number, the offset of the next
+        // entry, the length of the entry, the file type and the nul terminated name.
+        let mut payload = Vec::with_capacity(size);
+        let mut pos = 0;
+        while pos + 19 < len {
+            let field = |offset: usize, len: usize| &buf[pos + offset..pos + offset + len];
+            let ino = u64::from_ne_bytes(field(0, 8).try_into().unwrap());
+            let off = u64::from_ne_bytes(field(8, 8).try_into().unwrap());
+            let reclen = u16::from_ne_bytes(field(16, 2).try_into().unwrap()) as usize;
+            if reclen <= 19 || pos + reclen > len {
+                break;
+            }
+            let type_ = u32::from(buf[pos + 18]);
+            let name = &buf[pos + 19..pos + reclen];
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+
+            let dirent = Dirent {
+                ino,
+                off,
+                namelen: name.len() as u32,
+                type_,
+            };
+            let entry_len = mem::size_of::<Dirent>() + name.len();
+            let padded_len = (entry_len + 7) & !7;
+            if payload.len() + padded_len > size {
+                break;
+            }
+            payload.extend_from_slice(dirent.as_slice());
+            payload.extend_from_slice(name);
+            payload.resize(payload.len() + padded_len - entry_len, 0);
+            pos += reclen;
+        }
+        Ok(payload)
+    }
+
+    fn statfs(&self) -> io::Result<Vec<u8>> {
+        // Safe because `libc::statvfs` is plain data, for which all zeroes is a valid value.
+        let mut st: libc::statvfs = unsafe { mem::zeroed() };
+        // Safe because the kernel only writes to `st`, which is big enough for a
+        // `libc::statvfs`, and we check the return value.
+        check_retval(unsafe { libc::fstatvfs(self.inode_fd(ROOT_ID)?, &mut st) })?;
+        let out = Kstatfs {
+            blocks: st.f_blocks as u64,
+            bfree: st.f_bfree as u64,
+            bavail: st.f_bavail as u64,
+            files: st.f_files as u64,
+            ffree: st.f_ffree as u64,
+            bsize: st.f_bsize as u32,
+            namelen: st.f_namemax as u32,
+            frsize: st.f_frsize as u32,
+            ..Default::default()
+        };
+        Ok(out.as_slice().to_vec())
+    }
+
+    fn fsync(&self, reader: &mut Reader) -> io::Result<Vec<u8>> {
+        let arg: FsyncIn = reader.read_obj()?;
+        let fd = self
+            .handles
+            .get(&arg.fh)
+            .map(|file| file.as_raw_fd())
+            .ok_or_else(ebadf)?;
+        // Safe because these calls don't access memory of the process and we check their
+        // return values.
+        if arg.fsync_flags & FSYNC_FDATASYNC != 0 {
+            check_retval(unsafe { libc::fdatasync(fd) })?;
+        } else {
+            check_retval(unsafe { libc::fsync(fd) })?;
+        }
+        Ok(Vec::new())
+    }
+
+    fn release(&mut self, reader: &mut Reader) -> io::Result<Vec<u8>> {
+        let arg: ReleaseIn = reader.read_obj()?;
+        self.handles.remove(&arg.fh).ok_or_else(ebadf)?;
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use utils::tempdir::TempDir;
+
+    // Builds a request with `args` as arguments.
+    pub(crate) fn request(opcode: u32, nodeid: u64, args: &[&[u8]]) -> Vec<u8> {
+        let len: usize = args.iter().map(|arg| arg.len()).sum();
+        let header = InHeader {
+            len: (mem::size_of::<InHeader>() + len) as u32,
+            opcode,
+            unique: 42,
+            nodeid,
+            ..Default::default()
+        };
+        let mut request = header.as_slice().to_vec();
+        for arg in args {
+            request.extend_from_slice(arg);
+        }
+        request
+    }
+
+    // Returns the error and the payload of a reply.
+    pub(crate) fn parse_reply(reply: &[u8]) -> (i32, &[u8]) {
+        let mut header = OutHeader::default();
+        header
+            .as_mut_slice()
+            .copy_from_slice(&reply[..mem::size_of::<OutHeader>()]);
+        assert_eq!(header.len as usize, reply.len());
+        assert_eq!(header.unique, 42);
+        (header.error, &reply[mem::size_of::<OutHeader>()..])
+    }
+
+    fn read_obj<T: ByteValued + Default>(payload: &[u8]) -> T {
+        Reader { buf: payload }.read_obj().unwrap()
+    }
+
+    fn lookup(fs: &mut PassthroughFs, parent: u64, name: &str) -> Result<EntryOut, i32> {
+        let name = format!("{}\0", name);
+        let reply = fs
+            .handle_message(&request(LOOKUP, parent, &[name.as_bytes()]))
+            .unwrap();
+        match parse_reply(&reply) {
+            (0, payload) => Ok(read_obj(payload)),
+            (error, _) => Err(error),
+        }
+    }
+
+    fn open(fs: &mut PassthroughFs, nodeid: u64, flags: i32) -> Result<u64, i32> {
+        let arg = OpenIn {
+            flags: flags as u32,
+            unused: 0,
+        };
+        let reply = fs
+            .handle_message(&request(OPEN, nodeid, &[arg.as_slice()]))
+            .unwrap();
+        match parse_reply(&reply) {
+            (0, payload) => Ok(read_obj::<OpenOut>(payload).fh),
+            (error, _) => Err(error),
+        }
+    }
+
+    fn shared_dir() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir(dir.as_path().join("dir")).unwrap();
+        std::fs::write(dir.as_path().join("dir/file"), b"hello").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_init() {
+        let dir = shared_dir();
+        let mut fs = PassthroughFs::new(dir.as_path(), false).unwrap();
+
+        let arg = InitIn {
+            major: KERNEL_VERSION,
+            minor: 34,
+            max_readahead: 4096,
+            flags: ASYNC_READ | (1 << 10),
+        };
+        let reply = fs
+            .handle_message(&request(INIT, 0, &[arg.as_slice()]))
+            .unwrap();
+        let (error, payload) = parse_reply(&reply);
+        assert_eq!(error, 0);
+        let out: InitOut = read_obj(payload);
+        assert_eq!(out.major, KERNEL_VERSION);
+        assert_eq!(out.minor, KERNEL_MINOR_VERSION);
+        // Only the supported flags are acknowledged.
+        assert_eq!(out.flags, ASYNC_READ);
+        assert_eq!(out.max_write, MAX_IO_SIZE);
+
+        let arg = InitIn {
+            major: KERNEL_VERSION + 1,
+            ..arg
+        };
+        let reply = fs
+            .handle_message(&request(INIT, 0, &[arg.as_slice()]))
+            .unwrap();
+        assert_eq!(parse_reply(&reply), (-libc::EPROTO, &[][..]));
+
+        // Unsupported and malformed requests.
+        let reply = fs.handle_message(&request(1000, 0, &[])).unwrap();
+        assert_eq!(parse_reply(&reply).0, -libc::ENOSYS);
+        assert!(fs.handle_message(&[0u8; 8]).is_none());
+    }
+
+    #[test]
+    fn test_lookup_and_read() {
+        let dir = shared_dir();
+        let mut fs = PassthroughFs::new(dir.as_path(), false).unwrap();
+
+        let dir_entry = lookup(&mut fs, ROOT_ID, "dir").unwrap();
+        assert_eq!(dir_entry.attr.mode & libc::S_IFMT, libc::S_IFDIR);
+        let file_entry = lookup(&mut fs, dir_entry.nodeid, "file").unwrap();
+        assert_eq!(file_entry.attr.size, 5);
+        // Looking a node up again gives the same node.
+        assert_eq!(
+            lookup(&mut fs, dir_entry.nodeid, "file").unwrap().nodeid,
+            file_entry.nodeid
+        );
+        assert_eq!(
+            lookup(&mut fs, ROOT_ID, "missing").unwrap_err(),
+            -libc::ENOENT
+        );
+
+        let fh = open(&mut fs, file_entry.nodeid, libc::O_RDONLY).unwrap();
+        let arg = ReadIn {
+            fh,
+            offset: 1,
+            size: 100,
+            ..Default::default()
+        };
+        let reply = fs
+            .handle_message(&request(READ, file_entry.nodeid, &[arg.as_slice()]))
+            .unwrap();
+        assert_eq!(parse_reply(&reply), (0, &b"ello"[..]));
+
+        let arg = ReleaseIn {
+            fh,
+            ..Default::default()
+        };
+        let reply = fs
+            .handle_message(&request(RELEASE, file_entry.nodeid, &[arg.as_slice()]))
+            .unwrap();
+        assert_eq!(parse_reply(&reply).0, 0);
+        assert!(fs.handles.is_empty());
+
+        // Once forgotten, the nodes and the directory they kept known are dropped.
+        let arg = ForgetIn { nlookup: 2 };
+        assert!(fs
+            .handle_message(&request(FORGET, file_entry.nodeid, &[arg.as_slice()]))
+            .is_none());
+        assert_eq!(fs.inodes.len(), 2);
+        let arg = ForgetIn { nlookup: 1 };
+        fs.handle_message(&request(FORGET, dir_entry.nodeid, &[arg.as_slice()]));
+        assert_eq!(fs.inodes.len(), 1);
+        assert_eq!(fs.inodes_by_key.len(), 1);
+    }
+
+    #[test]
+    fn test_readdir() {
+        let dir = shared_dir();
+        let mut fs = PassthroughFs::new(dir.as_path(), false).unwrap();
+        let dir_entry = lookup(&mut fs, ROOT_ID, "dir").unwrap();
+
+        let reply = fs
+            .handle_message(&request(OPENDIR, dir_entry.nodeid, &[&[0u8; 8]]))
+            .unwrap();
+        let fh = read_obj::<OpenOut>(parse_reply(&reply).1).fh;
+
+        let mut names = Vec::new();
+        let mut offset = 0;
+        loop {
+            // Room for a single entry at a time.
+            let arg = ReadIn {
+                fh,
+                offset,
+                size: 32,
+                ..Default::default()
+            };
+            let reply = fs
+                .handle_message(&request(READDIR, dir_entry.nodeid, &[arg.as_slice()]))
+                .unwrap();
+            let (error, payload) = parse_reply(&reply);
+            assert_eq!(error, 0);
+            if payload.is_empty() {
+                break;
+            }
+            let dirent: Dirent = read_obj(payload);
+            let name = &payload[24..24 + dirent.namelen as usize];
+            names.push(String::from_utf8(name.to_vec()).unwrap());
+            offset = dirent.off;
+        }
+        names.sort();
+        assert_eq!(names, vec![".", "..", "file"]);
+    }
+
+    #[test]
+    fn test_write_ops() {
+        let dir = shared_dir();
+        let mut fs = PassthroughFs::new(dir.as_path(), false).unwrap();
+
+        let arg = CreateIn {
+            flags: libc::O_RDWR as u32,
+            mode: 0o644,
+            umask: 0o022,
+            padding: 0,
+        };
+        let reply = fs
+            .handle_message(&request(CREATE, ROOT_ID, &[arg.as_slice(), b"new\0"]))
+            .unwrap();
+        let (error, payload) = parse_reply(&reply);
+        assert_eq!(error, 0);
+        let entry: EntryOut = read_obj(payload);
+        let fh = read_obj::<OpenOut>(&payload[mem::size_of::<EntryOut>()..]).fh;
+
+        let arg = WriteIn {
+            fh,
+            offset: 0,
+            size: 3,
+            ..Default::default()
+        };
+        let reply = fs
+            .handle_message(&request(WRITE, entry.nodeid, &[arg.as_slice(), b"abc"]))
+            .unwrap();
+        let (error, payload) = parse_reply(&reply);
+        assert_eq!(error, 0);
+        assert_eq!(read_obj::<WriteOut>(payload).size, 3);
+        assert_eq!(std::fs::read(dir.as_path().join("new")).unwrap(), b"abc");
+
+        let arg = SetattrIn {
+            valid: FATTR_SIZE,
+            size: 1,
+            ..Default::default()
+        };
+        let reply = fs
+            .handle_message(&request(SETATTR, entry.nodeid, &[arg.as_slice()]))
+            .unwrap();
+        let (error, payload) = parse_reply(&reply);
+        assert_eq!(error, 0);
+        assert_eq!(read_obj::<AttrOut>(payload).attr.size, 1);
+
+        // The renamed node is still opened through its new name.
+        let arg = RenameIn { newdir: ROOT_ID };
+        let reply = fs
+            .handle_message(&request(
+                RENAME,
+                ROOT_ID,
+                &[arg.as_slice(), b"new\0", b"renamed\0"],
+            ))
+            .unwrap();
+        assert_eq!(parse_reply(&reply).0, 0);
+        assert!(open(&mut fs, entry.nodeid, libc::O_RDONLY).is_ok());
+
+        let arg = MkdirIn {
+            mode: 0o755,
+            umask: 0,
+        };
+        let reply = fs
+            .handle_message(&request(MKDIR, ROOT_ID, &[arg.as_slice(), b"subdir\0"]))
+            .unwrap();
+        assert_eq!(parse_reply(&reply).0, 0);
+        assert!(dir.as_path().join("subdir").is_dir());
+
+        let reply = fs
+            .handle_message(&request(RMDIR, ROOT_ID, &[b"subdir\0"]))
+            .unwrap();
+        assert_eq!(parse_reply(&reply).0, 0);
+        let reply = fs
+            .handle_message(&request(UNLINK, ROOT_ID, &[b"renamed\0"]))
+            .unwrap();
+        assert_eq!(parse_reply(&reply).0, 0);
+        assert!(!dir.as_path().join("renamed").exists());
+    }
+
+    #[test]
+    fn test_read_only() {
+        let dir = shared_dir();
+        let mut fs = PassthroughFs::new(dir.as_path(), true).unwrap();
+        assert!(fs.is_read_only());
+        let dir_entry = lookup(&mut fs, ROOT_ID, "dir").unwrap();
+        let file_entry = lookup(&mut fs, dir_entry.nodeid, "file").unwrap();
+
+        assert!(open(&mut fs, file_entry.nodeid, libc::O_RDONLY).is_ok());
+        assert_eq!(
+            open(&mut fs, file_entry.nodeid, libc::O_RDWR),
+            Err(-libc::EROFS)
+        );
+        assert_eq!(
+            open(&mut fs, file_entry.nodeid, libc::O_RDONLY | libc::O_TRUNC),
+            Err(-libc::EROFS)
+        );
+
+        let reply = fs
+            .handle_message(&request(UNLINK, dir_entry.nodeid, &[b"file\0"]))
+            .unwrap();
+        assert_eq!(parse_reply(&reply).0, -libc::EROFS);
+        let arg = SetattrIn {
+            valid: FATTR_SIZE,
+            ..Default::default()
+        };
+        let reply = fs
+            .handle_message(&request(SETATTR, file_entry.nodeid, &[arg.as_slice()]))
+            .unwrap();
+        assert_eq!(parse_reply(&reply).0, -libc::EROFS);
+        assert_eq!(
+            std::fs::read(dir.as_path().join("dir/file")).unwrap(),
+            b"hello"
+        );
+    }
+
+    #[test]
+    fn test_confinement() {
+        let dir = shared_dir();
+        let outside = TempDir::new().unwrap();
+        std::fs::write(outside.as_path().join("secret"), b"secret").unwrap();
+        std::os::unix::fs::symlink(outside.as_path(), dir.as_path().join("link")).unwrap();
+        let mut fs = PassthroughFs::new(dir.as_path(), false).unwrap();
+
+        // Names which are not a single component are refused.
+        for name in &["..", ".", "dir/file", ""] {
+            assert_eq!(lookup(&mut fs, ROOT_ID, name).unwrap_err(), -libc::EINVAL);
+        }
+
+        // A symbolic link is a node of its own, which is never followed.
+        let link = lookup(&mut fs, ROOT_ID, "link").unwrap();
+        assert_eq!(link.attr.mode & libc::S_IFMT, libc::S_IFLNK);
+        assert_eq!(
+            lookup(&mut fs, link.nodeid, "secret").unwrap_err(),
+            -libc::ENOTDIR
+        );
+        assert_eq!(
+            open(&mut fs, link.nodeid, libc::O_RDONLY),
+            Err(-libc::ELOOP)
+        );
+        let reply = fs
+            .handle_message(&request(READLINK, link.nodeid, &[]))
+            .unwrap();
+        assert_eq!(
+            parse_reply(&reply).1,
+            outside.as_path().as_os_str().as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_hard_links() {
+        let dir = shared_dir();
+        std::fs::hard_link(dir.as_path().join("dir/file"), dir.as_path().join("link")).unwrap();
+        let mut fs = PassthroughFs::new(dir.as_path(), false).unwrap();
+
+        let dir_entry = lookup(&mut fs, ROOT_ID, "dir").unwrap();
+        let file_entry = lookup(&mut fs, dir_entry.nodeid, "file").unwrap();
+        // Both links lead to the same node.
+        assert_eq!(
+            lookup(&mut fs, ROOT_ID, "link").unwrap().nodeid,
+            file_entry.nodeid
+        );
+
+        // The node can still be opened once the name it was first looked up through is gone.
+        let reply = fs
+            .handle_message(&request(UNLINK, dir_entry.nodeid, &[b"file\0"]))
+            .unwrap();
+        assert_eq!(parse_reply(&reply).0, 0);
+        assert!(open(&mut fs, file_entry.nodeid, libc::O_RDONLY).is_ok());
+
+        let arg = RenameIn {
+            newdir: dir_entry.nodeid,
+        };
+        let reply = fs
+            .handle_message(&request(
+                RENAME,
+                ROOT_ID,
+                &[arg.as_slice(), b"link\0", b"moved\0"],
+            ))
+            .unwrap();
+        assert_eq!(parse_reply(&reply).0, 0);
+        assert!(open(&mut fs, file_entry.nodeid, libc::O_RDONLY).is_ok());
+        assert_eq!(
+            fs.inodes_by_name
+                .get(&(dir_entry.nodeid, CString::new("moved").unwrap())),
+            Some(&file_entry.nodeid)
+        );
+        assert_eq!(fs.inodes_by_name.len(), 2);
+
+        // Once removed, the node has no name left to be opened through.
+        let reply = fs
+            .handle_message(&request(UNLINK, dir_entry.nodeid, &[b"moved\0"]))
+            .unwrap();
+        assert_eq!(parse_reply(&reply).0, 0);
+        assert_eq!(
+            open(&mut fs, file_entry.nodeid, libc::O_RDONLY),
+            Err(-libc::ESTALE)
+        );
+    }
+
+    #[test]
+    fn test_special_files() {
+        let dir = shared_dir();
+        let path = CString::new(dir.as_path().join("fifo").as_os_str().as_bytes()).unwrap();
+        // Safe because the kernel only reads the nul terminated `path`.
+        assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o644) }, 0);
+        let mut fs = PassthroughFs::new(dir.as_path(), false).unwrap();
+
+        // Opening a FIFO without a writer would block the thread serving the requests.
+        let fifo = lookup(&mut fs, ROOT_ID, "fifo").unwrap();
+        assert_eq!(fifo.attr.mode & libc::S_IFMT, libc::S_IFIFO);
+        assert_eq!(
+            open(&mut fs, fifo.nodeid, libc::O_RDONLY),
+            Err(-libc::ENXIO)
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let dir = shared_dir();
+        // The root is a node of its own.
+        for i in 0..MAX_INODES - 1 {
+            std::fs::write(dir.as_path().join(format!("file{}", i)), b"").unwrap();
+        }
+        let mut fs = PassthroughFs::new(dir.as_path(), false).unwrap();
+
+        let mut nodeids = Vec::new();
+        for i in 0..MAX_INODES - 1 {
+            nodeids.push(
+                lookup(&mut fs, ROOT_ID, &format!("file{}", i))
+                    .unwrap()
+                    .nodeid,
+            );
+        }
+        assert_eq!(lookup(&mut fs, ROOT_ID, "dir").unwrap_err(), -libc::ENFILE);
+        // The nodes already known can still be looked up.
+        assert_eq!(
+            lookup(&mut fs, ROOT_ID, "file0").unwrap().nodeid,
+            nodeids[0]
+        );
+
+        for _ in 0..MAX_HANDLES {
+            open(&mut fs, nodeids[0], libc::O_RDONLY).unwrap();
+        }
+        assert_eq!(
+            open(&mut fs, nodeids[0], libc::O_RDONLY),
+            Err(-libc::EMFILE)
+        );
+        let arg = CreateIn {
+            flags: libc::O_RDWR as u32,
+            mode: 0o644,
+            umask: 0o022,
+            padding: 0,
+        };
+        let reply = fs
+            .handle_message(&request(CREATE, ROOT_ID, &[arg.as_slice(), b"new\0"]))
+            .unwrap();
+        assert_eq!(parse_reply(&reply).0, -libc::EMFILE);
+        assert!(!dir.as_path().join("new").exists());
+
+        let arg = ReleaseIn {
+            fh: 0,
+            ..Default::default()
+        };
+        let reply = fs
+            .handle_message(&request(RELEASE, nodeids[0], &[arg.as_slice()]))
+            .unwrap();
+        assert_eq!(parse_reply(&reply).0, 0);
+        assert!(open(&mut fs, nodeids[0], libc::O_RDONLY).is_ok());
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::{Duration, Instant};
+
+use timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
+
+/// Coalesces the used queue interrupts of a virtio device, which raises at most one of them per
+/// interval. An interrupt requested sooner is deferred until the end of the interval, along with
+/// the ones requested meanwhile, so that the guest handles all their used descriptors at once.
+///
+/// The timer of the coalescer expires when a deferred interrupt is due, the device has to call
+/// `event_handler()` on the events of the file descriptor provided by `as_raw_fd()`.
+pub struct InterruptCoalescer {
+    interval: Duration,
+    last_interrupt: Option<Instant>,
+    pending: bool,
+    timer_fd: TimerFd,
+}
+
+impl InterruptCoalescer {
+    /// Creates a coalescer raising at most one interrupt every `interval_us` microseconds. Every
+    /// interrupt is raised right away when `interval_us` is 0.
+    pub fn new(interval_us: u64) -> io::Result<Self> {
+        // The timer is created even if the coalescing is disabled, since it can be enabled later
+        // on, when creating a timer may be prevented by the seccomp filters.
+        let timer_fd = TimerFd::new_custom(ClockId::Monotonic, true, true)?;
+        Ok(InterruptCoalescer {
+            interval: Duration::from_micros(interval_us),
+            last_interrupt: None,
+            pending: false,
+            timer_fd,
+        })
+    }
+
+    /// Returns the minimum time between two interrupts, in microseconds.
+    pub fn interval_us(&self) -> u64 {
+        self.interval.as_micros() as u64
+    }
+
+    /// Changes the minimum time between two interrupts. An interrupt deferred already is still
+    /// raised when the timer expires.
+    pub fn set_interval_us(&mut self, interval_us: u64) {
+        self.interval = Duration::from_micros(interval_us);
+    }
+
+    /// Says if an interrupt requested now can be raised. Otherwise, it is deferred until the
+    /// timer expires.
+    pub fn request(&mut self) -> bool {
+        if self.pending {
+            return false;
+        }
+
+        let now = Instant::now();
+        match self.last_interrupt {
+            Some(last) if now.duration_since(last) < self.interval => {
+                self.pending = true;
+                self.timer_fd.set_state(
+                    TimerState::Oneshot(self.interval - now.duration_since(last)),
+                    SetTimeFlags::Default,
+                );
+                false
+            }
+            _ => {
+                self.last_interrupt = Some(now);
+                true
+            }
+        }
+    }
+
+    /// Handles the expiration of the timer. Returns whether the deferred interrupt has to be
+    /// raised now.
+    pub fn event_handler(&mut self) -> bool {
+        // The timer may have been disarmed by `flush()` after it expired, nothing is due then.
+        self.timer_fd.read();
+        self.take_pending()
+    }
+
+    /// Returns whether an interrupt was deferred, which then has to be raised right away, e.g.
+    /// before saving the device state.
+    pub fn flush(&mut self) -> bool {
+        self.timer_fd
+            .set_state(TimerState::Disarmed, SetTimeFlags::Default);
+        self.take_pending()
+    }
+
+    fn take_pending(&mut self) -> bool {
+        if !self.pending {
+            return false;
+        }
+        self.pending = false;
+        self.last_interrupt = Some(Instant::now());
+        true
+    }
+}
+
+impl AsRawFd for InterruptCoalescer {
+    fn as_raw_fd(&self) -> RawFd {
+        self.timer_fd.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_disabled_coalescing() {
+        let mut coalescer = InterruptCoalescer::new(0).unwrap();
+        assert_eq!(coalescer.interval_us(), 0);
+        for _ in 0..10 {
+            assert!(coalescer.request());
+        }
+        assert!(!coalescer.event_handler());
+        assert!(!coalescer.flush());
+    }
+
+    #[test]
+    fn test_coalescing() {
+        let mut coalescer = InterruptCoalescer::new(10_000).unwrap();
+        assert_eq!(coalescer.interval_us(), 10_000);
+
+        // The first interrupt is raised, the following ones are merged into a deferred one.
+        assert!(coalescer.request());
+        assert!(!coalescer.request());
+        assert!(!coalescer.request());
+
+        // The deferred interrupt is raised once the timer expires.
+        thread::sleep(Duration::from_millis(20));
+        assert!(matches!(
+            coalescer.timer_fd.get_state(),
+            TimerState::Disarmed
+        ));
+        assert!(coalescer.event_handler());
+        assert!(!coalescer.event_handler());
+
+        // The interval starts again from the deferred interrupt.
+        assert!(!coalescer.request());
+        assert!(matches!(
+            coalescer.timer_fd.get_state(),
+            TimerState::Oneshot(_)
+        ));
+        assert!(coalescer.flush());
+        assert!(matches!(
+            coalescer.timer_fd.get_state(),
+            TimerState::Disarmed
+        ));
+        assert!(!coalescer.flush());
+
+        // The coalescing can be disabled.
+        coalescer.set_interval_us(0);
+        assert!(coalescer.request());
+        assert!(coalescer.request());
+    }
+}
@@ -8,7 +8,7 @@
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, MutexGuard};
 
-use logger::warn;
+use logger::{error, warn, IncMetric, SharedIncMetric};
 use utils::byte_order;
 use vm_memory::{GuestAddress, GuestMemoryMmap};
 
@@ -79,6 +79,14 @@ impl MmioTransport {
         self.device.clone()
     }
 
+    /// Asks the guest driver to reset the device, by setting `DEVICE_NEEDS_RESET` and raising a
+    /// configuration change interrupt. The device is re-initialized once the driver resets and
+    /// sets it up again.
+    pub fn request_reset(&mut self) -> std::io::Result<()> {
+        self.device_status |= device_status::DEVICE_NEEDS_RESET;
+        self.interrupt(VIRTIO_MMIO_INT_CONFIG)
+    }
+
     fn check_device_status(&self, set: u32, clr: u32) -> bool {
         self.device_status & (set | clr) == set
     }
@@ -174,9 +182,16 @@ impl MmioTransport {
                 self.device_status = status;
                 let device_activated = self.locked_device().is_activated();
                 if !device_activated && self.are_queues_valid() {
-                    self.locked_device()
-                        .activate(self.mem.clone())
-                        .expect("Failed to activate device");
+                    let activate_result = self.locked_device().activate(self.mem.clone());
+                    if let Err(err) = activate_result {
+                        // Let the guest driver know that the device is unusable, instead of
+                        // leaving it waiting for a device that never comes up.
+                        error!("Failed to activate virtio device: {:?}", err);
+                        self.device_status |= DEVICE_NEEDS_RESET;
+                        if let Err(err) = self.interrupt(VIRTIO_MMIO_INT_CONFIG) {
+                            error!("Failed to signal the virtio device config change: {}", err);
+                        }
+                    }
                 }
             }
             _ if (status & FAILED) != 0 => {
@@ -326,7 +341,9 @@ impl BusDevice for MmioTransport {
         // interrupt_evt() is safe to unwrap because the inner interrupt_evt is initialized in the
         // constructor.
         // write() is safe to unwrap because the inner syscall is tailored to be safe as well.
-        self.locked_device().interrupt_evt().write(1).unwrap();
+        let locked_device = self.locked_device();
+        locked_device.interrupt_evt().write(1).unwrap();
+        locked_device.irq_counter().inc();
         Ok(())
     }
 }
@@ -344,9 +361,11 @@ pub(crate) mod tests {
         avail_features: u64,
         interrupt_evt: EventFd,
         interrupt_status: Arc<AtomicUsize>,
+        irq_counter: Arc<SharedIncMetric>,
         queue_evts: Vec<EventFd>,
         queues: Vec<Queue>,
         device_activated: bool,
+        activate_should_error: bool,
         config_bytes: [u8; 0xeff],
     }
 
@@ -357,12 +376,14 @@ pub(crate) mod tests {
                 avail_features: 0,
                 interrupt_evt: EventFd::new(libc::EFD_NONBLOCK).unwrap(),
                 interrupt_status: Arc::new(AtomicUsize::new(0)),
+                irq_counter: Arc::default(),
                 queue_evts: vec![
                     EventFd::new(libc::EFD_NONBLOCK).unwrap(),
                     EventFd::new(libc::EFD_NONBLOCK).unwrap(),
                 ],
                 queues: vec![Queue::new(16), Queue::new(32)],
                 device_activated: false,
+                activate_should_error: false,
                 config_bytes: [0; 0xeff],
             }
         }
@@ -400,6 +421,9 @@ pub(crate) mod tests {
         }
 
         fn activate(&mut self, _: GuestMemoryMmap) -> ActivateResult {
+            if self.activate_should_error {
+                return Err(ActivateError::BadActivate);
+            }
             self.device_activated = true;
             Ok(())
         }
@@ -424,6 +448,14 @@ pub(crate) mod tests {
             self.interrupt_status.clone()
         }
 
+        fn irq_counter(&self) -> &SharedIncMetric {
+            &self.irq_counter
+        }
+
+        fn set_irq_counter(&mut self, irq_counter: Arc<SharedIncMetric>) {
+            self.irq_counter = irq_counter;
+        }
+
         fn is_activated(&self) -> bool {
             self.device_activated
         }
@@ -782,6 +814,57 @@ pub(crate) mod tests {
         assert_eq!(read_le_u32(&buf[..]), 1);
     }
 
+    #[test]
+    fn test_bus_device_activate_failure() {
+        let m = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), 0x1000)]).unwrap();
+        let mut device = DummyDevice::new();
+        device.activate_should_error = true;
+        let mut d = MmioTransport::new(m, Arc::new(Mutex::new(device)));
+
+        set_device_status(&mut d, device_status::ACKNOWLEDGE);
+        set_device_status(&mut d, device_status::ACKNOWLEDGE | device_status::DRIVER);
+        set_device_status(
+            &mut d,
+            device_status::ACKNOWLEDGE | device_status::DRIVER | device_status::FEATURES_OK,
+        );
+        let mut buf = vec![0; 4];
+        let queue_len = d.locked_device().queues().len();
+        for q in 0..queue_len {
+            d.queue_select = q as u32;
+            write_le_u32(&mut buf[..], 16);
+            d.write(0x38, &buf[..]);
+            write_le_u32(&mut buf[..], 1);
+            d.write(0x44, &buf[..]);
+        }
+        assert!(d.are_queues_valid());
+
+        // The failed activation doesn't bring down the VMM. Instead, the device is marked as
+        // needing a reset and the driver is notified through a config change interrupt.
+        set_device_status(
+            &mut d,
+            device_status::ACKNOWLEDGE
+                | device_status::DRIVER
+                | device_status::FEATURES_OK
+                | device_status::DRIVER_OK,
+        );
+        assert!(!d.locked_device().is_activated());
+        assert_eq!(
+            d.device_status,
+            device_status::ACKNOWLEDGE
+                | device_status::DRIVER
+                | device_status::FEATURES_OK
+                | device_status::DRIVER_OK
+                | device_status::DEVICE_NEEDS_RESET
+        );
+        d.read(0x60, &mut buf[..]);
+        assert_eq!(read_le_u32(&buf[..]), VIRTIO_MMIO_INT_CONFIG);
+        assert_eq!(d.locked_device().interrupt_evt().read().unwrap(), 1);
+
+        // The driver can reset the device.
+        set_device_status(&mut d, device_status::INIT);
+        assert_eq!(d.device_status, device_status::INIT);
+    }
+
     fn activate_device(d: &mut MmioTransport) {
         set_device_status(d, device_status::ACKNOWLEDGE);
         set_device_status(d, device_status::ACKNOWLEDGE | device_status::DRIVER);
@@ -845,6 +928,28 @@ pub(crate) mod tests {
         assert!(d.locked_device().is_activated());
     }
 
+    #[test]
+    fn test_request_reset() {
+        let m = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), 0x1000)]).unwrap();
+        let mut d = MmioTransport::new(m, Arc::new(Mutex::new(DummyDevice::new())));
+        let mut buf = vec![0; 4];
+        activate_device(&mut d);
+
+        // The driver is told through a config change interrupt that the device needs a reset.
+        d.request_reset().unwrap();
+        assert_eq!(
+            d.device_status,
+            device_status::ACKNOWLEDGE
+                | device_status::DRIVER
+                | device_status::FEATURES_OK
+                | device_status::DRIVER_OK
+                | device_status::DEVICE_NEEDS_RESET
+        );
+        d.read(0x60, &mut buf[..]);
+        assert_eq!(read_le_u32(&buf[..]), VIRTIO_MMIO_INT_CONFIG);
+        assert_eq!(d.locked_device().interrupt_evt().read().unwrap(), 1);
+    }
+
     #[test]
     fn test_get_avail_features() {
         let dummy_dev = DummyDevice::new();
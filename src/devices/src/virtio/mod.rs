@@ -11,7 +11,10 @@ use std::io::Error as IOError;
 
 pub mod balloon;
 pub mod block;
+pub mod console;
 pub mod device;
+pub mod fs;
+mod irq_coalescer;
 mod mmio;
 pub mod net;
 pub mod persist;
@@ -21,7 +24,10 @@ pub mod vsock;
 
 pub use self::balloon::*;
 pub use self::block::*;
+pub use self::console::*;
 pub use self::device::*;
+pub use self::fs::*;
+pub use self::irq_coalescer::*;
 pub use self::mmio::*;
 pub use self::net::*;
 pub use self::persist::*;
@@ -41,6 +47,7 @@ mod device_status {
     pub const ACKNOWLEDGE: u32 = 1;
     pub const DRIVER: u32 = 2;
     pub const FAILED: u32 = 128;
+    pub const DEVICE_NEEDS_RESET: u32 = 64;
     pub const FEATURES_OK: u32 = 8;
     pub const DRIVER_OK: u32 = 4;
 }
@@ -49,7 +56,9 @@ mod device_status {
 /// Type 0 is not used by virtio. Use it as wildcard for non-virtio devices
 pub const TYPE_NET: u32 = 1;
 pub const TYPE_BLOCK: u32 = 2;
+pub const TYPE_CONSOLE: u32 = 3;
 pub const TYPE_BALLOON: u32 = 5;
+pub const TYPE_FS: u32 = 26;
 
 /// Interrupt flags (re: interrupt status & acknowledge registers).
 /// See linux/virtio_mmio.h.
@@ -60,6 +69,12 @@ pub const VIRTIO_MMIO_INT_CONFIG: u32 = 0x02;
 /// queue events.
 pub const NOTIFY_REG_OFFSET: u32 = 0x50;
 
+/// The default number of descriptor chains a device processes from a queue for one event. A
+/// device which has more to process signals its queue event again, so that the other pending
+/// events are handled in between. It is the size of the block and net queues, which is as many
+/// chains as the driver can make available at once.
+pub const DEFAULT_MAX_DESCRIPTORS_PER_EVENT: u16 = 256;
+
 #[derive(Debug)]
 pub enum ActivateError {
     EpollCtl(IOError),
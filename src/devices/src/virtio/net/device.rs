@@ -5,6 +5,7 @@
 // Use of this source code is governed by a BSD-style license that can be
 // found in the THIRD-PARTY file.
 
+use crate::virtio::net::pcap::PcapWriter;
 use crate::virtio::net::tap::Tap;
 #[cfg(test)]
 use crate::virtio::net::test_utils::Mocks;
@@ -12,29 +13,32 @@ use crate::virtio::net::Error;
 use crate::virtio::net::Result;
 use crate::virtio::net::{MAX_BUFFER_SIZE, QUEUE_SIZE, QUEUE_SIZES, RX_INDEX, TX_INDEX};
 use crate::virtio::{
-    ActivateResult, DeviceState, Queue, VirtioDevice, TYPE_NET, VIRTIO_MMIO_INT_VRING,
+    ActivateResult, DescriptorChain, DeviceState, InterruptCoalescer, Queue, VirtioDevice,
+    DEFAULT_MAX_DESCRIPTORS_PER_EVENT, TYPE_NET, VIRTIO_MMIO_INT_VRING,
 };
 use crate::{report_net_event_fail, Error as DeviceError};
 
 use dumbo::pdu::ethernet::EthernetFrame;
 use libc::EAGAIN;
-use logger::{error, warn, IncMetric, METRICS};
+use logger::{error, warn, IncMetric, SharedIncMetric, METRICS};
 use mmds::ns::MmdsNetworkStack;
 use rate_limiter::{BucketUpdate, RateLimiter, TokenType};
 #[cfg(not(test))]
 use std::io;
 use std::io::{Read, Write};
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::{cmp, mem, result};
 use utils::eventfd::EventFd;
+use utils::net::ip_prefix::IpPrefix;
 use utils::net::mac::{MacAddr, MAC_ADDR_LEN};
 use virtio_gen::virtio_net::{
     virtio_net_hdr_v1, VIRTIO_F_VERSION_1, VIRTIO_NET_F_CSUM, VIRTIO_NET_F_GUEST_CSUM,
-    VIRTIO_NET_F_GUEST_TSO4, VIRTIO_NET_F_GUEST_UFO, VIRTIO_NET_F_HOST_TSO4, VIRTIO_NET_F_HOST_UFO,
-    VIRTIO_NET_F_MAC,
+    VIRTIO_NET_F_GUEST_TSO4, VIRTIO_NET_F_GUEST_TSO6, VIRTIO_NET_F_GUEST_UFO,
+    VIRTIO_NET_F_HOST_TSO4, VIRTIO_NET_F_HOST_TSO6, VIRTIO_NET_F_HOST_UFO, VIRTIO_NET_F_MAC,
+    VIRTIO_NET_F_MRG_RXBUF, VIRTIO_NET_F_MTU,
 };
 use vm_memory::{ByteValued, Bytes, GuestAddress, GuestMemoryError, GuestMemoryMmap};
 
@@ -46,6 +50,19 @@ enum FrontendError {
     ReadOnlyDescriptor,
 }
 
+// Checksum and segmentation offload features, which are only offered to the guest when
+// offloading is enabled for the device.
+pub(crate) const OFFLOAD_FEATURES: u64 = 1 << VIRTIO_NET_F_GUEST_CSUM
+    | 1 << VIRTIO_NET_F_CSUM
+    | 1 << VIRTIO_NET_F_GUEST_TSO4
+    | 1 << VIRTIO_NET_F_GUEST_TSO6
+    | 1 << VIRTIO_NET_F_GUEST_UFO
+    | 1 << VIRTIO_NET_F_HOST_TSO4
+    | 1 << VIRTIO_NET_F_HOST_TSO6
+    | 1 << VIRTIO_NET_F_HOST_UFO;
+
+// `VIRTIO_F_VERSION_1` is always offered, so the VNET header has the same layout regardless
+// of which offload features are negotiated.
 pub(crate) fn vnet_hdr_len() -> usize {
     mem::size_of::<virtio_net_hdr_v1>()
 }
@@ -68,6 +85,13 @@ fn frame_bytes_from_buf_mut(buf: &mut [u8]) -> Result<&mut [u8]> {
     }
 }
 
+// Sets the `num_buffers` field, the last one of the VNET header, which tells the guest over how
+// many descriptor chains the frame is spread when `VIRTIO_NET_F_MRG_RXBUF` is negotiated.
+fn set_vnet_hdr_num_buffers(buf: &mut [u8], num_buffers: u16) {
+    let offset = vnet_hdr_len() - mem::size_of::<u16>();
+    buf[offset..vnet_hdr_len()].copy_from_slice(&num_buffers.to_le_bytes());
+}
+
 // This initializes to all 0 the VNET hdr part of a buf.
 fn init_vnet_hdr(buf: &mut [u8]) {
     // The buffer should be larger than vnet_hdr_len.
@@ -77,15 +101,25 @@ fn init_vnet_hdr(buf: &mut [u8]) {
     }
 }
 
+// The layout of the virtio-net device configuration, up to the MTU field.
 #[derive(Clone, Copy)]
+#[repr(C)]
 pub struct ConfigSpace {
     pub guest_mac: [u8; MAC_ADDR_LEN],
+    // Only valid if `VIRTIO_NET_F_STATUS` is negotiated, which is never offered.
+    pub status: u16,
+    // Only valid if `VIRTIO_NET_F_MQ` is negotiated, which is never offered.
+    pub max_virtqueue_pairs: u16,
+    pub mtu: u16,
 }
 
 impl Default for ConfigSpace {
     fn default() -> ConfigSpace {
         ConfigSpace {
             guest_mac: [0; MAC_ADDR_LEN],
+            status: 0,
+            max_virtqueue_pairs: 0,
+            mtu: 0,
         }
     }
 }
@@ -108,15 +142,20 @@ pub struct Net {
 
     pub(crate) rx_deferred_frame: bool,
     rx_deferred_irqs: bool,
+    // Whether the TX queue processing stopped because the tap couldn't take more frames. It's
+    // resumed once the tap becomes writable.
+    pub(crate) tx_blocked_by_tap: bool,
 
-    rx_bytes_read: usize,
-    rx_frame_buf: [u8; MAX_BUFFER_SIZE],
+    pub(crate) rx_bytes_read: usize,
+    pub(crate) rx_frame_buf: [u8; MAX_BUFFER_SIZE],
 
     tx_iovec: Vec<(GuestAddress, usize)>,
     tx_frame_buf: [u8; MAX_BUFFER_SIZE],
 
     pub(crate) interrupt_status: Arc<AtomicUsize>,
+    pub(crate) irq_counter: Arc<SharedIncMetric>,
     pub(crate) interrupt_evt: EventFd,
+    pub(crate) irq_coalescer: InterruptCoalescer,
 
     pub(crate) config_space: ConfigSpace,
     pub(crate) guest_mac: Option<MacAddr>,
@@ -126,6 +165,10 @@ pub struct Net {
 
     pub(crate) mmds_ns: Option<MmdsNetworkStack>,
 
+    pcap: Option<PcapWriter>,
+
+    pub(crate) max_descriptors_per_event: u16,
+
     #[cfg(test)]
     pub(crate) mocks: Mocks,
 }
@@ -136,29 +179,32 @@ impl Net {
         id: String,
         tap_if_name: String,
         guest_mac: Option<&MacAddr>,
-        rx_rate_limiter: RateLimiter,
-        tx_rate_limiter: RateLimiter,
+        mut rx_rate_limiter: RateLimiter,
+        mut tx_rate_limiter: RateLimiter,
         allow_mmds_requests: bool,
+        enable_offload: bool,
+        mtu: Option<u16>,
     ) -> Result<Self> {
         let tap = Tap::open_named(&tap_if_name).map_err(Error::TapOpen)?;
 
-        // Set offload flags to match the virtio features below.
-        tap.set_offload(
-            net_gen::TUN_F_CSUM | net_gen::TUN_F_UFO | net_gen::TUN_F_TSO4 | net_gen::TUN_F_TSO6,
-        )
-        .map_err(Error::TapSetOffload)?;
+        rx_rate_limiter.set_metrics(METRICS.rate_limiters.get(&format!("net_{}_rx", id)));
+        tx_rate_limiter.set_metrics(METRICS.rate_limiters.get(&format!("net_{}_tx", id)));
+
+        // The tap must not hand us partially checksummed or segmented frames before the
+        // guest acknowledges that it can handle them. The offload flags are set on activation,
+        // based on the negotiated features.
+        tap.set_offload(0).map_err(Error::TapSetOffload)?;
 
         let vnet_hdr_size = vnet_hdr_len() as i32;
         tap.set_vnet_hdr_size(vnet_hdr_size)
             .map_err(Error::TapSetVnetHdrSize)?;
 
-        let mut avail_features = 1 << VIRTIO_NET_F_GUEST_CSUM
-            | 1 << VIRTIO_NET_F_CSUM
-            | 1 << VIRTIO_NET_F_GUEST_TSO4
-            | 1 << VIRTIO_NET_F_GUEST_UFO
-            | 1 << VIRTIO_NET_F_HOST_TSO4
-            | 1 << VIRTIO_NET_F_HOST_UFO
-            | 1 << VIRTIO_F_VERSION_1;
+        // The guest can post small RX buffers when it acknowledges `VIRTIO_NET_F_MRG_RXBUF`,
+        // since a frame is then spread over as many of them as needed.
+        let mut avail_features = 1 << VIRTIO_F_VERSION_1 | 1 << VIRTIO_NET_F_MRG_RXBUF;
+        if enable_offload {
+            avail_features |= OFFLOAD_FEATURES;
+        }
 
         let mut config_space = ConfigSpace::default();
         if let Some(mac) = guest_mac {
@@ -167,6 +213,13 @@ impl Net {
             // Otherwise, it should attempt to read the device MAC address from the config space.
             avail_features |= 1 << VIRTIO_NET_F_MAC;
         }
+        if let Some(mtu) = mtu {
+            tap.set_mtu(mtu).map_err(Error::TapSetMtu)?;
+            // The driver configures the guest interface with the MTU of the tap, so that the
+            // frames it sends aren't too large for the host.
+            config_space.mtu = mtu;
+            avail_features |= 1 << VIRTIO_NET_F_MTU;
+        }
 
         let mut queue_evts = Vec::new();
         for _ in QUEUE_SIZES.iter() {
@@ -191,17 +244,22 @@ impl Net {
             tx_rate_limiter,
             rx_deferred_frame: false,
             rx_deferred_irqs: false,
+            tx_blocked_by_tap: false,
             rx_bytes_read: 0,
             rx_frame_buf: [0u8; MAX_BUFFER_SIZE],
             tx_frame_buf: [0u8; MAX_BUFFER_SIZE],
             tx_iovec: Vec::with_capacity(QUEUE_SIZE as usize),
             interrupt_status: Arc::new(AtomicUsize::new(0)),
+            irq_counter: Arc::default(),
             interrupt_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?,
+            irq_coalescer: InterruptCoalescer::new(0).map_err(Error::IO)?,
             device_state: DeviceState::Inactive,
             activate_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?,
             config_space,
             mmds_ns,
             guest_mac: guest_mac.copied(),
+            pcap: None,
+            max_descriptors_per_event: DEFAULT_MAX_DESCRIPTORS_PER_EVENT,
 
             #[cfg(test)]
             mocks: Mocks::default(),
@@ -228,6 +286,75 @@ impl Net {
         self.mmds_ns.is_some()
     }
 
+    /// Says if checksum and segmentation offloading are offered to the guest.
+    pub fn offload_enabled(&self) -> bool {
+        self.avail_features & OFFLOAD_FEATURES != 0
+    }
+
+    /// Provides the MTU advertised to the guest, if one was configured.
+    pub fn mtu(&self) -> Option<u16> {
+        if self.avail_features & (1 << VIRTIO_NET_F_MTU) != 0 {
+            Some(self.config_space.mtu)
+        } else {
+            None
+        }
+    }
+
+    /// Provides the path of the packet capture file, if the traffic is captured.
+    pub fn pcap_path(&self) -> Option<String> {
+        self.pcap.as_ref().map(|pcap| pcap.path().to_string())
+    }
+
+    /// Starts capturing the traffic of this device to the pcap file at `path`, replacing any
+    /// previous capture. Passing `None` stops the capture.
+    pub fn set_pcap_path(&mut self, path: Option<String>) -> Result<()> {
+        if let Some(pcap) = self.pcap.as_mut() {
+            pcap.flush();
+        }
+        self.pcap = path
+            .map(PcapWriter::new)
+            .transpose()
+            .map_err(Error::PcapOpen)?;
+        Ok(())
+    }
+
+    // Adds a frame, given with its VNET header, to the packet capture.
+    fn capture_frame(pcap: Option<&mut PcapWriter>, frame_buf: &[u8]) {
+        if let Some(pcap) = pcap {
+            if let Ok(frame) = frame_bytes_from_buf(frame_buf) {
+                pcap.capture(frame);
+            }
+        }
+    }
+
+    fn flush_pcap(&mut self) {
+        if let Some(pcap) = self.pcap.as_mut() {
+            pcap.flush();
+        }
+    }
+
+    // Translates the features acked by the guest into the tap offload flags.
+    fn build_tap_offload_features(guest_supported_features: u64) -> u32 {
+        let flags = [
+            (VIRTIO_NET_F_GUEST_CSUM, net_gen::TUN_F_CSUM),
+            (VIRTIO_NET_F_GUEST_UFO, net_gen::TUN_F_UFO),
+            (VIRTIO_NET_F_GUEST_TSO4, net_gen::TUN_F_TSO4),
+            (VIRTIO_NET_F_GUEST_TSO6, net_gen::TUN_F_TSO6),
+        ];
+
+        flags
+            .iter()
+            .filter(|(virtio_flag, _)| guest_supported_features & (1 << virtio_flag) != 0)
+            .fold(0, |tap_features, (_, tap_flag)| tap_features | tap_flag)
+    }
+
+    /// Configures the tap offload flags to match the features negotiated with the guest.
+    pub(crate) fn apply_offload_features(&self) -> Result<()> {
+        self.tap
+            .set_offload(Self::build_tap_offload_features(self.acked_features))
+            .map_err(Error::TapSetOffload)
+    }
+
     /// Sets MMDS endpoint IPv4 address, if the device supports MMDS.
     pub fn set_mmds_ipv4_addr(&mut self, ipv4_addr: Ipv4Addr) {
         if let Some(mmds_ns) = self.mmds_ns.as_mut() {
@@ -235,6 +362,30 @@ impl Net {
         }
     }
 
+    /// Sets MMDS endpoint IPv6 address, if the device supports MMDS. Passing `None` stops the
+    /// MMDS from answering requests over IPv6.
+    pub fn set_mmds_ipv6_addr(&mut self, ipv6_addr: Option<Ipv6Addr>) {
+        if let Some(mmds_ns) = self.mmds_ns.as_mut() {
+            mmds_ns.set_ipv6_addr(ipv6_addr);
+        }
+    }
+
+    /// Restricts the guest addresses from which the MMDS can be reached, if the device supports
+    /// MMDS. Passing an empty list allows any address.
+    pub fn set_mmds_allowed_sources(&mut self, allowed_sources: Vec<IpPrefix>) {
+        if let Some(mmds_ns) = self.mmds_ns.as_mut() {
+            mmds_ns.set_allowed_sources(allowed_sources);
+        }
+    }
+
+    /// Provides the prefixes of the guest addresses allowed to reach the MMDS.
+    pub fn mmds_allowed_sources(&self) -> Vec<IpPrefix> {
+        self.mmds_ns
+            .as_ref()
+            .map(|mmds_ns| mmds_ns.allowed_sources().to_vec())
+            .unwrap_or_default()
+    }
+
     /// Provides a reference to the configured RX rate limiter.
     pub fn rx_rate_limiter(&self) -> &RateLimiter {
         &self.rx_rate_limiter
@@ -246,6 +397,16 @@ impl Net {
     }
 
     fn signal_used_queue(&mut self) -> result::Result<(), DeviceError> {
+        // A coalesced interrupt is raised later on, which covers the deferred rx ones too.
+        self.rx_deferred_irqs = false;
+        if !self.irq_coalescer.request() {
+            METRICS.net.coalesced_irq_count.inc();
+            return Ok(());
+        }
+        self.raise_used_queue_interrupt()
+    }
+
+    fn raise_used_queue_interrupt(&mut self) -> result::Result<(), DeviceError> {
         self.interrupt_status
             .fetch_or(VIRTIO_MMIO_INT_VRING as usize, Ordering::SeqCst);
         self.interrupt_evt.write(1).map_err(|e| {
@@ -253,8 +414,7 @@ impl Net {
             METRICS.net.event_fails.inc();
             DeviceError::FailedSignalingUsedQueue(e)
         })?;
-
-        self.rx_deferred_irqs = false;
+        self.irq_counter.inc();
         Ok(())
     }
 
@@ -302,40 +462,30 @@ impl Net {
         success
     }
 
-    // Copies a single frame from `self.rx_frame_buf` into the guest.
-    fn do_write_frame_to_guest(&mut self) -> std::result::Result<(), FrontendError> {
-        let mut result: std::result::Result<(), FrontendError> = Ok(());
-        let mem = match self.device_state {
-            DeviceState::Activated(ref mem) => mem,
-            // This should never happen, it's been already validated in the event handler.
-            DeviceState::Inactive => unreachable!(),
-        };
-
-        let queue = &mut self.queues[RX_INDEX];
-        let head_descriptor = queue.pop(mem).ok_or_else(|| {
-            METRICS.net.no_rx_avail_buffer.inc();
-            FrontendError::EmptyQueue
-        })?;
-        let head_index = head_descriptor.index;
-
-        let mut frame_slice = &self.rx_frame_buf[..self.rx_bytes_read];
-        let frame_len = frame_slice.len();
-        let mut maybe_next_descriptor = Some(head_descriptor);
+    // Copies the start of `frame_slice` into the buffers of the descriptor chain starting at
+    // `head`, until either of them is exhausted, and advances `frame_slice` past the bytes
+    // written.
+    fn write_to_descriptor_chain(
+        mem: &GuestMemoryMmap,
+        head: DescriptorChain,
+        frame_slice: &mut &[u8],
+    ) -> std::result::Result<(), FrontendError> {
+        let mut maybe_next_descriptor = Some(head);
         while let Some(descriptor) = &maybe_next_descriptor {
             if frame_slice.is_empty() {
                 break;
             }
 
             if !descriptor.is_write_only() {
-                result = Err(FrontendError::ReadOnlyDescriptor);
-                break;
+                return Err(FrontendError::ReadOnlyDescriptor);
             }
 
-            let len = std::cmp::min(frame_slice.len(), descriptor.len as usize);
-            match mem.write_slice(&frame_slice[..len], descriptor.addr) {
+            let remaining = *frame_slice;
+            let len = std::cmp::min(remaining.len(), descriptor.len as usize);
+            match mem.write_slice(&remaining[..len], descriptor.addr) {
                 Ok(()) => {
                     METRICS.net.rx_count.inc();
-                    frame_slice = &frame_slice[len..];
+                    *frame_slice = &remaining[len..];
                 }
                 Err(e) => {
                     error!("Failed to write slice: {:?}", e);
@@ -344,13 +494,55 @@ impl Net {
                         _ => &METRICS.net.rx_fails,
                     }
                     .inc();
-                    result = Err(FrontendError::GuestMemory(e));
-                    break;
+                    return Err(FrontendError::GuestMemory(e));
                 }
             };
 
             maybe_next_descriptor = descriptor.next_descriptor();
         }
+        Ok(())
+    }
+
+    // Returns the number of bytes which can be written in the descriptor chain starting at
+    // `head`, which stops at its first read only descriptor.
+    fn descriptor_chain_capacity(head: &DescriptorChain) -> usize {
+        if !head.is_write_only() {
+            return 0;
+        }
+        let mut capacity = head.len as usize;
+        let mut maybe_next_descriptor = head.next_descriptor();
+        while let Some(descriptor) = maybe_next_descriptor {
+            if !descriptor.is_write_only() {
+                break;
+            }
+            capacity += descriptor.len as usize;
+            maybe_next_descriptor = descriptor.next_descriptor();
+        }
+        capacity
+    }
+
+    // Copies a single frame from `self.rx_frame_buf` into the guest.
+    fn do_write_frame_to_guest(&mut self) -> std::result::Result<(), FrontendError> {
+        if self.acked_features & (1 << VIRTIO_NET_F_MRG_RXBUF) != 0 {
+            return self.do_write_merged_frame_to_guest();
+        }
+
+        let mem = match self.device_state {
+            DeviceState::Activated(ref mem) => mem,
+            // This should never happen, it's been already validated in the event handler.
+            DeviceState::Inactive => unreachable!(),
+        };
+
+        let queue = &mut self.queues[RX_INDEX];
+        let head_descriptor = queue.pop(mem).ok_or_else(|| {
+            METRICS.net.no_rx_avail_buffer.inc();
+            FrontendError::EmptyQueue
+        })?;
+        let head_index = head_descriptor.index;
+
+        let mut frame_slice = &self.rx_frame_buf[..self.rx_bytes_read];
+        let frame_len = frame_slice.len();
+        let mut result = Self::write_to_descriptor_chain(mem, head_descriptor, &mut frame_slice);
         if result.is_ok() && !frame_slice.is_empty() {
             warn!("Receiving buffer is too small to hold frame of current size");
             METRICS.net.rx_fails.inc();
@@ -372,6 +564,82 @@ impl Net {
         result
     }
 
+    // Copies a single frame from `self.rx_frame_buf` into the guest, spread over as many
+    // descriptor chains as needed, when the guest negotiated `VIRTIO_NET_F_MRG_RXBUF`. The
+    // chains are only made visible to the guest once the whole frame is written, so that it
+    // finds all of them when reading the `num_buffers` field of the VNET header.
+    fn do_write_merged_frame_to_guest(&mut self) -> std::result::Result<(), FrontendError> {
+        let mem = match self.device_state {
+            DeviceState::Activated(ref mem) => mem,
+            // This should never happen, it's been already validated in the event handler.
+            DeviceState::Inactive => unreachable!(),
+        };
+
+        let queue = &mut self.queues[RX_INDEX];
+        let frame_len = self.rx_bytes_read;
+
+        // Pop descriptor chains until they can hold the whole frame.
+        let mut heads = Vec::new();
+        let mut capacity = 0;
+        while capacity < frame_len {
+            match queue.pop(mem) {
+                Some(head) => {
+                    capacity += Self::descriptor_chain_capacity(&head);
+                    heads.push(head);
+                }
+                None => {
+                    // The frame is kept until the guest makes enough buffers available.
+                    for _ in 0..heads.len() {
+                        queue.undo_pop();
+                    }
+                    METRICS.net.no_rx_avail_buffer.inc();
+                    return Err(FrontendError::EmptyQueue);
+                }
+            }
+        }
+
+        // The number of chains is bounded by the queue size, so it fits the header field.
+        set_vnet_hdr_num_buffers(&mut self.rx_frame_buf, heads.len() as u16);
+        let mut frame_slice = &self.rx_frame_buf[..frame_len];
+        let mut result = Ok(());
+        let mut used = Vec::with_capacity(heads.len());
+        for head in heads {
+            let head_index = head.index;
+            let remaining = frame_slice.len();
+            if result.is_ok() {
+                result = Self::write_to_descriptor_chain(mem, head, &mut frame_slice);
+            }
+            used.push((head_index, (remaining - frame_slice.len()) as u32));
+        }
+        if result.is_ok() && !frame_slice.is_empty() {
+            warn!("Receiving buffers are too small to hold frame of current size");
+            METRICS.net.rx_fails.inc();
+            result = Err(FrontendError::DescriptorChainTooSmall);
+        }
+
+        // Mark the descriptor chains as used. If an error occurred, skip all of them.
+        for (offset, (head_index, len)) in used.iter().enumerate() {
+            let used_len = if result.is_err() { 0 } else { *len };
+            queue
+                .write_used_elem(mem, offset as u16, *head_index, used_len)
+                .map_err(|e| {
+                    error!("Failed to add available descriptor {}: {}", head_index, e);
+                    FrontendError::AddUsed
+                })?;
+        }
+        queue.advance_used(mem, used.len() as u16).map_err(|e| {
+            error!("Failed to publish the used descriptors: {}", e);
+            FrontendError::AddUsed
+        })?;
+        self.rx_deferred_irqs = true;
+
+        if result.is_ok() {
+            METRICS.net.rx_bytes_count.add(frame_len);
+            METRICS.net.rx_packets_count.inc();
+        }
+        result
+    }
+
     // Copies a single frame from `self.rx_frame_buf` into the guest. In case of an error retries
     // the operation if possible. Returns true if the operation was successfull.
     fn write_frame_to_guest(&mut self) -> bool {
@@ -440,6 +708,10 @@ impl Net {
                 METRICS.net.tx_packets_count.inc();
                 METRICS.net.tx_count.inc();
             }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                METRICS.net.tap_write_backpressure.inc();
+                return Err(Error::TapWriteBackpressure);
+            }
             Err(e) => {
                 error!("Failed to write to tap: {:?}", e);
                 METRICS.net.tap_write_fails.inc();
@@ -472,6 +744,7 @@ impl Net {
                 Ok(count) => {
                     self.rx_bytes_read = count;
                     METRICS.net.rx_count.inc();
+                    Self::capture_frame(self.pcap.as_mut(), &self.rx_frame_buf[..count]);
                     if !self.rate_limited_rx_single_frame() {
                         self.rx_deferred_frame = true;
                         break;
@@ -496,6 +769,8 @@ impl Net {
             }
         }
 
+        self.flush_pcap();
+
         // At this point we processed as many Rx frames as possible.
         // We have to wake the guest if at least one descriptor chain has been used.
         self.signal_rx_used_queue()
@@ -534,9 +809,23 @@ impl Net {
         // with the MMDS network stack.
         let mut process_rx_for_mmds = false;
         let mut raise_irq = false;
+        let mut processed: u16 = 0;
         let tx_queue = &mut self.queues[TX_INDEX];
 
         while let Some(head) = tx_queue.pop(mem) {
+            if processed == self.max_descriptors_per_event {
+                // Leave the rest of the queue for the next event, so that a busy queue doesn't
+                // hold up the other devices and the API. The RX frames aren't limited, as the
+                // tap is only read until it runs out of frames or the guest out of buffers.
+                tx_queue.undo_pop();
+                if let Err(e) = self.queue_evts[TX_INDEX].write(1) {
+                    error!("Failed to signal tx queue event: {:?}", e);
+                    METRICS.net.event_fails.inc();
+                }
+                break;
+            }
+            processed += 1;
+
             // If limiter.consume() fails it means there is no more TokenType::Ops
             // budget and rate limiting is in effect.
             if !self.tx_rate_limiter.consume(1, TokenType::Ops) {
@@ -606,14 +895,27 @@ impl Net {
                 }
             }
 
-            let frame_consumed_by_mmds = Self::write_to_mmds_or_tap(
+            let frame_consumed_by_mmds = match Self::write_to_mmds_or_tap(
                 self.mmds_ns.as_mut(),
                 &mut self.tx_rate_limiter,
                 &self.tx_frame_buf[..read_count],
                 &mut self.tap,
                 self.guest_mac,
-            )
-            .unwrap_or(false);
+            ) {
+                Ok(consumed) => consumed,
+                Err(Error::TapWriteBackpressure) => {
+                    // Return the frame to the avail ring, and its budget to the rate limiter.
+                    // It's sent again once the tap becomes writable, instead of being dropped.
+                    self.tx_rate_limiter.manual_replenish(1, TokenType::Ops);
+                    self.tx_rate_limiter
+                        .manual_replenish(read_count as u64, TokenType::Bytes);
+                    tx_queue.undo_pop();
+                    self.tx_blocked_by_tap = true;
+                    break;
+                }
+                Err(_) => false,
+            };
+            Self::capture_frame(self.pcap.as_mut(), &self.tx_frame_buf[..read_count]);
             if frame_consumed_by_mmds && !self.rx_deferred_frame {
                 // MMDS consumed this frame/request, let's also try to process the response.
                 process_rx_for_mmds = true;
@@ -625,9 +927,11 @@ impl Net {
             raise_irq = true;
         }
 
+        self.flush_pcap();
+
         if raise_irq {
             self.signal_used_queue()?;
-        } else {
+        } else if !self.tx_blocked_by_tap {
             METRICS.net.no_tx_avail_buffer.inc();
         }
 
@@ -651,6 +955,23 @@ impl Net {
         self.tx_rate_limiter.update_buckets(tx_bytes, tx_ops);
     }
 
+    /// Sets the number of descriptor chains processed from the TX queue for one event.
+    pub fn set_max_descriptors_per_event(&mut self, max_descriptors_per_event: u16) {
+        self.max_descriptors_per_event = max_descriptors_per_event;
+    }
+
+    /// Returns the minimum time between two used queue interrupts, in microseconds.
+    pub fn interrupt_coalescing_us(&self) -> u64 {
+        self.irq_coalescer.interval_us()
+    }
+
+    /// Sets the minimum time between two used queue interrupts, in microseconds. The interrupts
+    /// requested sooner are merged and raised at the end of the interval. Every interrupt is
+    /// raised right away when this is 0, which is the default.
+    pub fn set_interrupt_coalescing_us(&mut self, interrupt_coalescing_us: u64) {
+        self.irq_coalescer.set_interval_us(interrupt_coalescing_us);
+    }
+
     #[cfg(not(test))]
     fn read_tap(&mut self) -> io::Result<usize> {
         self.tap.read(&mut self.rx_frame_buf)
@@ -721,6 +1042,19 @@ impl Net {
         }
     }
 
+    pub fn process_tap_tx_event(&mut self) {
+        // The tap is reported writable along with its other events, the TX queue is only
+        // processed again when it was stopped by the tap backpressure.
+        if !self.tx_blocked_by_tap {
+            return;
+        }
+        METRICS.net.tx_tap_event_count.inc();
+        self.tx_blocked_by_tap = false;
+        if !self.tx_rate_limiter.is_blocked() {
+            self.process_tx().unwrap_or_else(report_net_event_fail);
+        }
+    }
+
     pub fn process_rx_rate_limiter_event(&mut self) {
         METRICS.net.rx_event_rate_limiter_count.inc();
         // Upon rate limiter event, call the rate limiter handler
@@ -759,6 +1093,37 @@ impl Net {
         let _ = self.resume_rx();
         let _ = self.process_tx();
     }
+
+    /// Brings the device queues to a consistent state, before saving the device state.
+    ///
+    /// Must be called while the guest is paused. Pending TX frames are sent, unless rate limiting
+    /// is in effect, in which case their descriptor chains are left in the avail ring. The
+    /// deferred RX frame, if any, is delivered if the guest provided buffers for it, otherwise
+    /// it is kept and saved along with the device state.
+    pub fn quiesce(&mut self) {
+        self.process_tx().unwrap_or_else(report_net_event_fail);
+        self.resume_rx().unwrap_or_else(report_net_event_fail);
+        if self.irq_coalescer.flush() {
+            self.raise_used_queue_interrupt()
+                .unwrap_or_else(report_net_event_fail);
+        }
+    }
+
+    pub fn process_irq_coalescer_event(&mut self) {
+        if self.irq_coalescer.event_handler() {
+            self.raise_used_queue_interrupt()
+                .unwrap_or_else(report_net_event_fail);
+        }
+    }
+
+    /// Returns the frame which could not be delivered to the guest yet, if any.
+    pub(crate) fn deferred_rx_frame(&self) -> Option<&[u8]> {
+        if self.rx_deferred_frame {
+            Some(&self.rx_frame_buf[..self.rx_bytes_read])
+        } else {
+            None
+        }
+    }
 }
 
 impl VirtioDevice for Net {
@@ -786,6 +1151,14 @@ impl VirtioDevice for Net {
         self.interrupt_status.clone()
     }
 
+    fn irq_counter(&self) -> &SharedIncMetric {
+        &self.irq_counter
+    }
+
+    fn set_irq_counter(&mut self, irq_counter: Arc<SharedIncMetric>) {
+        self.irq_counter = irq_counter;
+    }
+
     fn avail_features(&self) -> u64 {
         self.avail_features
     }
@@ -818,8 +1191,8 @@ impl VirtioDevice for Net {
     fn write_config(&mut self, offset: u64, data: &[u8]) {
         let data_len = data.len() as u64;
         let config_space_bytes = self.config_space.as_mut_slice();
-        let config_len = config_space_bytes.len() as u64;
-        if offset + data_len > config_len {
+        // Only the MAC address can be written by the driver, the other fields are read-only.
+        if offset + data_len > MAC_ADDR_LEN as u64 {
             error!("Failed to write config space");
             METRICS.net.cfg_fails.inc();
             return;
@@ -840,13 +1213,37 @@ impl VirtioDevice for Net {
     }
 
     fn activate(&mut self, mem: GuestMemoryMmap) -> ActivateResult {
+        if let Err(err) = self.apply_offload_features() {
+            METRICS.net.activate_fails.inc();
+            error!("Net: Cannot set tap offload flags: {:?}", err);
+            return Err(super::super::ActivateError::BadActivate);
+        }
         if self.activate_evt.write(1).is_err() {
+            METRICS.net.activate_fails.inc();
             error!("Net: Cannot write to activate_evt");
             return Err(super::super::ActivateError::BadActivate);
         }
         self.device_state = DeviceState::Activated(mem);
         Ok(())
     }
+
+    fn reset(&mut self) -> Option<(EventFd, Vec<EventFd>)> {
+        // The frame deferred for the old rx queue is dropped, the transport resets the queues and
+        // the device is activated again once the driver sets them up.
+        let interrupt_evt = self.interrupt_evt.try_clone().ok()?;
+        let queue_evts = self
+            .queue_evts
+            .iter()
+            .map(EventFd::try_clone)
+            .collect::<io::Result<Vec<EventFd>>>()
+            .ok()?;
+        self.acked_features = 0;
+        self.rx_deferred_frame = false;
+        self.rx_deferred_irqs = false;
+        self.rx_bytes_read = 0;
+        self.device_state = DeviceState::Inactive;
+        Some((interrupt_evt, queue_evts))
+    }
 }
 
 #[cfg(test)]
@@ -862,6 +1259,7 @@ pub mod tests {
     use std::{io, mem, thread};
 
     use crate::check_metric_after_block;
+    use crate::virtio::net::pcap::{PCAP_HEADER_LEN, PCAP_RECORD_HEADER_LEN};
     use crate::virtio::net::test_utils::test::TestHelper;
     use crate::virtio::net::test_utils::{
         check_used_queue_signal, default_net, if_index, inject_tap_tx_frame, set_mac, NetEvent,
@@ -878,8 +1276,9 @@ pub mod tests {
     use rate_limiter::{RateLimiter, TokenBucket, TokenType};
     use virtio_gen::virtio_net::{
         virtio_net_hdr_v1, VIRTIO_F_VERSION_1, VIRTIO_NET_F_CSUM, VIRTIO_NET_F_GUEST_CSUM,
-        VIRTIO_NET_F_GUEST_TSO4, VIRTIO_NET_F_GUEST_UFO, VIRTIO_NET_F_HOST_TSO4,
-        VIRTIO_NET_F_HOST_UFO, VIRTIO_NET_F_MAC,
+        VIRTIO_NET_F_GUEST_TSO4, VIRTIO_NET_F_GUEST_TSO6, VIRTIO_NET_F_GUEST_UFO,
+        VIRTIO_NET_F_HOST_TSO4, VIRTIO_NET_F_HOST_TSO6, VIRTIO_NET_F_HOST_UFO, VIRTIO_NET_F_MAC,
+        VIRTIO_NET_F_MRG_RXBUF, VIRTIO_NET_F_MTU,
     };
     use vm_memory::{Address, GuestMemory};
 
@@ -946,10 +1345,13 @@ pub mod tests {
         let features = 1 << VIRTIO_NET_F_GUEST_CSUM
             | 1 << VIRTIO_NET_F_CSUM
             | 1 << VIRTIO_NET_F_GUEST_TSO4
+            | 1 << VIRTIO_NET_F_GUEST_TSO6
             | 1 << VIRTIO_NET_F_MAC
             | 1 << VIRTIO_NET_F_GUEST_UFO
             | 1 << VIRTIO_NET_F_HOST_TSO4
+            | 1 << VIRTIO_NET_F_HOST_TSO6
             | 1 << VIRTIO_NET_F_HOST_UFO
+            | 1 << VIRTIO_NET_F_MRG_RXBUF
             | 1 << VIRTIO_F_VERSION_1;
 
         assert_eq!(net.avail_features_by_page(0), features as u32);
@@ -963,6 +1365,81 @@ pub mod tests {
         }
 
         assert_eq!(net.acked_features, features);
+        assert!(net.offload_enabled());
+    }
+
+    #[test]
+    fn test_virtio_device_features_no_offload() {
+        let net = Net::new_with_tap(
+            "net-no-offload".to_string(),
+            "net-no-offload".to_string(),
+            None,
+            RateLimiter::default(),
+            RateLimiter::default(),
+            false,
+            false,
+            None,
+        )
+        .unwrap();
+
+        // Only the basic features are offered when offloading is disabled.
+        assert_eq!(
+            net.avail_features,
+            1 << VIRTIO_F_VERSION_1 | 1 << VIRTIO_NET_F_MRG_RXBUF
+        );
+        assert!(!net.offload_enabled());
+        // The VNET header layout does not depend on the offload features.
+        assert_eq!(vnet_hdr_len(), mem::size_of::<virtio_net_hdr_v1>());
+    }
+
+    #[test]
+    fn test_virtio_device_mtu() {
+        let net = Net::new_with_tap(
+            "net-mtu".to_string(),
+            "net-mtu".to_string(),
+            None,
+            RateLimiter::default(),
+            RateLimiter::default(),
+            false,
+            true,
+            Some(9000),
+        )
+        .unwrap();
+
+        // The MTU is set on the tap and advertised to the guest.
+        assert_eq!(net.tap.mtu().unwrap(), 9000);
+        assert_ne!(net.avail_features & (1 << VIRTIO_NET_F_MTU), 0);
+        assert_eq!(net.mtu(), Some(9000));
+        let mut config_mtu = [0u8; 2];
+        net.read_config(10, &mut config_mtu);
+        assert_eq!(u16::from_le_bytes(config_mtu), 9000);
+
+        // The MTU isn't advertised when it isn't configured.
+        assert_eq!(default_net().mtu(), None);
+    }
+
+    #[test]
+    fn test_build_tap_offload_features() {
+        assert_eq!(Net::build_tap_offload_features(0), 0);
+        assert_eq!(
+            Net::build_tap_offload_features(1 << VIRTIO_F_VERSION_1 | 1 << VIRTIO_NET_F_MAC),
+            0
+        );
+        assert_eq!(
+            Net::build_tap_offload_features(1 << VIRTIO_NET_F_GUEST_CSUM),
+            net_gen::TUN_F_CSUM
+        );
+        // Host side features don't require any tap offload flags.
+        assert_eq!(
+            Net::build_tap_offload_features(
+                1 << VIRTIO_NET_F_CSUM | 1 << VIRTIO_NET_F_HOST_TSO4 | 1 << VIRTIO_NET_F_HOST_TSO6
+            ),
+            0
+        );
+        assert_eq!(
+            Net::build_tap_offload_features(OFFLOAD_FEATURES),
+            net_gen::TUN_F_CSUM | net_gen::TUN_F_UFO | net_gen::TUN_F_TSO4 | net_gen::TUN_F_TSO6
+        );
     }
 
     #[test]
@@ -978,7 +1455,7 @@ pub mod tests {
 
         // Invalid read.
         config_mac = [0u8; MAC_ADDR_LEN];
-        net.read_config(MAC_ADDR_LEN as u64 + 1, &mut config_mac);
+        net.read_config(mem::size_of::<ConfigSpace>() as u64 + 1, &mut config_mac);
         assert_eq!(config_mac, [0u8, 0u8, 0u8, 0u8, 0u8, 0u8]);
     }
 
@@ -1174,6 +1651,76 @@ pub mod tests {
         th.rxq.dtable[11].check_data(&frame[150..]);
     }
 
+    #[test]
+    fn test_rx_mergeable_buffers() {
+        let mut th = TestHelper::default();
+        th.activate_net();
+        th.net().acked_features |= 1 << VIRTIO_NET_F_MRG_RXBUF;
+        th.net().mocks.set_read_tap(ReadTapMock::TapFrame);
+
+        // The frame is spread over 3 descriptor chains, the last one holding 2 descriptors.
+        th.add_desc_chain(NetQueue::Rx, 0, &[(0, 100, VIRTQ_DESC_F_WRITE)]);
+        th.add_desc_chain(NetQueue::Rx, 200, &[(1, 100, VIRTQ_DESC_F_WRITE)]);
+        th.add_desc_chain(
+            NetQueue::Rx,
+            400,
+            &[(2, 50, VIRTQ_DESC_F_WRITE), (3, 4096, VIRTQ_DESC_F_WRITE)],
+        );
+        let mut frame = inject_tap_tx_frame(&th.net(), 1000);
+        check_metric_after_block!(
+            METRICS.net.rx_packets_count,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+
+        assert!(!th.net().rx_deferred_frame);
+        assert_eq!(th.rxq.used.idx.get(), 3);
+        check_used_queue_signal(&th.net(), 1);
+        th.rxq.check_used_elem(0, 0, 100);
+        th.rxq.check_used_elem(1, 1, 100);
+        th.rxq.check_used_elem(2, 2, 800);
+        // The VNET header holds the number of descriptor chains.
+        set_vnet_hdr_num_buffers(&mut frame, 3);
+        th.rxq.dtable[0].check_data(&frame[..100]);
+        th.rxq.dtable[1].check_data(&frame[100..200]);
+        th.rxq.dtable[2].check_data(&frame[200..250]);
+        th.rxq.dtable[3].check_data(&frame[250..]);
+    }
+
+    #[test]
+    fn test_rx_mergeable_buffers_deferred() {
+        let mut th = TestHelper::default();
+        th.activate_net();
+        th.net().acked_features |= 1 << VIRTIO_NET_F_MRG_RXBUF;
+        th.net().mocks.set_read_tap(ReadTapMock::TapFrame);
+
+        // The frame doesn't fit the available buffers, which are left to the guest.
+        th.add_desc_chain(NetQueue::Rx, 0, &[(0, 100, VIRTQ_DESC_F_WRITE)]);
+        let mut frame = inject_tap_tx_frame(&th.net(), 1000);
+        check_metric_after_block!(
+            METRICS.net.no_rx_avail_buffer,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+        assert!(th.net().rx_deferred_frame);
+        assert_eq!(th.rxq.used.idx.get(), 0);
+
+        // The frame is received once the guest adds enough buffers.
+        th.add_desc_chain(NetQueue::Rx, 200, &[(1, 4096, VIRTQ_DESC_F_WRITE)]);
+        check_metric_after_block!(
+            METRICS.net.rx_packets_count,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+        assert!(!th.net().rx_deferred_frame);
+        assert_eq!(th.rxq.used.idx.get(), 2);
+        th.rxq.check_used_elem(0, 0, 100);
+        th.rxq.check_used_elem(1, 1, 900);
+        set_vnet_hdr_num_buffers(&mut frame, 2);
+        th.rxq.dtable[0].check_data(&frame[..100]);
+        th.rxq.dtable[1].check_data(&frame[100..]);
+    }
+
     #[test]
     fn test_rx_multiple_frames() {
         let mut th = TestHelper::default();
@@ -1345,6 +1892,39 @@ pub mod tests {
         assert!(!tap_traffic_simulator.pop_rx_packet(&mut []));
     }
 
+    #[test]
+    fn test_tx_tap_backpressure() {
+        let mut th = TestHelper::default();
+        th.activate_net();
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+
+        let desc_list = [(0, 1000, 0)];
+        th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
+        let frame = th.write_tx_frame(&desc_list, 1000);
+
+        // The tap becoming writable is ignored while the transmission isn't blocked by it.
+        check_metric_after_block!(
+            &METRICS.net.tx_tap_event_count,
+            0,
+            th.net().process_tap_tx_event()
+        );
+        assert_eq!(th.txq.used.idx.get(), 0);
+
+        // The frames the tap couldn't take are sent once it becomes writable.
+        th.net().tx_blocked_by_tap = true;
+        check_metric_after_block!(
+            &METRICS.net.tx_tap_event_count,
+            1,
+            th.net().process_tap_tx_event()
+        );
+        assert!(!th.net().tx_blocked_by_tap);
+        assert_eq!(th.txq.used.idx.get(), 1);
+        check_used_queue_signal(&th.net(), 1);
+        let mut buf = vec![0; 1000];
+        assert!(tap_traffic_simulator.pop_rx_packet(&mut buf[vnet_hdr_len()..]));
+        assert_eq!(&buf, &frame);
+    }
+
     #[test]
     fn test_tx_complex_descriptor() {
         let mut th = TestHelper::default();
@@ -1409,6 +1989,35 @@ pub mod tests {
         assert_eq!(&buf[..600], &frame_2[..600]);
     }
 
+    #[test]
+    fn test_tx_max_descriptors_per_event() {
+        let mut th = TestHelper::default();
+        th.activate_net();
+        th.net().set_max_descriptors_per_event(1);
+
+        let desc_list = [(0, 100, 0)];
+        th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
+        th.write_tx_frame(&desc_list, 100);
+        let desc_list = [(1, 100, 0)];
+        th.add_desc_chain(NetQueue::Tx, 500, &desc_list);
+        th.write_tx_frame(&desc_list, 100);
+
+        // Only the first frame is sent, the tx queue event is signaled again for the second one.
+        check_metric_after_block!(
+            METRICS.net.tx_packets_count,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+        assert_eq!(th.txq.used.idx.get(), 1);
+        check_metric_after_block!(
+            METRICS.net.tx_packets_count,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+        assert_eq!(th.txq.used.idx.get(), 2);
+        th.txq.check_used_elem(1, 1, 0);
+    }
+
     fn create_arp_request(
         src_mac: MacAddr,
         src_ip: Ipv4Addr,
@@ -1441,6 +2050,60 @@ pub mod tests {
         (frame_buf, frame_len)
     }
 
+    #[test]
+    fn test_pcap_capture() {
+        let mut th = TestHelper::default();
+        th.activate_net();
+        th.net().mocks.set_read_tap(ReadTapMock::TapFrame);
+        let tmp_file = utils::tempfile::TempFile::new().unwrap();
+        let path = tmp_file.as_path().to_str().unwrap().to_string();
+
+        assert!(th.net().pcap_path().is_none());
+        th.net().set_pcap_path(Some(path.clone())).unwrap();
+        assert_eq!(th.net().pcap_path(), Some(path.clone()));
+
+        // Capture a transmitted frame.
+        let desc_list = [(0, 300, 0)];
+        th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
+        let tx_frame = th.write_tx_frame(&desc_list, 300);
+        check_metric_after_block!(
+            METRICS.net.tx_packets_count,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+
+        // Capture a received frame.
+        th.add_desc_chain(NetQueue::Rx, 0, &[(0, 4096, VIRTQ_DESC_F_WRITE)]);
+        let rx_frame = inject_tap_tx_frame(&th.net(), 1000);
+        check_metric_after_block!(
+            METRICS.net.rx_packets_count,
+            1,
+            th.event_manager.run_with_timeout(100).unwrap()
+        );
+
+        // The frames are captured without their VNET header.
+        let content = std::fs::read(&path).unwrap();
+        let tx_record = PCAP_HEADER_LEN + PCAP_RECORD_HEADER_LEN;
+        let rx_record = tx_record + tx_frame.len() - vnet_hdr_len() + PCAP_RECORD_HEADER_LEN;
+        assert_eq!(content.len(), rx_record + rx_frame.len() - vnet_hdr_len());
+        assert_eq!(
+            &content[tx_record..rx_record - PCAP_RECORD_HEADER_LEN],
+            &tx_frame[vnet_hdr_len()..]
+        );
+        assert_eq!(&content[rx_record..], &rx_frame[vnet_hdr_len()..]);
+
+        // Stop the capture.
+        th.net().set_pcap_path(None).unwrap();
+        assert!(th.net().pcap_path().is_none());
+
+        // The capture file must be creatable.
+        assert!(matches!(
+            th.net()
+                .set_pcap_path(Some("/invalid/path/capture.pcap".to_string())),
+            Err(Error::PcapOpen(_))
+        ));
+    }
+
     #[test]
     fn test_mmds_detour_and_injection() {
         let mut net = default_net();
@@ -1514,6 +2177,40 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_quiesce() {
+        let mut th = TestHelper::default();
+        th.activate_net();
+        th.net().mocks.set_read_tap(ReadTapMock::TapFrame);
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+
+        // Without any Rx descriptor chain, the frame is deferred.
+        let rx_frame = inject_tap_tx_frame(&th.net(), 1000);
+        th.simulate_event(NetEvent::Tap);
+        assert!(th.net().rx_deferred_frame);
+        assert_eq!(th.net().deferred_rx_frame().unwrap().len(), rx_frame.len());
+
+        // Queue a Tx frame and an Rx descriptor chain, without processing the queue events.
+        let desc_list = [(0, 1000, 0)];
+        th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
+        let tx_frame = th.write_tx_frame(&desc_list, 1000);
+        th.add_desc_chain(NetQueue::Rx, 2000, &[(1, 4096, VIRTQ_DESC_F_WRITE)]);
+
+        th.net().quiesce();
+
+        // The Tx frame was sent.
+        assert_eq!(th.txq.used.idx.get(), 1);
+        th.txq.check_used_elem(0, 0, 0);
+        let mut buf = vec![0; 1000];
+        assert!(tap_traffic_simulator.pop_rx_packet(&mut buf[vnet_hdr_len()..]));
+        assert_eq!(&buf[..1000], &tx_frame[..1000]);
+        // The deferred frame was delivered.
+        assert!(th.net().deferred_rx_frame().is_none());
+        assert_eq!(th.rxq.used.idx.get(), 1);
+        th.rxq.check_used_elem(0, 1, rx_frame.len() as u32);
+        th.rxq.dtable[1].check_data(&rx_frame);
+    }
+
     #[test]
     fn test_process_error_cases() {
         let mut th = TestHelper::default();
@@ -1868,4 +2565,18 @@ pub mod tests {
 
         check_used_queue_signal(&net, 0);
     }
+
+    #[test]
+    fn test_reset() {
+        let mut th = TestHelper::default();
+        th.activate_net();
+        let mut net = th.net.lock().unwrap();
+        net.rx_deferred_frame = true;
+
+        let (_, queue_evts) = net.reset().unwrap();
+        assert_eq!(queue_evts.len(), QUEUE_SIZES.len());
+        assert!(!net.is_activated());
+        assert_eq!(net.acked_features(), 0);
+        assert!(!net.rx_deferred_frame);
+    }
 }
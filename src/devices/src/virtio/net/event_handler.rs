@@ -24,9 +24,14 @@ impl Net {
         if let Err(e) = ops.add(Events::new(&self.tx_rate_limiter, EventSet::IN)) {
             error!("Failed to register tx queue event: {}", e);
         }
+        if let Err(e) = ops.add(Events::new(&self.irq_coalescer, EventSet::IN)) {
+            error!("Failed to register interrupt coalescing event: {}", e);
+        }
+        // The tap is also watched for becoming writable, to resume the transmission of the
+        // frames it couldn't take.
         if let Err(e) = ops.add(Events::new(
             &self.tap,
-            EventSet::IN | EventSet::EDGE_TRIGGERED,
+            EventSet::IN | EventSet::OUT | EventSet::EDGE_TRIGGERED,
         )) {
             error!("Failed to register tap event: {}", e);
         }
@@ -57,7 +62,7 @@ impl MutEventSubscriber for Net {
 
         // TODO: also check for errors. Pending high level discussions on how we want
         // to handle errors in devices.
-        let supported_events = EventSet::IN;
+        let supported_events = EventSet::IN | EventSet::OUT;
         if !supported_events.contains(event_set) {
             warn!(
                 "Received unknown event: {:?} from source: {:?}",
@@ -71,16 +76,25 @@ impl MutEventSubscriber for Net {
             let virtq_tx_ev_fd = self.queue_evts[TX_INDEX].as_raw_fd();
             let rx_rate_limiter_fd = self.rx_rate_limiter.as_raw_fd();
             let tx_rate_limiter_fd = self.tx_rate_limiter.as_raw_fd();
+            let irq_coalescer_fd = self.irq_coalescer.as_raw_fd();
             let tap_fd = self.tap.as_raw_fd();
             let activate_fd = self.activate_evt.as_raw_fd();
 
             // Looks better than C style if/else if/else.
             match source {
                 _ if source == virtq_rx_ev_fd => self.process_rx_queue_event(),
-                _ if source == tap_fd => self.process_tap_rx_event(),
+                _ if source == tap_fd => {
+                    if event_set.contains(EventSet::OUT) {
+                        self.process_tap_tx_event();
+                    }
+                    if event_set.contains(EventSet::IN) {
+                        self.process_tap_rx_event();
+                    }
+                }
                 _ if source == virtq_tx_ev_fd => self.process_tx_queue_event(),
                 _ if source == rx_rate_limiter_fd => self.process_rx_rate_limiter_event(),
                 _ if source == tx_rate_limiter_fd => self.process_tx_rate_limiter_event(),
+                _ if source == irq_coalescer_fd => self.process_irq_coalescer_event(),
                 _ if activate_fd == source => self.process_activate_event(ops),
                 _ => {
                     warn!("Net: Spurious event received: {:?}", source);
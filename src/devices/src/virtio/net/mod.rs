@@ -14,6 +14,7 @@ pub const TX_INDEX: usize = 1;
 
 pub mod device;
 pub mod event_handler;
+pub mod pcap;
 pub mod persist;
 mod tap;
 pub mod test_utils;
@@ -30,14 +31,20 @@ pub enum Error {
     TapSetOffload(TapError),
     /// Setting vnet header size failed.
     TapSetVnetHdrSize(TapError),
+    /// Setting the tap interface MTU failed.
+    TapSetMtu(TapError),
     /// Enabling tap interface failed.
     TapEnable(TapError),
     /// EventFd error.
     EventFd(io::Error),
     /// IO error.
     IO(io::Error),
+    /// Opening the packet capture file failed.
+    PcapOpen(io::Error),
     /// The VNET header is missing from the frame.
     VnetHeaderMissing,
+    /// The tap device can't take more frames until it becomes writable again.
+    TapWriteBackpressure,
 }
 
 pub type Result<T> = result::Result<T, Error>;
@@ -0,0 +1,195 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Packet capture of the frames going through a network device, in the pcap format.
+
+use std::cmp;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+
+use logger::{error, IncMetric, METRICS};
+use utils::time::{get_time_us, ClockType};
+
+// The capture is buffered in memory and written out without blocking the data path. Frames
+// which don't fit in the buffer while the writer falls behind are dropped.
+pub(crate) const PCAP_BUFFER_SIZE: usize = 1 << 20;
+// Frames are truncated to this length in the capture.
+pub(crate) const PCAP_SNAPLEN: u32 = 65535;
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const PCAP_VERSION_MAJOR: u16 = 2;
+const PCAP_VERSION_MINOR: u16 = 4;
+const PCAP_LINKTYPE_ETHERNET: u32 = 1;
+pub(crate) const PCAP_HEADER_LEN: usize = 24;
+pub(crate) const PCAP_RECORD_HEADER_LEN: usize = 16;
+
+/// Writes the captured frames of a network device to a pcap file.
+pub struct PcapWriter {
+    path: String,
+    file: File,
+    buf: Vec<u8>,
+}
+
+impl PcapWriter {
+    /// Creates the capture file at `path`, truncating it if it exists. Like for a FIFO, the
+    /// writes to it are non-blocking.
+    pub fn new(path: String) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)?;
+
+        let mut buf = Vec::with_capacity(PCAP_BUFFER_SIZE);
+        buf.extend_from_slice(&PCAP_MAGIC.to_ne_bytes());
+        buf.extend_from_slice(&PCAP_VERSION_MAJOR.to_ne_bytes());
+        buf.extend_from_slice(&PCAP_VERSION_MINOR.to_ne_bytes());
+        // The timestamps are in UTC and have no declared accuracy.
+        buf.extend_from_slice(&0i32.to_ne_bytes());
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        buf.extend_from_slice(&PCAP_SNAPLEN.to_ne_bytes());
+        buf.extend_from_slice(&PCAP_LINKTYPE_ETHERNET.to_ne_bytes());
+
+        Ok(PcapWriter { path, file, buf })
+    }
+
+    /// Provides the path of the capture file.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Buffers an Ethernet frame, or drops it if the buffer is full.
+    pub fn capture(&mut self, frame: &[u8]) {
+        let incl_len = cmp::min(frame.len(), PCAP_SNAPLEN as usize);
+        if self.buf.len() + PCAP_RECORD_HEADER_LEN + incl_len > PCAP_BUFFER_SIZE {
+            METRICS.net.pcap_dropped_frames.inc();
+            return;
+        }
+
+        let now_us = get_time_us(ClockType::Real);
+        self.buf
+            .extend_from_slice(&((now_us / 1_000_000) as u32).to_ne_bytes());
+        self.buf
+            .extend_from_slice(&((now_us % 1_000_000) as u32).to_ne_bytes());
+        self.buf.extend_from_slice(&(incl_len as u32).to_ne_bytes());
+        self.buf
+            .extend_from_slice(&(frame.len() as u32).to_ne_bytes());
+        self.buf.extend_from_slice(&frame[..incl_len]);
+    }
+
+    /// Writes out as much of the buffered capture as the file takes in a single write. The
+    /// rest is kept for the next flush.
+    pub fn flush(&mut self) {
+        if self.buf.is_empty() {
+            return;
+        }
+        match self.file.write(&self.buf) {
+            Ok(count) => {
+                self.buf.drain(..count);
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
+            Err(e) => {
+                error!("Failed to write the packet capture to {}: {}", self.path, e);
+                METRICS.net.pcap_write_fails.inc();
+            }
+        }
+    }
+
+    /// Provides the size of the capture which is not written out yet.
+    pub fn buffered_len(&self) -> usize {
+        self.buf.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::tempfile::TempFile;
+
+    fn read_u32(buf: &[u8], offset: usize) -> u32 {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&buf[offset..offset + 4]);
+        u32::from_ne_bytes(bytes)
+    }
+
+    #[test]
+    fn test_pcap_writer() {
+        let tmp_file = TempFile::new().unwrap();
+        let path = tmp_file.as_path().to_str().unwrap().to_string();
+        let mut writer = PcapWriter::new(path.clone()).unwrap();
+        assert_eq!(writer.path(), path);
+        assert_eq!(writer.buffered_len(), PCAP_HEADER_LEN);
+
+        let frame = [0xabu8; 60];
+        writer.capture(&frame);
+        assert_eq!(
+            writer.buffered_len(),
+            PCAP_HEADER_LEN + PCAP_RECORD_HEADER_LEN + frame.len()
+        );
+        // Nothing is written before the capture is flushed.
+        assert!(std::fs::read(&path).unwrap().is_empty());
+
+        writer.flush();
+        assert_eq!(writer.buffered_len(), 0);
+        let content = std::fs::read(&path).unwrap();
+        assert_eq!(
+            content.len(),
+            PCAP_HEADER_LEN + PCAP_RECORD_HEADER_LEN + frame.len()
+        );
+        assert_eq!(read_u32(&content, 0), PCAP_MAGIC);
+        assert_eq!(read_u32(&content, 16), PCAP_SNAPLEN);
+        assert_eq!(read_u32(&content, 20), PCAP_LINKTYPE_ETHERNET);
+        // The captured and the original length of the frame.
+        assert_eq!(read_u32(&content, PCAP_HEADER_LEN + 8), frame.len() as u32);
+        assert_eq!(read_u32(&content, PCAP_HEADER_LEN + 12), frame.len() as u32);
+        assert_eq!(
+            &content[PCAP_HEADER_LEN + PCAP_RECORD_HEADER_LEN..],
+            &frame[..]
+        );
+    }
+
+    #[test]
+    fn test_pcap_writer_truncates_frames() {
+        let tmp_file = TempFile::new().unwrap();
+        let path = tmp_file.as_path().to_str().unwrap().to_string();
+        let mut writer = PcapWriter::new(path.clone()).unwrap();
+
+        let frame = vec![0u8; PCAP_SNAPLEN as usize + 10];
+        writer.capture(&frame);
+        writer.flush();
+        let content = std::fs::read(&path).unwrap();
+        assert_eq!(read_u32(&content, PCAP_HEADER_LEN + 8), PCAP_SNAPLEN);
+        assert_eq!(read_u32(&content, PCAP_HEADER_LEN + 12), frame.len() as u32);
+        assert_eq!(
+            content.len(),
+            PCAP_HEADER_LEN + PCAP_RECORD_HEADER_LEN + PCAP_SNAPLEN as usize
+        );
+    }
+
+    #[test]
+    fn test_pcap_writer_drops_frames() {
+        let tmp_file = TempFile::new().unwrap();
+        let path = tmp_file.as_path().to_str().unwrap().to_string();
+        let mut writer = PcapWriter::new(path).unwrap();
+
+        // Fill up the buffer without flushing it, as if the writer fell behind.
+        let frame = vec![0u8; PCAP_SNAPLEN as usize];
+        let dropped = METRICS.net.pcap_dropped_frames.count();
+        let fitting = (PCAP_BUFFER_SIZE - PCAP_HEADER_LEN) / (PCAP_RECORD_HEADER_LEN + frame.len());
+        for _ in 0..fitting {
+            writer.capture(&frame);
+        }
+        assert_eq!(METRICS.net.pcap_dropped_frames.count(), dropped);
+
+        let buffered = writer.buffered_len();
+        writer.capture(&frame);
+        assert_eq!(writer.buffered_len(), buffered);
+        assert_eq!(METRICS.net.pcap_dropped_frames.count(), dropped + 1);
+
+        // Once the buffer is written out, frames are captured again.
+        writer.flush();
+        writer.capture(&frame);
+        assert_eq!(writer.buffered_len(), PCAP_RECORD_HEADER_LEN + frame.len());
+    }
+}
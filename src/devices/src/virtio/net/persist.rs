@@ -7,15 +7,16 @@ use std::io;
 use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
 
+use logger::warn;
 use mmds::{ns::MmdsNetworkStack, persist::MmdsNetworkStackState};
 use rate_limiter::{persist::RateLimiterState, RateLimiter};
 use snapshot::Persist;
 use utils::net::mac::{MacAddr, MAC_ADDR_LEN};
-use versionize::{VersionMap, Versionize, VersionizeResult};
+use versionize::{VersionMap, Versionize, VersionizeError, VersionizeResult};
 use versionize_derive::Versionize;
 use vm_memory::GuestMemoryMmap;
 
-use super::device::{ConfigSpace, Net};
+use super::device::{Net, OFFLOAD_FEATURES};
 use super::{NUM_QUEUES, QUEUE_SIZE};
 
 use crate::virtio::persist::{Error as VirtioStateError, VirtioDeviceState};
@@ -25,6 +26,20 @@ use crate::virtio::{DeviceState, TYPE_NET};
 // NOTICE: Any changes to this structure require a snapshot version bump.
 pub struct NetConfigSpaceState {
     guest_mac: [u8; MAC_ADDR_LEN],
+    #[version(start = 2, ser_fn = "mtu_ser")]
+    mtu: u16,
+}
+
+impl NetConfigSpaceState {
+    fn mtu_ser(&mut self, target_version: u16) -> VersionizeResult<()> {
+        if target_version < 2 && self.mtu != 0 {
+            return Err(VersionizeError::Semantic(
+                "Target version does not support setting the MTU of net devices.".to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Clone, Versionize)]
@@ -37,6 +52,32 @@ pub struct NetState {
     mmds_ns: Option<MmdsNetworkStackState>,
     config_space: NetConfigSpaceState,
     virtio_state: VirtioDeviceState,
+    #[version(
+        start = 2,
+        default_fn = "default_rx_deferred_frame",
+        ser_fn = "rx_deferred_frame_ser"
+    )]
+    rx_deferred_frame: Option<Vec<u8>>,
+    #[version(start = 2, default_fn = "default_interrupt_coalescing_us")]
+    interrupt_coalescing_us: u64,
+}
+
+impl NetState {
+    fn default_rx_deferred_frame(_source_version: u16) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn rx_deferred_frame_ser(&mut self, _target_version: u16) -> VersionizeResult<()> {
+        if self.rx_deferred_frame.is_some() {
+            warn!("Target version does not support saving pending RX frames. The frame is lost.");
+        }
+
+        Ok(())
+    }
+
+    fn default_interrupt_coalescing_us(_source_version: u16) -> u64 {
+        0
+    }
 }
 
 pub struct NetConstructorArgs {
@@ -47,6 +88,7 @@ pub struct NetConstructorArgs {
 pub enum Error {
     CreateNet(super::Error),
     CreateRateLimiter(io::Error),
+    InvalidRxDeferredFrame,
     VirtioState(VirtioStateError),
 }
 
@@ -64,8 +106,11 @@ impl Persist<'_> for Net {
             mmds_ns: self.mmds_ns.as_ref().map(|mmds| mmds.save()),
             config_space: NetConfigSpaceState {
                 guest_mac: self.config_space.guest_mac,
+                mtu: self.mtu().unwrap_or(0),
             },
             virtio_state: VirtioDeviceState::from_device(self),
+            rx_deferred_frame: self.deferred_rx_frame().map(|frame| frame.to_vec()),
+            interrupt_coalescing_us: self.interrupt_coalescing_us(),
         }
     }
 
@@ -85,6 +130,8 @@ impl Persist<'_> for Net {
             rx_rate_limiter,
             tx_rate_limiter,
             state.mmds_ns.is_some(),
+            state.virtio_state.avail_features & OFFLOAD_FEATURES != 0,
+            Some(state.config_space.mtu).filter(|&mtu| mtu != 0),
         )
         .map_err(Error::CreateNet)?;
 
@@ -101,15 +148,24 @@ impl Persist<'_> for Net {
         net.interrupt_status = Arc::new(AtomicUsize::new(state.virtio_state.interrupt_status));
         net.avail_features = state.virtio_state.avail_features;
         net.acked_features = state.virtio_state.acked_features;
-        net.config_space = ConfigSpace {
-            guest_mac: state.config_space.guest_mac,
-        };
+        net.config_space.guest_mac = state.config_space.guest_mac;
 
         net.guest_mac = Some(MacAddr::from_bytes_unchecked(
             &state.config_space.guest_mac[..MAC_ADDR_LEN],
         ));
 
+        if let Some(frame) = state.rx_deferred_frame.as_ref() {
+            if frame.len() > net.rx_frame_buf.len() {
+                return Err(Error::InvalidRxDeferredFrame);
+            }
+            net.rx_frame_buf[..frame.len()].copy_from_slice(frame);
+            net.rx_bytes_read = frame.len();
+            net.rx_deferred_frame = true;
+        }
+        net.set_interrupt_coalescing_us(state.interrupt_coalescing_us);
+
         if state.virtio_state.activated {
+            net.apply_offload_features().map_err(Error::CreateNet)?;
             net.device_state = DeviceState::Activated(constructor_args.mem);
         }
 
@@ -134,6 +190,7 @@ mod tests {
         let id;
         let tap_if_name;
         let allow_mmds_requests;
+        let offload_enabled;
         let virtio_state;
 
         // Create and save the net device.
@@ -148,6 +205,7 @@ mod tests {
             id = net.id.clone();
             tap_if_name = net.iface_name();
             allow_mmds_requests = net.mmds_ns.is_some();
+            offload_enabled = net.offload_enabled();
             virtio_state = VirtioDeviceState::from_device(&net);
         }
 
@@ -173,8 +231,152 @@ mod tests {
             assert_eq!(&restored_net.id, &id);
             assert_eq!(&restored_net.iface_name(), &tap_if_name);
             assert_eq!(restored_net.mmds_ns.is_some(), allow_mmds_requests);
+            assert_eq!(restored_net.offload_enabled(), offload_enabled);
             assert_eq!(restored_net.rx_rate_limiter, RateLimiter::default());
             assert_eq!(restored_net.tx_rate_limiter, RateLimiter::default());
+            assert!(restored_net.deferred_rx_frame().is_none());
         }
     }
+
+    #[test]
+    fn test_persist_rx_deferred_frame() {
+        let mut version_map = VersionMap::new();
+        version_map
+            .new_version()
+            .set_type_version(NetState::type_id(), 2);
+
+        let frame = [1u8, 2, 3, 4];
+        let mut mem_v1 = vec![0; 4096];
+        let mut mem_v2 = vec![0; 4096];
+
+        // Create and save a net device with a pending frame.
+        {
+            let mut net = default_net();
+            net.rx_frame_buf[..frame.len()].copy_from_slice(&frame);
+            net.rx_bytes_read = frame.len();
+            net.rx_deferred_frame = true;
+
+            let state = <Net as Persist>::save(&net);
+            state
+                .serialize(&mut mem_v1.as_mut_slice(), &version_map, 1)
+                .unwrap();
+            state
+                .serialize(&mut mem_v2.as_mut_slice(), &version_map, 2)
+                .unwrap();
+        }
+
+        // The pending frame is saved starting with version 2.
+        {
+            let restored_net = Net::restore(
+                NetConstructorArgs {
+                    mem: default_guest_memory(),
+                },
+                &NetState::deserialize(&mut mem_v2.as_slice(), &version_map, 2).unwrap(),
+            )
+            .unwrap();
+            assert_eq!(restored_net.deferred_rx_frame(), Some(&frame[..]));
+        }
+
+        // Older versions drop it.
+        let restored_net = Net::restore(
+            NetConstructorArgs {
+                mem: default_guest_memory(),
+            },
+            &NetState::deserialize(&mut mem_v1.as_slice(), &version_map, 1).unwrap(),
+        )
+        .unwrap();
+        assert!(restored_net.deferred_rx_frame().is_none());
+    }
+
+    #[test]
+    fn test_persist_mtu() {
+        let mut version_map = VersionMap::new();
+        version_map
+            .new_version()
+            .set_type_version(NetState::type_id(), 2)
+            .set_type_version(NetConfigSpaceState::type_id(), 2);
+
+        let mut mem = vec![0; 4096];
+
+        // Create and save a net device with a configured MTU.
+        {
+            let net = Net::new_with_tap(
+                "net-persist-mtu".to_string(),
+                "net-persist-mtu".to_string(),
+                None,
+                RateLimiter::default(),
+                RateLimiter::default(),
+                false,
+                true,
+                Some(9000),
+            )
+            .unwrap();
+
+            let state = <Net as Persist>::save(&net);
+            state
+                .serialize(&mut mem.as_mut_slice(), &version_map, 2)
+                .unwrap();
+            // Older versions can't save the MTU.
+            assert!(state
+                .serialize(&mut vec![0; 4096].as_mut_slice(), &version_map, 1)
+                .is_err());
+        }
+
+        let restored_net = Net::restore(
+            NetConstructorArgs {
+                mem: default_guest_memory(),
+            },
+            &NetState::deserialize(&mut mem.as_slice(), &version_map, 2).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(restored_net.mtu(), Some(9000));
+        assert_eq!(restored_net.tap.mtu().unwrap(), 9000);
+    }
+
+    #[test]
+    fn test_persist_interrupt_coalescing() {
+        let mut version_map = VersionMap::new();
+        version_map
+            .new_version()
+            .set_type_version(NetState::type_id(), 2);
+
+        let mut mem_v1 = vec![0; 4096];
+        let mut mem_v2 = vec![0; 4096];
+
+        // Create and save a net device which coalesces its interrupts.
+        {
+            let mut net = default_net();
+            net.set_interrupt_coalescing_us(1000);
+
+            let state = <Net as Persist>::save(&net);
+            state
+                .serialize(&mut mem_v1.as_mut_slice(), &version_map, 1)
+                .unwrap();
+            state
+                .serialize(&mut mem_v2.as_mut_slice(), &version_map, 2)
+                .unwrap();
+        }
+
+        // The coalescing interval is saved starting with version 2.
+        {
+            let restored_net = Net::restore(
+                NetConstructorArgs {
+                    mem: default_guest_memory(),
+                },
+                &NetState::deserialize(&mut mem_v2.as_slice(), &version_map, 2).unwrap(),
+            )
+            .unwrap();
+            assert_eq!(restored_net.interrupt_coalescing_us(), 1000);
+        }
+
+        // Older versions raise every interrupt right away.
+        let restored_net = Net::restore(
+            NetConstructorArgs {
+                mem: default_guest_memory(),
+            },
+            &NetState::deserialize(&mut mem_v1.as_slice(), &version_map, 1).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(restored_net.interrupt_coalescing_us(), 0);
+    }
 }
@@ -65,6 +65,18 @@ fn build_terminated_if_name(if_name: &str) -> Result<[u8; IFACE_NAME_MAX_LEN]> {
     Ok(terminated_if_name)
 }
 
+// Creates a socket for the ioctls which configure a network interface.
+fn create_inet_socket() -> Result<File> {
+    // This is safe since we check the return value.
+    let socket = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if socket < 0 {
+        return Err(Error::IoctlError(IoError::last_os_error()));
+    }
+
+    // This is safe; nothing else will use or hold onto the raw socket fd.
+    Ok(unsafe { File::from_raw_fd(socket) })
+}
+
 pub struct IfReqBuilder(ifreq);
 
 impl IfReqBuilder {
@@ -88,6 +100,14 @@ impl IfReqBuilder {
         self
     }
 
+    pub(crate) fn mtu(mut self, mtu: i32) -> Self {
+        // Since we don't call as_mut on the same union field more than once, this block is safe.
+        let ifru_mtu = unsafe { self.0.ifr_ifru.ifru_mtu.as_mut() };
+        *ifru_mtu = mtu;
+
+        self
+    }
+
     pub(crate) fn execute<F: AsRawFd>(mut self, socket: &F, ioctl: u64) -> Result<ifreq> {
         // ioctl is safe. Called with a valid socket fd, and we check the return.
         let ret = unsafe { ioctl_with_mut_ref(socket, ioctl, &mut self.0) };
@@ -153,6 +173,31 @@ impl Tap {
         Ok(())
     }
 
+    /// Set the MTU of the tap interface.
+    pub fn set_mtu(&self, mtu: u16) -> Result<()> {
+        // The MTU of an interface is set through a socket, not through the tap fd.
+        let socket = create_inet_socket()?;
+
+        IfReqBuilder::new()
+            .if_name(&self.if_name)
+            .mtu(i32::from(mtu))
+            .execute(&socket, c_ulong::from(net_gen::sockios::SIOCSIFMTU))?;
+
+        Ok(())
+    }
+
+    /// Get the MTU of the tap interface.
+    pub fn mtu(&self) -> Result<u16> {
+        let socket = create_inet_socket()?;
+
+        let ifreq = IfReqBuilder::new()
+            .if_name(&self.if_name)
+            .execute(&socket, c_ulong::from(net_gen::sockios::SIOCGIFMTU))?;
+
+        // Safe since the ioctl filled in the MTU.
+        Ok(unsafe { *ifreq.ifr_ifru.ifru_mtu.as_ref() } as u16)
+    }
+
     /// Set the size of the vnet hdr.
     pub fn set_vnet_hdr_size(&self, size: c_int) -> Result<()> {
         // ioctl is safe. Called with a valid tap fd, and we check the return.
@@ -243,6 +288,8 @@ pub mod tests {
         let tap = Tap::open_named("").unwrap();
         tap.set_vnet_hdr_size(16).unwrap();
         tap.set_offload(0).unwrap();
+        tap.set_mtu(9000).unwrap();
+        assert_eq!(tap.mtu().unwrap(), 9000);
 
         let faulty_tap = Tap {
             tap_file: unsafe { File::from_raw_fd(-2) },
@@ -250,6 +297,7 @@ pub mod tests {
         };
         assert!(faulty_tap.set_vnet_hdr_size(16).is_err());
         assert!(faulty_tap.set_offload(0).is_err());
+        assert!(faulty_tap.set_mtu(9000).is_err());
     }
 
     #[test]
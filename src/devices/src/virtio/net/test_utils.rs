@@ -39,6 +39,8 @@ pub fn default_net() -> Net {
         RateLimiter::default(),
         RateLimiter::default(),
         true,
+        true,
+        None,
     )
     .unwrap();
     enable(&net.tap);
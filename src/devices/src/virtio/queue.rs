@@ -351,6 +351,20 @@ impl Queue {
         mem: &GuestMemoryMmap,
         desc_index: u16,
         len: u32,
+    ) -> Result<(), QueueError> {
+        self.write_used_elem(mem, 0, desc_index, len)?;
+        self.advance_used(mem, 1)
+    }
+
+    /// Puts an available descriptor head into the used ring, `offset` entries past the next
+    /// used one, without making it visible to the guest. This lets the device publish several
+    /// descriptor chains at once, through `advance_used()`.
+    pub fn write_used_elem(
+        &self,
+        mem: &GuestMemoryMmap,
+        offset: u16,
+        desc_index: u16,
+        len: u32,
     ) -> Result<(), QueueError> {
         if desc_index >= self.actual_size() {
             error!(
@@ -360,23 +374,26 @@ impl Queue {
             return Err(QueueError::DescIndexOutOfBounds(desc_index));
         }
 
-        let used_ring = self.used_ring;
-        let next_used = u64::from(self.next_used.0 % self.actual_size());
-        let used_elem = used_ring.unchecked_add(4 + next_used * 8);
+        let next_used = u64::from((self.next_used + Wrapping(offset)).0 % self.actual_size());
+        let used_elem = self.used_ring.unchecked_add(4 + next_used * 8);
 
         mem.write_obj(u32::from(desc_index), used_elem)
             .map_err(QueueError::UsedRing)?;
 
         let len_addr = used_elem.unchecked_add(4);
         mem.write_obj(len as u32, len_addr)
-            .map_err(QueueError::UsedRing)?;
+            .map_err(QueueError::UsedRing)
+    }
 
-        self.next_used += Wrapping(1);
+    /// Makes the next `count` entries of the used ring, written by `write_used_elem()`, visible
+    /// to the guest.
+    pub fn advance_used(&mut self, mem: &GuestMemoryMmap, count: u16) -> Result<(), QueueError> {
+        self.next_used += Wrapping(count);
 
         // This fence ensures all descriptor writes are visible before the index update is.
         fence(Ordering::Release);
 
-        let next_used_addr = used_ring.unchecked_add(2);
+        let next_used_addr = self.used_ring.unchecked_add(2);
         mem.write_obj(self.next_used.0 as u16, next_used_addr)
             .map_err(QueueError::UsedRing)
     }
@@ -635,6 +652,37 @@ pub(crate) mod tests {
         }
     }
 
+    #[test]
+    fn test_write_used_elem() {
+        let m = &GuestMemoryMmap::from_ranges(&[(GuestAddress(0), 0x10000)]).unwrap();
+        let vq = VirtQueue::new(GuestAddress(0), m, 16);
+
+        let mut q = vq.create_queue();
+        q.add_used(m, 1, 0x100).unwrap();
+
+        // The entries are written after the next used one, but the guest doesn't see them yet.
+        q.write_used_elem(m, 1, 3, 0x300).unwrap();
+        q.write_used_elem(m, 0, 2, 0x200).unwrap();
+        assert_eq!(vq.used.idx.get(), 1);
+        match q.write_used_elem(m, 2, 16, 0) {
+            Err(DescIndexOutOfBounds(16)) => (),
+            _ => unreachable!(),
+        }
+
+        q.advance_used(m, 2).unwrap();
+        assert_eq!(vq.used.idx.get(), 3);
+        let x = vq.used.ring[1].get();
+        assert_eq!((x.id, x.len), (2, 0x200));
+        let x = vq.used.ring[2].get();
+        assert_eq!((x.id, x.len), (3, 0x300));
+
+        // The offset wraps around the end of the ring.
+        q.next_used = Wrapping(15);
+        q.write_used_elem(m, 1, 4, 0x400).unwrap();
+        let x = vq.used.ring[0].get();
+        assert_eq!((x.id, x.len), (4, 0x400));
+    }
+
     #[test]
     fn test_queue_error_display() {
         let err = UsedRing(GuestMemoryError::InvalidGuestAddress(GuestAddress(0)));
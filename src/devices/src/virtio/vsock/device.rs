@@ -23,7 +23,7 @@ use std::result;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use logger::{debug, error, warn, IncMetric, METRICS};
+use logger::{debug, error, warn, IncMetric, SharedIncMetric, METRICS};
 use utils::byte_order;
 use utils::eventfd::EventFd;
 use vm_memory::{Bytes, GuestMemoryMmap};
@@ -58,6 +58,7 @@ pub struct Vsock<B> {
     pub(crate) avail_features: u64,
     pub(crate) acked_features: u64,
     pub(crate) interrupt_status: Arc<AtomicUsize>,
+    pub(crate) irq_counter: Arc<SharedIncMetric>,
     pub(crate) interrupt_evt: EventFd,
     // This EventFd is the only one initially registered for a vsock device, and is used to convert
     // a VirtioDevice::activate call into an EventHandler read event which allows the other events
@@ -91,6 +92,7 @@ where
             avail_features: AVAIL_FEATURES,
             acked_features: 0,
             interrupt_status: Arc::new(AtomicUsize::new(0)),
+            irq_counter: Arc::default(),
             interrupt_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(VsockError::EventFd)?,
             activate_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(VsockError::EventFd)?,
             device_state: DeviceState::Inactive,
@@ -127,7 +129,9 @@ where
         self.interrupt_evt.write(1).map_err(|e| {
             error!("Failed to signal used queue: {:?}", e);
             DeviceError::FailedSignalingUsedQueue(e)
-        })
+        })?;
+        self.irq_counter.inc();
+        Ok(())
     }
 
     /// Walk the driver-provided RX queue buffers and attempt to fill them up with any data that we
@@ -295,6 +299,14 @@ where
         self.interrupt_status.clone()
     }
 
+    fn irq_counter(&self) -> &SharedIncMetric {
+        &self.irq_counter
+    }
+
+    fn set_irq_counter(&mut self, irq_counter: Arc<SharedIncMetric>) {
+        self.irq_counter = irq_counter;
+    }
+
     fn read_config(&self, offset: u64, data: &mut [u8]) {
         match offset {
             0 if data.len() == 8 => byte_order::write_le_u64(data, self.cid()),
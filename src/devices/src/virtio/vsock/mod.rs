@@ -19,7 +19,7 @@ use crate::virtio::persist::Error as VirtioStateError;
 
 pub use self::defs::uapi::VIRTIO_ID_VSOCK as TYPE_VSOCK;
 pub use self::device::Vsock;
-pub use self::unix::{Error as VsockUnixBackendError, VsockUnixBackend};
+pub use self::unix::{Error as VsockUnixBackendError, VsockPeerAllowlist, VsockUnixBackend};
 
 use utils::epoll::EventSet;
 use vm_memory::{GuestMemoryError, GuestMemoryMmap};
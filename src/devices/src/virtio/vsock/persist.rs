@@ -43,6 +43,47 @@ pub enum VsockBackendState {
 pub struct VsockUdsState {
     /// The path for the UDS socket.
     pub(crate) path: String,
+    /// The processes allowed to initiate connections from the host.
+    #[version(start = 2, ser_fn = "peer_allowlist_ser")]
+    pub(crate) peer_allowlist: Option<VsockPeerAllowlistState>,
+}
+
+impl VsockUdsState {
+    fn peer_allowlist_ser(&mut self, target_version: u16) -> VersionizeResult<()> {
+        if target_version < 2 && self.peer_allowlist.is_some() {
+            return Err(VersionizeError::Semantic(
+                "Target version does not support restricting the vsock peers.".to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// The serializable state of a `VsockPeerAllowlist`.
+#[derive(Clone, Versionize)]
+// NOTICE: Any changes to this structure require a snapshot version bump.
+pub struct VsockPeerAllowlistState {
+    uids: Vec<u32>,
+    gids: Vec<u32>,
+}
+
+impl From<&VsockPeerAllowlist> for VsockPeerAllowlistState {
+    fn from(allowlist: &VsockPeerAllowlist) -> Self {
+        VsockPeerAllowlistState {
+            uids: allowlist.uids.clone(),
+            gids: allowlist.gids.clone(),
+        }
+    }
+}
+
+impl From<&VsockPeerAllowlistState> for VsockPeerAllowlist {
+    fn from(state: &VsockPeerAllowlistState) -> Self {
+        VsockPeerAllowlist {
+            uids: state.uids.clone(),
+            gids: state.gids.clone(),
+        }
+    }
 }
 
 /// A helper structure that holds the constructor arguments for VsockUnixBackend
@@ -65,6 +106,10 @@ impl Persist<'_> for VsockUnixBackend {
     fn save(&self) -> Self::State {
         VsockBackendState::Uds(VsockUdsState {
             path: self.host_sock_path.clone(),
+            peer_allowlist: self
+                .peer_allowlist
+                .as_ref()
+                .map(VsockPeerAllowlistState::from),
         })
     }
 
@@ -76,6 +121,10 @@ impl Persist<'_> for VsockUnixBackend {
             VsockBackendState::Uds(uds_state) => Ok(VsockUnixBackend::new(
                 constructor_args.cid,
                 uds_state.path.clone(),
+                uds_state
+                    .peer_allowlist
+                    .as_ref()
+                    .map(VsockPeerAllowlist::from),
             )?),
         }
     }
@@ -141,6 +190,10 @@ pub(crate) mod tests {
         fn save(&self) -> Self::State {
             VsockBackendState::Uds(VsockUdsState {
                 path: "test".to_owned(),
+                peer_allowlist: Some(VsockPeerAllowlistState {
+                    uids: vec![1000],
+                    gids: vec![],
+                }),
             })
         }
 
@@ -189,6 +242,16 @@ pub(crate) mod tests {
                 backend: match restored_state.backend {
                     VsockBackendState::Uds(uds_state) => {
                         assert_eq!(uds_state.path, "test".to_owned());
+                        assert_eq!(
+                            uds_state
+                                .peer_allowlist
+                                .as_ref()
+                                .map(VsockPeerAllowlist::from),
+                            Some(VsockPeerAllowlist {
+                                uids: vec![1000],
+                                gids: vec![],
+                            })
+                        );
                         TestBackend::new()
                     }
                 },
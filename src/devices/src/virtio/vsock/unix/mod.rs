@@ -13,6 +13,8 @@ mod muxer_rxq;
 
 pub use muxer::VsockMuxer as VsockUnixBackend;
 
+use serde::{Deserialize, Serialize};
+
 mod defs {
     /// Maximum number of established connections that we can handle.
     pub const MAX_CONNECTIONS: usize = 1023;
@@ -40,9 +42,34 @@ pub enum Error {
     UnixConnect(std::io::Error),
     /// Error reading from host-side Unix socket.
     UnixRead(std::io::Error),
+    /// Error reading the credentials of the peer of a host-side Unix socket.
+    UnixPeerCredentials(std::io::Error),
+    /// The peer of a host-initiated connection is not in the allowlist.
+    PeerNotAllowed { uid: u32, gid: u32 },
     /// Muxer connection limit reached.
     TooManyConnections,
 }
 
+/// The host-side processes which are allowed to initiate connections to the guest,
+/// identified by the credentials of their Unix socket (`SO_PEERCRED`). A process is
+/// allowed if either its uid or its gid is listed.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct VsockPeerAllowlist {
+    /// The allowed user IDs.
+    #[serde(default)]
+    pub uids: Vec<u32>,
+    /// The allowed group IDs.
+    #[serde(default)]
+    pub gids: Vec<u32>,
+}
+
+impl VsockPeerAllowlist {
+    /// Checks whether a peer with the given credentials is allowed.
+    pub fn allows(&self, uid: u32, gid: u32) -> bool {
+        self.uids.contains(&uid) || self.gids.contains(&gid)
+    }
+}
+
 type Result<T> = std::result::Result<T, Error>;
 type MuxerConnection = super::csm::VsockConnection<std::os::unix::net::UnixStream>;
@@ -31,7 +31,7 @@
 ///    To route all these events to their handlers, the muxer uses another `HashMap` object,
 ///    mapping `RawFd`s to `EpollListener`s.
 use std::collections::{HashMap, HashSet};
-use std::io::Read;
+use std::io::{self, Read};
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::os::unix::net::{UnixListener, UnixStream};
 
@@ -49,7 +49,7 @@ use super::defs;
 use super::muxer_killq::MuxerKillQ;
 use super::muxer_rxq::MuxerRxQ;
 use super::MuxerConnection;
-use super::{Error, Result};
+use super::{Error, Result, VsockPeerAllowlist};
 
 /// A unique identifier of a `MuxerConnection` object. Connections are stored in a hash map,
 /// keyed by a `ConnMapKey` object.
@@ -103,6 +103,9 @@ pub struct VsockMuxer {
     /// The file system path of the host-side Unix socket. This is used to figure out the path
     /// to Unix sockets listening on specific ports. I.e. "<this path>_<port number>".
     pub(crate) host_sock_path: String,
+    /// The processes allowed to initiate connections from the host. All of them are allowed
+    /// if there's no allowlist.
+    pub(crate) peer_allowlist: Option<VsockPeerAllowlist>,
     /// The nested epoll event set, used to register epoll listeners.
     epoll: Epoll,
     /// A hash set used to keep track of used host-side (local) ports, in order to assign local
@@ -307,7 +310,11 @@ impl VsockBackend for VsockMuxer {}
 
 impl VsockMuxer {
     /// Muxer constructor.
-    pub fn new(cid: u64, host_sock_path: String) -> Result<Self> {
+    pub fn new(
+        cid: u64,
+        host_sock_path: String,
+        peer_allowlist: Option<VsockPeerAllowlist>,
+    ) -> Result<Self> {
         // Open/bind on the host Unix socket, so we can accept host-initiated
         // connections.
         let host_sock = UnixListener::bind(&host_sock_path)
@@ -318,6 +325,7 @@ impl VsockMuxer {
             cid,
             host_sock,
             host_sock_path,
+            peer_allowlist,
             epoll: Epoll::new().map_err(Error::EpollFdCreate)?,
             rxq: MuxerRxQ::new(),
             conn_map: HashMap::with_capacity(defs::MAX_CONNECTIONS),
@@ -371,6 +379,15 @@ impl VsockMuxer {
                             .map(|_| stream)
                             .map_err(Error::UnixAccept)
                     })
+                    .and_then(|stream| {
+                        Self::check_peer_credentials(&stream, self.peer_allowlist.as_ref())
+                            .map(|_| stream)
+                            .map_err(|err| {
+                                // The refused connection is closed when the stream is dropped.
+                                METRICS.vsock.conns_refused.inc();
+                                err
+                            })
+                    })
                     .and_then(|stream| {
                         // Before forwarding this connection to a listening AF_VSOCK socket on
                         // the guest side, we need to know the destination port. We'll read
@@ -420,6 +437,48 @@ impl VsockMuxer {
         }
     }
 
+    /// Check the credentials of the process which initiated a host-side connection against
+    /// the allowlist, if there is one.
+    fn check_peer_credentials(
+        stream: &UnixStream,
+        allowlist: Option<&VsockPeerAllowlist>,
+    ) -> Result<()> {
+        let allowlist = match allowlist {
+            Some(allowlist) => allowlist,
+            None => return Ok(()),
+        };
+
+        let mut cred = libc::ucred {
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        };
+        let mut cred_len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        // Safe because the kernel only writes up to `cred_len` bytes to `cred`, and we check
+        // the return value.
+        let ret = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut cred as *mut libc::ucred as *mut libc::c_void,
+                &mut cred_len,
+            )
+        };
+        if ret < 0 {
+            return Err(Error::UnixPeerCredentials(io::Error::last_os_error()));
+        }
+
+        if allowlist.allows(cred.uid, cred.gid) {
+            Ok(())
+        } else {
+            Err(Error::PeerNotAllowed {
+                uid: cred.uid,
+                gid: cred.gid,
+            })
+        }
+    }
+
     /// Parse a host "connect" command, and extract the destination vsock port.
     fn read_local_stream_port(stream: &mut UnixStream) -> Result<u32> {
         let mut buf = [0u8; 32];
@@ -818,7 +877,7 @@ mod tests {
             )
             .unwrap();
 
-            let muxer = VsockMuxer::new(PEER_CID, get_file(name)).unwrap();
+            let muxer = VsockMuxer::new(PEER_CID, get_file(name), None).unwrap();
             Self {
                 _vsock_test_ctx: vsock_test_ctx,
                 pkt,
@@ -1526,4 +1585,40 @@ mod tests {
         // Check that the connection was removed.
         assert_eq!(METRICS.vsock.conns_removed.count(), conns_removed + 1);
     }
+
+    #[test]
+    fn test_peer_allowlist() {
+        let peer_port = 1025;
+        let mut ctx = MuxerTestContext::new("peer_allowlist");
+
+        // The connections of an allowed process are accepted.
+        let uid = unsafe { libc::getuid() };
+        ctx.muxer.peer_allowlist = Some(VsockPeerAllowlist {
+            uids: vec![uid],
+            gids: vec![],
+        });
+        ctx.local_connect(peer_port);
+
+        let gid = unsafe { libc::getgid() };
+        ctx.muxer.peer_allowlist = Some(VsockPeerAllowlist {
+            uids: vec![],
+            gids: vec![gid],
+        });
+        ctx.local_connect(peer_port);
+
+        // An empty allowlist refuses all the connections.
+        ctx.muxer.peer_allowlist = Some(VsockPeerAllowlist::default());
+        let (init_local_lsn_count, init_conn_lsn_count) = ctx.count_epoll_listeners();
+        let conns_refused = METRICS.vsock.conns_refused.count();
+        let mut stream = UnixStream::connect(ctx.muxer.host_sock_path.clone()).unwrap();
+        ctx.notify_muxer();
+        assert_eq!(METRICS.vsock.conns_refused.count(), conns_refused + 1);
+        assert_eq!(
+            ctx.count_epoll_listeners(),
+            (init_local_lsn_count, init_conn_lsn_count)
+        );
+        // The refused connection is closed.
+        let mut buf = [0u8; 1];
+        assert_eq!(stream.read(&mut buf).unwrap(), 0);
+    }
 }
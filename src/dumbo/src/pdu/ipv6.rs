@@ -0,0 +1,488 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Contains support for parsing and writing IPv6 packets.
+//!
+//! Only the fixed header is supported; packets carrying extension headers are handled as if the
+//! first extension header was the upper-layer payload. A picture of the IPv6 packet header can be
+//! found [here].
+//!
+//! [here]: https://en.wikipedia.org/wiki/IPv6_packet#Fixed_header
+
+use std::convert::From;
+use std::net::Ipv6Addr;
+use std::result::Result;
+
+use crate::pdu::bytes::{InnerBytes, NetworkBytes, NetworkBytesMut};
+use crate::pdu::ethernet;
+use crate::pdu::Incomplete;
+
+const VERSION_AND_TRAFFIC_CLASS_OFFSET: usize = 0;
+const PAYLOAD_LEN_OFFSET: usize = 4;
+const NEXT_HEADER_OFFSET: usize = 6;
+const HOP_LIMIT_OFFSET: usize = 7;
+const SOURCE_ADDRESS_OFFSET: usize = 8;
+const DESTINATION_ADDRESS_OFFSET: usize = 24;
+const ADDRESS_LEN: usize = 16;
+
+/// The length of the IPv6 fixed header.
+pub const HEADER_LEN: usize = 40;
+
+/// Indicates version 6 of the IP protocol
+pub const IPV6_VERSION: u8 = 0x06;
+/// Default hop limit value
+pub const DEFAULT_HOP_LIMIT: u8 = 64;
+
+/// The next header value associated with ICMPv6.
+pub const PROTOCOL_ICMPV6: u8 = 0x3a;
+
+/// Describes the errors which may occur while handling IPv6 packets.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The payload length of the packet is invalid.
+    InvalidPayloadLen,
+    /// The length of the given slice does not match the length of the packet.
+    SliceExactLen,
+    /// The length of the given slice is less than the IPv6 header length.
+    SliceTooShort,
+    /// The version header field is invalid.
+    Version,
+}
+
+/// Interprets the inner bytes as an IPv6 packet.
+pub struct IPv6Packet<'a, T: 'a> {
+    bytes: InnerBytes<'a, T>,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl<'a, T: NetworkBytes> IPv6Packet<'a, T> {
+    /// Interpret `bytes` as an IPv6Packet without checking the validity of the header fields, and
+    /// the length of the inner byte sequence.
+    ///
+    /// # Panics
+    ///
+    /// This method does not panic, but further method calls on the resulting object may panic if
+    /// `bytes` contains invalid input.
+    #[inline]
+    pub fn from_bytes_unchecked(bytes: T) -> Self {
+        IPv6Packet {
+            bytes: InnerBytes::new(bytes),
+        }
+    }
+
+    /// Attempts to interpret `bytes` as an IPv6 packet, checking the validity of the header fields
+    /// and the length of the inner byte sequence.
+    pub fn from_bytes(bytes: T) -> Result<Self, Error> {
+        let bytes_len = bytes.len();
+
+        if bytes_len < HEADER_LEN {
+            return Err(Error::SliceTooShort);
+        }
+
+        let packet = IPv6Packet::from_bytes_unchecked(bytes);
+
+        if packet.version() != IPV6_VERSION {
+            return Err(Error::Version);
+        }
+
+        // Jumbograms (a payload length of 0 followed by a Jumbo Payload option) are not supported.
+        let payload_len = packet.payload_len() as usize;
+        if payload_len == 0 {
+            return Err(Error::InvalidPayloadLen);
+        }
+
+        if HEADER_LEN + payload_len != bytes_len {
+            return Err(Error::SliceExactLen);
+        }
+
+        Ok(packet)
+    }
+
+    /// Returns the value of the `version` header field.
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.bytes[VERSION_AND_TRAFFIC_CLASS_OFFSET] >> 4
+    }
+
+    /// Returns the value of the `payload length` header field.
+    #[inline]
+    pub fn payload_len(&self) -> u16 {
+        self.bytes.ntohs_unchecked(PAYLOAD_LEN_OFFSET)
+    }
+
+    /// Returns the value of the `next header` header field.
+    #[inline]
+    pub fn next_header(&self) -> u8 {
+        self.bytes[NEXT_HEADER_OFFSET]
+    }
+
+    /// Returns the value of the `hop limit` header field.
+    #[inline]
+    pub fn hop_limit(&self) -> u8 {
+        self.bytes[HOP_LIMIT_OFFSET]
+    }
+
+    /// Returns the source IPv6 address of the packet.
+    #[inline]
+    pub fn source_address(&self) -> Ipv6Addr {
+        read_address(&self.bytes, SOURCE_ADDRESS_OFFSET)
+    }
+
+    /// Returns the destination IPv6 address of the packet.
+    #[inline]
+    pub fn destination_address(&self) -> Ipv6Addr {
+        read_address(&self.bytes, DESTINATION_ADDRESS_OFFSET)
+    }
+
+    /// Returns a byte slice that contains the payload of the packet.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        self.bytes.split_at(HEADER_LEN).1
+    }
+
+    /// Returns the length of the inner byte sequence.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl<'a, T: NetworkBytesMut> IPv6Packet<'a, T> {
+    /// Attempts to write an IPv6 packet header to `buf`, making sure there is enough space.
+    ///
+    /// This method returns an incomplete packet, because the size of the payload might be unknown
+    /// at this point. The `traffic class` and `flow label` fields are set to 0. The `hop limit` is
+    /// set to a default value. The `payload length` field will be set when the length of the
+    /// incomplete packet is determined.
+    pub fn write_header(
+        buf: T,
+        next_header: u8,
+        src_addr: Ipv6Addr,
+        dst_addr: Ipv6Addr,
+    ) -> Result<Incomplete<Self>, Error> {
+        if buf.len() < HEADER_LEN {
+            return Err(Error::SliceTooShort);
+        }
+        let mut packet = IPv6Packet::from_bytes_unchecked(buf);
+        packet
+            .set_version_traffic_class_and_flow_label(IPV6_VERSION)
+            .set_next_header(next_header)
+            .set_hop_limit(DEFAULT_HOP_LIMIT)
+            .set_source_address(src_addr)
+            .set_destination_address(dst_addr);
+
+        Ok(Incomplete::new(packet))
+    }
+
+    /// Sets the value of the `version` header field, while zeroing the `traffic class` and
+    /// `flow label` fields.
+    #[inline]
+    pub fn set_version_traffic_class_and_flow_label(&mut self, version: u8) -> &mut Self {
+        self.bytes
+            .htonl_unchecked(VERSION_AND_TRAFFIC_CLASS_OFFSET, u32::from(version) << 28);
+        self
+    }
+
+    /// Sets the value of the `payload length` header field.
+    #[inline]
+    pub fn set_payload_len(&mut self, value: u16) -> &mut Self {
+        self.bytes.htons_unchecked(PAYLOAD_LEN_OFFSET, value);
+        self
+    }
+
+    /// Sets the value of the `next header` header field.
+    #[inline]
+    pub fn set_next_header(&mut self, value: u8) -> &mut Self {
+        self.bytes[NEXT_HEADER_OFFSET] = value;
+        self
+    }
+
+    /// Sets the value of the `hop limit` header field.
+    #[inline]
+    pub fn set_hop_limit(&mut self, value: u8) -> &mut Self {
+        self.bytes[HOP_LIMIT_OFFSET] = value;
+        self
+    }
+
+    /// Sets the source address of the packet.
+    #[inline]
+    pub fn set_source_address(&mut self, addr: Ipv6Addr) -> &mut Self {
+        self.bytes[SOURCE_ADDRESS_OFFSET..SOURCE_ADDRESS_OFFSET + ADDRESS_LEN]
+            .copy_from_slice(&addr.octets());
+        self
+    }
+
+    /// Sets the destination address of the packet.
+    #[inline]
+    pub fn set_destination_address(&mut self, addr: Ipv6Addr) -> &mut Self {
+        self.bytes[DESTINATION_ADDRESS_OFFSET..DESTINATION_ADDRESS_OFFSET + ADDRESS_LEN]
+            .copy_from_slice(&addr.octets());
+        self
+    }
+
+    /// Returns a mutable byte slice representing the payload of the packet.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        self.bytes.split_at_mut(HEADER_LEN).1
+    }
+}
+
+/// An incomplete packet is one where the payload length has not been determined yet.
+///
+/// It can be transformed into an `IPv6Packet` by specifying the size of the payload, and
+/// shrinking the inner byte sequence to be as large as the packet itself (this includes setting
+/// the `payload length` header field).
+impl<'a, T: NetworkBytesMut> Incomplete<IPv6Packet<'a, T>> {
+    /// Transforms `self` into an `IPv6Packet` based on the supplied payload length. May panic for
+    /// invalid values of the input parameters.
+    ///
+    /// # Panics
+    ///
+    /// This method may panic if the value of `payload_len` is invalid.
+    #[inline]
+    pub fn with_payload_len_unchecked(mut self, payload_len: usize) -> IPv6Packet<'a, T> {
+        {
+            let packet = &mut self.inner;
+            // This unchecked is fine as long as the total length is smaller than the length of
+            // the original slice, which should be the case if our code is not wrong.
+            packet.bytes.shrink_unchecked(HEADER_LEN + payload_len);
+            packet.set_payload_len(payload_len as u16);
+        }
+        self.inner
+    }
+}
+
+#[inline]
+fn read_address(bytes: &[u8], offset: usize) -> Ipv6Addr {
+    let mut octets = [0u8; ADDRESS_LEN];
+    octets.copy_from_slice(&bytes[offset..offset + ADDRESS_LEN]);
+    Ipv6Addr::from(octets)
+}
+
+/// Returns the solicited-node multicast address associated with `addr`, as defined by RFC 4291.
+#[inline]
+pub fn solicited_node_multicast_addr(addr: Ipv6Addr) -> Ipv6Addr {
+    let octets = addr.octets();
+    Ipv6Addr::from([
+        0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0xff, octets[13], octets[14], octets[15],
+    ])
+}
+
+/// Computes the checksum of an upper-layer packet (such as a TCP segment or an ICMPv6 message)
+/// carried over IPv6, which also covers the IPv6 pseudo-header described in RFC 8200.
+pub fn compute_upper_layer_checksum(
+    bytes: &[u8],
+    src_addr: Ipv6Addr,
+    dst_addr: Ipv6Addr,
+    next_header: u8,
+) -> u16 {
+    let mut sum = 0u32;
+
+    for segment in src_addr.segments().iter().chain(dst_addr.segments().iter()) {
+        sum += u32::from(*segment);
+    }
+
+    let len = bytes.len();
+    sum += (len as u32) >> 16;
+    sum += (len as u32) & 0xffff;
+    sum += u32::from(next_header);
+
+    for i in 0..len / 2 {
+        sum += u32::from(bytes.ntohs_unchecked(i * 2));
+    }
+
+    if len % 2 != 0 {
+        sum += u32::from(bytes[len - 1]) << 8;
+    }
+
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+/// This function checks if `buf` may hold an IPv6Packet heading towards the given address. Cannot
+/// produce false negatives.
+#[inline]
+pub fn test_speculative_dst_addr(buf: &[u8], addr: Ipv6Addr) -> bool {
+    // The unchecked methods are safe because we actually check the buffer length beforehand.
+    if buf.len() >= ethernet::PAYLOAD_OFFSET + HEADER_LEN {
+        let bytes = &buf[ethernet::PAYLOAD_OFFSET..];
+        if IPv6Packet::from_bytes_unchecked(bytes).destination_address() == addr {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt;
+
+    use crate::MacAddr;
+
+    use super::*;
+
+    impl<'a, T: NetworkBytes> fmt::Debug for IPv6Packet<'a, T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "(IPv6 packet)")
+        }
+    }
+
+    impl<'a, T: NetworkBytes> fmt::Debug for Incomplete<IPv6Packet<'a, T>> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "(Incomplete IPv6 packet)")
+        }
+    }
+
+    #[test]
+    fn test_set_get() {
+        let mut a = [0u8; 100];
+        let mut p = IPv6Packet::from_bytes_unchecked(a.as_mut());
+
+        assert_eq!(p.version(), 0);
+        p.set_version_traffic_class_and_flow_label(IPV6_VERSION);
+        assert_eq!(p.version(), IPV6_VERSION);
+
+        assert_eq!(p.payload_len(), 0);
+        p.set_payload_len(123);
+        assert_eq!(p.payload_len(), 123);
+
+        assert_eq!(p.next_header(), 0);
+        p.set_next_header(PROTOCOL_ICMPV6);
+        assert_eq!(p.next_header(), PROTOCOL_ICMPV6);
+
+        assert_eq!(p.hop_limit(), 0);
+        p.set_hop_limit(255);
+        assert_eq!(p.hop_limit(), 255);
+
+        let src = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 4);
+        let dst = Ipv6Addr::new(0xfe80, 0, 0, 0, 0xa9fe, 0xa9fe, 0xa9fe, 0xa9fe);
+
+        assert_eq!(p.source_address(), Ipv6Addr::UNSPECIFIED);
+        p.set_source_address(src);
+        assert_eq!(p.source_address(), src);
+
+        assert_eq!(p.destination_address(), Ipv6Addr::UNSPECIFIED);
+        p.set_destination_address(dst);
+        assert_eq!(p.destination_address(), dst);
+
+        assert_eq!(p.payload().len(), 100 - HEADER_LEN);
+        assert_eq!(p.payload_mut().len(), 100 - HEADER_LEN);
+        assert_eq!(p.len(), 100);
+    }
+
+    #[test]
+    fn test_constructors() {
+        let src = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 4);
+        let dst = Ipv6Addr::new(0xfe80, 0, 0, 0, 4, 3, 2, 1);
+        let mut buf = [0u8; 100];
+
+        // Not enough room for the header.
+        assert_eq!(
+            IPv6Packet::write_header(&mut buf[..HEADER_LEN - 1], PROTOCOL_ICMPV6, src, dst)
+                .unwrap_err(),
+            Error::SliceTooShort
+        );
+
+        let payload_len = 30;
+        let len = {
+            let mut p = IPv6Packet::write_header(buf.as_mut(), PROTOCOL_ICMPV6, src, dst)
+                .unwrap()
+                .with_payload_len_unchecked(payload_len);
+            assert_eq!(p.payload_mut().len(), payload_len);
+            p.len()
+        };
+        assert_eq!(len, HEADER_LEN + payload_len);
+
+        {
+            let p = IPv6Packet::from_bytes(&buf[..len]).unwrap();
+            assert_eq!(p.version(), IPV6_VERSION);
+            assert_eq!(p.payload_len() as usize, payload_len);
+            assert_eq!(p.next_header(), PROTOCOL_ICMPV6);
+            assert_eq!(p.hop_limit(), DEFAULT_HOP_LIMIT);
+            assert_eq!(p.source_address(), src);
+            assert_eq!(p.destination_address(), dst);
+        }
+
+        // Slice too short.
+        assert_eq!(
+            IPv6Packet::from_bytes(&buf[..HEADER_LEN - 1]).unwrap_err(),
+            Error::SliceTooShort
+        );
+
+        // Slice length does not match the payload length.
+        assert_eq!(
+            IPv6Packet::from_bytes(&buf[..len - 1]).unwrap_err(),
+            Error::SliceExactLen
+        );
+
+        // Invalid payload length.
+        IPv6Packet::from_bytes_unchecked(buf.as_mut()).set_payload_len(0);
+        assert_eq!(
+            IPv6Packet::from_bytes(&buf[..len]).unwrap_err(),
+            Error::InvalidPayloadLen
+        );
+
+        // Invalid version.
+        IPv6Packet::from_bytes_unchecked(buf.as_mut())
+            .set_version_traffic_class_and_flow_label(4)
+            .set_payload_len(payload_len as u16);
+        assert_eq!(
+            IPv6Packet::from_bytes(&buf[..len]).unwrap_err(),
+            Error::Version
+        );
+    }
+
+    #[test]
+    fn test_solicited_node_multicast_addr() {
+        let addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0x1234, 0x5678, 0x9abc, 0xdef0);
+        assert_eq!(
+            solicited_node_multicast_addr(addr),
+            Ipv6Addr::new(0xff02, 0, 0, 0, 0, 1, 0xffbc, 0xdef0)
+        );
+    }
+
+    #[test]
+    fn test_checksum() {
+        let src = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 4);
+        let dst = Ipv6Addr::new(0xfe80, 0, 0, 0, 4, 3, 2, 1);
+        let mut payload = [0u8; 11];
+        payload[0] = 0x88;
+        payload[10] = 0x55;
+
+        let checksum = compute_upper_layer_checksum(&payload, src, dst, PROTOCOL_ICMPV6);
+        // The checksum field of ICMPv6 messages lies at offset 2.
+        payload[2..4].copy_from_slice(&checksum.to_be_bytes());
+        // Verifying a packet that contains its own checksum yields 0.
+        assert_eq!(
+            compute_upper_layer_checksum(&payload, src, dst, PROTOCOL_ICMPV6),
+            0
+        );
+    }
+
+    #[test]
+    fn test_speculative() {
+        let mac = MacAddr::from_bytes_unchecked(&[0; 6]);
+        let mut buf = [0u8; 1000];
+        let mut eth =
+            crate::pdu::ethernet::EthernetFrame::write_incomplete(buf.as_mut(), mac, mac, 0)
+                .unwrap();
+        let mut packet = IPv6Packet::from_bytes_unchecked(eth.inner_mut().payload_mut());
+        let addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 4);
+        packet.set_destination_address(addr);
+
+        assert!(test_speculative_dst_addr(buf.as_ref(), addr));
+        assert!(!test_speculative_dst_addr(
+            buf.as_ref(),
+            Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 5)
+        ));
+
+        // The buffer is too short.
+        assert!(!test_speculative_dst_addr(
+            &buf[..ethernet::PAYLOAD_OFFSET + HEADER_LEN - 1],
+            addr
+        ));
+    }
+}
@@ -16,6 +16,8 @@ pub mod arp;
 pub mod bytes;
 pub mod ethernet;
 pub mod ipv4;
+pub mod ipv6;
+pub mod ndp;
 pub mod tcp;
 pub mod udp;
 
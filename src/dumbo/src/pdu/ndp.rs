@@ -0,0 +1,319 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Contains logic that helps with handling Neighbor Discovery messages, which are the IPv6
+//! counterpart of ARP frames. Only Neighbor Solicitation requests and Neighbor Advertisement
+//! replies are supported, which is what's needed for address resolution.
+//!
+//! A more detailed view of these ICMPv6 messages can be found in [RFC 4861].
+//!
+//! [RFC 4861]: https://tools.ietf.org/html/rfc4861#section-4.3
+use std::net::Ipv6Addr;
+use std::result::Result;
+
+use super::bytes::{InnerBytes, NetworkBytes, NetworkBytesMut};
+use super::ipv6::{compute_upper_layer_checksum, PROTOCOL_ICMPV6};
+
+use utils::net::mac::{MacAddr, MAC_ADDR_LEN};
+
+/// ICMPv6 message type of Neighbor Solicitation messages.
+pub const TYPE_NEIGHBOR_SOLICITATION: u8 = 135;
+
+/// ICMPv6 message type of Neighbor Advertisement messages.
+pub const TYPE_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+/// The hop limit of every Neighbor Discovery packet, which lets receivers know it wasn't forwarded
+/// by a router.
+pub const HOP_LIMIT: u8 = 255;
+
+/// The length of a Neighbor Advertisement which carries the target link-layer address option.
+pub const NEIGHBOR_ADVERTISEMENT_LEN: usize = 32;
+
+// Advertisement flags.
+const FLAG_SOLICITED: u32 = 0x4000_0000;
+const FLAG_OVERRIDE: u32 = 0x2000_0000;
+
+// The target link-layer address option carries a MAC address and spans 8 bytes in total.
+const OPTION_TARGET_LL_ADDR: u8 = 2;
+const OPTION_TARGET_LL_ADDR_LEN_UNITS: u8 = 1;
+
+const TYPE_OFFSET: usize = 0;
+const CODE_OFFSET: usize = 1;
+const CHECKSUM_OFFSET: usize = 2;
+const FLAGS_OFFSET: usize = 4;
+const TARGET_ADDRESS_OFFSET: usize = 8;
+const OPTIONS_OFFSET: usize = 24;
+
+const IPV6_ADDR_LEN: usize = 16;
+
+/// Represents errors which may occur while parsing or writing a message.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// Invalid ICMPv6 code.
+    Code,
+    /// Invalid ICMPv6 message type.
+    MessageType,
+    /// The provided slice does not fit the size of a message.
+    SliceExactLen,
+    /// The provided slice is shorter than the fixed part of a message.
+    SliceTooShort,
+}
+
+/// The inner bytes will be interpreted as a Neighbor Solicitation or Neighbor Advertisement
+/// ICMPv6 message.
+pub struct NeighborMessage<'a, T: 'a> {
+    bytes: InnerBytes<'a, T>,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl<'a, T: NetworkBytes> NeighborMessage<'a, T> {
+    /// Interprets the given bytes as a Neighbor Discovery message, without doing any validity
+    /// checks beforehand.
+    ///
+    ///  # Panics
+    ///
+    /// This method does not panic, but further method calls on the resulting object may panic if
+    /// `bytes` contains invalid input.
+    #[inline]
+    pub fn from_bytes_unchecked(bytes: T) -> Self {
+        NeighborMessage {
+            bytes: InnerBytes::new(bytes),
+        }
+    }
+
+    /// Tries to interpret a byte slice as a valid Neighbor Solicitation message.
+    ///
+    /// If no error occurs, it guarantees accessor methods (which make use of various `_unchecked`
+    /// functions) are safe to call on the result, because all predefined offsets will be valid.
+    /// The ICMPv6 checksum is not verified.
+    pub fn solicitation_from_bytes(bytes: T) -> Result<Self, Error> {
+        // Any options follow the fixed part of the message.
+        if bytes.len() < OPTIONS_OFFSET {
+            return Err(Error::SliceTooShort);
+        }
+
+        let maybe = NeighborMessage::from_bytes_unchecked(bytes);
+
+        if maybe.message_type() != TYPE_NEIGHBOR_SOLICITATION {
+            return Err(Error::MessageType);
+        }
+
+        if maybe.code() != 0 {
+            return Err(Error::Code);
+        }
+
+        Ok(maybe)
+    }
+
+    /// Returns the ICMPv6 message type.
+    #[inline]
+    pub fn message_type(&self) -> u8 {
+        self.bytes[TYPE_OFFSET]
+    }
+
+    /// Returns the ICMPv6 code.
+    #[inline]
+    pub fn code(&self) -> u8 {
+        self.bytes[CODE_OFFSET]
+    }
+
+    /// Returns the ICMPv6 checksum.
+    #[inline]
+    pub fn checksum(&self) -> u16 {
+        self.bytes.ntohs_unchecked(CHECKSUM_OFFSET)
+    }
+
+    /// Returns the flags of the message (only meaningful for advertisements).
+    #[inline]
+    pub fn flags(&self) -> u32 {
+        self.bytes.ntohl_unchecked(FLAGS_OFFSET)
+    }
+
+    /// Returns the target address, which is the address being resolved.
+    #[inline]
+    pub fn target_address(&self) -> Ipv6Addr {
+        let mut octets = [0u8; IPV6_ADDR_LEN];
+        octets.copy_from_slice(
+            &self.bytes[TARGET_ADDRESS_OFFSET..TARGET_ADDRESS_OFFSET + IPV6_ADDR_LEN],
+        );
+        Ipv6Addr::from(octets)
+    }
+
+    /// Returns the length of the message.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl<'a, T: NetworkBytesMut> NeighborMessage<'a, T> {
+    /// Attempts to write a solicited Neighbor Advertisement to `buf`, which tells the owner of
+    /// `dst_addr` that `target_addr` can be reached at `target_mac`. `src_addr` and `dst_addr` are
+    /// the addresses of the IPv6 packet which carries the message, and are required to compute the
+    /// checksum.
+    pub fn write_advertisement(
+        buf: T,
+        target_addr: Ipv6Addr,
+        target_mac: MacAddr,
+        src_addr: Ipv6Addr,
+        dst_addr: Ipv6Addr,
+    ) -> Result<Self, Error> {
+        if buf.len() != NEIGHBOR_ADVERTISEMENT_LEN {
+            return Err(Error::SliceExactLen);
+        }
+
+        // This is ok, because we've checked the length of the slice.
+        let mut message = NeighborMessage::from_bytes_unchecked(buf);
+
+        message.set_message_type(TYPE_NEIGHBOR_ADVERTISEMENT);
+        message.set_code(0);
+        message.set_checksum(0);
+        message.set_flags(FLAG_SOLICITED | FLAG_OVERRIDE);
+        message.set_target_address(target_addr);
+        message.set_target_ll_addr_option(target_mac);
+
+        let checksum =
+            compute_upper_layer_checksum(&message.bytes, src_addr, dst_addr, PROTOCOL_ICMPV6);
+        message.set_checksum(checksum);
+
+        Ok(message)
+    }
+
+    /// Sets the ICMPv6 message type.
+    #[inline]
+    pub fn set_message_type(&mut self, value: u8) {
+        self.bytes[TYPE_OFFSET] = value;
+    }
+
+    /// Sets the ICMPv6 code.
+    #[inline]
+    pub fn set_code(&mut self, value: u8) {
+        self.bytes[CODE_OFFSET] = value;
+    }
+
+    /// Sets the ICMPv6 checksum.
+    #[inline]
+    pub fn set_checksum(&mut self, value: u16) {
+        self.bytes.htons_unchecked(CHECKSUM_OFFSET, value);
+    }
+
+    /// Sets the flags of the message (together with the reserved bits that follow them).
+    #[inline]
+    pub fn set_flags(&mut self, value: u32) {
+        self.bytes.htonl_unchecked(FLAGS_OFFSET, value);
+    }
+
+    /// Sets the target address.
+    #[inline]
+    pub fn set_target_address(&mut self, addr: Ipv6Addr) {
+        self.bytes[TARGET_ADDRESS_OFFSET..TARGET_ADDRESS_OFFSET + IPV6_ADDR_LEN]
+            .copy_from_slice(&addr.octets());
+    }
+
+    // Writes the target link-layer address option right after the fixed part of the message.
+    #[inline]
+    fn set_target_ll_addr_option(&mut self, addr: MacAddr) {
+        self.bytes[OPTIONS_OFFSET] = OPTION_TARGET_LL_ADDR;
+        self.bytes[OPTIONS_OFFSET + 1] = OPTION_TARGET_LL_ADDR_LEN_UNITS;
+        self.bytes[OPTIONS_OFFSET + 2..OPTIONS_OFFSET + 2 + MAC_ADDR_LEN]
+            .copy_from_slice(addr.get_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt;
+
+    use super::*;
+
+    impl<'a, T: NetworkBytes> fmt::Debug for NeighborMessage<'a, T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "(Neighbor Discovery message)")
+        }
+    }
+
+    #[test]
+    fn test_neighbor_messages() {
+        let mac = MacAddr::parse_str("06:01:23:45:67:01").unwrap();
+        let target = Ipv6Addr::new(0xfe80, 0, 0, 0, 0xa9fe, 0xa9fe, 0xa9fe, 0xa9fe);
+        let remote = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 4);
+        let mut buf = [0u8; 100];
+
+        // Slice size doesn't match.
+        assert_eq!(
+            NeighborMessage::write_advertisement(
+                &mut buf[..NEIGHBOR_ADVERTISEMENT_LEN - 1],
+                target,
+                mac,
+                target,
+                remote
+            )
+            .unwrap_err(),
+            Error::SliceExactLen
+        );
+
+        {
+            let m = NeighborMessage::write_advertisement(
+                &mut buf[..NEIGHBOR_ADVERTISEMENT_LEN],
+                target,
+                mac,
+                target,
+                remote,
+            )
+            .unwrap();
+            assert_eq!(m.len(), NEIGHBOR_ADVERTISEMENT_LEN);
+            assert_eq!(m.message_type(), TYPE_NEIGHBOR_ADVERTISEMENT);
+            assert_eq!(m.code(), 0);
+            assert_eq!(m.flags(), FLAG_SOLICITED | FLAG_OVERRIDE);
+            assert_eq!(m.target_address(), target);
+        }
+
+        // The checksum is valid.
+        assert_eq!(
+            compute_upper_layer_checksum(
+                &buf[..NEIGHBOR_ADVERTISEMENT_LEN],
+                target,
+                remote,
+                PROTOCOL_ICMPV6
+            ),
+            0
+        );
+        assert_eq!(buf[OPTIONS_OFFSET], OPTION_TARGET_LL_ADDR);
+        assert_eq!(buf[OPTIONS_OFFSET + 1], OPTION_TARGET_LL_ADDR_LEN_UNITS);
+        assert_eq!(
+            &buf[OPTIONS_OFFSET + 2..NEIGHBOR_ADVERTISEMENT_LEN],
+            mac.get_bytes()
+        );
+
+        // An advertisement is not a solicitation.
+        assert_eq!(
+            NeighborMessage::solicitation_from_bytes(&buf[..NEIGHBOR_ADVERTISEMENT_LEN])
+                .unwrap_err(),
+            Error::MessageType
+        );
+
+        // Turn it into a solicitation.
+        NeighborMessage::from_bytes_unchecked(buf.as_mut())
+            .set_message_type(TYPE_NEIGHBOR_SOLICITATION);
+        {
+            let m = NeighborMessage::solicitation_from_bytes(&buf[..NEIGHBOR_ADVERTISEMENT_LEN])
+                .unwrap();
+            assert_eq!(m.target_address(), target);
+        }
+
+        // Solicitations without options are fine.
+        assert!(NeighborMessage::solicitation_from_bytes(&buf[..OPTIONS_OFFSET]).is_ok());
+
+        assert_eq!(
+            NeighborMessage::solicitation_from_bytes(&buf[..OPTIONS_OFFSET - 1]).unwrap_err(),
+            Error::SliceTooShort
+        );
+
+        NeighborMessage::from_bytes_unchecked(buf.as_mut()).set_code(1);
+        assert_eq!(
+            NeighborMessage::solicitation_from_bytes(&buf[..NEIGHBOR_ADVERTISEMENT_LEN])
+                .unwrap_err(),
+            Error::Code
+        );
+    }
+}
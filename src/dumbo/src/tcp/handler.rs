@@ -1,22 +1,25 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-//! Exposes simple TCP over IPv4 listener functionality via the [`TcpIPv4Handler`] structure.
+//! Exposes simple TCP over IPv4 (and optionally IPv6) listener functionality via the
+//! [`TcpIPv4Handler`] structure.
 //!
 //! [`TcpIPv4Handler`]: struct.TcpIPv4Handler.html
 
 use std::collections::{HashMap, HashSet};
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::num::NonZeroUsize;
 
 use crate::pdu::bytes::NetworkBytes;
 use crate::pdu::ipv4::{Error as IPv4PacketError, IPv4Packet, PROTOCOL_TCP};
+use crate::pdu::ipv6::{self, compute_upper_layer_checksum, Error as IPv6PacketError, IPv6Packet};
 use crate::pdu::tcp::{Error as TcpSegmentError, Flags as TcpFlags, TcpSegment};
 use crate::tcp::endpoint::Endpoint;
 use crate::tcp::{NextSegmentStatus, RstConfig};
 use micro_http::{Request, Response};
 
-// TODO: This is currently IPv4 specific. Maybe change it to a more generic implementation.
+// Length of the IPv4 header written by the handler, which does not use any IP options.
+const IPV4_HEADER_LEN: usize = 20;
 
 /// Describes events which may occur when the handler receives packets.
 #[cfg_attr(test, derive(Debug, PartialEq))]
@@ -57,6 +60,8 @@ pub enum WriteEvent {
 /// [`TcpIPv4Handler`]: struct.TcpIPv4Handler.html
 #[cfg_attr(test, derive(Debug, PartialEq))]
 pub enum RecvError {
+    /// The segment was received over IPv6, but the handler has no local IPv6 address.
+    IPv6Disabled,
     /// The inner segment has an invalid destination port.
     InvalidPort,
     /// The handler encountered an error while parsing the inner TCP segment.
@@ -72,30 +77,34 @@ pub enum RecvError {
 pub enum WriteNextError {
     /// There was an error while writing the contents of the IPv4 packet.
     IPv4Packet(IPv4PacketError),
+    /// There was an error while writing the contents of the IPv6 packet.
+    IPv6Packet(IPv6PacketError),
     /// There was an error while writing the contents of the inner TCP segment.
     TcpSegment(TcpSegmentError),
 }
 
-// Generally speaking, a TCP/IPv4 connection is identified using the four-tuple (src_addr, src_port,
-// dst_addr, dst_port). However, the IPv4 address and TCP port of the MMDS endpoint are fixed, so
+// Generally speaking, a TCP/IP connection is identified using the four-tuple (src_addr, src_port,
+// dst_addr, dst_port). However, the IP addresses and TCP port of the MMDS endpoint are fixed, so
 // we can get away with uniquely identifying connections using just the remote address and port.
+// The remote address also tells which IP version the connection uses.
 #[derive(Clone, Copy, Eq, Hash, PartialEq)]
 #[cfg_attr(test, derive(Debug))]
 struct ConnectionTuple {
-    remote_addr: Ipv4Addr,
+    remote_addr: IpAddr,
     remote_port: u16,
 }
 
 impl ConnectionTuple {
-    fn new(remote_addr: Ipv4Addr, remote_port: u16) -> Self {
+    fn new<A: Into<IpAddr>>(remote_addr: A, remote_port: u16) -> Self {
         ConnectionTuple {
-            remote_addr,
+            remote_addr: remote_addr.into(),
             remote_port,
         }
     }
 }
 
-/// Implements a minimalist TCP over IPv4 listener.
+/// Implements a minimalist TCP over IPv4 listener, which also accepts connections over IPv6 once
+/// a local IPv6 address is set through [`set_local_ipv6_addr`].
 ///
 /// Forwards incoming TCP segments to the appropriate connection object, based on the associated
 /// tuple, or attempts to establish new connections (when receiving `SYN` segments). Aside from
@@ -120,9 +129,13 @@ impl ConnectionTuple {
 /// [`receive_packet`]: ../handler/struct.TcpIPv4Handler.html#method.receive_packet
 /// [`write_next_packet`]: ../handler/struct.TcpIPv4Handler.html#method.write_next_packet
 /// [`next_segment_status`]: ../handler/struct.TcpIPv4Handler.html#method.next_segment_status
+/// [`set_local_ipv6_addr`]: ../handler/struct.TcpIPv4Handler.html#method.set_local_ipv6_addr
 pub struct TcpIPv4Handler {
-    // Handler IPv4 address used for every connection.
+    // Handler IPv4 address used for every connection over IPv4.
     local_ipv4_addr: Ipv4Addr,
+    // Handler IPv6 address used for every connection over IPv6, which are only accepted when
+    // this is set.
+    local_ipv6_addr: Option<Ipv6Addr>,
     // Handler TCP port used for every connection.
     local_port: u16,
     // This map holds the currently active endpoints, identified by their connection tuple.
@@ -166,6 +179,7 @@ impl TcpIPv4Handler {
         let max_pending_resets = max_pending_resets.get();
         TcpIPv4Handler {
             local_ipv4_addr,
+            local_ipv6_addr: None,
             local_port,
             connections: HashMap::with_capacity(max_connections),
             max_connections,
@@ -186,6 +200,29 @@ impl TcpIPv4Handler {
         self.local_ipv4_addr
     }
 
+    /// Setter for the local IPv6 address of this TCP handler. Connections over IPv6 are only
+    /// accepted while this is set, and the ones opened for a previous address are dropped.
+    pub fn set_local_ipv6_addr(&mut self, ipv6_addr: Option<Ipv6Addr>) {
+        self.local_ipv6_addr = ipv6_addr;
+
+        let ipv6_tuples: Vec<ConnectionTuple> = self
+            .connections
+            .keys()
+            .filter(|tuple| tuple.remote_addr.is_ipv6())
+            .copied()
+            .collect();
+        for tuple in ipv6_tuples {
+            self.remove_connection(tuple);
+        }
+        self.rst_queue
+            .retain(|(tuple, _)| tuple.remote_addr.is_ipv4());
+    }
+
+    /// Returns the local IPv6 address of this TCP handler.
+    pub fn local_ipv6_addr(&self) -> Option<Ipv6Addr> {
+        self.local_ipv6_addr
+    }
+
     /// Returns the local port of this TCP handler.
     pub fn local_port(&self) -> u16 {
         self.local_port
@@ -209,17 +246,37 @@ impl TcpIPv4Handler {
         packet: &IPv4Packet<T>,
         callback: fn(Request) -> Response,
     ) -> Result<RecvEvent, RecvError> {
+        self.receive_segment(
+            IpAddr::V4(packet.source_address()),
+            packet.payload(),
+            callback,
+        )
+    }
+
+    /// Contains logic for handling incoming segments which were sent from `remote_addr`, when
+    /// the caller has already taken care of the network layer.
+    ///
+    /// Any changes to the state of the handler are communicated through an `Ok(RecvEvent)`.
+    pub fn receive_segment(
+        &mut self,
+        remote_addr: IpAddr,
+        bytes: &[u8],
+        callback: fn(Request) -> Response,
+    ) -> Result<RecvEvent, RecvError> {
+        if remote_addr.is_ipv6() && self.local_ipv6_addr.is_none() {
+            return Err(RecvError::IPv6Disabled);
+        }
+
         // TODO: We skip verifying the checksum, just in case the device model relies on offloading
         // checksum computation from the guest to some other entity. Clear this up at some point!
         // (Issue #520)
-        let segment =
-            TcpSegment::from_bytes(packet.payload(), None).map_err(RecvError::TcpSegment)?;
+        let segment = TcpSegment::from_bytes(bytes, None).map_err(RecvError::TcpSegment)?;
 
         if segment.destination_port() != self.local_port {
             return Err(RecvError::InvalidPort);
         }
 
-        let tuple = ConnectionTuple::new(packet.source_address(), segment.source_port());
+        let tuple = ConnectionTuple::new(remote_addr, segment.source_port());
 
         let outcome = if let Some(endpoint) = self.connections.get_mut(&tuple) {
             endpoint.receive_segment(&segment, callback);
@@ -386,10 +443,9 @@ impl TcpIPv4Handler {
         let mut writer_status = None;
         let mut event = WriteEvent::Nothing;
 
-        // Write an incomplete Ipv4 packet and complete it afterwards with missing information.
-        let mut packet =
-            IPv4Packet::write_header(buf, PROTOCOL_TCP, Ipv4Addr::LOCALHOST, Ipv4Addr::LOCALHOST)
-                .map_err(WriteNextError::IPv4Packet)?;
+        if buf.len() < IPV4_HEADER_LEN {
+            return Err(WriteNextError::IPv4Packet(IPv4PacketError::SliceTooShort));
+        }
 
         // We set mss_used to 0, because we don't add any IP options.
         // TODO: Maybe get this nicely from packet at some point.
@@ -401,7 +457,7 @@ impl TcpIPv4Handler {
         if let Some((tuple, rst_cfg)) = self.rst_queue.pop() {
             let (seq, ack, flags_after_ns) = rst_cfg.seq_ack_tcp_flags();
             let segment_len = TcpSegment::write_incomplete_segment::<[u8]>(
-                packet.inner_mut().payload_mut(),
+                ip_payload_mut(buf, tuple)?,
                 seq,
                 ack,
                 flags_after_ns,
@@ -411,24 +467,11 @@ impl TcpIPv4Handler {
                 None,
             )
             .map_err(WriteNextError::TcpSegment)?
-            .finalize(
-                self.local_port,
-                tuple.remote_port,
-                Some((self.local_ipv4_addr, tuple.remote_addr)),
-            )
+            .finalize(self.local_port, tuple.remote_port, None)
             .len();
 
-            packet
-                .inner_mut()
-                .set_source_address(self.local_ipv4_addr)
-                .set_destination_address(tuple.remote_addr);
-
-            let packet_len = packet.with_payload_len_unchecked(segment_len, true).len();
-            // The unwrap() is safe because packet_len > 0.
-            return Ok((
-                Some(NonZeroUsize::new(packet_len).unwrap()),
-                WriteEvent::Nothing,
-            ));
+            let packet_len = self.write_ip_header(buf, tuple, segment_len)?;
+            return Ok((Some(packet_len), WriteEvent::Nothing));
         }
 
         for tuple in self
@@ -440,34 +483,22 @@ impl TcpIPv4Handler {
             // in self.connections.
             let endpoint = self.connections.get_mut(tuple).unwrap();
             // We need this block to clearly delimit the lifetime of the mutable borrow started by
-            // the following packet.inner_mut().
+            // the following ip_payload_mut().
             let segment_len = {
                 let maybe_segment =
-                    endpoint.write_next_segment(packet.inner_mut().payload_mut(), mss_reserved);
+                    endpoint.write_next_segment(ip_payload_mut(buf, *tuple)?, mss_reserved);
 
                 match maybe_segment {
                     Some(segment) => segment
-                        .finalize(
-                            self.local_port,
-                            tuple.remote_port,
-                            Some((self.local_ipv4_addr, tuple.remote_addr)),
-                        )
+                        .finalize(self.local_port, tuple.remote_port, None)
                         .len(),
                     None => continue,
                 }
             };
-
-            packet
-                .inner_mut()
-                .set_source_address(self.local_ipv4_addr)
-                .set_destination_address(tuple.remote_addr);
-
-            let ip_len = packet.with_payload_len_unchecked(segment_len, true).len();
-
-            // The unwrap is safe because ip_len > 0.
-            len = Some(NonZeroUsize::new(ip_len).unwrap());
             writer_status = Some((*tuple, endpoint.is_done()));
 
+            len = Some(self.write_ip_header(buf, *tuple, segment_len)?);
+
             break;
         }
 
@@ -488,6 +519,50 @@ impl TcpIPv4Handler {
         Ok((len, event))
     }
 
+    // Writes the header of the IP packet going to `tuple`, which carries the `segment_len` bytes
+    // long TCP segment found in the payload area of `buf`, and fills in the segment checksum
+    // (which also covers the IP addresses). Returns the length of the whole packet.
+    fn write_ip_header(
+        &self,
+        buf: &mut [u8],
+        tuple: ConnectionTuple,
+        segment_len: usize,
+    ) -> Result<NonZeroUsize, WriteNextError> {
+        let packet_len = match tuple.remote_addr {
+            IpAddr::V4(remote_addr) => {
+                let mut packet =
+                    IPv4Packet::write_header(buf, PROTOCOL_TCP, self.local_ipv4_addr, remote_addr)
+                        .map_err(WriteNextError::IPv4Packet)?;
+                let mut segment = TcpSegment::from_bytes_unchecked(
+                    &mut packet.inner_mut().payload_mut()[..segment_len],
+                );
+                segment.set_checksum(0);
+                let checksum = segment.compute_checksum(self.local_ipv4_addr, remote_addr);
+                segment.set_checksum(checksum);
+
+                packet.with_payload_len_unchecked(segment_len, true).len()
+            }
+            IpAddr::V6(remote_addr) => {
+                // The unwrap() is safe because connections over IPv6 are only accepted, and kept,
+                // while the local IPv6 address is set.
+                let local_addr = self.local_ipv6_addr.unwrap();
+                let mut packet =
+                    IPv6Packet::write_header(buf, PROTOCOL_TCP, local_addr, remote_addr)
+                        .map_err(WriteNextError::IPv6Packet)?;
+                let payload = &mut packet.inner_mut().payload_mut()[..segment_len];
+                TcpSegment::from_bytes_unchecked(&mut *payload).set_checksum(0);
+                let checksum =
+                    compute_upper_layer_checksum(payload, local_addr, remote_addr, PROTOCOL_TCP);
+                TcpSegment::from_bytes_unchecked(payload).set_checksum(checksum);
+
+                packet.with_payload_len_unchecked(segment_len).len()
+            }
+        };
+
+        // The unwrap() is safe because packet_len > 0.
+        Ok(NonZeroUsize::new(packet_len).unwrap())
+    }
+
     /// Describes the status of the next segment to be sent by the handler.
     #[inline]
     pub fn next_segment_status(&self) -> NextSegmentStatus {
@@ -503,6 +578,18 @@ impl TcpIPv4Handler {
     }
 }
 
+// Returns the part of `buf` which holds the payload of an IP packet going to `tuple`.
+fn ip_payload_mut(buf: &mut [u8], tuple: ConnectionTuple) -> Result<&mut [u8], WriteNextError> {
+    match tuple.remote_addr {
+        IpAddr::V4(_) => buf
+            .get_mut(IPV4_HEADER_LEN..)
+            .ok_or(WriteNextError::IPv4Packet(IPv4PacketError::SliceTooShort)),
+        IpAddr::V6(_) => buf
+            .get_mut(ipv6::HEADER_LEN..)
+            .ok_or(WriteNextError::IPv6Packet(IPv6PacketError::SliceTooShort)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -823,4 +910,87 @@ mod tests {
         assert_eq!(h.connections.len(), 1);
         assert_eq!(h.active_connections.len(), 0);
     }
+
+    #[test]
+    fn test_handler_ipv6() {
+        let mut buf = [0u8; 100];
+        let mut buf2 = [0u8; 2000];
+
+        let local_ipv4_addr = Ipv4Addr::new(169, 254, 169, 254);
+        let local_addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0xa9fe, 0xa9fe, 0xa9fe, 0xa9fe);
+        let remote_addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 4);
+        let remote_addr2 = Ipv6Addr::new(0xfe80, 0, 0, 0, 5, 6, 7, 8);
+        let local_port = 80;
+        let remote_port = 1012;
+
+        let mut h = TcpIPv4Handler::new(
+            local_ipv4_addr,
+            local_port,
+            NonZeroUsize::new(2).unwrap(),
+            NonZeroUsize::new(2).unwrap(),
+        );
+
+        let s_len = TcpSegment::write_segment::<[u8]>(
+            buf.as_mut(),
+            remote_port,
+            local_port,
+            123,
+            456,
+            TcpFlags::SYN,
+            10000,
+            None,
+            100,
+            None,
+            None,
+        )
+        .unwrap()
+        .len();
+        let syn = &buf[..s_len];
+
+        // Segments received over IPv6 are rejected until the handler has a local IPv6 address.
+        assert_eq!(
+            h.receive_segment(IpAddr::V6(remote_addr), syn, mock_callback),
+            Err(RecvError::IPv6Disabled)
+        );
+
+        h.set_local_ipv6_addr(Some(local_addr));
+        assert_eq!(h.local_ipv6_addr(), Some(local_addr));
+
+        // Two peers using the same port get distinct connections.
+        for addr in [remote_addr, remote_addr2].iter() {
+            assert_eq!(
+                h.receive_segment(IpAddr::V6(*addr), syn, mock_callback),
+                Ok(RecvEvent::NewConnectionSuccessful)
+            );
+        }
+        assert_eq!(h.connections.len(), 2);
+
+        // Each SYNACK goes to its own peer, over IPv6.
+        let mut destinations = Vec::new();
+        for _ in 0..2 {
+            let (len, _) = h.write_next_packet(buf2.as_mut()).unwrap();
+            let p = IPv6Packet::from_bytes(&buf2[..len.unwrap().get()]).unwrap();
+            assert_eq!(p.source_address(), local_addr);
+            assert_eq!(
+                compute_upper_layer_checksum(
+                    p.payload(),
+                    local_addr,
+                    p.destination_address(),
+                    PROTOCOL_TCP
+                ),
+                0
+            );
+            let s = TcpSegment::from_bytes(p.payload(), None).unwrap();
+            assert_eq!(s.flags_after_ns(), TcpFlags::SYN | TcpFlags::ACK);
+            assert_eq!(s.destination_port(), remote_port);
+            destinations.push(p.destination_address());
+        }
+        destinations.sort();
+        assert_eq!(destinations, vec![remote_addr, remote_addr2]);
+
+        // Removing the local IPv6 address drops the connections over IPv6.
+        h.set_local_ipv6_addr(None);
+        assert_eq!(h.connections.len(), 0);
+        assert_eq!(h.next_segment_status(), NextSegmentStatus::Nothing);
+    }
 }
@@ -16,10 +16,13 @@ use mmds::MMDS;
 use seccompiler::BpfThreadMap;
 use utils::{epoll::EventSet, eventfd::EventFd};
 use vmm::{
+    recent_errors::RecentErrors,
     resources::VmResources,
     rpc_interface::{PrebootApiController, RuntimeApiController, VmmAction},
-    vmm_config::instance_info::InstanceInfo,
-    EventManager, ExitCode, Vmm,
+    vmm_config::{
+        instance_info::InstanceInfo, resource_limits::ResourceLimits, serial::SerialConfig,
+    },
+    EventManager, ExitCode, Vmm, FC_EXIT_CODE_UNEXPECTED_ERROR,
 };
 
 struct ApiServerAdapter {
@@ -31,29 +34,66 @@ struct ApiServerAdapter {
 
 impl ApiServerAdapter {
     /// Runs the vmm to completion, while any arising control events are deferred
-    /// to a `RuntimeApiController`.
-    fn run_microvm(
+    /// to a `RuntimeApiController`. When the guest reboots, the microVM is booted again
+    /// through `restart` if it's configured to restart then.
+    fn run_microvm<F>(
         api_event_fd: EventFd,
         from_api: Receiver<ApiRequest>,
         to_api: Sender<ApiResponse>,
         vm_resources: VmResources,
         vmm: Arc<Mutex<Vmm>>,
         event_manager: &mut EventManager,
-    ) -> ExitCode {
-        let api_adapter = Arc::new(Mutex::new(Self {
+        housekeeping_period_ms: u64,
+        mut restart: F,
+    ) -> ExitCode
+    where
+        F: FnMut(&mut EventManager, &mut VmResources) -> Result<Arc<Mutex<Vmm>>, ExitCode>,
+    {
+        let mut api_adapter = Arc::new(Mutex::new(Self {
             api_event_fd,
             from_api,
             to_api,
             controller: RuntimeApiController::new(vm_resources, vmm.clone()),
         }));
-        event_manager.add_subscriber(api_adapter);
+        let mut vmm = vmm;
         loop {
-            event_manager
-                .run()
-                .expect("EventManager events driver fatal error");
-            if let Some(exit_code) = vmm.lock().unwrap().shutdown_exit_code() {
+            event_manager.add_subscriber(api_adapter.clone());
+            let mut stall_detector = super::event_loop::StallDetector::new(housekeeping_period_ms);
+            let exit_code =
+                super::event_loop::run(event_manager, &vmm, housekeeping_period_ms, |_| {
+                    stall_detector.check();
+                });
+            let restart_on_reboot = super::restart_on_reboot(
+                api_adapter
+                    .lock()
+                    .expect("Poisoned lock")
+                    .controller
+                    .vm_resources(),
+                exit_code,
+            );
+            if !restart_on_reboot {
                 return exit_code;
             }
+
+            // Tear the stopped microVM down, before booting it again. Dropping the
+            // `EventManager` leaves this function with the only references to the adapter.
+            *event_manager = EventManager::new().expect("Unable to create EventManager");
+            drop(vmm);
+            let adapter = match Arc::try_unwrap(api_adapter) {
+                Ok(adapter) => adapter.into_inner().expect("Poisoned lock"),
+                Err(_) => panic!("The API server adapter is still in use."),
+            };
+            let mut vm_resources = adapter.controller.into_vm_resources();
+            vmm = match restart(event_manager, &mut vm_resources) {
+                Ok(vmm) => vmm,
+                Err(exit_code) => return exit_code,
+            };
+            api_adapter = Arc::new(Mutex::new(Self {
+                api_event_fd: adapter.api_event_fd,
+                from_api: adapter.from_api,
+                to_api: adapter.to_api,
+                controller: RuntimeApiController::new(vm_resources, vmm.clone()),
+            }));
         }
     }
 
@@ -65,6 +105,18 @@ impl ApiServerAdapter {
             .map_err(|_| ())
             .expect("one-shot channel closed");
     }
+
+    // No more requests can be received once the API server thread is gone, so the microVM is
+    // stopped. The VMM shuts down as on any other exit, the vCPUs are terminated and the
+    // metrics are written.
+    fn stop_on_api_disconnect(&mut self) {
+        error!("The API server thread is gone, stopping the microVM.");
+        self.controller
+            .vmm()
+            .lock()
+            .expect("Poisoned lock")
+            .stop(FC_EXIT_CODE_UNEXPECTED_ERROR);
+    }
 }
 impl MutEventSubscriber for ApiServerAdapter {
     /// Handle a read event (EPOLLIN).
@@ -87,7 +139,13 @@ impl MutEventSubscriber for ApiServerAdapter {
                         // This loop only attempts to process API requests, so things like the
                         // metric flush timerfd handling are frozen as well.
                         loop {
-                            let req = self.from_api.recv().expect("Error receiving API request.");
+                            let req = match self.from_api.recv() {
+                                Ok(req) => req,
+                                Err(_) => {
+                                    self.stop_on_api_disconnect();
+                                    break;
+                                }
+                            };
                             let req_is_resume = *req == VmmAction::Resume;
                             self.handle_request(*req);
                             if req_is_resume {
@@ -99,9 +157,7 @@ impl MutEventSubscriber for ApiServerAdapter {
                 Err(TryRecvError::Empty) => {
                     warn!("Got a spurious notification from api thread");
                 }
-                Err(TryRecvError::Disconnected) => {
-                    panic!("The channel's sending half was disconnected. Cannot receive data.");
-                }
+                Err(TryRecvError::Disconnected) => self.stop_on_api_disconnect(),
             };
             let _ = self.api_event_fd.read();
         } else {
@@ -116,13 +172,19 @@ impl MutEventSubscriber for ApiServerAdapter {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn run_with_api(
     seccomp_filters: &mut BpfThreadMap,
     config_json: Option<String>,
     bind_path: PathBuf,
-    instance_info: InstanceInfo,
+    mut instance_info: InstanceInfo,
     process_time_reporter: ProcessTimeReporter,
     boot_timer_enabled: bool,
+    boot_timeout_ms: Option<u64>,
+    serial_config: SerialConfig,
+    resource_limits: ResourceLimits,
+    recent_errors: RecentErrors,
+    housekeeping_period_ms: u64,
 ) -> ExitCode {
     // FD to notify of API events. This is a blocking eventfd by design.
     // It is used in the config/pre-boot loop which is a simple blocking loop
@@ -179,35 +241,42 @@ pub(crate) fn run_with_api(
             &seccomp_filters,
             &mut event_manager,
             json,
-            instance_info,
+            instance_info.clone(),
             boot_timer_enabled,
+            boot_timeout_ms,
+            serial_config,
+            resource_limits,
+            recent_errors,
         ),
         None => PrebootApiController::build_microvm_from_requests(
             &seccomp_filters,
             &mut event_manager,
-            instance_info,
+            instance_info.clone(),
             || {
-                let req = from_api
-                    .recv()
-                    .expect("The channel's sending half was disconnected. Cannot receive data.");
+                // The API server thread is gone once the channel is disconnected.
+                let req = from_api.recv().ok()?;
                 // Also consume the API event along with the message. It is safe to unwrap()
                 // because this event_fd is blocking.
                 api_event_fd
                     .read()
                     .expect("VMM: Failed to read the API event_fd");
-                *req
-            },
-            |response| {
-                to_api
-                    .send(Box::new(response))
-                    .expect("one-shot channel closed")
+                Some(*req)
             },
+            |response| to_api.send(Box::new(response)).map_err(|_| ()),
             boot_timer_enabled,
+            boot_timeout_ms,
+            serial_config,
+            resource_limits,
+            recent_errors,
         ),
     };
 
     let exit_code = match build_result {
         Ok((vm_resources, vmm)) => {
+            // The instance ID may have been set through the API, the microVM keeps the same one
+            // when it's restarted.
+            instance_info.id = vmm.lock().expect("Poisoned lock").instance_info().id;
+
             // Start the metrics.
             firecracker_metrics
                 .lock()
@@ -221,6 +290,17 @@ pub(crate) fn run_with_api(
                 vm_resources,
                 vmm,
                 &mut event_manager,
+                housekeeping_period_ms,
+                |event_manager, vm_resources| {
+                    let vmm = super::restart_microvm(
+                        &seccomp_filters,
+                        event_manager,
+                        &instance_info,
+                        vm_resources,
+                    )?;
+                    event_manager.add_subscriber(firecracker_metrics.clone());
+                    Ok(vmm)
+                },
             )
         }
         Err(exit_code) => exit_code,
@@ -233,13 +313,19 @@ pub(crate) fn run_with_api(
     // way to do it...but having another way would involve multiplexing micro-http server
     // with some other communication mechanism, or enhancing micro-http with exit
     // conditions.
-    let mut sock = UnixStream::connect(bind_path).unwrap();
-    sock.write_all(b"PUT /shutdown-internal HTTP/1.1\r\n\r\n")
-        .unwrap();
+    // The API thread may already be gone, in which case there's no one left to connect to.
+    match UnixStream::connect(bind_path) {
+        Ok(mut sock) => sock
+            .write_all(b"PUT /shutdown-internal HTTP/1.1\r\n\r\n")
+            .unwrap(),
+        Err(e) => warn!("Cannot connect to the API socket to shut it down: {}", e),
+    }
 
     // This call to thread::join() should block until the API thread has processed the
     // shutdown-internal and returns from its function.
-    api_thread.join().unwrap();
+    if api_thread.join().is_err() {
+        error!("The API server thread panicked.");
+    }
 
     exit_code
 }
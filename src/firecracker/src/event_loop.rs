@@ -0,0 +1,182 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::cmp;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use logger::{warn, IncMetric, METRICS};
+use vmm::{EventManager, ExitCode, Vmm};
+
+/// Default period of the housekeeping work done by the VMM thread.
+pub(crate) const DEFAULT_HOUSEKEEPING_PERIOD_MS: u64 = 1000;
+
+/// Parses the value of the `--housekeeping-period` parameter, a number of millisecs greater
+/// than 0.
+pub(crate) fn parse_housekeeping_period(value: &str) -> Result<u64, String> {
+    match value.parse::<u64>() {
+        Ok(housekeeping_period_ms) if housekeeping_period_ms > 0 => Ok(housekeeping_period_ms),
+        _ => Err(format!(
+            "'housekeeping-period' parameter expected to be a number of milliseconds greater \
+             than 0, got '{}'.",
+            value
+        )),
+    }
+}
+
+/// Runs the `EventManager` that drives everything in the microVM, until the VMM is shut down.
+///
+/// Waiting for events is bounded, so that `housekeeping` runs every `housekeeping_period_ms`
+/// millisecs, whether or not any events were handled in the meantime. The housekeeping work
+/// delays the handling of events, so it has to be lightweight.
+pub(crate) fn run<F>(
+    event_manager: &mut EventManager,
+    vmm: &Arc<Mutex<Vmm>>,
+    housekeeping_period_ms: u64,
+    mut housekeeping: F,
+) -> ExitCode
+where
+    F: FnMut(&mut Vmm),
+{
+    let housekeeping_period = Duration::from_millis(housekeeping_period_ms);
+    let mut last_housekeeping = Instant::now();
+
+    loop {
+        let timeout = housekeeping_period
+            .checked_sub(last_housekeeping.elapsed())
+            .unwrap_or_default();
+        // Timing out without handling any event is not an error, it only means that it's time
+        // for housekeeping.
+        event_manager
+            .run_with_timeout(cmp::min(timeout.as_millis(), i32::MAX as u128) as i32)
+            .expect("EventManager events driver fatal error");
+
+        let mut vmm = vmm.lock().expect("Poisoned lock");
+        if let Some(exit_code) = vmm.shutdown_exit_code() {
+            return exit_code;
+        }
+
+        if last_housekeeping.elapsed() >= housekeeping_period {
+            housekeeping(&mut vmm);
+            last_housekeeping = Instant::now();
+        }
+    }
+}
+
+/// Default housekeeping work, which reports the VMM thread as stalled when handling events
+/// kept it from doing housekeeping for more than two periods.
+pub(crate) struct StallDetector {
+    max_delay: Duration,
+    last_run: Instant,
+}
+
+impl StallDetector {
+    /// Creates a `StallDetector` for housekeeping done every `housekeeping_period_ms` millisecs.
+    pub(crate) fn new(housekeeping_period_ms: u64) -> Self {
+        StallDetector {
+            max_delay: Duration::from_millis(2 * housekeeping_period_ms),
+            last_run: Instant::now(),
+        }
+    }
+
+    /// Checks how long ago the previous housekeeping ran. Returns `true` if the VMM thread
+    /// stalled in the meantime.
+    pub(crate) fn check(&mut self) -> bool {
+        let delay = self.last_run.elapsed();
+        self.last_run = Instant::now();
+
+        if delay > self.max_delay {
+            METRICS.vmm.event_loop_stalls.inc();
+            warn!(
+                "The VMM thread did not run housekeeping for {} ms.",
+                delay.as_millis()
+            );
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use event_manager::{EventOps, Events, MutEventSubscriber};
+    use utils::epoll::EventSet;
+    use utils::eventfd::EventFd;
+    use vmm::utilities::test_utils::default_vmm;
+    use vmm::FC_EXIT_CODE_OK;
+
+    // Stalls the VMM thread for `stall` when its event is handled.
+    struct StallingSubscriber {
+        event_fd: EventFd,
+        stall: Duration,
+    }
+
+    impl MutEventSubscriber for StallingSubscriber {
+        fn process(&mut self, _: Events, _: &mut EventOps) {
+            self.event_fd.read().unwrap();
+            std::thread::sleep(self.stall);
+        }
+
+        fn init(&mut self, ops: &mut EventOps) {
+            ops.add(Events::new(&self.event_fd, EventSet::IN)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_parse_housekeeping_period() {
+        assert_eq!(parse_housekeeping_period("1000"), Ok(1000));
+        assert_eq!(
+            parse_housekeeping_period("0"),
+            Err(
+                "'housekeeping-period' parameter expected to be a number of milliseconds \
+                 greater than 0, got '0'."
+                    .to_string()
+            )
+        );
+        assert!(parse_housekeeping_period("-1").is_err());
+        assert!(parse_housekeeping_period("1s").is_err());
+    }
+
+    #[test]
+    fn test_stall_detector() {
+        let mut stall_detector = StallDetector::new(10);
+
+        let stalls = METRICS.vmm.event_loop_stalls.count();
+        assert!(!stall_detector.check());
+        assert_eq!(METRICS.vmm.event_loop_stalls.count(), stalls);
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(stall_detector.check());
+        assert_eq!(METRICS.vmm.event_loop_stalls.count(), stalls + 1);
+
+        // The delay is measured from the previous check.
+        assert!(!stall_detector.check());
+
+        // Handling an event which keeps the event loop from its housekeeping for more than two
+        // periods is reported as a stall. The microVM is stopped once it is detected.
+        let (vmm, mut event_manager) = default_vmm(None);
+        let stalling_subscriber = Arc::new(Mutex::new(StallingSubscriber {
+            event_fd: EventFd::new(libc::EFD_NONBLOCK).unwrap(),
+            stall: Duration::from_millis(50),
+        }));
+        event_manager.add_subscriber(stalling_subscriber.clone());
+        stalling_subscriber
+            .lock()
+            .unwrap()
+            .event_fd
+            .write(1)
+            .unwrap();
+
+        let stalls = METRICS.vmm.event_loop_stalls.count();
+        let mut stall_detector = StallDetector::new(10);
+        let exit_code = run(&mut event_manager, &vmm, 10, |vmm| {
+            if stall_detector.check() {
+                vmm.stop(FC_EXIT_CODE_OK);
+            }
+        });
+        assert_eq!(exit_code, FC_EXIT_CODE_OK);
+        assert_eq!(METRICS.vmm.event_loop_stalls.count(), stalls + 1);
+    }
+}
@@ -1,6 +1,7 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 mod api_server_adapter;
+mod event_loop;
 mod metrics;
 
 use std::fs::{self, File};
@@ -17,12 +18,17 @@ use snapshot::Snapshot;
 use utils::arg_parser::{ArgParser, Argument, Arguments};
 use utils::terminal::Terminal;
 use utils::validators::validate_instance_id;
+use vmm::builder::StartMicrovmError;
+use vmm::recent_errors::RecentErrors;
 use vmm::seccomp_filters::{get_filters, SeccompConfig};
 use vmm::signal_handler::register_signal_handlers;
 use vmm::version_map::{FC_VERSION_TO_SNAP_VERSION, VERSION_MAP};
 use vmm::vmm_config::instance_info::{InstanceInfo, VmState};
 use vmm::vmm_config::logger::{init_logger, LoggerConfig, LoggerLevel};
-use vmm::{resources::VmResources, EventManager, ExitCode};
+use vmm::vmm_config::machine_config::{RebootAction, ResetAction};
+use vmm::vmm_config::resource_limits::ResourceLimits;
+use vmm::vmm_config::serial::{ConsoleType, SerialConfig, DEFAULT_SERIAL_LOG_SIZE};
+use vmm::{resources::VmResources, EventManager, ExitCode, Vmm};
 
 // The reason we place default API socket under /run is that API socket is a
 // runtime file.
@@ -102,6 +108,7 @@ fn main_exitable() -> ExitCode {
         }
     }));
 
+    let default_housekeeping_period = event_loop::DEFAULT_HOUSEKEEPING_PERIOD_MS.to_string();
     let mut arg_parser = ArgParser::new()
         .arg(
             Argument::new("api-sock")
@@ -129,6 +136,15 @@ fn main_exitable() -> ExitCode {
                     "Optional parameter which allows specifying the path to a custom seccomp filter. For advanced users."
                 ),
         )
+        .arg(
+            Argument::new("seccomp-supplement")
+                .takes_value(true)
+                .forbids(vec!["seccomp-level", "seccomp-filter", "no-seccomp"])
+                .help(
+                    "Optional parameter which allows specifying the path to a seccomp filter which allows system \
+                    calls on top of the default filters. For advanced users."
+                ),
+        )
         .arg(
             Argument::new("no-seccomp")
                 .takes_value(false)
@@ -136,6 +152,13 @@ fn main_exitable() -> ExitCode {
                 .help("Optional parameter which allows starting and using a microVM without seccomp filtering. \
                     Not recommended.")
         )
+        .arg(
+            Argument::new("seccomp-allow-load-failure")
+                .takes_value(false)
+                .forbids(vec!["seccomp-level", "no-seccomp"])
+                .help("Optional parameter which lets the threads run without seccomp filtering when their \
+                    filter can't be loaded, instead of failing. Not recommended.")
+        )
         .arg(
             Argument::new("start-time-us")
                 .takes_value(true)
@@ -191,6 +214,57 @@ fn main_exitable() -> ExitCode {
                 .takes_value(false)
                 .help("Whether or not to load boot timer device for logging elapsed time since InstanceStart command.")
         )
+        .arg(
+            Argument::new("boot-timeout")
+                .takes_value(true)
+                .help("Time limit in milliseconds for starting the microVM. A microVM which doesn't start in time is torn down and the start request fails. Firecracker exits if the timeout is hit once the VMM thread installed its seccomp filter. The boot steps can't be interrupted, so the timeout is only checked between them.")
+        )
+        .arg(
+            Argument::new("disable-serial-input")
+                .takes_value(false)
+                .help("Whether or not to make the serial console output-only, ignoring any input from stdin.")
+        )
+        .arg(
+            Argument::new("serial-log-size")
+                .takes_value(true)
+                .help("Size in bytes of the in-memory log of the guest serial output, retrievable through the API. A size of 0 disables the log.")
+        )
+        .arg(
+            Argument::new("second-serial-output")
+                .takes_value(true)
+                .help("Path to a file or FIFO that receives the output of the second serial port (ttyS1). Only supported on x86_64.")
+        )
+        .arg(
+            Argument::new("console")
+                .takes_value(true)
+                .help("Device backing the guest console: 'serial' for the legacy serial port (default) or 'virtio' for a virtio console (hvc0).")
+        )
+        .arg(
+            Argument::new("auto-console")
+                .takes_value(false)
+                .help("Whether or not to add the 'console=' parameter matching the console device to the kernel command line, unless the command line already selects a console.")
+        )
+        .arg(
+            Argument::new("rlimit-nofile")
+                .takes_value(true)
+                .help("Limit on the number of open file descriptors (RLIMIT_NOFILE), set once the microVM is built. A limit of 0 is not set.")
+        )
+        .arg(
+            Argument::new("rlimit-fsize")
+                .takes_value(true)
+                .help("Limit in bytes on the size of the files created by Firecracker (RLIMIT_FSIZE), set once the microVM is built. A limit of 0 is not set.")
+        )
+        .arg(
+            Argument::new("rlimit-nproc")
+                .takes_value(true)
+                .help("Limit on the number of threads of the user (RLIMIT_NPROC), set once the microVM is built. A limit of 0 is not set.")
+        )
+        .arg(
+            Argument::new("housekeeping-period")
+                .takes_value(true)
+                .default_value(&default_housekeeping_period)
+                .help("Period in milliseconds of the housekeeping work done by the VMM thread, e.g. detecting when handling events stalls it. Must be greater than 0.")
+        )
         .arg(
             Argument::new("version")
                 .takes_value(false)
@@ -248,6 +322,9 @@ fn main_exitable() -> ExitCode {
     };
 
     LOGGER.set_instance_id(instance_id.to_owned());
+    // The errors logged from now on are kept for the API to report them.
+    let recent_errors = RecentErrors::default();
+    recent_errors.record_logged_errors(&LOGGER);
 
     if let Some(log) = arguments.single_value("log-path") {
         // It's safe to unwrap here because the field's been provided with a default value.
@@ -280,6 +357,7 @@ fn main_exitable() -> ExitCode {
         arguments.single_value("seccomp-level"),
         arguments.flag_present("no-seccomp"),
         arguments.single_value("seccomp-filter"),
+        arguments.single_value("seccomp-supplement"),
     )
     .and_then(get_filters)
     {
@@ -288,6 +366,10 @@ fn main_exitable() -> ExitCode {
             return generic_error_exit(&format!("Seccomp error: {}", e));
         }
     };
+    if arguments.flag_present("seccomp-allow-load-failure") {
+        warn!("The threads run without seccomp filtering if their filter can't be loaded.");
+        vmm::seccomp_filters::set_allow_load_failure(true);
+    }
 
     let vmm_config_json = arguments
         .single_value("config-file")
@@ -295,6 +377,60 @@ fn main_exitable() -> ExitCode {
         .map(|x| x.expect("Unable to open or read from the configuration file"));
 
     let boot_timer_enabled = arguments.flag_present("boot-timer");
+    let boot_timeout_ms = arguments.single_value("boot-timeout").map(|s| {
+        s.parse::<u64>()
+            .expect("'boot-timeout' parameter expected to be of 'u64' type.")
+    });
+    let serial_config = SerialConfig {
+        disable_input: arguments.flag_present("disable-serial-input"),
+        log_size: arguments
+            .single_value("serial-log-size")
+            .map(|s| {
+                s.parse::<usize>()
+                    .expect("'serial-log-size' parameter expected to be of 'usize' type.")
+            })
+            .unwrap_or(DEFAULT_SERIAL_LOG_SIZE),
+        second_output_path: arguments
+            .single_value("second-serial-output")
+            .map(PathBuf::from),
+        console_type: arguments
+            .single_value("console")
+            .map(|s| {
+                s.parse::<ConsoleType>()
+                    .expect("'console' parameter expected to be 'serial' or 'virtio'.")
+            })
+            .unwrap_or_default(),
+        auto_console: arguments.flag_present("auto-console"),
+    };
+    let rlimit = |name: &str| {
+        arguments
+            .single_value(name)
+            .map(|s| {
+                s.parse::<u64>().unwrap_or_else(|_| {
+                    panic!("'{}' parameter expected to be of 'u64' type.", name)
+                })
+            })
+            .unwrap_or_default()
+    };
+    let resource_limits = ResourceLimits {
+        nofile: rlimit("rlimit-nofile"),
+        fsize: rlimit("rlimit-fsize"),
+        nproc: rlimit("rlimit-nproc"),
+    };
+    // It's safe to unwrap here because the field's been provided with a default value.
+    let housekeeping_period_ms = match event_loop::parse_housekeeping_period(
+        arguments.single_value("housekeeping-period").unwrap(),
+    ) {
+        Ok(housekeeping_period_ms) => housekeeping_period_ms,
+        Err(err) => {
+            error!(
+                "Arguments parsing error: {} \n\n\
+                     For more information try --help.",
+                err
+            );
+            return vmm::FC_EXIT_CODE_ARG_PARSING;
+        }
+    };
     let api_enabled = !arguments.flag_present("no-api");
 
     if api_enabled {
@@ -327,6 +463,11 @@ fn main_exitable() -> ExitCode {
             instance_info,
             process_time_reporter,
             boot_timer_enabled,
+            boot_timeout_ms,
+            serial_config,
+            resource_limits,
+            recent_errors,
+            housekeeping_period_ms,
         )
     } else {
         let seccomp_filters: BpfThreadMap = seccomp_filters
@@ -338,6 +479,11 @@ fn main_exitable() -> ExitCode {
             vmm_config_json,
             instance_info,
             boot_timer_enabled,
+            boot_timeout_ms,
+            serial_config,
+            resource_limits,
+            recent_errors,
+            housekeeping_period_ms,
         )
     }
 }
@@ -420,12 +566,17 @@ fn print_snapshot_data_format(snapshot_path: &str) {
 }
 
 // Configure and start a microVM as described by the command-line JSON.
+#[allow(clippy::too_many_arguments)]
 fn build_microvm_from_json(
     seccomp_filters: &BpfThreadMap,
     event_manager: &mut EventManager,
     config_json: String,
     instance_info: InstanceInfo,
     boot_timer_enabled: bool,
+    boot_timeout_ms: Option<u64>,
+    serial_config: SerialConfig,
+    resource_limits: ResourceLimits,
+    recent_errors: RecentErrors,
 ) -> std::result::Result<(VmResources, Arc<Mutex<vmm::Vmm>>), ExitCode> {
     let mut vm_resources = VmResources::from_json(&config_json, &instance_info).map_err(|err| {
         error!(
@@ -435,6 +586,10 @@ fn build_microvm_from_json(
         vmm::FC_EXIT_CODE_BAD_CONFIGURATION
     })?;
     vm_resources.boot_timer = boot_timer_enabled;
+    vm_resources.boot_timeout_ms = boot_timeout_ms;
+    vm_resources.serial_config = serial_config;
+    vm_resources.resource_limits = resource_limits;
+    vm_resources.recent_errors = recent_errors;
     let vmm = vmm::builder::build_microvm_for_boot(
         &instance_info,
         &vm_resources,
@@ -446,18 +601,73 @@ fn build_microvm_from_json(
             "Building VMM configured from cmdline json failed: {:?}",
             err
         );
-        vmm::FC_EXIT_CODE_BAD_CONFIGURATION
+        match err {
+            StartMicrovmError::BootTimeoutWithSeccompFilter(_) => vmm::FC_EXIT_CODE_BOOT_TIMEOUT,
+            _ => vmm::FC_EXIT_CODE_BAD_CONFIGURATION,
+        }
     })?;
     info!("Successfully started microvm that was configured from one single json");
 
     Ok((vm_resources, vmm))
 }
 
+// Whether the microVM has to be booted again after it stopped with `exit_code`. Unless the
+// guest reboot is reported with its own exit code, it can't be told apart from the guest
+// halting, both stop the microVM cleanly.
+fn restart_on_reboot(vm_resources: &VmResources, exit_code: ExitCode) -> bool {
+    let reboot_exit_code = match vm_resources.vm_config().reset_action {
+        Some(ResetAction::ReportReboot) => vmm::FC_EXIT_CODE_GUEST_REBOOT,
+        _ => vmm::FC_EXIT_CODE_OK,
+    };
+    exit_code == reboot_exit_code
+        && vm_resources.vm_config().on_reboot == Some(RebootAction::Restart)
+        // A microVM restored from a snapshot can't be booted from scratch.
+        && vm_resources.boot_source().is_some()
+}
+
+// Boots the microVM again after the guest rebooted, with the same resources and new devices.
+// The `EventManager` which drove the previous microVM has to be dropped by now, along with all
+// the references to its `Vmm`, so that its devices released their host resources.
+fn restart_microvm(
+    seccomp_filters: &BpfThreadMap,
+    event_manager: &mut EventManager,
+    instance_info: &InstanceInfo,
+    vm_resources: &mut VmResources,
+) -> std::result::Result<Arc<Mutex<Vmm>>, ExitCode> {
+    info!("The guest rebooted, restarting the microVM.");
+    // The microVM restarted after a guest reboot runs right away, `start_paused` only applies
+    // to the boot requested through `InstanceStart`.
+    vm_resources.start_paused = false;
+    vm_resources.rebuild_devices().map_err(|err| {
+        error!("Rebuilding the devices of the microVM failed: {:?}", err);
+        vmm::FC_EXIT_CODE_GENERIC_ERROR
+    })?;
+    let vmm = vmm::builder::build_microvm_for_boot(
+        instance_info,
+        vm_resources,
+        event_manager,
+        seccomp_filters,
+    )
+    .map_err(|err| {
+        error!("Restarting the microVM failed: {:?}", err);
+        vmm::FC_EXIT_CODE_GENERIC_ERROR
+    })?;
+    METRICS.vmm.restarts.inc();
+
+    Ok(vmm)
+}
+
+#[allow(clippy::too_many_arguments)]
 fn run_without_api(
     seccomp_filters: &BpfThreadMap,
     config_json: Option<String>,
     instance_info: InstanceInfo,
     bool_timer_enabled: bool,
+    boot_timeout_ms: Option<u64>,
+    serial_config: SerialConfig,
+    resource_limits: ResourceLimits,
+    recent_errors: RecentErrors,
+    housekeeping_period_ms: u64,
 ) -> ExitCode {
     let mut event_manager = EventManager::new().expect("Unable to create EventManager");
 
@@ -465,14 +675,18 @@ fn run_without_api(
     let firecracker_metrics = Arc::new(Mutex::new(metrics::PeriodicMetrics::new()));
     event_manager.add_subscriber(firecracker_metrics.clone());
 
-    // Build the microVm. We can ignore VmResources since it's not used without api.
-    let (_, vmm) = match build_microvm_from_json(
+    // Build the microVm. VmResources are kept to boot the microVM again on guest reboot.
+    let (mut vm_resources, mut vmm) = match build_microvm_from_json(
         seccomp_filters,
         &mut event_manager,
         // Safe to unwrap since '--no-api' requires this to be set.
         config_json.unwrap(),
-        instance_info,
+        instance_info.clone(),
         bool_timer_enabled,
+        boot_timeout_ms,
+        serial_config,
+        resource_limits,
+        recent_errors,
     ) {
         Ok((res, vmm)) => (res, vmm),
         Err(exit_code) => return exit_code,
@@ -484,14 +698,28 @@ fn run_without_api(
         .expect("Poisoned lock")
         .start(metrics::WRITE_METRICS_PERIOD_MS);
 
-    // Run the EventManager that drives everything in the microVM.
     loop {
-        event_manager
-            .run()
-            .expect("Failed to start the event manager");
-
-        if let Some(exit_code) = vmm.lock().unwrap().shutdown_exit_code() {
+        // Run the EventManager that drives everything in the microVM.
+        let mut stall_detector = event_loop::StallDetector::new(housekeeping_period_ms);
+        let exit_code = event_loop::run(&mut event_manager, &vmm, housekeeping_period_ms, |_| {
+            stall_detector.check();
+        });
+        if !restart_on_reboot(&vm_resources, exit_code) {
             return exit_code;
         }
+
+        // Tear the stopped microVM down, before booting it again.
+        event_manager = EventManager::new().expect("Unable to create EventManager");
+        drop(vmm);
+        vmm = match restart_microvm(
+            seccomp_filters,
+            &mut event_manager,
+            &instance_info,
+            &mut vm_resources,
+        ) {
+            Ok(vmm) => vmm,
+            Err(exit_code) => return exit_code,
+        };
+        event_manager.add_subscriber(firecracker_metrics.clone());
     }
 }
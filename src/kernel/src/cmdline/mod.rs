@@ -117,6 +117,11 @@ impl Cmdline {
         self.line.len()
     }
 
+    /// Returns the capacity of the command line, which includes the nul terminator.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
     /// Returns whether the command line is empty.
     pub fn is_empty(&self) -> bool {
         self.len() == 0
@@ -225,6 +230,7 @@ mod tests {
     #[test]
     fn insert_string() {
         let mut cl = Cmdline::new(13);
+        assert_eq!(cl.capacity(), 13);
         assert_eq!(cl.as_str(), "");
         assert!(cl.insert_str("noapic").is_ok());
         assert_eq!(cl.as_str(), "noapic");
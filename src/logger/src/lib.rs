@@ -6,12 +6,12 @@ mod metrics;
 
 use std::sync::LockResult;
 
-pub use crate::logger::{LoggerError, LOGGER};
+pub use crate::logger::{ErrorHook, Logger, LoggerError, LOGGER};
 #[cfg(target_arch = "aarch64")]
 pub use crate::metrics::RTCDeviceMetrics;
 pub use crate::metrics::{
-    IncMetric, MetricsError, ProcessTimeReporter, SharedIncMetric, SharedStoreMetric, StoreMetric,
-    METRICS,
+    IncMetric, MetricsError, MetricsFormat, ProcessTimeReporter, RateLimiterMetrics,
+    SharedIncMetric, SharedStoreMetric, StoreMetric, METRICS,
 };
 pub use log::Level::*;
 pub use log::*;
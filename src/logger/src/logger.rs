@@ -100,6 +100,9 @@ pub type Result<T> = result::Result<T, LoggerError>;
 // Values used by the Logger.
 const DEFAULT_MAX_LEVEL: LevelFilter = LevelFilter::Warn;
 
+/// Function called for every error-level log message.
+pub type ErrorHook = Box<dyn Fn(&Record) + Send + Sync>;
+
 lazy_static! {
     static ref _LOGGER_INNER: Logger = Logger::new();
 
@@ -121,6 +124,13 @@ pub struct Logger {
     show_file_path: AtomicBool,
     show_line_numbers: AtomicBool,
     instance_id: RwLock<String>,
+    error_hook: RwLock<Option<ErrorHook>>,
+}
+
+impl Default for Logger {
+    fn default() -> Logger {
+        Logger::new()
+    }
 }
 
 impl Logger {
@@ -133,6 +143,7 @@ impl Logger {
             show_line_numbers: AtomicBool::new(true),
             show_file_path: AtomicBool::new(true),
             instance_id: RwLock::new(String::new()),
+            error_hook: RwLock::new(None),
         }
     }
 
@@ -245,6 +256,17 @@ impl Logger {
         self
     }
 
+    /// Sets the function called for every error-level log message, after it's written, which
+    /// replaces the one set before, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Function called with the log record of each error.
+    pub fn set_error_hook(&self, hook: ErrorHook) -> &Self {
+        *extract_guard(self.error_hook.write()) = Some(hook);
+        self
+    }
+
     /// Get the current thread's name.
     fn get_thread_name(&self) -> String {
         thread::current().name().unwrap_or("-").to_string()
@@ -365,6 +387,23 @@ impl Logger {
         Ok(())
     }
 
+    /// Replaces the destination of the logs, e.g. after the file or named pipe provided upon
+    /// initialization was rotated. The logger must have been initialized before.
+    ///
+    /// # Arguments
+    ///
+    /// * `log_dest` - Buffer for plain text logs. Needs to implements `Write` and `Send`.
+    pub fn reopen(&self, log_dest: Box<dyn Write + Send>) -> Result<()> {
+        if !self.init.is_initialized() {
+            return Err(LoggerError::NotInitialized);
+        }
+
+        let mut g = extract_guard(self.log_buf.lock());
+        *g = log_dest;
+
+        Ok(())
+    }
+
     /// The `write_log` method takes care of the common logic involved in writing
     /// regular log messages.
     fn write_log(&self, mut msg: String, msg_level: Level) {
@@ -395,12 +434,15 @@ impl Logger {
 pub enum LoggerError {
     /// Initialization Error.
     Init(init::Error),
+    /// The logger has not been initialized yet.
+    NotInitialized,
 }
 
 impl fmt::Display for LoggerError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let printable = match *self {
             LoggerError::Init(ref e) => format!("Logger initialization failure: {}", e),
+            LoggerError::NotInitialized => "The logger is not initialized.".to_string(),
         };
         write!(f, "{}", printable)
     }
@@ -421,6 +463,12 @@ impl Log for Logger {
             record.args()
         );
         self.write_log(msg, record.metadata().level());
+
+        if record.level() == Level::Error {
+            if let Some(hook) = extract_guard(self.error_hook.read()).as_ref() {
+                hook(record);
+            }
+        }
     }
 
     // This is currently not used.
@@ -708,6 +756,33 @@ mod tests {
         assert_eq!(r.is_ok(), true);
     }
 
+    #[test]
+    fn test_reopen() {
+        let logger = Logger::mock_new();
+        logger
+            .set_include_level(false)
+            .set_include_origin(false, false);
+        logger.set_instance_id("".to_string());
+
+        // The destination can't be replaced before initialization.
+        let (writer, _) = log_channel();
+        assert!(logger.reopen(Box::new(writer)).is_err());
+
+        let mut reader = logger.mock_init();
+        logger.mock_log(Level::Info, "before");
+        validate_log(&mut Box::new(&mut reader), "before\n");
+
+        let (writer, mut new_reader) = log_channel();
+        assert!(logger.reopen(Box::new(writer)).is_ok());
+        logger.mock_log(Level::Info, "after");
+        validate_log(&mut Box::new(&mut new_reader), "after\n");
+
+        // Nothing else reaches the old destination.
+        let mut log = Vec::new();
+        reader.read_to_end(&mut log).unwrap();
+        assert!(log.is_empty());
+    }
+
     #[test]
     fn test_static_logger() {
         log::set_max_level(log::LevelFilter::Info);
@@ -719,11 +794,40 @@ mod tests {
         validate_log(&mut Box::new(&mut reader), "info\n");
     }
 
+    #[test]
+    fn test_error_hook() {
+        let logger = Logger::new();
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let hook_errors = errors.clone();
+        logger.set_error_hook(Box::new(move |record| {
+            hook_errors.lock().unwrap().push(record.args().to_string())
+        }));
+
+        // Only the errors are passed to the hook.
+        logger.log(
+            &Record::builder()
+                .args(format_args!("warning"))
+                .level(Level::Warn)
+                .build(),
+        );
+        logger.log(
+            &Record::builder()
+                .args(format_args!("error"))
+                .level(Level::Error)
+                .build(),
+        );
+        assert_eq!(*errors.lock().unwrap(), vec!["error".to_string()]);
+    }
+
     #[test]
     fn test_error_messages() {
         assert_eq!(
             format!("{}", LoggerError::Init(init::Error::AlreadyInitialized)),
             "Logger initialization failure: The component is already initialized."
         );
+        assert_eq!(
+            format!("{}", LoggerError::NotInitialized),
+            "The logger is not initialized."
+        );
     }
 }
@@ -31,6 +31,18 @@
 //! named `block` which is in turn a serializable child structure collecting metrics for
 //! the block device such as `activate_fails`, `cfg_fails`, etc.
 //!
+//! ## OpenMetrics example
+//! The metrics can also be flushed in the OpenMetrics text format, which Prometheus scrapes
+//! directly. Each metric is named after its path in the JSON representation, and the metrics kept
+//! per device or rate limiter carry its name as a label:
+//! ```bash
+//! # TYPE firecracker_block_read_count gauge
+//! firecracker_block_read_count 0
+//! # TYPE firecracker_irqs gauge
+//! firecracker_irqs{device="net_eth0"} 12
+//! # EOF
+//! ```
+//!
 //! # Limitations
 //! Metrics are only written to buffers.
 //!
@@ -60,18 +72,19 @@
 //! If if turns out this approach is not really what we want, it's pretty easy to resort to
 //! something else, while working behind the same interface.
 
+use std::collections::BTreeMap;
 use std::fmt;
 use std::io::Write;
 use std::ops::Deref;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-#[cfg(target_arch = "aarch64")]
 use std::sync::Arc;
 use std::sync::Mutex;
 
 #[cfg(target_arch = "aarch64")]
 use crate::warn;
 use lazy_static::lazy_static;
-use serde::{Serialize, Serializer};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
 #[cfg(target_arch = "aarch64")]
 use vm_superio::rtc_pl031::RTCEvents;
 
@@ -88,6 +101,7 @@ lazy_static! {
 pub struct Metrics<T: Serialize> {
     // Metrics will get flushed here.
     metrics_buf: Mutex<Option<Box<dyn Write + Send>>>,
+    format: Mutex<MetricsFormat>,
     is_initialized: AtomicBool,
     pub app_metrics: T,
 }
@@ -99,6 +113,7 @@ impl<T: Serialize> Metrics<T> {
     pub fn new(app_metrics: T) -> Metrics<T> {
         Metrics {
             metrics_buf: Mutex::new(None),
+            format: Mutex::new(MetricsFormat::default()),
             is_initialized: AtomicBool::new(false),
             app_metrics,
         }
@@ -129,6 +144,30 @@ impl<T: Serialize> Metrics<T> {
         Ok(())
     }
 
+    /// Replaces the destination of the metrics, e.g. after the file or named pipe provided upon
+    /// initialization was rotated. The metrics system must have been initialized before.
+    ///
+    /// # Arguments
+    ///
+    /// * `metrics_dest` - Buffer for JSON formatted metrics. Needs to implement `Write` and `Send`.
+    pub fn reopen(&self, metrics_dest: Box<dyn Write + Send>) -> Result<(), MetricsError> {
+        if !self.is_initialized.load(Ordering::Relaxed) {
+            return Err(MetricsError::NeverInitialized(
+                "Metrics system is not initialized.".to_string(),
+            ));
+        }
+
+        let mut g = extract_guard(self.metrics_buf.lock());
+        *g = Some(metrics_dest);
+
+        Ok(())
+    }
+
+    /// Sets the format in which the metrics are written, JSON being the default.
+    pub fn set_format(&self, format: MetricsFormat) {
+        *extract_guard(self.format.lock()) = format;
+    }
+
     /// Writes metrics to the destination provided as argument upon initialization of the metrics.
     /// Upon failure, an error is returned if metrics system is initialized and metrics could not be
     /// written.
@@ -141,7 +180,11 @@ impl<T: Serialize> Metrics<T> {
     /// thread-safety on all its members.
     pub fn write(&self) -> Result<bool, MetricsError> {
         if self.is_initialized.load(Ordering::Relaxed) {
-            match serde_json::to_string(&self.app_metrics) {
+            let serialized = match *extract_guard(self.format.lock()) {
+                MetricsFormat::Json => serde_json::to_string(&self.app_metrics),
+                MetricsFormat::OpenMetrics => open_metrics_text(&self.app_metrics),
+            };
+            match serialized {
                 Ok(msg) => {
                     if let Some(guard) = extract_guard(self.metrics_buf.lock()).as_mut() {
                         // No need to explicitly call flush because the underlying LineWriter flushes
@@ -176,6 +219,97 @@ impl<T: Serialize> Deref for Metrics<T> {
     }
 }
 
+/// The formats in which the metrics can be written.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum MetricsFormat {
+    /// A JSON object holding all the metrics, on a single line.
+    Json,
+    /// The OpenMetrics text exposition format, which Prometheus can scrape.
+    OpenMetrics,
+}
+
+impl Default for MetricsFormat {
+    fn default() -> MetricsFormat {
+        MetricsFormat::Json
+    }
+}
+
+// The metrics kept in a map, by the name of a device or rate limiter, along with the label
+// holding that name in the OpenMetrics format.
+const OPEN_METRICS_LABELS: [(&str, &str); 3] = [
+    ("block_snapshot_flushes", "drive_id"),
+    ("irqs", "device"),
+    ("rate_limiters", "name"),
+];
+
+// Serializes the metrics in the OpenMetrics text format. The incremental metrics are reset upon
+// each flush, same as for the JSON format, so every metric is exposed as a gauge.
+fn open_metrics_text<T: Serialize>(metrics: &T) -> Result<String, serde_json::Error> {
+    let mut families = BTreeMap::new();
+    if let serde_json::Value::Object(groups) = serde_json::to_value(metrics)? {
+        for (group, value) in groups.iter() {
+            let label = OPEN_METRICS_LABELS
+                .iter()
+                .find(|(labeled_group, _)| *labeled_group == group.as_str())
+                .map(|(_, label)| *label);
+            match (label, value) {
+                (Some(label), serde_json::Value::Object(entries)) => {
+                    for (name, value) in entries.iter() {
+                        let labels = format!("{{{}=\"{}\"}}", label, escape_label_value(name));
+                        add_open_metrics_samples(&mut families, group, &labels, value);
+                    }
+                }
+                _ => add_open_metrics_samples(&mut families, group, "", value),
+            }
+        }
+    }
+
+    let mut text = String::new();
+    for (family, samples) in families.iter() {
+        text.push_str(&format!("# TYPE firecracker_{} gauge\n", family));
+        for (labels, value) in samples.iter() {
+            text.push_str(&format!("firecracker_{}{} {}\n", family, labels, value));
+        }
+    }
+    text.push_str("# EOF");
+    Ok(text)
+}
+
+// Adds the numeric metrics found in `value` to their families, named after their path from
+// `name`. The timestamp of the JSON format and the non-numeric values are left out.
+fn add_open_metrics_samples(
+    families: &mut BTreeMap<String, Vec<(String, u64)>>,
+    name: &str,
+    labels: &str,
+    value: &serde_json::Value,
+) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (field, value) in fields.iter() {
+                let name = format!("{}_{}", name, field);
+                add_open_metrics_samples(families, &name, labels, value);
+            }
+        }
+        serde_json::Value::Number(number) if name != "utc_timestamp_ms" => {
+            if let Some(number) = number.as_u64() {
+                families
+                    .entry(name.to_string())
+                    .or_default()
+                    .push((labels.to_string(), number));
+            }
+        }
+        _ => (),
+    }
+}
+
+// Escapes the characters which can't appear as such in an OpenMetrics label value.
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
 /// Describes the errors which may occur while handling metrics scenarios.
 #[derive(Debug)]
 pub enum MetricsError {
@@ -353,12 +487,20 @@ pub struct ApiServerMetrics {
 /// Metrics specific to GET API Requests for counting user triggered actions and/or failures.
 #[derive(Default, Serialize)]
 pub struct GetRequestsMetrics {
+    /// Number of GETs for getting the devices attached to the MMIO bus.
+    pub devices_count: SharedIncMetric,
     /// Number of GETs for getting information on the instance.
     pub instance_info_count: SharedIncMetric,
     /// Number of GETs for getting status on attaching machine configuration.
     pub machine_cfg_count: SharedIncMetric,
     /// Number of GETs for getting mmds.
     pub mmds_count: SharedIncMetric,
+    /// Number of GETs for getting the latest errors hit by the VMM.
+    pub recent_errors_count: SharedIncMetric,
+    /// Number of GETs for getting the guest serial output.
+    pub serial_log_count: SharedIncMetric,
+    /// Number of GETs for getting the registers of a vCPU.
+    pub vcpu_count: SharedIncMetric,
 }
 
 /// Metrics specific to PUT API Requests for counting user triggered actions and/or failures.
@@ -372,10 +514,18 @@ pub struct PutRequestsMetrics {
     pub boot_source_count: SharedIncMetric,
     /// Number of failures during attaching source of boot.
     pub boot_source_fails: SharedIncMetric,
+    /// Number of PUTs asking the guest driver to reset a device.
+    pub device_reset_count: SharedIncMetric,
+    /// Number of failures in asking the guest driver to reset a device.
+    pub device_reset_fails: SharedIncMetric,
     /// Number of PUTs triggering a block attach.
     pub drive_count: SharedIncMetric,
     /// Number of failures in attaching a block device.
     pub drive_fails: SharedIncMetric,
+    /// Number of PUTs triggering a virtio-fs device attach.
+    pub fs_count: SharedIncMetric,
+    /// Number of failures in attaching a virtio-fs device.
+    pub fs_fails: SharedIncMetric,
     /// Number of PUTs for initializing the logging system.
     pub logger_count: SharedIncMetric,
     /// Number of failures in initializing the logging system.
@@ -396,11 +546,23 @@ pub struct PutRequestsMetrics {
     pub mmds_count: SharedIncMetric,
     /// Number of failures in creating a new mmds.
     pub mmds_fails: SharedIncMetric,
+    /// Number of PUTs setting the full microVM configuration.
+    pub vm_config_count: SharedIncMetric,
+    /// Number of failures in setting the full microVM configuration.
+    pub vm_config_fails: SharedIncMetric,
 }
 
 /// Metrics specific to PATCH API Requests for counting user triggered actions and/or failures.
 #[derive(Default, Serialize)]
 pub struct PatchRequestsMetrics {
+    /// Number of tries to PATCH the boot source.
+    pub boot_source_count: SharedIncMetric,
+    /// Number of failures in PATCHing the boot source.
+    pub boot_source_fails: SharedIncMetric,
+    /// Number of tries to PATCH the instance information.
+    pub instance_info_count: SharedIncMetric,
+    /// Number of failures in PATCHing the instance information.
+    pub instance_info_fails: SharedIncMetric,
     /// Number of tries to PATCH a block device.
     pub drive_count: SharedIncMetric,
     /// Number of failures in PATCHing a block device.
@@ -434,6 +596,46 @@ pub struct BalloonDeviceMetrics {
     pub deflate_count: SharedIncMetric,
     /// Number of times when handling events on a balloon device failed.
     pub event_fails: SharedIncMetric,
+    /// Number of free page reports processed by the balloon device.
+    pub free_page_report_count: SharedIncMetric,
+    /// Number of bytes of reported free pages freed by the balloon device.
+    pub free_page_report_freed: SharedIncMetric,
+    /// Number of reported free page ranges which could not be freed.
+    pub free_page_report_fails: SharedIncMetric,
+}
+
+/// Virtio console device associated metrics.
+#[derive(Default, Serialize)]
+pub struct ConsoleDeviceMetrics {
+    /// Number of times when activate failed on the console device.
+    pub activate_fails: SharedIncMetric,
+    /// Number of times when handling events on the console device failed.
+    pub event_fails: SharedIncMetric,
+    /// Number of bytes of input delivered to the guest.
+    pub rx_bytes_count: SharedIncMetric,
+    /// Number of times the host input was paused because the guest did not consume it.
+    pub rx_paused_count: SharedIncMetric,
+    /// Number of bytes of guest output written to the host.
+    pub tx_bytes_count: SharedIncMetric,
+    /// Number of bytes of guest output dropped because the host output was not writable.
+    pub tx_dropped_bytes_count: SharedIncMetric,
+}
+
+/// Virtio-fs device associated metrics.
+#[derive(Default, Serialize)]
+pub struct FsDeviceMetrics {
+    /// Number of times when activate failed on a virtio-fs device.
+    pub activate_fails: SharedIncMetric,
+    /// Number of times when interacting with the space config of a virtio-fs device failed.
+    pub cfg_fails: SharedIncMetric,
+    /// Number of times when handling events on a virtio-fs device failed.
+    pub event_fails: SharedIncMetric,
+    /// Number of requests which could not be served, because they were malformed.
+    pub invalid_reqs_count: SharedIncMetric,
+    /// Number of events triggered on the queues of a virtio-fs device.
+    pub queue_event_count: SharedIncMetric,
+    /// Number of requests received by the virtio-fs devices.
+    pub request_count: SharedIncMetric,
 }
 
 /// Block Device associated metrics.
@@ -447,6 +649,8 @@ pub struct BlockDeviceMetrics {
     pub no_avail_buffer: SharedIncMetric,
     /// Number of times when handling events on a block device failed.
     pub event_fails: SharedIncMetric,
+    /// Number of used queue interrupts merged into a later one by the interrupt coalescing.
+    pub coalesced_irq_count: SharedIncMetric,
     /// Number of failures in executing a request on a block device.
     pub execute_fails: SharedIncMetric,
     /// Number of invalid requests received for this block device.
@@ -469,8 +673,37 @@ pub struct BlockDeviceMetrics {
     pub read_count: SharedIncMetric,
     /// Number of successful write operations.
     pub write_count: SharedIncMetric,
+    /// Number of successful write zeroes operations.
+    pub write_zeroes_count: SharedIncMetric,
     /// Number of rate limiter throttling events.
     pub rate_limiter_throttled_events: SharedIncMetric,
+    /// Number of requests served together with adjacent requests, by a single vectored IO.
+    pub coalesced_reqs_count: SharedIncMetric,
+}
+
+/// Flushes of the block devices backing files when snapshotting, by drive ID.
+#[derive(Default)]
+pub struct BlockSnapshotFlushesMetrics(Mutex<BTreeMap<String, Arc<SharedIncMetric>>>);
+
+impl BlockSnapshotFlushesMetrics {
+    /// Provides the flush counter of the drive `drive_id`, creating it if needed.
+    pub fn get(&self, drive_id: &str) -> Arc<SharedIncMetric> {
+        extract_guard(self.0.lock())
+            .entry(drive_id.to_string())
+            .or_default()
+            .clone()
+    }
+}
+
+impl Serialize for BlockSnapshotFlushesMetrics {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let flushes = extract_guard(self.0.lock());
+        let mut map = serializer.serialize_map(Some(flushes.len()))?;
+        for (drive_id, counter) in flushes.iter() {
+            map.serialize_entry(drive_id, counter.as_ref())?;
+        }
+        map.end()
+    }
 }
 
 /// Metrics specific to the i8042 device.
@@ -512,6 +745,9 @@ pub struct MmdsMetrics {
     pub rx_accepted_err: SharedIncMetric,
     /// Number of uncommon events encountered while processing packets through MMDS.
     pub rx_accepted_unusual: SharedIncMetric,
+    /// Number of frames heading to MMDS which were dropped since their source address isn't
+    /// allowed to reach it.
+    pub rx_source_not_allowed: SharedIncMetric,
     /// The number of buffers which couldn't be parsed as valid Ethernet frames by the MMDS.
     pub rx_bad_eth: SharedIncMetric,
     /// The total number of successful receive operations by the MMDS.
@@ -545,6 +781,12 @@ pub struct NetDeviceMetrics {
     pub no_tx_avail_buffer: SharedIncMetric,
     /// Number of times when handling events on a network device failed.
     pub event_fails: SharedIncMetric,
+    /// Number of used queue interrupts merged into a later one by the interrupt coalescing.
+    pub coalesced_irq_count: SharedIncMetric,
+    /// Number of frames left out of the packet capture because its writer fell behind.
+    pub pcap_dropped_frames: SharedIncMetric,
+    /// Number of times writing the packet capture failed.
+    pub pcap_write_fails: SharedIncMetric,
     /// Number of events associated with the receiving queue.
     pub rx_queue_event_count: SharedIncMetric,
     /// Number of events associated with the rate limiter installed on the receiving path.
@@ -567,6 +809,9 @@ pub struct NetDeviceMetrics {
     pub tap_read_fails: SharedIncMetric,
     /// Number of times writing to TAP failed.
     pub tap_write_fails: SharedIncMetric,
+    /// Number of times the TAP couldn't take more frames, which are sent again once it becomes
+    /// writable.
+    pub tap_write_backpressure: SharedIncMetric,
     /// Number of transmitted bytes.
     pub tx_bytes_count: SharedIncMetric,
     /// Number of malformed TX frames.
@@ -581,6 +826,8 @@ pub struct NetDeviceMetrics {
     pub tx_partial_reads: SharedIncMetric,
     /// Number of events associated with the transmitting queue.
     pub tx_queue_event_count: SharedIncMetric,
+    /// Number of times the transmission resumed after the TAP became writable again.
+    pub tx_tap_event_count: SharedIncMetric,
     /// Number of events associated with the rate limiter installed on the transmitting path.
     pub tx_rate_limiter_event_count: SharedIncMetric,
     /// Number of RX rate limiter throttling events.
@@ -619,6 +866,18 @@ pub struct PerformanceMetrics {
     pub vmm_pause_vm: SharedStoreMetric,
     /// Measures the microVM resuming duration, at the VMM level, in microseconds.
     pub vmm_resume_vm: SharedStoreMetric,
+    /// Measures the guest memory creation during the microVM boot, in microseconds.
+    pub vmm_boot_mem_init: SharedStoreMetric,
+    /// Measures the VM and vCPUs creation during the microVM boot, in microseconds.
+    pub vmm_boot_create_vm: SharedStoreMetric,
+    /// Measures the kernel and initrd loading during the microVM boot, in microseconds.
+    pub vmm_boot_load_kernel: SharedStoreMetric,
+    /// Measures the device attaching during the microVM boot, in microseconds.
+    pub vmm_boot_attach_devices: SharedStoreMetric,
+    /// Measures the vCPUs configuration and start during the microVM boot, in microseconds.
+    pub vmm_boot_configure_vcpus: SharedStoreMetric,
+    /// Measures the vCPUs resuming during the microVM boot, in microseconds.
+    pub vmm_boot_resume_vcpus: SharedStoreMetric,
 }
 
 /// Metrics specific to the RTC device.
@@ -648,11 +907,78 @@ impl RTCEvents for RTCDeviceMetrics {
     }
 }
 
+/// Metrics specific to a rate limiter.
+#[derive(Default, Serialize)]
+pub struct RateLimiterMetrics {
+    /// Number of times the rate limiter ran out of tokens.
+    pub exhausted_count: SharedIncMetric,
+    /// Time spent by the rate limiter in the blocked state, in nanoseconds.
+    pub throttled_ns: SharedIncMetric,
+}
+
+/// Metrics of the rate limiters, by the name of the rate limiter.
+#[derive(Default)]
+pub struct RateLimitersMetrics(Mutex<BTreeMap<String, Arc<RateLimiterMetrics>>>);
+
+impl RateLimitersMetrics {
+    /// Provides the metrics of the rate limiter called `name`, creating them if needed.
+    ///
+    /// Rate limiters which share a name share the metrics, so a device which is replaced keeps
+    /// its metrics.
+    pub fn get(&self, name: &str) -> Arc<RateLimiterMetrics> {
+        extract_guard(self.0.lock())
+            .entry(name.to_string())
+            .or_default()
+            .clone()
+    }
+}
+
+impl Serialize for RateLimitersMetrics {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let rate_limiters = extract_guard(self.0.lock());
+        let mut map = serializer.serialize_map(Some(rate_limiters.len()))?;
+        for (name, metrics) in rate_limiters.iter() {
+            map.serialize_entry(name, metrics.as_ref())?;
+        }
+        map.end()
+    }
+}
+
+/// Interrupt counters of the devices, by the name of the device.
+#[derive(Default)]
+pub struct IrqsMetrics(Mutex<BTreeMap<String, Arc<SharedIncMetric>>>);
+
+impl IrqsMetrics {
+    /// Provides the interrupt counter of the device called `name`, creating it if needed.
+    ///
+    /// The lock is only taken when the counter is handed to a device, so the device increments
+    /// it without contention.
+    pub fn get(&self, name: &str) -> Arc<SharedIncMetric> {
+        extract_guard(self.0.lock())
+            .entry(name.to_string())
+            .or_default()
+            .clone()
+    }
+}
+
+impl Serialize for IrqsMetrics {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let irqs = extract_guard(self.0.lock());
+        let mut map = serializer.serialize_map(Some(irqs.len()))?;
+        for (name, counter) in irqs.iter() {
+            map.serialize_entry(name, counter.as_ref())?;
+        }
+        map.end()
+    }
+}
+
 /// Metrics for the seccomp filtering.
 #[derive(Default, Serialize)]
 pub struct SeccompMetrics {
     /// Number of errors inside the seccomp filtering.
     pub num_faults: SharedIncMetric,
+    /// Number of threads running without filtering since their filter couldn't be loaded.
+    pub load_fails: SharedIncMetric,
 }
 
 /// Metrics specific to the UART device.
@@ -715,6 +1041,18 @@ pub struct VmmMetrics {
     pub device_events: SharedIncMetric,
     /// Metric for signaling a panic has occurred.
     pub panic_count: SharedIncMetric,
+    /// Number of times handling events kept the VMM thread from its periodic housekeeping.
+    pub event_loop_stalls: SharedIncMetric,
+    /// Number of times the microVM was booted again after the guest rebooted.
+    pub restarts: SharedIncMetric,
+    /// Number of kernel panics reported by the guest through the pvpanic device.
+    pub guest_panics: SharedIncMetric,
+    /// Number of snapshots requested by the guest through the snapshot trigger device.
+    pub guest_snapshots: SharedIncMetric,
+    /// Number of snapshots requested by the guest which couldn't be created.
+    pub guest_snapshot_fails: SharedIncMetric,
+    /// Number of vCPUs which didn't stop within the shutdown grace period.
+    pub vcpus_left_running: SharedIncMetric,
 }
 
 /// Vsock-related metrics.
@@ -752,6 +1090,8 @@ pub struct VsockDeviceMetrics {
     pub conns_killed: SharedIncMetric,
     /// Number of removed connections.
     pub conns_removed: SharedIncMetric,
+    /// Number of host-initiated connections refused because of the peer credentials.
+    pub conns_refused: SharedIncMetric,
     /// How many times the killq has been resynced.
     pub killq_resync: SharedIncMetric,
     /// How many flush fails have been seen.
@@ -784,10 +1124,18 @@ pub struct FirecrackerMetrics {
     pub balloon: BalloonDeviceMetrics,
     /// A block device's related metrics.
     pub block: BlockDeviceMetrics,
+    /// Metrics related to the flushes of the block devices taken by the snapshots.
+    pub block_snapshot_flushes: BlockSnapshotFlushesMetrics,
+    /// Metrics related to the virtio console device.
+    pub console: ConsoleDeviceMetrics,
+    /// Metrics related to the virtio-fs devices.
+    pub fs: FsDeviceMetrics,
     /// Metrics related to API GET requests.
     pub get_api_requests: GetRequestsMetrics,
     /// Metrics related to the i8042 device.
     pub i8042: I8042DeviceMetrics,
+    /// Metrics related to the interrupts raised by the MMIO devices.
+    pub irqs: IrqsMetrics,
     /// Metrics related to performance measurements.
     pub latencies_us: PerformanceMetrics,
     /// Logging related metrics.
@@ -800,6 +1148,8 @@ pub struct FirecrackerMetrics {
     pub patch_api_requests: PatchRequestsMetrics,
     /// Metrics related to API PUT requests.
     pub put_api_requests: PutRequestsMetrics,
+    /// Metrics related to the rate limiters of the devices.
+    pub rate_limiters: RateLimitersMetrics,
     #[cfg(target_arch = "aarch64")]
     /// Metrics related to the RTC device.
     pub rtc: Arc<RTCDeviceMetrics>,
@@ -846,6 +1196,30 @@ mod tests {
         assert!(m.init(Box::new(f.into_file()),).is_err());
     }
 
+    #[test]
+    fn test_reopen() {
+        let m = Metrics::new(FirecrackerMetrics::default());
+
+        // The destination can't be replaced before initialization.
+        let f = TempFile::new().expect("Failed to create temporary metrics file");
+        assert!(m.reopen(Box::new(f.into_file())).is_err());
+
+        let old = TempFile::new().expect("Failed to create temporary metrics file");
+        assert!(m.init(Box::new(old.as_file().try_clone().unwrap())).is_ok());
+        assert!(m.write().unwrap());
+
+        let new = TempFile::new().expect("Failed to create temporary metrics file");
+        assert!(m
+            .reopen(Box::new(new.as_file().try_clone().unwrap()))
+            .is_ok());
+        let old_len = old.as_file().metadata().unwrap().len();
+        assert!(m.write().unwrap());
+
+        // Metrics are only written to the new destination.
+        assert_eq!(old.as_file().metadata().unwrap().len(), old_len);
+        assert!(new.as_file().metadata().unwrap().len() > 0);
+    }
+
     #[test]
     fn test_shared_inc_metric() {
         let metric = Arc::new(SharedIncMetric::default());
@@ -881,6 +1255,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_shared_inc_metric_flush() {
+        let metric = Arc::new(SharedIncMetric::default());
+        metric.add(5);
+        assert_eq!(serde_json::to_string(metric.as_ref()).unwrap(), "5");
+        // A flush only reports what was counted since the previous flush.
+        assert_eq!(serde_json::to_string(metric.as_ref()).unwrap(), "0");
+        metric.inc();
+        assert_eq!(serde_json::to_string(metric.as_ref()).unwrap(), "1");
+
+        // Increments done while flushing are reported exactly once, by one of the flushes.
+        const NUM_INCREMENTS: usize = 100_000;
+        let r = metric.clone();
+        let handle = thread::spawn(move || {
+            for _ in 0..NUM_INCREMENTS {
+                r.inc();
+            }
+        });
+        let flush = || {
+            serde_json::to_string(metric.as_ref())
+                .unwrap()
+                .parse::<usize>()
+                .unwrap()
+        };
+        let mut reported = 0;
+        for _ in 0..100 {
+            reported += flush();
+        }
+        handle.join().unwrap();
+        reported += flush();
+        assert_eq!(reported, NUM_INCREMENTS);
+        // The total count is still available.
+        assert_eq!(metric.count(), 6 + NUM_INCREMENTS);
+    }
+
     #[test]
     fn test_shared_store_metric() {
         let m1 = Arc::new(SharedStoreMetric::default());
@@ -889,12 +1298,120 @@ mod tests {
         assert_eq!(1, m1.fetch());
     }
 
+    #[test]
+    fn test_rate_limiters_metrics() {
+        let rate_limiters = RateLimitersMetrics::default();
+        assert_eq!(serde_json::to_string(&rate_limiters).unwrap(), "{}");
+
+        rate_limiters.get("net_eth0_rx").exhausted_count.inc();
+        // The metrics are shared by rate limiters with the same name.
+        rate_limiters.get("net_eth0_rx").throttled_ns.add(100);
+        rate_limiters.get("block_rootfs");
+        assert_eq!(
+            serde_json::to_string(&rate_limiters).unwrap(),
+            "{\"block_rootfs\":{\"exhausted_count\":0,\"throttled_ns\":0},\
+             \"net_eth0_rx\":{\"exhausted_count\":1,\"throttled_ns\":100}}"
+        );
+
+        // The counters are reset when flushed.
+        assert_eq!(
+            serde_json::to_string(&rate_limiters).unwrap(),
+            "{\"block_rootfs\":{\"exhausted_count\":0,\"throttled_ns\":0},\
+             \"net_eth0_rx\":{\"exhausted_count\":0,\"throttled_ns\":0}}"
+        );
+    }
+
+    #[test]
+    fn test_block_snapshot_flushes_metrics() {
+        let flushes = BlockSnapshotFlushesMetrics::default();
+        assert_eq!(serde_json::to_string(&flushes).unwrap(), "{}");
+
+        flushes.get("rootfs").inc();
+        flushes.get("rootfs").inc();
+        flushes.get("scratch");
+        assert_eq!(
+            serde_json::to_string(&flushes).unwrap(),
+            "{\"rootfs\":2,\"scratch\":0}"
+        );
+    }
+
+    #[test]
+    fn test_irqs_metrics() {
+        let irqs = IrqsMetrics::default();
+        assert_eq!(serde_json::to_string(&irqs).unwrap(), "{}");
+
+        irqs.get("net_eth0").inc();
+        // The counter is shared by the devices with the same name.
+        irqs.get("net_eth0").add(2);
+        irqs.get("block_rootfs");
+        assert_eq!(
+            serde_json::to_string(&irqs).unwrap(),
+            "{\"block_rootfs\":0,\"net_eth0\":3}"
+        );
+
+        // The counters are reset when flushed.
+        assert_eq!(
+            serde_json::to_string(&irqs).unwrap(),
+            "{\"block_rootfs\":0,\"net_eth0\":0}"
+        );
+    }
+
     #[test]
     fn test_serialize() {
         let s = serde_json::to_string(&FirecrackerMetrics::default());
         assert!(s.is_ok());
     }
 
+    #[test]
+    fn test_open_metrics_text() {
+        let metrics = FirecrackerMetrics::default();
+        metrics.block.read_count.add(5);
+        metrics.api_server.process_startup_time_us.store(100);
+        metrics.irqs.get("net_eth0").add(12);
+        metrics
+            .rate_limiters
+            .get("net_\"eth0\"_rx")
+            .exhausted_count
+            .inc();
+
+        let text = open_metrics_text(&metrics).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines.contains(&"# TYPE firecracker_block_read_count gauge"));
+        assert!(lines.contains(&"firecracker_block_read_count 5"));
+        assert!(lines.contains(&"firecracker_api_server_process_startup_time_us 100"));
+        assert!(lines.contains(&"# TYPE firecracker_irqs gauge"));
+        assert!(lines.contains(&"firecracker_irqs{device=\"net_eth0\"} 12"));
+        assert!(lines.contains(
+            &"firecracker_rate_limiters_exhausted_count{name=\"net_\\\"eth0\\\"_rx\"} 1"
+        ));
+        assert!(!text.contains("utc_timestamp_ms"));
+        assert_eq!(lines.last(), Some(&"# EOF"));
+
+        // Each family is described once, before its samples.
+        let type_lines = lines.iter().filter(|line| line.starts_with("# TYPE"));
+        let samples = lines.iter().filter(|line| !line.starts_with('#'));
+        assert_eq!(type_lines.count(), samples.count());
+
+        // The incremental metrics are reset by the flush, same as for the JSON format.
+        let text = open_metrics_text(&metrics).unwrap();
+        assert!(text
+            .lines()
+            .any(|line| line == "firecracker_block_read_count 0"));
+    }
+
+    #[test]
+    fn test_write_open_metrics() {
+        let m = Metrics::new(FirecrackerMetrics::default());
+        m.set_format(MetricsFormat::OpenMetrics);
+        let f = TempFile::new().expect("Failed to create temporary metrics file");
+        assert!(m.init(Box::new(f.as_file().try_clone().unwrap())).is_ok());
+        assert!(m.write().unwrap());
+
+        let text = std::fs::read_to_string(f.as_path()).unwrap();
+        assert!(text.starts_with("# TYPE firecracker_"));
+        assert!(text.ends_with("# EOF\n"));
+    }
+
     #[test]
     fn test_error_messages() {
         assert_eq!(
@@ -1,14 +1,34 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::fmt;
 
+use crate::token::{Error as TokenError, TokenAuthority};
+
 /// The Mmds is the Microvm Metadata Service represented as an untyped json.
-#[derive(Clone)]
 pub struct Mmds {
     data_store: Value,
     is_initialized: bool,
+    version: MmdsVersion,
+    // Only available when session tokens are required.
+    token_authority: Option<TokenAuthority>,
+}
+
+/// MMDS versions, which differ in how guest applications are authorized to retrieve metadata.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum MmdsVersion {
+    /// Metadata can be retrieved without a session token.
+    V1,
+    /// A session token has to be obtained before retrieving metadata.
+    V2,
+}
+
+impl Default for MmdsVersion {
+    fn default() -> Self {
+        MmdsVersion::V1
+    }
 }
 
 /// MMDS possible outputs.
@@ -19,6 +39,8 @@ pub enum OutputFormat {
 
 #[derive(Debug, PartialEq)]
 pub enum Error {
+    NonObjectPatch,
+    NonObjectRoot,
     NotFound,
     NotInitialized,
     UnsupportedValueType,
@@ -27,6 +49,15 @@ pub enum Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
+            Error::NonObjectPatch => write!(
+                f,
+                "The MMDS patch is not a JSON object. Use PUT to replace the whole data store."
+            ),
+            Error::NonObjectRoot => write!(
+                f,
+                "The MMDS data store is not a JSON object, so it cannot be patched. Use PUT to \
+                 replace it."
+            ),
             Error::NotFound => write!(f, "The MMDS resource does not exist."),
             Error::NotInitialized => write!(f, "The MMDS data store is not initialized."),
             Error::UnsupportedValueType => write!(
@@ -42,6 +73,8 @@ impl Default for Mmds {
         Mmds {
             data_store: Value::default(),
             is_initialized: false,
+            version: MmdsVersion::default(),
+            token_authority: None,
         }
     }
 }
@@ -58,14 +91,47 @@ impl Mmds {
         }
     }
 
+    /// Sets the MMDS version. Switching to a version which requires session tokens invalidates
+    /// any previously generated token.
+    pub fn set_version(&mut self, version: MmdsVersion) -> Result<(), TokenError> {
+        self.token_authority = match version {
+            MmdsVersion::V1 => None,
+            MmdsVersion::V2 => Some(TokenAuthority::new()?),
+        };
+        self.version = version;
+        Ok(())
+    }
+
+    /// Returns the MMDS version.
+    pub fn version(&self) -> MmdsVersion {
+        self.version
+    }
+
+    /// Returns the authority which manages session tokens, if the MMDS version requires them.
+    pub fn token_authority(&mut self) -> Option<&mut TokenAuthority> {
+        self.token_authority.as_mut()
+    }
+
     pub fn put_data(&mut self, data: Value) -> Result<(), Error> {
         self.data_store = data;
         self.is_initialized = true;
         Ok(())
     }
 
+    /// Updates the data store with `patch_data`, following the JSON Merge Patch semantics
+    /// (RFC 7396): the null values remove their keys, the objects are merged recursively and
+    /// the other values replace the existing ones.
+    ///
+    /// Both the data store and the patch have to be JSON objects, the data store is otherwise
+    /// replaced through `put_data`.
     pub fn patch_data(&mut self, patch_data: Value) -> Result<(), Error> {
         self.check_data_store_initialized()?;
+        if !self.data_store.is_object() {
+            return Err(Error::NonObjectRoot);
+        }
+        if !patch_data.is_object() {
+            return Err(Error::NonObjectPatch);
+        }
         super::json_patch(&mut self.data_store, &patch_data);
         Ok(())
     }
@@ -196,6 +262,30 @@ mod tests {
         assert_eq!(mmds.get_data_str(), mmds_json);
     }
 
+    #[test]
+    fn test_set_version() {
+        let mut mmds = Mmds::default();
+        assert_eq!(mmds.version(), MmdsVersion::V1);
+        assert!(mmds.token_authority().is_none());
+
+        mmds.set_version(MmdsVersion::V2).unwrap();
+        assert_eq!(mmds.version(), MmdsVersion::V2);
+        let token = mmds
+            .token_authority()
+            .unwrap()
+            .generate_token_secret(60)
+            .unwrap();
+        assert!(mmds.token_authority().unwrap().is_valid(&token));
+
+        // Setting the version again invalidates the existing tokens.
+        mmds.set_version(MmdsVersion::V2).unwrap();
+        assert!(!mmds.token_authority().unwrap().is_valid(&token));
+
+        mmds.set_version(MmdsVersion::V1).unwrap();
+        assert_eq!(mmds.version(), MmdsVersion::V1);
+        assert!(mmds.token_authority().is_none());
+    }
+
     #[test]
     fn test_get_value() {
         let mut mmds = Mmds::default();
@@ -386,5 +476,24 @@ mod tests {
         }"#;
         let data_store: Value = serde_json::from_str(data).unwrap();
         assert!(mmds.patch_data(data_store).is_ok());
+        assert_eq!(
+            mmds.get_data_str(),
+            r#"{"age":"43","name":{"first":"John"}}"#
+        );
+
+        // The patch has to be an object.
+        assert_eq!(
+            mmds.patch_data(Value::String("John".to_string())),
+            Err(Error::NonObjectPatch)
+        );
+        assert_eq!(mmds.patch_data(Value::Null), Err(Error::NonObjectPatch));
+
+        // Only an object can be patched.
+        assert!(mmds.put_data(Value::String("John".to_string())).is_ok());
+        assert_eq!(
+            mmds.patch_data(serde_json::json!({"age": "43"})),
+            Err(Error::NonObjectRoot)
+        );
+        assert_eq!(mmds.get_data_str(), r#""John""#);
     }
 }
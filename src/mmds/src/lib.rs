@@ -4,11 +4,13 @@
 pub mod data_store;
 pub mod ns;
 pub mod persist;
+pub mod token;
 
 use serde_json::{Map, Value};
 use std::sync::{Arc, Mutex};
 
-use crate::data_store::{Error as MmdsError, Mmds, OutputFormat};
+use crate::data_store::{Error as MmdsError, Mmds, MmdsVersion, OutputFormat};
+use crate::token::{X_METADATA_TOKEN_HEADER, X_METADATA_TOKEN_TTL_SECONDS_HEADER};
 use lazy_static::lazy_static;
 use micro_http::{Body, MediaType, Method, Request, Response, StatusCode, Version};
 
@@ -19,6 +21,9 @@ lazy_static! {
     pub static ref MMDS: Arc<Mutex<Mmds>> = Arc::new(Mutex::new(Mmds::default()));
 }
 
+// Path on which guest applications can obtain session tokens.
+const TOKEN_PATH: &str = "/latest/api/token";
+
 impl From<MediaType> for OutputFormat {
     fn from(media_type: MediaType) -> Self {
         match media_type {
@@ -76,7 +81,23 @@ fn sanitize_uri(mut uri: String) -> String {
     uri
 }
 
+// Returns the value of the custom header `name` of `request`. Header names are case insensitive.
+fn custom_header<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request
+        .headers
+        .custom_entries()
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
 fn convert_to_response(request: Request) -> Response {
+    // The lock can be held by one thread only, so it is safe to unwrap.
+    // If another thread poisoned the lock, we abort the execution.
+    respond_to_request(&mut MMDS.lock().expect("Poisoned lock"), request)
+}
+
+fn respond_to_request(mmds: &mut Mmds, request: Request) -> Response {
     let uri = request.uri().get_abs_path();
     if uri.is_empty() {
         return build_response(
@@ -86,26 +107,121 @@ fn convert_to_response(request: Request) -> Response {
         );
     }
 
-    if request.method() != Method::Get {
-        let mut response = build_response(
-            request.http_version(),
-            StatusCode::MethodNotAllowed,
-            Body::new("Not allowed HTTP method."),
-        );
-        response.allow_method(Method::Get);
-        return response;
-    }
-
     // The data store expects a strict json path, so we need to
     // sanitize the URI.
     let json_pointer = sanitize_uri(uri.to_string());
 
-    // The lock can be held by one thread only, so it is safe to unwrap.
-    // If another thread poisoned the lock, we abort the execution.
-    let response = MMDS
-        .lock()
-        .expect("Poisoned lock")
-        .get_value(json_pointer, request.headers.accept().into());
+    match mmds.version() {
+        MmdsVersion::V1 => {
+            if request.method() != Method::Get {
+                let mut response = build_response(
+                    request.http_version(),
+                    StatusCode::MethodNotAllowed,
+                    Body::new("Not allowed HTTP method."),
+                );
+                response.allow_method(Method::Get);
+                return response;
+            }
+
+            respond_to_get_request(mmds, &request, json_pointer)
+        }
+        MmdsVersion::V2 => match request.method() {
+            Method::Get => {
+                let is_valid_token = match custom_header(&request, X_METADATA_TOKEN_HEADER) {
+                    Some(token) => mmds
+                        .token_authority()
+                        .map_or(false, |authority| authority.is_valid(token)),
+                    None => {
+                        return build_response(
+                            request.http_version(),
+                            StatusCode::Unauthorized,
+                            Body::new(format!(
+                                "No MMDS token provided. Use `{}` header to specify the session \
+                                 token.",
+                                X_METADATA_TOKEN_HEADER
+                            )),
+                        )
+                    }
+                };
+
+                if !is_valid_token {
+                    return build_response(
+                        request.http_version(),
+                        StatusCode::Unauthorized,
+                        Body::new("MMDS token not valid.".to_string()),
+                    );
+                }
+
+                respond_to_get_request(mmds, &request, json_pointer)
+            }
+            Method::Put if json_pointer == TOKEN_PATH => respond_to_token_request(mmds, &request),
+            Method::Put => build_response(
+                request.http_version(),
+                StatusCode::NotFound,
+                Body::new(format!("Resource not found: {}.", uri)),
+            ),
+            _ => {
+                let mut response = build_response(
+                    request.http_version(),
+                    StatusCode::MethodNotAllowed,
+                    Body::new("Not allowed HTTP method."),
+                );
+                response.allow_method(Method::Get);
+                response.allow_method(Method::Put);
+                response
+            }
+        },
+    }
+}
+
+fn respond_to_token_request(mmds: &mut Mmds, request: &Request) -> Response {
+    let ttl_seconds = match custom_header(request, X_METADATA_TOKEN_TTL_SECONDS_HEADER)
+        .map(|value| value.parse::<u32>())
+    {
+        Some(Ok(ttl_seconds)) => ttl_seconds,
+        _ => {
+            return build_response(
+                request.http_version(),
+                StatusCode::BadRequest,
+                Body::new(format!(
+                    "Token time to live value not found. Use `{}` header to specify the token's \
+                     lifetime.",
+                    X_METADATA_TOKEN_TTL_SECONDS_HEADER
+                )),
+            )
+        }
+    };
+
+    let result = match mmds.token_authority() {
+        Some(authority) => authority.generate_token_secret(ttl_seconds),
+        // Tokens are only handed out when they are required.
+        None => {
+            return build_response(
+                request.http_version(),
+                StatusCode::NotFound,
+                Body::new(format!("Resource not found: {}.", TOKEN_PATH)),
+            )
+        }
+    };
+
+    match result {
+        Ok(token) => build_response(request.http_version(), StatusCode::OK, Body::new(token)),
+        Err(e @ token::Error::InvalidTtlValue(_)) => build_response(
+            request.http_version(),
+            StatusCode::BadRequest,
+            Body::new(e.to_string()),
+        ),
+        Err(e) => build_response(
+            request.http_version(),
+            StatusCode::InternalServerError,
+            Body::new(e.to_string()),
+        ),
+    }
+}
+
+fn respond_to_get_request(mmds: &Mmds, request: &Request, json_pointer: String) -> Response {
+    let uri = request.uri().get_abs_path();
+    let response = mmds.get_value(json_pointer, request.headers.accept().into());
 
     match response {
         Ok(response_body) => build_response(
@@ -127,7 +243,9 @@ fn convert_to_response(request: Request) -> Response {
                 StatusCode::NotImplemented,
                 Body::new(e.to_string()),
             ),
-            MmdsError::NotInitialized => unreachable!(),
+            MmdsError::NonObjectPatch | MmdsError::NonObjectRoot | MmdsError::NotInitialized => {
+                unreachable!()
+            }
         },
     }
 }
@@ -136,6 +254,10 @@ fn convert_to_response(request: Request) -> Response {
 mod tests {
     use super::*;
 
+    fn respond(mmds: &mut Mmds, request_bytes: &[u8]) -> Response {
+        respond_to_request(mmds, Request::try_from(request_bytes).unwrap())
+    }
+
     #[test]
     fn test_sanitize_uri() {
         let sanitized = "/a/b/c/d";
@@ -240,6 +362,114 @@ mod tests {
         assert_eq!(actual_response, expected_response);
     }
 
+    #[test]
+    fn test_respond_to_request_mmdsv2() {
+        let mut mmds = Mmds::default();
+        mmds.put_data(serde_json::json!({"age": "43"})).unwrap();
+        mmds.set_version(MmdsVersion::V2).unwrap();
+
+        // Test no token provided.
+        let mut expected_response = Response::new(Version::Http11, StatusCode::Unauthorized);
+        expected_response.set_body(Body::new(
+            "No MMDS token provided. Use `X-metadata-token` header to specify the session token."
+                .to_string(),
+        ));
+        assert_eq!(
+            respond(&mut mmds, b"GET /age HTTP/1.1\r\n\r\n"),
+            expected_response
+        );
+
+        // Test invalid token.
+        let mut expected_response = Response::new(Version::Http11, StatusCode::Unauthorized);
+        expected_response.set_body(Body::new("MMDS token not valid.".to_string()));
+        assert_eq!(
+            respond(
+                &mut mmds,
+                b"GET /age HTTP/1.1\r\nX-metadata-token: foo\r\n\r\n"
+            ),
+            expected_response
+        );
+
+        // Test token request without a time to live.
+        let response = respond(&mut mmds, b"PUT /latest/api/token HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status(), StatusCode::BadRequest);
+
+        // Test token request with an invalid time to live.
+        let mut expected_response = Response::new(Version::Http11, StatusCode::BadRequest);
+        expected_response.set_body(Body::new(token::Error::InvalidTtlValue(0).to_string()));
+        assert_eq!(
+            respond(
+                &mut mmds,
+                b"PUT /latest/api/token HTTP/1.1\r\n\
+                  X-metadata-token-ttl-seconds: 0\r\n\r\n",
+            ),
+            expected_response
+        );
+
+        // Test PUT on a path other than the token one.
+        let response = respond(
+            &mut mmds,
+            b"PUT /age HTTP/1.1\r\nX-metadata-token-ttl-seconds: 60\r\n\r\n",
+        );
+        assert_eq!(response.status(), StatusCode::NotFound);
+
+        // Test not allowed HTTP method.
+        let mut expected_response = Response::new(Version::Http11, StatusCode::MethodNotAllowed);
+        expected_response.set_body(Body::new("Not allowed HTTP method.".to_string()));
+        expected_response.allow_method(Method::Get);
+        expected_response.allow_method(Method::Put);
+        assert_eq!(
+            respond(&mut mmds, b"PATCH /age HTTP/1.1\r\n\r\n"),
+            expected_response
+        );
+
+        // Obtain a valid token and use it. Header names are case insensitive.
+        let response = respond(
+            &mut mmds,
+            b"PUT /latest/api/token HTTP/1.1\r\n\
+              x-metadata-token-ttl-seconds: 60\r\n\r\n",
+        );
+        assert_eq!(response.status(), StatusCode::OK);
+        let token = String::from_utf8(response.body().unwrap().body).unwrap();
+
+        let request_bytes = format!(
+            "GET /age HTTP/1.1\r\nAccept: application/json\r\nX-metadata-token: {}\r\n\r\n",
+            token
+        );
+        let mut expected_response = Response::new(Version::Http11, StatusCode::OK);
+        expected_response.set_body(Body::new("\"43\"".to_string()));
+        assert_eq!(
+            respond(&mut mmds, request_bytes.as_bytes()),
+            expected_response
+        );
+
+        // Tokens are not handed out by MMDS version 1.
+        mmds.set_version(MmdsVersion::V1).unwrap();
+        let response = respond(
+            &mut mmds,
+            b"PUT /latest/api/token HTTP/1.1\r\n\
+              X-metadata-token-ttl-seconds: 60\r\n\r\n",
+        );
+        assert_eq!(response.status(), StatusCode::MethodNotAllowed);
+    }
+
+    #[test]
+    fn test_custom_header() {
+        let request = Request::try_from(
+            b"PUT /latest/api/token HTTP/1.1\r\n\
+              Accept: application/json\r\n\
+              x-METADATA-token-ttl-seconds: 60\r\n\r\n",
+        )
+        .unwrap();
+
+        // Header names are case insensitive.
+        assert_eq!(
+            custom_header(&request, X_METADATA_TOKEN_TTL_SECONDS_HEADER),
+            Some("60")
+        );
+        assert_eq!(custom_header(&request, X_METADATA_TOKEN_HEADER), None);
+    }
+
     #[test]
     fn test_json_patch() {
         let mut data = serde_json::json!({
@@ -5,7 +5,7 @@
 #![allow(missing_docs)]
 
 use std::convert::From;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::num::NonZeroUsize;
 use std::result::Result;
 
@@ -13,16 +13,22 @@ use dumbo::pdu::arp::{
     test_speculative_tpa, Error as ArpFrameError, EthIPv4ArpFrame, ETH_IPV4_FRAME_LEN,
 };
 use dumbo::pdu::ethernet::{
-    Error as EthernetFrameError, EthernetFrame, ETHERTYPE_ARP, ETHERTYPE_IPV4,
+    Error as EthernetFrameError, EthernetFrame, ETHERTYPE_ARP, ETHERTYPE_IPV4, ETHERTYPE_IPV6,
 };
 use dumbo::pdu::ipv4::{
     test_speculative_dst_addr, Error as IPv4PacketError, IPv4Packet, PROTOCOL_TCP,
 };
+use dumbo::pdu::ipv6::{
+    self, solicited_node_multicast_addr, Error as IPv6PacketError, IPv6Packet, IPV6_VERSION,
+    PROTOCOL_ICMPV6,
+};
+use dumbo::pdu::ndp::{self, Error as NdpError, NeighborMessage, NEIGHBOR_ADVERTISEMENT_LEN};
 use dumbo::pdu::tcp::Error as TcpSegmentError;
 use dumbo::pdu::Incomplete;
-use dumbo::tcp::handler::{self, RecvEvent, TcpIPv4Handler, WriteEvent};
+use dumbo::tcp::handler::{self, RecvError, RecvEvent, TcpIPv4Handler, WriteEvent};
 use dumbo::tcp::NextSegmentStatus;
 use logger::{IncMetric, METRICS};
+use utils::net::ip_prefix::IpPrefix;
 use utils::net::mac::MacAddr;
 use utils::time::timestamp_cycles;
 
@@ -39,9 +45,18 @@ enum WriteArpFrameError {
     Ethernet(EthernetFrameError),
 }
 
+#[cfg_attr(test, derive(Debug, PartialEq))]
+enum WriteNdpFrameError {
+    NoPendingNdpReply,
+    Ndp(NdpError),
+    Ethernet(EthernetFrameError),
+    IPv6Packet(IPv6PacketError),
+}
+
 #[cfg_attr(test, derive(Debug, PartialEq))]
 enum WritePacketError {
     IPv4Packet(IPv4PacketError),
+    IPv6Packet(IPv6PacketError),
     Ethernet(EthernetFrameError),
     TcpSegment(TcpSegmentError),
 }
@@ -50,6 +65,7 @@ impl From<handler::WriteNextError> for WritePacketError {
     fn from(error: handler::WriteNextError) -> Self {
         match error {
             handler::WriteNextError::IPv4Packet(inner) => WritePacketError::IPv4Packet(inner),
+            handler::WriteNextError::IPv6Packet(inner) => WritePacketError::IPv6Packet(inner),
             handler::WriteNextError::TcpSegment(inner) => WritePacketError::TcpSegment(inner),
         }
     }
@@ -66,6 +82,13 @@ pub struct MmdsNetworkStack {
     // It is the Ipv4Addr of the network interface for which the MmdsNetworkStack
     // routes the packets.
     pending_arp_reply_dest: Option<Ipv4Addr>,
+    // MMDS server IPv6 address. Requests over IPv6 are only served when this is configured.
+    pub(crate) ipv6_addr: Option<Ipv6Addr>,
+    // Neighbor Advertisement destination IPv6 address (requester of address resolution reply).
+    pending_ndp_reply_dest: Option<Ipv6Addr>,
+    // The prefixes holding the guest addresses which may reach the MMDS. Every address is allowed
+    // when this is empty.
+    pub(crate) allowed_sources: Vec<IpPrefix>,
     // This handles MMDS<->guest interaction at the TCP level.
     pub(crate) tcp_handler: TcpIPv4Handler,
 }
@@ -83,6 +106,9 @@ impl MmdsNetworkStack {
             mac_addr,
             ipv4_addr,
             pending_arp_reply_dest: None,
+            ipv6_addr: None,
+            pending_ndp_reply_dest: None,
+            allowed_sources: Vec::new(),
             tcp_handler: TcpIPv4Handler::new(
                 ipv4_addr,
                 tcp_port,
@@ -116,20 +142,59 @@ impl MmdsNetworkStack {
         Ipv4Addr::from(DEFAULT_IPV4_ADDR)
     }
 
+    /// Enables (or disables, when `None` is provided) answering MMDS requests over IPv6.
+    pub fn set_ipv6_addr(&mut self, ipv6_addr: Option<Ipv6Addr>) {
+        self.ipv6_addr = ipv6_addr;
+        self.pending_ndp_reply_dest = None;
+        self.tcp_handler.set_local_ipv6_addr(ipv6_addr);
+    }
+
+    /// Restricts the guest addresses from which the MMDS can be reached to the ones belonging to
+    /// `allowed_sources`. Passing an empty list allows any address.
+    pub fn set_allowed_sources(&mut self, allowed_sources: Vec<IpPrefix>) {
+        self.allowed_sources = allowed_sources;
+    }
+
+    /// Provides the prefixes of the guest addresses allowed to reach the MMDS.
+    pub fn allowed_sources(&self) -> &[IpPrefix] {
+        &self.allowed_sources
+    }
+
+    // Says if the MMDS answers the frames coming from `addr`. The frames of the other addresses
+    // are dropped, so they neither reach the MMDS nor the tap.
+    fn is_allowed_source(&self, addr: IpAddr) -> bool {
+        let allowed = self.allowed_sources.is_empty()
+            || self
+                .allowed_sources
+                .iter()
+                .any(|prefix| prefix.contains(addr));
+        if !allowed {
+            METRICS.mmds.rx_source_not_allowed.inc();
+        }
+        allowed
+    }
+
     // This is the entry point into the MMDS network stack. The src slice should hold the contents
     // of an Ethernet frame (of that exact size, without the CRC).
     pub fn detour_frame(&mut self, src: &[u8]) -> bool {
-        // The frame cannot possibly contain an ARP request or IPv4 packet for the MMDS.
-        if !test_speculative_tpa(src, self.ipv4_addr)
-            && !test_speculative_dst_addr(src, self.ipv4_addr)
-        {
+        let maybe_ipv4 = test_speculative_tpa(src, self.ipv4_addr)
+            || test_speculative_dst_addr(src, self.ipv4_addr);
+        // Address resolution requests are sent to the solicited-node multicast address.
+        let maybe_ipv6 = self.ipv6_addr.map_or(false, |addr| {
+            ipv6::test_speculative_dst_addr(src, addr)
+                || ipv6::test_speculative_dst_addr(src, solicited_node_multicast_addr(addr))
+        });
+
+        // The frame cannot possibly contain an ARP request, NDP request or IP packet for the MMDS.
+        if !maybe_ipv4 && !maybe_ipv6 {
             return false;
         }
 
         if let Ok(eth) = EthernetFrame::from_bytes(src) {
             match eth.ethertype() {
-                ETHERTYPE_ARP => return self.detour_arp(eth),
-                ETHERTYPE_IPV4 => return self.detour_ipv4(eth),
+                ETHERTYPE_ARP if maybe_ipv4 => return self.detour_arp(eth),
+                ETHERTYPE_IPV4 if maybe_ipv4 => return self.detour_ipv4(eth),
+                ETHERTYPE_IPV6 if maybe_ipv6 => return self.detour_ipv6(eth),
                 _ => (),
             };
         } else {
@@ -141,6 +206,9 @@ impl MmdsNetworkStack {
 
     fn detour_arp(&mut self, eth: EthernetFrame<&[u8]>) -> bool {
         if let Ok(arp) = EthIPv4ArpFrame::request_from_bytes(eth.payload()) {
+            if !self.is_allowed_source(IpAddr::V4(arp.spa())) {
+                return true;
+            }
             self.remote_mac_addr = arp.sha();
             self.pending_arp_reply_dest = Some(arp.spa());
             return true;
@@ -154,36 +222,71 @@ impl MmdsNetworkStack {
         // checksum computation from the guest driver to some other entity. Clear up this entire
         // context at some point!
         if let Ok(ip) = IPv4Packet::from_bytes(eth.payload(), false) {
+            if !self.is_allowed_source(IpAddr::V4(ip.source_address())) {
+                return true;
+            }
             if ip.protocol() == PROTOCOL_TCP {
                 // Note-1: `remote_mac_address` is actually the network device mac address, where
                 // this TCP segment came from.
                 // Note-2: For every routed packet we will have a single source MAC address, because
                 // each MmdsNetworkStack routes packets for only one network device.
                 self.remote_mac_addr = eth.src_mac();
-                match self
+                let result = self
                     .tcp_handler
-                    .receive_packet(&ip, super::convert_to_response)
-                {
-                    Ok(event) => {
-                        METRICS.mmds.rx_count.inc();
-                        match event {
-                            RecvEvent::NewConnectionSuccessful => {
-                                METRICS.mmds.connections_created.inc()
-                            }
-                            RecvEvent::NewConnectionReplacing => {
-                                METRICS.mmds.connections_created.inc();
-                                METRICS.mmds.connections_destroyed.inc();
-                            }
-                            RecvEvent::EndpointDone => {
-                                METRICS.mmds.connections_destroyed.inc();
-                            }
-                            _ => (),
+                    .receive_packet(&ip, super::convert_to_response);
+                Self::update_recv_metrics(result);
+            } else {
+                // A non-TCP IPv4 packet heading towards the MMDS; we consider it unusual.
+                METRICS.mmds.rx_accepted_unusual.inc();
+            }
+            return true;
+        }
+
+        false
+    }
+
+    fn detour_ipv6(&mut self, eth: EthernetFrame<&[u8]>) -> bool {
+        let ipv6_addr = match self.ipv6_addr {
+            Some(addr) => addr,
+            None => return false,
+        };
+
+        // Same as for IPv4, the checksums are not verified.
+        if let Ok(ip) = IPv6Packet::from_bytes(eth.payload()) {
+            if ip.next_header() == PROTOCOL_ICMPV6 {
+                if let Ok(ns) = NeighborMessage::solicitation_from_bytes(ip.payload()) {
+                    // Solicitations coming from the unspecified address are part of duplicate
+                    // address detection, and don't need a reply.
+                    if ns.target_address() == ipv6_addr
+                        && ip.source_address() != Ipv6Addr::UNSPECIFIED
+                    {
+                        if !self.is_allowed_source(IpAddr::V6(ip.source_address())) {
+                            return true;
                         }
+                        self.remote_mac_addr = eth.src_mac();
+                        self.pending_ndp_reply_dest = Some(ip.source_address());
+                        return true;
                     }
-                    Err(_) => METRICS.mmds.rx_accepted_err.inc(),
                 }
+            }
+
+            if ip.destination_address() != ipv6_addr {
+                return false;
+            }
+            if !self.is_allowed_source(IpAddr::V6(ip.source_address())) {
+                return true;
+            }
+
+            if ip.next_header() == PROTOCOL_TCP {
+                self.remote_mac_addr = eth.src_mac();
+                let result = self.tcp_handler.receive_segment(
+                    IpAddr::V6(ip.source_address()),
+                    ip.payload(),
+                    super::convert_to_response,
+                );
+                Self::update_recv_metrics(result);
             } else {
-                // A non-TCP IPv4 packet heading towards the MMDS; we consider it unusual.
+                // A non-TCP IPv6 packet heading towards the MMDS; we consider it unusual.
                 METRICS.mmds.rx_accepted_unusual.inc();
             }
             return true;
@@ -192,6 +295,26 @@ impl MmdsNetworkStack {
         false
     }
 
+    fn update_recv_metrics(result: Result<RecvEvent, RecvError>) {
+        match result {
+            Ok(event) => {
+                METRICS.mmds.rx_count.inc();
+                match event {
+                    RecvEvent::NewConnectionSuccessful => METRICS.mmds.connections_created.inc(),
+                    RecvEvent::NewConnectionReplacing => {
+                        METRICS.mmds.connections_created.inc();
+                        METRICS.mmds.connections_destroyed.inc();
+                    }
+                    RecvEvent::EndpointDone => {
+                        METRICS.mmds.connections_destroyed.inc();
+                    }
+                    _ => (),
+                }
+            }
+            Err(_) => METRICS.mmds.rx_accepted_err.inc(),
+        }
+    }
+
     // Allows the MMDS network stack to write a frame to the specified buffer. Will return:
     // - None, if the MMDS network stack has no frame to send at this point. The buffer can be
     // used for something else by the device model.
@@ -210,6 +333,18 @@ impl MmdsNetworkStack {
                     None
                 }
             };
+        } else if self.pending_ndp_reply_dest.is_some() {
+            return match self.write_ndp_reply(buf) {
+                Ok(something) => {
+                    METRICS.mmds.tx_count.inc();
+                    self.pending_ndp_reply_dest = None;
+                    something
+                }
+                Err(_) => {
+                    METRICS.mmds.tx_errors.inc();
+                    None
+                }
+            };
         } else {
             let call_write = match self.tcp_handler.next_segment_status() {
                 NextSegmentStatus::Available => true,
@@ -270,6 +405,51 @@ impl MmdsNetworkStack {
         ))
     }
 
+    fn write_ndp_reply(&self, buf: &mut [u8]) -> Result<Option<NonZeroUsize>, WriteNdpFrameError> {
+        let ndp_reply_dest = self
+            .pending_ndp_reply_dest
+            .ok_or(WriteNdpFrameError::NoPendingNdpReply)?;
+        let ipv6_addr = self
+            .ipv6_addr
+            .ok_or(WriteNdpFrameError::NoPendingNdpReply)?;
+
+        let mut eth_unsized = self
+            .prepare_eth_unsized(buf, ETHERTYPE_IPV6)
+            .map_err(WriteNdpFrameError::Ethernet)?;
+
+        let packet_len = {
+            let mut packet = IPv6Packet::write_header(
+                eth_unsized.inner_mut().payload_mut(),
+                PROTOCOL_ICMPV6,
+                ipv6_addr,
+                ndp_reply_dest,
+            )
+            .map_err(WriteNdpFrameError::IPv6Packet)?;
+            packet.inner_mut().set_hop_limit(ndp::HOP_LIMIT);
+
+            let ndp_len = NeighborMessage::write_advertisement(
+                packet
+                    .inner_mut()
+                    .payload_mut()
+                    .get_mut(..NEIGHBOR_ADVERTISEMENT_LEN)
+                    .ok_or(WriteNdpFrameError::Ndp(NdpError::SliceExactLen))?,
+                ipv6_addr,
+                self.mac_addr,
+                ipv6_addr,
+                ndp_reply_dest,
+            )
+            .map_err(WriteNdpFrameError::Ndp)?
+            .len();
+
+            packet.with_payload_len_unchecked(ndp_len).len()
+        };
+
+        Ok(Some(
+            // The unwrap() is safe because packet_len > 0.
+            NonZeroUsize::new(eth_unsized.with_payload_len_unchecked(packet_len).len()).unwrap(),
+        ))
+    }
+
     fn write_packet(&mut self, buf: &mut [u8]) -> Result<Option<NonZeroUsize>, WritePacketError> {
         let mut eth_unsized = self
             .prepare_eth_unsized(buf, ETHERTYPE_IPV4)
@@ -284,14 +464,17 @@ impl MmdsNetworkStack {
         }
 
         if let Some(packet_len) = maybe_len {
+            let packet_len = packet_len.get();
+            // The handler writes either an IPv4 or an IPv6 packet, depending on the connection.
+            let version = IPv6Packet::from_bytes_unchecked(eth_unsized.inner().payload()).version();
+            if version == IPV6_VERSION {
+                eth_unsized.inner_mut().set_ethertype(ETHERTYPE_IPV6);
+            }
+
             return Ok(Some(
                 // The unwrap() is safe because packet_len > 0.
-                NonZeroUsize::new(
-                    eth_unsized
-                        .with_payload_len_unchecked(packet_len.get())
-                        .len(),
-                )
-                .unwrap(),
+                NonZeroUsize::new(eth_unsized.with_payload_len_unchecked(packet_len).len())
+                    .unwrap(),
             ));
         }
 
@@ -304,6 +487,7 @@ mod tests {
     use std::str::FromStr;
 
     use super::*;
+    use dumbo::pdu::ipv6::compute_upper_layer_checksum;
     use dumbo::pdu::tcp::{Flags as TcpFlags, TcpSegment};
 
     // We use LOCALHOST here because const new() is not stable yet, so just reuse this const, since
@@ -313,6 +497,8 @@ mod tests {
     const MMDS_PORT: u16 = 80;
     const REMOTE_PORT: u16 = 1235;
     const SEQ_NUMBER: u32 = 123;
+    const MMDS_IPV6_ADDR: Ipv6Addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0xa9fe, 0xa9fe, 0xa9fe, 0xa9fe);
+    const REMOTE_IPV6_ADDR: Ipv6Addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 4);
 
     // Helper methods which only make sense for testing.
     impl MmdsNetworkStack {
@@ -373,6 +559,81 @@ mod tests {
             eth_unsized.with_payload_len_unchecked(packet_len).len()
         }
 
+        fn write_neighbor_solicitation(&self, buf: &mut [u8], target: Ipv6Addr) -> usize {
+            let mut eth_unsized = self.prepare_eth_unsized(buf, ETHERTYPE_IPV6).unwrap();
+            let packet_len = {
+                let mut packet = IPv6Packet::write_header(
+                    eth_unsized.inner_mut().payload_mut(),
+                    PROTOCOL_ICMPV6,
+                    REMOTE_IPV6_ADDR,
+                    solicited_node_multicast_addr(target),
+                )
+                .unwrap();
+
+                // Write an advertisement and then modify it into a solicitation.
+                NeighborMessage::write_advertisement(
+                    &mut packet.inner_mut().payload_mut()[..NEIGHBOR_ADVERTISEMENT_LEN],
+                    target,
+                    MacAddr::parse_str(REMOTE_MAC_STR).unwrap(),
+                    REMOTE_IPV6_ADDR,
+                    solicited_node_multicast_addr(target),
+                )
+                .unwrap()
+                .set_message_type(ndp::TYPE_NEIGHBOR_SOLICITATION);
+
+                packet
+                    .with_payload_len_unchecked(NEIGHBOR_ADVERTISEMENT_LEN)
+                    .len()
+            };
+
+            eth_unsized.with_payload_len_unchecked(packet_len).len()
+        }
+
+        fn write_incoming_ipv6_tcp_segment(
+            &self,
+            buf: &mut [u8],
+            src_addr: Ipv6Addr,
+            addr: Ipv6Addr,
+            flags: TcpFlags,
+        ) -> usize {
+            let mut eth_unsized = self.prepare_eth_unsized(buf, ETHERTYPE_IPV6).unwrap();
+            let packet_len = {
+                let mut packet = IPv6Packet::write_header(
+                    eth_unsized.inner_mut().payload_mut(),
+                    PROTOCOL_TCP,
+                    src_addr,
+                    addr,
+                )
+                .unwrap();
+
+                // The checksum is not verified by the MMDS, so we don't bother computing it.
+                let segment_len = TcpSegment::write_incomplete_segment::<[u8]>(
+                    packet.inner_mut().payload_mut(),
+                    SEQ_NUMBER,
+                    1234,
+                    flags,
+                    10000,
+                    None,
+                    0,
+                    None,
+                )
+                .unwrap()
+                .finalize(REMOTE_PORT, MMDS_PORT, None)
+                .len();
+
+                packet.with_payload_len_unchecked(segment_len).len()
+            };
+
+            eth_unsized.with_payload_len_unchecked(packet_len).len()
+        }
+
+        fn next_frame_as_ipv6_packet<'a>(&mut self, buf: &'a mut [u8]) -> IPv6Packet<&'a [u8]> {
+            let len = self.write_next_frame(buf).unwrap().get();
+            let eth = EthernetFrame::from_bytes(&buf[..len]).unwrap();
+            assert_eq!(eth.ethertype(), ETHERTYPE_IPV6);
+            IPv6Packet::from_bytes(&buf[eth.payload_offset()..len]).unwrap()
+        }
+
         fn next_frame_as_ipv4_packet<'a>(&mut self, buf: &'a mut [u8]) -> IPv4Packet<&'a [u8]> {
             let len = self.write_next_frame(buf).unwrap().get();
             let eth = EthernetFrame::from_bytes(&buf[..len]).unwrap();
@@ -511,6 +772,231 @@ mod tests {
         assert!(ns.write_next_frame(buf.as_mut()).is_none());
     }
 
+    #[test]
+    fn test_ns_ipv6() {
+        let mut ns = MmdsNetworkStack::new_with_defaults(None);
+        let mut buf = [0u8; 2000];
+
+        let bad_ipv6_addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 4, 3, 2, 1);
+        let other_remote_ipv6_addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 5, 6, 7, 8);
+
+        // IPv6 is not enabled, so nothing is detoured.
+        {
+            let len = ns.write_neighbor_solicitation(buf.as_mut(), MMDS_IPV6_ADDR);
+            assert!(!ns.detour_frame(&buf[..len]));
+            let len = ns.write_incoming_ipv6_tcp_segment(
+                buf.as_mut(),
+                REMOTE_IPV6_ADDR,
+                MMDS_IPV6_ADDR,
+                TcpFlags::SYN,
+            );
+            assert!(!ns.detour_frame(&buf[..len]));
+            assert!(ns.write_next_frame(buf.as_mut()).is_none());
+        }
+
+        ns.set_ipv6_addr(Some(MMDS_IPV6_ADDR));
+
+        // Not asking for the MMDS MAC address.
+        {
+            let len = ns.write_neighbor_solicitation(buf.as_mut(), bad_ipv6_addr);
+            assert!(!ns.detour_frame(&buf[..len]));
+            assert!(ns.write_next_frame(buf.as_mut()).is_none());
+        }
+
+        // Asking for the MMDS MAC address.
+        {
+            let len = ns.write_neighbor_solicitation(buf.as_mut(), MMDS_IPV6_ADDR);
+            assert!(ns.detour_frame(&buf[..len]));
+            assert_eq!(ns.pending_ndp_reply_dest, Some(REMOTE_IPV6_ADDR));
+        }
+
+        // There should be a Neighbor Advertisement to send.
+        {
+            let curr_tx_count = METRICS.mmds.tx_count.count();
+            let ip = ns.next_frame_as_ipv6_packet(buf.as_mut());
+            assert_eq!(curr_tx_count + 1, METRICS.mmds.tx_count.count());
+            assert_eq!(ip.next_header(), PROTOCOL_ICMPV6);
+            assert_eq!(ip.hop_limit(), ndp::HOP_LIMIT);
+            assert_eq!(ip.source_address(), MMDS_IPV6_ADDR);
+            assert_eq!(ip.destination_address(), REMOTE_IPV6_ADDR);
+            assert_eq!(
+                compute_upper_layer_checksum(
+                    ip.payload(),
+                    MMDS_IPV6_ADDR,
+                    REMOTE_IPV6_ADDR,
+                    PROTOCOL_ICMPV6
+                ),
+                0
+            );
+
+            let na = NeighborMessage::from_bytes_unchecked(ip.payload());
+            assert_eq!(na.message_type(), ndp::TYPE_NEIGHBOR_ADVERTISEMENT);
+            assert_eq!(na.target_address(), MMDS_IPV6_ADDR);
+        }
+
+        // Nothing to send anymore.
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+
+        // A TCP segment heading to the wrong address is rejected.
+        {
+            let len = ns.write_incoming_ipv6_tcp_segment(
+                buf.as_mut(),
+                REMOTE_IPV6_ADDR,
+                bad_ipv6_addr,
+                TcpFlags::SYN,
+            );
+            assert!(!ns.detour_frame(&buf[..len]));
+            assert!(ns.write_next_frame(buf.as_mut()).is_none());
+        }
+
+        // Let's send a TCP SYN into the ns.
+        {
+            let len = ns.write_incoming_ipv6_tcp_segment(
+                buf.as_mut(),
+                REMOTE_IPV6_ADDR,
+                MMDS_IPV6_ADDR,
+                TcpFlags::SYN,
+            );
+            let curr_rx_count = METRICS.mmds.rx_count.count();
+            assert!(ns.detour_frame(&buf[..len]));
+            assert_eq!(curr_rx_count + 1, METRICS.mmds.rx_count.count());
+        }
+
+        // We should be getting a SYNACK over IPv6 in response.
+        {
+            let ip = ns.next_frame_as_ipv6_packet(buf.as_mut());
+            assert_eq!(ip.next_header(), PROTOCOL_TCP);
+            assert_eq!(ip.source_address(), MMDS_IPV6_ADDR);
+            assert_eq!(ip.destination_address(), REMOTE_IPV6_ADDR);
+            assert_eq!(
+                compute_upper_layer_checksum(
+                    ip.payload(),
+                    MMDS_IPV6_ADDR,
+                    REMOTE_IPV6_ADDR,
+                    PROTOCOL_TCP
+                ),
+                0
+            );
+
+            let s = TcpSegment::from_bytes(ip.payload(), None).unwrap();
+            assert_eq!(s.flags_after_ns(), TcpFlags::SYN | TcpFlags::ACK);
+            assert_eq!(s.source_port(), MMDS_PORT);
+            assert_eq!(s.destination_port(), REMOTE_PORT);
+            assert_eq!(s.ack_number(), SEQ_NUMBER.wrapping_add(1));
+        }
+
+        // Nothing else to send.
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+
+        // Another client using the same port gets a connection of its own, and the replies
+        // are sent to the right peer.
+        {
+            let len = ns.write_incoming_ipv6_tcp_segment(
+                buf.as_mut(),
+                other_remote_ipv6_addr,
+                MMDS_IPV6_ADDR,
+                TcpFlags::SYN,
+            );
+            assert!(ns.detour_frame(&buf[..len]));
+
+            let ip = ns.next_frame_as_ipv6_packet(buf.as_mut());
+            assert_eq!(ip.destination_address(), other_remote_ipv6_addr);
+            let s = TcpSegment::from_bytes(ip.payload(), None).unwrap();
+            assert_eq!(s.flags_after_ns(), TcpFlags::SYN | TcpFlags::ACK);
+        }
+
+        // Nothing else to send.
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+
+        // IPv4 keeps working alongside IPv6.
+        {
+            let len = ns.write_incoming_tcp_segment(buf.as_mut(), ns.ipv4_addr, TcpFlags::SYN);
+            assert!(ns.detour_frame(&buf[..len]));
+
+            let ip = ns.next_frame_as_ipv4_packet(buf.as_mut());
+            assert_eq!(ip.destination_address(), REMOTE_ADDR);
+            let s = TcpSegment::from_bytes(
+                ip.payload(),
+                Some((ip.source_address(), ip.destination_address())),
+            )
+            .unwrap();
+            assert_eq!(s.flags_after_ns(), TcpFlags::SYN | TcpFlags::ACK);
+        }
+    }
+
+    #[test]
+    fn test_set_ipv6_addr() {
+        let mut ns = MmdsNetworkStack::new_with_defaults(None);
+        assert_eq!(ns.ipv6_addr, None);
+
+        ns.set_ipv6_addr(Some(MMDS_IPV6_ADDR));
+        ns.pending_ndp_reply_dest = Some(REMOTE_IPV6_ADDR);
+        assert_eq!(ns.ipv6_addr, Some(MMDS_IPV6_ADDR));
+        assert_eq!(ns.tcp_handler.local_ipv6_addr(), Some(MMDS_IPV6_ADDR));
+
+        ns.set_ipv6_addr(None);
+        assert_eq!(ns.ipv6_addr, None);
+        assert_eq!(ns.pending_ndp_reply_dest, None);
+        assert_eq!(ns.tcp_handler.local_ipv6_addr(), None);
+    }
+
+    #[test]
+    fn test_allowed_sources() {
+        let mut ns = MmdsNetworkStack::new_with_defaults(None);
+        ns.set_ipv6_addr(Some(MMDS_IPV6_ADDR));
+        let mut buf = [0u8; 2000];
+        let mmds_addr = ns.ipv4_addr;
+
+        // The frames of the sources which aren't allowed are dropped, without any reply.
+        ns.set_allowed_sources(vec![
+            IpPrefix::from_str("10.0.0.0/8").unwrap(),
+            IpPrefix::from_str("fd00::/8").unwrap(),
+        ]);
+        let not_allowed_count = METRICS.mmds.rx_source_not_allowed.count();
+
+        let len = ns.write_arp_request(buf.as_mut(), true);
+        assert!(ns.detour_frame(&buf[..len]));
+        let len = ns.write_incoming_tcp_segment(buf.as_mut(), mmds_addr, TcpFlags::SYN);
+        assert!(ns.detour_frame(&buf[..len]));
+        let len = ns.write_neighbor_solicitation(buf.as_mut(), MMDS_IPV6_ADDR);
+        assert!(ns.detour_frame(&buf[..len]));
+        let len = ns.write_incoming_ipv6_tcp_segment(buf.as_mut(), MMDS_IPV6_ADDR, TcpFlags::SYN);
+        assert!(ns.detour_frame(&buf[..len]));
+
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+        assert_eq!(
+            METRICS.mmds.rx_source_not_allowed.count(),
+            not_allowed_count + 4
+        );
+
+        // The allowed sources are answered.
+        ns.set_allowed_sources(vec![
+            IpPrefix::from_str("127.0.0.1").unwrap(),
+            IpPrefix::from_str("fe80::/64").unwrap(),
+        ]);
+        assert_eq!(ns.allowed_sources().len(), 2);
+
+        let len = ns.write_arp_request(buf.as_mut(), true);
+        assert!(ns.detour_frame(&buf[..len]));
+        assert!(ns.write_next_frame(buf.as_mut()).is_some());
+        let len = ns.write_neighbor_solicitation(buf.as_mut(), MMDS_IPV6_ADDR);
+        assert!(ns.detour_frame(&buf[..len]));
+        assert!(ns.write_next_frame(buf.as_mut()).is_some());
+        let len = ns.write_incoming_tcp_segment(buf.as_mut(), mmds_addr, TcpFlags::SYN);
+        assert!(ns.detour_frame(&buf[..len]));
+        assert!(ns.write_next_frame(buf.as_mut()).is_some());
+
+        // An empty list allows every source.
+        ns.set_allowed_sources(Vec::new());
+        let len = ns.write_incoming_ipv6_tcp_segment(buf.as_mut(), MMDS_IPV6_ADDR, TcpFlags::SYN);
+        assert!(ns.detour_frame(&buf[..len]));
+        assert!(ns.write_next_frame(buf.as_mut()).is_some());
+        assert_eq!(
+            METRICS.mmds.rx_source_not_allowed.count(),
+            not_allowed_count + 4
+        );
+    }
+
     #[test]
     fn test_set_ipv4_addr() {
         let mut ns = MmdsNetworkStack::new_with_defaults(None);
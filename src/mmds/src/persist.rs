@@ -1,17 +1,48 @@
 // Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-//! Defines the structures needed for saving/restoring MmdsNetworkStack.
+//! Defines the structures needed for saving/restoring MmdsNetworkStack and the MMDS version.
 
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 
+use logger::warn;
 use snapshot::Persist;
+use utils::net::ip_prefix::IpPrefix;
 use utils::net::mac::{MacAddr, MAC_ADDR_LEN};
 use versionize::{VersionMap, Versionize, VersionizeResult};
 use versionize_derive::Versionize;
 
+use super::data_store::MmdsVersion;
 use super::ns::MmdsNetworkStack;
 
+/// State of the MMDS version.
+#[derive(Clone, Copy, Debug, PartialEq, Versionize)]
+// NOTICE: Any changes to this structure require a snapshot version bump.
+pub enum MmdsVersionState {
+    /// MMDS version 1.
+    V1,
+    /// MMDS version 2.
+    V2,
+}
+
+impl From<MmdsVersion> for MmdsVersionState {
+    fn from(version: MmdsVersion) -> Self {
+        match version {
+            MmdsVersion::V1 => MmdsVersionState::V1,
+            MmdsVersion::V2 => MmdsVersionState::V2,
+        }
+    }
+}
+
+impl From<MmdsVersionState> for MmdsVersion {
+    fn from(state: MmdsVersionState) -> Self {
+        match state {
+            MmdsVersionState::V1 => MmdsVersion::V1,
+            MmdsVersionState::V2 => MmdsVersion::V2,
+        }
+    }
+}
+
 /// State of a MmdsNetworkStack.
 #[derive(Clone, Versionize)]
 // NOTICE: Any changes to this structure require a snapshot version bump.
@@ -21,6 +52,43 @@ pub struct MmdsNetworkStackState {
     tcp_port: u16,
     max_connections: usize,
     max_pending_resets: usize,
+    #[version(start = 2, default_fn = "default_ipv6_addr", ser_fn = "ipv6_addr_ser")]
+    ipv6_addr: Option<Vec<u8>>,
+    #[version(
+        start = 2,
+        default_fn = "default_allowed_sources",
+        ser_fn = "allowed_sources_ser"
+    )]
+    allowed_sources: Vec<String>,
+}
+
+impl MmdsNetworkStackState {
+    fn default_ipv6_addr(_source_version: u16) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn ipv6_addr_ser(&mut self, _target_version: u16) -> VersionizeResult<()> {
+        if self.ipv6_addr.is_some() {
+            warn!("Target version does not support MMDS over IPv6. Only IPv4 will be served.");
+        }
+
+        Ok(())
+    }
+
+    fn default_allowed_sources(_source_version: u16) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn allowed_sources_ser(&mut self, _target_version: u16) -> VersionizeResult<()> {
+        if !self.allowed_sources.is_empty() {
+            warn!(
+                "Target version does not support restricting the MMDS sources. Any guest \
+                 address will reach the MMDS."
+            );
+        }
+
+        Ok(())
+    }
 }
 
 impl Persist<'_> for MmdsNetworkStack {
@@ -38,6 +106,12 @@ impl Persist<'_> for MmdsNetworkStack {
             tcp_port: self.tcp_handler.local_port(),
             max_connections: self.tcp_handler.max_connections(),
             max_pending_resets: self.tcp_handler.max_pending_resets(),
+            ipv6_addr: self.ipv6_addr.map(|addr| addr.octets().to_vec()),
+            allowed_sources: self
+                .allowed_sources
+                .iter()
+                .map(IpPrefix::to_string)
+                .collect(),
         }
     }
 
@@ -45,18 +119,39 @@ impl Persist<'_> for MmdsNetworkStack {
         _: Self::ConstructorArgs,
         state: &Self::State,
     ) -> std::result::Result<Self, Self::Error> {
-        Ok(MmdsNetworkStack::new(
+        let mut ns = MmdsNetworkStack::new(
             MacAddr::from_bytes_unchecked(&state.mac_addr),
             Ipv4Addr::from(state.ipv4_addr),
             state.tcp_port,
             std::num::NonZeroUsize::new(state.max_connections).unwrap(),
             std::num::NonZeroUsize::new(state.max_pending_resets).unwrap(),
-        ))
+        );
+
+        if let Some(bytes) = state.ipv6_addr.as_ref() {
+            let mut octets = [0u8; 16];
+            if bytes.len() != octets.len() {
+                return Err(());
+            }
+            octets.copy_from_slice(bytes);
+            ns.set_ipv6_addr(Some(Ipv6Addr::from(octets)));
+        }
+        ns.set_allowed_sources(
+            state
+                .allowed_sources
+                .iter()
+                .map(|prefix| prefix.parse())
+                .collect::<Result<Vec<IpPrefix>, _>>()
+                .map_err(|_| ())?,
+        );
+
+        Ok(ns)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::str::FromStr;
+
     use super::*;
 
     #[test]
@@ -90,5 +185,45 @@ mod tests {
             restored_ns.tcp_handler.max_pending_resets(),
             ns.tcp_handler.max_pending_resets()
         );
+        assert_eq!(restored_ns.ipv6_addr, None);
+    }
+
+    #[test]
+    fn test_persistence_ipv6() {
+        let mut ns = MmdsNetworkStack::new_with_defaults(None);
+        let ipv6_addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0xa9fe, 0xa9fe, 0xa9fe, 0xa9fe);
+        ns.set_ipv6_addr(Some(ipv6_addr));
+        let allowed_sources = vec![IpPrefix::from_str("10.0.0.0/8").unwrap()];
+        ns.set_allowed_sources(allowed_sources.clone());
+
+        let mut mem = vec![0; 4096];
+        let mut version_map = VersionMap::new();
+        version_map
+            .new_version()
+            .set_type_version(MmdsNetworkStackState::type_id(), 2);
+
+        ns.save()
+            .serialize(&mut mem.as_mut_slice(), &version_map, 2)
+            .unwrap();
+        let restored_ns = MmdsNetworkStack::restore(
+            (),
+            &MmdsNetworkStackState::deserialize(&mut mem.as_slice(), &version_map, 2).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(restored_ns.ipv6_addr, Some(ipv6_addr));
+        assert_eq!(restored_ns.allowed_sources, allowed_sources);
+
+        // The address and the allowed sources are dropped when saving to a version which doesn't
+        // know about them.
+        ns.save()
+            .serialize(&mut mem.as_mut_slice(), &version_map, 1)
+            .unwrap();
+        let restored_ns = MmdsNetworkStack::restore(
+            (),
+            &MmdsNetworkStackState::deserialize(&mut mem.as_slice(), &version_map, 1).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(restored_ns.ipv6_addr, None);
+        assert!(restored_ns.allowed_sources.is_empty());
     }
 }
@@ -0,0 +1,187 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Session tokens which guest applications have to obtain before querying the MMDS, when
+//! tokens are required by the configured MMDS version.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+
+use utils::time::{get_time_ns, ClockType};
+
+/// Header which carries the session token on metadata requests.
+pub const X_METADATA_TOKEN_HEADER: &str = "X-metadata-token";
+/// Header which carries the requested lifetime of a new session token, in seconds.
+pub const X_METADATA_TOKEN_TTL_SECONDS_HEADER: &str = "X-metadata-token-ttl-seconds";
+
+/// Minimum lifetime of a session token, in seconds.
+pub const MIN_TOKEN_TTL_SECONDS: u32 = 1;
+/// Maximum lifetime of a session token, in seconds.
+pub const MAX_TOKEN_TTL_SECONDS: u32 = 21600;
+
+// Upper bound for the number of live tokens, so that a guest can't make the MMDS use an
+// unbounded amount of memory.
+const MAX_TOKENS: usize = 1000;
+// Number of random bytes which make up a token.
+const TOKEN_LEN: usize = 32;
+const ENTROPY_POOL_PATH: &str = "/dev/urandom";
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// Errors associated with session tokens.
+#[derive(Debug)]
+pub enum Error {
+    /// Failed to open or read from the source of random bytes.
+    EntropyPool(io::Error),
+    /// The requested token lifetime is out of bounds.
+    InvalidTtlValue(u32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::EntropyPool(err) => write!(f, "Failed to generate a session token: {}", err),
+            Error::InvalidTtlValue(value) => write!(
+                f,
+                "Invalid time to live value provided for token: {}. Please provide a value \
+                 between {} and {}.",
+                value, MIN_TOKEN_TTL_SECONDS, MAX_TOKEN_TTL_SECONDS
+            ),
+        }
+    }
+}
+
+/// Generates session tokens and keeps track of their expiry.
+pub struct TokenAuthority {
+    // Source of random bytes. It is opened once, so that generating tokens after the seccomp
+    // filters are installed does not require opening any file.
+    entropy_pool: File,
+    // Tokens and the moment (on the monotonic clock, in nanoseconds) they expire.
+    tokens: HashMap<String, u64>,
+}
+
+impl TokenAuthority {
+    /// Creates a new token authority, with no valid tokens.
+    pub fn new() -> Result<Self, Error> {
+        Ok(TokenAuthority {
+            entropy_pool: File::open(ENTROPY_POOL_PATH).map_err(Error::EntropyPool)?,
+            tokens: HashMap::new(),
+        })
+    }
+
+    /// Generates a new token which remains valid for `ttl_seconds`.
+    pub fn generate_token_secret(&mut self, ttl_seconds: u32) -> Result<String, Error> {
+        if !(MIN_TOKEN_TTL_SECONDS..=MAX_TOKEN_TTL_SECONDS).contains(&ttl_seconds) {
+            return Err(Error::InvalidTtlValue(ttl_seconds));
+        }
+
+        let mut bytes = [0u8; TOKEN_LEN];
+        self.entropy_pool
+            .read_exact(&mut bytes)
+            .map_err(Error::EntropyPool)?;
+        let token: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+        let now = get_time_ns(ClockType::Monotonic);
+        self.remove_expired_tokens(now);
+        if self.tokens.len() >= MAX_TOKENS {
+            // Make room by dropping the token which would expire first.
+            if let Some(oldest) = self
+                .tokens
+                .iter()
+                .min_by_key(|(_, expiry)| **expiry)
+                .map(|(token, _)| token.clone())
+            {
+                self.tokens.remove(&oldest);
+            }
+        }
+
+        let expiry = now + u64::from(ttl_seconds) * NANOS_PER_SECOND;
+        self.tokens.insert(token.clone(), expiry);
+        Ok(token)
+    }
+
+    /// Checks if `token` was generated by this authority and has not expired yet.
+    pub fn is_valid(&self, token: &str) -> bool {
+        match self.tokens.get(token) {
+            Some(expiry) => get_time_ns(ClockType::Monotonic) < *expiry,
+            None => false,
+        }
+    }
+
+    fn remove_expired_tokens(&mut self, now: u64) {
+        self.tokens.retain(|_, expiry| now < *expiry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_token_secret() {
+        let mut token_authority = TokenAuthority::new().unwrap();
+
+        assert_eq!(
+            token_authority
+                .generate_token_secret(MIN_TOKEN_TTL_SECONDS - 1)
+                .unwrap_err()
+                .to_string(),
+            Error::InvalidTtlValue(0).to_string()
+        );
+        assert!(token_authority
+            .generate_token_secret(MAX_TOKEN_TTL_SECONDS + 1)
+            .is_err());
+
+        let token = token_authority.generate_token_secret(60).unwrap();
+        assert_eq!(token.len(), 2 * TOKEN_LEN);
+        assert!(token_authority.is_valid(&token));
+        assert!(!token_authority.is_valid("invalid_token"));
+
+        let other_token = token_authority.generate_token_secret(60).unwrap();
+        assert_ne!(token, other_token);
+        assert!(token_authority.is_valid(&token));
+        assert!(token_authority.is_valid(&other_token));
+    }
+
+    #[test]
+    fn test_token_expiry() {
+        let mut token_authority = TokenAuthority::new().unwrap();
+        let token = token_authority.generate_token_secret(60).unwrap();
+        assert!(token_authority.is_valid(&token));
+
+        // Pretend the token lifetime is over.
+        let now = get_time_ns(ClockType::Monotonic);
+        token_authority.tokens.insert(token.clone(), now);
+        assert!(!token_authority.is_valid(&token));
+
+        token_authority.remove_expired_tokens(now);
+        assert!(token_authority.tokens.is_empty());
+    }
+
+    #[test]
+    fn test_max_tokens() {
+        let mut token_authority = TokenAuthority::new().unwrap();
+        let first_token = token_authority.generate_token_secret(1).unwrap();
+        for _ in 0..MAX_TOKENS {
+            token_authority.generate_token_secret(60).unwrap();
+        }
+
+        assert_eq!(token_authority.tokens.len(), MAX_TOKENS);
+        // The token closest to expiring was dropped to make room for the others.
+        assert!(!token_authority.is_valid(&first_token));
+    }
+
+    #[test]
+    fn test_error_display() {
+        assert_eq!(
+            Error::InvalidTtlValue(0).to_string(),
+            "Invalid time to live value provided for token: 0. Please provide a value between 1 \
+             and 21600."
+        );
+        assert_eq!(
+            Error::EntropyPool(io::Error::new(io::ErrorKind::NotFound, "missing")).to_string(),
+            "Failed to generate a session token: missing"
+        );
+    }
+}
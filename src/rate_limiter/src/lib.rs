@@ -42,8 +42,9 @@
 //! It is meant to be used in an external event loop and thus implements the `AsRawFd`
 //! trait and provides an *event-handler* as part of its API. This *event-handler*
 //! needs to be called by the user on every event on the rate limiter's `AsRawFd` FD.
-use logger::error;
+use logger::{error, IncMetric, RateLimiterMetrics};
 use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use std::{fmt, io};
 use timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
@@ -64,6 +65,10 @@ const TIMER_REFILL_STATE: TimerState =
 
 const NANOSEC_IN_ONE_MILLISEC: u64 = 1_000_000;
 
+/// The largest complete refill time of a token bucket, in milliseconds, which can still be
+/// expressed in nanoseconds.
+pub const MAX_REFILL_TIME_MS: u64 = u64::MAX / NANOSEC_IN_ONE_MILLISEC;
+
 // Euclid's two-thousand-year-old algorithm for finding the greatest common divisor.
 fn gcd(x: u64, y: u64) -> u64 {
     let mut x = x;
@@ -120,10 +125,12 @@ impl TokenBucket {
     /// extra credit on top of total capacity, that does not replenish and which can be used
     /// for an initial burst of data.
     ///
-    /// If the `size` or the `complete refill time` are zero, then `None` is returned.
+    /// If the `size` or the `complete refill time` are zero, or the `complete refill time`
+    /// exceeds `MAX_REFILL_TIME_MS`, then `None` is returned.
     pub fn new(size: u64, one_time_burst: u64, complete_refill_time_ms: u64) -> Option<Self> {
         // If either token bucket capacity or refill time is 0, disable limiting.
-        if size == 0 || complete_refill_time_ms == 0 {
+        if size == 0 || complete_refill_time_ms == 0 || complete_refill_time_ms > MAX_REFILL_TIME_MS
+        {
             return None;
         }
         // Formula for computing current refill amount:
@@ -162,8 +169,11 @@ impl TokenBucket {
         // refill_amount = (time_delta * size) / (complete_refill_time_ms * 1_000_000)
         // `processed_capacity` and `processed_refill_time` are the result of simplifying above
         // fraction formula with their greatest-common-factor.
-        let tokens = (time_delta * self.processed_capacity) / self.processed_refill_time;
-        self.budget = std::cmp::min(self.budget + tokens, self.size);
+        // The product is computed on 128 bits, as it overflows 64 bits for large buckets.
+        let tokens = u128::from(time_delta) * u128::from(self.processed_capacity)
+            / u128::from(self.processed_refill_time);
+        let tokens = std::cmp::min(tokens, u128::from(self.size)) as u64;
+        self.budget = std::cmp::min(self.budget.saturating_add(tokens), self.size);
     }
 
     /// Attempts to consume `tokens` from the bucket and returns whether the action succeeded.
@@ -218,10 +228,10 @@ impl TokenBucket {
         // budget which should now be replenished, but for performance and code-complexity
         // reasons we're just gonna let that slide since it's practically inconsequential.
         if self.one_time_burst > 0 {
-            self.one_time_burst += tokens;
+            self.one_time_burst = self.one_time_burst.saturating_add(tokens);
             return;
         }
-        self.budget = std::cmp::min(self.budget + tokens, self.size);
+        self.budget = std::cmp::min(self.budget.saturating_add(tokens), self.size);
     }
 
     /// Returns the capacity of the token bucket.
@@ -290,6 +300,10 @@ pub struct RateLimiter {
     timer_fd: TimerFd,
     // Internal flag that quickly determines timer state.
     timer_active: bool,
+    // When the timer was last armed.
+    timer_activation: Instant,
+
+    metrics: Option<Arc<RateLimiterMetrics>>,
 }
 
 impl PartialEq for RateLimiter {
@@ -360,14 +374,25 @@ impl RateLimiter {
             ops: ops_token_bucket,
             timer_fd,
             timer_active: false,
+            timer_activation: Instant::now(),
+            metrics: None,
         })
     }
 
+    /// Sets the metrics updated by this rate limiter when it runs out of tokens.
+    pub fn set_metrics(&mut self, metrics: Arc<RateLimiterMetrics>) {
+        self.metrics = Some(metrics);
+    }
+
     // Arm the timer of the rate limiter with the provided `TimerState`.
     fn activate_timer(&mut self, timer_state: TimerState) {
         // Register the timer; don't care about its previous state
         self.timer_fd.set_state(timer_state, SetTimeFlags::Default);
         self.timer_active = true;
+        self.timer_activation = Instant::now();
+        if let Some(metrics) = self.metrics.as_ref() {
+            metrics.exhausted_count.inc();
+        }
     }
 
     /// Attempts to consume tokens and returns whether that is possible.
@@ -459,6 +484,11 @@ impl RateLimiter {
             )),
             _ => {
                 self.timer_active = false;
+                if let Some(metrics) = self.metrics.as_ref() {
+                    metrics
+                        .throttled_ns
+                        .add(self.timer_activation.elapsed().as_nanos() as usize);
+                }
                 Ok(())
             }
         }
@@ -569,6 +599,23 @@ pub(crate) mod tests {
         assert!(TokenBucket::new(0, 1234, 1000).is_none());
         assert!(TokenBucket::new(100, 1234, 0).is_none());
         assert!(TokenBucket::new(0, 1234, 0).is_none());
+        assert!(TokenBucket::new(100, 1234, MAX_REFILL_TIME_MS + 1).is_none());
+        assert!(TokenBucket::new(100, 1234, MAX_REFILL_TIME_MS).is_some());
+    }
+
+    #[test]
+    fn test_token_bucket_no_overflow() {
+        // A huge bucket which refills quickly doesn't overflow when replenished.
+        let mut tb = TokenBucket::new(u64::MAX, u64::MAX, 1).unwrap();
+        tb.force_replenish(1);
+        assert_eq!(tb.one_time_burst(), u64::MAX);
+
+        assert_eq!(tb.reduce(u64::MAX), BucketReduction::Success);
+        assert_eq!(tb.reduce(u64::MAX), BucketReduction::Success);
+        assert_eq!(tb.budget(), 0);
+        thread::sleep(Duration::from_millis(10));
+        tb.force_replenish(1);
+        assert_eq!(tb.reduce(u64::MAX), BucketReduction::Success);
     }
 
     #[test]
@@ -710,6 +757,32 @@ pub(crate) mod tests {
         assert!(l.consume(100, TokenType::Bytes));
     }
 
+    #[test]
+    fn test_rate_limiter_metrics() {
+        // rate limiter with limit of 1000 bytes/s
+        let mut l = RateLimiter::new(1000, 0, 1000, 0, 0, 0).unwrap();
+        let metrics = Arc::new(RateLimiterMetrics::default());
+        l.set_metrics(metrics.clone());
+
+        assert!(l.consume(1000, TokenType::Bytes));
+        assert_eq!(metrics.exhausted_count.count(), 0);
+
+        // running out of tokens blocks the limiter
+        assert!(!l.consume(100, TokenType::Bytes));
+        assert_eq!(metrics.exhausted_count.count(), 1);
+        // failing to consume while blocked doesn't count again
+        assert!(!l.consume(100, TokenType::Bytes));
+        assert_eq!(metrics.exhausted_count.count(), 1);
+        assert_eq!(metrics.throttled_ns.count(), 0);
+
+        thread::sleep(Duration::from_millis(REFILL_TIMER_INTERVAL_MS));
+        assert!(l.event_handler().is_ok());
+        assert!(
+            metrics.throttled_ns.count() as u64
+                >= REFILL_TIMER_INTERVAL_MS * NANOSEC_IN_ONE_MILLISEC
+        );
+    }
+
     #[test]
     fn test_rate_limiter_ops() {
         // rate limiter with limit of 1000 ops/s
@@ -85,6 +85,8 @@ impl Persist<'_> for RateLimiter {
             },
             timer_fd: TimerFd::new_custom(ClockId::Monotonic, true, true)?,
             timer_active: false,
+            timer_activation: Instant::now(),
+            metrics: None,
         };
 
         Ok(rate_limiter)
@@ -0,0 +1,168 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Contains support for parsing and matching IP address prefixes, written in the CIDR notation
+//! (`192.168.0.0/24`, `fd00::/8`). An address without a prefix length stands for that single
+//! address.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::result::Result;
+use std::str::FromStr;
+
+use serde::de::{Deserialize, Deserializer, Error};
+use serde::ser::{Serialize, Serializer};
+
+/// Represents a range of IPv4 or IPv6 addresses, sharing their first `len` bits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IpPrefix {
+    addr: IpAddr,
+    len: u8,
+}
+
+impl IpPrefix {
+    /// Creates the prefix of length `len` of `addr`. Returns `None` if `len` is larger than the
+    /// number of bits of `addr`.
+    pub fn new(addr: IpAddr, len: u8) -> Option<IpPrefix> {
+        if len > Self::max_len(addr) {
+            return None;
+        }
+        Some(IpPrefix { addr, len })
+    }
+
+    fn max_len(addr: IpAddr) -> u8 {
+        match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        }
+    }
+
+    /// Returns the address the prefix was built from.
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// Returns the number of leading bits shared by the addresses of the prefix.
+    pub fn prefix_len(&self) -> u8 {
+        self.len
+    }
+
+    /// Says if `addr` belongs to the prefix. An IPv4 address never belongs to an IPv6 prefix,
+    /// and vice versa.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(prefix), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.len)).unwrap_or(0);
+                u32::from(prefix) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(prefix), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.len))
+                    .unwrap_or(0);
+                u128::from(prefix) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for IpPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.len)
+    }
+}
+
+impl FromStr for IpPrefix {
+    type Err = String;
+
+    /// Parses `addr/len`, or a single `addr`, which stands for the prefix holding only it.
+    fn from_str(s: &str) -> Result<IpPrefix, String> {
+        let invalid = || format!("Invalid IP address or prefix: {}", s);
+        let mut parts = s.splitn(2, '/');
+        // The unwrap is safe since splitn always yields at least one item.
+        let addr = IpAddr::from_str(parts.next().unwrap()).map_err(|_| invalid())?;
+        let len = match parts.next() {
+            Some(len) => len.parse::<u8>().map_err(|_| invalid())?,
+            None => Self::max_len(addr),
+        };
+        IpPrefix::new(addr, len).ok_or_else(invalid)
+    }
+}
+
+impl Serialize for IpPrefix {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for IpPrefix {
+    fn deserialize<D>(deserializer: D) -> Result<IpPrefix, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        IpPrefix::from_str(&s).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let prefix = IpPrefix::from_str("192.168.0.0/16").unwrap();
+        assert_eq!(prefix.addr(), IpAddr::V4(Ipv4Addr::new(192, 168, 0, 0)));
+        assert_eq!(prefix.prefix_len(), 16);
+        assert_eq!(prefix.to_string(), "192.168.0.0/16");
+
+        // A single address.
+        let prefix = IpPrefix::from_str("10.0.0.2").unwrap();
+        assert_eq!(prefix.prefix_len(), 32);
+        let prefix = IpPrefix::from_str("fd00::1").unwrap();
+        assert_eq!(prefix.prefix_len(), 128);
+        assert_eq!(prefix.to_string(), "fd00::1/128");
+
+        assert!(IpPrefix::from_str("").is_err());
+        assert!(IpPrefix::from_str("10.0.0.256").is_err());
+        assert!(IpPrefix::from_str("10.0.0.0/").is_err());
+        assert!(IpPrefix::from_str("10.0.0.0/33").is_err());
+        assert!(IpPrefix::from_str("fd00::/129").is_err());
+        assert!(IpPrefix::from_str("10.0.0.0/8/8").is_err());
+    }
+
+    #[test]
+    fn test_contains() {
+        let prefix = IpPrefix::from_str("192.168.0.0/16").unwrap();
+        assert!(prefix.contains(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2))));
+        assert!(!prefix.contains(IpAddr::V4(Ipv4Addr::new(192, 169, 1, 2))));
+        assert!(!prefix.contains(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+
+        let prefix = IpPrefix::from_str("10.0.0.2").unwrap();
+        assert!(prefix.contains(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2))));
+        assert!(!prefix.contains(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3))));
+
+        // The empty prefix holds every address of its family.
+        let prefix = IpPrefix::from_str("0.0.0.0/0").unwrap();
+        assert!(prefix.contains(IpAddr::V4(Ipv4Addr::BROADCAST)));
+
+        let prefix = IpPrefix::from_str("fe80::/10").unwrap();
+        assert!(prefix.contains(IpAddr::V6(Ipv6Addr::new(0xfebf, 0, 0, 0, 0, 0, 0, 1))));
+        assert!(!prefix.contains(IpAddr::V6(Ipv6Addr::new(0xfec0, 0, 0, 0, 0, 0, 0, 1))));
+        assert!(!prefix.contains(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2))));
+    }
+
+    #[test]
+    fn test_serialization_and_deserialization() {
+        let prefix: IpPrefix = serde_json::from_str("\"10.0.0.0/8\"").unwrap();
+        assert_eq!(prefix, IpPrefix::from_str("10.0.0.0/8").unwrap());
+        assert_eq!(serde_json::to_string(&prefix).unwrap(), "\"10.0.0.0/8\"");
+
+        assert!(serde_json::from_str::<IpPrefix>("\"10.0.0.0/64\"").is_err());
+    }
+}
@@ -0,0 +1,48 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::net::Ipv6Addr;
+
+/// Checks if an IPv6 address is a valid unicast link-local address, per RFC 4291. The interface
+/// identifier must not be zero, because that would be the Subnet-Router anycast address.
+/// # Examples
+///
+/// ```
+/// use std::net::Ipv6Addr;
+/// use utils::net::ipv6addr::is_link_local_valid;
+///
+/// assert!(is_link_local_valid(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)));
+/// ```
+pub fn is_link_local_valid(ipv6_addr: Ipv6Addr) -> bool {
+    let segments = ipv6_addr.segments();
+    segments[0] & 0xffc0 == 0xfe80 && segments[4..].iter().any(|segment| *segment != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::net::ipv6addr::is_link_local_valid;
+    use std::net::Ipv6Addr;
+
+    #[test]
+    fn test_is_link_local_valid() {
+        // Outside the link-local IPv6 address range (fe80::/10).
+        let mut ipv6_addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        assert!(!is_link_local_valid(ipv6_addr));
+        ipv6_addr = Ipv6Addr::LOCALHOST;
+        assert!(!is_link_local_valid(ipv6_addr));
+        ipv6_addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+        assert!(!is_link_local_valid(ipv6_addr));
+        ipv6_addr = Ipv6Addr::new(0xfec0, 0, 0, 0, 0, 0, 0, 1);
+        assert!(!is_link_local_valid(ipv6_addr));
+
+        // The Subnet-Router anycast address can not be used.
+        ipv6_addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0);
+        assert!(!is_link_local_valid(ipv6_addr));
+
+        // Valid IPv6 link-local addresses.
+        ipv6_addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        assert!(is_link_local_valid(ipv6_addr));
+        ipv6_addr = Ipv6Addr::new(0xfebf, 0, 0, 0, 0xa9fe, 0xa9fe, 0xa9fe, 0xa9fe);
+        assert!(is_link_local_valid(ipv6_addr));
+    }
+}
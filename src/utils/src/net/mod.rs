@@ -11,6 +11,9 @@
 //! Provides tools for representing and handling network related concepts like MAC addresses and
 //! network interfaces.
 
+pub mod ip_prefix;
 /// Provides IPv4 address utility methods.
 pub mod ipv4addr;
+/// Provides IPv6 address utility methods.
+pub mod ipv6addr;
 pub mod mac;
@@ -74,8 +74,11 @@ fn create_microvm_state(is_diff: bool) -> MicrovmState {
     let snapshot_params = CreateSnapshotParams {
         snapshot_type,
         snapshot_path: snapshot_file.as_path().to_path_buf(),
+        snapshot_fd: None,
         mem_file_path: memory_file.as_path().to_path_buf(),
+        mem_file_fd: None,
         version: None,
+        live: false,
     };
 
     {
@@ -5,9 +5,12 @@
 
 use std::convert::TryFrom;
 use std::fmt::{Display, Formatter};
+use std::fs::OpenOptions;
 use std::io::{self, Read, Seek, SeekFrom};
 use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 #[cfg(target_arch = "aarch64")]
 use crate::construct_kvm_mpidrs;
@@ -15,34 +18,53 @@ use crate::construct_kvm_mpidrs;
 use crate::device_manager::legacy::PortIODeviceManager;
 use crate::device_manager::mmio::MMIODeviceManager;
 use crate::device_manager::persist::MMIODevManagerConstructorArgs;
+use crate::device_manager::thread::DeviceThread;
 use crate::persist::{MicrovmState, MicrovmStateError};
-use crate::vmm_config::boot_source::BootConfig;
+use crate::recent_errors::RecentErrors;
+use crate::seccomp_filters;
+use crate::vmm_config::boot_source::{BootConfig, RNG_SEED_SIZE};
+#[cfg(target_arch = "x86_64")]
+use crate::vmm_config::machine_config::ResetAction;
+use crate::vmm_config::machine_config::{
+    host_mem_available_mib, sorted_mem_regions, InterruptController, PanicAction, RebootAction,
+    VmConfig, DEFAULT_SHUTDOWN_GRACE_PERIOD_MS,
+};
 use crate::vstate::{
     system::KvmContext,
     vcpu::{Vcpu, VcpuConfig},
     vm::Vm,
 };
-use crate::{device_manager, Error, EventManager, Vmm, VmmEventsObserver};
+use crate::{
+    device_manager, Error, EventManager, Vmm, VmmEventsObserver, FC_EXIT_CODE_GENERIC_ERROR,
+};
+#[cfg(target_arch = "x86_64")]
+use crate::{FC_EXIT_CODE_GUEST_REBOOT, FC_EXIT_CODE_OK};
 
 use crate::vmm_config::instance_info::InstanceInfo;
-use arch::InitrdConfig;
+use crate::vmm_config::resource_limits::{ResourceLimits, ResourceLimitsError};
+use crate::vmm_config::serial::{ConsoleType, SerialConfig};
+use arch::{InitrdConfig, SystemInfo};
 #[cfg(target_arch = "x86_64")]
 use cpuid::common::is_same_model;
+use devices::legacy::{PvPanic, Serial, SerialLog, SerialLogWriter, SnapshotTrigger};
 #[cfg(target_arch = "aarch64")]
-use devices::legacy::RTCDevice;
-use devices::legacy::Serial;
-use devices::virtio::{Balloon, Block, MmioTransport, Net, VirtioDevice, Vsock, VsockUnixBackend};
+use devices::legacy::{RTCDevice, RTC_LOAD_REGISTER_OFFSET};
+use devices::virtio::{
+    Balloon, Block, Console, Fs, MmioTransport, Net, VirtioDevice, Vsock, VsockUnixBackend,
+    DEFAULT_MAX_DESCRIPTORS_PER_EVENT,
+};
 use event_manager::{MutEventSubscriber, SubscriberOps};
 use kernel::cmdline::Cmdline as KernelCmdline;
-#[cfg(target_arch = "aarch64")]
-use logger::METRICS;
-use logger::{error, warn};
-use seccompiler::BpfThreadMap;
+#[cfg(target_arch = "x86_64")]
+use kvm_ioctls::Cap;
+use logger::{error, info, warn, SharedStoreMetric, StoreMetric, METRICS};
+use seccompiler::{BpfProgram, BpfThreadMap};
 use snapshot::Persist;
 use utils::eventfd::EventFd;
 use utils::terminal::Terminal;
 use utils::time::TimestampUs;
-use vm_memory::{GuestAddress, GuestMemoryMmap};
+use vm_memory::mmap::MmapRegionError;
+use vm_memory::{FileOffset, GuestAddress, GuestMemoryMmap};
 #[cfg(target_arch = "aarch64")]
 use vm_superio::RTC;
 
@@ -51,12 +73,27 @@ use vm_superio::RTC;
 pub enum StartMicrovmError {
     /// Unable to attach block device to Vmm.
     AttachBlockDevice(io::Error),
+    /// The microVM did not start within the boot timeout, in milliseconds.
+    BootTimeout(u64),
+    /// The microVM did not start within the boot timeout, in milliseconds, which was hit once
+    /// the VMM thread installed its seccomp filter and can no longer boot another microVM.
+    BootTimeoutWithSeccompFilter(u64),
     /// This error is thrown by the minimal boot loader implementation.
     ConfigureSystem(arch::Error),
+    /// Failed to create the virtio console device.
+    CreateConsoleDevice(devices::virtio::console::Error),
     /// Internal errors are due to resource exhaustion.
     CreateNetDevice(devices::virtio::net::Error),
     /// Failed to create a `RateLimiter` object.
     CreateRateLimiter(io::Error),
+    /// Cannot generate the random seed of the guest kernel.
+    GenerateRngSeed(io::Error),
+    /// The size of the guest memory backing file, in bytes, doesn't match the size of the guest
+    /// memory, in bytes.
+    GuestMemoryBackingFileSize(u64, usize),
+    /// The host couldn't allocate the guest memory, of the given size in MiB, with the given
+    /// memory available on the host, in MiB, if known.
+    GuestMemoryAllocationFailed(usize, Option<usize>),
     /// Memory regions are overlapping or mmap fails.
     GuestMemoryMmap(vm_memory::Error),
     /// Cannot load initrd due to an invalid memory configuration.
@@ -67,6 +104,9 @@ pub enum StartMicrovmError {
     Internal(Error),
     /// The kernel command line is invalid.
     KernelCmdline(String),
+    /// The kernel command line, with the entries added for the devices, is estimated to be
+    /// longer than its maximum size, both in bytes.
+    KernelCmdlineOverflow(usize, usize),
     /// Cannot load kernel due to invalid memory configuration or invalid kernel image.
     KernelLoader(kernel::loader::Error),
     /// Cannot load command line string.
@@ -81,10 +121,19 @@ pub enum StartMicrovmError {
     NetDeviceNotConfigured,
     /// Cannot open the block device backing file.
     OpenBlockDevice(io::Error),
+    /// Cannot open the guest memory backing file.
+    OpenGuestMemoryBackingFile(io::Error),
     /// Cannot initialize a MMIO Device or add a device to the MMIO Bus or cmdline.
     RegisterMmioDevice(device_manager::mmio::Error),
+    /// Restarting the microVM on guest reboot is not possible with a seccomp filter for the
+    /// VMM thread.
+    RestartWithSeccompFilter,
     /// Cannot restore microvm state.
     RestoreMicrovmState(MicrovmStateError),
+    /// Cannot set the resource limits of the Firecracker process.
+    SetResourceLimits(ResourceLimitsError),
+    /// The host can't create the requested interrupt controller.
+    UnsupportedInterruptController(InterruptController, crate::vstate::vm::Error),
 }
 
 /// It's convenient to automatically convert `kernel::cmdline::Error`s
@@ -102,7 +151,19 @@ impl Display for StartMicrovmError {
             AttachBlockDevice(err) => {
                 write!(f, "Unable to attach block device to Vmm. Error: {}", err)
             }
+            BootTimeout(timeout_ms) => write!(
+                f,
+                "The microVM did not start within the boot timeout of {} ms.",
+                timeout_ms
+            ),
+            BootTimeoutWithSeccompFilter(timeout_ms) => write!(
+                f,
+                "The microVM did not start within the boot timeout of {} ms, once the VMM \
+                 thread installed its seccomp filter.",
+                timeout_ms
+            ),
             ConfigureSystem(e) => write!(f, "System configuration error: {:?}", e),
+            CreateConsoleDevice(err) => write!(f, "Cannot create console device. {:?}", err),
             CreateRateLimiter(err) => write!(f, "Cannot create RateLimiter: {}", err),
             CreateNetDevice(err) => {
                 let mut err_msg = format!("{:?}", err);
@@ -110,6 +171,28 @@ impl Display for StartMicrovmError {
 
                 write!(f, "Cannot create network device. {}", err_msg)
             }
+            GenerateRngSeed(err) => write!(
+                f,
+                "Cannot generate the random seed of the guest kernel: {}",
+                err
+            ),
+            GuestMemoryAllocationFailed(mem_size_mib, Some(available_mib)) => write!(
+                f,
+                "Cannot allocate {} MiB of guest memory, with {} MiB of memory available on the \
+                 host.",
+                mem_size_mib, available_mib
+            ),
+            GuestMemoryAllocationFailed(mem_size_mib, None) => write!(
+                f,
+                "Cannot allocate {} MiB of guest memory, the host is out of memory.",
+                mem_size_mib
+            ),
+            GuestMemoryBackingFileSize(file_size, mem_size) => write!(
+                f,
+                "The guest memory backing file has {} bytes, instead of the {} bytes of the \
+                 guest memory.",
+                file_size, mem_size
+            ),
             GuestMemoryMmap(err) => {
                 // Remove imbricated quotes from error message.
                 let mut err_msg = format!("{:?}", err);
@@ -123,6 +206,13 @@ impl Display for StartMicrovmError {
             InitrdRead(err) => write!(f, "Cannot load initrd due to an invalid image: {}", err),
             Internal(err) => write!(f, "Internal error while starting microVM: {}", err),
             KernelCmdline(err) => write!(f, "Invalid kernel command line: {}", err),
+            KernelCmdlineOverflow(len, max_len) => write!(
+                f,
+                "The kernel command line would be up to {} bytes long once the devices are \
+                 attached, more than its maximum size of {} bytes. Attach fewer devices, \
+                 shorten the boot arguments or use a larger CMDLINE_MAX_SIZE.",
+                len, max_len
+            ),
             KernelLoader(err) => {
                 let mut err_msg = format!("{}", err);
                 err_msg = err_msg.replace("\"", "");
@@ -156,6 +246,9 @@ impl Display for StartMicrovmError {
 
                 write!(f, "Cannot open the block device backing file. {}", err_msg)
             }
+            OpenGuestMemoryBackingFile(err) => {
+                write!(f, "Cannot open the guest memory backing file: {}", err)
+            }
             RegisterMmioDevice(err) => {
                 let mut err_msg = format!("{}", err);
                 err_msg = err_msg.replace("\"", "");
@@ -165,7 +258,18 @@ impl Display for StartMicrovmError {
                     err_msg
                 )
             }
+            RestartWithSeccompFilter => write!(
+                f,
+                "Cannot restart the microVM on guest reboot with a seccomp filter for the VMM \
+                 thread."
+            ),
             RestoreMicrovmState(err) => write!(f, "Cannot restore microvm state. Error: {}", err),
+            SetResourceLimits(err) => write!(f, "{}", err),
+            UnsupportedInterruptController(interrupt_controller, err) => write!(
+                f,
+                "The {} interrupt controller is not supported by the host: {}",
+                interrupt_controller, err
+            ),
         }
     }
 }
@@ -216,21 +320,42 @@ impl VmmEventsObserver for SerialStdin {
 }
 
 #[cfg_attr(target_arch = "aarch64", allow(unused))]
+#[cfg_attr(target_arch = "x86_64", allow(unused_variables))]
+#[allow(clippy::too_many_arguments)]
 fn create_vmm_and_vcpus(
     instance_info: &InstanceInfo,
     event_manager: &mut EventManager,
     guest_memory: GuestMemoryMmap,
     track_dirty_pages: bool,
     vcpu_count: u8,
+    interrupt_controller: Option<InterruptController>,
+    serial_config: &SerialConfig,
+    recent_errors: RecentErrors,
 ) -> std::result::Result<(Vmm, Vec<Vcpu>), StartMicrovmError> {
     use self::StartMicrovmError::*;
 
+    let serial_log = if serial_config.log_size > 0 {
+        Some(SerialLog::new(serial_config.log_size))
+    } else {
+        None
+    };
+
     // Set up Kvm Vm and register memory regions.
     let mut vm = setup_kvm_vm(&guest_memory, track_dirty_pages)?;
 
     let vcpus_exit_evt = EventFd::new(libc::EFD_NONBLOCK)
         .map_err(Error::EventFd)
         .map_err(Internal)?;
+    #[cfg(target_arch = "x86_64")]
+    let i8042_reset_evt = EventFd::new(libc::EFD_NONBLOCK)
+        .map_err(Error::EventFd)
+        .map_err(Internal)?;
+    let guest_panic_evt = EventFd::new(libc::EFD_NONBLOCK)
+        .map_err(Error::EventFd)
+        .map_err(Internal)?;
+    let guest_snapshot_evt = EventFd::new(libc::EFD_NONBLOCK)
+        .map_err(Error::EventFd)
+        .map_err(Internal)?;
 
     // Instantiate the MMIO device manager.
     // 'mmio_base' address has to be an address which is protected by the kernel
@@ -249,19 +374,29 @@ fn create_vmm_and_vcpus(
         // Make stdout non blocking.
         set_stdout_nonblocking();
 
-        // Serial device setup.
-        let serial_device = setup_serial_device(
-            event_manager,
-            Box::new(SerialStdin::get()),
-            Box::new(io::stdout()),
-        )
+        // Serial device setup. With the virtio console, the serial port is still present, but
+        // it isn't connected to the host.
+        let serial_device = match serial_config.console_type {
+            ConsoleType::Serial => setup_serial_device(
+                event_manager,
+                serial_input(serial_config.disable_input),
+                serial_output(serial_log.clone()),
+            ),
+            ConsoleType::Virtio => setup_sink_serial_device(),
+        }
         .map_err(Internal)?;
-        // x86_64 uses the i8042 reset event as the Vmm exit event.
-        let reset_evt = vcpus_exit_evt
+        let second_serial_out = serial_config
+            .second_output_path
+            .as_ref()
+            .map(|path| second_serial_output(path.as_path()))
+            .transpose()
+            .map_err(Internal)?;
+        // The i8042 reset event stops the Vmm, like its exit event, but with its own exit code.
+        let reset_evt = i8042_reset_evt
             .try_clone()
             .map_err(Error::EventFd)
             .map_err(Internal)?;
-        create_pio_dev_manager_with_legacy_devices(&vm, serial_device, reset_evt)
+        create_pio_dev_manager_with_legacy_devices(&vm, serial_device, second_serial_out, reset_evt)
             .map_err(Internal)?
     };
 
@@ -272,18 +407,39 @@ fn create_vmm_and_vcpus(
     #[cfg(target_arch = "aarch64")]
     {
         vcpus = create_vcpus(&vm, vcpu_count, &vcpus_exit_evt).map_err(Internal)?;
-        setup_interrupt_controller(&mut vm, vcpu_count)?;
+        setup_interrupt_controller(&mut vm, vcpu_count, interrupt_controller)?;
     }
 
+    // Without serial input, the terminal is left untouched.
+    let events_observer: Option<Box<dyn VmmEventsObserver>> = if serial_config.disable_input {
+        None
+    } else {
+        Some(Box::new(SerialStdin::get()))
+    };
+
     let vmm = Vmm {
-        events_observer: Some(Box::new(SerialStdin::get())),
+        events_observer,
         instance_info: instance_info.clone(),
         shutdown_exit_code: None,
+        serial_log,
+        recent_errors,
         vm,
         guest_memory,
         vcpus_handles: Vec::new(),
+        parked_vcpus: 0,
         vcpus_exit_evt,
+        #[cfg(target_arch = "x86_64")]
+        i8042_reset_evt,
+        #[cfg(target_arch = "x86_64")]
+        reboot_exit_code: FC_EXIT_CODE_OK,
+        guest_panic_evt,
+        guest_snapshot_evt,
+        guest_snapshot: None,
+        shutdown_grace_period: Duration::from_millis(DEFAULT_SHUTDOWN_GRACE_PERIOD_MS),
+        zero_mem_on_shutdown: false,
         mmio_device_manager,
+        device_subscribers: Vec::new(),
+        device_threads: Vec::new(),
         #[cfg(target_arch = "x86_64")]
         pio_device_manager,
     };
@@ -305,72 +461,318 @@ pub fn build_microvm_for_boot(
     seccomp_filters: &BpfThreadMap,
 ) -> std::result::Result<Arc<Mutex<Vmm>>, StartMicrovmError> {
     use self::StartMicrovmError::*;
-    let boot_config = vm_resources.boot_source().ok_or(MissingKernelConfig)?;
+    let boot_start = Instant::now();
+    let mut boot_phases = BootPhases::new();
+    vm_resources.boot_source().ok_or(MissingKernelConfig)?;
+
+    // Booting the microVM again on guest reboot needs the VMM thread to create the VM, open
+    // the device backing files and spawn the vCPU threads, which its filter doesn't allow.
+    if vm_resources.vm_config().on_reboot == Some(RebootAction::Restart)
+        && seccomp_filters
+            .get("vmm")
+            .map_or(false, |filter| !filter.is_empty())
+    {
+        return Err(RestartWithSeccompFilter);
+    }
 
     let track_dirty_pages = vm_resources.track_dirty_pages();
-    let guest_memory = create_guest_memory(
-        vm_resources
-            .vm_config()
-            .mem_size_mib
-            .ok_or(MissingMemSizeConfig)?,
-        track_dirty_pages,
-    )?;
-    let vcpu_config = vm_resources.vcpu_config();
-    let entry_addr = load_kernel(boot_config, &guest_memory)?;
-    let initrd = load_initrd_from_config(boot_config, &guest_memory)?;
-    // Clone the command-line so that a failed boot doesn't pollute the original.
-    #[allow(unused_mut)]
-    let mut boot_cmdline = boot_config.cmdline.clone();
+    let mem_layout = guest_memory_layout(vm_resources.vm_config())?;
+    let guest_memory = match vm_resources.vm_config().mem_backing_file.as_ref() {
+        Some(path) => create_file_backed_guest_memory(path, &mem_layout, track_dirty_pages)?,
+        None => create_guest_memory_with_layout(&mem_layout, track_dirty_pages)?,
+    };
+    boot_phases.end_phase("memory init", &METRICS.latencies_us.vmm_boot_mem_init);
 
     // Timestamp for measuring microVM boot duration.
     let request_ts = TimestampUs::default();
 
-    let (mut vmm, mut vcpus) = create_vmm_and_vcpus(
+    let (mut vmm, vcpus) = create_vmm_and_vcpus(
         instance_info,
         event_manager,
         guest_memory,
         track_dirty_pages,
-        vcpu_config.vcpu_count,
+        vm_resources.vcpu_config().vcpu_count,
+        vm_resources.vm_config().interrupt_controller,
+        &vm_resources.serial_config,
+        vm_resources.recent_errors.clone(),
     )?;
+    boot_phases.end_phase("VM create", &METRICS.latencies_us.vmm_boot_create_vm);
+    if let Some(grace_period_ms) = vm_resources.vm_config().shutdown_grace_period_ms {
+        vmm.shutdown_grace_period = Duration::from_millis(grace_period_ms);
+    }
+    vmm.zero_mem_on_shutdown = vm_resources.vm_config().zero_mem_on_shutdown == Some(true);
+    if let Some(max_devices_per_irq) = vm_resources.vm_config().max_devices_per_irq {
+        vmm.mmio_device_manager
+            .set_max_devices_per_irq(max_devices_per_irq);
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        if vm_resources.vm_config().reset_action == Some(ResetAction::ReportReboot) {
+            vmm.reboot_exit_code = FC_EXIT_CODE_GUEST_REBOOT;
+        }
+    }
+
+    // A failed boot leaves behind a partially built microVM, which is torn down so that
+    // it doesn't leak vCPU threads or device file descriptors.
+    if let Err(err) = start_microvm_for_boot(
+        &mut vmm,
+        vcpus,
+        vm_resources,
+        event_manager,
+        seccomp_filters,
+        request_ts,
+        boot_start,
+        &mut boot_phases,
+    ) {
+        teardown_failed_boot(&mut vmm, event_manager);
+        return Err(err);
+    }
+    boot_phases.log();
+
+    let vmm = Arc::new(Mutex::new(vmm));
+    event_manager.add_subscriber(vmm.clone());
+
+    Ok(vmm)
+}
+
+/// Runs the checks of `build_microvm_for_boot` which don't need the microVM to be built, and
+/// provides all the problems found.
+///
+/// This has no side effects: neither the guest memory nor the KVM VM are created, KVM is only
+/// queried for its capabilities.
+pub fn validate_microvm_for_boot(
+    vm_resources: &super::resources::VmResources,
+    seccomp_filters: &BpfThreadMap,
+) -> Vec<StartMicrovmError> {
+    use self::StartMicrovmError::*;
+    let mut problems = Vec::new();
+
+    match vm_resources.boot_source() {
+        Some(boot_config) => {
+            // The kernel and initrd files were opened when configured, but might have been
+            // truncated since.
+            match boot_config.kernel_file.metadata() {
+                Ok(metadata) if metadata.len() == 0 => problems.push(Internal(Error::KernelFile(
+                    io::Error::new(io::ErrorKind::InvalidData, "Kernel image is empty"),
+                ))),
+                Ok(_) => (),
+                Err(err) => problems.push(Internal(Error::KernelFile(err))),
+            }
+            if let Some(initrd_file) = boot_config.initrd_file.as_ref() {
+                match initrd_file.metadata() {
+                    Ok(metadata) if metadata.len() == 0 => problems.push(InitrdRead(
+                        io::Error::new(io::ErrorKind::InvalidData, "Initrd image is empty"),
+                    )),
+                    Ok(_) => (),
+                    Err(err) => problems.push(InitrdRead(err)),
+                }
+            }
+        }
+        None => problems.push(MissingKernelConfig),
+    }
+
+    if vm_resources.vm_config().on_reboot == Some(RebootAction::Restart)
+        && seccomp_filters
+            .get("vmm")
+            .map_or(false, |filter| !filter.is_empty())
+    {
+        problems.push(RestartWithSeccompFilter);
+    }
+
+    match KvmContext::new() {
+        Ok(kvm) => match guest_memory_layout(vm_resources.vm_config()) {
+            Ok(mem_layout) if mem_layout.len() > kvm.max_memslots() => problems.push(Internal(
+                Error::Vm(crate::vstate::vm::Error::NotEnoughMemorySlots),
+            )),
+            Ok(_) => (),
+            Err(err) => problems.push(err),
+        },
+        Err(err) => problems.push(Internal(Error::KvmContext(err))),
+    }
+
+    // The CPU templates mask the CPUID of Intel hosts only.
+    #[cfg(target_arch = "x86_64")]
+    {
+        if vm_resources.vcpu_config().cpu_template.is_some() {
+            match cpuid::common::get_vendor_id_from_host() {
+                Ok(vendor_id) if &vendor_id == cpuid::common::VENDOR_ID_INTEL => (),
+                Ok(_) => problems.push(Internal(Error::VcpuConfigure(
+                    crate::vstate::vcpu::VcpuError::CpuId(cpuid::Error::InvalidVendor),
+                ))),
+                Err(err) => problems.push(Internal(Error::VcpuConfigure(
+                    crate::vstate::vcpu::VcpuError::CpuId(cpuid::Error::InternalError(err)),
+                ))),
+            }
+        }
+    }
+
+    problems
+}
+
+// Provides the regions of the guest memory, as configured.
+fn guest_memory_layout(
+    vm_config: &VmConfig,
+) -> std::result::Result<Vec<(GuestAddress, usize)>, StartMicrovmError> {
+    let mem_size_mib = vm_config
+        .mem_size_mib
+        .ok_or(StartMicrovmError::MissingMemSizeConfig)?;
+    Ok(match vm_config.mem_regions.as_ref() {
+        Some(mem_regions) => sorted_mem_regions(mem_regions)
+            .iter()
+            .map(|region| (GuestAddress(region.guest_addr), region.size_mib << 20))
+            .collect(),
+        None => arch::arch_memory_regions(mem_size_mib << 20),
+    })
+}
+
+/// Loads the guest kernel, attaches the devices and starts the vCPUs of a microVM created by
+/// `create_vmm_and_vcpus`.
+fn start_microvm_for_boot(
+    vmm: &mut Vmm,
+    mut vcpus: Vec<Vcpu>,
+    vm_resources: &super::resources::VmResources,
+    event_manager: &mut EventManager,
+    seccomp_filters: &BpfThreadMap,
+    request_ts: TimestampUs,
+    boot_start: Instant,
+    boot_phases: &mut BootPhases,
+) -> std::result::Result<(), StartMicrovmError> {
+    use self::StartMicrovmError::*;
+    let boot_config = vm_resources.boot_source().ok_or(MissingKernelConfig)?;
+
+    let vcpu_config = vm_resources.vcpu_config();
+    let entry_addr = load_kernel(boot_config, vmm.guest_memory())?;
+    let initrd = load_initrd_from_config(boot_config, vmm.guest_memory())?;
+    boot_phases.end_phase("kernel load", &METRICS.latencies_us.vmm_boot_load_kernel);
+    // Clone the command-line so that a failed boot doesn't pollute the original.
+    #[allow(unused_mut)]
+    let mut boot_cmdline = boot_config.cmdline.clone();
+    check_cmdline_capacity(&boot_cmdline, vm_resources)?;
+    check_boot_timeout(vm_resources, boot_start)?;
+    // Added before the legacy devices, which leave the aarch64 serial port out without it.
+    if vm_resources.serial_config.auto_console {
+        add_serial_console_to_cmdline(&mut boot_cmdline, &vm_resources.serial_config)?;
+    }
 
     // The boot timer device needs to be the first device attached in order
     // to maintain the same MMIO address referenced in the documentation
     // and tests.
     if vm_resources.boot_timer {
-        attach_boot_timer_device(&mut vmm, request_ts)?;
+        attach_boot_timer_device(vmm, request_ts)?;
     }
 
     if let Some(balloon) = vm_resources.balloon.get() {
-        attach_balloon_device(&mut vmm, &mut boot_cmdline, balloon, event_manager)?;
+        attach_balloon_device(vmm, &mut boot_cmdline, balloon, event_manager)?;
     }
 
+    let max_descriptors_per_event = vm_resources
+        .vm_config()
+        .max_descriptors_per_event
+        .unwrap_or(DEFAULT_MAX_DESCRIPTORS_PER_EVENT);
+    for block in vm_resources.block.list.iter() {
+        block
+            .lock()
+            .expect("Poisoned lock")
+            .set_max_descriptors_per_event(max_descriptors_per_event);
+    }
+    for net in vm_resources.net_builder.iter() {
+        net.lock()
+            .expect("Poisoned lock")
+            .set_max_descriptors_per_event(max_descriptors_per_event);
+    }
     attach_block_devices(
-        &mut vmm,
+        vmm,
         &mut boot_cmdline,
         vm_resources.block.list.iter(),
         event_manager,
     )?;
     attach_net_devices(
-        &mut vmm,
+        vmm,
         &mut boot_cmdline,
         vm_resources.net_builder.iter(),
         event_manager,
     )?;
+    attach_fs_devices(
+        vmm,
+        &mut boot_cmdline,
+        vm_resources.fs.list.iter(),
+        seccomp_filters
+            .get("fs")
+            .ok_or_else(|| MissingSeccompFilters("fs".to_string()))?
+            .clone(),
+    )?;
     if let Some(unix_vsock) = vm_resources.vsock.get() {
-        attach_unixsock_vsock_device(&mut vmm, &mut boot_cmdline, unix_vsock, event_manager)?;
+        attach_unixsock_vsock_device(vmm, &mut boot_cmdline, unix_vsock, event_manager)?;
+    }
+    if vm_resources.serial_config.console_type == ConsoleType::Virtio {
+        attach_console_device(
+            vmm,
+            &mut boot_cmdline,
+            &vm_resources.serial_config,
+            event_manager,
+        )?;
     }
 
     #[cfg(target_arch = "aarch64")]
-    attach_legacy_devices_aarch64(event_manager, &mut vmm, &mut boot_cmdline).map_err(Internal)?;
+    attach_legacy_devices_aarch64(
+        event_manager,
+        vmm,
+        &mut boot_cmdline,
+        &vm_resources.serial_config,
+        vm_resources.vm_config().rtc_base_time,
+    )
+    .map_err(Internal)?;
 
+    // Attached after the other devices, so that it doesn't move them on the MMIO bus.
+    if vm_resources.vm_config().panic_action == Some(PanicAction::ReportPanic) {
+        attach_pvpanic_device(vmm)?;
+    }
+    if let Some(guest_snapshot) = vm_resources.vm_config().guest_snapshot.as_ref() {
+        attach_snapshot_trigger_device(vmm)?;
+        vmm.guest_snapshot = Some(guest_snapshot.clone());
+    }
+    boot_phases.end_phase(
+        "device attach",
+        &METRICS.latencies_us.vmm_boot_attach_devices,
+    );
+
+    // The NUMA node of each guest memory region, in the order of the guest memory regions.
+    let numa_nodes: Option<Vec<u32>> =
+        vm_resources
+            .vm_config()
+            .mem_regions
+            .as_ref()
+            .map(|mem_regions| {
+                sorted_mem_regions(mem_regions)
+                    .iter()
+                    .map(|region| region.numa_node)
+                    .collect()
+            });
+    // The configuration is validated when it is set.
+    let system_info = vm_resources
+        .vm_config()
+        .smbios
+        .clone()
+        .unwrap_or_default()
+        .system_info();
+    // A new seed is generated for every boot, so that guests booted from the same
+    // configuration don't share the state of their CRNG.
+    let rng_seed = match boot_config.description.rng_seed {
+        Some(false) => None,
+        _ => Some(generate_rng_seed()?),
+    };
     configure_system_for_boot(
-        &vmm,
+        vmm,
         vcpus.as_mut(),
         vcpu_config,
         entry_addr,
         &initrd,
         boot_cmdline,
+        numa_nodes.as_deref(),
+        &system_info,
+        rng_seed.as_ref().map(|seed| &seed[..]),
     )?;
+    check_boot_timeout(vm_resources, boot_start)?;
 
     // Move vcpus to their own threads and start their state machine in the 'Paused' state.
     vmm.start_vcpus(
@@ -379,54 +781,253 @@ pub fn build_microvm_for_boot(
             .get("vcpu")
             .ok_or_else(|| MissingSeccompFilters("vcpu".to_string()))?
             .clone(),
+        vm_resources.vm_config().vcpu_affinity.as_ref(),
     )
     .map_err(Internal)?;
+    boot_phases.end_phase(
+        "vCPU configure",
+        &METRICS.latencies_us.vmm_boot_configure_vcpus,
+    );
+
+    // All the long-lived file descriptors are open by now.
+    vm_resources
+        .resource_limits
+        .apply()
+        .map_err(StartMicrovmError::SetResourceLimits)?;
 
     // Load seccomp filters for the VMM thread.
-    // Execution panics if filters cannot be loaded, use --no-seccomp if skipping filters
-    // altogether is the desired behaviour.
+    // The microVM isn't started if filters cannot be loaded, use --no-seccomp if skipping
+    // filters altogether is the desired behaviour.
     // Keep this as the last step before resuming vcpus.
-    seccompiler::apply_filter(
+    seccomp_filters::apply_filter(
         seccomp_filters
             .get("vmm")
             .ok_or_else(|| MissingSeccompFilters("vmm".to_string()))?,
     )
     .map_err(Error::SeccompFilters)
     .map_err(Internal)?;
+    // The VMM thread can't boot another microVM once its filter is installed, so a boot timeout
+    // hit from now on is fatal.
+    let has_vmm_filter = seccomp_filters
+        .get("vmm")
+        .map_or(false, |filter| !filter.is_empty());
+    let check_fatal_boot_timeout = || match check_boot_timeout(vm_resources, boot_start) {
+        Err(BootTimeout(timeout_ms)) if has_vmm_filter => {
+            Err(BootTimeoutWithSeccompFilter(timeout_ms))
+        }
+        result => result,
+    };
+    check_fatal_boot_timeout()?;
+
+    if vm_resources.start_paused {
+        info!("The microVM is started paused, it runs once resumed.");
+        return Ok(());
+    }
 
     // The vcpus start off in the `Paused` state, let them run.
-    vmm.resume_vm().map_err(Internal)?;
+    if let Err(err) = vmm.resume_vm() {
+        // vCPUs which don't respond in time are reported as a boot timeout, if one is set.
+        check_fatal_boot_timeout()?;
+        return Err(Internal(err));
+    }
+    boot_phases.end_phase("vCPU resume", &METRICS.latencies_us.vmm_boot_resume_vcpus);
 
-    let vmm = Arc::new(Mutex::new(vmm));
-    event_manager.add_subscriber(vmm.clone());
+    Ok(())
+}
 
-    Ok(vmm)
+/// Measures the consecutive phases of the microVM boot, in both real and CPU time, to tell
+/// which of them dominates the boot time on a given host.
+struct BootPhases {
+    phase_start: TimestampUs,
+    phases: Vec<(&'static str, TimestampUs)>,
+}
+
+impl BootPhases {
+    /// Starts measuring the first phase.
+    fn new() -> Self {
+        BootPhases {
+            phase_start: TimestampUs::default(),
+            phases: Vec::new(),
+        }
+    }
+
+    /// Ends the current phase, whose duration, in microseconds, is also stored in `metric`, and
+    /// starts the next one.
+    fn end_phase(&mut self, name: &'static str, metric: &SharedStoreMetric) {
+        let now = TimestampUs::default();
+        let duration = TimestampUs {
+            time_us: now.time_us - self.phase_start.time_us,
+            cputime_us: now.cputime_us - self.phase_start.cputime_us,
+        };
+        metric.store(duration.time_us as usize);
+        self.phases.push((name, duration));
+        self.phase_start = now;
+    }
+
+    /// Logs the duration of the ended phases.
+    fn log(&self) {
+        let phases = self
+            .phases
+            .iter()
+            .map(|(name, duration)| {
+                format!(
+                    "{} = {} us, {} CPU us",
+                    name, duration.time_us, duration.cputime_us
+                )
+            })
+            .collect::<Vec<_>>();
+        info!("Boot phases: {}", phases.join("; "));
+    }
+}
+
+/// Checks that the kernel command line can hold the entries added when attaching the devices
+/// of `vm_resources`, using an upper bound of their length, so that a command line which would
+/// overflow is reported before attaching any device.
+fn check_cmdline_capacity(
+    cmdline: &KernelCmdline,
+    vm_resources: &super::resources::VmResources,
+) -> std::result::Result<(), StartMicrovmError> {
+    let mut len = cmdline.len();
+
+    // The guest root is the first root block device, see `attach_block_devices`.
+    if let Some(root) = vm_resources
+        .block
+        .list
+        .iter()
+        .find(|block| block.lock().expect("Poisoned lock").is_root_device())
+    {
+        len += match root.lock().expect("Poisoned lock").partuuid() {
+            Some(partuuid) => " root=PARTUUID=".len() + partuuid.len(),
+            None => " root=/dev/vda".len(),
+        } + " rw".len();
+    }
+    let virtio_console = vm_resources.serial_config.console_type == ConsoleType::Virtio;
+    if virtio_console {
+        len += " console=hvc0".len();
+    } else if vm_resources.serial_config.auto_console && !has_console(cmdline) {
+        len += " console=ttyS0".len();
+    }
+
+    // The virtio devices are only described on the command line on x86_64.
+    #[cfg(target_arch = "x86_64")]
+    {
+        let device_count = vm_resources.block.list.len()
+            + vm_resources.net_builder.iter().count()
+            + vm_resources.fs.list.len()
+            + vm_resources.vsock.get().is_some() as usize
+            + vm_resources.balloon.get().is_some() as usize
+            + virtio_console as usize;
+        len += device_count * MMIODeviceManager::virtio_device_cmdline_max_len();
+    }
+
+    // The capacity includes the nul terminator.
+    if len >= cmdline.capacity() {
+        return Err(StartMicrovmError::KernelCmdlineOverflow(
+            len,
+            cmdline.capacity() - 1,
+        ));
+    }
+    Ok(())
+}
+
+// Says if the kernel command line selects a console.
+fn has_console(cmdline: &KernelCmdline) -> bool {
+    cmdline
+        .as_str()
+        .split(' ')
+        .any(|param| param.starts_with("console="))
+}
+
+/// Adds the `console=` parameter of the serial port to the kernel command line, unless the
+/// command line already selects a console. The virtio console always adds its own parameter,
+/// see `attach_console_device`.
+fn add_serial_console_to_cmdline(
+    cmdline: &mut KernelCmdline,
+    serial_config: &SerialConfig,
+) -> std::result::Result<(), StartMicrovmError> {
+    if serial_config.console_type == ConsoleType::Serial && !has_console(cmdline) {
+        cmdline.insert("console", ConsoleType::Serial.guest_device())?;
+    }
+    Ok(())
+}
+
+/// Checks that the microVM is still within the boot timeout, if one is configured, since the
+/// boot started at `boot_start`. The boot steps can't be interrupted, so the timeout is only
+/// checked between them.
+fn check_boot_timeout(
+    vm_resources: &super::resources::VmResources,
+    boot_start: Instant,
+) -> std::result::Result<(), StartMicrovmError> {
+    match vm_resources.boot_timeout_ms {
+        Some(timeout_ms) if boot_start.elapsed() >= Duration::from_millis(timeout_ms) => {
+            Err(StartMicrovmError::BootTimeout(timeout_ms))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Tears down a microVM which failed to boot: the vCPU threads are stopped and joined, and the
+/// devices are unregistered from the `EventManager`, which releases their file descriptors.
+fn teardown_failed_boot(vmm: &mut Vmm, event_manager: &mut EventManager) {
+    vmm.stop(FC_EXIT_CODE_GENERIC_ERROR);
+    for subscriber_id in vmm.device_subscribers.drain(..) {
+        if let Err(err) = event_manager.remove_subscriber(subscriber_id) {
+            warn!(
+                "Failed to unregister a device from the event manager: {:?}",
+                err
+            );
+        }
+    }
 }
 
 /// Builds and starts a microVM based on the provided MicrovmState.
 ///
 /// An `Arc` reference of the built `Vmm` is also plugged in the `EventManager`, while another
-/// is returned.
+/// is returned. When the TSC of the guest can't be scaled to the frequency saved in the
+/// snapshot, the build fails if `require_tsc_scaling` is set and only warns otherwise.
+#[allow(clippy::too_many_arguments)]
 pub fn build_microvm_from_snapshot(
     instance_info: &InstanceInfo,
     event_manager: &mut EventManager,
     microvm_state: MicrovmState,
     guest_memory: GuestMemoryMmap,
     track_dirty_pages: bool,
+    rebase_clock: bool,
+    #[cfg_attr(target_arch = "aarch64", allow(unused_variables))] require_tsc_scaling: bool,
     seccomp_filters: &BpfThreadMap,
+    serial_config: SerialConfig,
+    resource_limits: ResourceLimits,
+    recent_errors: RecentErrors,
 ) -> std::result::Result<Arc<Mutex<Vmm>>, StartMicrovmError> {
     use self::StartMicrovmError::*;
     let vcpu_count = u8::try_from(microvm_state.vcpu_states.len())
         .map_err(|_| MicrovmStateError::InvalidInput)
         .map_err(RestoreMicrovmState)?;
 
+    #[cfg(target_arch = "aarch64")]
+    if rebase_clock {
+        return Err(RestoreMicrovmState(MicrovmStateError::IncompatibleState(
+            "Rebasing the guest clock is not supported on aarch64.".to_string(),
+        )));
+    }
+
+    if serial_config.console_type == ConsoleType::Virtio {
+        return Err(RestoreMicrovmState(MicrovmStateError::IncompatibleState(
+            "The virtio console is not supported with snapshots.".to_string(),
+        )));
+    }
+
     // Build Vmm.
-    let (mut vmm, vcpus) = create_vmm_and_vcpus(
+    #[allow(unused_mut)]
+    let (mut vmm, mut vcpus) = create_vmm_and_vcpus(
         instance_info,
         event_manager,
         guest_memory.clone(),
         track_dirty_pages,
         vcpu_count,
+        None,
+        &serial_config,
+        recent_errors,
     )?;
 
     #[cfg(target_arch = "x86_64")]
@@ -435,32 +1036,11 @@ pub fn build_microvm_from_snapshot(
     // the same as this host's. If they are the same, we don't
     // need to do anything else.
     if !is_same_model(&microvm_state.vcpu_states[0].cpuid) {
-        // Extract the TSC freq from the state.
-        // No TSC freq in snapshot means we have to fail-fast.
-        let state_tsc = microvm_state.vcpu_states[0]
-            .tsc_khz
-            .ok_or_else(|| {
-                MicrovmStateError::IncompatibleState(
-                    "Error configuring the TSC, frequency not \
-                    present in snapshot."
-                        .to_string(),
-                )
-            })
-            .map_err(RestoreMicrovmState)?;
-
-        // Scale the TSC frequency for all VCPUs, if needed.
-        if vcpus[0]
-            .kvm_vcpu
-            .is_tsc_scaling_required(state_tsc)
-            .map_err(|e| MicrovmStateError::IncompatibleState(e.to_string()))
-            .map_err(RestoreMicrovmState)?
-        {
-            for vcpu in &vcpus {
-                vcpu.kvm_vcpu
-                    .set_tsc_khz(state_tsc)
-                    .map_err(|e| MicrovmStateError::IncompatibleState(e.to_string()))
-                    .map_err(RestoreMicrovmState)?;
+        if let Err(e) = scale_tsc(&vmm.vm, &vcpus, microvm_state.vcpu_states[0].tsc_khz) {
+            if require_tsc_scaling {
+                return Err(RestoreMicrovmState(MicrovmStateError::IncompatibleState(e)));
             }
+            warn!("{} The guest timekeeping may drift.", e);
         }
     }
 
@@ -476,16 +1056,30 @@ pub fn build_microvm_from_snapshot(
 
     // Restore kvm vm state.
     #[cfg(target_arch = "x86_64")]
-    vmm.vm
-        .restore_state(&microvm_state.vm_state)
-        .map_err(MicrovmStateError::RestoreVmState)
-        .map_err(RestoreMicrovmState)?;
+    {
+        vmm.vm
+            .restore_state(&microvm_state.vm_state)
+            .map_err(MicrovmStateError::RestoreVmState)
+            .map_err(RestoreMicrovmState)?;
+
+        if rebase_clock {
+            vmm.vm
+                .rebase_clock(&microvm_state.vm_state)
+                .map_err(MicrovmStateError::RestoreVmState)
+                .map_err(RestoreMicrovmState)?;
+            for vcpu in vcpus.iter_mut() {
+                vcpu.kvm_vcpu.pvclock_stopped_hint = true;
+            }
+        }
+    }
 
     // Restore devices states.
     let mmio_ctor_args = MMIODevManagerConstructorArgs {
         mem: guest_memory,
         vm: vmm.vm.fd(),
         event_manager,
+        disable_serial_input: serial_config.disable_input,
+        serial_log: vmm.serial_log.clone(),
     };
     vmm.mmio_device_manager =
         MMIODeviceManager::restore(mmio_ctor_args, &microvm_state.device_states)
@@ -499,6 +1093,7 @@ pub fn build_microvm_from_snapshot(
             .get("vcpu")
             .ok_or_else(|| MissingSeccompFilters("vcpu".to_string()))?
             .clone(),
+        None,
     )
     .map_err(Internal)?;
 
@@ -509,9 +1104,14 @@ pub fn build_microvm_from_snapshot(
     let vmm = Arc::new(Mutex::new(vmm));
     event_manager.add_subscriber(vmm.clone());
 
+    // All the long-lived file descriptors are open by now.
+    resource_limits
+        .apply()
+        .map_err(StartMicrovmError::SetResourceLimits)?;
+
     // Load seccomp filters for the VMM thread.
     // Keep this as the last step of the building process.
-    seccompiler::apply_filter(
+    seccomp_filters::apply_filter(
         seccomp_filters
             .get("vmm")
             .ok_or_else(|| MissingSeccompFilters("vmm".to_string()))?,
@@ -522,6 +1122,34 @@ pub fn build_microvm_from_snapshot(
     Ok(vmm)
 }
 
+// Scales the TSC of the vCPUs to the frequency saved in the snapshot, when the TSC of this
+// host runs at another frequency, so that the guest timekeeping doesn't drift.
+#[cfg(target_arch = "x86_64")]
+fn scale_tsc(vm: &Vm, vcpus: &[Vcpu], state_tsc: Option<u32>) -> std::result::Result<(), String> {
+    // Snapshots created before Firecracker v0.25 don't hold the TSC frequency.
+    let state_tsc =
+        state_tsc.ok_or_else(|| "The TSC frequency is not present in the snapshot.".to_string())?;
+    if !vcpus[0]
+        .kvm_vcpu
+        .is_tsc_scaling_required(state_tsc)
+        .map_err(|e| e.to_string())?
+    {
+        return Ok(());
+    }
+    if !vm.fd().check_extension(Cap::TscControl) {
+        return Err(format!(
+            "The host can't scale the TSC to the {} kHz frequency of the snapshot.",
+            state_tsc
+        ));
+    }
+    for vcpu in vcpus {
+        vcpu.kvm_vcpu
+            .set_tsc_khz(state_tsc)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
 /// Creates GuestMemory of `mem_size_mib` MiB in size.
 pub fn create_guest_memory(
     mem_size_mib: usize,
@@ -530,8 +1158,85 @@ pub fn create_guest_memory(
     let mem_size = mem_size_mib << 20;
     let arch_mem_regions = arch::arch_memory_regions(mem_size);
 
-    GuestMemoryMmap::from_ranges_guarded(&arch_mem_regions, track_dirty_pages)
-        .map_err(StartMicrovmError::GuestMemoryMmap)
+    create_guest_memory_with_layout(&arch_mem_regions, track_dirty_pages)
+}
+
+/// Creates GuestMemory made of the given regions, each registered as a separate memory slot.
+pub fn create_guest_memory_with_layout(
+    mem_layout: &[(GuestAddress, usize)],
+    track_dirty_pages: bool,
+) -> std::result::Result<GuestMemoryMmap, StartMicrovmError> {
+    GuestMemoryMmap::from_ranges_guarded(mem_layout, track_dirty_pages).map_err(|err| {
+        let mem_size_mib = mem_layout.iter().map(|(_, size)| size).sum::<usize>() >> 20;
+        guest_memory_error(err, mem_size_mib)
+    })
+}
+
+/// Creates GuestMemory made of the given regions, backed by consecutive ranges of the file at
+/// `path`. The file has to be exactly as large as the guest memory. It is mapped shared, so
+/// the guest memory can be observed by other processes mapping the same file.
+pub fn create_file_backed_guest_memory(
+    path: &Path,
+    mem_layout: &[(GuestAddress, usize)],
+    track_dirty_pages: bool,
+) -> std::result::Result<GuestMemoryMmap, StartMicrovmError> {
+    use self::StartMicrovmError::{GuestMemoryBackingFileSize, OpenGuestMemoryBackingFile};
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(OpenGuestMemoryBackingFile)?;
+    let file_size = file.metadata().map_err(OpenGuestMemoryBackingFile)?.len();
+    let mem_size = mem_layout.iter().map(|(_, size)| size).sum::<usize>();
+    if file_size != mem_size as u64 {
+        return Err(GuestMemoryBackingFileSize(file_size, mem_size));
+    }
+
+    let mut ranges = Vec::with_capacity(mem_layout.len());
+    let mut offset = 0;
+    for (guest_addr, size) in mem_layout.iter() {
+        let file = file.try_clone().map_err(OpenGuestMemoryBackingFile)?;
+        ranges.push((*guest_addr, *size, Some(FileOffset::new(file, offset))));
+        offset += *size as u64;
+    }
+    GuestMemoryMmap::from_ranges_with_files_guarded(&ranges, track_dirty_pages)
+        .map_err(|err| guest_memory_error(err, mem_size >> 20))
+}
+
+// Tells the allocation failures of the guest memory, which mean that the microVM is too big for
+// the host, apart from the other memory errors.
+fn guest_memory_error(err: vm_memory::Error, mem_size_mib: usize) -> StartMicrovmError {
+    match err {
+        vm_memory::Error::MmapRegion(MmapRegionError::Mmap(ref io_err))
+            if io_err.raw_os_error() == Some(libc::ENOMEM) =>
+        {
+            StartMicrovmError::GuestMemoryAllocationFailed(mem_size_mib, host_mem_available_mib())
+        }
+        err => StartMicrovmError::GuestMemoryMmap(err),
+    }
+}
+
+// Fills a seed for the guest kernel CRNG from the host CSPRNG. The seed is secret, it must not
+// be logged.
+fn generate_rng_seed() -> std::result::Result<[u8; RNG_SEED_SIZE], StartMicrovmError> {
+    let mut seed = [0u8; RNG_SEED_SIZE];
+    // Safe because the kernel writes at most `seed.len()` bytes in `seed`, and we check the
+    // return value.
+    let ret = unsafe { libc::syscall(libc::SYS_getrandom, seed.as_mut_ptr(), seed.len(), 0) };
+    if ret < 0 {
+        return Err(StartMicrovmError::GenerateRngSeed(
+            io::Error::last_os_error(),
+        ));
+    }
+    // Requests of up to 256 bytes are never partially filled, once the host CSPRNG is ready.
+    if ret as usize != seed.len() {
+        return Err(StartMicrovmError::GenerateRngSeed(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "The host CSPRNG returned a partial seed.",
+        )));
+    }
+    Ok(seed)
 }
 
 fn load_kernel(
@@ -632,50 +1337,112 @@ pub fn setup_interrupt_controller(vm: &mut Vm) -> std::result::Result<(), StartM
         .map_err(StartMicrovmError::Internal)
 }
 
-/// Sets up the irqchip for a aarch64 microVM.
+/// Sets up the irqchip for a aarch64 microVM, of the requested version if any.
 #[cfg(target_arch = "aarch64")]
 pub fn setup_interrupt_controller(
     vm: &mut Vm,
     vcpu_count: u8,
+    interrupt_controller: Option<InterruptController>,
 ) -> std::result::Result<(), StartMicrovmError> {
-    vm.setup_irqchip(vcpu_count)
-        .map_err(Error::Vm)
-        .map_err(StartMicrovmError::Internal)
+    use arch::aarch64::gic::GICVersion;
+
+    let version = match interrupt_controller {
+        Some(InterruptController::GicV2) => Some(GICVersion::GICV2),
+        Some(InterruptController::GicV3) => Some(GICVersion::GICV3),
+        _ => None,
+    };
+    // Only the GIC versions are accepted on aarch64, so a requested controller is always
+    // mapped to a version and its creation failing means the host doesn't support it.
+    vm.setup_irqchip(vcpu_count, version)
+        .map_err(|err| match interrupt_controller {
+            Some(interrupt_controller) => {
+                StartMicrovmError::UnsupportedInterruptController(interrupt_controller, err)
+            }
+            None => StartMicrovmError::Internal(Error::Vm(err)),
+        })
+}
+
+/// Returns the input source of the serial console, or `None` if input is disabled.
+pub(crate) fn serial_input(
+    disable_serial_input: bool,
+) -> Option<Box<dyn devices::legacy::ReadableFd + Send>> {
+    if disable_serial_input {
+        None
+    } else {
+        Some(Box::new(SerialStdin::get()))
+    }
+}
+
+/// Returns the output of the serial console, which is also recorded in `serial_log` if any.
+pub(crate) fn serial_output(serial_log: Option<SerialLog>) -> Box<dyn io::Write + Send> {
+    match serial_log {
+        Some(serial_log) => Box::new(SerialLogWriter::new(Box::new(io::stdout()), serial_log)),
+        None => Box::new(io::stdout()),
+    }
+}
+
+/// Sets up a serial device which is not connected to the host, discarding the guest output.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn setup_sink_serial_device() -> super::Result<Arc<Mutex<Serial>>> {
+    let interrupt_evt = EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?;
+    Ok(Arc::new(Mutex::new(Serial::new_sink(interrupt_evt))))
 }
 
-/// Sets up the serial device.
+/// Sets up the serial device. Without an `input`, the serial console is output-only.
 pub fn setup_serial_device(
     event_manager: &mut EventManager,
-    input: Box<dyn devices::legacy::ReadableFd + Send>,
+    input: Option<Box<dyn devices::legacy::ReadableFd + Send>>,
     out: Box<dyn io::Write + Send>,
 ) -> super::Result<Arc<Mutex<Serial>>> {
     let interrupt_evt = EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?;
-    let kick_stdin_read_evt = EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?;
-    let serial = Arc::new(Mutex::new(Serial::new_in_out(
-        interrupt_evt,
-        input,
-        out,
-        Some(kick_stdin_read_evt),
-    )));
+    let serial = match input {
+        Some(input) => {
+            let kick_stdin_read_evt = EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?;
+            Serial::new_in_out(interrupt_evt, input, out, Some(kick_stdin_read_evt))
+        }
+        None => Serial::new_out(interrupt_evt, out),
+    };
+    let serial = Arc::new(Mutex::new(serial));
     event_manager.add_subscriber(serial.clone());
     Ok(serial)
 }
 
 #[cfg(target_arch = "aarch64")]
-/// Sets up the RTC device.
-pub fn setup_rtc_device() -> Arc<Mutex<RTCDevice>> {
-    let rtc = RTC::with_events(METRICS.rtc.clone());
+/// Sets up the RTC device. It reads `base_time` (in seconds since the Unix epoch) at first, if
+/// set, and the host time otherwise.
+pub fn setup_rtc_device(base_time: Option<u32>) -> Arc<Mutex<RTCDevice>> {
+    let mut rtc = RTC::with_events(METRICS.rtc.clone());
+    if let Some(base_time) = base_time {
+        // Loading the RTC, like the guest would, offsets it from the host time.
+        rtc.write(RTC_LOAD_REGISTER_OFFSET, &base_time.to_le_bytes());
+    }
     Arc::new(Mutex::new(rtc))
 }
 
+/// Opens the file or FIFO which receives the output of the second serial port. Like stdout,
+/// it is non-blocking, so that a slow reader can't stall the vCPU writing to the port.
+#[cfg(target_arch = "x86_64")]
+fn second_serial_output(path: &std::path::Path) -> super::Result<Box<dyn io::Write + Send>> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)
+        .map_err(Error::SerialOutput)?;
+    Ok(Box::new(file))
+}
+
 #[cfg(target_arch = "x86_64")]
 fn create_pio_dev_manager_with_legacy_devices(
     vm: &Vm,
     serial: Arc<Mutex<devices::legacy::Serial>>,
+    second_serial_out: Option<Box<dyn io::Write + Send>>,
     i8042_reset_evfd: EventFd,
 ) -> std::result::Result<PortIODeviceManager, super::Error> {
-    let mut pio_dev_mgr =
-        PortIODeviceManager::new(serial, i8042_reset_evfd).map_err(Error::CreateLegacyDevice)?;
+    let mut pio_dev_mgr = PortIODeviceManager::new(serial, second_serial_out, i8042_reset_evfd)
+        .map_err(Error::CreateLegacyDevice)?;
     pio_dev_mgr
         .register_devices(vm.fd())
         .map_err(Error::LegacyIOBus)?;
@@ -687,15 +1454,17 @@ fn attach_legacy_devices_aarch64(
     event_manager: &mut EventManager,
     vmm: &mut Vmm,
     cmdline: &mut KernelCmdline,
+    serial_config: &SerialConfig,
+    rtc_base_time: Option<u32>,
 ) -> super::Result<()> {
-    // Serial device setup.
-    if cmdline.as_str().contains("console=") {
+    // Serial device setup. With the virtio console, the serial device is left out.
+    if serial_config.console_type == ConsoleType::Serial && has_console(cmdline) {
         // Make stdout non-blocking.
         set_stdout_nonblocking();
         let serial = setup_serial_device(
             event_manager,
-            Box::new(SerialStdin::get()),
-            Box::new(io::stdout()),
+            serial_input(serial_config.disable_input),
+            serial_output(vmm.serial_log.clone()),
         )?;
         vmm.mmio_device_manager
             .register_mmio_serial(vmm.vm.fd(), serial, None)
@@ -705,7 +1474,7 @@ fn attach_legacy_devices_aarch64(
             .map_err(Error::RegisterMMIODevice)?;
     }
 
-    let rtc = setup_rtc_device();
+    let rtc = setup_rtc_device(rtc_base_time);
     vmm.mmio_device_manager
         .register_mmio_rtc(rtc, None)
         .map_err(Error::RegisterMMIODevice)
@@ -726,7 +1495,14 @@ fn create_vcpus(vm: &Vm, vcpu_count: u8, exit_evt: &EventFd) -> super::Result<Ve
 }
 
 /// Configures the system for booting Linux.
+///
+/// On aarch64, the guest is told the NUMA node of each guest memory region through the FDT.
+/// There is no such mechanism on x86_64, where the configuration can't assign NUMA nodes and
+/// `numa_nodes` is ignored. The `system_info` is written in the SMBIOS tables on x86_64 and in
+/// the FDT on aarch64. The `rng_seed`, if any, is passed through the setup_data on x86_64 and
+/// through the FDT on aarch64.
 #[cfg_attr(target_arch = "aarch64", allow(unused))]
+#[cfg_attr(target_arch = "x86_64", allow(unused_variables))]
 pub fn configure_system_for_boot(
     vmm: &Vmm,
     vcpus: &mut [Vcpu],
@@ -734,6 +1510,9 @@ pub fn configure_system_for_boot(
     entry_addr: GuestAddress,
     initrd: &Option<InitrdConfig>,
     boot_cmdline: KernelCmdline,
+    numa_nodes: Option<&[u32]>,
+    system_info: &SystemInfo,
+    rng_seed: Option<&[u8]>,
 ) -> std::result::Result<(), StartMicrovmError> {
     use self::StartMicrovmError::*;
     #[cfg(target_arch = "x86_64")]
@@ -764,6 +1543,8 @@ pub fn configure_system_for_boot(
             boot_cmdline.len() + 1,
             initrd,
             vcpus.len() as u8,
+            Some(system_info),
+            rng_seed,
         )
         .map_err(ConfigureSystem)?;
     }
@@ -787,6 +1568,9 @@ pub fn configure_system_for_boot(
             vmm.mmio_device_manager.get_device_info(),
             vmm.vm.get_irqchip(),
             initrd,
+            numa_nodes,
+            Some(system_info),
+            rng_seed,
         )
         .map_err(ConfigureSystem)?;
     }
@@ -803,7 +1587,8 @@ fn attach_virtio_device<T: 'static + VirtioDevice + MutEventSubscriber>(
 ) -> std::result::Result<(), StartMicrovmError> {
     use self::StartMicrovmError::*;
 
-    event_manager.add_subscriber(device.clone());
+    let subscriber_id = event_manager.add_subscriber(device.clone());
+    vmm.device_subscribers.push(subscriber_id);
 
     // The device mutex mustn't be locked here otherwise it will deadlock.
     let device = MmioTransport::new(vmm.guest_memory().clone(), device);
@@ -813,6 +1598,36 @@ fn attach_virtio_device<T: 'static + VirtioDevice + MutEventSubscriber>(
         .map(|_| ())
 }
 
+fn attach_pvpanic_device(vmm: &mut Vmm) -> std::result::Result<(), StartMicrovmError> {
+    use self::StartMicrovmError::*;
+
+    let panic_evt = vmm
+        .guest_panic_evt
+        .try_clone()
+        .map_err(Error::EventFd)
+        .map_err(Internal)?;
+    vmm.mmio_device_manager
+        .register_mmio_pvpanic(PvPanic::new(panic_evt))
+        .map_err(RegisterMmioDevice)?;
+
+    Ok(())
+}
+
+fn attach_snapshot_trigger_device(vmm: &mut Vmm) -> std::result::Result<(), StartMicrovmError> {
+    use self::StartMicrovmError::*;
+
+    let trigger_evt = vmm
+        .guest_snapshot_evt
+        .try_clone()
+        .map_err(Error::EventFd)
+        .map_err(Internal)?;
+    vmm.mmio_device_manager
+        .register_mmio_snapshot_trigger(SnapshotTrigger::new(trigger_evt))
+        .map_err(RegisterMmioDevice)?;
+
+    Ok(())
+}
+
 pub(crate) fn attach_boot_timer_device(
     vmm: &mut Vmm,
     request_ts: TimestampUs,
@@ -834,10 +1649,13 @@ fn attach_block_devices<'a>(
     blocks: impl Iterator<Item = &'a Arc<Mutex<Block>>>,
     event_manager: &mut EventManager,
 ) -> std::result::Result<(), StartMicrovmError> {
+    let mut has_root = false;
     for block in blocks {
         let id = {
             let locked = block.lock().expect("Poisoned lock");
-            if locked.is_root_device() {
+            // The root devices come first in boot order, so the guest root is the first of them.
+            if locked.is_root_device() && !has_root {
+                has_root = true;
                 cmdline.insert_str(if let Some(partuuid) = locked.partuuid() {
                     format!("root=PARTUUID={}", partuuid)
                 } else {
@@ -870,6 +1688,35 @@ fn attach_net_devices<'a>(
     Ok(())
 }
 
+/// Attaches the virtio-fs devices, which are served on their own thread, under the
+/// `fs_seccomp_filter`, instead of the VMM thread.
+fn attach_fs_devices<'a>(
+    vmm: &mut Vmm,
+    cmdline: &mut KernelCmdline,
+    fs_devices: impl Iterator<Item = &'a Arc<Mutex<Fs>>>,
+    fs_seccomp_filter: Arc<BpfProgram>,
+) -> std::result::Result<(), StartMicrovmError> {
+    use self::StartMicrovmError::*;
+
+    let mut subscribers: Vec<Arc<Mutex<dyn MutEventSubscriber + Send>>> = Vec::new();
+    for fs_device in fs_devices {
+        let id = String::from(fs_device.lock().expect("Poisoned lock").id());
+        // The device mutex mustn't be locked here otherwise it will deadlock.
+        let device = MmioTransport::new(vmm.guest_memory().clone(), fs_device.clone());
+        vmm.mmio_device_manager
+            .register_mmio_virtio_for_boot(vmm.vm.fd(), id, device, cmdline)
+            .map_err(RegisterMmioDevice)?;
+        subscribers.push(fs_device.clone());
+    }
+    if !subscribers.is_empty() {
+        let fs_thread = DeviceThread::start("fc_fs", subscribers, fs_seccomp_filter)
+            .map_err(Error::DeviceThread)
+            .map_err(Internal)?;
+        vmm.device_threads.push(fs_thread);
+    }
+    Ok(())
+}
+
 fn attach_unixsock_vsock_device(
     vmm: &mut Vmm,
     cmdline: &mut KernelCmdline,
@@ -892,6 +1739,37 @@ fn attach_balloon_device(
     attach_virtio_device(event_manager, vmm, id, balloon.clone(), cmdline)
 }
 
+/// Attaches a virtio console connected to the Firecracker stdin and stdout, and makes it the
+/// primary console of the guest.
+fn attach_console_device(
+    vmm: &mut Vmm,
+    cmdline: &mut KernelCmdline,
+    serial_config: &SerialConfig,
+    event_manager: &mut EventManager,
+) -> std::result::Result<(), StartMicrovmError> {
+    use self::StartMicrovmError::*;
+
+    // Make stdout non-blocking.
+    set_stdout_nonblocking();
+    let console = Console::new(
+        serial_input(serial_config.disable_input),
+        serial_output(vmm.serial_log.clone()),
+    )
+    .map_err(CreateConsoleDevice)?;
+    let id = String::from(console.id());
+    attach_virtio_device(
+        event_manager,
+        vmm,
+        id,
+        Arc::new(Mutex::new(console)),
+        cmdline,
+    )?;
+
+    // The kernel uses the last `console=` parameter for /dev/console.
+    cmdline.insert("console", ConsoleType::Virtio.guest_device())?;
+    Ok(())
+}
+
 // Adds `O_NONBLOCK` to the stdout flags.
 pub(crate) fn set_stdout_nonblocking() {
     let flags = unsafe { libc::fcntl(libc::STDOUT_FILENO, libc::F_GETFL, 0) };
@@ -909,21 +1787,33 @@ pub mod tests {
     use std::io::Cursor;
 
     use super::*;
+    use crate::resources::VmResources;
     use crate::vmm_config::balloon::{BalloonBuilder, BalloonDeviceConfig, BALLOON_DEV_ID};
-    use crate::vmm_config::boot_source::DEFAULT_KERNEL_CMDLINE;
-    use crate::vmm_config::drive::{BlockBuilder, BlockDeviceConfig, CacheType};
+    use crate::vmm_config::boot_source::{BootSourceConfig, DEFAULT_KERNEL_CMDLINE};
+    use crate::vmm_config::drive::{
+        AccessPattern, BlockBuilder, BlockDeviceConfig, CacheType, DiskBacking, OpenRetryConfig,
+    };
+    use crate::vmm_config::machine_config::GuestSnapshotConfig;
     use crate::vmm_config::net::{NetBuilder, NetworkInterfaceConfig};
     use crate::vmm_config::vsock::tests::default_config;
     use crate::vmm_config::vsock::{VsockBuilder, VsockDeviceConfig};
+    use crate::FC_EXIT_CODE_GUEST_PANIC;
     use arch::DeviceType;
-    use devices::virtio::{TYPE_BALLOON, TYPE_BLOCK, TYPE_VSOCK};
+    use devices::legacy::{PVPANIC_PANICKED, SNAPSHOT_TRIGGER_REQUEST};
+    use devices::virtio::{
+        CONSOLE_DEV_ID, TYPE_BALLOON, TYPE_BLOCK, TYPE_CONSOLE, TYPE_FS, TYPE_VSOCK,
+    };
+    use devices::BusDevice;
     use kernel::cmdline::Cmdline;
+    use logger::IncMetric;
+    use utils::tempdir::TempDir;
     use utils::tempfile::TempFile;
 
     pub(crate) struct CustomBlockConfig {
         drive_id: String,
         is_root_device: bool,
         partuuid: Option<String>,
+        boot_index: Option<u32>,
         is_read_only: bool,
         cache_type: CacheType,
     }
@@ -940,6 +1830,7 @@ pub mod tests {
                 drive_id,
                 is_root_device,
                 partuuid,
+                boot_index: None,
                 is_read_only,
                 cache_type,
             }
@@ -956,6 +1847,7 @@ pub mod tests {
             Arc::new(Mutex::new(Serial::new_sink(
                 EventFd::new(libc::EFD_NONBLOCK).unwrap(),
             ))),
+            None,
             EventFd::new(libc::EFD_NONBLOCK).unwrap(),
         )
         .unwrap()
@@ -987,18 +1879,32 @@ pub mod tests {
         {
             let exit_evt = EventFd::new(libc::EFD_NONBLOCK).unwrap();
             let _vcpu = Vcpu::new(1, &vm, exit_evt).unwrap();
-            setup_interrupt_controller(&mut vm, 1).unwrap();
+            setup_interrupt_controller(&mut vm, 1, None).unwrap();
         }
 
         Vmm {
             events_observer: Some(Box::new(SerialStdin::get())),
             instance_info: InstanceInfo::default(),
             shutdown_exit_code: None,
+            serial_log: None,
+            recent_errors: RecentErrors::default(),
             vm,
             guest_memory,
             vcpus_handles: Vec::new(),
+            parked_vcpus: 0,
             vcpus_exit_evt,
+            #[cfg(target_arch = "x86_64")]
+            i8042_reset_evt: EventFd::new(libc::EFD_NONBLOCK).unwrap(),
+            #[cfg(target_arch = "x86_64")]
+            reboot_exit_code: FC_EXIT_CODE_OK,
+            guest_panic_evt: EventFd::new(libc::EFD_NONBLOCK).unwrap(),
+            guest_snapshot_evt: EventFd::new(libc::EFD_NONBLOCK).unwrap(),
+            guest_snapshot: None,
+            shutdown_grace_period: Duration::from_millis(DEFAULT_SHUTDOWN_GRACE_PERIOD_MS),
+            zero_mem_on_shutdown: false,
             mmio_device_manager,
+            device_subscribers: Vec::new(),
+            device_threads: Vec::new(),
             #[cfg(target_arch = "x86_64")]
             pio_device_manager,
         }
@@ -1023,10 +1929,15 @@ pub mod tests {
                     .to_str()
                     .unwrap()
                     .to_string(),
+                backing: DiskBacking::File,
                 is_root_device: custom_block_cfg.is_root_device,
                 partuuid: custom_block_cfg.partuuid.clone(),
+                boot_index: custom_block_cfg.boot_index,
                 is_read_only: custom_block_cfg.is_read_only,
                 cache_type: custom_block_cfg.cache_type,
+                open_retry: OpenRetryConfig::default(),
+                access_pattern: AccessPattern::default(),
+                interrupt_coalescing_us: 0,
                 rate_limiter: None,
             };
             block_dev_configs.insert(block_device_config).unwrap();
@@ -1151,6 +2062,36 @@ pub mod tests {
         assert_eq!(wrapper.as_raw_fd(), io::stdin().as_raw_fd())
     }
 
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn test_second_serial_output() {
+        let tmp = TempFile::new().unwrap();
+        let mut out = second_serial_output(tmp.as_path()).unwrap();
+        out.write_all(b"ttyS1").unwrap();
+        assert_eq!(tmp.as_file().metadata().unwrap().len(), 5);
+
+        match second_serial_output(std::path::Path::new("/invalid/path/ttyS1")) {
+            Err(Error::SerialOutput(_)) => (),
+            _ => panic!("Opening the output should fail."),
+        }
+    }
+
+    #[test]
+    #[cfg(target_arch = "aarch64")]
+    fn test_setup_rtc_device() {
+        let mut data = [0u8; 4];
+
+        // The RTC reads the host time by default.
+        let rtc = setup_rtc_device(None);
+        rtc.lock().unwrap().read(0x000, &mut data);
+        assert!(u32::from_le_bytes(data) > 1_000_000_000);
+
+        let rtc = setup_rtc_device(Some(1000));
+        rtc.lock().unwrap().read(0x000, &mut data);
+        let rtc_time = u32::from_le_bytes(data);
+        assert!((1000..1010).contains(&rtc_time));
+    }
+
     #[test]
     fn test_create_guest_memory() {
         let mem_size = 4096 * 2;
@@ -1168,6 +2109,66 @@ pub mod tests {
         }
     }
 
+    #[test]
+    fn test_create_file_backed_guest_memory() {
+        use vm_memory::Bytes;
+
+        let mem_layout = [(GuestAddress(0), 4096), (GuestAddress(0x10000), 4096)];
+        let backing_file = TempFile::new().unwrap();
+
+        // The file has to be as large as the guest memory.
+        backing_file.as_file().set_len(4096).unwrap();
+        match create_file_backed_guest_memory(backing_file.as_path(), &mem_layout, false) {
+            Err(StartMicrovmError::GuestMemoryBackingFileSize(4096, 8192)) => (),
+            _ => unreachable!(),
+        }
+
+        backing_file.as_file().set_len(8192).unwrap();
+        let guest_memory =
+            create_file_backed_guest_memory(backing_file.as_path(), &mem_layout, true).unwrap();
+        assert!(guest_memory.is_dirty_tracking_enabled());
+
+        // The second region is backed by the second half of the file.
+        guest_memory
+            .write_obj(0xabu8, GuestAddress(0x10000))
+            .unwrap();
+        let mut data = [0u8];
+        let mut file = backing_file.as_file();
+        file.seek(SeekFrom::Start(4096)).unwrap();
+        file.read_exact(&mut data).unwrap();
+        assert_eq!(data[0], 0xab);
+
+        match create_file_backed_guest_memory(Path::new("/no/such/file"), &mem_layout, false) {
+            Err(StartMicrovmError::OpenGuestMemoryBackingFile(_)) => (),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_guest_memory_error() {
+        let err = vm_memory::Error::MmapRegion(MmapRegionError::Mmap(
+            io::Error::from_raw_os_error(libc::ENOMEM),
+        ));
+        match guest_memory_error(err, 1024) {
+            StartMicrovmError::GuestMemoryAllocationFailed(1024, _) => (),
+            err => panic!("Unexpected error: {}", err),
+        }
+
+        // The other mmap failures are not allocation failures.
+        let err = vm_memory::Error::MmapRegion(MmapRegionError::Mmap(
+            io::Error::from_raw_os_error(libc::EINVAL),
+        ));
+        match guest_memory_error(err, 1024) {
+            StartMicrovmError::GuestMemoryMmap(_) => (),
+            err => panic!("Unexpected error: {}", err),
+        }
+        let err = vm_memory::Error::MmapRegion(MmapRegionError::InvalidPointer);
+        match guest_memory_error(err, 1024) {
+            StartMicrovmError::GuestMemoryMmap(_) => (),
+            err => panic!("Unexpected error: {}", err),
+        }
+    }
+
     #[test]
     fn test_create_vcpus() {
         let vcpu_count = 2;
@@ -1184,6 +2185,23 @@ pub mod tests {
         assert_eq!(vcpu_vec.len(), vcpu_count as usize);
     }
 
+    #[test]
+    fn test_zero_mem_on_shutdown() {
+        use vm_memory::Bytes;
+
+        let addr = GuestAddress(0x1000);
+        let mut vmm = default_vmm();
+        vmm.guest_memory.write_obj(0xdead_beef_u32, addr).unwrap();
+
+        // The guest memory is left as is by default.
+        vmm.stop(FC_EXIT_CODE_OK);
+        assert_eq!(vmm.guest_memory.read_obj::<u32>(addr).unwrap(), 0xdead_beef);
+
+        vmm.zero_mem_on_shutdown = true;
+        vmm.stop(FC_EXIT_CODE_OK);
+        assert_eq!(vmm.guest_memory.read_obj::<u32>(addr).unwrap(), 0);
+    }
+
     #[test]
     fn test_attach_net_devices() {
         let mut event_manager = EventManager::new().expect("Unable to create EventManager");
@@ -1196,6 +2214,11 @@ pub mod tests {
             rx_rate_limiter: None,
             tx_rate_limiter: None,
             allow_mmds_requests: true,
+            mmds_allowed_sources: Vec::new(),
+            enable_offload: true,
+            mtu: None,
+            pcap_path: None,
+            interrupt_coalescing_us: 0,
         };
 
         let mut cmdline = default_kernel_cmdline();
@@ -1366,7 +2389,46 @@ pub mod tests {
                 .is_some());
         }
 
-        // Use case 7: root block device is rw with flush enabled
+        // Use case 7: several root block devices, the one with the lowest boot index is used.
+        {
+            let block_configs = vec![
+                CustomBlockConfig {
+                    boot_index: Some(2),
+                    ..CustomBlockConfig::new(
+                        String::from("root_b"),
+                        true,
+                        Some("0eaa91a0-02".to_string()),
+                        true,
+                        CacheType::Unsafe,
+                    )
+                },
+                CustomBlockConfig {
+                    boot_index: Some(1),
+                    ..CustomBlockConfig::new(
+                        String::from("root_a"),
+                        true,
+                        Some("0eaa91a0-01".to_string()),
+                        false,
+                        CacheType::Unsafe,
+                    )
+                },
+            ];
+            let mut vmm = default_vmm();
+            let mut cmdline = default_kernel_cmdline();
+            insert_block_devices(&mut vmm, &mut cmdline, &mut event_manager, block_configs);
+            assert!(cmdline.as_str().contains("root=PARTUUID=0eaa91a0-01 rw"));
+            assert!(!cmdline.as_str().contains("root=PARTUUID=0eaa91a0-02"));
+            assert!(vmm
+                .mmio_device_manager
+                .get_device(DeviceType::Virtio(TYPE_BLOCK), "root_a")
+                .is_some());
+            assert!(vmm
+                .mmio_device_manager
+                .get_device(DeviceType::Virtio(TYPE_BLOCK), "root_b")
+                .is_some());
+        }
+
+        // Use case 8: root block device is rw with flush enabled
         {
             let drive_id = String::from("root");
             let block_configs = vec![CustomBlockConfig::new(
@@ -1409,6 +2471,7 @@ pub mod tests {
             amount_mib: 0,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            free_page_reporting: false,
         };
 
         let mut cmdline = default_kernel_cmdline();
@@ -1420,6 +2483,324 @@ pub mod tests {
             .contains("virtio_mmio.device=4K@0xd0000000:5"));
     }
 
+    #[test]
+    fn test_attach_console_device() {
+        let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+        let mut vmm = default_vmm();
+        let serial_config = SerialConfig {
+            disable_input: true,
+            console_type: ConsoleType::Virtio,
+            ..Default::default()
+        };
+
+        let mut cmdline = default_kernel_cmdline();
+        attach_console_device(&mut vmm, &mut cmdline, &serial_config, &mut event_manager).unwrap();
+        assert!(vmm
+            .get_bus_device(DeviceType::Virtio(TYPE_CONSOLE), CONSOLE_DEV_ID)
+            .is_some());
+        // The virtio console is the last console, which makes it the primary one.
+        assert!(cmdline.as_str().ends_with("console=hvc0"));
+        #[cfg(target_arch = "x86_64")]
+        assert!(cmdline
+            .as_str()
+            .contains("virtio_mmio.device=4K@0xd0000000:5"));
+    }
+
+    #[test]
+    fn test_attach_fs_devices() {
+        let mut vmm = default_vmm();
+        let shared_dir = TempDir::new().unwrap();
+        let fs = Fs::new(String::from("fs0"), shared_dir.as_path(), "shared", false).unwrap();
+        let fs_devices = vec![Arc::new(Mutex::new(fs))];
+
+        let mut cmdline = default_kernel_cmdline();
+        attach_fs_devices(&mut vmm, &mut cmdline, fs_devices.iter(), Arc::new(vec![])).unwrap();
+        assert!(vmm
+            .get_bus_device(DeviceType::Virtio(TYPE_FS), "fs0")
+            .is_some());
+        // The device is served on its own thread.
+        assert_eq!(vmm.device_threads.len(), 1);
+        assert!(vmm.device_subscribers.is_empty());
+        #[cfg(target_arch = "x86_64")]
+        assert!(cmdline
+            .as_str()
+            .contains("virtio_mmio.device=4K@0xd0000000:5"));
+    }
+
+    #[test]
+    fn test_check_boot_timeout() {
+        let mut vm_resources = VmResources::default();
+        let boot_start = Instant::now() - Duration::from_millis(100);
+
+        // Without a boot timeout, the boot never times out.
+        assert!(check_boot_timeout(&vm_resources, boot_start).is_ok());
+
+        vm_resources.boot_timeout_ms = Some(10_000);
+        assert!(check_boot_timeout(&vm_resources, boot_start).is_ok());
+
+        vm_resources.boot_timeout_ms = Some(50);
+        match check_boot_timeout(&vm_resources, boot_start) {
+            Err(StartMicrovmError::BootTimeout(50)) => (),
+            _ => panic!("Expected a boot timeout."),
+        }
+    }
+
+    #[test]
+    fn test_boot_phases() {
+        let metric = SharedStoreMetric::default();
+        let mut boot_phases = BootPhases::new();
+        std::thread::sleep(Duration::from_millis(10));
+        boot_phases.end_phase("first", &metric);
+        boot_phases.end_phase("second", &metric);
+
+        assert_eq!(boot_phases.phases.len(), 2);
+        assert_eq!(boot_phases.phases[0].0, "first");
+        assert!(boot_phases.phases[0].1.time_us >= 10_000);
+        // The metric holds the duration of the last phase.
+        assert_eq!(metric.fetch(), boot_phases.phases[1].1.time_us as usize);
+        boot_phases.log();
+    }
+
+    #[test]
+    fn test_add_serial_console_to_cmdline() {
+        let serial_config = SerialConfig {
+            auto_console: true,
+            ..Default::default()
+        };
+        let mut cmdline = default_kernel_cmdline();
+        assert!(!has_console(&cmdline));
+        add_serial_console_to_cmdline(&mut cmdline, &serial_config).unwrap();
+        assert!(cmdline.as_str().ends_with(" console=ttyS0"));
+        assert!(has_console(&cmdline));
+
+        // A console selected by the user is kept.
+        let mut cmdline = Cmdline::new(4096);
+        cmdline.insert_str("netconsole=@/,@10.0.0.1/").unwrap();
+        assert!(!has_console(&cmdline));
+        cmdline.insert_str("console=hvc1").unwrap();
+        add_serial_console_to_cmdline(&mut cmdline, &serial_config).unwrap();
+        assert_eq!(cmdline.as_str(), "netconsole=@/,@10.0.0.1/ console=hvc1");
+
+        // The virtio console adds its own parameter when it is attached.
+        let serial_config = SerialConfig {
+            console_type: ConsoleType::Virtio,
+            auto_console: true,
+            ..Default::default()
+        };
+        let mut cmdline = default_kernel_cmdline();
+        add_serial_console_to_cmdline(&mut cmdline, &serial_config).unwrap();
+        assert_eq!(cmdline.as_str(), DEFAULT_KERNEL_CMDLINE);
+    }
+
+    #[test]
+    fn test_check_cmdline_capacity() {
+        let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+        let mut vm_resources = VmResources::default();
+        let block_files: Vec<TempFile> = (0..3).map(|_| TempFile::new().unwrap()).collect();
+        for (i, block_file) in block_files.iter().enumerate() {
+            vm_resources
+                .block
+                .insert(BlockDeviceConfig {
+                    drive_id: format!("block{}", i),
+                    path_on_host: block_file.as_path().to_str().unwrap().to_string(),
+                    backing: DiskBacking::File,
+                    is_root_device: i == 0,
+                    partuuid: None,
+                    boot_index: None,
+                    is_read_only: false,
+                    cache_type: CacheType::Unsafe,
+                    open_retry: OpenRetryConfig::default(),
+                    access_pattern: AccessPattern::default(),
+                    interrupt_coalescing_us: 0,
+                    rate_limiter: None,
+                })
+                .unwrap();
+        }
+
+        let growth = " root=/dev/vda rw".len();
+        #[cfg(target_arch = "x86_64")]
+        let growth = growth + 3 * MMIODeviceManager::virtio_device_cmdline_max_len();
+
+        // Only the nul terminator is left once the devices are attached.
+        let mut cmdline = Cmdline::new(DEFAULT_KERNEL_CMDLINE.len() + growth + 1);
+        cmdline.insert_str(DEFAULT_KERNEL_CMDLINE).unwrap();
+        assert!(check_cmdline_capacity(&cmdline, &vm_resources).is_ok());
+        // The estimate is an upper bound of what the devices add.
+        let mut vmm = default_vmm();
+        attach_block_devices(
+            &mut vmm,
+            &mut cmdline,
+            vm_resources.block.list.iter(),
+            &mut event_manager,
+        )
+        .unwrap();
+
+        let mut cmdline = Cmdline::new(DEFAULT_KERNEL_CMDLINE.len() + growth);
+        cmdline.insert_str(DEFAULT_KERNEL_CMDLINE).unwrap();
+        match check_cmdline_capacity(&cmdline, &vm_resources) {
+            Err(StartMicrovmError::KernelCmdlineOverflow(len, max_len)) => {
+                assert_eq!(len, DEFAULT_KERNEL_CMDLINE.len() + growth);
+                assert_eq!(max_len, DEFAULT_KERNEL_CMDLINE.len() + growth - 1);
+            }
+            _ => panic!("Expected a kernel command line overflow."),
+        }
+    }
+
+    #[test]
+    fn test_validate_microvm_for_boot() {
+        use crate::builder::StartMicrovmError::*;
+        use std::io::Write;
+
+        let mut vm_resources = VmResources::default();
+        let mut seccomp_filters = BpfThreadMap::new();
+
+        // The kernel isn't configured.
+        let problems = validate_microvm_for_boot(&vm_resources, &seccomp_filters);
+        assert_eq!(problems.len(), 1);
+        assert!(matches!(problems[0], MissingKernelConfig));
+
+        // Empty kernel and initrd images are reported together.
+        let kernel_file = TempFile::new().unwrap();
+        let initrd_file = TempFile::new().unwrap();
+        vm_resources
+            .set_boot_source(BootSourceConfig {
+                kernel_image_path: kernel_file.as_path().to_str().unwrap().to_string(),
+                initrd_path: Some(initrd_file.as_path().to_str().unwrap().to_string()),
+                boot_args: None,
+                rng_seed: None,
+            })
+            .unwrap();
+        let problems = validate_microvm_for_boot(&vm_resources, &seccomp_filters);
+        assert_eq!(problems.len(), 2);
+        assert!(matches!(problems[0], Internal(Error::KernelFile(_))));
+        assert!(matches!(problems[1], InitrdRead(_)));
+
+        kernel_file.as_file().write_all(&[0u8; 16]).unwrap();
+        initrd_file.as_file().write_all(&[0u8; 16]).unwrap();
+        assert!(validate_microvm_for_boot(&vm_resources, &seccomp_filters).is_empty());
+
+        // Restarting on guest reboot isn't possible with a filter for the VMM thread.
+        vm_resources
+            .set_vm_config(&VmConfig {
+                on_reboot: Some(RebootAction::Restart),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(validate_microvm_for_boot(&vm_resources, &seccomp_filters).is_empty());
+        seccomp_filters.insert(
+            "vmm".to_string(),
+            Arc::new(vec![seccompiler::sock_filter {
+                code: 0x06,
+                jt: 0,
+                jf: 0,
+                k: 0x7fff_0000,
+            }]),
+        );
+        let problems = validate_microvm_for_boot(&vm_resources, &seccomp_filters);
+        assert_eq!(problems.len(), 1);
+        assert!(matches!(problems[0], RestartWithSeccompFilter));
+    }
+
+    #[test]
+    fn test_teardown_failed_boot() {
+        let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+        let mut vmm = default_vmm();
+
+        let balloon_config = BalloonDeviceConfig {
+            amount_mib: 0,
+            deflate_on_oom: false,
+            stats_polling_interval_s: 0,
+            free_page_reporting: false,
+        };
+        let mut cmdline = default_kernel_cmdline();
+        insert_balloon_device(&mut vmm, &mut cmdline, &mut event_manager, balloon_config);
+        assert_eq!(vmm.device_subscribers.len(), 1);
+        let subscriber_id = vmm.device_subscribers[0];
+
+        teardown_failed_boot(&mut vmm, &mut event_manager);
+        assert!(vmm.device_subscribers.is_empty());
+        assert_eq!(vmm.shutdown_exit_code(), Some(FC_EXIT_CODE_GENERIC_ERROR));
+        // The device is no longer registered with the event manager.
+        assert!(event_manager.remove_subscriber(subscriber_id).is_err());
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn test_i8042_reset() {
+        // By default, the guest rebooting stops the microVM with the success exit code.
+        let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+        let vmm = Arc::new(Mutex::new(default_vmm()));
+        event_manager.add_subscriber(vmm.clone());
+        vmm.lock().unwrap().i8042_reset_evt.write(1).unwrap();
+        event_manager.run_with_timeout(500).unwrap();
+        assert_eq!(
+            vmm.lock().unwrap().shutdown_exit_code(),
+            Some(FC_EXIT_CODE_OK)
+        );
+
+        // The guest reboot can be reported with its own exit code.
+        let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+        let vmm = Arc::new(Mutex::new(default_vmm()));
+        vmm.lock().unwrap().reboot_exit_code = FC_EXIT_CODE_GUEST_REBOOT;
+        event_manager.add_subscriber(vmm.clone());
+        vmm.lock().unwrap().i8042_reset_evt.write(1).unwrap();
+        event_manager.run_with_timeout(500).unwrap();
+        assert_eq!(
+            vmm.lock().unwrap().shutdown_exit_code(),
+            Some(FC_EXIT_CODE_GUEST_REBOOT)
+        );
+    }
+
+    #[test]
+    fn test_guest_panic() {
+        let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+        let mut vmm = default_vmm();
+        attach_pvpanic_device(&mut vmm).unwrap();
+        let pvpanic = vmm
+            .get_bus_device(DeviceType::PvPanic, &DeviceType::PvPanic.to_string())
+            .unwrap();
+
+        // The guest panic stops the microVM with its own exit code.
+        pvpanic.lock().unwrap().write(0, &[PVPANIC_PANICKED]);
+        let vmm = Arc::new(Mutex::new(vmm));
+        event_manager.add_subscriber(vmm.clone());
+        event_manager.run_with_timeout(500).unwrap();
+        assert_eq!(
+            vmm.lock().unwrap().shutdown_exit_code(),
+            Some(FC_EXIT_CODE_GUEST_PANIC)
+        );
+    }
+
+    #[test]
+    fn test_guest_snapshot() {
+        let tmp_dir = TempDir::new().unwrap();
+        let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+        let mut vmm = default_vmm();
+        attach_snapshot_trigger_device(&mut vmm).unwrap();
+        vmm.guest_snapshot = Some(GuestSnapshotConfig {
+            snapshot_path: tmp_dir.as_path().join("vm.snap"),
+            mem_file_path: tmp_dir.as_path().join("vm.mem"),
+        });
+        let trigger = vmm
+            .get_bus_device(
+                DeviceType::SnapshotTrigger,
+                &DeviceType::SnapshotTrigger.to_string(),
+            )
+            .unwrap();
+
+        // The guest request is served on the VMM thread, which keeps the microVM running.
+        let guest_snapshots = METRICS.vmm.guest_snapshots.count();
+        trigger
+            .lock()
+            .unwrap()
+            .write(0, &[SNAPSHOT_TRIGGER_REQUEST]);
+        let vmm = Arc::new(Mutex::new(vmm));
+        event_manager.add_subscriber(vmm.clone());
+        event_manager.run_with_timeout(500).unwrap();
+        assert_eq!(METRICS.vmm.guest_snapshots.count(), guest_snapshots + 1);
+        assert_eq!(vmm.lock().unwrap().shutdown_exit_code(), None);
+    }
+
     #[test]
     fn test_attach_vsock_device() {
         let mut event_manager = EventManager::new().expect("Unable to create EventManager");
@@ -1438,12 +2819,31 @@ pub mod tests {
             .contains("virtio_mmio.device=4K@0xd0000000:5"));
     }
 
+    #[test]
+    fn test_generate_rng_seed() {
+        // Every boot gets a new seed.
+        let seed = generate_rng_seed().unwrap();
+        assert_ne!(seed, generate_rng_seed().unwrap());
+        assert_ne!(seed, [0u8; RNG_SEED_SIZE]);
+    }
+
     #[test]
     fn test_error_messages() {
         use crate::builder::StartMicrovmError::*;
         let err = AttachBlockDevice(io::Error::from_raw_os_error(0));
         let _ = format!("{}{:?}", err, err);
 
+        let err = BootTimeout(1000);
+        let _ = format!("{}{:?}", err, err);
+
+        let err = CreateConsoleDevice(devices::virtio::console::Error::EventFd(
+            io::Error::from_raw_os_error(0),
+        ));
+        let _ = format!("{}{:?}", err, err);
+
+        let err = BootTimeoutWithSeccompFilter(1000);
+        let _ = format!("{}{:?}", err, err);
+
         let err = CreateNetDevice(devices::virtio::net::Error::EventFd(
             io::Error::from_raw_os_error(0),
         ));
@@ -1452,12 +2852,30 @@ pub mod tests {
         let err = CreateRateLimiter(io::Error::from_raw_os_error(0));
         let _ = format!("{}{:?}", err, err);
 
+        let err = GenerateRngSeed(io::Error::from_raw_os_error(0));
+        let _ = format!("{}{:?}", err, err);
+
+        let err = GuestMemoryAllocationFailed(1024, Some(512));
+        assert_eq!(
+            err.to_string(),
+            "Cannot allocate 1024 MiB of guest memory, with 512 MiB of memory available on the \
+             host."
+        );
+        let err = GuestMemoryAllocationFailed(1024, None);
+        let _ = format!("{}{:?}", err, err);
+
         let err = Internal(Error::Serial(io::Error::from_raw_os_error(0)));
         let _ = format!("{}{:?}", err, err);
 
+        let err = Internal(Error::SerialOutput(io::Error::from_raw_os_error(0)));
+        let _ = format!("{}{:?}", err, err);
+
         let err = KernelCmdline(String::from("dummy --cmdline"));
         let _ = format!("{}{:?}", err, err);
 
+        let err = KernelCmdlineOverflow(4096, 2047);
+        let _ = format!("{}{:?}", err, err);
+
         let err = KernelLoader(kernel::loader::Error::InvalidElfMagicNumber);
         let _ = format!("{}{:?}", err, err);
 
@@ -1475,6 +2893,15 @@ pub mod tests {
 
         let err = OpenBlockDevice(io::Error::from_raw_os_error(0));
         let _ = format!("{}{:?}", err, err);
+
+        let err = RestartWithSeccompFilter;
+        let _ = format!("{}{:?}", err, err);
+
+        let err = SetResourceLimits(ResourceLimitsError::SetLimit(
+            "RLIMIT_NOFILE",
+            io::Error::from_raw_os_error(0),
+        ));
+        let _ = format!("{}{:?}", err, err);
     }
 
     #[test]
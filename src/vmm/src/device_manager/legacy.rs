@@ -7,6 +7,7 @@
 #![cfg(target_arch = "x86_64")]
 
 use std::fmt;
+use std::io;
 use std::sync::{Arc, Mutex};
 
 use kvm_ioctls::VmFd;
@@ -40,6 +41,8 @@ type Result<T> = ::std::result::Result<T, Error>;
 pub struct PortIODeviceManager {
     pub io_bus: devices::Bus,
     pub stdio_serial: Arc<Mutex<devices::legacy::Serial>>,
+    /// The second serial port (COM2, ttyS1 in the guest), output-only.
+    pub second_serial: Arc<Mutex<devices::legacy::Serial>>,
     pub i8042: Arc<Mutex<devices::legacy::I8042Device>>,
 
     pub com_evt_1_3: EventFd,
@@ -49,8 +52,12 @@ pub struct PortIODeviceManager {
 
 impl PortIODeviceManager {
     /// Create a new DeviceManager handling legacy devices (uart, i8042).
+    ///
+    /// The output of the second serial port goes to `second_serial_out` if any, and is
+    /// discarded otherwise.
     pub fn new(
         serial: Arc<Mutex<devices::legacy::Serial>>,
+        second_serial_out: Option<Box<dyn io::Write + Send>>,
         i8042_reset_evfd: EventFd,
     ) -> Result<Self> {
         let io_bus = devices::Bus::new();
@@ -61,6 +68,11 @@ impl PortIODeviceManager {
             .try_clone()
             .map_err(Error::EventFd)?;
         let com_evt_2_4 = EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?;
+        let second_serial_evt = com_evt_2_4.try_clone().map_err(Error::EventFd)?;
+        let second_serial = Arc::new(Mutex::new(match second_serial_out {
+            Some(out) => devices::legacy::Serial::new_out(second_serial_evt, out),
+            None => devices::legacy::Serial::new_sink(second_serial_evt),
+        }));
         let kbd_evt = EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?;
 
         let i8042 = Arc::new(Mutex::new(devices::legacy::I8042Device::new(
@@ -71,6 +83,7 @@ impl PortIODeviceManager {
         Ok(PortIODeviceManager {
             io_bus,
             stdio_serial: serial,
+            second_serial,
             i8042,
             com_evt_1_3,
             com_evt_2_4,
@@ -84,13 +97,7 @@ impl PortIODeviceManager {
             .insert(self.stdio_serial.clone(), 0x3f8, 0x8)
             .map_err(Error::BusError)?;
         self.io_bus
-            .insert(
-                Arc::new(Mutex::new(devices::legacy::Serial::new_sink(
-                    self.com_evt_2_4.try_clone().map_err(Error::EventFd)?,
-                ))),
-                0x2f8,
-                0x8,
-            )
+            .insert(self.second_serial.clone(), 0x2f8, 0x8)
             .map_err(Error::BusError)?;
         self.io_bus
             .insert(
@@ -141,12 +148,37 @@ mod tests {
         let serial = devices::legacy::Serial::new_sink(EventFd::new(libc::EFD_NONBLOCK).unwrap());
         let mut ldm = PortIODeviceManager::new(
             Arc::new(Mutex::new(serial)),
+            None,
             EventFd::new(libc::EFD_NONBLOCK).unwrap(),
         )
         .unwrap();
         assert!(ldm.register_devices(vm.fd()).is_ok());
     }
 
+    #[test]
+    fn test_second_serial_output() {
+        use devices::BusDevice;
+        use std::io::Read;
+        use utils::tempfile::TempFile;
+
+        let tmp = TempFile::new().unwrap();
+        let serial = devices::legacy::Serial::new_sink(EventFd::new(libc::EFD_NONBLOCK).unwrap());
+        let ldm = PortIODeviceManager::new(
+            Arc::new(Mutex::new(serial)),
+            Some(Box::new(tmp.as_file().try_clone().unwrap())),
+            EventFd::new(libc::EFD_NONBLOCK).unwrap(),
+        )
+        .unwrap();
+
+        // Writes to the data register of the second serial port end up in its output.
+        for byte in b"ttyS1" {
+            ldm.second_serial.lock().unwrap().write(0, &[*byte]);
+        }
+        let mut output = String::new();
+        tmp.as_file().read_to_string(&mut output).unwrap();
+        assert_eq!(output, "ttyS1");
+    }
+
     #[test]
     fn test_debug_error() {
         assert_eq!(
@@ -5,7 +5,7 @@
 // Use of this source code is governed by a BSD-style license that can be
 // found in the THIRD-PARTY file.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, Mutex};
 use std::{fmt, io};
 
@@ -14,18 +14,53 @@ use arch::aarch64::DeviceInfoForFDT;
 use arch::DeviceType;
 #[cfg(target_arch = "aarch64")]
 use devices::legacy::RTCDevice;
+use devices::legacy::{PvPanic, SnapshotTrigger};
 use devices::pseudo::BootTimer;
 use devices::virtio::{
-    Balloon, Block, MmioTransport, Net, VirtioDevice, TYPE_BALLOON, TYPE_BLOCK, TYPE_NET,
-    TYPE_VSOCK,
+    Balloon, Block, MmioTransport, Net, VirtioDevice, TYPE_BALLOON, TYPE_BLOCK, TYPE_CONSOLE,
+    TYPE_FS, TYPE_NET, TYPE_VSOCK,
 };
 use devices::BusDevice;
 use kernel::cmdline as kernel_cmdline;
 use kvm_ioctls::{IoEventAddress, VmFd};
-use logger::info;
+use logger::{info, METRICS};
 use versionize::{VersionMap, Versionize, VersionizeResult};
 use versionize_derive::Versionize;
 
+use crate::vmm_config::devices::MmioDeviceDescription;
+
+/// Provides the name of a virtio device type.
+fn virtio_type_name(device_type: u32) -> &'static str {
+    match device_type {
+        TYPE_NET => "net",
+        TYPE_BLOCK => "block",
+        TYPE_VSOCK => "vsock",
+        TYPE_BALLOON => "balloon",
+        TYPE_CONSOLE => "console",
+        TYPE_FS => "fs",
+        _ => "virtio",
+    }
+}
+
+/// Provides the name of a device type, as described to the user.
+fn device_type_name(device_type: DeviceType) -> &'static str {
+    match device_type {
+        DeviceType::Virtio(virtio_type) => virtio_type_name(virtio_type),
+        #[cfg(target_arch = "aarch64")]
+        DeviceType::Serial => "serial",
+        #[cfg(target_arch = "aarch64")]
+        DeviceType::Rtc => "rtc",
+        DeviceType::BootTimer => "boot_timer",
+        DeviceType::PvPanic => "pvpanic",
+        DeviceType::SnapshotTrigger => "snapshot_trigger",
+    }
+}
+
+/// Provides the name under which the interrupts of a virtio device are counted in the metrics.
+fn irq_metrics_name(device_type: u32, device_id: &str) -> String {
+    format!("{}_{}", virtio_type_name(device_type), device_id)
+}
+
 /// Errors for MMIO device manager.
 #[derive(Debug)]
 pub enum Error {
@@ -95,6 +130,10 @@ struct IrqManager {
     first: u32,
     last: u32,
     next_avail: u32,
+    // The maximum number of virtio devices sharing an IRQ line.
+    max_devices_per_irq: u32,
+    // The number of virtio devices using each of the IRQ lines given to them.
+    virtio_irq_users: BTreeMap<u32, u32>,
 }
 
 impl IrqManager {
@@ -103,6 +142,8 @@ impl IrqManager {
             first,
             last,
             next_avail: first,
+            max_devices_per_irq: 1,
+            virtio_irq_users: BTreeMap::new(),
         }
     }
 
@@ -118,6 +159,26 @@ impl IrqManager {
         Ok(irqs)
     }
 
+    /// Provides the IRQ line of a virtio device. The device gets a line of its own while there
+    /// are unused ones left, then shares the least used line of the other virtio devices, if
+    /// allowed. The virtio devices tell apart their interrupts through their interrupt status.
+    pub fn get_virtio(&mut self) -> Result<u32> {
+        let irq = match self.get(1) {
+            Ok(irqs) => irqs[0],
+            Err(err) => {
+                let max_devices_per_irq = self.max_devices_per_irq;
+                self.virtio_irq_users
+                    .iter()
+                    .filter(|(_, users)| **users < max_devices_per_irq)
+                    .min_by_key(|(_, users)| **users)
+                    .map(|(irq, _)| *irq)
+                    .ok_or(err)?
+            }
+        };
+        *self.virtio_irq_users.entry(irq).or_insert(0) += 1;
+        Ok(irq)
+    }
+
     pub fn check(&self, irqs: &[u32]) -> Result<()> {
         for irq in irqs {
             // Check for out of range.
@@ -150,16 +211,32 @@ impl MMIODeviceManager {
         }
     }
 
+    /// Lets up to `max_devices_per_irq` virtio devices share an IRQ line, once there are no
+    /// unused lines left. Each virtio device has an IRQ line of its own by default.
+    pub fn set_max_devices_per_irq(&mut self, max_devices_per_irq: u32) {
+        self.irqs.max_devices_per_irq = max_devices_per_irq;
+    }
+
     /// Allocates resources for a new device to be added.
     fn allocate_new_slot(&mut self, irq_count: u32) -> Result<MMIODeviceInfo> {
         let irqs = self.irqs.get(irq_count)?;
+        Ok(self.allocate_mmio_range(irqs))
+    }
+
+    /// Allocates resources for a new virtio device to be added, whose IRQ line may be shared.
+    fn allocate_virtio_slot(&mut self) -> Result<MMIODeviceInfo> {
+        let irq = self.irqs.get_virtio()?;
+        Ok(self.allocate_mmio_range(vec![irq]))
+    }
+
+    fn allocate_mmio_range(&mut self, irqs: Vec<u32>) -> MMIODeviceInfo {
         let slot = MMIODeviceInfo {
             addr: self.next_avail_mmio,
             len: MMIO_LEN,
             irqs,
         };
         self.next_avail_mmio += MMIO_LEN;
-        Ok(slot)
+        slot
     }
 
     /// Does a slot sanity check against expected values.
@@ -199,7 +276,11 @@ impl MMIODeviceManager {
         }
         let identifier;
         {
-            let locked_device = mmio_device.locked_device();
+            let mut locked_device = mmio_device.locked_device();
+            let irq_counter = METRICS
+                .irqs
+                .get(&irq_metrics_name(locked_device.device_type(), &device_id));
+            locked_device.set_irq_counter(irq_counter);
             identifier = (DeviceType::Virtio(locked_device.device_type()), device_id);
             for (i, queue_evt) in locked_device.queue_events().iter().enumerate() {
                 let io_addr =
@@ -214,6 +295,16 @@ impl MMIODeviceManager {
         self.register_mmio_device(identifier, slot.clone(), Arc::new(Mutex::new(mmio_device)))
     }
 
+    /// Provides the interrupt counter of a registered device, which is the one reported in the
+    /// metrics.
+    pub fn irq_counter(&self, device_type: DeviceType, device_id: &str) -> Option<usize> {
+        let mmio_device = self.get_device(device_type, device_id)?;
+        let locked_device = mmio_device.lock().expect("Poisoned lock");
+        let mmio_transport = locked_device.as_any().downcast_ref::<MmioTransport>()?;
+        let count = mmio_transport.locked_device().irq_counter().count();
+        Some(count)
+    }
+
     /// Append a registered virtio-over-MMIO device to the kernel cmdline.
     #[cfg(target_arch = "x86_64")]
     pub fn add_virtio_device_to_cmdline(
@@ -233,6 +324,19 @@ impl MMIODeviceManager {
             .map_err(Error::Cmdline)
     }
 
+    /// Upper bound of the length that `add_virtio_device_to_cmdline` adds to the kernel cmdline,
+    /// including the separator. The MMIO base addresses fit in 32 bits.
+    #[cfg(target_arch = "x86_64")]
+    pub fn virtio_device_cmdline_max_len() -> usize {
+        format!(
+            " virtio_mmio.device={}K@0x{:08x}:{}",
+            MMIO_LEN / 1024,
+            u32::max_value(),
+            arch::IRQ_MAX
+        )
+        .len()
+    }
+
     /// Allocate slot and register an already created virtio-over-MMIO device. Also Adds the device
     /// to the boot cmdline.
     pub fn register_mmio_virtio_for_boot(
@@ -242,7 +346,7 @@ impl MMIODeviceManager {
         mmio_device: MmioTransport,
         _cmdline: &mut kernel_cmdline::Cmdline,
     ) -> Result<MMIODeviceInfo> {
-        let mmio_slot = self.allocate_new_slot(1)?;
+        let mmio_slot = self.allocate_virtio_slot()?;
         self.register_mmio_virtio(vm, device_id, mmio_device, &mmio_slot)?;
         #[cfg(target_arch = "x86_64")]
         Self::add_virtio_device_to_cmdline(_cmdline, &mmio_slot)?;
@@ -308,11 +412,75 @@ impl MMIODeviceManager {
         self.register_mmio_device(identifier, slot, Arc::new(Mutex::new(device)))
     }
 
+    /// Register a pvpanic device.
+    pub fn register_mmio_pvpanic(&mut self, device: PvPanic) -> Result<()> {
+        // The guest only writes to the device, which doesn't need an IRQ.
+        let slot = self.allocate_new_slot(0)?;
+
+        let identifier = (DeviceType::PvPanic, DeviceType::PvPanic.to_string());
+        self.register_mmio_device(identifier, slot, Arc::new(Mutex::new(device)))
+    }
+
+    /// Register a snapshot trigger device.
+    pub fn register_mmio_snapshot_trigger(&mut self, device: SnapshotTrigger) -> Result<()> {
+        // The guest only writes to the device, which doesn't need an IRQ.
+        let slot = self.allocate_new_slot(0)?;
+
+        let identifier = (
+            DeviceType::SnapshotTrigger,
+            DeviceType::SnapshotTrigger.to_string(),
+        );
+        self.register_mmio_device(identifier, slot, Arc::new(Mutex::new(device)))
+    }
+
     /// Gets the information of the devices registered up to some point in time.
     pub fn get_device_info(&self) -> &HashMap<(DeviceType, String), MMIODeviceInfo> {
         &self.id_to_dev_info
     }
 
+    /// Describes the devices registered up to some point in time, ordered by their MMIO address.
+    pub fn describe_devices(&self) -> Vec<MmioDeviceDescription> {
+        let mut devices: Vec<MmioDeviceDescription> = self
+            .id_to_dev_info
+            .iter()
+            .map(|((device_type, device_id), device_info)| {
+                let (avail_features, acked_features) = match device_type {
+                    DeviceType::Virtio(_) => self.virtio_features(device_info.addr),
+                    _ => (None, None),
+                };
+                MmioDeviceDescription {
+                    device_type: device_type_name(*device_type).to_string(),
+                    id: device_id.clone(),
+                    mmio_base: device_info.addr,
+                    irq: device_info.irqs.first().copied(),
+                    avail_features,
+                    acked_features,
+                }
+            })
+            .collect();
+        devices.sort_by_key(|device| device.mmio_base);
+        devices
+    }
+
+    /// Gets the features offered by the virtio device mapped at `addr` and, once the device is
+    /// activated, the ones acked by the guest driver.
+    fn virtio_features(&self, addr: u64) -> (Option<u64>, Option<u64>) {
+        let (_, bus_dev) = match self.bus.get_device(addr) {
+            Some(device) => device,
+            None => return (None, None),
+        };
+        let bus_dev = bus_dev.lock().expect("Poisoned lock");
+        // Virtio devices are guaranteed MmioTransport.
+        let mmio_dev = bus_dev.as_any().downcast_ref::<MmioTransport>().unwrap();
+        let virtio = mmio_dev.locked_device();
+        let acked_features = if virtio.is_activated() {
+            Some(virtio.acked_features())
+        } else {
+            None
+        };
+        (Some(virtio.avail_features()), acked_features)
+    }
+
     #[cfg(target_arch = "x86_64")]
     /// Gets the number of interrupts used by the devices registered.
     pub fn used_irqs_count(&self) -> usize {
@@ -441,6 +609,42 @@ impl MMIODeviceManager {
             Ok(())
         });
     }
+
+    /// Brings the queues of the virtio devices to a consistent state, so that the device states
+    /// can be saved. Must be called while the vCPUs are paused.
+    pub fn quiesce_devices(&self) {
+        info!("Quiesce devices.");
+        let _: Result<()> = self.for_each_device(|devtype, id, _, bus_dev| {
+            if let DeviceType::Virtio(virtio_type) = *devtype {
+                let bus_dev = bus_dev.lock().expect("Poisoned lock");
+                // Virtio devices are guaranteed MmioTransport.
+                let mmio_dev = bus_dev.as_any().downcast_ref::<MmioTransport>().unwrap();
+                let mut virtio = mmio_dev.locked_device();
+                match virtio_type {
+                    TYPE_BLOCK => {
+                        let block = virtio.as_mut_any().downcast_mut::<Block>().unwrap();
+                        // Block requests are handled synchronously, so processing the queue
+                        // completes all the requests the guest submitted before pausing, except
+                        // for the rate limited ones, which stay in the avail ring.
+                        if block.is_activated() {
+                            info!("quiesce block {}.", id);
+                            block.process_virtio_queues();
+                            block.flush_coalesced_interrupt();
+                        }
+                    }
+                    TYPE_NET => {
+                        let net = virtio.as_mut_any().downcast_mut::<Net>().unwrap();
+                        if net.is_activated() {
+                            info!("quiesce net {}.", id);
+                            net.quiesce();
+                        }
+                    }
+                    _ => (),
+                }
+            };
+            Ok(())
+        });
+    }
 }
 
 #[cfg(target_arch = "aarch64")]
@@ -461,10 +665,12 @@ mod tests {
     use super::*;
     use crate::builder;
     use devices::virtio::{ActivateResult, Queue, VirtioDevice};
+    use logger::{IncMetric, SharedIncMetric};
     use std::sync::atomic::AtomicUsize;
     use std::sync::Arc;
     use utils::errno;
     use utils::eventfd::EventFd;
+    use utils::time::TimestampUs;
     use vm_memory::{GuestAddress, GuestMemoryMmap};
 
     const QUEUE_SIZES: &[u16] = &[64];
@@ -491,6 +697,7 @@ mod tests {
         queues: Vec<Queue>,
         queue_evts: [EventFd; 1],
         interrupt_evt: EventFd,
+        irq_counter: Arc<SharedIncMetric>,
     }
 
     impl DummyDevice {
@@ -500,6 +707,7 @@ mod tests {
                 queues: QUEUE_SIZES.iter().map(|&s| Queue::new(s)).collect(),
                 queue_evts: [EventFd::new(libc::EFD_NONBLOCK).expect("cannot create eventFD")],
                 interrupt_evt: EventFd::new(libc::EFD_NONBLOCK).expect("cannot create eventFD"),
+                irq_counter: Arc::default(),
             }
         }
     }
@@ -539,6 +747,14 @@ mod tests {
             Arc::new(AtomicUsize::new(0))
         }
 
+        fn irq_counter(&self) -> &SharedIncMetric {
+            &self.irq_counter
+        }
+
+        fn set_irq_counter(&mut self, irq_counter: Arc<SharedIncMetric>) {
+            self.irq_counter = irq_counter;
+        }
+
         fn ack_features_by_page(&mut self, page: u32, value: u32) {
             let _ = page;
             let _ = value;
@@ -578,13 +794,114 @@ mod tests {
         #[cfg(target_arch = "x86_64")]
         assert!(builder::setup_interrupt_controller(&mut vm).is_ok());
         #[cfg(target_arch = "aarch64")]
-        assert!(builder::setup_interrupt_controller(&mut vm, 1).is_ok());
+        assert!(builder::setup_interrupt_controller(&mut vm, 1, None).is_ok());
 
         assert!(device_manager
             .register_virtio_test_device(vm.fd(), guest_mem, dummy, &mut cmdline, "dummy")
             .is_ok());
     }
 
+    #[test]
+    fn test_irq_counter() {
+        let start_addr1 = GuestAddress(0x0);
+        let start_addr2 = GuestAddress(0x1000);
+        let guest_mem =
+            GuestMemoryMmap::from_ranges(&[(start_addr1, 0x1000), (start_addr2, 0x1000)]).unwrap();
+        let mut vm = builder::setup_kvm_vm(&guest_mem, false).unwrap();
+        let mut device_manager =
+            MMIODeviceManager::new(0xd000_0000, (arch::IRQ_BASE, arch::IRQ_MAX));
+
+        let mut cmdline = kernel_cmdline::Cmdline::new(4096);
+        let dummy = Arc::new(Mutex::new(DummyDevice::new()));
+        #[cfg(target_arch = "x86_64")]
+        assert!(builder::setup_interrupt_controller(&mut vm).is_ok());
+        #[cfg(target_arch = "aarch64")]
+        assert!(builder::setup_interrupt_controller(&mut vm, 1, None).is_ok());
+
+        assert!(device_manager
+            .irq_counter(DeviceType::Virtio(0), "irq_dummy")
+            .is_none());
+        device_manager
+            .register_virtio_test_device(
+                vm.fd(),
+                guest_mem,
+                dummy.clone(),
+                &mut cmdline,
+                "irq_dummy",
+            )
+            .unwrap();
+        assert_eq!(
+            device_manager.irq_counter(DeviceType::Virtio(0), "irq_dummy"),
+            Some(0)
+        );
+
+        // The device counts its interrupts in the metrics of its registration.
+        dummy.lock().unwrap().irq_counter().inc();
+        assert_eq!(
+            device_manager.irq_counter(DeviceType::Virtio(0), "irq_dummy"),
+            Some(1)
+        );
+        assert_eq!(METRICS.irqs.get("virtio_irq_dummy").count(), 1);
+    }
+
+    #[test]
+    fn test_describe_devices() {
+        let start_addr1 = GuestAddress(0x0);
+        let start_addr2 = GuestAddress(0x1000);
+        let guest_mem =
+            GuestMemoryMmap::from_ranges(&[(start_addr1, 0x1000), (start_addr2, 0x1000)]).unwrap();
+        let mut vm = builder::setup_kvm_vm(&guest_mem, false).unwrap();
+        let mut device_manager =
+            MMIODeviceManager::new(0xd000_0000, (arch::IRQ_BASE, arch::IRQ_MAX));
+
+        let mut cmdline = kernel_cmdline::Cmdline::new(4096);
+        #[cfg(target_arch = "x86_64")]
+        assert!(builder::setup_interrupt_controller(&mut vm).is_ok());
+        #[cfg(target_arch = "aarch64")]
+        assert!(builder::setup_interrupt_controller(&mut vm, 1, None).is_ok());
+
+        assert!(device_manager.describe_devices().is_empty());
+        for id in &["dummy1", "dummy2"] {
+            let dummy = Arc::new(Mutex::new(DummyDevice::new()));
+            device_manager
+                .register_virtio_test_device(vm.fd(), guest_mem.clone(), dummy, &mut cmdline, id)
+                .unwrap();
+        }
+        device_manager
+            .register_mmio_boot_timer(BootTimer::new(TimestampUs::default()))
+            .unwrap();
+
+        assert_eq!(
+            device_manager.describe_devices(),
+            vec![
+                MmioDeviceDescription {
+                    device_type: "virtio".to_string(),
+                    id: "dummy1".to_string(),
+                    mmio_base: 0xd000_0000,
+                    irq: Some(arch::IRQ_BASE),
+                    avail_features: Some(0),
+                    acked_features: None,
+                },
+                MmioDeviceDescription {
+                    device_type: "virtio".to_string(),
+                    id: "dummy2".to_string(),
+                    mmio_base: 0xd000_0000 + MMIO_LEN,
+                    irq: Some(arch::IRQ_BASE + 1),
+                    avail_features: Some(0),
+                    acked_features: None,
+                },
+                MmioDeviceDescription {
+                    device_type: "boot_timer".to_string(),
+                    id: DeviceType::BootTimer.to_string(),
+                    mmio_base: 0xd000_0000 + 2 * MMIO_LEN,
+                    irq: None,
+                    avail_features: None,
+                    acked_features: None,
+                },
+            ]
+        );
+    }
+
     #[test]
     fn test_register_too_many_devices() {
         let start_addr1 = GuestAddress(0x0);
@@ -599,7 +916,7 @@ mod tests {
         #[cfg(target_arch = "x86_64")]
         assert!(builder::setup_interrupt_controller(&mut vm).is_ok());
         #[cfg(target_arch = "aarch64")]
-        assert!(builder::setup_interrupt_controller(&mut vm, 1).is_ok());
+        assert!(builder::setup_interrupt_controller(&mut vm, 1, None).is_ok());
 
         for _i in arch::IRQ_BASE..=arch::IRQ_MAX {
             device_manager
@@ -629,6 +946,60 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_register_devices_sharing_irqs() {
+        let start_addr1 = GuestAddress(0x0);
+        let start_addr2 = GuestAddress(0x1000);
+        let guest_mem =
+            GuestMemoryMmap::from_ranges(&[(start_addr1, 0x1000), (start_addr2, 0x1000)]).unwrap();
+        let mut vm = builder::setup_kvm_vm(&guest_mem, false).unwrap();
+        let mut device_manager =
+            MMIODeviceManager::new(0xd000_0000, (arch::IRQ_BASE, arch::IRQ_MAX));
+        device_manager.set_max_devices_per_irq(2);
+
+        let mut cmdline = kernel_cmdline::Cmdline::new(4096);
+        #[cfg(target_arch = "x86_64")]
+        assert!(builder::setup_interrupt_controller(&mut vm).is_ok());
+        #[cfg(target_arch = "aarch64")]
+        assert!(builder::setup_interrupt_controller(&mut vm, 1, None).is_ok());
+
+        // The first devices get an IRQ line of their own, the next ones share them.
+        let irq_count = arch::IRQ_MAX - arch::IRQ_BASE + 1;
+        for i in 0..2 * irq_count {
+            let dev_id = format!("dummy{}", i);
+            device_manager
+                .register_virtio_test_device(
+                    vm.fd(),
+                    guest_mem.clone(),
+                    Arc::new(Mutex::new(DummyDevice::new())),
+                    &mut cmdline,
+                    &dev_id,
+                )
+                .unwrap();
+            let (_, dev_info) = device_manager
+                .get_device_info()
+                .iter()
+                .find(|((_, id), _)| *id == dev_id)
+                .unwrap();
+            assert_eq!(dev_info.irqs, vec![arch::IRQ_BASE + i % irq_count]);
+        }
+        assert_eq!(
+            format!(
+                "{}",
+                device_manager
+                    .register_virtio_test_device(
+                        vm.fd(),
+                        guest_mem,
+                        Arc::new(Mutex::new(DummyDevice::new())),
+                        &mut cmdline,
+                        "dummy"
+                    )
+                    .unwrap_err()
+            ),
+            "no more IRQs are available".to_string()
+        );
+    }
+
     #[test]
     fn test_dummy_device() {
         let dummy = DummyDevice::new();
@@ -684,7 +1055,7 @@ mod tests {
         #[cfg(target_arch = "x86_64")]
         assert!(builder::setup_interrupt_controller(&mut vm).is_ok());
         #[cfg(target_arch = "aarch64")]
-        assert!(builder::setup_interrupt_controller(&mut vm, 1).is_ok());
+        assert!(builder::setup_interrupt_controller(&mut vm, 1, None).is_ok());
 
         let mut device_manager =
             MMIODeviceManager::new(0xd000_0000, (arch::IRQ_BASE, arch::IRQ_MAX));
@@ -764,6 +1135,23 @@ mod tests {
         let _addr = device_manager.allocate_new_slot(1);
         assert_eq!(device_manager.irqs.next_avail, arch::IRQ_MAX + 1);
         assert!(device_manager.allocate_new_slot(0).is_ok());
+
+        // Only the IRQ lines of the virtio devices are shared.
+        let mut device_manager =
+            MMIODeviceManager::new(0xd000_0000, (arch::IRQ_BASE, arch::IRQ_BASE + 2));
+        device_manager.set_max_devices_per_irq(3);
+        let _addr = device_manager.allocate_new_slot(1);
+        let slot = device_manager.allocate_virtio_slot().unwrap();
+        assert_eq!(slot.irqs, vec![arch::IRQ_BASE + 1]);
+        let slot = device_manager.allocate_virtio_slot().unwrap();
+        assert_eq!(slot.irqs, vec![arch::IRQ_BASE + 2]);
+        // The least used line is shared first.
+        for irq in &[1, 2, 1, 2] {
+            let slot = device_manager.allocate_virtio_slot().unwrap();
+            assert_eq!(slot.irqs, vec![arch::IRQ_BASE + irq]);
+        }
+        assert!(device_manager.allocate_virtio_slot().is_err());
+        assert!(device_manager.allocate_new_slot(1).is_err());
     }
 
     #[test]
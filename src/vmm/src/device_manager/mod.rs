@@ -11,3 +11,5 @@ pub mod legacy;
 pub mod mmio;
 /// Device managers (de)serialization support.
 pub mod persist;
+/// Threads serving device events away from the VMM thread.
+pub mod thread;
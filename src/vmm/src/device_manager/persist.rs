@@ -13,6 +13,7 @@ use logger::error;
 
 #[cfg(target_arch = "aarch64")]
 use arch::DeviceType;
+use devices::legacy::SerialLog;
 use devices::virtio::balloon::persist::{BalloonConstructorArgs, BalloonState};
 use devices::virtio::balloon::{Balloon, Error as BalloonError};
 use devices::virtio::block::persist::{BlockConstructorArgs, BlockState};
@@ -27,6 +28,9 @@ use devices::virtio::{
 };
 use event_manager::{MutEventSubscriber, SubscriberOps};
 use kvm_ioctls::VmFd;
+use mmds::persist::MmdsVersionState;
+use mmds::token::Error as TokenError;
+use mmds::MMDS;
 use snapshot::Persist;
 use versionize::{VersionMap, Versionize, VersionizeError, VersionizeResult};
 use versionize_derive::Versionize;
@@ -41,6 +45,7 @@ pub enum Error {
     MmioTransport,
     #[cfg(target_arch = "aarch64")]
     Legacy(crate::Error),
+    MmdsVersion(TokenError),
     Net(NetError),
     Vsock(VsockError),
     VsockUnixBackend(VsockUnixBackendError),
@@ -128,6 +133,9 @@ pub struct DeviceStates {
     /// Balloon device state.
     #[version(start = 2, ser_fn = "balloon_serialize")]
     pub balloon_device: Option<ConnectedBalloonState>,
+    /// MMDS version, saved when MMDS requests are allowed on any net device.
+    #[version(start = 3, ser_fn = "mmds_version_serialize")]
+    pub mmds_version: Option<MmdsVersionState>,
 }
 
 impl DeviceStates {
@@ -140,12 +148,24 @@ impl DeviceStates {
 
         Ok(())
     }
+
+    fn mmds_version_serialize(&mut self, target_version: u16) -> VersionizeResult<()> {
+        if target_version < 3 && self.mmds_version == Some(MmdsVersionState::V2) {
+            return Err(VersionizeError::Semantic(
+                "Target version does not implement MMDS version 2.".to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
 }
 
 pub struct MMIODevManagerConstructorArgs<'a> {
     pub mem: GuestMemoryMmap,
     pub vm: &'a VmFd,
     pub event_manager: &'a mut EventManager,
+    pub disable_serial_input: bool,
+    pub serial_log: Option<SerialLog>,
 }
 
 impl<'a> Persist<'a> for MMIODeviceManager {
@@ -161,10 +181,14 @@ impl<'a> Persist<'a> for MMIODeviceManager {
             vsock_device: None,
             #[cfg(target_arch = "aarch64")]
             legacy_devices: Vec::new(),
+            mmds_version: None,
         };
         let _: Result<(), ()> = self.for_each_device(|devtype, devid, devinfo, bus_dev| {
-            if *devtype == arch::DeviceType::BootTimer {
-                // No need to save BootTimer state.
+            if *devtype == arch::DeviceType::BootTimer
+                || *devtype == arch::DeviceType::SnapshotTrigger
+            {
+                // No need to save BootTimer state. The snapshot trigger isn't restored, the
+                // paths it writes the snapshots to belong to the microVM which was booted.
                 return Ok(());
             }
 
@@ -217,10 +241,14 @@ impl<'a> Persist<'a> for MMIODeviceManager {
                     });
                 }
                 TYPE_NET => {
-                    let net_state = locked_device.as_any().downcast_ref::<Net>().unwrap().save();
+                    let net = locked_device.as_any().downcast_ref::<Net>().unwrap();
+                    if net.mmds_enabled() {
+                        states.mmds_version =
+                            Some(MMDS.lock().expect("Poisoned lock").version().into());
+                    }
                     states.net_devices.push(ConnectedNetState {
                         device_id: devid.clone(),
-                        device_state: net_state,
+                        device_state: net.save(),
                         transport_state,
                         mmio_slot: devinfo.clone(),
                     });
@@ -269,14 +297,21 @@ impl<'a> Persist<'a> for MMIODeviceManager {
         let mem = &constructor_args.mem;
         let vm = constructor_args.vm;
 
+        if let Some(mmds_version) = state.mmds_version {
+            MMDS.lock()
+                .expect("Poisoned lock")
+                .set_version(mmds_version.into())
+                .map_err(Error::MmdsVersion)?;
+        }
+
         #[cfg(target_arch = "aarch64")]
         {
             for state in &state.legacy_devices {
                 if state.type_ == DeviceType::Serial {
                     let serial = crate::builder::setup_serial_device(
                         constructor_args.event_manager,
-                        Box::new(crate::builder::SerialStdin::get()),
-                        Box::new(io::stdout()),
+                        crate::builder::serial_input(constructor_args.disable_serial_input),
+                        crate::builder::serial_output(constructor_args.serial_log.clone()),
                     )
                     .map_err(Error::Legacy)?;
 
@@ -285,7 +320,9 @@ impl<'a> Persist<'a> for MMIODeviceManager {
                         .map_err(Error::DeviceManager)?;
                 }
                 if state.type_ == DeviceType::Rtc {
-                    let rtc = crate::builder::setup_rtc_device();
+                    // The RTC time isn't saved, so the RTC of the restored microVM reads the
+                    // host time.
+                    let rtc = crate::builder::setup_rtc_device(None);
                     dev_manager
                         .register_mmio_rtc(rtc, Some(state.mmio_slot.clone()))
                         .map_err(Error::DeviceManager)?;
@@ -488,6 +525,7 @@ mod tests {
                 && self.block_devices == other.block_devices
                 && self.net_devices == other.net_devices
                 && self.vsock_device == other.vsock_device
+                && self.mmds_version == other.mmds_version
         }
     }
 
@@ -553,6 +591,7 @@ mod tests {
                 amount_mib: 123,
                 deflate_on_oom: false,
                 stats_polling_interval_s: 1,
+                free_page_reporting: false,
             };
             insert_balloon_device(&mut vmm, &mut cmdline, &mut event_manager, balloon_cfg);
             // Add a block device.
@@ -574,6 +613,11 @@ mod tests {
                 rx_rate_limiter: None,
                 tx_rate_limiter: None,
                 allow_mmds_requests: true,
+                mmds_allowed_sources: Vec::new(),
+                enable_offload: true,
+                mtu: None,
+                pcap_path: None,
+                interrupt_coalescing_us: 0,
             };
             insert_net_device(
                 &mut vmm,
@@ -587,6 +631,7 @@ mod tests {
                 vsock_id: vsock_dev_id.to_string(),
                 guest_cid: 3,
                 uds_path: tmp_sock_file.as_path().to_str().unwrap().to_string(),
+                peer_allowlist: None,
             };
             insert_vsock_device(&mut vmm, &mut cmdline, &mut event_manager, vsock_config);
 
@@ -620,10 +665,57 @@ mod tests {
             mem: vmm.guest_memory().clone(),
             vm: vmm.vm.fd(),
             event_manager: &mut event_manager,
+            disable_serial_input: false,
+            serial_log: None,
         };
         let restored_dev_manager =
             MMIODeviceManager::restore(restore_args, &device_states).unwrap();
 
         assert_eq!(restored_dev_manager, original_mmio_device_manager);
     }
+
+    #[test]
+    fn test_mmds_version_persistence() {
+        let mut buf = vec![0; 1024];
+        let mut version_map = VersionMap::new();
+        version_map
+            .new_version()
+            .set_type_version(DeviceStates::type_id(), 2)
+            .new_version()
+            .set_type_version(DeviceStates::type_id(), 3);
+
+        let mut states = DeviceStates {
+            #[cfg(target_arch = "aarch64")]
+            legacy_devices: Vec::new(),
+            block_devices: Vec::new(),
+            net_devices: Vec::new(),
+            vsock_device: None,
+            balloon_device: None,
+            mmds_version: Some(MmdsVersionState::V2),
+        };
+
+        // Older snapshots would silently fall back to MMDS version 1.
+        assert_eq!(
+            states.serialize(&mut buf.as_mut_slice(), &version_map, 2),
+            Err(VersionizeError::Semantic(
+                "Target version does not implement MMDS version 2.".to_string()
+            ))
+        );
+
+        states
+            .serialize(&mut buf.as_mut_slice(), &version_map, 3)
+            .unwrap();
+        let restored_states =
+            DeviceStates::deserialize(&mut buf.as_slice(), &version_map, 3).unwrap();
+        assert_eq!(restored_states, states);
+
+        // MMDS version 1 can be saved in older snapshots, which don't hold the version at all.
+        states.mmds_version = Some(MmdsVersionState::V1);
+        states
+            .serialize(&mut buf.as_mut_slice(), &version_map, 2)
+            .unwrap();
+        let restored_states =
+            DeviceStates::deserialize(&mut buf.as_slice(), &version_map, 2).unwrap();
+        assert_eq!(restored_states.mmds_version, None);
+    }
 }
@@ -0,0 +1,189 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::seccomp_filters;
+use event_manager::{
+    EventManager as BaseEventManager, EventOps, Events, MutEventSubscriber, SubscriberOps,
+};
+use logger::error;
+use seccompiler::BpfProgram;
+use utils::epoll::EventSet;
+use utils::eventfd::EventFd;
+
+/// The event manager of a device thread, its subscribers are handled on that thread.
+type DeviceEventManager = BaseEventManager<Arc<Mutex<dyn MutEventSubscriber + Send>>>;
+
+/// Errors associated with the device threads.
+#[derive(Debug)]
+pub enum Error {
+    /// Cannot create the exit event of the thread.
+    EventFd(io::Error),
+    /// Cannot create the event manager of the thread.
+    EventManager(event_manager::Error),
+    /// Cannot spawn the thread.
+    Spawn(io::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        use self::Error::*;
+
+        match self {
+            EventFd(e) => write!(f, "Cannot create the device thread exit event: {}", e),
+            EventManager(e) => write!(f, "Cannot create the device thread event manager: {}", e),
+            Spawn(e) => write!(f, "Cannot spawn the device thread: {}", e),
+        }
+    }
+}
+
+// Stops the event loop of a device thread once its exit event is written.
+struct ExitHandler {
+    exit_evt: EventFd,
+    exited: Arc<AtomicBool>,
+}
+
+impl MutEventSubscriber for ExitHandler {
+    fn process(&mut self, event: Events, _: &mut EventOps) {
+        if event.fd() == self.exit_evt.as_raw_fd() && event.event_set() == EventSet::IN {
+            let _ = self.exit_evt.read();
+            self.exited.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn init(&mut self, ops: &mut EventOps) {
+        if let Err(e) = ops.add(Events::new(&self.exit_evt, EventSet::IN)) {
+            error!("Failed to register the device thread exit event: {}", e);
+        }
+    }
+}
+
+/// A thread serving the events of a set of devices, away from the VMM thread.
+///
+/// The thread runs under its own seccomp filter, so the system calls needed only by these
+/// devices don't have to be allowed on the VMM thread. It is stopped and joined when the handle
+/// is dropped.
+pub struct DeviceThread {
+    exit_evt: EventFd,
+    // Rust JoinHandles have to be wrapped in Option to be joined on drop.
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl DeviceThread {
+    /// Spawns a thread named `name`, which loads `seccomp_filter` and then serves the events of
+    /// `devices` until the handle is dropped.
+    pub fn start(
+        name: &str,
+        devices: Vec<Arc<Mutex<dyn MutEventSubscriber + Send>>>,
+        seccomp_filter: Arc<BpfProgram>,
+    ) -> Result<DeviceThread, Error> {
+        let exit_evt = EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?;
+        let exited = Arc::new(AtomicBool::new(false));
+
+        let mut event_manager = DeviceEventManager::new().map_err(Error::EventManager)?;
+        event_manager.add_subscriber(Arc::new(Mutex::new(ExitHandler {
+            exit_evt: exit_evt.try_clone().map_err(Error::EventFd)?,
+            exited: exited.clone(),
+        })));
+        for device in devices {
+            event_manager.add_subscriber(device);
+        }
+
+        let thread_name = name.to_string();
+        let thread = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                // Execution panics if filters cannot be loaded, use --no-seccomp if skipping
+                // filters altogether is the desired behaviour.
+                if let Err(e) = seccomp_filters::apply_filter(&seccomp_filter) {
+                    panic!(
+                        "Failed to set the requested seccomp filters on {}: Error: {}",
+                        thread_name, e
+                    );
+                }
+                while !exited.load(Ordering::SeqCst) {
+                    if let Err(e) = event_manager.run() {
+                        error!("Failed to run the {} event loop: {}", thread_name, e);
+                    }
+                }
+            })
+            .map_err(Error::Spawn)?;
+
+        Ok(DeviceThread {
+            exit_evt,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for DeviceThread {
+    fn drop(&mut self) {
+        if let Err(e) = self.exit_evt.write(1) {
+            error!("Failed to stop a device thread: {}", e);
+            return;
+        }
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("A device thread panicked.");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct EventCounter {
+        evt: EventFd,
+        count: Arc<Mutex<u64>>,
+    }
+
+    impl MutEventSubscriber for EventCounter {
+        fn process(&mut self, _: Events, _: &mut EventOps) {
+            *self.count.lock().unwrap() += self.evt.read().unwrap();
+        }
+
+        fn init(&mut self, ops: &mut EventOps) {
+            ops.add(Events::new(&self.evt, EventSet::IN)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_device_thread() {
+        let evt = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        let count = Arc::new(Mutex::new(0));
+        let device: Arc<Mutex<dyn MutEventSubscriber + Send>> =
+            Arc::new(Mutex::new(EventCounter {
+                evt: evt.try_clone().unwrap(),
+                count: count.clone(),
+            }));
+
+        let device_thread =
+            DeviceThread::start("fc_test_dev", vec![device], Arc::new(vec![])).unwrap();
+        evt.write(3).unwrap();
+        while *count.lock().unwrap() == 0 {
+            thread::yield_now();
+        }
+        assert_eq!(*count.lock().unwrap(), 3);
+
+        // Dropping the handle stops and joins the thread.
+        drop(device_thread);
+        evt.write(1).unwrap();
+        assert_eq!(evt.read().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_error_messages() {
+        let err = Error::Spawn(io::Error::from_raw_os_error(libc::EAGAIN));
+        assert!(err
+            .to_string()
+            .starts_with("Cannot spawn the device thread"));
+    }
+}
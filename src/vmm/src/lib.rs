@@ -13,9 +13,12 @@
 /// Handles setup and initialization a `Vmm` object.
 pub mod builder;
 pub(crate) mod device_manager;
+pub mod memory_dump;
 pub mod memory_snapshot;
 /// Save/restore utilities.
 pub mod persist;
+/// Bounded log of the latest errors hit by the VMM.
+pub mod recent_errors;
 /// Resource store for configured microVM resources.
 pub mod resources;
 /// microVM RPC API adapters.
@@ -32,34 +35,45 @@ pub mod version_map;
 pub mod vmm_config;
 mod vstate;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Display, Formatter};
 use std::io;
 use std::os::unix::io::AsRawFd;
 use std::sync::mpsc::{RecvTimeoutError, TryRecvError};
 use std::sync::{Arc, Barrier, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[cfg(target_arch = "x86_64")]
 use crate::device_manager::legacy::PortIODeviceManager;
 use crate::device_manager::mmio::MMIODeviceManager;
+use crate::device_manager::thread::DeviceThread;
 use crate::memory_snapshot::SnapshotMemory;
 use crate::persist::{MicrovmState, MicrovmStateError, VmInfo};
+use crate::recent_errors::{RecentError, RecentErrors};
+use crate::version_map::VERSION_MAP;
+use crate::vmm_config::devices::{
+    DeviceResetConfig, DeviceResetError, MmioDeviceDescription, ResettableDeviceType,
+};
 use crate::vmm_config::instance_info::{InstanceInfo, VmState};
-use crate::vstate::vcpu::VcpuState;
+use crate::vmm_config::machine_config::GuestSnapshotConfig;
+use crate::vmm_config::snapshot::{CreateSnapshotParams, SnapshotType};
+use crate::vstate::vcpu::{VcpuRegisters, VcpuState};
 use crate::vstate::{
     vcpu::{Vcpu, VcpuEvent, VcpuHandle, VcpuResponse},
     vm::Vm,
 };
 use arch::DeviceType;
+use devices::legacy::SerialLog;
 use devices::virtio::balloon::Error as BalloonError;
 use devices::virtio::{
-    Balloon, BalloonConfig, BalloonStats, Block, MmioTransport, Net, BALLOON_DEV_ID, TYPE_BALLOON,
-    TYPE_BLOCK, TYPE_NET,
+    Balloon, BalloonConfig, BalloonStats, Block, DiskBacking, MmioTransport, Net, BALLOON_DEV_ID,
+    CONSOLE_DEV_ID, TYPE_BALLOON, TYPE_BLOCK, TYPE_CONSOLE, TYPE_FS, TYPE_NET,
 };
 use devices::BusDevice;
-use event_manager::{EventManager as BaseEventManager, EventOps, Events, MutEventSubscriber};
-use logger::{error, info, warn, LoggerError, MetricsError, METRICS};
+use event_manager::{
+    EventManager as BaseEventManager, EventOps, Events, MutEventSubscriber, SubscriberId,
+};
+use logger::{error, info, warn, IncMetric, LoggerError, MetricsError, METRICS};
 use rate_limiter::BucketUpdate;
 use seccompiler::BpfProgram;
 use snapshot::Persist;
@@ -98,6 +112,13 @@ pub const FC_EXIT_CODE_SIGILL: ExitCode = 157;
 pub const FC_EXIT_CODE_BAD_CONFIGURATION: ExitCode = 152;
 /// Command line arguments parsing error.
 pub const FC_EXIT_CODE_ARG_PARSING: ExitCode = 153;
+/// The guest rebooted, when it is configured to be reported.
+pub const FC_EXIT_CODE_GUEST_REBOOT: ExitCode = 158;
+/// The guest kernel panicked, when it is configured to be reported.
+pub const FC_EXIT_CODE_GUEST_PANIC: ExitCode = 159;
+/// The microVM didn't start within the boot timeout, once the VMM thread installed its seccomp
+/// filter.
+pub const FC_EXIT_CODE_BOOT_TIMEOUT: ExitCode = 160;
 
 /// Errors associated with the VMM internal logic. These errors cannot be generated by direct user
 /// input, but can result from bad configuration of the host (for example if Firecracker doesn't
@@ -110,8 +131,12 @@ pub enum Error {
     CreateLegacyDevice(device_manager::legacy::Error),
     /// Device manager error.
     DeviceManager(device_manager::mmio::Error),
+    /// Cannot start a device thread.
+    DeviceThread(device_manager::thread::Error),
     /// Cannot fetch the KVM dirty bitmap.
     DirtyBitmap(kvm_ioctls::Error),
+    /// The guest memory doesn't track the pages dirtied by the VMM.
+    DirtyPageTrackingNotSupported,
     /// Cannot read from an Event file descriptor.
     EventFd(io::Error),
     /// I8042 Error.
@@ -134,6 +159,8 @@ pub enum Error {
     SeccompFilters(seccompiler::InstallationError),
     /// Write to the serial console failed.
     Serial(io::Error),
+    /// Cannot open the output of the second serial port.
+    SerialOutput(io::Error),
     /// Cannot create Timer file descriptor.
     TimerFd(io::Error),
     /// Vcpu configuration error.
@@ -173,7 +200,12 @@ impl Display for Error {
             #[cfg(target_arch = "x86_64")]
             CreateLegacyDevice(e) => write!(f, "Error creating legacy device: {}", e),
             DeviceManager(e) => write!(f, "{}", e),
+            DeviceThread(e) => write!(f, "{}", e),
             DirtyBitmap(e) => write!(f, "Error getting the KVM dirty bitmap. {}", e),
+            DirtyPageTrackingNotSupported => write!(
+                f,
+                "Dirty page tracking cannot be enabled on a microVM started without it."
+            ),
             EventFd(e) => write!(f, "Event fd error: {}", e),
             I8042Error(e) => write!(f, "I8042 error: {}", e),
             KernelFile(e) => write!(f, "Cannot access kernel file: {}", e),
@@ -185,6 +217,7 @@ impl Display for Error {
             RegisterMMIODevice(e) => write!(f, "Cannot add a device to the MMIO Bus. {}", e),
             SeccompFilters(e) => write!(f, "Cannot install seccomp filters: {}", e),
             Serial(e) => write!(f, "Error writing to the serial console: {}", e),
+            SerialOutput(e) => write!(f, "Cannot open the second serial port output: {}", e),
             TimerFd(e) => write!(f, "Error creating timer fd: {}", e),
             VcpuConfigure(e) => write!(f, "Error configuring the vcpu for boot: {}", e),
             VcpuCreate(e) => write!(f, "Error creating the vcpu: {}", e),
@@ -238,16 +271,42 @@ pub struct Vmm {
     events_observer: Option<Box<dyn VmmEventsObserver>>,
     instance_info: InstanceInfo,
     shutdown_exit_code: Option<ExitCode>,
+    serial_log: Option<SerialLog>,
+    // The latest errors hit by the VMM, kept for post-mortem debugging.
+    recent_errors: RecentErrors,
 
     // Guest VM core resources.
     vm: Vm,
     guest_memory: GuestMemoryMmap,
     vcpus_handles: Vec<VcpuHandle>,
+    // The number of vCPUs, at the end of `vcpus_handles`, which are never resumed. The guest
+    // took them offline before the snapshot the microVM was restored from.
+    parked_vcpus: usize,
     // Used by Vcpus and devices to initiate teardown; Vmm should never write here.
     vcpus_exit_evt: EventFd,
+    // Written by the i8042 controller when the guest resets it to reboot.
+    #[cfg(target_arch = "x86_64")]
+    i8042_reset_evt: EventFd,
+    // The exit code of the microVM when the guest reboots through the i8042 controller.
+    #[cfg(target_arch = "x86_64")]
+    reboot_exit_code: ExitCode,
+    // Written by the pvpanic device when the guest kernel panics.
+    guest_panic_evt: EventFd,
+    // Written by the snapshot trigger device when the guest asks for a snapshot.
+    guest_snapshot_evt: EventFd,
+    // Where the snapshots the guest asks for are written, if it can ask for them.
+    guest_snapshot: Option<GuestSnapshotConfig>,
+    // How long the vCPUs are given to stop when the microVM shuts down.
+    shutdown_grace_period: Duration,
+    // Whether the anonymous guest memory is zeroed when the microVM shuts down.
+    zero_mem_on_shutdown: bool,
 
     // Guest VM devices.
     mmio_device_manager: MMIODeviceManager,
+    // Event subscribers of the devices attached at boot, unregistered if the boot fails.
+    device_subscribers: Vec<SubscriberId>,
+    // Threads serving the devices which don't run on the VMM thread.
+    device_threads: Vec<DeviceThread>,
     #[cfg(target_arch = "x86_64")]
     pio_device_manager: PortIODeviceManager,
 }
@@ -263,6 +322,28 @@ impl Vmm {
         self.shutdown_exit_code
     }
 
+    /// Provides the latest guest serial output, if the serial log is enabled.
+    pub fn serial_log(&self) -> Option<Vec<u8>> {
+        self.serial_log.as_ref().map(SerialLog::contents)
+    }
+
+    /// Records an error hit by the VMM in the log of the latest errors. The errors reported
+    /// through the logger are recorded already, once the log is hooked to it with
+    /// `RecentErrors::record_logged_errors`.
+    pub fn record_error(&self, message: String) {
+        self.recent_errors.record(message);
+    }
+
+    /// Provides the latest errors hit by the VMM, oldest first.
+    pub fn recent_errors(&self) -> Vec<RecentError> {
+        self.recent_errors.list()
+    }
+
+    /// Describes the devices attached to the MMIO bus.
+    pub fn mmio_devices(&self) -> Vec<MmioDeviceDescription> {
+        self.mmio_device_manager.describe_devices()
+    }
+
     /// Gets the specified bus device.
     pub fn get_bus_device(
         &self,
@@ -272,11 +353,40 @@ impl Vmm {
         self.mmio_device_manager.get_device(device_type, device_id)
     }
 
+    /// Asks the guest driver to reset the virtio device described by `config`. The driver is
+    /// notified through a config change interrupt and the device queues are re-initialized once
+    /// the driver sets them up again.
+    pub fn reset_device(
+        &self,
+        config: &DeviceResetConfig,
+    ) -> std::result::Result<(), DeviceResetError> {
+        let virtio_type = match config.device_type {
+            ResettableDeviceType::Block => TYPE_BLOCK,
+            ResettableDeviceType::Net => TYPE_NET,
+        };
+        let bus_dev = self
+            .get_bus_device(DeviceType::Virtio(virtio_type), &config.id)
+            .ok_or_else(|| DeviceResetError::DeviceNotFound(config.id.clone()))?;
+        let mut bus_dev = bus_dev.lock().expect("Poisoned lock");
+        // Virtio devices are guaranteed MmioTransport.
+        let mmio_dev = bus_dev
+            .as_mut_any()
+            .downcast_mut::<MmioTransport>()
+            .unwrap();
+        if !mmio_dev.locked_device().is_activated() {
+            return Err(DeviceResetError::DeviceNotActivated(config.id.clone()));
+        }
+        mmio_dev
+            .request_reset()
+            .map_err(DeviceResetError::SignalDevice)
+    }
+
     /// Starts the microVM vcpus.
     pub fn start_vcpus(
         &mut self,
         mut vcpus: Vec<Vcpu>,
         vcpu_seccomp_filter: Arc<BpfProgram>,
+        vcpu_affinity: Option<&BTreeMap<u8, Vec<usize>>>,
     ) -> Result<()> {
         let vcpu_count = vcpus.len();
         let barrier = Arc::new(Barrier::new(vcpu_count + 1));
@@ -295,10 +405,16 @@ impl Vmm {
             vcpu.kvm_vcpu
                 .set_pio_bus(self.pio_device_manager.io_bus.clone());
 
-            self.vcpus_handles.push(
-                vcpu.start_threaded(vcpu_seccomp_filter.clone(), barrier.clone())
-                    .map_err(Error::VcpuHandle)?,
-            );
+            let vcpu_index = vcpu.kvm_vcpu.index;
+            let vcpu_handle = vcpu
+                .start_threaded(vcpu_seccomp_filter.clone(), barrier.clone())
+                .map_err(Error::VcpuHandle)?;
+            if let Some(host_cpus) = vcpu_affinity.and_then(|affinity| affinity.get(&vcpu_index)) {
+                vcpu_handle
+                    .set_affinity(host_cpus)
+                    .map_err(Error::VcpuHandle)?;
+            }
+            self.vcpus_handles.push(vcpu_handle);
         }
         self.instance_info.state = VmState::Paused;
         // Wait for vCPUs to initialize their TLS before moving forward.
@@ -312,7 +428,7 @@ impl Vmm {
         &mut self,
         _expected_response: VcpuResponse,
     ) -> std::result::Result<(), ()> {
-        for handle in self.vcpus_handles.iter() {
+        for handle in self.running_vcpus_handles() {
             match handle
                 .response_receiver()
                 .recv_timeout(Duration::from_millis(1000))
@@ -337,6 +453,9 @@ impl Vmm {
     pub fn pause_vm(&mut self) -> Result<()> {
         self.broadcast_vcpu_event(VcpuEvent::Pause, VcpuResponse::Paused)
             .map_err(|_| Error::VcpuPause)?;
+        // With the vCPUs paused, the guest can't touch the device queues anymore, so they can
+        // be brought to a state which is safe to save.
+        self.mmio_device_manager.quiesce_devices();
         self.instance_info.state = VmState::Paused;
         Ok(())
     }
@@ -346,6 +465,27 @@ impl Vmm {
         &self.guest_memory
     }
 
+    /// Returns the number of started vCPUs.
+    pub fn vcpu_count(&self) -> usize {
+        self.vcpus_handles.len()
+    }
+
+    /// Keeps the last `count` vCPUs paused when the microVM is resumed. The caller makes sure
+    /// that the guest took them offline.
+    pub(crate) fn park_vcpus(&mut self, count: usize) {
+        self.parked_vcpus = count;
+    }
+
+    // The handles of the vCPUs which are paused and resumed along with the microVM.
+    fn running_vcpus_handles(&self) -> &[VcpuHandle] {
+        &self.vcpus_handles[..self.vcpus_handles.len() - self.parked_vcpus]
+    }
+
+    /// Returns the size of the guest memory, in MiB.
+    pub fn mem_size_mib(&self) -> u64 {
+        mem_size_mib(&self.guest_memory)
+    }
+
     /// Injects CTRL+ALT+DEL keystroke combo in the i8042 device.
     #[cfg(target_arch = "x86_64")]
     pub fn send_ctrl_alt_del(&mut self) -> Result<()> {
@@ -360,6 +500,60 @@ impl Vmm {
     /// Saves the state of a paused Microvm.
     pub fn save_state(&mut self) -> std::result::Result<MicrovmState, MicrovmStateError> {
         use self::MicrovmStateError::SaveVmState;
+        // The state of the virtio console can't be saved.
+        if self
+            .get_bus_device(DeviceType::Virtio(TYPE_CONSOLE), CONSOLE_DEV_ID)
+            .is_some()
+        {
+            return Err(MicrovmStateError::NotAllowed(
+                "Snapshots are not supported with the virtio console.".to_string(),
+            ));
+        }
+        // Nor the one of the pvpanic device, which isn't restored.
+        if self
+            .get_bus_device(DeviceType::PvPanic, &DeviceType::PvPanic.to_string())
+            .is_some()
+        {
+            return Err(MicrovmStateError::NotAllowed(
+                "Snapshots are not supported with the pvpanic device.".to_string(),
+            ));
+        }
+        // Neither can the content of the memory backed drives.
+        let memory_backed_drive =
+            self.mmio_device_manager
+                .for_each_device(|device_type, id, _, bus_dev| {
+                    if let DeviceType::Virtio(TYPE_BLOCK) = *device_type {
+                        let bus_dev = bus_dev.lock().expect("Poisoned lock");
+                        // Virtio devices are guaranteed MmioTransport.
+                        let mmio_dev = bus_dev.as_any().downcast_ref::<MmioTransport>().unwrap();
+                        let mut virtio = mmio_dev.locked_device();
+                        let block = virtio.as_mut_any().downcast_mut::<Block>().unwrap();
+                        if block.backing() != DiskBacking::File {
+                            return Err(id.clone());
+                        }
+                    }
+                    Ok(())
+                });
+        if let Err(drive_id) = memory_backed_drive {
+            return Err(MicrovmStateError::NotAllowed(format!(
+                "Snapshots are not supported with the memory backed drive {}.",
+                drive_id
+            )));
+        }
+        // Nor can the state of the shared directories, which lives on the host.
+        let fs_device = self
+            .mmio_device_manager
+            .for_each_device(|device_type, id, _, _| match *device_type {
+                DeviceType::Virtio(TYPE_FS) => Err(id.clone()),
+                _ => Ok(()),
+            });
+        if let Err(fs_id) = fs_device {
+            return Err(MicrovmStateError::NotAllowed(format!(
+                "Snapshots are not supported with the virtio-fs device {}.",
+                fs_id
+            )));
+        }
+        self.sync_block_devices()?;
         let vcpu_states = self.save_vcpu_states()?;
         let vm_state = {
             #[cfg(target_arch = "x86_64")]
@@ -379,7 +573,11 @@ impl Vmm {
         let memory_state = self.guest_memory().describe();
 
         Ok(MicrovmState {
-            vm_info: VmInfo { mem_size_mib },
+            // The memory file checksum is only known once the memory is written.
+            vm_info: VmInfo {
+                mem_size_mib,
+                mem_checksum: None,
+            },
             memory_state,
             vm_state,
             vcpu_states,
@@ -387,6 +585,82 @@ impl Vmm {
         })
     }
 
+    // Writes the data of the drives out to the host storage. The vCPUs are paused, so no request
+    // is in flight and the disks are consistent with the guest memory being snapshotted.
+    fn sync_block_devices(&self) -> std::result::Result<(), MicrovmStateError> {
+        self.mmio_device_manager
+            .for_each_device(|device_type, id, _, bus_dev| {
+                if let DeviceType::Virtio(TYPE_BLOCK) = *device_type {
+                    let bus_dev = bus_dev.lock().expect("Poisoned lock");
+                    // Virtio devices are guaranteed MmioTransport.
+                    let mmio_dev = bus_dev.as_any().downcast_ref::<MmioTransport>().unwrap();
+                    let mut virtio = mmio_dev.locked_device();
+                    let block = virtio.as_mut_any().downcast_mut::<Block>().unwrap();
+                    block
+                        .sync_disk()
+                        .map_err(|err| MicrovmStateError::SyncBlockDevice(id.clone(), err))?;
+                }
+                Ok(())
+            })
+    }
+
+    // Writes the data of the block devices out to the host storage, a failing device doesn't
+    // keep the others from being flushed.
+    fn flush_block_devices(&self) {
+        let _: std::result::Result<(), ()> =
+            self.mmio_device_manager
+                .for_each_device(|device_type, id, _, bus_dev| {
+                    if let DeviceType::Virtio(TYPE_BLOCK) = *device_type {
+                        let bus_dev = bus_dev.lock().expect("Poisoned lock");
+                        // Virtio devices are guaranteed MmioTransport.
+                        let mmio_dev = bus_dev.as_any().downcast_ref::<MmioTransport>().unwrap();
+                        let mut virtio = mmio_dev.locked_device();
+                        let block = virtio.as_mut_any().downcast_mut::<Block>().unwrap();
+                        if let Err(e) = block.flush_disk() {
+                            error!("Failed to flush the block device {}: {}", id, e);
+                        }
+                    }
+                    Ok(())
+                });
+    }
+
+    // Releases the guest memory pages to the host, which zeroes them, so that the guest data
+    // doesn't linger in the host memory. Unlike overwriting the memory, this doesn't fault in
+    // the pages the guest never touched. The regions backed by a file, like the memory file of
+    // a snapshot or a shared memory backing file, are skipped, so as not to wipe the file.
+    fn zero_guest_memory(&self) {
+        let start = Instant::now();
+        let mut zeroed = 0;
+        let _: std::result::Result<(), ()> = self.guest_memory.with_regions(|_, region| {
+            if region.file_offset().is_none() {
+                // Safe because the region is a valid private anonymous mapping of `region.len()`
+                // bytes, whose pages read as zeros once released. The guest can't rely on its
+                // content anymore, the microVM being stopped.
+                let ret = unsafe {
+                    libc::madvise(
+                        region.as_ptr() as *mut _,
+                        region.len() as usize,
+                        libc::MADV_DONTNEED,
+                    )
+                };
+                if ret < 0 {
+                    error!(
+                        "Failed to zero the guest memory: {}",
+                        io::Error::last_os_error()
+                    );
+                } else {
+                    zeroed += region.len();
+                }
+            }
+            Ok(())
+        });
+        info!(
+            "Zeroed {} MiB of guest memory in {} ms.",
+            zeroed >> 20,
+            start.elapsed().as_millis()
+        );
+    }
+
     fn save_vcpu_states(&mut self) -> std::result::Result<Vec<VcpuState>, MicrovmStateError> {
         use self::MicrovmStateError::*;
         for handle in self.vcpus_handles.iter() {
@@ -420,13 +694,40 @@ impl Vmm {
         Ok(vcpu_states)
     }
 
+    /// Provides the registers of a paused vCPU, for debugging the guest.
+    pub fn vcpu_registers(
+        &mut self,
+        vcpu_id: u8,
+    ) -> std::result::Result<VcpuRegisters, MicrovmStateError> {
+        use self::MicrovmStateError::*;
+        let handle = self
+            .vcpus_handles
+            .get(vcpu_id as usize)
+            .ok_or(InvalidVcpuId(vcpu_id))?;
+        handle
+            .send_event(VcpuEvent::SaveState)
+            .map_err(SignalVcpu)?;
+
+        // The vCPU only saves its state while paused, otherwise it refuses the request.
+        match handle
+            .response_receiver()
+            .recv_timeout(Duration::from_millis(1000))
+            .map_err(|_| UnexpectedVcpuResponse)?
+        {
+            VcpuResponse::SavedState(state) => Ok(state.registers()),
+            VcpuResponse::Error(e) => Err(SaveVcpuState(e)),
+            VcpuResponse::NotAllowed(reason) => Err(NotAllowed(reason)),
+            _ => Err(UnexpectedVcpuResponse),
+        }
+    }
+
     // Sends an event to all vCPUs and waits for a response.
     fn broadcast_vcpu_event(
         &mut self,
         event: VcpuEvent,
         expected_response: VcpuResponse,
     ) -> Result<()> {
-        for handle in self.vcpus_handles.iter() {
+        for handle in self.running_vcpus_handles() {
             handle
                 .send_event(event.clone())
                 .map_err(|_| Error::VcpuMessage)?;
@@ -496,15 +797,32 @@ impl Vmm {
     }
 
     /// Enables or disables KVM dirty page tracking.
+    ///
+    /// It can only be enabled if the guest memory also tracks the pages dirtied by the VMM, i.e.
+    /// the microVM was started with dirty page tracking. Once enabled, only the pages dirtied
+    /// from then on are tracked.
     pub fn set_dirty_page_tracking(&mut self, enable: bool) -> Result<()> {
+        if enable && !self.guest_memory.is_dirty_tracking_enabled() {
+            return Err(Error::DirtyPageTrackingNotSupported);
+        }
         // This function _always_ results in an ioctl update. The VMM is stateless in the sense
         // that it's unaware of the current dirty page tracking setting.
-        // The VMM's consumer will need to cache the dirty tracking setting internally. For
-        // example, if this function were to be exposed through the VMM controller, the VMM
-        // resources should cache the flag.
+        // The VMM's consumer will need to cache the dirty tracking setting internally, the VMM
+        // controller caches it in the VMM resources.
         self.vm
             .set_kvm_memory_regions(&self.guest_memory, enable)
-            .map_err(Error::Vm)
+            .map_err(Error::Vm)?;
+        if enable {
+            // Getting the KVM dirty bitmap clears it, the VMM one is cleared explicitly.
+            self.get_dirty_bitmap()?;
+            self.guest_memory.with_regions(|_, region| -> Result<()> {
+                if let Some(bitmap) = region.dirty_bitmap() {
+                    bitmap.reset();
+                }
+                Ok(())
+            })?;
+        }
+        Ok(())
     }
 
     /// Updates the path of the host file backing the emulated block device with id `drive_id`.
@@ -551,6 +869,16 @@ impl Vmm {
             .map_err(Error::DeviceManager)
     }
 
+    /// Starts capturing the traffic of the network device with `net_id` id to the pcap file
+    /// at `pcap_path`, or stops the capture when `pcap_path` is `None`.
+    pub fn update_net_pcap(&mut self, net_id: &str, pcap_path: Option<String>) -> Result<()> {
+        self.mmio_device_manager
+            .with_virtio_device_with_id(TYPE_NET, net_id, |net: &mut Net| {
+                net.set_pcap_path(pcap_path).map_err(|e| format!("{:?}", e))
+            })
+            .map_err(Error::DeviceManager)
+    }
+
     /// Returns a reference to the balloon device if present.
     pub fn balloon_config(&self) -> std::result::Result<BalloonConfig, BalloonError> {
         if let Some(busdev) = self.get_bus_device(DeviceType::Virtio(TYPE_BALLOON), BALLOON_DEV_ID)
@@ -679,6 +1007,54 @@ impl Vmm {
         }
     }
 
+    // Creates the full snapshot the guest asked for through the snapshot trigger device. The
+    // microVM is paused while the snapshot is written, unless it already is.
+    fn create_guest_snapshot(&mut self) {
+        let params = match self.guest_snapshot.as_ref() {
+            Some(config) => CreateSnapshotParams {
+                snapshot_type: SnapshotType::Full,
+                snapshot_path: config.snapshot_path.clone(),
+                snapshot_fd: None,
+                mem_file_path: config.mem_file_path.clone(),
+                mem_file_fd: None,
+                version: None,
+                live: false,
+            },
+            None => {
+                error!("Spurious guest snapshot request.");
+                return;
+            }
+        };
+        METRICS.vmm.guest_snapshots.inc();
+
+        let pause = self.instance_info.state == VmState::Running;
+        if pause {
+            if let Err(e) = self.pause_vm() {
+                error!("Failed to pause the microVM for the guest snapshot: {}", e);
+                METRICS.vmm.guest_snapshot_fails.inc();
+                return;
+            }
+        }
+        match persist::create_snapshot(self, &params, VERSION_MAP.clone()) {
+            Ok(()) => info!("Created the snapshot requested by the guest."),
+            Err(e) => {
+                error!(
+                    "Failed to create the snapshot requested by the guest: {}",
+                    e
+                );
+                METRICS.vmm.guest_snapshot_fails.inc();
+            }
+        }
+        if pause {
+            if let Err(e) = self.resume_vm() {
+                error!(
+                    "Failed to resume the microVM after the guest snapshot: {}",
+                    e
+                );
+            }
+        }
+    }
+
     /// Signals Vmm to stop and exit.
     pub fn stop(&mut self, exit_code: ExitCode) {
         /*
@@ -713,11 +1089,34 @@ impl Vmm {
                 );
             }
         }
+        // The vCPUs share the grace period. The ones which are still running past it, e.g. stuck
+        // handling an exit, are left behind instead of blocking the shutdown, and are killed
+        // when the process exits.
+        //
         // The actual thread::join() that runs to release the thread's resource is done in
         // the VcpuHandle's Drop trait.  We can trigger that to happen now by clearing the
         // list of handles. Do it here instead of Vmm::Drop to avoid dependency cycles.
         // (Vmm's Drop will also assert this list is empty).
-        self.vcpus_handles.clear();
+        let deadline = Instant::now() + self.shutdown_grace_period;
+        for (idx, handle) in self.vcpus_handles.drain(..).enumerate() {
+            if !handle.wait_finished(deadline.saturating_duration_since(Instant::now())) {
+                error!(
+                    "vCPU {} did not stop within the shutdown grace period of {} ms.",
+                    idx,
+                    self.shutdown_grace_period.as_millis()
+                );
+                METRICS.vmm.vcpus_left_running.inc();
+                handle.detach();
+            }
+        }
+        // The guest can't submit more requests, so the data it wrote is flushed out to the host
+        // storage before the devices are dropped.
+        self.flush_block_devices();
+        // The device threads are stopped and joined when their handles are dropped.
+        self.device_threads.clear();
+        if self.zero_mem_on_shutdown {
+            self.zero_guest_memory();
+        }
 
         // Break the main event loop, propagating the Vmm exit-code.
         self.shutdown_exit_code = Some(exit_code);
@@ -793,7 +1192,22 @@ impl MutEventSubscriber for Vmm {
                 }
             }
             self.stop(exit_code.unwrap_or(FC_EXIT_CODE_OK));
+        } else if source == self.guest_panic_evt.as_raw_fd() && event_set == EventSet::IN {
+            let _ = self.guest_panic_evt.read();
+            error!("The guest kernel panicked.");
+            self.stop(FC_EXIT_CODE_GUEST_PANIC);
+        } else if source == self.guest_snapshot_evt.as_raw_fd() && event_set == EventSet::IN {
+            let _ = self.guest_snapshot_evt.read();
+            self.create_guest_snapshot();
         } else {
+            #[cfg(target_arch = "x86_64")]
+            {
+                if source == self.i8042_reset_evt.as_raw_fd() && event_set == EventSet::IN {
+                    let _ = self.i8042_reset_evt.read();
+                    self.stop(self.reboot_exit_code);
+                    return;
+                }
+            }
             error!("Spurious EventManager event for handler: Vmm");
         }
     }
@@ -802,5 +1216,15 @@ impl MutEventSubscriber for Vmm {
         if let Err(e) = ops.add(Events::new(&self.vcpus_exit_evt, EventSet::IN)) {
             error!("Failed to register vmm exit event: {}", e);
         }
+        #[cfg(target_arch = "x86_64")]
+        if let Err(e) = ops.add(Events::new(&self.i8042_reset_evt, EventSet::IN)) {
+            error!("Failed to register i8042 reset event: {}", e);
+        }
+        if let Err(e) = ops.add(Events::new(&self.guest_panic_evt, EventSet::IN)) {
+            error!("Failed to register guest panic event: {}", e);
+        }
+        if let Err(e) = ops.add(Events::new(&self.guest_snapshot_evt, EventSet::IN)) {
+            error!("Failed to register guest snapshot event: {}", e);
+        }
     }
 }
@@ -0,0 +1,205 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Defines functionality for dumping the guest memory of a running microVM, for offline
+//! analysis. Unlike a snapshot, a dump holds no vCPU or device state.
+//!
+//! All the fields of a dump are little-endian. It starts with a header:
+//!
+//! | Offset | Size | Field                                  |
+//! |--------|------|----------------------------------------|
+//! | 0      | 8    | Magic: `FCMEMDMP`                      |
+//! | 8      | 4    | Format version: 1                      |
+//! | 12     | 4    | Number of guest memory regions         |
+//!
+//! which is followed by a 24 bytes descriptor for each guest memory region:
+//!
+//! | Offset | Size | Field                                  |
+//! |--------|------|----------------------------------------|
+//! | 0      | 8    | Guest physical address of the region   |
+//! | 8      | 8    | Size of the region, in bytes           |
+//! | 16     | 8    | Offset of the region data in the dump  |
+//!
+//! The raw data of the regions comes next, in the order of the descriptors, starting at the
+//! first offset aligned to `MEMORY_DUMP_ALIGNMENT` after the descriptors.
+
+use std::fmt::{Display, Formatter};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+
+use crate::memory_snapshot::{self, SnapshotMemory};
+use crate::vmm_config::instance_info::VmState;
+use crate::vmm_config::memory_dump::DumpGuestMemoryParams;
+use crate::{Error as VmmError, Vmm};
+use vm_memory::GuestMemoryMmap;
+
+/// Magic number at the start of a guest memory dump.
+pub const MEMORY_DUMP_MAGIC: &[u8; 8] = b"FCMEMDMP";
+/// Version of the guest memory dump format.
+pub const MEMORY_DUMP_VERSION: u32 = 1;
+/// Alignment of the region data in a guest memory dump, so that it can be mapped.
+pub const MEMORY_DUMP_ALIGNMENT: u64 = 4096;
+const MEMORY_DUMP_HEADER_LEN: u64 = 16;
+const MEMORY_DUMP_REGION_DESCRIPTOR_LEN: u64 = 24;
+
+/// Errors associated with dumping the guest memory.
+#[derive(Debug)]
+pub enum DumpGuestMemoryError {
+    /// Cannot create or write the memory dump file.
+    MemoryDumpFile(io::Error),
+    /// Cannot write the guest memory to the memory dump file.
+    Memory(memory_snapshot::Error),
+    /// Cannot pause the microVM before dumping its memory.
+    PauseVm(VmmError),
+    /// Cannot resume the microVM after dumping its memory.
+    ResumeVm(VmmError),
+}
+
+impl Display for DumpGuestMemoryError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        use self::DumpGuestMemoryError::*;
+        match self {
+            MemoryDumpFile(err) => write!(f, "Cannot write the memory dump file: {}", err),
+            Memory(err) => write!(f, "Cannot dump the guest memory: {}", err),
+            PauseVm(err) => write!(f, "Cannot pause the microVM: {}", err),
+            ResumeVm(err) => write!(f, "Cannot resume the microVM: {}", err),
+        }
+    }
+}
+
+/// Dumps the guest memory of the microVM to `params.mem_file_path`. If `params.pause_vm` is
+/// set, a running microVM is paused for the dump and resumed afterwards.
+pub fn dump_guest_memory(
+    vmm: &mut Vmm,
+    params: &DumpGuestMemoryParams,
+) -> std::result::Result<(), DumpGuestMemoryError> {
+    let pause = params.pause_vm && vmm.instance_info().state == VmState::Running;
+    if pause {
+        vmm.pause_vm().map_err(DumpGuestMemoryError::PauseVm)?;
+    }
+
+    let result = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&params.mem_file_path)
+        .map_err(DumpGuestMemoryError::MemoryDumpFile)
+        .and_then(|mut file| write_memory_dump(vmm.guest_memory(), &mut file));
+
+    // The microVM is resumed even if the dump failed, which is then the error reported.
+    if pause {
+        let resumed = vmm.resume_vm().map_err(DumpGuestMemoryError::ResumeVm);
+        return result.and(resumed);
+    }
+    result
+}
+
+/// Writes `guest_memory` to `writer`, in the guest memory dump format.
+pub fn write_memory_dump<T: Write>(
+    guest_memory: &GuestMemoryMmap,
+    writer: &mut T,
+) -> std::result::Result<(), DumpGuestMemoryError> {
+    let regions = guest_memory.describe().regions;
+    let descriptors_end =
+        MEMORY_DUMP_HEADER_LEN + MEMORY_DUMP_REGION_DESCRIPTOR_LEN * regions.len() as u64;
+    let data_start = (descriptors_end + MEMORY_DUMP_ALIGNMENT - 1) / MEMORY_DUMP_ALIGNMENT
+        * MEMORY_DUMP_ALIGNMENT;
+
+    let mut header = Vec::with_capacity(data_start as usize);
+    header.extend_from_slice(MEMORY_DUMP_MAGIC);
+    header.extend_from_slice(&MEMORY_DUMP_VERSION.to_le_bytes());
+    header.extend_from_slice(&(regions.len() as u32).to_le_bytes());
+    for region in regions.iter() {
+        header.extend_from_slice(&region.base_address.to_le_bytes());
+        header.extend_from_slice(&(region.size as u64).to_le_bytes());
+        // The regions are dumped back to back, like in a snapshot memory file.
+        header.extend_from_slice(&(data_start + region.offset).to_le_bytes());
+    }
+    header.resize(data_start as usize, 0);
+
+    writer
+        .write_all(&header)
+        .map_err(DumpGuestMemoryError::MemoryDumpFile)?;
+    guest_memory
+        .dump(writer)
+        .map_err(DumpGuestMemoryError::Memory)?;
+    writer.flush().map_err(DumpGuestMemoryError::MemoryDumpFile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vm_memory::{Bytes, GuestAddress};
+
+    fn read_u32(buf: &[u8], offset: usize) -> u32 {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&buf[offset..offset + 4]);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn read_u64(buf: &[u8], offset: usize) -> u64 {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&buf[offset..offset + 8]);
+        u64::from_le_bytes(bytes)
+    }
+
+    #[test]
+    fn test_write_memory_dump() {
+        let page_size = 4096;
+        // Two regions, of one and two pages, with a one page gap between them.
+        let mem_regions = [
+            (GuestAddress(0), page_size),
+            (GuestAddress(page_size as u64 * 2), page_size * 2),
+        ];
+        let guest_memory = GuestMemoryMmap::from_ranges(&mem_regions[..]).unwrap();
+        guest_memory
+            .write_slice(&vec![1u8; page_size], GuestAddress(0))
+            .unwrap();
+        guest_memory
+            .write_slice(
+                &vec![2u8; page_size * 2],
+                GuestAddress(page_size as u64 * 2),
+            )
+            .unwrap();
+
+        let mut dump = Vec::new();
+        write_memory_dump(&guest_memory, &mut dump).unwrap();
+        assert_eq!(dump.len(), MEMORY_DUMP_ALIGNMENT as usize + page_size * 3);
+
+        assert_eq!(&dump[..8], MEMORY_DUMP_MAGIC);
+        assert_eq!(read_u32(&dump, 8), MEMORY_DUMP_VERSION);
+        assert_eq!(read_u32(&dump, 12), 2);
+
+        let expected_descriptors = [
+            (0, page_size as u64, MEMORY_DUMP_ALIGNMENT),
+            (
+                page_size as u64 * 2,
+                page_size as u64 * 2,
+                MEMORY_DUMP_ALIGNMENT + page_size as u64,
+            ),
+        ];
+        for (i, (addr, size, offset)) in expected_descriptors.iter().enumerate() {
+            let descriptor = 16 + 24 * i;
+            assert_eq!(read_u64(&dump, descriptor), *addr);
+            assert_eq!(read_u64(&dump, descriptor + 8), *size);
+            assert_eq!(read_u64(&dump, descriptor + 16), *offset);
+
+            let data = &dump[*offset as usize..(*offset + *size) as usize];
+            assert!(data.iter().all(|&b| b == i as u8 + 1));
+        }
+    }
+
+    #[test]
+    fn test_error_messages() {
+        use std::io::ErrorKind;
+
+        let err = DumpGuestMemoryError::MemoryDumpFile(io::Error::from(ErrorKind::NotFound));
+        assert!(err
+            .to_string()
+            .starts_with("Cannot write the memory dump file"));
+        let err = DumpGuestMemoryError::PauseVm(VmmError::VcpuPause);
+        assert!(err.to_string().starts_with("Cannot pause the microVM"));
+        let err = DumpGuestMemoryError::ResumeVm(VmmError::VcpuResume);
+        assert!(err.to_string().starts_with("Cannot resume the microVM"));
+    }
+}
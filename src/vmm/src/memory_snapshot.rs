@@ -54,10 +54,15 @@ where
     ) -> std::result::Result<(), Error>;
     /// Creates a GuestMemoryMmap given a `file` containing the data
     /// and a `state` containing mapping information.
+    ///
+    /// Unless `shared` is set, the regions are private copy-on-write mappings of `file`,
+    /// so `file` only needs to be readable and guest writes are never persisted to it.
+    /// Otherwise, `file` must also be writable and the guest writes end up in it.
     fn restore(
         file: &File,
         state: &GuestMemoryState,
         track_dirty_pages: bool,
+        shared: bool,
     ) -> std::result::Result<Self, Error>;
 }
 
@@ -175,7 +180,13 @@ impl SnapshotMemory for GuestMemoryMmap {
         file: &File,
         state: &GuestMemoryState,
         track_dirty_pages: bool,
+        shared: bool,
     ) -> std::result::Result<Self, Error> {
+        let sharing_flag = if shared {
+            libc::MAP_SHARED
+        } else {
+            libc::MAP_PRIVATE
+        };
         let mut mmap_regions = Vec::new();
         for region in state.regions.iter() {
             let mmap_region = GuestRegionMmap::build_guarded(
@@ -185,7 +196,7 @@ impl SnapshotMemory for GuestMemoryMmap {
                 )),
                 region.size,
                 libc::PROT_READ | libc::PROT_WRITE,
-                libc::MAP_NORESERVE | libc::MAP_PRIVATE,
+                libc::MAP_NORESERVE | sharing_flag,
             )
             .map(|r| {
                 let mut region = GuestRegionMmap::new(r, GuestAddress(region.base_address))?;
@@ -311,7 +322,8 @@ mod tests {
             guest_memory.dump(&mut memory_file.as_file()).unwrap();
 
             let restored_guest_memory =
-                GuestMemoryMmap::restore(&memory_file.as_file(), &memory_state, false).unwrap();
+                GuestMemoryMmap::restore(&memory_file.as_file(), &memory_state, false, false)
+                    .unwrap();
 
             // Check that the region contents are the same.
             let mut actual_region = vec![0u8; page_size * 2];
@@ -345,7 +357,7 @@ mod tests {
 
             // We can restore from this because this is the first dirty dump.
             let restored_guest_memory =
-                GuestMemoryMmap::restore(&file.as_file(), &memory_state, false).unwrap();
+                GuestMemoryMmap::restore(&file.as_file(), &memory_state, false, false).unwrap();
 
             // Check that the region contents are the same.
             let mut actual_region = vec![0u8; page_size * 2];
@@ -392,4 +404,69 @@ mod tests {
             assert_eq!(expected_first_region, diff_file_content);
         }
     }
+
+    #[test]
+    fn test_restore_memory_read_only_file() {
+        let page_size: usize = get_page_size().unwrap();
+
+        let mem_regions = [(GuestAddress(0), page_size)];
+        let guest_memory = GuestMemoryMmap::from_ranges(&mem_regions[..]).unwrap();
+        let ones = vec![1u8; page_size];
+        guest_memory.write(&ones[..], GuestAddress(0)).unwrap();
+        let memory_state = guest_memory.describe();
+
+        let memory_file = TempFile::new().unwrap();
+        guest_memory.dump(&mut memory_file.as_file()).unwrap();
+
+        // The memory file is only opened for reading, as it would be on a read-only mount.
+        let read_only_file = File::open(memory_file.as_path()).unwrap();
+        let restored_guest_memory =
+            GuestMemoryMmap::restore(&read_only_file, &memory_state, false, false).unwrap();
+
+        // The guest can still write to its memory, but the writes never reach the file.
+        let twos = vec![2u8; page_size];
+        restored_guest_memory
+            .write(&twos[..], GuestAddress(0))
+            .unwrap();
+        let mut actual_region = vec![0u8; page_size];
+        restored_guest_memory
+            .read(&mut actual_region.as_mut_slice(), GuestAddress(0))
+            .unwrap();
+        assert_eq!(twos, actual_region);
+
+        let mut file_content = Vec::new();
+        let mut reader = memory_file.as_file();
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        reader.read_to_end(&mut file_content).unwrap();
+        assert_eq!(ones, file_content);
+    }
+
+    #[test]
+    fn test_restore_memory_shared_mapping() {
+        let page_size: usize = get_page_size().unwrap();
+
+        let mem_regions = [(GuestAddress(0), page_size)];
+        let guest_memory = GuestMemoryMmap::from_ranges(&mem_regions[..]).unwrap();
+        let ones = vec![1u8; page_size];
+        guest_memory.write(&ones[..], GuestAddress(0)).unwrap();
+        let memory_state = guest_memory.describe();
+
+        let memory_file = TempFile::new().unwrap();
+        guest_memory.dump(&mut memory_file.as_file()).unwrap();
+
+        let restored_guest_memory =
+            GuestMemoryMmap::restore(&memory_file.as_file(), &memory_state, false, true).unwrap();
+
+        // The writes of the guest are carried through to the file.
+        let twos = vec![2u8; page_size];
+        restored_guest_memory
+            .write(&twos[..], GuestAddress(0))
+            .unwrap();
+
+        let mut file_content = Vec::new();
+        let mut reader = memory_file.as_file();
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        reader.read_to_end(&mut file_content).unwrap();
+        assert_eq!(twos, file_content);
+    }
 }
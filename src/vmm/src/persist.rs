@@ -3,33 +3,42 @@
 
 //! Defines state structures for saving/restoring a Firecracker microVM.
 
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::fs::{File, OpenOptions};
-use std::io::{self, Write};
-use std::path::Path;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
 use crate::builder::{self, StartMicrovmError};
 use crate::device_manager::persist::Error as DevicePersistError;
 use crate::mem_size_mib;
 use crate::vmm_config::machine_config::MAX_SUPPORTED_VCPUS;
-use crate::vmm_config::snapshot::{CreateSnapshotParams, LoadSnapshotParams, SnapshotType};
+use crate::vmm_config::resource_limits::ResourceLimits;
+use crate::vmm_config::serial::SerialConfig;
+use crate::vmm_config::snapshot::{
+    CreateSnapshotParams, InspectSnapshotParams, LoadSnapshotParams, MemoryMapping, SnapshotInfo,
+    SnapshotType,
+};
 use crate::vstate::{self, vcpu::VcpuState, vm::VmState};
 
 use crate::device_manager::persist::DeviceStates;
 use crate::memory_snapshot;
 use crate::memory_snapshot::{GuestMemoryState, SnapshotMemory};
+use crate::recent_errors::RecentErrors;
 use crate::version_map::FC_VERSION_TO_SNAP_VERSION;
-use crate::{Error as VmmError, EventManager, Vmm};
+use crate::{DirtyBitmap, Error as VmmError, EventManager, Vmm};
 #[cfg(target_arch = "x86_64")]
 use cpuid::common::{get_vendor_id_from_cpuid, get_vendor_id_from_host};
 
-use crate::vmm_config::instance_info::InstanceInfo;
+use crate::vmm_config::instance_info::{InstanceInfo, VmState as InstanceState};
 #[cfg(target_arch = "aarch64")]
 use arch::regs::{get_manufacturer_id_from_host, get_manufacturer_id_from_state};
-use logger::{error, info};
+use logger::{error, info, warn};
 use seccompiler::BpfThreadMap;
 use snapshot::Snapshot;
+use versionize::crc::{CRC64Reader, CRC64Writer};
 use versionize::{VersionMap, Versionize, VersionizeResult};
 use versionize_derive::Versionize;
 use vm_memory::GuestMemoryMmap;
@@ -39,12 +48,34 @@ const FC_V0_23_SNAP_VERSION: u16 = 1;
 #[cfg(target_arch = "x86_64")]
 const FC_V0_23_MAX_DEVICES: u32 = 11;
 
+// Maximum number of bytes written to a snapshot file in one go, so that a pending
+// cancellation request is noticed in a timely manner.
+const SNAPSHOT_WRITE_CHUNK_SIZE: usize = 4 << 20;
+
+// Maximum number of rounds in which a live snapshot copies the pages dirtied by the running guest,
+// before pausing it.
+const LIVE_SNAPSHOT_MAX_ROUNDS: usize = 8;
+// A live snapshot pauses the guest as soon as a round copies at most this many dirty pages.
+const LIVE_SNAPSHOT_DIRTY_PAGES_THRESHOLD: usize = 1024;
+
+/// Set from the `SIGUSR1` handler to abort the snapshot currently being created.
+pub static SNAPSHOT_CANCEL_REQUESTED: AtomicBool = AtomicBool::new(false);
+
 /// Holds information related to the VM that is not part of VmState.
 #[derive(Debug, PartialEq, Versionize)]
 // NOTICE: Any changes to this structure require a snapshot version bump.
 pub struct VmInfo {
     /// Guest memory size.
     pub mem_size_mib: u64,
+    /// CRC64 checksum of the memory file. Only full snapshots have one.
+    #[version(start = 2, default_fn = "default_mem_checksum")]
+    pub mem_checksum: Option<u64>,
+}
+
+impl VmInfo {
+    fn default_mem_checksum(_source_version: u16) -> Option<u64> {
+        None
+    }
 }
 
 /// Contains the necesary state for saving/restoring a microVM.
@@ -70,6 +101,8 @@ pub enum MicrovmStateError {
     IncompatibleState(String),
     /// Provided MicroVM state is invalid.
     InvalidInput,
+    /// The microVM has no vCPU with the provided index.
+    InvalidVcpuId(u8),
     /// Operation not allowed.
     NotAllowed(String),
     /// Failed to restore devices.
@@ -84,6 +117,8 @@ pub enum MicrovmStateError {
     SaveVmState(vstate::vm::Error),
     /// Failed to send event.
     SignalVcpu(vstate::vcpu::Error),
+    /// Failed to sync the backing file of the drive with the given ID.
+    SyncBlockDevice(String, io::Error),
     /// Vcpu is in unexpected state.
     UnexpectedVcpuResponse,
 }
@@ -94,6 +129,7 @@ impl Display for MicrovmStateError {
         match self {
             IncompatibleState(msg) => write!(f, "Compatibility checks failed: {}", msg),
             InvalidInput => write!(f, "Provided MicroVM state is invalid."),
+            InvalidVcpuId(id) => write!(f, "The microVM has no vCPU with index {}.", id),
             NotAllowed(msg) => write!(f, "Operation not allowed: {}", msg),
             RestoreDevices(err) => write!(f, "Cannot restore devices. Error: {:?}", err),
             RestoreVcpuState(err) => write!(f, "Cannot restore Vcpu state. Error: {:?}", err),
@@ -101,6 +137,11 @@ impl Display for MicrovmStateError {
             SaveVcpuState(err) => write!(f, "Cannot save Vcpu state. Error: {:?}", err),
             SaveVmState(err) => write!(f, "Cannot save Vm state. Error: {:?}", err),
             SignalVcpu(err) => write!(f, "Cannot signal Vcpu: {:?}", err),
+            SyncBlockDevice(drive_id, err) => write!(
+                f,
+                "Cannot sync the backing file of the drive {}: {}",
+                drive_id, err
+            ),
             UnexpectedVcpuResponse => write!(f, "Vcpu is in unexpected state."),
         }
     }
@@ -109,6 +150,8 @@ impl Display for MicrovmStateError {
 /// Errors associated with creating a snapshot.
 #[derive(Debug)]
 pub enum CreateSnapshotError {
+    /// Snapshot creation was cancelled.
+    Cancelled,
     /// Failed to get dirty bitmap.
     DirtyBitmap(VmmError),
     /// Invalid microVM version format
@@ -121,6 +164,8 @@ pub enum CreateSnapshotError {
     MemoryBackingFile(&'static str, io::Error),
     /// Failed to save MicrovmState.
     MicrovmState(MicrovmStateError),
+    /// Failed to pause the microVM at the end of a live snapshot.
+    PauseVm(VmmError),
     /// Failed to serialize microVM state.
     SerializeMicrovmState(snapshot::Error),
     /// Failed to open the snapshot backing file.
@@ -134,6 +179,7 @@ impl Display for CreateSnapshotError {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         use self::CreateSnapshotError::*;
         match self {
+            Cancelled => write!(f, "Snapshot creation was cancelled"),
             DirtyBitmap(err) => write!(f, "Cannot get dirty bitmap: {}", err),
             InvalidVersionFormat => write!(f, "Invalid microVM version format"),
             UnsupportedVersion => write!(
@@ -147,6 +193,7 @@ impl Display for CreateSnapshotError {
                 action, err
             ),
             MicrovmState(err) => write!(f, "Cannot save the microVM state: {}", err),
+            PauseVm(err) => write!(f, "Cannot pause the microVM: {}", err),
             SerializeMicrovmState(err) => {
                 write!(f, "Cannot serialize the microVM state: {:?}", err)
             }
@@ -185,6 +232,16 @@ pub enum LoadSnapshotError {
     CpuVendorCheck(String),
     /// Snapshot failed sanity checks.
     InvalidSnapshot(String),
+    /// Snapshot files don't match their checksums.
+    IntegrityCheckFailed(String),
+    /// The snapshot data version is newer than the latest one this build supports.
+    UnsupportedVersion(u16, u16),
+    /// A new path was given for a drive which isn't in the snapshot.
+    UnknownDrive(String),
+    /// The backing file of a drive cannot be accessed.
+    DriveBackingFile(String, String, io::Error),
+    /// The requested vCPU count cannot be resumed from the snapshot.
+    InvalidVcpuCount(String),
 }
 
 impl Display for LoadSnapshotError {
@@ -209,6 +266,19 @@ impl Display for LoadSnapshotError {
             ),
             CpuVendorCheck(err) => write!(f, "CPU vendor check failed: {}", err),
             InvalidSnapshot(err) => write!(f, "Snapshot sanity check failed: {}", err),
+            IntegrityCheckFailed(err) => write!(f, "Snapshot integrity check failed: {}", err),
+            UnsupportedVersion(snapshot_version, latest_version) => write!(
+                f,
+                "The snapshot data version {} is not supported, the latest supported version is {}",
+                snapshot_version, latest_version
+            ),
+            UnknownDrive(drive_id) => write!(f, "The snapshot has no drive with ID {}", drive_id),
+            DriveBackingFile(drive_id, path, err) => write!(
+                f,
+                "Cannot access the backing file {} of drive {}: {}",
+                path, drive_id, err
+            ),
+            InvalidVcpuCount(err) => write!(f, "Invalid vCPU count: {}", err),
         }
     }
 }
@@ -222,38 +292,134 @@ pub fn create_snapshot(
     // Fail early from invalid target version.
     let snapshot_data_version = get_snapshot_data_version(&params.version, &version_map, &vmm)?;
 
-    let microvm_state = vmm
-        .save_state()
-        .map_err(CreateSnapshotError::MicrovmState)?;
+    let result = if params.live {
+        // Only cancellation requests received while this snapshot is being written are honored.
+        SNAPSHOT_CANCEL_REQUESTED.store(false, Ordering::SeqCst);
+        let mut mem_file = open_memory_file(params)?;
+        create_live_snapshot(
+            vmm,
+            &mut mem_file,
+            params,
+            snapshot_data_version,
+            version_map,
+        )
+    } else {
+        let mut microvm_state = vmm
+            .save_state()
+            .map_err(CreateSnapshotError::MicrovmState)?;
+
+        // Only cancellation requests received while this snapshot is being written are honored.
+        SNAPSHOT_CANCEL_REQUESTED.store(false, Ordering::SeqCst);
+
+        // The memory file is written first, so that the microVM state can hold its checksum.
+        let mut mem_file = open_memory_file(params)?;
+        snapshot_memory_to_file(vmm, &mut mem_file, &params.snapshot_type).and_then(
+            |mem_checksum| {
+                microvm_state.vm_info.mem_checksum = mem_checksum;
+                snapshot_state_to_file(&microvm_state, params, snapshot_data_version, version_map)
+            },
+        )
+    };
+
+    // The errors above are returned before the memory file is opened, which leaves the files
+    // untouched. From then on, a snapshot file already at its path no longer matches the truncated
+    // memory file.
+    if result.is_err() {
+        info!("Snapshot creation failed, removing the partially written files.");
+        remove_snapshot_files(params);
+    }
 
-    snapshot_state_to_file(
-        &microvm_state,
-        &params.snapshot_path,
-        snapshot_data_version,
-        version_map,
-    )?;
+    if SNAPSHOT_CANCEL_REQUESTED.swap(false, Ordering::SeqCst) && result.is_err() {
+        info!("Snapshot creation cancelled.");
+        return Err(CreateSnapshotError::Cancelled);
+    }
 
-    snapshot_memory_to_file(vmm, &params.mem_file_path, &params.snapshot_type)?;
+    result
+}
 
-    Ok(())
+// Removes the files of a snapshot whose creation failed, so that a half-written snapshot can't
+// be loaded later on. The removal is best-effort, the files which can't be removed are logged.
+// The files passed as descriptors have no path to remove them by, they are left to the caller.
+fn remove_snapshot_files(params: &CreateSnapshotParams) {
+    let files = [
+        (&params.snapshot_path, params.snapshot_fd),
+        (&params.mem_file_path, params.mem_file_fd),
+    ];
+    for (path, _) in files.iter().filter(|(_, fd)| fd.is_none()) {
+        match std::fs::remove_file(path) {
+            Ok(()) => {}
+            // The snapshot file is only created once the memory file is written.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => warn!(
+                "Cannot remove the partially written snapshot file {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+}
+
+// Wraps a snapshot file and fails any write issued after a cancellation request.
+struct CancellableWriter<'a> {
+    file: &'a mut File,
+    cancel_requested: &'a AtomicBool,
+}
+
+impl<'a> CancellableWriter<'a> {
+    // Wraps `file`, whose writes are cancelled through `SNAPSHOT_CANCEL_REQUESTED`.
+    fn new(file: &'a mut File) -> Self {
+        CancellableWriter {
+            file,
+            cancel_requested: &SNAPSHOT_CANCEL_REQUESTED,
+        }
+    }
+}
+
+impl<'a> Write for CancellableWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.cancel_requested.load(Ordering::SeqCst) {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "Snapshot creation was cancelled",
+            ));
+        }
+        let len = std::cmp::min(buf.len(), SNAPSHOT_WRITE_CHUNK_SIZE);
+        self.file.write(&buf[..len])
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl<'a> Seek for CancellableWriter<'a> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
 }
 
 fn snapshot_state_to_file(
     microvm_state: &MicrovmState,
-    snapshot_path: &Path,
+    params: &CreateSnapshotParams,
     snapshot_data_version: u16,
     version_map: VersionMap,
 ) -> std::result::Result<(), CreateSnapshotError> {
     use self::CreateSnapshotError::*;
-    let mut snapshot_file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .open(snapshot_path)
-        .map_err(|e| SnapshotBackingFile("open", e))?;
+    let mut snapshot_file = match params.snapshot_fd {
+        Some(fd) => file_from_fd(fd).and_then(|file| file.set_len(0).map(|_| file)),
+        None => OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(&params.snapshot_path),
+    }
+    .map_err(|e| SnapshotBackingFile("open", e))?;
 
     let mut snapshot = Snapshot::new(version_map, snapshot_data_version);
     snapshot
-        .save(&mut snapshot_file, microvm_state)
+        .save(
+            &mut CancellableWriter::new(&mut snapshot_file),
+            microvm_state,
+        )
         .map_err(SerializeMicrovmState)?;
     snapshot_file
         .flush()
@@ -263,38 +429,156 @@ fn snapshot_state_to_file(
         .map_err(|e| SnapshotBackingFile("sync_all", e))
 }
 
-fn snapshot_memory_to_file(
-    vmm: &Vmm,
-    mem_file_path: &Path,
-    snapshot_type: &SnapshotType,
+// Copies the guest memory while the microVM keeps running, then pauses it only to copy the
+// pages dirtied in the meantime and to save its state, and resumes it. The guest memory is first
+// copied whole, then each round copies the pages dirtied during the previous one, until a round
+// copies few enough pages for the final copy to be short.
+fn create_live_snapshot(
+    vmm: &mut Vmm,
+    file: &mut File,
+    params: &CreateSnapshotParams,
+    snapshot_data_version: u16,
+    version_map: VersionMap,
 ) -> std::result::Result<(), CreateSnapshotError> {
     use self::CreateSnapshotError::*;
-    let mut file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(mem_file_path)
-        .map_err(|e| MemoryBackingFile("open", e))?;
-
-    // Set the length of the file to the full size of the memory area.
-    let mem_size_mib = mem_size_mib(vmm.guest_memory());
-    file.set_len((mem_size_mib * 1024 * 1024) as u64)
-        .map_err(|e| MemoryBackingFile("set_length", e))?;
+    // A microVM which was paused before the snapshot stays paused once it's created.
+    let was_running = vmm.instance_info().state == InstanceState::Running;
+    set_memory_file_len(vmm, file)?;
+    let mut writer = CancellableWriter::new(file);
+
+    // Getting the dirty bitmap clears it, so that it only holds the pages dirtied from now on.
+    vmm.get_dirty_bitmap().map_err(DirtyBitmap)?;
+    vmm.guest_memory().dump(&mut writer).map_err(Memory)?;
+    for _ in 0..LIVE_SNAPSHOT_MAX_ROUNDS {
+        let dirty_bitmap = vmm.get_dirty_bitmap().map_err(DirtyBitmap)?;
+        vmm.guest_memory()
+            .dump_dirty(&mut writer, &dirty_bitmap)
+            .map_err(Memory)?;
+        if dirty_page_count(&dirty_bitmap) <= LIVE_SNAPSHOT_DIRTY_PAGES_THRESHOLD {
+            break;
+        }
+    }
 
-    match snapshot_type {
-        SnapshotType::Diff => {
-            let dirty_bitmap = vmm.get_dirty_bitmap().map_err(DirtyBitmap)?;
+    vmm.pause_vm().map_err(PauseVm)?;
+    let pause_start_us = utils::time::get_time_us(utils::time::ClockType::Monotonic);
+
+    let result = vmm
+        .get_dirty_bitmap()
+        .map_err(DirtyBitmap)
+        .and_then(|dirty_bitmap| {
             vmm.guest_memory()
-                .dump_dirty(&mut file, &dirty_bitmap)
+                .dump_dirty(&mut writer, &dirty_bitmap)
                 .map_err(Memory)
+        })
+        .and_then(|_| sync_memory_file(writer.file))
+        .and_then(|_| vmm.save_state().map_err(MicrovmState))
+        .and_then(|microvm_state| {
+            // The memory is copied while the guest writes to it, so it gets no checksum.
+            snapshot_state_to_file(&microvm_state, params, snapshot_data_version, version_map)
+        });
+
+    if was_running {
+        if let Err(e) = vmm.resume_vm() {
+            error!("Failed to resume microVM after live snapshot: {}", e);
         }
-        SnapshotType::Full => vmm.guest_memory().dump(&mut file).map_err(Memory),
-    }?;
+    }
+    info!(
+        "The live snapshot paused the microVM for {} us.",
+        utils::time::get_time_us(utils::time::ClockType::Monotonic) - pause_start_us
+    );
+    result
+}
+
+// Counts the pages marked as dirty in a KVM dirty bitmap.
+fn dirty_page_count(dirty_bitmap: &DirtyBitmap) -> usize {
+    dirty_bitmap
+        .values()
+        .flat_map(|bitmap| bitmap.iter())
+        .map(|word| word.count_ones() as usize)
+        .sum()
+}
+
+// Creates the memory file, or truncates the one passed as a descriptor.
+fn open_memory_file(
+    params: &CreateSnapshotParams,
+) -> std::result::Result<File, CreateSnapshotError> {
+    match params.mem_file_fd {
+        Some(fd) => file_from_fd(fd).and_then(|file| file.set_len(0).map(|_| file)),
+        None => OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&params.mem_file_path),
+    }
+    .map_err(|e| CreateSnapshotError::MemoryBackingFile("open", e))
+}
+
+// Takes ownership of a file descriptor passed instead of a path, e.g. by a supervisor which
+// opened the file outside of the jail, and rewinds it. The descriptor is closed along with the
+// returned file. Only regular files are accepted, so that a wrong descriptor number can't take
+// away e.g. the KVM or API socket descriptors of Firecracker.
+fn file_from_fd(fd: RawFd) -> io::Result<File> {
+    // Safe because the kernel only fills in the zeroed stat structure, and we check the return
+    // value.
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if stat.st_mode & libc::S_IFMT != libc::S_IFREG {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("File descriptor {} does not refer to a regular file", fd),
+        ));
+    }
+    // Safe because the descriptor is open, and is owned by the returned file from now on.
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+// Sets the length of the memory file to the full size of the memory area.
+fn set_memory_file_len(vmm: &Vmm, file: &File) -> std::result::Result<(), CreateSnapshotError> {
+    let mem_size_mib = mem_size_mib(vmm.guest_memory());
+    file.set_len((mem_size_mib * 1024 * 1024) as u64)
+        .map_err(|e| CreateSnapshotError::MemoryBackingFile("set_length", e))
+}
+
+fn sync_memory_file(file: &mut File) -> std::result::Result<(), CreateSnapshotError> {
+    use self::CreateSnapshotError::*;
     file.flush().map_err(|e| MemoryBackingFile("flush", e))?;
     file.sync_all()
         .map_err(|e| MemoryBackingFile("sync_all", e))
 }
 
+// Returns the checksum of the memory file for full snapshots. The memory file of a diff snapshot
+// only becomes meaningful after being merged on top of a base, so it doesn't get one.
+fn snapshot_memory_to_file(
+    vmm: &Vmm,
+    file: &mut File,
+    snapshot_type: &SnapshotType,
+) -> std::result::Result<Option<u64>, CreateSnapshotError> {
+    use self::CreateSnapshotError::*;
+    set_memory_file_len(vmm, file)?;
+
+    let mut writer = CancellableWriter::new(&mut *file);
+    let mem_checksum = match snapshot_type {
+        SnapshotType::Diff => {
+            let dirty_bitmap = vmm.get_dirty_bitmap().map_err(DirtyBitmap)?;
+            vmm.guest_memory()
+                .dump_dirty(&mut writer, &dirty_bitmap)
+                .map_err(Memory)?;
+            None
+        }
+        SnapshotType::Full => {
+            let mut crc_writer = CRC64Writer::new(&mut writer);
+            vmm.guest_memory().dump(&mut crc_writer).map_err(Memory)?;
+            Some(crc_writer.checksum())
+        }
+    };
+    sync_memory_file(file)?;
+    Ok(mem_checksum)
+}
+
 /// Validate the microVM version and translate it to its corresponding snapshot data format.
 pub fn get_snapshot_data_version(
     version: &Option<String>,
@@ -378,6 +662,20 @@ pub fn validate_cpu_manufacturer_id(
 /// Performs sanity checks against the state file and returns specific errors.
 pub fn snapshot_state_sanity_check(
     microvm_state: &MicrovmState,
+) -> std::result::Result<(), LoadSnapshotError> {
+    snapshot_state_layout_check(microvm_state)?;
+
+    #[cfg(target_arch = "x86_64")]
+    validate_cpu_vendor(&microvm_state)?;
+    #[cfg(target_arch = "aarch64")]
+    validate_cpu_manufacturer_id(&microvm_state)?;
+
+    Ok(())
+}
+
+// Checks the vCPUs and the memory regions of the state file, regardless of the host.
+fn snapshot_state_layout_check(
+    microvm_state: &MicrovmState,
 ) -> std::result::Result<(), LoadSnapshotError> {
     // Check if the snapshot contains at least 1 vCPU state entry.
     if microvm_state.vcpu_states.is_empty()
@@ -397,66 +695,206 @@ pub fn snapshot_state_sanity_check(
         ));
     }
 
-    #[cfg(target_arch = "x86_64")]
-    validate_cpu_vendor(&microvm_state)?;
-    #[cfg(target_arch = "aarch64")]
-    validate_cpu_manufacturer_id(&microvm_state)?;
-
     Ok(())
 }
 
+/// Describes the microVM saved in a snapshot, e.g. to decide where to load it. Only the
+/// microVM state is read: no KVM resources or threads are created. The CPU vendor isn't
+/// checked, since the snapshot may be loaded on another host than the inspecting one.
+pub fn inspect_snapshot(
+    params: &InspectSnapshotParams,
+    version_map: VersionMap,
+) -> std::result::Result<SnapshotInfo, LoadSnapshotError> {
+    let snapshot_file = File::open(&params.snapshot_path)
+        .map_err(|e| LoadSnapshotError::SnapshotBackingFile("open", e))?;
+    let microvm_state = snapshot_state_from_file(snapshot_file, version_map)?;
+    snapshot_state_layout_check(&microvm_state)?;
+
+    let device_states = &microvm_state.device_states;
+    Ok(SnapshotInfo {
+        vcpu_count: microvm_state.vcpu_states.len(),
+        mem_size_mib: microvm_state.vm_info.mem_size_mib,
+        block_devices: device_states
+            .block_devices
+            .iter()
+            .map(|state| state.device_id.clone())
+            .collect(),
+        net_devices: device_states
+            .net_devices
+            .iter()
+            .map(|state| state.device_id.clone())
+            .collect(),
+        vsock_device: device_states
+            .vsock_device
+            .as_ref()
+            .map(|state| state.device_id.clone()),
+        balloon_device: device_states.balloon_device.is_some(),
+    })
+}
+
 /// Loads a Microvm snapshot producing a 'paused' Microvm.
+#[allow(clippy::too_many_arguments)]
 pub fn restore_from_snapshot(
     instance_info: &InstanceInfo,
     event_manager: &mut EventManager,
     seccomp_filters: &BpfThreadMap,
     params: &LoadSnapshotParams,
     version_map: VersionMap,
+    serial_config: SerialConfig,
+    resource_limits: ResourceLimits,
+    recent_errors: RecentErrors,
 ) -> std::result::Result<Arc<Mutex<Vmm>>, LoadSnapshotError> {
     use self::LoadSnapshotError::*;
     let track_dirty_pages = params.enable_diff_snapshots;
-    let microvm_state = snapshot_state_from_file(&params.snapshot_path, version_map)?;
+    let snapshot_file = match params.snapshot_fd {
+        Some(fd) => file_from_fd(fd),
+        None => File::open(&params.snapshot_path),
+    }
+    .map_err(|e| SnapshotBackingFile("open", e))?;
+    let mut microvm_state = snapshot_state_from_file(snapshot_file, version_map)?;
 
     // Some sanity checks before building the microvm.
     snapshot_state_sanity_check(&microvm_state)?;
+    remap_drive_paths(&mut microvm_state.device_states, &params.drive_paths)?;
+    let parked_vcpus = parked_vcpus_count(&microvm_state.vcpu_states, params.vcpu_count)?;
+
+    let shared = params.mem_mapping == MemoryMapping::Shared;
+    // The guest writes only reach the memory file through a shared mapping.
+    let mut mem_file = match params.mem_file_fd {
+        Some(fd) => file_from_fd(fd),
+        None => OpenOptions::new()
+            .read(true)
+            .write(shared)
+            .open(&params.mem_file_path),
+    }
+    .map_err(MemoryBackingFile)?;
+
+    if params.verify_mem_checksum {
+        verify_mem_checksum(&mut mem_file, microvm_state.vm_info.mem_checksum)?;
+    }
 
-    let guest_memory = guest_memory_from_file(
-        &params.mem_file_path,
+    let guest_memory = GuestMemoryMmap::restore(
+        &mem_file,
         &microvm_state.memory_state,
         track_dirty_pages,
-    )?;
+        shared,
+    )
+    .map_err(DeserializeMemory)?;
     builder::build_microvm_from_snapshot(
         instance_info,
         event_manager,
         microvm_state,
         guest_memory,
         track_dirty_pages,
+        params.rebase_clock,
+        params.require_tsc_scaling,
         seccomp_filters,
+        serial_config,
+        resource_limits,
+        recent_errors,
     )
+    .map(|vmm| {
+        vmm.lock().expect("Poisoned lock").park_vcpus(parked_vcpus);
+        vmm
+    })
     .map_err(BuildMicroVm)
 }
 
+// Returns how many of the snapshot vCPUs are parked to run only `vcpu_count` of them. Only the
+// last vCPUs can be parked, and only if the guest took them offline: the guest doesn't expect
+// them to run, so they are restored to keep their state in the next snapshots, but never resumed.
+fn parked_vcpus_count(
+    vcpu_states: &[VcpuState],
+    vcpu_count: Option<u8>,
+) -> std::result::Result<usize, LoadSnapshotError> {
+    use self::LoadSnapshotError::InvalidVcpuCount;
+
+    let vcpu_count = match vcpu_count {
+        Some(count) => usize::from(count),
+        None => return Ok(0),
+    };
+    if vcpu_count == 0 || vcpu_count > vcpu_states.len() {
+        return Err(InvalidVcpuCount(format!(
+            "{} vCPUs cannot be resumed from a snapshot of {} vCPUs.",
+            vcpu_count,
+            vcpu_states.len()
+        )));
+    }
+    if let Some(idx) = (vcpu_count..vcpu_states.len()).find(|&idx| !vcpu_states[idx].is_offline()) {
+        return Err(InvalidVcpuCount(format!(
+            "vCPU {} was not taken offline by the guest before the snapshot.",
+            idx
+        )));
+    }
+
+    Ok(vcpu_states.len() - vcpu_count)
+}
+
 fn snapshot_state_from_file(
-    snapshot_path: &Path,
+    mut snapshot_reader: File,
     version_map: VersionMap,
 ) -> std::result::Result<MicrovmState, LoadSnapshotError> {
-    use self::LoadSnapshotError::{DeserializeMicrovmState, SnapshotBackingFile};
-    let mut snapshot_reader =
-        File::open(snapshot_path).map_err(|e| SnapshotBackingFile("open", e))?;
-    let metadata = std::fs::metadata(snapshot_path)
+    use self::LoadSnapshotError::{
+        DeserializeMicrovmState, IntegrityCheckFailed, SnapshotBackingFile, UnsupportedVersion,
+    };
+    let metadata = snapshot_reader
+        .metadata()
         .map_err(|e| SnapshotBackingFile("metadata retrieval", e))?;
     let snapshot_len = metadata.len() as usize;
-    Snapshot::load(&mut snapshot_reader, snapshot_len, version_map).map_err(DeserializeMicrovmState)
+    // Snapshots of older data versions are translated through the version map while they
+    // are deserialized, only the newer ones can't be loaded.
+    let latest_version = version_map.latest_version();
+    Snapshot::load(&mut snapshot_reader, snapshot_len, version_map).map_err(|err| match err {
+        snapshot::Error::Crc64(_) => {
+            IntegrityCheckFailed("The microVM state doesn't match its checksum.".to_string())
+        }
+        snapshot::Error::InvalidDataVersion(version) => UnsupportedVersion(version, latest_version),
+        err => DeserializeMicrovmState(err),
+    })
 }
 
-fn guest_memory_from_file(
-    mem_file_path: &Path,
-    mem_state: &GuestMemoryState,
-    track_dirty_pages: bool,
-) -> std::result::Result<GuestMemoryMmap, LoadSnapshotError> {
-    use self::LoadSnapshotError::{DeserializeMemory, MemoryBackingFile};
-    let mem_file = File::open(mem_file_path).map_err(MemoryBackingFile)?;
-    GuestMemoryMmap::restore(&mem_file, mem_state, track_dirty_pages).map_err(DeserializeMemory)
+/// Points the drives to their new backing files, then checks that the backing files of all
+/// the drives can be accessed, so that a missing one is reported by its drive ID.
+fn remap_drive_paths(
+    device_states: &mut DeviceStates,
+    drive_paths: &HashMap<String, String>,
+) -> std::result::Result<(), LoadSnapshotError> {
+    use self::LoadSnapshotError::{DriveBackingFile, UnknownDrive};
+    for (drive_id, path) in drive_paths {
+        let block_state = device_states
+            .block_devices
+            .iter_mut()
+            .find(|state| &state.device_id == drive_id)
+            .ok_or_else(|| UnknownDrive(drive_id.clone()))?;
+        block_state.device_state.set_disk_path(path.clone());
+    }
+
+    for block_state in &device_states.block_devices {
+        let path = block_state.device_state.disk_path();
+        std::fs::metadata(path)
+            .map_err(|e| DriveBackingFile(block_state.device_id.clone(), path.to_string(), e))?;
+    }
+    Ok(())
+}
+
+/// Checks that the memory file matches the checksum saved in the microVM state.
+fn verify_mem_checksum(
+    mem_file: &mut File,
+    mem_checksum: Option<u64>,
+) -> std::result::Result<(), LoadSnapshotError> {
+    use self::LoadSnapshotError::{IntegrityCheckFailed, MemoryBackingFile};
+    let expected_checksum = mem_checksum.ok_or_else(|| {
+        IntegrityCheckFailed("The snapshot has no memory file checksum.".to_string())
+    })?;
+
+    let mut crc_reader = CRC64Reader::new(mem_file);
+    io::copy(&mut crc_reader, &mut io::sink()).map_err(MemoryBackingFile)?;
+    if crc_reader.checksum() != expected_checksum {
+        return Err(IntegrityCheckFailed(
+            "The memory file doesn't match its checksum.".to_string(),
+        ));
+    }
+    Ok(())
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -500,6 +938,9 @@ mod tests {
     use crate::vmm_config::vsock::tests::default_config;
     use crate::Vmm;
 
+    use std::io::Read;
+    use std::os::unix::io::{AsRawFd, IntoRawFd};
+
     use snapshot::Persist;
     use utils::{errno, tempfile::TempFile};
 
@@ -516,6 +957,7 @@ mod tests {
             amount_mib: 0,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            free_page_reporting: false,
         };
         insert_balloon_device(&mut vmm, &mut cmdline, &mut event_manager, balloon_config);
 
@@ -538,6 +980,11 @@ mod tests {
             rx_rate_limiter: None,
             tx_rate_limiter: None,
             allow_mmds_requests: true,
+            mmds_allowed_sources: Vec::new(),
+            enable_offload: true,
+            mtu: None,
+            pcap_path: None,
+            interrupt_coalescing_us: 0,
         };
         insert_net_device(
             &mut vmm,
@@ -576,7 +1023,10 @@ mod tests {
             device_states: states,
             memory_state,
             vcpu_states,
-            vm_info: VmInfo { mem_size_mib: 1u64 },
+            vm_info: VmInfo {
+                mem_size_mib: 1u64,
+                mem_checksum: Some(1234),
+            },
             #[cfg(target_arch = "aarch64")]
             vm_state: vmm.vm.save_state(&mpidrs).unwrap(),
             #[cfg(target_arch = "x86_64")]
@@ -604,7 +1054,124 @@ mod tests {
         assert_eq!(
             restored_microvm_state.device_states,
             microvm_state.device_states
-        )
+        );
+
+        // The memory file checksum is not saved in older versions.
+        let mut buf = vec![0; 10000];
+        microvm_state
+            .vm_info
+            .serialize(&mut buf.as_mut_slice(), &version_map, 2)
+            .unwrap();
+        let restored_vm_info = VmInfo::deserialize(&mut buf.as_slice(), &version_map, 2).unwrap();
+        assert_eq!(restored_vm_info.mem_size_mib, 1);
+        assert_eq!(restored_vm_info.mem_checksum, None);
+
+        version_map
+            .new_version()
+            .set_type_version(VmInfo::type_id(), 2);
+        microvm_state
+            .vm_info
+            .serialize(&mut buf.as_mut_slice(), &version_map, 3)
+            .unwrap();
+        let restored_vm_info = VmInfo::deserialize(&mut buf.as_slice(), &version_map, 3).unwrap();
+        assert_eq!(restored_vm_info, microvm_state.vm_info);
+    }
+
+    #[test]
+    fn test_inspect_snapshot() {
+        let vmm = default_vmm_with_devices();
+        let device_states = vmm.mmio_device_manager.save();
+        let vcpu_states = vec![VcpuState::default(), VcpuState::default()];
+        #[cfg(target_arch = "aarch64")]
+        let mpidrs = construct_kvm_mpidrs(&vcpu_states);
+        let mut microvm_state = MicrovmState {
+            device_states,
+            memory_state: vmm.guest_memory().describe(),
+            vcpu_states,
+            vm_info: VmInfo {
+                mem_size_mib: 1u64,
+                mem_checksum: None,
+            },
+            #[cfg(target_arch = "aarch64")]
+            vm_state: vmm.vm.save_state(&mpidrs).unwrap(),
+            #[cfg(target_arch = "x86_64")]
+            vm_state: vmm.vm.save_state().unwrap(),
+        };
+
+        let snapshot_file = TempFile::new().unwrap();
+        let params = InspectSnapshotParams {
+            snapshot_path: snapshot_file.as_path().to_path_buf(),
+        };
+        let mut snapshot = Snapshot::new(VERSION_MAP.clone(), VERSION_MAP.latest_version());
+        snapshot
+            .save(&mut snapshot_file.as_file(), &microvm_state)
+            .unwrap();
+
+        let info = inspect_snapshot(&params, VERSION_MAP.clone()).unwrap();
+        assert_eq!(info.vcpu_count, 2);
+        assert_eq!(info.mem_size_mib, 1);
+        assert_eq!(
+            info.block_devices,
+            vec![microvm_state.device_states.block_devices[0]
+                .device_id
+                .clone()]
+        );
+        assert_eq!(
+            info.net_devices,
+            vec![microvm_state.device_states.net_devices[0].device_id.clone()]
+        );
+        assert_eq!(
+            info.vsock_device,
+            microvm_state
+                .device_states
+                .vsock_device
+                .as_ref()
+                .map(|state| state.device_id.clone())
+        );
+        assert!(info.balloon_device);
+
+        // A snapshot without vCPUs is invalid.
+        microvm_state.vcpu_states.clear();
+        let snapshot_file = TempFile::new().unwrap();
+        let params = InspectSnapshotParams {
+            snapshot_path: snapshot_file.as_path().to_path_buf(),
+        };
+        snapshot
+            .save(&mut snapshot_file.as_file(), &microvm_state)
+            .unwrap();
+        match inspect_snapshot(&params, VERSION_MAP.clone()) {
+            Err(LoadSnapshotError::InvalidSnapshot(_)) => (),
+            _ => panic!("Expected an invalid snapshot error."),
+        }
+
+        // A snapshot of a newer data version can't be loaded.
+        let mut newer_version_map = VERSION_MAP.clone();
+        newer_version_map.new_version();
+        let newer_version = newer_version_map.latest_version();
+        let mut snapshot = Snapshot::new(newer_version_map, newer_version);
+        let snapshot_file = TempFile::new().unwrap();
+        let params = InspectSnapshotParams {
+            snapshot_path: snapshot_file.as_path().to_path_buf(),
+        };
+        snapshot
+            .save(&mut snapshot_file.as_file(), &microvm_state)
+            .unwrap();
+        match inspect_snapshot(&params, VERSION_MAP.clone()) {
+            Err(LoadSnapshotError::UnsupportedVersion(version, latest_version)) => {
+                assert_eq!(version, newer_version);
+                assert_eq!(latest_version, VERSION_MAP.latest_version());
+            }
+            _ => panic!("Expected an unsupported version error."),
+        }
+
+        // The snapshot file must exist.
+        let params = InspectSnapshotParams {
+            snapshot_path: "/invalid/snapshot".into(),
+        };
+        match inspect_snapshot(&params, VERSION_MAP.clone()) {
+            Err(LoadSnapshotError::SnapshotBackingFile("open", _)) => (),
+            _ => panic!("Expected a snapshot file error."),
+        }
     }
 
     #[test]
@@ -647,11 +1214,110 @@ mod tests {
         assert!(get_snapshot_data_version(&Some("0.24.0".to_string()), &VERSION_MAP, &vmm).is_ok());
     }
 
+    #[test]
+    fn test_cancellable_writer() {
+        let tmp = TempFile::new().unwrap();
+        let mut file = tmp.into_file();
+        // A flag of its own, as the other tests create snapshots in parallel.
+        let cancel_requested = AtomicBool::new(false);
+        let mut writer = CancellableWriter {
+            file: &mut file,
+            cancel_requested: &cancel_requested,
+        };
+
+        assert_eq!(writer.write(&[0u8; 16]).unwrap(), 16);
+        // Writes are split into chunks.
+        let buf = vec![0u8; SNAPSHOT_WRITE_CHUNK_SIZE + 1];
+        assert_eq!(writer.write(&buf).unwrap(), SNAPSHOT_WRITE_CHUNK_SIZE);
+
+        cancel_requested.store(true, Ordering::SeqCst);
+        assert!(writer.write(&[0u8; 16]).is_err());
+        cancel_requested.store(false, Ordering::SeqCst);
+        assert_eq!(writer.write(&[0u8; 16]).unwrap(), 16);
+    }
+
+    #[test]
+    fn test_remove_snapshot_files() {
+        let snapshot_file = TempFile::new().unwrap();
+        let mem_file = TempFile::new().unwrap();
+        let mut params = CreateSnapshotParams {
+            snapshot_type: SnapshotType::Full,
+            snapshot_path: snapshot_file.as_path().to_path_buf(),
+            snapshot_fd: None,
+            mem_file_path: mem_file.as_path().to_path_buf(),
+            mem_file_fd: None,
+            version: None,
+            live: false,
+        };
+
+        remove_snapshot_files(&params);
+        assert!(!params.snapshot_path.exists());
+        assert!(!params.mem_file_path.exists());
+        // Missing files are skipped.
+        remove_snapshot_files(&params);
+
+        // The files passed as descriptors are left to the caller.
+        let mem_file = TempFile::new().unwrap();
+        params.mem_file_path = mem_file.as_path().to_path_buf();
+        params.mem_file_fd = Some(mem_file.as_file().as_raw_fd());
+        remove_snapshot_files(&params);
+        assert!(params.mem_file_path.exists());
+    }
+
+    #[test]
+    fn test_verify_mem_checksum() {
+        let tmp = TempFile::new().unwrap();
+        tmp.as_file().write_all(&[0xAAu8; 4096]).unwrap();
+
+        let mut crc_writer = CRC64Writer::new(io::sink());
+        crc_writer.write_all(&[0xAAu8; 4096]).unwrap();
+        let checksum = crc_writer.checksum();
+
+        let mem_file = || File::open(tmp.as_path()).unwrap();
+        assert!(verify_mem_checksum(&mut mem_file(), Some(checksum)).is_ok());
+        // Snapshots without a memory file checksum can't be verified.
+        match verify_mem_checksum(&mut mem_file(), None) {
+            Err(LoadSnapshotError::IntegrityCheckFailed(_)) => (),
+            _ => panic!("Verification should fail."),
+        }
+
+        // Alter one byte of the memory file.
+        tmp.as_file().seek(SeekFrom::Start(100)).unwrap();
+        tmp.as_file().write_all(&[0xABu8]).unwrap();
+        match verify_mem_checksum(&mut mem_file(), Some(checksum)) {
+            Err(LoadSnapshotError::IntegrityCheckFailed(_)) => (),
+            _ => panic!("Verification should fail."),
+        }
+    }
+
+    #[test]
+    fn test_file_from_fd() {
+        let tmp = TempFile::new().unwrap();
+        tmp.as_file().write_all(&[0xAAu8; 16]).unwrap();
+
+        // The file is rewound, and its descriptor is owned by the returned file.
+        let mut file = file_from_fd(File::open(tmp.as_path()).unwrap().into_raw_fd()).unwrap();
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, vec![0xAAu8; 16]);
+
+        // Only regular files are accepted.
+        let dir = File::open("/tmp").unwrap();
+        assert_eq!(
+            file_from_fd(dir.as_raw_fd()).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert!(file_from_fd(-1).is_err());
+    }
+
     #[test]
     fn test_create_snapshot_error_display() {
         use crate::persist::CreateSnapshotError::*;
         use vm_memory::GuestMemoryError;
 
+        let err = Cancelled;
+        let _ = format!("{}{:?}", err, err);
+
         let err = DirtyBitmap(VmmError::DirtyBitmap(kvm_ioctls::Error::new(20)));
         let _ = format!("{}{:?}", err, err);
 
@@ -685,6 +1351,57 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_remap_drive_paths() {
+        let vmm = default_vmm_with_devices();
+        let mut states = vmm.mmio_device_manager.save();
+        // The backing file of the drive was removed along with the test microVM's files.
+        match remap_drive_paths(&mut states, &HashMap::new()) {
+            Err(LoadSnapshotError::DriveBackingFile(drive_id, _, _)) => {
+                assert_eq!(drive_id, "root")
+            }
+            _ => unreachable!(),
+        }
+
+        let new_file = TempFile::new().unwrap();
+        let new_path = new_file.as_path().to_str().unwrap().to_string();
+        let mut drive_paths = HashMap::new();
+        drive_paths.insert("root".to_string(), new_path.clone());
+        remap_drive_paths(&mut states, &drive_paths).unwrap();
+        assert_eq!(states.block_devices[0].device_state.disk_path(), new_path);
+
+        drive_paths.insert("data".to_string(), new_path);
+        match remap_drive_paths(&mut states, &drive_paths) {
+            Err(LoadSnapshotError::UnknownDrive(drive_id)) => assert_eq!(drive_id, "data"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_parked_vcpus_count() {
+        let vcpu_states = vec![VcpuState::default(); 2];
+        assert_eq!(parked_vcpus_count(&vcpu_states, None).unwrap(), 0);
+        assert_eq!(parked_vcpus_count(&vcpu_states, Some(2)).unwrap(), 0);
+
+        // The vCPU count can only be reduced.
+        match parked_vcpus_count(&vcpu_states, Some(0)) {
+            Err(LoadSnapshotError::InvalidVcpuCount(_)) => (),
+            _ => unreachable!(),
+        }
+        match parked_vcpus_count(&vcpu_states, Some(3)) {
+            Err(LoadSnapshotError::InvalidVcpuCount(_)) => (),
+            _ => unreachable!(),
+        }
+        // The guest didn't take the second vCPU offline.
+        match parked_vcpus_count(&vcpu_states, Some(1)) {
+            Err(LoadSnapshotError::InvalidVcpuCount(err)) => assert_eq!(
+                err,
+                "vCPU 1 was not taken offline by the guest before the snapshot."
+            ),
+            _ => unreachable!(),
+        }
+    }
+
     #[test]
     fn test_load_snapshot_error_display() {
         use crate::persist::LoadSnapshotError::*;
@@ -708,6 +1425,25 @@ mod tests {
 
         let err = CpuVendorCheck(String::new());
         let _ = format!("{}{:?}", err, err);
+
+        let err = IntegrityCheckFailed(String::new());
+        let _ = format!("{}{:?}", err, err);
+
+        let err = UnsupportedVersion(4, 3);
+        let _ = format!("{}{:?}", err, err);
+
+        let err = UnknownDrive(String::new());
+        let _ = format!("{}{:?}", err, err);
+
+        let err = DriveBackingFile(
+            String::new(),
+            String::new(),
+            io::Error::from_raw_os_error(0),
+        );
+        let _ = format!("{}{:?}", err, err);
+
+        let err = InvalidVcpuCount(String::new());
+        let _ = format!("{}{:?}", err, err);
     }
 
     #[test]
@@ -717,6 +1453,9 @@ mod tests {
         let err = InvalidInput;
         let _ = format!("{}{:?}", err, err);
 
+        let err = InvalidVcpuId(0);
+        let _ = format!("{}{:?}", err, err);
+
         let err = NotAllowed(String::from(""));
         let _ = format!("{}{:?}", err, err);
 
@@ -738,6 +1477,9 @@ mod tests {
         let err = SignalVcpu(vstate::vcpu::Error::SignalVcpu(errno::Error::new(0)));
         let _ = format!("{}{:?}", err, err);
 
+        let err = SyncBlockDevice("rootfs".to_string(), io::Error::from_raw_os_error(0));
+        let _ = format!("{}{:?}", err, err);
+
         let err = UnexpectedVcpuResponse;
         let _ = format!("{}{:?}", err, err);
     }
@@ -0,0 +1,127 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use logger::Logger;
+use serde::Serialize;
+use utils::time::{get_time_us, ClockType};
+
+/// The number of errors kept by a `RecentErrors` log.
+pub const MAX_RECENT_ERRORS: usize = 16;
+
+/// An error hit by the VMM, as kept by a `RecentErrors` log.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RecentError {
+    /// When the error was hit, in microseconds since the Unix epoch.
+    pub timestamp_us: u64,
+    /// The description of the error.
+    pub message: String,
+}
+
+/// Bounded in-memory log of the latest errors hit by the VMM, which complements the logger for
+/// post-mortem debugging.
+///
+/// Clones share the same log, so errors can be recorded from any holder of a clone.
+#[derive(Clone, Default)]
+pub struct RecentErrors {
+    errors: Arc<Mutex<VecDeque<RecentError>>>,
+}
+
+impl RecentErrors {
+    /// Appends an error described by `message`, dropping the oldest one if the log is full.
+    pub fn record(&self, message: String) {
+        let mut errors = self.errors.lock().expect("Poisoned lock");
+        if errors.len() == MAX_RECENT_ERRORS {
+            errors.pop_front();
+        }
+        errors.push_back(RecentError {
+            timestamp_us: get_time_us(ClockType::Real),
+            message,
+        });
+    }
+
+    /// Records every error reported through `logger` from now on, such as the failed API requests
+    /// and the errors hit by the event loop and the devices.
+    pub fn record_logged_errors(&self, logger: &Logger) {
+        let recent_errors = self.clone();
+        logger.set_error_hook(Box::new(move |record| {
+            recent_errors.record(record.args().to_string())
+        }));
+    }
+
+    /// Provides the errors currently held by the log, oldest first.
+    pub fn list(&self) -> Vec<RecentError> {
+        self.errors
+            .lock()
+            .expect("Poisoned lock")
+            .iter()
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use logger::{Level, Log, Record};
+
+    #[test]
+    fn test_recent_errors() {
+        let recent_errors = RecentErrors::default();
+        assert!(recent_errors.list().is_empty());
+
+        // Clones share the log.
+        recent_errors.clone().record("first".to_string());
+        let errors = recent_errors.list();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "first");
+        assert!(errors[0].timestamp_us > 0);
+
+        // The oldest errors are dropped when the log is full.
+        for i in 0..MAX_RECENT_ERRORS {
+            recent_errors.record(i.to_string());
+        }
+        let errors = recent_errors.list();
+        assert_eq!(errors.len(), MAX_RECENT_ERRORS);
+        assert_eq!(errors[0].message, "0");
+        assert_eq!(
+            errors[MAX_RECENT_ERRORS - 1].message,
+            (MAX_RECENT_ERRORS - 1).to_string()
+        );
+        assert!(errors[0].timestamp_us <= errors[MAX_RECENT_ERRORS - 1].timestamp_us);
+
+        assert_eq!(
+            serde_json::to_string(&errors[0]).unwrap(),
+            format!(
+                "{{\"timestamp_us\":{},\"message\":\"0\"}}",
+                errors[0].timestamp_us
+            )
+        );
+    }
+
+    #[test]
+    fn test_record_logged_errors() {
+        // A local logger, as the errors logged by the other tests go through the global one.
+        let logger = Logger::default();
+        let recent_errors = RecentErrors::default();
+        recent_errors.record_logged_errors(&logger);
+
+        logger.log(
+            &Record::builder()
+                .args(format_args!("logged warning"))
+                .level(Level::Warn)
+                .build(),
+        );
+        logger.log(
+            &Record::builder()
+                .args(format_args!("logged error"))
+                .level(Level::Error)
+                .build(),
+        );
+        let errors = recent_errors.list();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "logged error");
+    }
+}
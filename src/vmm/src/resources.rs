@@ -3,22 +3,33 @@
 
 #![deny(warnings)]
 
+use crate::recent_errors::RecentErrors;
 use crate::vmm_config::balloon::*;
-use crate::vmm_config::boot_source::{BootConfig, BootSourceConfig, BootSourceConfigError};
+use crate::vmm_config::boot_source::{
+    BootConfig, BootSourceConfig, BootSourceConfigError, BootSourceUpdateConfig,
+};
 use crate::vmm_config::drive::*;
+use crate::vmm_config::fs::*;
 use crate::vmm_config::instance_info::InstanceInfo;
 use crate::vmm_config::logger::{init_logger, LoggerConfig, LoggerConfigError};
-use crate::vmm_config::machine_config::{VmConfig, VmConfigError, DEFAULT_MEM_SIZE_MIB};
+use crate::vmm_config::machine_config::{
+    validate_host_memory, validate_mem_regions, validate_vcpu_affinity, VmConfig, VmConfigError,
+    DEFAULT_MEM_SIZE_MIB,
+};
 use crate::vmm_config::metrics::{init_metrics, MetricsConfig, MetricsConfigError};
 use crate::vmm_config::mmds::{MmdsConfig, MmdsConfigError};
 use crate::vmm_config::net::*;
+use crate::vmm_config::resource_limits::ResourceLimits;
+use crate::vmm_config::serial::{ConsoleType, SerialConfig};
 use crate::vmm_config::vsock::*;
+use crate::vmm_config::MAX_DEVICE_IRQS;
 use crate::vstate::vcpu::VcpuConfig;
 use mmds::ns::MmdsNetworkStack;
-use utils::net::ipv4addr::is_link_local_valid;
+use utils::net::{ipv4addr, ipv6addr};
 
 use serde::{Deserialize, Serialize};
 use std::convert::From;
+use std::fmt::{Display, Formatter};
 
 type Result<E> = std::result::Result<(), E>;
 
@@ -31,6 +42,8 @@ pub enum Error {
     BlockDevice(DriveError),
     /// Boot source configuration error.
     BootSource(BootSourceConfigError),
+    /// Virtio-fs device configuration error.
+    FsDevice(FsConfigError),
     /// JSON is invalid.
     InvalidJson,
     /// Logger configuration error.
@@ -41,12 +54,41 @@ pub enum Error {
     MmdsConfig(MmdsConfigError),
     /// Net device configuration error.
     NetDevice(NetworkInterfaceError),
+    /// The logger or metrics, which configure the whole process, are part of a microVM
+    /// configuration provided at runtime.
+    ProcessConfigNotAllowed,
     /// microVM vCpus or memory configuration error.
     VmConfig(VmConfigError),
     /// Vsock device configuration error.
     VsockDevice(VsockConfigError),
 }
 
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        use self::Error::*;
+
+        // The sections are named after the keys of the JSON configuration.
+        match self {
+            BalloonDevice(err) => write!(f, "Invalid \"balloon\" section: {}", err),
+            BlockDevice(err) => write!(f, "Invalid \"drives\" section: {}", err),
+            BootSource(err) => write!(f, "Invalid \"boot-source\" section: {}", err),
+            FsDevice(err) => write!(f, "Invalid \"fs\" section: {}", err),
+            InvalidJson => write!(f, "Invalid JSON configuration."),
+            Logger(err) => write!(f, "Invalid \"logger\" section: {}", err),
+            Metrics(err) => write!(f, "Invalid \"metrics\" section: {}", err),
+            MmdsConfig(err) => write!(f, "Invalid \"mmds-config\" section: {}", err),
+            NetDevice(err) => write!(f, "Invalid \"network-interfaces\" section: {}", err),
+            ProcessConfigNotAllowed => write!(
+                f,
+                "The \"logger\" and \"metrics\" sections are not allowed, they are configured \
+                 through their own requests."
+            ),
+            VmConfig(err) => write!(f, "Invalid \"machine-config\" section: {}", err),
+            VsockDevice(err) => write!(f, "Invalid \"vsock\" section: {}", err),
+        }
+    }
+}
+
 /// Used for configuring a vmm from one single json passed to the Firecracker process.
 #[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct VmmConfig {
@@ -56,6 +98,8 @@ pub struct VmmConfig {
     block_devices: Vec<BlockDeviceConfig>,
     #[serde(rename = "boot-source")]
     boot_source: BootSourceConfig,
+    #[serde(rename = "fs", default)]
+    fs_devices: Vec<FsDeviceConfig>,
     #[serde(rename = "logger")]
     logger: Option<LoggerConfig>,
     #[serde(rename = "machine-config")]
@@ -80,6 +124,8 @@ pub struct VmResources {
     boot_config: Option<BootConfig>,
     /// The block devices.
     pub block: BlockBuilder,
+    /// The virtio-fs devices.
+    pub fs: FsBuilder,
     /// The vsock device.
     pub vsock: VsockBuilder,
     /// The balloon device.
@@ -90,6 +136,16 @@ pub struct VmResources {
     pub mmds_config: Option<MmdsConfig>,
     /// Whether or not to load boot timer device.
     pub boot_timer: bool,
+    /// Time limit in milliseconds for starting the microVM, if any.
+    pub boot_timeout_ms: Option<u64>,
+    /// Whether the vCPUs are left paused once the microVM is started.
+    pub start_paused: bool,
+    /// The serial console configuration.
+    pub serial_config: SerialConfig,
+    /// The resource limits of the Firecracker process.
+    pub resource_limits: ResourceLimits,
+    /// The log of the latest errors hit by the VMM, shared with the Vmm once built.
+    pub recent_errors: RecentErrors,
 }
 
 impl VmResources {
@@ -98,17 +154,40 @@ impl VmResources {
         config_json: &str,
         instance_info: &InstanceInfo,
     ) -> std::result::Result<Self, Error> {
-        let vmm_config: VmmConfig = serde_json::from_slice::<VmmConfig>(config_json.as_bytes())
+        let mut vmm_config: VmmConfig = serde_json::from_slice::<VmmConfig>(config_json.as_bytes())
             .map_err(|_| Error::InvalidJson)?;
 
-        if let Some(logger) = vmm_config.logger {
+        if let Some(logger) = vmm_config.logger.take() {
             init_logger(logger, instance_info).map_err(Error::Logger)?;
         }
 
-        if let Some(metrics) = vmm_config.metrics {
+        if let Some(metrics) = vmm_config.metrics.take() {
             init_metrics(metrics).map_err(Error::Metrics)?;
         }
 
+        Self::from_vmm_config(vmm_config)
+    }
+
+    /// Replaces the whole microVM configuration with the one described by `vmm_config`, keeping
+    /// the settings provided through the command line. Nothing is changed if any section of
+    /// `vmm_config` is invalid.
+    pub fn set_full_vm_config(&mut self, vmm_config: VmmConfig) -> Result<Error> {
+        let mut resources = Self::from_vmm_config(vmm_config)?;
+        resources.boot_timer = self.boot_timer;
+        resources.boot_timeout_ms = self.boot_timeout_ms;
+        resources.serial_config = self.serial_config.clone();
+        resources.resource_limits = self.resource_limits;
+        *self = resources;
+        Ok(())
+    }
+
+    // Builds the resources described by `vmm_config`, which can't hold the logger and metrics
+    // sections since they configure the whole process.
+    fn from_vmm_config(vmm_config: VmmConfig) -> std::result::Result<Self, Error> {
+        if vmm_config.logger.is_some() || vmm_config.metrics.is_some() {
+            return Err(Error::ProcessConfigNotAllowed);
+        }
+
         let mut resources: Self = Self::default();
         if let Some(machine_config) = vmm_config.machine_config {
             resources
@@ -132,6 +211,12 @@ impl VmResources {
                 .map_err(Error::NetDevice)?;
         }
 
+        for fs_config in vmm_config.fs_devices.into_iter() {
+            resources
+                .set_fs_device(fs_config)
+                .map_err(Error::FsDevice)?;
+        }
+
         if let Some(vsock_config) = vmm_config.vsock_device {
             resources
                 .set_vsock_device(vsock_config)
@@ -161,6 +246,7 @@ impl VmResources {
             vcpu_count: self.vm_config().vcpu_count.unwrap(),
             ht_enabled: self.vm_config().ht_enabled.unwrap(),
             cpu_template: self.vm_config().cpu_template,
+            interrupt_controller: self.vm_config().interrupt_controller,
         }
     }
 
@@ -219,6 +305,64 @@ impl VmResources {
             return Err(VmConfigError::InvalidVcpuCount);
         }
 
+        // The affinity has to match the vcpu count, even if only the latter changes.
+        let vcpu_affinity = machine_config
+            .vcpu_affinity
+            .as_ref()
+            .or_else(|| self.vm_config.vcpu_affinity.as_ref());
+        if let Some(vcpu_affinity) = vcpu_affinity {
+            validate_vcpu_affinity(vcpu_affinity, vcpu_count_value)?;
+        }
+
+        // Likewise, the memory layout has to match the memory size.
+        let mem_regions = machine_config
+            .mem_regions
+            .as_ref()
+            .or_else(|| self.vm_config.mem_regions.as_ref());
+        if let Some(mem_regions) = mem_regions {
+            let mem_size_mib = machine_config
+                .mem_size_mib
+                .or(self.vm_config.mem_size_mib)
+                .unwrap_or(DEFAULT_MEM_SIZE_MIB);
+            validate_mem_regions(mem_regions, mem_size_mib)?;
+        }
+
+        if machine_config.max_descriptors_per_event == Some(0) {
+            return Err(VmConfigError::InvalidMaxDescriptorsPerEvent);
+        }
+
+        if machine_config.max_devices_per_irq == Some(0) {
+            return Err(VmConfigError::InvalidMaxDevicesPerIrq);
+        }
+
+        if let Some(interrupt_controller) = machine_config.interrupt_controller {
+            if !interrupt_controller.is_available() {
+                return Err(VmConfigError::InvalidInterruptController(
+                    interrupt_controller,
+                ));
+            }
+        }
+
+        if let Some(smbios) = machine_config.smbios.as_ref() {
+            smbios.validate()?;
+        }
+
+        if cfg!(target_arch = "x86_64") && machine_config.rtc_base_time.is_some() {
+            return Err(VmConfigError::UnsupportedRtcBaseTime);
+        }
+
+        // The host memory is only checked when the memory size changes, unless overcommit is
+        // allowed.
+        let allow_mem_overcommit = machine_config
+            .allow_mem_overcommit
+            .or(self.vm_config.allow_mem_overcommit)
+            .unwrap_or(false);
+        if let Some(mem_size_mib) = machine_config.mem_size_mib {
+            if !allow_mem_overcommit && Some(mem_size_mib) != self.vm_config.mem_size_mib {
+                validate_host_memory(mem_size_mib)?;
+            }
+        }
+
         // Update all the fields that have a new value.
         self.vm_config.vcpu_count = Some(vcpu_count_value);
         self.vm_config.ht_enabled = Some(ht_enabled);
@@ -232,6 +376,64 @@ impl VmResources {
             self.vm_config.cpu_template = machine_config.cpu_template;
         }
 
+        if machine_config.vcpu_affinity.is_some() {
+            self.vm_config.vcpu_affinity = machine_config.vcpu_affinity.clone();
+        }
+
+        if machine_config.mem_regions.is_some() {
+            self.vm_config.mem_regions = machine_config.mem_regions.clone();
+        }
+
+        if machine_config.allow_mem_overcommit.is_some() {
+            self.vm_config.allow_mem_overcommit = machine_config.allow_mem_overcommit;
+        }
+
+        if machine_config.mem_backing_file.is_some() {
+            self.vm_config.mem_backing_file = machine_config.mem_backing_file.clone();
+        }
+
+        if machine_config.on_reboot.is_some() {
+            self.vm_config.on_reboot = machine_config.on_reboot;
+        }
+
+        if machine_config.reset_action.is_some() {
+            self.vm_config.reset_action = machine_config.reset_action;
+        }
+
+        if machine_config.panic_action.is_some() {
+            self.vm_config.panic_action = machine_config.panic_action;
+        }
+
+        if machine_config.rtc_base_time.is_some() {
+            self.vm_config.rtc_base_time = machine_config.rtc_base_time;
+        }
+
+        if machine_config.max_descriptors_per_event.is_some() {
+            self.vm_config.max_descriptors_per_event = machine_config.max_descriptors_per_event;
+        }
+
+        if machine_config.max_devices_per_irq.is_some() {
+            self.vm_config.max_devices_per_irq = machine_config.max_devices_per_irq;
+        }
+
+        if machine_config.interrupt_controller.is_some() {
+            self.vm_config.interrupt_controller = machine_config.interrupt_controller;
+        }
+
+        if machine_config.smbios.is_some() {
+            self.vm_config.smbios = machine_config.smbios.clone();
+        }
+
+        if machine_config.shutdown_grace_period_ms.is_some() {
+            self.vm_config.shutdown_grace_period_ms = machine_config.shutdown_grace_period_ms;
+        }
+        if machine_config.guest_snapshot.is_some() {
+            self.vm_config.guest_snapshot = machine_config.guest_snapshot.clone();
+        }
+        if machine_config.zero_mem_on_shutdown.is_some() {
+            self.vm_config.zero_mem_on_shutdown = machine_config.zero_mem_on_shutdown;
+        }
+
         Ok(())
     }
 
@@ -269,6 +471,27 @@ impl VmResources {
         Ok(())
     }
 
+    /// Updates the kernel image and/or the boot arguments of the guest boot source configuration.
+    /// The configuration is left unchanged if the update is not valid.
+    pub fn update_boot_source(
+        &mut self,
+        update_cfg: BootSourceUpdateConfig,
+    ) -> Result<BootSourceConfigError> {
+        let mut boot_source_cfg = self
+            .boot_config
+            .as_ref()
+            .map(BootSourceConfig::from)
+            .ok_or(BootSourceConfigError::MissingBootSource)?;
+        if let Some(kernel_image_path) = update_cfg.kernel_image_path {
+            boot_source_cfg.kernel_image_path = kernel_image_path;
+        }
+        if let Some(boot_args) = update_cfg.boot_args {
+            boot_source_cfg.boot_args = Some(boot_args);
+        }
+
+        self.set_boot_source(boot_source_cfg)
+    }
+
     /// Inserts a block to be attached when the VM starts.
     // Only call this function as part of user configuration.
     // If the drive_id does not exist, a new Block Device Config is added to the list.
@@ -276,53 +499,193 @@ impl VmResources {
         &mut self,
         block_device_config: BlockDeviceConfig,
     ) -> Result<DriveError> {
+        let is_new = !self
+            .block
+            .list
+            .iter()
+            .any(|b| b.lock().expect("Poisoned lock").id() == &block_device_config.drive_id);
+        if is_new && !self.has_free_device_irq() {
+            return Err(DriveError::TooManyDevices);
+        }
         self.block.insert(block_device_config)
     }
 
+    /// Updates a block to be attached when the VM starts. Only the fields which are set in
+    /// `update_cfg` are changed.
+    pub fn update_block_device(
+        &mut self,
+        update_cfg: BlockDeviceUpdateConfig,
+    ) -> Result<DriveError> {
+        let mut block_device_cfg = self
+            .block
+            .configs()
+            .into_iter()
+            .find(|cfg| cfg.drive_id == update_cfg.drive_id)
+            .ok_or(DriveError::DriveNotFound(update_cfg.drive_id))?;
+        if let Some(path_on_host) = update_cfg.path_on_host {
+            block_device_cfg.path_on_host = path_on_host;
+        }
+        if let Some(update_rate_limiter) = update_cfg.rate_limiter {
+            // Like for a running device, only the token buckets which are set are updated.
+            let mut rate_limiter = block_device_cfg.rate_limiter.unwrap_or_default();
+            if update_rate_limiter.bandwidth.is_some() {
+                rate_limiter.bandwidth = update_rate_limiter.bandwidth;
+            }
+            if update_rate_limiter.ops.is_some() {
+                rate_limiter.ops = update_rate_limiter.ops;
+            }
+            block_device_cfg.rate_limiter = Some(rate_limiter);
+        }
+
+        self.block.insert(block_device_cfg)
+    }
+
     /// Builds a network device to be attached when the VM starts.
     pub fn build_net_device(
         &mut self,
         body: NetworkInterfaceConfig,
     ) -> Result<NetworkInterfaceError> {
+        let is_new = !self
+            .net_builder
+            .iter()
+            .any(|net| net.lock().expect("Poisoned lock").id() == &body.iface_id);
+        if is_new && !self.has_free_device_irq() {
+            return Err(NetworkInterfaceError::TooManyDevices);
+        }
         self.net_builder.build(body).map(|net_device| {
-            // Update `Net` device `MmdsNetworkStack` IPv4 address.
+            // Update `Net` device `MmdsNetworkStack` IP addresses.
             match &self.mmds_config {
-                Some(cfg) => cfg.ipv4_addr().map_or((), |ipv4_addr| {
-                    net_device
-                        .lock()
-                        .expect("Poisoned lock")
-                        .set_mmds_ipv4_addr(ipv4_addr);
-                }),
+                Some(cfg) => {
+                    let mut net_device = net_device.lock().expect("Poisoned lock");
+                    if let Some(ipv4_addr) = cfg.ipv4_addr() {
+                        net_device.set_mmds_ipv4_addr(ipv4_addr);
+                    }
+                    net_device.set_mmds_ipv6_addr(cfg.ipv6_addr());
+                }
                 None => (),
             };
         })
     }
 
+    /// Inserts a virtio-fs device to be attached when the VM starts. If a device with the same
+    /// ID exists, it is replaced.
+    pub fn set_fs_device(&mut self, config: FsDeviceConfig) -> Result<FsConfigError> {
+        let is_new = !self
+            .fs
+            .list
+            .iter()
+            .any(|fs| fs.lock().expect("Poisoned lock").id() == config.fs_id);
+        if is_new && !self.has_free_device_irq() {
+            return Err(FsConfigError::TooManyDevices);
+        }
+        self.fs.insert(config)
+    }
+
     /// Sets a vsock device to be attached when the VM starts.
     pub fn set_vsock_device(&mut self, config: VsockDeviceConfig) -> Result<VsockConfigError> {
+        if self.vsock.get().is_none() && !self.has_free_device_irq() {
+            return Err(VsockConfigError::TooManyDevices);
+        }
         self.vsock.insert(config)
     }
 
+    // Checks whether another device can be attached without running out of IRQs when the
+    // devices are registered on the MMIO bus.
+    fn has_free_device_irq(&self) -> bool {
+        let mut virtio_devices =
+            self.block.list.len() + self.net_builder.iter().count() + self.fs.list.len();
+        if self.vsock.get().is_some() {
+            virtio_devices += 1;
+        }
+        if self.balloon.get().is_some() {
+            virtio_devices += 1;
+        }
+        if self.serial_config.console_type == ConsoleType::Virtio {
+            virtio_devices += 1;
+        }
+        // The serial console and the RTC take IRQs of their own from the same range.
+        let virtio_irqs = if cfg!(target_arch = "aarch64") {
+            MAX_DEVICE_IRQS - 2
+        } else {
+            MAX_DEVICE_IRQS
+        };
+        // The virtio devices share the IRQ lines once every line is used.
+        let max_devices_per_irq = self.vm_config.max_devices_per_irq.unwrap_or(1) as usize;
+
+        virtio_devices < virtio_irqs.saturating_mul(max_devices_per_irq)
+    }
+
     /// Setter for mmds config.
     pub fn set_mmds_config(&mut self, config: MmdsConfig) -> Result<MmdsConfigError> {
         // Check IPv4 address validity.
         let ipv4_addr = match config.ipv4_addr() {
-            Some(ipv4_addr) if is_link_local_valid(ipv4_addr) => Ok(ipv4_addr),
+            Some(ipv4_addr) if ipv4addr::is_link_local_valid(ipv4_addr) => Ok(ipv4_addr),
             None => Ok(MmdsNetworkStack::default_ipv4_addr()),
             _ => Err(MmdsConfigError::InvalidIpv4Addr),
         }?;
 
-        // Update existing built network device `MmdsNetworkStack` IPv4 address.
+        // Check IPv6 address validity.
+        let ipv6_addr = config.ipv6_addr();
+        if !ipv6_addr.map_or(true, ipv6addr::is_link_local_valid) {
+            return Err(MmdsConfigError::InvalidIpv6Addr);
+        }
+
+        mmds::MMDS
+            .lock()
+            .expect("Poisoned lock")
+            .set_version(config.version())
+            .map_err(|err| MmdsConfigError::MmdsVersion(config.version(), err))?;
+
+        // Update existing built network device `MmdsNetworkStack` IP addresses.
         for net_device in self.net_builder.iter_mut() {
-            net_device
-                .lock()
-                .expect("Poisoned lock")
-                .set_mmds_ipv4_addr(ipv4_addr);
+            let mut net_device = net_device.lock().expect("Poisoned lock");
+            net_device.set_mmds_ipv4_addr(ipv4_addr);
+            net_device.set_mmds_ipv6_addr(ipv6_addr);
         }
 
         self.mmds_config = Some(config);
         Ok(())
     }
+
+    /// Replaces the devices with new ones, built from the configuration of the current ones,
+    /// so that a microVM which was stopped can be booted again. The current devices must not
+    /// be used anymore, so that they release their host resources (e.g. tap interfaces) once
+    /// dropped here.
+    pub fn rebuild_devices(&mut self) -> std::result::Result<(), Error> {
+        let block_configs = self.block.configs();
+        let net_configs = self.net_builder.configs();
+        let fs_configs = self.fs.configs();
+        let vsock_config = self.vsock.config();
+        let balloon_config = self.balloon.get_config().ok();
+
+        self.block = BlockBuilder::new();
+        self.net_builder = NetBuilder::new();
+        self.fs = FsBuilder::new();
+        self.vsock = VsockBuilder::new();
+        self.balloon = BalloonBuilder::new();
+
+        for drive_config in block_configs.into_iter() {
+            self.set_block_device(drive_config)
+                .map_err(Error::BlockDevice)?;
+        }
+        for net_config in net_configs.into_iter() {
+            self.build_net_device(net_config)
+                .map_err(Error::NetDevice)?;
+        }
+        for fs_config in fs_configs.into_iter() {
+            self.set_fs_device(fs_config).map_err(Error::FsDevice)?;
+        }
+        if let Some(vsock_config) = vsock_config {
+            self.set_vsock_device(vsock_config)
+                .map_err(Error::VsockDevice)?;
+        }
+        if let Some(balloon_config) = balloon_config {
+            self.set_balloon_device(balloon_config)
+                .map_err(Error::BalloonDevice)?;
+        }
+
+        Ok(())
+    }
 }
 
 impl From<&VmResources> for VmmConfig {
@@ -336,6 +699,7 @@ impl From<&VmResources> for VmmConfig {
             balloon_device: resources.balloon.get_config().ok(),
             block_devices: resources.block.configs(),
             boot_source,
+            fs_devices: resources.fs.configs(),
             logger: None,
             machine_config: Some(resources.vm_config.clone()),
             metrics: None,
@@ -348,20 +712,30 @@ impl From<&VmResources> for VmmConfig {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
     use std::fs::File;
+    use std::net::{Ipv4Addr, Ipv6Addr};
     use std::os::linux::fs::MetadataExt;
+    use std::path::PathBuf;
 
     use super::*;
     use crate::resources::VmResources;
-    use crate::vmm_config::boot_source::{BootConfig, BootSourceConfig, DEFAULT_KERNEL_CMDLINE};
+    use crate::vmm_config::boot_source::{
+        BootConfig, BootSourceConfig, BootSourceUpdateConfig, DEFAULT_KERNEL_CMDLINE,
+    };
     use crate::vmm_config::drive::{BlockBuilder, BlockDeviceConfig};
-    use crate::vmm_config::machine_config::{CpuFeaturesTemplate, VmConfig, VmConfigError};
+    use crate::vmm_config::machine_config::{
+        CpuFeaturesTemplate, GuestSnapshotConfig, InterruptController, MemoryRegionConfig,
+        PanicAction, RebootAction, ResetAction, SmbiosConfig, VmConfig, VmConfigError,
+    };
     use crate::vmm_config::net::{NetBuilder, NetworkInterfaceConfig};
     use crate::vmm_config::vsock::tests::default_config;
-    use crate::vmm_config::RateLimiterConfig;
+    use crate::vmm_config::{RateLimiterConfig, TokenBucketConfig};
     use crate::vstate::vcpu::VcpuConfig;
     use logger::{LevelFilter, LOGGER};
+    use mmds::data_store::MmdsVersion;
     use utils::net::mac::MacAddr;
+    use utils::tempdir::TempDir;
     use utils::tempfile::TempFile;
 
     fn default_net_cfg() -> NetworkInterfaceConfig {
@@ -378,6 +752,11 @@ mod tests {
             rx_rate_limiter: Some(RateLimiterConfig::default()),
             tx_rate_limiter: Some(RateLimiterConfig::default()),
             allow_mmds_requests: false,
+            mmds_allowed_sources: Vec::new(),
+            enable_offload: true,
+            mtu: None,
+            pcap_path: None,
+            interrupt_coalescing_us: 0,
         }
     }
 
@@ -394,9 +773,14 @@ mod tests {
             BlockDeviceConfig {
                 drive_id: "block1".to_string(),
                 path_on_host: tmp_file.as_path().to_str().unwrap().to_string(),
+                backing: DiskBacking::File,
                 is_root_device: false,
                 partuuid: Some("0eaa91a0-01".to_string()),
+                boot_index: None,
                 cache_type: CacheType::Unsafe,
+                open_retry: OpenRetryConfig::default(),
+                access_pattern: AccessPattern::default(),
+                interrupt_coalescing_us: 0,
                 is_read_only: false,
                 rate_limiter: Some(RateLimiterConfig::default()),
             },
@@ -428,11 +812,17 @@ mod tests {
             vm_config: VmConfig::default(),
             boot_config: Some(default_boot_cfg()),
             block: default_blocks(),
+            fs: Default::default(),
             vsock: Default::default(),
             balloon: Default::default(),
             net_builder: default_net_builder(),
             mmds_config: None,
             boot_timer: false,
+            boot_timeout_ms: None,
+            start_paused: false,
+            serial_config: SerialConfig::default(),
+            resource_limits: ResourceLimits::default(),
+            recent_errors: RecentErrors::default(),
         }
     }
 
@@ -754,6 +1144,114 @@ mod tests {
         assert!(VmResources::from_json(json.as_str(), &default_instance_info).is_ok());
     }
 
+    #[test]
+    fn test_set_full_vm_config() {
+        let kernel_file = TempFile::new().unwrap();
+        let rootfs_file = TempFile::new().unwrap();
+        let mut vm_resources = default_vm_resources();
+        vm_resources.boot_timer = true;
+        vm_resources.boot_timeout_ms = Some(1000);
+
+        let config = |rootfs_path: &str, extra: &str| {
+            format!(
+                r#"{{
+                    "boot-source": {{
+                        "kernel_image_path": "{}",
+                        "boot_args": "console=ttyS0 reboot=k panic=1 pci=off"
+                    }},
+                    "drives": [
+                        {{
+                            "drive_id": "root",
+                            "path_on_host": "{}",
+                            "is_root_device": true,
+                            "is_read_only": true
+                        }}
+                    ],
+                    "machine-config": {{
+                        "vcpu_count": 2,
+                        "mem_size_mib": 256,
+                        "ht_enabled": false
+                    }}{}
+                }}"#,
+                kernel_file.as_path().to_str().unwrap(),
+                rootfs_path,
+                extra
+            )
+        };
+
+        // An invalid section leaves the resources unchanged.
+        let vmm_config: VmmConfig = serde_json::from_str(&config("/invalid/path", "")).unwrap();
+        let err = vm_resources.set_full_vm_config(vmm_config).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::BlockDevice(DriveError::InvalidBlockDevicePath)
+        ));
+        assert!(err.to_string().starts_with("Invalid \"drives\" section"));
+        assert_eq!(vm_resources.block.list.len(), 1);
+        assert_eq!(vm_resources.block.list[0].lock().unwrap().id(), "block1");
+
+        // The logger and metrics sections are refused.
+        let vmm_config: VmmConfig = serde_json::from_str(&config(
+            rootfs_file.as_path().to_str().unwrap(),
+            r#", "metrics": { "metrics_path": "/tmp/metrics" }"#,
+        ))
+        .unwrap();
+        assert!(matches!(
+            vm_resources.set_full_vm_config(vmm_config),
+            Err(Error::ProcessConfigNotAllowed)
+        ));
+
+        // A valid configuration replaces the whole microVM configuration, but keeps the
+        // settings provided through the command line.
+        let vmm_config: VmmConfig =
+            serde_json::from_str(&config(rootfs_file.as_path().to_str().unwrap(), "")).unwrap();
+        vm_resources.set_full_vm_config(vmm_config).unwrap();
+        assert_eq!(vm_resources.block.list.len(), 1);
+        assert_eq!(vm_resources.block.list[0].lock().unwrap().id(), "root");
+        assert!(vm_resources.block.list[0].lock().unwrap().is_read_only());
+        assert!(vm_resources.net_builder.iter().next().is_none());
+        assert_eq!(vm_resources.vm_config().vcpu_count, Some(2));
+        assert_eq!(vm_resources.vm_config().mem_size_mib, Some(256));
+        assert!(vm_resources.boot_timer);
+        assert_eq!(vm_resources.boot_timeout_ms, Some(1000));
+    }
+
+    #[test]
+    fn test_set_mmds_config() {
+        let mut vm_resources = default_vm_resources();
+
+        let mut mmds_config = MmdsConfig {
+            version: MmdsVersion::V1,
+            ipv4_address: Some(Ipv4Addr::new(169, 254, 170, 2)),
+            ipv6_address: Some(Ipv6Addr::new(
+                0xfe80, 0, 0, 0, 0xa9fe, 0xa9fe, 0xa9fe, 0xa9fe,
+            )),
+        };
+        assert!(vm_resources.set_mmds_config(mmds_config.clone()).is_ok());
+        assert_eq!(vm_resources.mmds_config, Some(mmds_config.clone()));
+
+        // Only link-local IPv6 addresses are accepted.
+        mmds_config.ipv6_address = Some(Ipv6Addr::LOCALHOST);
+        assert_eq!(
+            vm_resources
+                .set_mmds_config(mmds_config.clone())
+                .unwrap_err()
+                .to_string(),
+            MmdsConfigError::InvalidIpv6Addr.to_string()
+        );
+
+        // Only link-local IPv4 addresses are accepted.
+        mmds_config.ipv4_address = Some(Ipv4Addr::LOCALHOST);
+        mmds_config.ipv6_address = None;
+        assert_eq!(
+            vm_resources
+                .set_mmds_config(mmds_config)
+                .unwrap_err()
+                .to_string(),
+            MmdsConfigError::InvalidIpv4Addr.to_string()
+        );
+    }
+
     #[test]
     fn test_vcpu_config() {
         let vm_resources = default_vm_resources();
@@ -761,6 +1259,7 @@ mod tests {
             vcpu_count: vm_resources.vm_config().vcpu_count.unwrap(),
             ht_enabled: vm_resources.vm_config().ht_enabled.unwrap(),
             cpu_template: vm_resources.vm_config().cpu_template,
+            interrupt_controller: vm_resources.vm_config().interrupt_controller,
         };
 
         let vcpu_config = vm_resources.vcpu_config();
@@ -784,6 +1283,21 @@ mod tests {
             ht_enabled: Some(true),
             cpu_template: Some(CpuFeaturesTemplate::T2),
             track_dirty_pages: false,
+            vcpu_affinity: None,
+            mem_regions: None,
+            allow_mem_overcommit: None,
+            mem_backing_file: None,
+            on_reboot: None,
+            reset_action: None,
+            panic_action: None,
+            rtc_base_time: None,
+            max_descriptors_per_event: None,
+            max_devices_per_irq: None,
+            interrupt_controller: None,
+            smbios: None,
+            shutdown_grace_period_ms: None,
+            guest_snapshot: None,
+            zero_mem_on_shutdown: None,
         };
 
         assert_ne!(vm_resources.vm_config, aux_vm_config);
@@ -817,6 +1331,7 @@ mod tests {
                 amount_mib: 100,
                 deflate_on_oom: false,
                 stats_polling_interval_s: 0,
+                free_page_reporting: false,
             })
             .unwrap();
         aux_vm_config.mem_size_mib = Some(90);
@@ -828,6 +1343,249 @@ mod tests {
         // mem_size_mib compatible with balloon size.
         aux_vm_config.mem_size_mib = Some(256);
         assert!(vm_resources.set_vm_config(&aux_vm_config).is_ok());
+
+        // Valid vcpu affinity.
+        let mut vcpu_affinity = BTreeMap::new();
+        vcpu_affinity.insert(0, vec![0]);
+        vcpu_affinity.insert(31, vec![0]);
+        aux_vm_config.vcpu_affinity = Some(vcpu_affinity.clone());
+        vm_resources.set_vm_config(&aux_vm_config).unwrap();
+        assert_eq!(
+            vm_resources.vm_config.vcpu_affinity,
+            Some(vcpu_affinity.clone())
+        );
+
+        // Omitting the vcpu affinity keeps the current one.
+        aux_vm_config.vcpu_affinity = None;
+        vm_resources.set_vm_config(&aux_vm_config).unwrap();
+        assert_eq!(
+            vm_resources.vm_config.vcpu_affinity,
+            Some(vcpu_affinity.clone())
+        );
+
+        // The current vcpu affinity refers to a vcpu which no longer exists.
+        aux_vm_config.vcpu_count = Some(2);
+        assert_eq!(
+            vm_resources.set_vm_config(&aux_vm_config),
+            Err(VmConfigError::InvalidVcpuAffinity)
+        );
+        aux_vm_config.vcpu_count = Some(32);
+
+        // Invalid vcpu id.
+        vcpu_affinity.insert(32, vec![0]);
+        aux_vm_config.vcpu_affinity = Some(vcpu_affinity.clone());
+        assert_eq!(
+            vm_resources.set_vm_config(&aux_vm_config),
+            Err(VmConfigError::InvalidVcpuAffinity)
+        );
+        vcpu_affinity.remove(&32);
+
+        // Empty host CPU set.
+        vcpu_affinity.insert(1, vec![]);
+        aux_vm_config.vcpu_affinity = Some(vcpu_affinity.clone());
+        assert_eq!(
+            vm_resources.set_vm_config(&aux_vm_config),
+            Err(VmConfigError::InvalidVcpuAffinity)
+        );
+
+        // Invalid host CPU id.
+        vcpu_affinity.insert(1, vec![libc::CPU_SETSIZE as usize]);
+        aux_vm_config.vcpu_affinity = Some(vcpu_affinity);
+        assert_eq!(
+            vm_resources.set_vm_config(&aux_vm_config),
+            Err(VmConfigError::InvalidVcpuAffinity)
+        );
+        aux_vm_config.vcpu_affinity = None;
+        vm_resources.vm_config.vcpu_affinity = None;
+
+        // More memory than the host has available, 1 PiB.
+        aux_vm_config.mem_size_mib = Some(1 << 30);
+        assert!(matches!(
+            vm_resources.set_vm_config(&aux_vm_config),
+            Err(VmConfigError::InsufficientHostMemory(_, _))
+        ));
+        assert_eq!(vm_resources.vm_config.mem_size_mib, Some(256));
+
+        // Unless overcommit is allowed.
+        aux_vm_config.allow_mem_overcommit = Some(true);
+        vm_resources.set_vm_config(&aux_vm_config).unwrap();
+        assert_eq!(vm_resources.vm_config.mem_size_mib, Some(1 << 30));
+        assert_eq!(vm_resources.vm_config.allow_mem_overcommit, Some(true));
+        aux_vm_config.allow_mem_overcommit = None;
+        aux_vm_config.mem_size_mib = Some(256);
+        vm_resources.set_vm_config(&aux_vm_config).unwrap();
+        vm_resources.vm_config.allow_mem_overcommit = None;
+
+        // The guest memory backing file is kept.
+        aux_vm_config.mem_backing_file = Some(PathBuf::from("/dev/shm/guest_mem"));
+        vm_resources.set_vm_config(&aux_vm_config).unwrap();
+        assert_eq!(
+            vm_resources.vm_config.mem_backing_file,
+            Some(PathBuf::from("/dev/shm/guest_mem"))
+        );
+        aux_vm_config.mem_backing_file = None;
+        vm_resources.vm_config.mem_backing_file = None;
+
+        // The action on guest reboot is kept.
+        aux_vm_config.on_reboot = Some(RebootAction::Restart);
+        vm_resources.set_vm_config(&aux_vm_config).unwrap();
+        assert_eq!(
+            vm_resources.vm_config.on_reboot,
+            Some(RebootAction::Restart)
+        );
+        aux_vm_config.on_reboot = None;
+        vm_resources.vm_config.on_reboot = None;
+
+        // The action on i8042 reset is kept.
+        aux_vm_config.reset_action = Some(ResetAction::ReportReboot);
+        vm_resources.set_vm_config(&aux_vm_config).unwrap();
+        assert_eq!(
+            vm_resources.vm_config.reset_action,
+            Some(ResetAction::ReportReboot)
+        );
+        aux_vm_config.reset_action = None;
+        vm_resources.vm_config.reset_action = None;
+
+        // The action on guest panic is kept.
+        aux_vm_config.panic_action = Some(PanicAction::ReportPanic);
+        vm_resources.set_vm_config(&aux_vm_config).unwrap();
+        assert_eq!(
+            vm_resources.vm_config.panic_action,
+            Some(PanicAction::ReportPanic)
+        );
+        aux_vm_config.panic_action = None;
+        vm_resources.vm_config.panic_action = None;
+
+        // The RTC base time is kept. It's rejected on x86_64, where the guest has no RTC.
+        aux_vm_config.rtc_base_time = Some(1_000_000_000);
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(
+            vm_resources.set_vm_config(&aux_vm_config),
+            Err(VmConfigError::UnsupportedRtcBaseTime)
+        );
+        #[cfg(target_arch = "aarch64")]
+        {
+            vm_resources.set_vm_config(&aux_vm_config).unwrap();
+            assert_eq!(vm_resources.vm_config.rtc_base_time, Some(1_000_000_000));
+        }
+        aux_vm_config.rtc_base_time = None;
+        vm_resources.vm_config.rtc_base_time = None;
+
+        // The maximum number of descriptor chains per event can't be 0.
+        aux_vm_config.max_descriptors_per_event = Some(0);
+        assert_eq!(
+            vm_resources.set_vm_config(&aux_vm_config),
+            Err(VmConfigError::InvalidMaxDescriptorsPerEvent)
+        );
+        aux_vm_config.max_descriptors_per_event = Some(64);
+        vm_resources.set_vm_config(&aux_vm_config).unwrap();
+        assert_eq!(vm_resources.vm_config.max_descriptors_per_event, Some(64));
+        aux_vm_config.max_descriptors_per_event = None;
+        vm_resources.vm_config.max_descriptors_per_event = None;
+
+        // The maximum number of devices sharing an IRQ line has to be at least 1.
+        aux_vm_config.max_devices_per_irq = Some(0);
+        assert_eq!(
+            vm_resources.set_vm_config(&aux_vm_config),
+            Err(VmConfigError::InvalidMaxDevicesPerIrq)
+        );
+        aux_vm_config.max_devices_per_irq = Some(4);
+        vm_resources.set_vm_config(&aux_vm_config).unwrap();
+        assert_eq!(vm_resources.vm_config.max_devices_per_irq, Some(4));
+        aux_vm_config.max_devices_per_irq = None;
+        vm_resources.vm_config.max_devices_per_irq = None;
+
+        // The shutdown grace period is kept.
+        aux_vm_config.shutdown_grace_period_ms = Some(5000);
+        vm_resources.set_vm_config(&aux_vm_config).unwrap();
+        assert_eq!(vm_resources.vm_config.shutdown_grace_period_ms, Some(5000));
+        aux_vm_config.shutdown_grace_period_ms = None;
+        vm_resources.vm_config.shutdown_grace_period_ms = None;
+
+        // The guest snapshot paths are kept.
+        let guest_snapshot = GuestSnapshotConfig {
+            snapshot_path: PathBuf::from("/srv/vm.snap"),
+            mem_file_path: PathBuf::from("/srv/vm.mem"),
+        };
+        aux_vm_config.guest_snapshot = Some(guest_snapshot.clone());
+        vm_resources.set_vm_config(&aux_vm_config).unwrap();
+        assert_eq!(vm_resources.vm_config.guest_snapshot, Some(guest_snapshot));
+        aux_vm_config.guest_snapshot = None;
+        vm_resources.vm_config.guest_snapshot = None;
+
+        // The guest memory zeroing is kept.
+        aux_vm_config.zero_mem_on_shutdown = Some(true);
+        vm_resources.set_vm_config(&aux_vm_config).unwrap();
+        assert_eq!(vm_resources.vm_config.zero_mem_on_shutdown, Some(true));
+        aux_vm_config.zero_mem_on_shutdown = None;
+        vm_resources.vm_config.zero_mem_on_shutdown = None;
+
+        // The interrupt controller has to be available on the architecture.
+        #[cfg(target_arch = "x86_64")]
+        let (valid, invalid) = (InterruptController::X2Apic, InterruptController::GicV3);
+        #[cfg(target_arch = "aarch64")]
+        let (valid, invalid) = (InterruptController::GicV3, InterruptController::X2Apic);
+        aux_vm_config.interrupt_controller = Some(invalid);
+        assert_eq!(
+            vm_resources.set_vm_config(&aux_vm_config),
+            Err(VmConfigError::InvalidInterruptController(invalid))
+        );
+        aux_vm_config.interrupt_controller = Some(valid);
+        vm_resources.set_vm_config(&aux_vm_config).unwrap();
+        assert_eq!(vm_resources.vm_config.interrupt_controller, Some(valid));
+        aux_vm_config.interrupt_controller = None;
+        vm_resources.vm_config.interrupt_controller = None;
+
+        // The SMBIOS strings and UUID are validated.
+        aux_vm_config.smbios = Some(SmbiosConfig {
+            uuid: Some("not-a-uuid".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(
+            vm_resources.set_vm_config(&aux_vm_config),
+            Err(VmConfigError::InvalidSmbiosConfig)
+        );
+        let smbios = SmbiosConfig {
+            serial_number: "fc-0123".to_string(),
+            ..Default::default()
+        };
+        aux_vm_config.smbios = Some(smbios.clone());
+        vm_resources.set_vm_config(&aux_vm_config).unwrap();
+        assert_eq!(vm_resources.vm_config.smbios, Some(smbios));
+        aux_vm_config.smbios = None;
+        vm_resources.vm_config.smbios = None;
+
+        // Valid memory layout.
+        #[cfg(target_arch = "x86_64")]
+        let mem_start = 0;
+        #[cfg(target_arch = "aarch64")]
+        let mem_start = arch::aarch64::layout::DRAM_MEM_START;
+        let mem_regions = vec![
+            MemoryRegionConfig {
+                guest_addr: mem_start,
+                size_mib: 128,
+                numa_node: 0,
+            },
+            MemoryRegionConfig {
+                guest_addr: mem_start + (1 << 30),
+                size_mib: 128,
+                #[cfg(target_arch = "x86_64")]
+                numa_node: 0,
+                #[cfg(target_arch = "aarch64")]
+                numa_node: 1,
+            },
+        ];
+        aux_vm_config.mem_regions = Some(mem_regions.clone());
+        vm_resources.set_vm_config(&aux_vm_config).unwrap();
+        assert_eq!(vm_resources.vm_config.mem_regions, Some(mem_regions));
+
+        // The current memory layout doesn't match the new memory size.
+        aux_vm_config.mem_regions = None;
+        aux_vm_config.mem_size_mib = Some(512);
+        assert_eq!(
+            vm_resources.set_vm_config(&aux_vm_config),
+            Err(VmConfigError::InvalidMemoryLayout)
+        );
     }
 
     #[test]
@@ -836,16 +1594,23 @@ mod tests {
             vm_config: VmConfig::default(),
             boot_config: Some(default_boot_cfg()),
             block: default_blocks(),
+            fs: Default::default(),
             vsock: Default::default(),
             balloon: BalloonBuilder::new(),
             net_builder: default_net_builder(),
             mmds_config: None,
             boot_timer: false,
+            boot_timeout_ms: None,
+            start_paused: false,
+            serial_config: SerialConfig::default(),
+            resource_limits: ResourceLimits::default(),
+            recent_errors: RecentErrors::default(),
         };
         let mut new_balloon_cfg = BalloonDeviceConfig {
             amount_mib: 100,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            free_page_reporting: false,
         };
         assert!(vm_resources.balloon.get().is_none());
         vm_resources
@@ -867,11 +1632,17 @@ mod tests {
             vm_config: VmConfig::default(),
             boot_config: Some(default_boot_cfg()),
             block: default_blocks(),
+            fs: Default::default(),
             vsock: Default::default(),
             balloon: BalloonBuilder::new(),
             net_builder: default_net_builder(),
             mmds_config: None,
             boot_timer: false,
+            boot_timeout_ms: None,
+            start_paused: false,
+            serial_config: SerialConfig::default(),
+            resource_limits: ResourceLimits::default(),
+            recent_errors: RecentErrors::default(),
         };
         new_balloon_cfg.amount_mib = 256;
         assert!(vm_resources.set_balloon_device(new_balloon_cfg).is_err());
@@ -894,6 +1665,7 @@ mod tests {
             kernel_image_path: String::from(tmp_file.as_path().to_str().unwrap()),
             initrd_path: Some(String::from(tmp_file.as_path().to_str().unwrap())),
             boot_args: Some(cmdline.to_string()),
+            rng_seed: None,
         };
 
         let mut vm_resources = default_vm_resources();
@@ -929,6 +1701,68 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_update_boot_source() {
+        let mut vm_resources = default_vm_resources();
+        vm_resources.boot_config = None;
+
+        // The boot source can't be updated before being configured.
+        match vm_resources.update_boot_source(BootSourceUpdateConfig::default()) {
+            Err(BootSourceConfigError::MissingBootSource) => (),
+            _ => unreachable!(),
+        }
+
+        let kernel_file = TempFile::new().unwrap();
+        let kernel_path = kernel_file.as_path().to_str().unwrap().to_string();
+        vm_resources
+            .set_boot_source(BootSourceConfig {
+                kernel_image_path: kernel_path.clone(),
+                initrd_path: None,
+                boot_args: Some("console=ttyS0".to_string()),
+                rng_seed: None,
+            })
+            .unwrap();
+
+        // Update only the boot arguments.
+        vm_resources
+            .update_boot_source(BootSourceUpdateConfig {
+                kernel_image_path: None,
+                boot_args: Some("reboot=k".to_string()),
+            })
+            .unwrap();
+        let boot_cfg = vm_resources.boot_source().unwrap();
+        assert_eq!(boot_cfg.cmdline.as_str(), "reboot=k");
+        assert_eq!(boot_cfg.description.kernel_image_path, kernel_path);
+
+        // An invalid kernel path leaves the configuration unchanged.
+        match vm_resources.update_boot_source(BootSourceUpdateConfig {
+            kernel_image_path: Some("/invalid/kernel/path".to_string()),
+            boot_args: None,
+        }) {
+            Err(BootSourceConfigError::InvalidKernelPath(_)) => (),
+            _ => unreachable!(),
+        }
+        let boot_cfg = vm_resources.boot_source().unwrap();
+        assert_eq!(boot_cfg.description.kernel_image_path, kernel_path);
+
+        // Update only the kernel image.
+        let new_kernel_file = TempFile::new().unwrap();
+        let new_kernel_path = new_kernel_file.as_path().to_str().unwrap().to_string();
+        vm_resources
+            .update_boot_source(BootSourceUpdateConfig {
+                kernel_image_path: Some(new_kernel_path.clone()),
+                boot_args: None,
+            })
+            .unwrap();
+        let boot_cfg = vm_resources.boot_source().unwrap();
+        assert_eq!(boot_cfg.description.kernel_image_path, new_kernel_path);
+        assert_eq!(boot_cfg.cmdline.as_str(), "reboot=k");
+        assert_eq!(
+            boot_cfg.kernel_file.metadata().unwrap().st_ino(),
+            new_kernel_file.as_file().metadata().unwrap().st_ino()
+        );
+    }
+
     #[test]
     fn test_set_block_device() {
         let mut vm_resources = default_vm_resources();
@@ -941,6 +1775,104 @@ mod tests {
         assert_eq!(vm_resources.block.list.len(), 2);
     }
 
+    #[test]
+    fn test_update_block_device() {
+        let mut vm_resources = default_vm_resources();
+        let (mut block_device_cfg, _file) = default_block_cfg();
+        block_device_cfg.drive_id = "block2".to_string();
+        vm_resources.set_block_device(block_device_cfg).unwrap();
+        let bandwidth = TokenBucketConfig {
+            size: 1000,
+            one_time_burst: None,
+            refill_time: 100,
+        };
+        let ops = TokenBucketConfig {
+            size: 10,
+            one_time_burst: Some(5),
+            refill_time: 100,
+        };
+
+        // Unknown drive.
+        match vm_resources.update_block_device(BlockDeviceUpdateConfig {
+            drive_id: "block3".to_string(),
+            ..Default::default()
+        }) {
+            Err(DriveError::DriveNotFound(drive_id)) => assert_eq!(drive_id, "block3"),
+            _ => unreachable!(),
+        }
+
+        // Update the rate limiter buckets one at a time.
+        vm_resources
+            .update_block_device(BlockDeviceUpdateConfig {
+                drive_id: "block2".to_string(),
+                path_on_host: None,
+                rate_limiter: Some(RateLimiterConfig {
+                    bandwidth: Some(bandwidth),
+                    ops: None,
+                }),
+            })
+            .unwrap();
+        vm_resources
+            .update_block_device(BlockDeviceUpdateConfig {
+                drive_id: "block2".to_string(),
+                path_on_host: None,
+                rate_limiter: Some(RateLimiterConfig {
+                    bandwidth: None,
+                    ops: Some(ops),
+                }),
+            })
+            .unwrap();
+        let block_cfg = vm_resources
+            .block
+            .configs()
+            .into_iter()
+            .find(|cfg| cfg.drive_id == "block2")
+            .unwrap();
+        assert_eq!(
+            block_cfg.rate_limiter,
+            Some(RateLimiterConfig {
+                bandwidth: Some(bandwidth),
+                ops: Some(ops),
+            })
+        );
+
+        // Update the path.
+        let tmp_file = TempFile::new().unwrap();
+        let new_path = tmp_file.as_path().to_str().unwrap().to_string();
+        vm_resources
+            .update_block_device(BlockDeviceUpdateConfig {
+                drive_id: "block2".to_string(),
+                path_on_host: Some(new_path.clone()),
+                rate_limiter: None,
+            })
+            .unwrap();
+        let block_cfg = vm_resources
+            .block
+            .configs()
+            .into_iter()
+            .find(|cfg| cfg.drive_id == "block2")
+            .unwrap();
+        assert_eq!(block_cfg.path_on_host, new_path);
+        assert!(block_cfg.rate_limiter.is_some());
+
+        // An invalid path leaves the drive unchanged.
+        match vm_resources.update_block_device(BlockDeviceUpdateConfig {
+            drive_id: "block2".to_string(),
+            path_on_host: Some("/invalid/drive/path".to_string()),
+            rate_limiter: None,
+        }) {
+            Err(DriveError::InvalidBlockDevicePath) => (),
+            _ => unreachable!(),
+        }
+        let block_cfg = vm_resources
+            .block
+            .configs()
+            .into_iter()
+            .find(|cfg| cfg.drive_id == "block2")
+            .unwrap();
+        assert_eq!(block_cfg.path_on_host, new_path);
+    }
+
     #[test]
     fn test_set_vsock_device() {
         let mut vm_resources = default_vm_resources();
@@ -972,4 +1904,126 @@ mod tests {
         vm_resources.build_net_device(new_net_device_cfg).unwrap();
         assert_eq!(vm_resources.net_builder.len(), 2);
     }
+
+    #[test]
+    fn test_device_irqs_exhausted() {
+        let mut vm_resources = default_vm_resources();
+
+        // Fill up the IRQs with block devices.
+        let mut index = 0;
+        loop {
+            let (mut block_device_cfg, _file) = default_block_cfg();
+            block_device_cfg.drive_id = format!("block_irq{}", index);
+            match vm_resources.set_block_device(block_device_cfg) {
+                Ok(()) => index += 1,
+                Err(DriveError::TooManyDevices) => break,
+                Err(e) => panic!("Unexpected error: {}", e),
+            }
+        }
+        let devices = vm_resources.block.list.len() + vm_resources.net_builder.len();
+        if cfg!(target_arch = "aarch64") {
+            assert_eq!(devices, MAX_DEVICE_IRQS - 2);
+        } else {
+            assert_eq!(devices, MAX_DEVICE_IRQS);
+        }
+
+        // The existing devices can still be updated.
+        let (mut block_device_cfg, _file) = default_block_cfg();
+        block_device_cfg.is_read_only = true;
+        vm_resources.set_block_device(block_device_cfg).unwrap();
+
+        let mut net_device_cfg = default_net_cfg();
+        net_device_cfg.iface_id = "net_irq".to_string();
+        net_device_cfg.guest_mac = Some(MacAddr::parse_str("01:23:45:67:89:0c").unwrap());
+        match vm_resources.build_net_device(net_device_cfg) {
+            Err(NetworkInterfaceError::TooManyDevices) => (),
+            _ => unreachable!(),
+        }
+
+        let mut tmp_sock_file = TempFile::new().unwrap();
+        tmp_sock_file.remove().unwrap();
+        match vm_resources.set_vsock_device(default_config(&tmp_sock_file)) {
+            Err(VsockConfigError::TooManyDevices) => (),
+            _ => unreachable!(),
+        }
+        assert!(vm_resources.vsock.get().is_none());
+
+        let dir = TempDir::new().unwrap();
+        let fs_cfg = FsDeviceConfig {
+            fs_id: "fs_irq".to_string(),
+            shared_dir: dir.as_path().to_str().unwrap().to_string(),
+            tag: "shared".to_string(),
+            is_read_only: false,
+        };
+        match vm_resources.set_fs_device(fs_cfg) {
+            Err(FsConfigError::TooManyDevices) => (),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_shared_device_irqs() {
+        let mut vm_resources = default_vm_resources();
+        vm_resources.vm_config.max_devices_per_irq = Some(2);
+
+        // Two devices share each IRQ line once every line is used.
+        let mut index = 0;
+        loop {
+            let (mut block_device_cfg, _file) = default_block_cfg();
+            block_device_cfg.drive_id = format!("block_irq{}", index);
+            match vm_resources.set_block_device(block_device_cfg) {
+                Ok(()) => index += 1,
+                Err(DriveError::TooManyDevices) => break,
+                Err(e) => panic!("Unexpected error: {}", e),
+            }
+        }
+        let devices = vm_resources.block.list.len() + vm_resources.net_builder.len();
+        assert!(devices > MAX_DEVICE_IRQS);
+        if cfg!(target_arch = "aarch64") {
+            assert_eq!(devices, 2 * (MAX_DEVICE_IRQS - 2));
+        } else {
+            assert_eq!(devices, 2 * MAX_DEVICE_IRQS);
+        }
+    }
+
+    #[test]
+    fn test_set_fs_device() {
+        let mut vm_resources = default_vm_resources();
+        let dir = TempDir::new().unwrap();
+        let fs_cfg = FsDeviceConfig {
+            fs_id: "fs0".to_string(),
+            shared_dir: dir.as_path().to_str().unwrap().to_string(),
+            tag: "shared".to_string(),
+            is_read_only: true,
+        };
+        vm_resources.set_fs_device(fs_cfg.clone()).unwrap();
+        assert_eq!(vm_resources.fs.configs(), vec![fs_cfg.clone()]);
+
+        // The device is rebuilt with the same configuration.
+        vm_resources.rebuild_devices().unwrap();
+        assert_eq!(vm_resources.fs.configs(), vec![fs_cfg]);
+        assert_eq!(VmmConfig::from(&vm_resources).fs_devices.len(), 1);
+    }
+
+    #[test]
+    fn test_rebuild_devices() {
+        let mut vm_resources = default_vm_resources();
+        let (block_device_cfg, _file) = default_block_cfg();
+        vm_resources.set_block_device(block_device_cfg).unwrap();
+        let block_configs = vm_resources.block.configs();
+        let net_configs = vm_resources.net_builder.configs();
+        let old_block = vm_resources.block.list[0].clone();
+
+        vm_resources.rebuild_devices().unwrap();
+
+        // The devices are new, with the same configuration.
+        assert!(!std::sync::Arc::ptr_eq(
+            &old_block,
+            &vm_resources.block.list[0]
+        ));
+        assert_eq!(vm_resources.block.configs(), block_configs);
+        assert_eq!(vm_resources.net_builder.configs(), net_configs);
+        assert!(vm_resources.vsock.get().is_none());
+        assert!(vm_resources.balloon.get().is_none());
+    }
 }
@@ -8,38 +8,55 @@ use std::sync::{Arc, Mutex};
 use super::Error as VmmError;
 #[cfg(not(test))]
 use super::{
-    builder::build_microvm_for_boot, persist::create_snapshot, persist::restore_from_snapshot,
+    builder::build_microvm_for_boot, builder::validate_microvm_for_boot,
+    memory_dump::dump_guest_memory, persist::create_snapshot, persist::restore_from_snapshot,
     resources::VmResources, Vmm,
 };
-use crate::persist::{CreateSnapshotError, LoadSnapshotError};
-use crate::resources::VmmConfig;
+use crate::memory_dump::DumpGuestMemoryError;
+use crate::persist::{inspect_snapshot, CreateSnapshotError, LoadSnapshotError, MicrovmStateError};
+use crate::recent_errors::{RecentError, RecentErrors};
+use crate::resources::{Error as ResourcesError, VmmConfig};
 use crate::version_map::VERSION_MAP;
 use crate::vmm_config::balloon::{
     BalloonConfigError, BalloonDeviceConfig, BalloonStats, BalloonUpdateConfig,
-    BalloonUpdateStatsConfig,
+    BalloonUpdateStatsConfig, GuestMemoryStats,
 };
-use crate::vmm_config::boot_source::{BootSourceConfig, BootSourceConfigError};
+use crate::vmm_config::boot_source::{
+    BootSourceConfig, BootSourceConfigError, BootSourceUpdateConfig,
+};
+use crate::vmm_config::devices::{DeviceResetConfig, DeviceResetError, MmioDeviceDescription};
 use crate::vmm_config::drive::{BlockDeviceConfig, BlockDeviceUpdateConfig, DriveError};
-use crate::vmm_config::instance_info::InstanceInfo;
+use crate::vmm_config::fs::{FsConfigError, FsDeviceConfig};
+use crate::vmm_config::instance_info::{InstanceInfo, StartMicroVmConfig, VmState};
 use crate::vmm_config::logger::{LoggerConfig, LoggerConfigError};
 use crate::vmm_config::machine_config::{VmConfig, VmConfigError};
+use crate::vmm_config::memory_dump::DumpGuestMemoryParams;
 use crate::vmm_config::metrics::{MetricsConfig, MetricsConfigError};
 use crate::vmm_config::mmds::{MmdsConfig, MmdsConfigError};
 use crate::vmm_config::net::{
     NetworkInterfaceConfig, NetworkInterfaceError, NetworkInterfaceUpdateConfig,
 };
-use crate::vmm_config::snapshot::{CreateSnapshotParams, LoadSnapshotParams, SnapshotType};
+use crate::vmm_config::resource_limits::ResourceLimits;
+use crate::vmm_config::serial::SerialConfig;
+use crate::vmm_config::snapshot::{
+    CreateSnapshotParams, InspectSnapshotParams, LoadSnapshotParams, SnapshotInfo, SnapshotType,
+};
 use crate::vmm_config::vsock::{VsockConfigError, VsockDeviceConfig};
 use crate::vmm_config::{self, RateLimiterUpdate};
+use crate::vstate::vcpu::VcpuRegisters;
 use crate::{builder::StartMicrovmError, EventManager};
-use crate::{ExitCode, FC_EXIT_CODE_BAD_CONFIGURATION};
-use logger::{info, update_metric_with_elapsed_time, METRICS};
+use crate::{
+    ExitCode, FC_EXIT_CODE_BAD_CONFIGURATION, FC_EXIT_CODE_BOOT_TIMEOUT,
+    FC_EXIT_CODE_UNEXPECTED_ERROR,
+};
+use logger::{error, info, update_metric_with_elapsed_time, LOGGER, METRICS};
 use seccompiler::BpfThreadMap;
 #[cfg(test)]
 use tests::{
-    build_microvm_for_boot, create_snapshot, restore_from_snapshot, MockVmRes as VmResources,
-    MockVmm as Vmm,
+    build_microvm_for_boot, create_snapshot, dump_guest_memory, restore_from_snapshot,
+    validate_microvm_for_boot, MockVmRes as VmResources, MockVmm as Vmm,
 };
+use utils::validators::{validate_instance_id, Error as InstanceIdError};
 
 /// This enum represents the public interface of the VMM. Each action contains various
 /// bits of information (ids, paths, etc.).
@@ -57,12 +74,30 @@ pub enum VmmAction {
     /// Create a snapshot using as input the `CreateSnapshotParams`. This action can only be called
     /// after the microVM has booted and only when the microVM is in `Paused` state.
     CreateSnapshot(CreateSnapshotParams),
+    /// Dump the guest memory using as input the `DumpGuestMemoryParams`. This action can only be
+    /// called after the microVM has booted.
+    DumpGuestMemory(DumpGuestMemoryParams),
     /// Get the balloon device configuration.
     GetBalloonConfig,
     /// Get the ballon device latest statistics.
     GetBalloonStats,
+    /// Get the description of the devices attached to the MMIO bus. This action can only be
+    /// called after the microVM has booted.
+    GetDevices,
     /// Get complete microVM configuration in JSON format.
     GetFullVmConfig,
+    /// Get the guest memory usage, from the latest balloon device statistics. This action can
+    /// only be called after the microVM has booted.
+    GetGuestMemoryStats,
+    /// Get the latest errors hit by the VMM, with the time they were hit at.
+    GetRecentErrors,
+    /// Get the latest guest serial output. This action can only be called after the microVM has
+    /// booted.
+    GetSerialLog,
+    /// Get the registers of the vCPU with the given index, for debugging the guest. This action
+    /// can only be called after the microVM has booted and only when the microVM is in `Paused`
+    /// state.
+    GetVcpuState(u8),
     /// Get the machine configuration of the microVM.
     GetVmMachineConfig,
     /// Get microVM instance information.
@@ -72,6 +107,12 @@ pub enum VmmAction {
     /// Add a new block device or update one that already exists using the `BlockDeviceConfig` as
     /// input. This action can only be called before the microVM has booted.
     InsertBlockDevice(BlockDeviceConfig),
+    /// Add a new virtio-fs device or update one that already exists using the `FsDeviceConfig`
+    /// as input. This action can only be called before the microVM has booted.
+    InsertFsDevice(FsDeviceConfig),
+    /// Describe the microVM saved in a snapshot using as input the `InspectSnapshotParams`,
+    /// without loading it. This action doesn't change the state of the running microVM, if any.
+    InspectSnapshot(InspectSnapshotParams),
     /// Add a new network interface config or update one that already exists using the
     /// `NetworkInterfaceConfig` as input. This action can only be called before the microVM has
     /// booted.
@@ -82,12 +123,31 @@ pub enum VmmAction {
     LoadSnapshot(LoadSnapshotParams),
     /// Pause the guest, by pausing the microVM VCPUs.
     Pause,
+    /// Close and reopen the logs and metrics destinations at the paths provided when they were
+    /// configured, e.g. after they were rotated. The paths themselves can't be changed.
+    ReopenLogFiles,
+    /// Ask the guest driver of an activated virtio device to reset it, e.g. to recover a device
+    /// the driver wedged. This action can only be called after the microVM has booted.
+    ResetDevice(DeviceResetConfig),
     /// Resume the guest, by resuming the microVM VCPUs.
     Resume,
     /// Set the balloon device or update the one that already exists using the
     /// `BalloonDeviceConfig` as input. This action can only be called before the microVM
     /// has booted.
     SetBalloonDevice(BalloonDeviceConfig),
+    /// Enable or disable the dirty page tracking, which the diff and live snapshots need. This
+    /// action can only be called after the microVM has booted, and the tracking can only be
+    /// enabled if the microVM was started with it.
+    SetDirtyPageTracking(bool),
+    /// Set the whole microVM configuration at once using `VmmConfig` as input, replacing the
+    /// one already set. Nothing is changed if any section of the configuration is invalid. This
+    /// action can only be called before the microVM has booted and before any other resource
+    /// was configured.
+    SetFullVmConfig(VmmConfig),
+    /// Set the ID of the microVM, which the logger and the snapshots carry, replacing the one
+    /// provided through the command line. This action can only be called before the microVM has
+    /// booted.
+    SetInstanceId(String),
     /// Set the MMDS configuration.
     SetMmdsConfiguration(MmdsConfig),
     /// Set the vsock device or update the one that already exists using the
@@ -97,8 +157,13 @@ pub enum VmmAction {
     /// Set the microVM configuration (memory & vcpu) using `VmConfig` as input. This
     /// action can only be called before the microVM has booted.
     SetVmConfiguration(VmConfig),
-    /// Launch the microVM. This action can only be called before the microVM has booted.
-    StartMicroVm,
+    /// Launch the microVM, using the `StartMicroVmConfig` options. This action can only be
+    /// called before the microVM has booted.
+    StartMicroVm(StartMicroVmConfig),
+    /// Check the current configuration for the problems which would make `StartMicroVm` fail,
+    /// without building the microVM. This action can only be called before the microVM has
+    /// booted and doesn't change the state of the microVM.
+    ValidateConfiguration,
     /// Send CTRL+ALT+DEL to the microVM, using the i8042 keyboard function. If an AT-keyboard
     /// driver is listening on the guest end, this can be used to shut down the microVM gracefully.
     #[cfg(target_arch = "x86_64")]
@@ -109,6 +174,9 @@ pub enum VmmAction {
     UpdateBalloonStatistics(BalloonUpdateStatsConfig),
     /// Update existing block device properties such as `path_on_host` or `rate_limiter`.
     UpdateBlockDevice(BlockDeviceUpdateConfig),
+    /// Update the kernel image and/or the boot arguments of the boot source configuration. This
+    /// action can only be called before the microVM has booted.
+    UpdateBootSource(BootSourceUpdateConfig),
     /// Update a network interface, after microVM start. Currently, the only updatable properties
     /// are the RX and TX rate limiters.
     UpdateNetworkInterface(NetworkInterfaceUpdateConfig),
@@ -123,9 +191,23 @@ pub enum VmmActionError {
     BootSource(BootSourceConfigError),
     /// The action `CreateSnapshot` failed.
     CreateSnapshot(CreateSnapshotError),
-    /// One of the actions `InsertBlockDevice` or `UpdateBlockDevicePath`
+    /// The action `ResetDevice` failed.
+    DeviceReset(DeviceResetError),
+    /// One of the actions `InsertBlockDevice` or `UpdateBlockDevice`
     /// failed because of bad user input.
     DriveConfig(DriveError),
+    /// The action `DumpGuestMemory` failed.
+    DumpGuestMemory(DumpGuestMemoryError),
+    /// The action `InsertFsDevice` failed because of bad user input.
+    FsConfig(FsConfigError),
+    /// The action `SetFullVmConfig` failed because of bad user input.
+    FullVmConfig(ResourcesError),
+    /// Setting the full microVM configuration not allowed after configuring other resources.
+    FullVmConfigNotAllowed,
+    /// The action `SetInstanceId` failed because of bad user input.
+    InstanceId(InstanceIdError),
+    /// The action `InspectSnapshot` failed.
+    InspectSnapshot(LoadSnapshotError),
     /// Internal Vmm error.
     InternalVmm(VmmError),
     /// Loading a microVM snapshot failed.
@@ -150,6 +232,8 @@ pub enum VmmActionError {
     OperationNotSupportedPreBoot,
     /// The action `StartMicroVm` failed because of an internal error.
     StartMicrovm(StartMicrovmError),
+    /// The action `GetVcpuState` failed.
+    VcpuState(MicrovmStateError),
     /// The action `SetVsockDevice` failed because of bad user input.
     VsockConfig(VsockConfigError),
 }
@@ -165,7 +249,18 @@ impl Display for VmmActionError {
                 BalloonConfig(err) => err.to_string(),
                 BootSource(err) => err.to_string(),
                 CreateSnapshot(err) => err.to_string(),
+                DeviceReset(err) => format!("Cannot reset the device: {}", err),
                 DriveConfig(err) => err.to_string(),
+                DumpGuestMemory(err) => format!("Dump guest memory error: {}", err),
+                FsConfig(err) => err.to_string(),
+                FullVmConfig(err) => err.to_string(),
+                FullVmConfigNotAllowed => {
+                    "Setting the full microVM configuration not allowed after configuring other \
+                     resources."
+                        .to_string()
+                }
+                InstanceId(err) => format!("Invalid instance ID: {}", err),
+                InspectSnapshot(err) => format!("Inspect microVM snapshot error: {}", err),
                 InternalVmm(err) => format!("Internal Vmm error: {}", err),
                 LoadSnapshot(err) => format!("Load microVM snapshot error: {}", err),
                 LoadSnapshotNotAllowed => {
@@ -187,6 +282,7 @@ impl Display for VmmActionError {
                         .to_string()
                 }
                 StartMicrovm(err) => err.to_string(),
+                VcpuState(err) => format!("Cannot get the vCPU state: {}", err),
                 // The action `SetVsockDevice` failed because of bad user input.
                 VsockConfig(err) => err.to_string(),
             }
@@ -202,14 +298,28 @@ pub enum VmmData {
     BalloonConfig(BalloonDeviceConfig),
     /// The latest balloon device statistics.
     BalloonStats(BalloonStats),
+    /// The description of the devices attached to the MMIO bus.
+    Devices(Vec<MmioDeviceDescription>),
     /// No data is sent on the channel.
     Empty,
+    /// The problems found when validating the microVM configuration, if any.
+    ConfigurationProblems(Vec<String>),
     /// The complete microVM configuration in JSON format.
     FullVmConfig(VmmConfig),
+    /// The guest memory usage, from the latest balloon device statistics.
+    GuestMemoryStats(GuestMemoryStats),
     /// The microVM configuration represented by `VmConfig`.
     MachineConfiguration(VmConfig),
     /// The microVM instance information.
     InstanceInformation(InstanceInfo),
+    /// The latest errors hit by the VMM, oldest first.
+    RecentErrors(Vec<RecentError>),
+    /// The latest guest serial output.
+    SerialLog(String),
+    /// The description of the microVM saved in a snapshot.
+    SnapshotInfo(SnapshotInfo),
+    /// The registers of a paused vCPU.
+    VcpuState(VcpuRegisters),
 }
 
 /// Shorthand result type for external VMM commands.
@@ -251,9 +361,12 @@ impl<'a> PrebootApiController<'a> {
 
     /// Default implementation for the function that builds and starts a microVM.
     /// It takes two closures `recv_req` and `respond` as params which abstract away
-    /// the message transport.
+    /// the message transport. `recv_req` provides `None` and `respond` fails once the transport
+    /// is disconnected, in which case the microVM can't be configured anymore and
+    /// `FC_EXIT_CODE_UNEXPECTED_ERROR` is returned.
     ///
     /// Returns a populated `VmResources` object and a running `Vmm` object.
+    #[allow(clippy::too_many_arguments)]
     pub fn build_microvm_from_requests<F, G>(
         seccomp_filters: &BpfThreadMap,
         event_manager: &mut EventManager,
@@ -261,10 +374,14 @@ impl<'a> PrebootApiController<'a> {
         recv_req: F,
         respond: G,
         boot_timer_enabled: bool,
+        boot_timeout_ms: Option<u64>,
+        serial_config: SerialConfig,
+        resource_limits: ResourceLimits,
+        recent_errors: RecentErrors,
     ) -> result::Result<(VmResources, Arc<Mutex<Vmm>>), ExitCode>
     where
-        F: Fn() -> VmmAction,
-        G: Fn(ActionResult),
+        F: Fn() -> Option<VmmAction>,
+        G: Fn(ActionResult) -> result::Result<(), ()>,
     {
         let mut vm_resources = VmResources::default();
         // Silence false clippy warning. Clippy suggests using
@@ -273,6 +390,10 @@ impl<'a> PrebootApiController<'a> {
         #[allow(clippy::field_reassign_with_default)]
         {
             vm_resources.boot_timer = boot_timer_enabled;
+            vm_resources.boot_timeout_ms = boot_timeout_ms;
+            vm_resources.serial_config = serial_config;
+            vm_resources.resource_limits = resource_limits;
+            vm_resources.recent_errors = recent_errors;
         }
         let mut preboot_controller = PrebootApiController::new(
             seccomp_filters,
@@ -285,7 +406,23 @@ impl<'a> PrebootApiController<'a> {
         // The loop breaks when a microVM is successfully started, and a running Vmm is built.
         while preboot_controller.built_vmm.is_none() {
             // Get request, process it, send back the response.
-            respond(preboot_controller.handle_preboot_request(recv_req()));
+            let request = match recv_req() {
+                Some(request) => request,
+                None => {
+                    error!("The API transport is disconnected, cannot receive requests.");
+                    return Err(FC_EXIT_CODE_UNEXPECTED_ERROR);
+                }
+            };
+            if respond(preboot_controller.handle_preboot_request(request)).is_err() {
+                error!("The API transport is disconnected, cannot send responses.");
+                // A microVM started by the last request is stopped along with the others.
+                if let Some(vmm) = preboot_controller.built_vmm.take() {
+                    vmm.lock()
+                        .expect("Poisoned lock")
+                        .stop(FC_EXIT_CODE_UNEXPECTED_ERROR);
+                }
+                return Err(FC_EXIT_CODE_UNEXPECTED_ERROR);
+            }
             // If any fatal errors were encountered, break the loop.
             if let Some(exit_code) = preboot_controller.fatal_error {
                 return Err(exit_code);
@@ -318,24 +455,46 @@ impl<'a> PrebootApiController<'a> {
             GetVmMachineConfig => Ok(VmmData::MachineConfiguration(
                 self.vm_resources.vm_config().clone(),
             )),
+            GetRecentErrors => Ok(VmmData::RecentErrors(
+                self.vm_resources.recent_errors.list(),
+            )),
             GetVmInstanceInfo => Ok(VmmData::InstanceInformation(self.instance_info.clone())),
             InsertBlockDevice(config) => self.insert_block_device(config),
+            InsertFsDevice(config) => self.insert_fs_device(config),
             InsertNetworkDevice(config) => self.insert_net_device(config),
+            InspectSnapshot(params) => inspect_snapshot_action(&params),
             LoadSnapshot(config) => self.load_snapshot(&config),
             SetBalloonDevice(config) => self.set_balloon_device(config),
+            SetFullVmConfig(config) => self.set_full_vm_config(config),
+            SetInstanceId(id) => self.set_instance_id(id),
             SetVsockDevice(config) => self.set_vsock_device(config),
             SetVmConfiguration(config) => self.set_vm_config(config),
             SetMmdsConfiguration(config) => self.set_mmds_config(config),
-            StartMicroVm => self.start_microvm(),
+            StartMicroVm(config) => self.start_microvm(config),
+            ValidateConfiguration => Ok(VmmData::ConfigurationProblems(
+                validate_microvm_for_boot(&self.vm_resources, self.seccomp_filters)
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+            )),
+            UpdateBootSource(update) => self.update_boot_source(update),
+            UpdateBlockDevice(update) => self.update_block_device(update),
+            ReopenLogFiles => reopen_log_files(),
             // Operations not allowed pre-boot.
             CreateSnapshot(_)
+            | DumpGuestMemory(_)
             | FlushMetrics
             | Pause
+            | ResetDevice(_)
             | Resume
+            | SetDirtyPageTracking(_)
             | GetBalloonStats
+            | GetDevices
+            | GetGuestMemoryStats
+            | GetSerialLog
+            | GetVcpuState(_)
             | UpdateBalloon(_)
             | UpdateBalloonStatistics(_)
-            | UpdateBlockDevice(_)
             | UpdateNetworkInterface(_) => Err(VmmActionError::OperationNotSupportedPreBoot),
             #[cfg(target_arch = "x86_64")]
             SendCtrlAltDel => Err(VmmActionError::OperationNotSupportedPreBoot),
@@ -358,6 +517,14 @@ impl<'a> PrebootApiController<'a> {
             .map_err(VmmActionError::DriveConfig)
     }
 
+    fn insert_fs_device(&mut self, cfg: FsDeviceConfig) -> ActionResult {
+        self.boot_path = true;
+        self.vm_resources
+            .set_fs_device(cfg)
+            .map(|()| VmmData::Empty)
+            .map_err(VmmActionError::FsConfig)
+    }
+
     fn insert_net_device(&mut self, cfg: NetworkInterfaceConfig) -> ActionResult {
         self.boot_path = true;
         self.vm_resources
@@ -382,6 +549,49 @@ impl<'a> PrebootApiController<'a> {
             .map_err(VmmActionError::BootSource)
     }
 
+    fn update_boot_source(&mut self, update: BootSourceUpdateConfig) -> ActionResult {
+        self.boot_path = true;
+        self.vm_resources
+            .update_boot_source(update)
+            .map(|()| VmmData::Empty)
+            .map_err(VmmActionError::BootSource)
+    }
+
+    fn update_block_device(&mut self, update: BlockDeviceUpdateConfig) -> ActionResult {
+        self.boot_path = true;
+        self.vm_resources
+            .update_block_device(update)
+            .map(|()| VmmData::Empty)
+            .map_err(VmmActionError::DriveConfig)
+    }
+
+    // The whole configuration is replaced, so it can't follow the requests configuring single
+    // resources, whose devices would otherwise be dropped.
+    fn set_full_vm_config(&mut self, cfg: VmmConfig) -> ActionResult {
+        if self.boot_path {
+            let err = VmmActionError::FullVmConfigNotAllowed;
+            info!("{}", err);
+            return Err(err);
+        }
+
+        self.vm_resources
+            .set_full_vm_config(cfg)
+            .map(|()| {
+                self.boot_path = true;
+                VmmData::Empty
+            })
+            .map_err(VmmActionError::FullVmConfig)
+    }
+
+    // The microVM is built with the instance information of the controller, so only the logger
+    // has to be updated here.
+    fn set_instance_id(&mut self, id: String) -> ActionResult {
+        validate_instance_id(&id).map_err(VmmActionError::InstanceId)?;
+        LOGGER.set_instance_id(id.clone());
+        self.instance_info.id = id;
+        Ok(VmmData::Empty)
+    }
+
     fn set_mmds_config(&mut self, cfg: MmdsConfig) -> ActionResult {
         self.boot_path = true;
         self.vm_resources
@@ -408,7 +618,8 @@ impl<'a> PrebootApiController<'a> {
 
     // On success, this command will end the pre-boot stage and this controller
     // will be replaced by a runtime controller.
-    fn start_microvm(&mut self) -> ActionResult {
+    fn start_microvm(&mut self, config: StartMicroVmConfig) -> ActionResult {
+        self.vm_resources.start_paused = config.start_paused;
         build_microvm_for_boot(
             &self.instance_info,
             &self.vm_resources,
@@ -419,7 +630,13 @@ impl<'a> PrebootApiController<'a> {
             self.built_vmm = Some(vmm);
             VmmData::Empty
         })
-        .map_err(VmmActionError::StartMicrovm)
+        .map_err(|err| {
+            // The VMM thread can't boot another microVM once its filter is installed.
+            if let StartMicrovmError::BootTimeoutWithSeccompFilter(_) = err {
+                self.fatal_error = Some(FC_EXIT_CODE_BOOT_TIMEOUT);
+            }
+            VmmActionError::StartMicrovm(err)
+        })
     }
 
     // On success, this command will end the pre-boot stage and this controller
@@ -443,6 +660,9 @@ impl<'a> PrebootApiController<'a> {
             self.seccomp_filters,
             load_params,
             VERSION_MAP.clone(),
+            self.vm_resources.serial_config.clone(),
+            self.vm_resources.resource_limits,
+            self.vm_resources.recent_errors.clone(),
         )
         .and_then(|vmm| {
             let ret = if load_params.resume_vm {
@@ -470,6 +690,20 @@ impl<'a> PrebootApiController<'a> {
     }
 }
 
+// Describes a snapshot without loading it, so it is allowed both pre-boot and post-boot.
+fn inspect_snapshot_action(params: &InspectSnapshotParams) -> ActionResult {
+    inspect_snapshot(params, VERSION_MAP.clone())
+        .map(VmmData::SnapshotInfo)
+        .map_err(VmmActionError::InspectSnapshot)
+}
+
+// Reopens the logs and metrics destinations, if configured.
+fn reopen_log_files() -> ActionResult {
+    vmm_config::logger::reopen_logger().map_err(VmmActionError::Logger)?;
+    vmm_config::metrics::reopen_metrics().map_err(VmmActionError::Metrics)?;
+    Ok(VmmData::Empty)
+}
+
 /// Enables RPC interaction with a running Firecracker VMM.
 pub struct RuntimeApiController {
     vmm: Arc<Mutex<Vmm>>,
@@ -483,6 +717,7 @@ impl RuntimeApiController {
         match request {
             // Supported operations allowed post-boot.
             CreateSnapshot(snapshot_create_cfg) => self.create_snapshot(&snapshot_create_cfg),
+            DumpGuestMemory(params) => self.dump_guest_memory(&params),
             FlushMetrics => self.flush_metrics(),
             GetBalloonConfig => self
                 .vmm
@@ -498,17 +733,48 @@ impl RuntimeApiController {
                 .latest_balloon_stats()
                 .map(VmmData::BalloonStats)
                 .map_err(|e| VmmActionError::BalloonConfig(BalloonConfigError::from(e))),
+            GetDevices => Ok(VmmData::Devices(
+                self.vmm.lock().expect("Poisoned lock").mmio_devices(),
+            )),
             GetFullVmConfig => Ok(VmmData::FullVmConfig((&self.vm_resources).into())),
+            GetGuestMemoryStats => self
+                .vmm
+                .lock()
+                .expect("Poisoned lock")
+                .latest_balloon_stats()
+                .map(|stats| VmmData::GuestMemoryStats(GuestMemoryStats::from(&stats)))
+                .map_err(|e| VmmActionError::BalloonConfig(BalloonConfigError::from(e))),
+            GetRecentErrors => Ok(VmmData::RecentErrors(
+                self.vmm.lock().expect("Poisoned lock").recent_errors(),
+            )),
+            GetSerialLog => self.serial_log(),
+            GetVcpuState(vcpu_id) => self
+                .vmm
+                .lock()
+                .expect("Poisoned lock")
+                .vcpu_registers(vcpu_id)
+                .map(VmmData::VcpuState)
+                .map_err(VmmActionError::VcpuState),
             GetVmMachineConfig => Ok(VmmData::MachineConfiguration(
                 self.vm_resources.vm_config().clone(),
             )),
             GetVmInstanceInfo => Ok(VmmData::InstanceInformation(
                 self.vmm.lock().expect("Poisoned lock").instance_info(),
             )),
+            InspectSnapshot(params) => inspect_snapshot_action(&params),
             Pause => self.pause(),
+            ReopenLogFiles => reopen_log_files(),
+            ResetDevice(config) => self
+                .vmm
+                .lock()
+                .expect("Poisoned lock")
+                .reset_device(&config)
+                .map(|_| VmmData::Empty)
+                .map_err(VmmActionError::DeviceReset),
             Resume => self.resume(),
             #[cfg(target_arch = "x86_64")]
             SendCtrlAltDel => self.send_ctrl_alt_del(),
+            SetDirtyPageTracking(enable) => self.set_dirty_page_tracking(enable),
             UpdateBalloon(balloon_update) => self
                 .vmm
                 .lock()
@@ -524,20 +790,25 @@ impl RuntimeApiController {
                 .map(|_| VmmData::Empty)
                 .map_err(|e| VmmActionError::BalloonConfig(BalloonConfigError::from(e))),
             UpdateBlockDevice(new_cfg) => self.update_block_device(new_cfg),
-            UpdateNetworkInterface(netif_update) => self.update_net_rate_limiters(netif_update),
+            UpdateNetworkInterface(netif_update) => self.update_net_device(netif_update),
+            SetVmConfiguration(config) => self.check_vm_config(config),
 
             // Operations not allowed post-boot.
             ConfigureBootSource(_)
             | ConfigureLogger(_)
             | ConfigureMetrics(_)
             | InsertBlockDevice(_)
+            | InsertFsDevice(_)
             | InsertNetworkDevice(_)
             | LoadSnapshot(_)
             | SetBalloonDevice(_)
+            | SetFullVmConfig(_)
+            | SetInstanceId(_)
             | SetVsockDevice(_)
             | SetMmdsConfiguration(_)
-            | SetVmConfiguration(_)
-            | StartMicroVm => Err(VmmActionError::OperationNotSupportedPostBoot),
+            | StartMicroVm(_)
+            | UpdateBootSource(_)
+            | ValidateConfiguration => Err(VmmActionError::OperationNotSupportedPostBoot),
         }
     }
 
@@ -546,6 +817,22 @@ impl RuntimeApiController {
         Self { vmm, vm_resources }
     }
 
+    /// Provides the microVM.
+    pub fn vmm(&self) -> &Arc<Mutex<Vmm>> {
+        &self.vmm
+    }
+
+    /// Provides the resources of the microVM.
+    pub fn vm_resources(&self) -> &VmResources {
+        &self.vm_resources
+    }
+
+    /// Gives back the resources of the microVM, e.g. to boot it again once it stopped. The
+    /// reference to the `Vmm` held by the controller is dropped.
+    pub fn into_vm_resources(self) -> VmResources {
+        self.vm_resources
+    }
+
     /// Pauses the microVM by pausing the vCPUs.
     pub fn pause(&mut self) -> ActionResult {
         let pause_start_us = utils::time::get_time_us(utils::time::ClockType::Monotonic);
@@ -580,6 +867,19 @@ impl RuntimeApiController {
         Ok(VmmData::Empty)
     }
 
+    /// Enables or disables the dirty page tracking, caching the setting in the resources of the
+    /// microVM, which gate the diff and live snapshots on it.
+    fn set_dirty_page_tracking(&mut self, enable: bool) -> ActionResult {
+        self.vmm
+            .lock()
+            .expect("Poisoned lock")
+            .set_dirty_page_tracking(enable)
+            .map_err(VmmActionError::InternalVmm)?;
+        self.vm_resources.set_track_dirty_pages(enable);
+
+        Ok(VmmData::Empty)
+    }
+
     /// Write the metrics on user demand (flush). We use the word `flush` here to highlight the fact
     /// that the metrics will be written immediately.
     /// Defer to inner Vmm. We'll move to a variant where the Vmm simply exposes functionality like
@@ -593,6 +893,16 @@ impl RuntimeApiController {
             .map_err(VmmActionError::InternalVmm)
     }
 
+    /// Provides the latest guest serial output, with invalid UTF-8 sequences replaced.
+    fn serial_log(&mut self) -> ActionResult {
+        self.vmm
+            .lock()
+            .expect("Poisoned lock")
+            .serial_log()
+            .map(|log| VmmData::SerialLog(String::from_utf8_lossy(&log).into_owned()))
+            .ok_or_else(|| VmmActionError::NotSupported("The serial log is disabled.".to_string()))
+    }
+
     /// Injects CTRL+ALT+DEL keystroke combo to the inner Vmm (if present).
     #[cfg(target_arch = "x86_64")]
     fn send_ctrl_alt_del(&mut self) -> ActionResult {
@@ -604,6 +914,21 @@ impl RuntimeApiController {
             .map_err(VmmActionError::InternalVmm)
     }
 
+    fn dump_guest_memory(&mut self, params: &DumpGuestMemoryParams) -> ActionResult {
+        let mut locked_vmm = self.vmm.lock().expect("Poisoned lock");
+        let dump_start_us = utils::time::get_time_us(utils::time::ClockType::Monotonic);
+
+        dump_guest_memory(&mut locked_vmm, params).map_err(VmmActionError::DumpGuestMemory)?;
+
+        let elapsed_time_us = utils::time::get_time_us(utils::time::ClockType::Monotonic)
+            .saturating_sub(dump_start_us);
+        info!(
+            "'dump guest memory' VMM action took {} us.",
+            elapsed_time_us
+        );
+        Ok(VmmData::Empty)
+    }
+
     fn create_snapshot(&mut self, create_params: &CreateSnapshotParams) -> ActionResult {
         if create_params.snapshot_type == SnapshotType::Diff
             && !self.vm_resources.track_dirty_pages()
@@ -613,12 +938,36 @@ impl RuntimeApiController {
                     .to_string(),
             ));
         }
+        if create_params.live {
+            if create_params.snapshot_type == SnapshotType::Diff {
+                return Err(VmmActionError::NotSupported(
+                    "Live snapshots can only be full snapshots.".to_string(),
+                ));
+            }
+            if !self.vm_resources.track_dirty_pages() {
+                return Err(VmmActionError::NotSupported(
+                    "Live snapshots are not allowed on uVMs with dirty page tracking disabled."
+                        .to_string(),
+                ));
+            }
+        }
 
         let mut locked_vmm = self.vmm.lock().unwrap();
         let create_start_us = utils::time::get_time_us(utils::time::ClockType::Monotonic);
-
-        create_snapshot(&mut locked_vmm, create_params, VERSION_MAP.clone())
-            .map_err(VmmActionError::CreateSnapshot)?;
+        let was_running = locked_vmm.instance_info().state == VmState::Running;
+
+        if let Err(err) = create_snapshot(&mut locked_vmm, create_params, VERSION_MAP.clone()) {
+            // A cancelled snapshot leaves the microVM in the state it was in before the request,
+            // so a microVM which was paused by the user stays paused.
+            if let CreateSnapshotError::Cancelled = err {
+                if was_running && locked_vmm.instance_info().state != VmState::Running {
+                    if let Err(e) = locked_vmm.resume_vm() {
+                        error!("Failed to resume microVM after cancelled snapshot: {}", e);
+                    }
+                }
+            }
+            return Err(VmmActionError::CreateSnapshot(err));
+        }
 
         match create_params.snapshot_type {
             SnapshotType::Full => {
@@ -650,6 +999,12 @@ impl RuntimeApiController {
     ///    update the disk image on the device and its virtio configuration
     ///  - rate limiter configuration.
     fn update_block_device(&mut self, new_cfg: BlockDeviceUpdateConfig) -> ActionResult {
+        if let Some(rate_limiter) = new_cfg.rate_limiter.as_ref() {
+            rate_limiter
+                .validate()
+                .map_err(DriveError::CreateRateLimiter)
+                .map_err(VmmActionError::DriveConfig)?;
+        }
         let mut vmm = self.vmm.lock().expect("Poisoned lock");
         if let Some(new_path) = new_cfg.path_on_host {
             vmm.update_block_device_path(&new_cfg.drive_id, new_path)
@@ -671,20 +1026,54 @@ impl RuntimeApiController {
     }
 
     /// Updates configuration for an emulated net device as described in `new_cfg`.
-    fn update_net_rate_limiters(&mut self, new_cfg: NetworkInterfaceUpdateConfig) -> ActionResult {
-        self.vmm
-            .lock()
-            .expect("Poisoned lock")
-            .update_net_rate_limiters(
-                &new_cfg.iface_id,
-                RateLimiterUpdate::from(new_cfg.rx_rate_limiter).bandwidth,
-                RateLimiterUpdate::from(new_cfg.rx_rate_limiter).ops,
-                RateLimiterUpdate::from(new_cfg.tx_rate_limiter).bandwidth,
-                RateLimiterUpdate::from(new_cfg.tx_rate_limiter).ops,
-            )
-            .map(|()| VmmData::Empty)
-            .map_err(NetworkInterfaceError::DeviceUpdate)
-            .map_err(VmmActionError::NetworkConfig)
+    fn update_net_device(&mut self, new_cfg: NetworkInterfaceUpdateConfig) -> ActionResult {
+        for rate_limiter in new_cfg
+            .rx_rate_limiter
+            .iter()
+            .chain(new_cfg.tx_rate_limiter.iter())
+        {
+            rate_limiter
+                .validate()
+                .map_err(NetworkInterfaceError::CreateRateLimiter)
+                .map_err(VmmActionError::NetworkConfig)?;
+        }
+        let mut vmm = self.vmm.lock().expect("Poisoned lock");
+        vmm.update_net_rate_limiters(
+            &new_cfg.iface_id,
+            RateLimiterUpdate::from(new_cfg.rx_rate_limiter).bandwidth,
+            RateLimiterUpdate::from(new_cfg.rx_rate_limiter).ops,
+            RateLimiterUpdate::from(new_cfg.tx_rate_limiter).bandwidth,
+            RateLimiterUpdate::from(new_cfg.tx_rate_limiter).ops,
+        )
+        .map_err(NetworkInterfaceError::DeviceUpdate)
+        .map_err(VmmActionError::NetworkConfig)?;
+        if let Some(pcap_path) = new_cfg.pcap_path {
+            // An empty path stops the packet capture.
+            let pcap_path = Some(pcap_path).filter(|path| !path.is_empty());
+            vmm.update_net_pcap(&new_cfg.iface_id, pcap_path)
+                .map_err(NetworkInterfaceError::DeviceUpdate)
+                .map_err(VmmActionError::NetworkConfig)?;
+        }
+        Ok(VmmData::Empty)
+    }
+
+    /// The machine configuration can't be updated post-boot. Asking for a vcpu count or memory
+    /// size different from the running microVM ones, e.g. the ones of a resumed snapshot,
+    /// violates an invariant of the started vcpus, so it gets reported as such.
+    fn check_vm_config(&mut self, cfg: VmConfig) -> ActionResult {
+        let vmm = self.vmm.lock().expect("Poisoned lock");
+        let vcpu_count_changed = cfg
+            .vcpu_count
+            .map_or(false, |count| usize::from(count) != vmm.vcpu_count());
+        let mem_size_changed = cfg
+            .mem_size_mib
+            .map_or(false, |size| size as u64 != vmm.mem_size_mib());
+        if vmm.vcpu_count() > 0 && (vcpu_count_changed || mem_size_changed) {
+            return Err(VmmActionError::MachineConfig(
+                VmConfigError::UpdateNotAllowedPostBoot,
+            ));
+        }
+        Err(VmmActionError::OperationNotSupportedPostBoot)
     }
 }
 
@@ -692,13 +1081,20 @@ impl RuntimeApiController {
 mod tests {
     use super::*;
     use crate::vmm_config::balloon::BalloonBuilder;
-    use crate::vmm_config::drive::CacheType;
+    use crate::vmm_config::devices::ResettableDeviceType;
+    use crate::vmm_config::drive::{AccessPattern, CacheType, DiskBacking, OpenRetryConfig};
     use crate::vmm_config::logger::LoggerLevel;
+    use crate::vmm_config::machine_config::DEFAULT_MEM_SIZE_MIB;
+    use crate::vmm_config::snapshot::MemoryMapping;
     use crate::vmm_config::vsock::VsockBuilder;
+    use crate::vmm_config::{RateLimiterConfig, TokenBucketConfig};
     use devices::virtio::balloon::{BalloonConfig, Error as BalloonError};
     use devices::virtio::VsockError;
+    use logger::MetricsFormat;
+    use mmds::data_store::MmdsVersion;
     use seccompiler::BpfThreadMap;
 
+    use std::collections::HashMap;
     use std::path::PathBuf;
 
     impl PartialEq for VmmActionError {
@@ -709,7 +1105,14 @@ mod tests {
                 (BalloonConfig(_), BalloonConfig(_))
                     | (BootSource(_), BootSource(_))
                     | (CreateSnapshot(_), CreateSnapshot(_))
+                    | (DeviceReset(_), DeviceReset(_))
                     | (DriveConfig(_), DriveConfig(_))
+                    | (DumpGuestMemory(_), DumpGuestMemory(_))
+                    | (FsConfig(_), FsConfig(_))
+                    | (FullVmConfig(_), FullVmConfig(_))
+                    | (FullVmConfigNotAllowed, FullVmConfigNotAllowed)
+                    | (InstanceId(_), InstanceId(_))
+                    | (InspectSnapshot(_), InspectSnapshot(_))
                     | (InternalVmm(_), InternalVmm(_))
                     | (LoadSnapshot(_), LoadSnapshot(_))
                     | (LoadSnapshotNotAllowed, LoadSnapshotNotAllowed)
@@ -722,6 +1125,7 @@ mod tests {
                     | (OperationNotSupportedPostBoot, OperationNotSupportedPostBoot)
                     | (OperationNotSupportedPreBoot, OperationNotSupportedPreBoot)
                     | (StartMicrovm(_), StartMicrovm(_))
+                    | (VcpuState(_), VcpuState(_))
                     | (VsockConfig(_), VsockConfig(_))
             )
         }
@@ -737,10 +1141,16 @@ mod tests {
         balloon_set: bool,
         boot_cfg_set: bool,
         block_set: bool,
+        fs_set: bool,
         vsock_set: bool,
         net_set: bool,
         mmds_set: bool,
+        full_config_set: bool,
         pub boot_timer: bool,
+        pub start_paused: bool,
+        pub serial_config: SerialConfig,
+        pub resource_limits: ResourceLimits,
+        pub recent_errors: RecentErrors,
         // when `true`, all self methods are forced to fail
         pub force_errors: bool,
     }
@@ -798,6 +1208,17 @@ mod tests {
             Ok(())
         }
 
+        pub fn update_boot_source(
+            &mut self,
+            _: BootSourceUpdateConfig,
+        ) -> Result<(), BootSourceConfigError> {
+            if self.force_errors {
+                return Err(BootSourceConfigError::MissingBootSource);
+            }
+            self.boot_cfg_set = true;
+            Ok(())
+        }
+
         pub fn set_block_device(&mut self, _: BlockDeviceConfig) -> Result<(), DriveError> {
             if self.force_errors {
                 return Err(DriveError::RootBlockDeviceAlreadyAdded);
@@ -806,6 +1227,25 @@ mod tests {
             Ok(())
         }
 
+        pub fn update_block_device(
+            &mut self,
+            _: BlockDeviceUpdateConfig,
+        ) -> Result<(), DriveError> {
+            if self.force_errors {
+                return Err(DriveError::DriveNotFound(String::new()));
+            }
+            self.block_set = true;
+            Ok(())
+        }
+
+        pub fn set_fs_device(&mut self, _: FsDeviceConfig) -> Result<(), FsConfigError> {
+            if self.force_errors {
+                return Err(FsConfigError::TooManyDevices);
+            }
+            self.fs_set = true;
+            Ok(())
+        }
+
         pub fn build_net_device(
             &mut self,
             _: NetworkInterfaceConfig,
@@ -834,6 +1274,14 @@ mod tests {
             self.mmds_set = true;
             Ok(())
         }
+
+        pub fn set_full_vm_config(&mut self, _: VmmConfig) -> Result<(), ResourcesError> {
+            if self.force_errors {
+                return Err(ResourcesError::ProcessConfigNotAllowed);
+            }
+            self.full_config_set = true;
+            Ok(())
+        }
     }
 
     impl From<&MockVmRes> for VmmConfig {
@@ -855,6 +1303,9 @@ mod tests {
         pub update_balloon_stats_config_called: bool,
         pub update_block_device_path_called: bool,
         pub update_net_rate_limiters_called: bool,
+        pub update_net_pcap_path: Option<Option<String>>,
+        pub dirty_page_tracking: Option<bool>,
+        pub recent_errors: Vec<String>,
         // when `true`, all self methods are forced to fail
         pub force_errors: bool,
     }
@@ -876,6 +1327,8 @@ mod tests {
             Ok(())
         }
 
+        pub fn stop(&mut self, _exit_code: ExitCode) {}
+
         #[cfg(target_arch = "x86_64")]
         pub fn send_ctrl_alt_del(&mut self) -> Result<(), VmmError> {
             if self.force_errors {
@@ -955,9 +1408,81 @@ mod tests {
             Ok(())
         }
 
+        pub fn update_net_pcap(
+            &mut self,
+            _: &str,
+            pcap_path: Option<String>,
+        ) -> Result<(), VmmError> {
+            if self.force_errors {
+                return Err(VmmError::DeviceManager(
+                    crate::device_manager::mmio::Error::IncorrectDeviceType,
+                ));
+            }
+            self.update_net_pcap_path = Some(pcap_path);
+            Ok(())
+        }
+
         pub fn instance_info(&self) -> InstanceInfo {
             InstanceInfo::default()
         }
+
+        pub fn vcpu_count(&self) -> usize {
+            1
+        }
+
+        pub fn mem_size_mib(&self) -> u64 {
+            DEFAULT_MEM_SIZE_MIB as u64
+        }
+
+        pub fn mmio_devices(&self) -> Vec<MmioDeviceDescription> {
+            vec![MmioDeviceDescription {
+                device_type: "block".to_string(),
+                id: "rootfs".to_string(),
+                mmio_base: 0xd000_0000,
+                irq: Some(5),
+                avail_features: Some(1 << 32),
+                acked_features: Some(1 << 32),
+            }]
+        }
+
+        pub fn serial_log(&self) -> Option<Vec<u8>> {
+            if self.force_errors {
+                return None;
+            }
+            Some(b"serial output".to_vec())
+        }
+
+        pub fn recent_errors(&self) -> Vec<RecentError> {
+            self.recent_errors
+                .iter()
+                .map(|message| RecentError {
+                    timestamp_us: 0,
+                    message: message.clone(),
+                })
+                .collect()
+        }
+
+        pub fn set_dirty_page_tracking(&mut self, enable: bool) -> Result<(), VmmError> {
+            if self.force_errors {
+                return Err(VmmError::DirtyPageTrackingNotSupported);
+            }
+            self.dirty_page_tracking = Some(enable);
+            Ok(())
+        }
+
+        pub fn reset_device(&self, config: &DeviceResetConfig) -> Result<(), DeviceResetError> {
+            if self.force_errors {
+                return Err(DeviceResetError::DeviceNotActivated(config.id.clone()));
+            }
+            Ok(())
+        }
+
+        pub fn vcpu_registers(&mut self, _: u8) -> Result<VcpuRegisters, MicrovmStateError> {
+            if self.force_errors {
+                return Err(MicrovmStateError::NotAllowed(String::new()));
+            }
+            Ok(VcpuRegisters::default())
+        }
     }
 
     // Need to redefine this since the non-test one uses real VmResources
@@ -971,6 +1496,17 @@ mod tests {
         Ok(Arc::new(Mutex::new(MockVmm::default())))
     }
 
+    // Need to redefine this since the non-test one uses real VmResources.
+    pub fn validate_microvm_for_boot(
+        vm_resources: &VmResources,
+        _: &BpfThreadMap,
+    ) -> Vec<StartMicrovmError> {
+        if vm_resources.force_errors {
+            return vec![StartMicrovmError::MissingKernelConfig];
+        }
+        Vec::new()
+    }
+
     // Need to redefine this since the non-test one uses real Vmm
     // instead of our mocks.
     pub fn create_snapshot(
@@ -981,6 +1517,18 @@ mod tests {
         Ok(())
     }
 
+    // Need to redefine this since the non-test one uses real Vmm
+    // instead of our mocks.
+    pub fn dump_guest_memory(
+        vmm: &mut Vmm,
+        _: &DumpGuestMemoryParams,
+    ) -> std::result::Result<(), DumpGuestMemoryError> {
+        if vmm.force_errors {
+            return Err(DumpGuestMemoryError::PauseVm(VmmError::VcpuPause));
+        }
+        Ok(())
+    }
+
     // Need to redefine this since the non-test one uses real Vmm
     // instead of our mocks.
     pub fn restore_from_snapshot(
@@ -989,6 +1537,9 @@ mod tests {
         _: &BpfThreadMap,
         _: &LoadSnapshotParams,
         _: versionize::VersionMap,
+        _: SerialConfig,
+        _: ResourceLimits,
+        _: RecentErrors,
     ) -> Result<Arc<Mutex<Vmm>>, LoadSnapshotError> {
         Ok(Arc::new(Mutex::new(MockVmm::default())))
     }
@@ -1044,6 +1595,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_preboot_update_boot_src() {
+        let req = VmmAction::UpdateBootSource(BootSourceUpdateConfig::default());
+        check_preboot_request(req, |result, vm_res| {
+            assert_eq!(result, Ok(VmmData::Empty));
+            assert!(vm_res.boot_cfg_set)
+        });
+
+        let req = VmmAction::UpdateBootSource(BootSourceUpdateConfig::default());
+        check_preboot_request_err(
+            req,
+            VmmActionError::BootSource(BootSourceConfigError::MissingBootSource),
+        );
+    }
+
     #[test]
     fn test_preboot_get_vm_config() {
         let req = VmmAction::GetVmMachineConfig;
@@ -1070,6 +1636,22 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_preboot_get_recent_errors() {
+        let mut vm_resources = MockVmRes::default();
+        vm_resources.recent_errors.record("error".to_string());
+        let mut evmgr = EventManager::new().unwrap();
+        let seccomp_filters = BpfThreadMap::new();
+        let mut preboot = default_preboot(&mut vm_resources, &mut evmgr, &seccomp_filters);
+        match preboot.handle_preboot_request(VmmAction::GetRecentErrors) {
+            Ok(VmmData::RecentErrors(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].message, "error");
+            }
+            _ => unreachable!(),
+        }
+    }
+
     #[test]
     fn test_preboot_set_vm_config() {
         let req = VmmAction::SetVmConfiguration(VmConfig::default());
@@ -1086,6 +1668,72 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_preboot_set_full_vm_config() {
+        let req = VmmAction::SetFullVmConfig(VmmConfig::default());
+        check_preboot_request(req, |result, vm_res| {
+            assert_eq!(result, Ok(VmmData::Empty));
+            assert!(vm_res.full_config_set);
+        });
+
+        let req = VmmAction::SetFullVmConfig(VmmConfig::default());
+        check_preboot_request_err(
+            req,
+            VmmActionError::FullVmConfig(ResourcesError::ProcessConfigNotAllowed),
+        );
+
+        let mut vm_resources = MockVmRes::default();
+        let mut evmgr = EventManager::new().unwrap();
+        let seccomp_filters = BpfThreadMap::new();
+        let mut preboot = default_preboot(&mut vm_resources, &mut evmgr, &seccomp_filters);
+        preboot
+            .handle_preboot_request(VmmAction::SetFullVmConfig(VmmConfig::default()))
+            .unwrap();
+        // Neither the full configuration nor a snapshot can follow it.
+        assert!(preboot.boot_path);
+        assert_eq!(
+            preboot
+                .handle_preboot_request(VmmAction::SetFullVmConfig(VmmConfig::default()))
+                .unwrap_err(),
+            VmmActionError::FullVmConfigNotAllowed
+        );
+
+        // The full configuration can't follow the requests configuring single resources.
+        let mut vm_resources = MockVmRes::default();
+        let mut preboot = default_preboot(&mut vm_resources, &mut evmgr, &seccomp_filters);
+        preboot
+            .handle_preboot_request(VmmAction::SetVmConfiguration(VmConfig::default()))
+            .unwrap();
+        let err = preboot
+            .handle_preboot_request(VmmAction::SetFullVmConfig(VmmConfig::default()))
+            .unwrap_err();
+        assert_eq!(err, VmmActionError::FullVmConfigNotAllowed);
+        assert!(!vm_resources.full_config_set);
+    }
+
+    #[test]
+    fn test_preboot_set_instance_id() {
+        let mut vm_resources = MockVmRes::default();
+        let mut evmgr = EventManager::new().unwrap();
+        let seccomp_filters = BpfThreadMap::new();
+        let mut preboot = default_preboot(&mut vm_resources, &mut evmgr, &seccomp_filters);
+
+        let req = VmmAction::SetInstanceId("new-id".to_string());
+        assert_eq!(preboot.handle_preboot_request(req), Ok(VmmData::Empty));
+        assert_eq!(preboot.instance_info.id, "new-id");
+        // The ID doesn't prevent loading a snapshot.
+        assert!(!preboot.boot_path);
+
+        let req = VmmAction::SetInstanceId("invalid:id".to_string());
+        assert_eq!(
+            preboot.handle_preboot_request(req),
+            Err(VmmActionError::InstanceId(InstanceIdError::InvalidChar(
+                ':', 7
+            )))
+        );
+        assert_eq!(preboot.instance_info.id, "new-id");
+    }
+
     #[test]
     fn test_preboot_set_balloon_dev() {
         let req = VmmAction::SetBalloonDevice(BalloonDeviceConfig::default());
@@ -1105,9 +1753,14 @@ mod tests {
     fn test_preboot_insert_block_dev() {
         let req = VmmAction::InsertBlockDevice(BlockDeviceConfig {
             path_on_host: String::new(),
+            backing: DiskBacking::File,
             is_root_device: false,
             partuuid: None,
+            boot_index: None,
             cache_type: CacheType::Unsafe,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::default(),
+            interrupt_coalescing_us: 0,
             is_read_only: false,
             drive_id: String::new(),
             rate_limiter: None,
@@ -1119,9 +1772,14 @@ mod tests {
 
         let req = VmmAction::InsertBlockDevice(BlockDeviceConfig {
             path_on_host: String::new(),
+            backing: DiskBacking::File,
             is_root_device: false,
             partuuid: None,
+            boot_index: None,
             cache_type: CacheType::Unsafe,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::default(),
+            interrupt_coalescing_us: 0,
             is_read_only: false,
             drive_id: String::new(),
             rate_limiter: None,
@@ -1133,27 +1791,74 @@ mod tests {
     }
 
     #[test]
-    fn test_preboot_insert_net_dev() {
-        let req = VmmAction::InsertNetworkDevice(NetworkInterfaceConfig {
-            iface_id: String::new(),
-            host_dev_name: String::new(),
-            guest_mac: None,
-            rx_rate_limiter: None,
-            tx_rate_limiter: None,
-            allow_mmds_requests: false,
+    fn test_preboot_insert_fs_dev() {
+        let req = VmmAction::InsertFsDevice(FsDeviceConfig {
+            fs_id: String::new(),
+            shared_dir: String::new(),
+            tag: String::new(),
+            is_read_only: false,
         });
         check_preboot_request(req, |result, vm_res| {
             assert_eq!(result, Ok(VmmData::Empty));
-            assert!(vm_res.net_set)
+            assert!(vm_res.fs_set)
         });
 
-        let req = VmmAction::InsertNetworkDevice(NetworkInterfaceConfig {
-            iface_id: String::new(),
-            host_dev_name: String::new(),
-            guest_mac: None,
-            rx_rate_limiter: None,
+        let req = VmmAction::InsertFsDevice(FsDeviceConfig {
+            fs_id: String::new(),
+            shared_dir: String::new(),
+            tag: String::new(),
+            is_read_only: false,
+        });
+        check_preboot_request_err(req, VmmActionError::FsConfig(FsConfigError::TooManyDevices));
+    }
+
+    #[test]
+    fn test_preboot_update_block_dev() {
+        let req = VmmAction::UpdateBlockDevice(BlockDeviceUpdateConfig::default());
+        check_preboot_request(req, |result, vm_res| {
+            assert_eq!(result, Ok(VmmData::Empty));
+            assert!(vm_res.block_set)
+        });
+
+        let req = VmmAction::UpdateBlockDevice(BlockDeviceUpdateConfig::default());
+        check_preboot_request_err(
+            req,
+            VmmActionError::DriveConfig(DriveError::DriveNotFound(String::new())),
+        );
+    }
+
+    #[test]
+    fn test_preboot_insert_net_dev() {
+        let req = VmmAction::InsertNetworkDevice(NetworkInterfaceConfig {
+            iface_id: String::new(),
+            host_dev_name: String::new(),
+            guest_mac: None,
+            rx_rate_limiter: None,
             tx_rate_limiter: None,
             allow_mmds_requests: false,
+            mmds_allowed_sources: Vec::new(),
+            enable_offload: true,
+            mtu: None,
+            pcap_path: None,
+            interrupt_coalescing_us: 0,
+        });
+        check_preboot_request(req, |result, vm_res| {
+            assert_eq!(result, Ok(VmmData::Empty));
+            assert!(vm_res.net_set)
+        });
+
+        let req = VmmAction::InsertNetworkDevice(NetworkInterfaceConfig {
+            iface_id: String::new(),
+            host_dev_name: String::new(),
+            guest_mac: None,
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            allow_mmds_requests: false,
+            mmds_allowed_sources: Vec::new(),
+            enable_offload: true,
+            mtu: None,
+            pcap_path: None,
+            interrupt_coalescing_us: 0,
         });
         check_preboot_request_err(
             req,
@@ -1169,6 +1874,7 @@ mod tests {
             vsock_id: String::new(),
             guest_cid: 0,
             uds_path: String::new(),
+            peer_allowlist: None,
         });
         check_preboot_request(req, |result, vm_res| {
             assert_eq!(result, Ok(VmmData::Empty));
@@ -1179,6 +1885,7 @@ mod tests {
             vsock_id: String::new(),
             guest_cid: 0,
             uds_path: String::new(),
+            peer_allowlist: None,
         });
         check_preboot_request_err(
             req,
@@ -1190,19 +1897,42 @@ mod tests {
 
     #[test]
     fn test_preboot_set_mmds_config() {
-        let req = VmmAction::SetMmdsConfiguration(MmdsConfig { ipv4_address: None });
+        let req = VmmAction::SetMmdsConfiguration(MmdsConfig {
+            version: MmdsVersion::V1,
+            ipv4_address: None,
+            ipv6_address: None,
+        });
         check_preboot_request(req, |result, vm_res| {
             assert_eq!(result, Ok(VmmData::Empty));
             assert!(vm_res.mmds_set)
         });
 
-        let req = VmmAction::SetMmdsConfiguration(MmdsConfig { ipv4_address: None });
+        let req = VmmAction::SetMmdsConfiguration(MmdsConfig {
+            version: MmdsVersion::V1,
+            ipv4_address: None,
+            ipv6_address: None,
+        });
         check_preboot_request_err(
             req,
             VmmActionError::MmdsConfig(MmdsConfigError::InvalidIpv4Addr),
         );
     }
 
+    #[test]
+    fn test_preboot_start_microvm() {
+        let req = VmmAction::StartMicroVm(StartMicroVmConfig::default());
+        check_preboot_request(req, |result, vm_res| {
+            assert_eq!(result, Ok(VmmData::Empty));
+            assert!(!vm_res.start_paused);
+        });
+
+        let req = VmmAction::StartMicroVm(StartMicroVmConfig { start_paused: true });
+        check_preboot_request(req, |result, vm_res| {
+            assert_eq!(result, Ok(VmmData::Empty));
+            assert!(vm_res.start_paused);
+        });
+    }
+
     #[cfg(target_arch = "x86_64")]
     #[test]
     fn test_preboot_load_snapshot() {
@@ -1214,9 +1944,17 @@ mod tests {
         // Without resume.
         let req = VmmAction::LoadSnapshot(LoadSnapshotParams {
             snapshot_path: PathBuf::new(),
+            snapshot_fd: None,
             mem_file_path: PathBuf::new(),
+            mem_file_fd: None,
             enable_diff_snapshots: false,
             resume_vm: false,
+            rebase_clock: false,
+            require_tsc_scaling: false,
+            verify_mem_checksum: false,
+            drive_paths: HashMap::new(),
+            vcpu_count: None,
+            mem_mapping: MemoryMapping::Private,
         });
         // Request should succeed.
         preboot.handle_preboot_request(req).unwrap();
@@ -1227,9 +1965,17 @@ mod tests {
         // With resume.
         let req = VmmAction::LoadSnapshot(LoadSnapshotParams {
             snapshot_path: PathBuf::new(),
+            snapshot_fd: None,
             mem_file_path: PathBuf::new(),
+            mem_file_fd: None,
             enable_diff_snapshots: false,
             resume_vm: true,
+            rebase_clock: false,
+            require_tsc_scaling: false,
+            verify_mem_checksum: false,
+            drive_paths: HashMap::new(),
+            vcpu_count: None,
+            mem_mapping: MemoryMapping::Private,
         });
         // Request should succeed.
         preboot.handle_preboot_request(req).unwrap();
@@ -1240,8 +1986,60 @@ mod tests {
         assert!(!vmm.pause_called);
     }
 
+    #[test]
+    fn test_preboot_inspect_snapshot() {
+        let mut vm_resources = MockVmRes::default();
+        let mut evmgr = EventManager::new().unwrap();
+        let seccomp_filters = BpfThreadMap::new();
+        let mut preboot = default_preboot(&mut vm_resources, &mut evmgr, &seccomp_filters);
+
+        let req = VmmAction::InspectSnapshot(InspectSnapshotParams {
+            snapshot_path: PathBuf::from("/invalid/snapshot"),
+        });
+        assert_eq!(
+            preboot.handle_preboot_request(req).unwrap_err(),
+            VmmActionError::InspectSnapshot(LoadSnapshotError::InvalidSnapshot(String::new()))
+        );
+        // Inspecting a snapshot neither builds a microVM nor prevents loading a snapshot.
+        assert!(preboot.built_vmm.is_none());
+        assert!(!preboot.boot_path);
+        assert!(preboot.fatal_error.is_none());
+    }
+
+    #[test]
+    fn test_preboot_validate_config() {
+        let req = VmmAction::ValidateConfiguration;
+        check_preboot_request(req, |result, _| {
+            assert_eq!(result, Ok(VmmData::ConfigurationProblems(Vec::new())));
+        });
+
+        let mut vm_resources = MockVmRes {
+            force_errors: true,
+            ..Default::default()
+        };
+        let mut evmgr = EventManager::new().unwrap();
+        let seccomp_filters = BpfThreadMap::new();
+        let mut preboot = default_preboot(&mut vm_resources, &mut evmgr, &seccomp_filters);
+        assert_eq!(
+            preboot.handle_preboot_request(VmmAction::ValidateConfiguration),
+            Ok(VmmData::ConfigurationProblems(vec![
+                StartMicrovmError::MissingKernelConfig.to_string()
+            ]))
+        );
+        // Validating the configuration neither builds a microVM nor prevents loading a snapshot.
+        assert!(preboot.built_vmm.is_none());
+        assert!(!preboot.boot_path);
+    }
+
     #[test]
     fn test_preboot_disallowed() {
+        check_preboot_request_err(
+            VmmAction::DumpGuestMemory(DumpGuestMemoryParams {
+                mem_file_path: PathBuf::new(),
+                pause_vm: false,
+            }),
+            VmmActionError::OperationNotSupportedPreBoot,
+        );
         check_preboot_request_err(
             VmmAction::FlushMetrics,
             VmmActionError::OperationNotSupportedPreBoot,
@@ -1259,17 +2057,40 @@ mod tests {
             VmmActionError::OperationNotSupportedPreBoot,
         );
         check_preboot_request_err(
-            VmmAction::UpdateBalloon(BalloonUpdateConfig { amount_mib: 0 }),
+            VmmAction::GetGuestMemoryStats,
             VmmActionError::OperationNotSupportedPreBoot,
         );
         check_preboot_request_err(
-            VmmAction::UpdateBalloonStatistics(BalloonUpdateStatsConfig {
-                stats_polling_interval_s: 0,
+            VmmAction::GetSerialLog,
+            VmmActionError::OperationNotSupportedPreBoot,
+        );
+        check_preboot_request_err(
+            VmmAction::GetDevices,
+            VmmActionError::OperationNotSupportedPreBoot,
+        );
+        check_preboot_request_err(
+            VmmAction::GetVcpuState(0),
+            VmmActionError::OperationNotSupportedPreBoot,
+        );
+        check_preboot_request_err(
+            VmmAction::ResetDevice(DeviceResetConfig {
+                device_type: ResettableDeviceType::Block,
+                id: "rootfs".to_string(),
             }),
             VmmActionError::OperationNotSupportedPreBoot,
         );
         check_preboot_request_err(
-            VmmAction::UpdateBlockDevice(BlockDeviceUpdateConfig::default()),
+            VmmAction::SetDirtyPageTracking(true),
+            VmmActionError::OperationNotSupportedPreBoot,
+        );
+        check_preboot_request_err(
+            VmmAction::UpdateBalloon(BalloonUpdateConfig { amount_mib: 0 }),
+            VmmActionError::OperationNotSupportedPreBoot,
+        );
+        check_preboot_request_err(
+            VmmAction::UpdateBalloonStatistics(BalloonUpdateStatsConfig {
+                stats_polling_interval_s: 0,
+            }),
             VmmActionError::OperationNotSupportedPreBoot,
         );
         check_preboot_request_err(
@@ -1277,6 +2098,7 @@ mod tests {
                 iface_id: String::new(),
                 rx_rate_limiter: None,
                 tx_rate_limiter: None,
+                pcap_path: None,
             }),
             VmmActionError::OperationNotSupportedPreBoot,
         );
@@ -1284,8 +2106,11 @@ mod tests {
             VmmAction::CreateSnapshot(CreateSnapshotParams {
                 snapshot_type: SnapshotType::Full,
                 snapshot_path: PathBuf::new(),
+                snapshot_fd: None,
                 mem_file_path: PathBuf::new(),
+                mem_file_fd: None,
                 version: None,
+                live: false,
             }),
             VmmActionError::OperationNotSupportedPreBoot,
         );
@@ -1305,10 +2130,10 @@ mod tests {
         let commands = || {
             cmd_step.fetch_add(1, Ordering::SeqCst);
             match cmd_step.load(Ordering::SeqCst) {
-                1 => VmmAction::FlushMetrics,
-                2 => VmmAction::Pause,
-                3 => VmmAction::Resume,
-                4 => VmmAction::StartMicroVm,
+                1 => Some(VmmAction::FlushMetrics),
+                2 => Some(VmmAction::Pause),
+                3 => Some(VmmAction::Resume),
+                4 => Some(VmmAction::StartMicroVm(StartMicroVmConfig::default())),
                 _ => unreachable!(),
             }
         };
@@ -1324,6 +2149,7 @@ mod tests {
                 _ => unreachable!(),
             };
             assert_eq!(resp, expect);
+            Ok(())
         };
 
         let (_vm_res, _vmm) = PrebootApiController::build_microvm_from_requests(
@@ -1333,8 +2159,41 @@ mod tests {
             commands,
             expected_resp,
             false,
+            None,
+            SerialConfig::default(),
+            ResourceLimits::default(),
+            RecentErrors::default(),
         )
         .unwrap();
+
+        // The configuration stops once the transport is disconnected.
+        let result = PrebootApiController::build_microvm_from_requests(
+            &BpfThreadMap::new(),
+            &mut EventManager::new().unwrap(),
+            InstanceInfo::default(),
+            || None,
+            |_| Ok(()),
+            false,
+            None,
+            SerialConfig::default(),
+            ResourceLimits::default(),
+            RecentErrors::default(),
+        );
+        assert_eq!(result.err(), Some(FC_EXIT_CODE_UNEXPECTED_ERROR));
+
+        let result = PrebootApiController::build_microvm_from_requests(
+            &BpfThreadMap::new(),
+            &mut EventManager::new().unwrap(),
+            InstanceInfo::default(),
+            || Some(VmmAction::StartMicroVm(StartMicroVmConfig::default())),
+            |_| Err(()),
+            false,
+            None,
+            SerialConfig::default(),
+            ResourceLimits::default(),
+            RecentErrors::default(),
+        );
+        assert_eq!(result.err(), Some(FC_EXIT_CODE_UNEXPECTED_ERROR));
     }
 
     fn check_runtime_request<F>(request: VmmAction, check_success: F)
@@ -1358,6 +2217,56 @@ mod tests {
         assert_eq!(err, expected_err);
     }
 
+    #[test]
+    fn test_runtime_create_live_snapshot() {
+        let live_snapshot_params = |snapshot_type| CreateSnapshotParams {
+            snapshot_type,
+            snapshot_path: PathBuf::new(),
+            snapshot_fd: None,
+            mem_file_path: PathBuf::new(),
+            mem_file_fd: None,
+            version: None,
+            live: true,
+        };
+        let vmm = Arc::new(Mutex::new(MockVmm::default()));
+        let mut vm_res = MockVmRes::default();
+
+        // Live snapshots need the dirty page tracking.
+        let mut runtime = RuntimeApiController::new(vm_res, vmm.clone());
+        let req = VmmAction::CreateSnapshot(live_snapshot_params(SnapshotType::Full));
+        assert_eq!(
+            runtime.handle_request(req),
+            Err(VmmActionError::NotSupported(String::new()))
+        );
+
+        vm_res = MockVmRes::default();
+        vm_res.set_track_dirty_pages(true);
+        let mut runtime = RuntimeApiController::new(vm_res, vmm);
+        // Live snapshots are full snapshots.
+        let req = VmmAction::CreateSnapshot(live_snapshot_params(SnapshotType::Diff));
+        assert_eq!(
+            runtime.handle_request(req),
+            Err(VmmActionError::NotSupported(String::new()))
+        );
+        let req = VmmAction::CreateSnapshot(live_snapshot_params(SnapshotType::Full));
+        assert_eq!(runtime.handle_request(req), Ok(VmmData::Empty));
+    }
+
+    #[test]
+    fn test_runtime_dump_guest_memory() {
+        let dump_params = || DumpGuestMemoryParams {
+            mem_file_path: PathBuf::new(),
+            pause_vm: true,
+        };
+        check_runtime_request(VmmAction::DumpGuestMemory(dump_params()), |result, _| {
+            assert_eq!(result, Ok(VmmData::Empty));
+        });
+        check_runtime_request_err(
+            VmmAction::DumpGuestMemory(dump_params()),
+            VmmActionError::DumpGuestMemory(DumpGuestMemoryError::PauseVm(VmmError::VcpuPause)),
+        );
+    }
+
     #[test]
     fn test_runtime_get_vm_config() {
         let req = VmmAction::GetVmMachineConfig;
@@ -1369,6 +2278,43 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_runtime_set_vm_config() {
+        // Not changing the vcpu count and memory size of the running microVM is still not
+        // supported post-boot.
+        let req = VmmAction::SetVmConfiguration(VmConfig::default());
+        check_runtime_request(req, |result, _| {
+            assert_eq!(result, Err(VmmActionError::OperationNotSupportedPostBoot));
+        });
+
+        let req = VmmAction::SetVmConfiguration(VmConfig {
+            vcpu_count: Some(2),
+            ..Default::default()
+        });
+        check_runtime_request(req, |result, _| {
+            assert_eq!(
+                result,
+                Err(VmmActionError::MachineConfig(
+                    VmConfigError::UpdateNotAllowedPostBoot
+                ))
+            );
+        });
+
+        let req = VmmAction::SetVmConfiguration(VmConfig {
+            vcpu_count: None,
+            mem_size_mib: Some(DEFAULT_MEM_SIZE_MIB * 2),
+            ..Default::default()
+        });
+        check_runtime_request(req, |result, _| {
+            assert_eq!(
+                result,
+                Err(VmmActionError::MachineConfig(
+                    VmConfigError::UpdateNotAllowedPostBoot
+                ))
+            );
+        });
+    }
+
     #[test]
     fn test_runtime_pause() {
         let req = VmmAction::Pause;
@@ -1444,6 +2390,179 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_runtime_guest_memory_stats() {
+        let req = VmmAction::GetGuestMemoryStats;
+        check_runtime_request(req, |result, vmm| {
+            assert_eq!(
+                result,
+                Ok(VmmData::GuestMemoryStats(GuestMemoryStats::default()))
+            );
+            assert!(vmm.latest_balloon_stats_called)
+        });
+
+        // The guest memory usage is only known through the balloon device.
+        let req = VmmAction::GetGuestMemoryStats;
+        check_runtime_request_err(
+            req,
+            VmmActionError::BalloonConfig(BalloonConfigError::DeviceNotFound),
+        );
+    }
+
+    #[test]
+    fn test_runtime_vm_resources() {
+        let vm_resources = MockVmRes {
+            boot_timer: true,
+            ..Default::default()
+        };
+        let vmm = Arc::new(Mutex::new(MockVmm::default()));
+        let controller = RuntimeApiController::new(vm_resources, vmm.clone());
+        assert!(controller.vm_resources().boot_timer);
+        assert_eq!(Arc::strong_count(&vmm), 2);
+
+        assert!(controller.into_vm_resources().boot_timer);
+        assert_eq!(Arc::strong_count(&vmm), 1);
+    }
+
+    #[test]
+    fn test_runtime_serial_log() {
+        let req = VmmAction::GetSerialLog;
+        check_runtime_request(req, |result, _| {
+            assert_eq!(result, Ok(VmmData::SerialLog("serial output".to_string())));
+        });
+
+        // The serial log is disabled.
+        let req = VmmAction::GetSerialLog;
+        check_runtime_request_err(req, VmmActionError::NotSupported(String::new()));
+    }
+
+    #[test]
+    fn test_runtime_recent_errors() {
+        let vmm = Arc::new(Mutex::new(MockVmm::default()));
+        let mut runtime = RuntimeApiController::new(MockVmRes::default(), vmm.clone());
+        assert_eq!(
+            runtime.handle_request(VmmAction::GetRecentErrors),
+            Ok(VmmData::RecentErrors(vec![]))
+        );
+
+        vmm.lock().unwrap().recent_errors.push("error".to_string());
+        assert_eq!(
+            runtime.handle_request(VmmAction::GetRecentErrors),
+            Ok(VmmData::RecentErrors(vec![RecentError {
+                timestamp_us: 0,
+                message: "error".to_string(),
+            }]))
+        );
+    }
+
+    #[test]
+    fn test_runtime_get_devices() {
+        let req = VmmAction::GetDevices;
+        check_runtime_request(req, |result, _| {
+            assert_eq!(
+                result,
+                Ok(VmmData::Devices(vec![MmioDeviceDescription {
+                    device_type: "block".to_string(),
+                    id: "rootfs".to_string(),
+                    mmio_base: 0xd000_0000,
+                    irq: Some(5),
+                    avail_features: Some(1 << 32),
+                    acked_features: Some(1 << 32),
+                }]))
+            );
+        });
+    }
+
+    #[test]
+    fn test_runtime_reset_device() {
+        let config = DeviceResetConfig {
+            device_type: ResettableDeviceType::Net,
+            id: "eth0".to_string(),
+        };
+        let req = VmmAction::ResetDevice(config.clone());
+        check_runtime_request(req, |result, _| {
+            assert_eq!(result, Ok(VmmData::Empty));
+        });
+
+        // The device isn't activated.
+        let req = VmmAction::ResetDevice(config);
+        check_runtime_request_err(
+            req,
+            VmmActionError::DeviceReset(DeviceResetError::DeviceNotActivated(String::new())),
+        );
+    }
+
+    #[test]
+    fn test_runtime_set_dirty_page_tracking() {
+        let vmm = Arc::new(Mutex::new(MockVmm::default()));
+        let mut vm_res = MockVmRes::default();
+        vm_res.set_track_dirty_pages(true);
+        let mut runtime = RuntimeApiController::new(vm_res, vmm.clone());
+
+        // Diff snapshots are refused while the tracking is disabled.
+        let req = VmmAction::SetDirtyPageTracking(false);
+        assert_eq!(runtime.handle_request(req), Ok(VmmData::Empty));
+        assert_eq!(vmm.lock().unwrap().dirty_page_tracking, Some(false));
+        assert!(!runtime.vm_resources().track_dirty_pages());
+        let req = VmmAction::CreateSnapshot(CreateSnapshotParams {
+            snapshot_type: SnapshotType::Diff,
+            snapshot_path: PathBuf::new(),
+            snapshot_fd: None,
+            mem_file_path: PathBuf::new(),
+            mem_file_fd: None,
+            version: None,
+            live: false,
+        });
+        assert_eq!(
+            runtime.handle_request(req),
+            Err(VmmActionError::NotSupported(String::new()))
+        );
+
+        let req = VmmAction::SetDirtyPageTracking(true);
+        assert_eq!(runtime.handle_request(req), Ok(VmmData::Empty));
+        assert_eq!(vmm.lock().unwrap().dirty_page_tracking, Some(true));
+        assert!(runtime.vm_resources().track_dirty_pages());
+
+        // The microVM was started without dirty page tracking.
+        let req = VmmAction::SetDirtyPageTracking(true);
+        check_runtime_request_err(
+            req,
+            VmmActionError::InternalVmm(VmmError::DirtyPageTrackingNotSupported),
+        );
+    }
+
+    #[test]
+    fn test_runtime_get_vcpu_state() {
+        let req = VmmAction::GetVcpuState(0);
+        check_runtime_request(req, |result, _| {
+            assert_eq!(result, Ok(VmmData::VcpuState(VcpuRegisters::default())));
+        });
+
+        // The microVM is running.
+        let req = VmmAction::GetVcpuState(0);
+        check_runtime_request_err(
+            req,
+            VmmActionError::VcpuState(MicrovmStateError::NotAllowed(String::new())),
+        );
+    }
+
+    #[test]
+    fn test_runtime_inspect_snapshot() {
+        let req = VmmAction::InspectSnapshot(InspectSnapshotParams {
+            snapshot_path: PathBuf::from("/invalid/snapshot"),
+        });
+        check_runtime_request(req, |result, vmm| {
+            assert_eq!(
+                result,
+                Err(VmmActionError::InspectSnapshot(
+                    LoadSnapshotError::InvalidSnapshot(String::new())
+                ))
+            );
+            // The microVM is left untouched.
+            assert_eq!(*vmm, MockVmm::default());
+        });
+    }
+
     #[test]
     fn test_runtime_update_balloon_config() {
         let req = VmmAction::UpdateBalloon(BalloonUpdateConfig { amount_mib: 0 });
@@ -1501,22 +2620,94 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_runtime_update_invalid_rate_limiters() {
+        // A token bucket with a non-zero size which never refills is rejected.
+        let invalid_rl = Some(RateLimiterConfig {
+            bandwidth: Some(TokenBucketConfig {
+                size: 1000,
+                one_time_burst: None,
+                refill_time: 0,
+            }),
+            ops: None,
+        });
+
+        let req = VmmAction::UpdateBlockDevice(BlockDeviceUpdateConfig {
+            rate_limiter: invalid_rl,
+            ..Default::default()
+        });
+        check_runtime_request(req, |result, vmm| {
+            assert!(matches!(
+                result,
+                Err(VmmActionError::DriveConfig(DriveError::CreateRateLimiter(
+                    _
+                )))
+            ));
+            assert!(!vmm.update_block_device_path_called);
+        });
+
+        let req = VmmAction::UpdateNetworkInterface(NetworkInterfaceUpdateConfig {
+            iface_id: String::new(),
+            rx_rate_limiter: None,
+            tx_rate_limiter: invalid_rl,
+            pcap_path: None,
+        });
+        check_runtime_request(req, |result, vmm| {
+            assert!(matches!(
+                result,
+                Err(VmmActionError::NetworkConfig(
+                    NetworkInterfaceError::CreateRateLimiter(_)
+                ))
+            ));
+            assert!(!vmm.update_net_rate_limiters_called);
+        });
+    }
+
     #[test]
     fn test_runtime_update_net_rate_limiters() {
         let req = VmmAction::UpdateNetworkInterface(NetworkInterfaceUpdateConfig {
             iface_id: String::new(),
             rx_rate_limiter: None,
             tx_rate_limiter: None,
+            pcap_path: None,
         });
         check_runtime_request(req, |result, vmm| {
             assert_eq!(result, Ok(VmmData::Empty));
-            assert!(vmm.update_net_rate_limiters_called)
+            assert!(vmm.update_net_rate_limiters_called);
+            assert!(vmm.update_net_pcap_path.is_none());
         });
 
         let req = VmmAction::UpdateNetworkInterface(NetworkInterfaceUpdateConfig {
             iface_id: String::new(),
             rx_rate_limiter: None,
             tx_rate_limiter: None,
+            pcap_path: Some("capture.pcap".to_string()),
+        });
+        check_runtime_request(req, |result, vmm| {
+            assert_eq!(result, Ok(VmmData::Empty));
+            assert_eq!(
+                vmm.update_net_pcap_path,
+                Some(Some("capture.pcap".to_string()))
+            );
+        });
+
+        // An empty path stops the packet capture.
+        let req = VmmAction::UpdateNetworkInterface(NetworkInterfaceUpdateConfig {
+            iface_id: String::new(),
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            pcap_path: Some(String::new()),
+        });
+        check_runtime_request(req, |result, vmm| {
+            assert_eq!(result, Ok(VmmData::Empty));
+            assert_eq!(vmm.update_net_pcap_path, Some(None));
+        });
+
+        let req = VmmAction::UpdateNetworkInterface(NetworkInterfaceUpdateConfig {
+            iface_id: String::new(),
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            pcap_path: None,
         });
         check_runtime_request_err(
             req,
@@ -1532,6 +2723,18 @@ mod tests {
             VmmAction::ConfigureBootSource(BootSourceConfig::default()),
             VmmActionError::OperationNotSupportedPostBoot,
         );
+        check_runtime_request_err(
+            VmmAction::SetFullVmConfig(VmmConfig::default()),
+            VmmActionError::OperationNotSupportedPostBoot,
+        );
+        check_runtime_request_err(
+            VmmAction::SetInstanceId("new-id".to_string()),
+            VmmActionError::OperationNotSupportedPostBoot,
+        );
+        check_runtime_request_err(
+            VmmAction::UpdateBootSource(BootSourceUpdateConfig::default()),
+            VmmActionError::OperationNotSupportedPostBoot,
+        );
         check_runtime_request_err(
             VmmAction::ConfigureLogger(LoggerConfig {
                 log_path: PathBuf::new(),
@@ -1544,21 +2747,36 @@ mod tests {
         check_runtime_request_err(
             VmmAction::ConfigureMetrics(MetricsConfig {
                 metrics_path: PathBuf::new(),
+                format: MetricsFormat::Json,
             }),
             VmmActionError::OperationNotSupportedPostBoot,
         );
         check_runtime_request_err(
             VmmAction::InsertBlockDevice(BlockDeviceConfig {
                 path_on_host: String::new(),
+                backing: DiskBacking::File,
                 is_root_device: false,
                 partuuid: None,
+                boot_index: None,
                 cache_type: CacheType::Unsafe,
+                open_retry: OpenRetryConfig::default(),
+                access_pattern: AccessPattern::default(),
+                interrupt_coalescing_us: 0,
                 is_read_only: false,
                 drive_id: String::new(),
                 rate_limiter: None,
             }),
             VmmActionError::OperationNotSupportedPostBoot,
         );
+        check_runtime_request_err(
+            VmmAction::InsertFsDevice(FsDeviceConfig {
+                fs_id: String::new(),
+                shared_dir: String::new(),
+                tag: String::new(),
+                is_read_only: false,
+            }),
+            VmmActionError::OperationNotSupportedPostBoot,
+        );
         check_runtime_request_err(
             VmmAction::InsertNetworkDevice(NetworkInterfaceConfig {
                 iface_id: String::new(),
@@ -1567,6 +2785,11 @@ mod tests {
                 rx_rate_limiter: None,
                 tx_rate_limiter: None,
                 allow_mmds_requests: false,
+                mmds_allowed_sources: Vec::new(),
+                enable_offload: true,
+                mtu: None,
+                pcap_path: None,
+                interrupt_coalescing_us: 0,
             }),
             VmmActionError::OperationNotSupportedPostBoot,
         );
@@ -1575,6 +2798,7 @@ mod tests {
                 vsock_id: String::new(),
                 guest_cid: 0,
                 uds_path: String::new(),
+                peer_allowlist: None,
             }),
             VmmActionError::OperationNotSupportedPostBoot,
         );
@@ -1587,11 +2811,16 @@ mod tests {
                 vsock_id: String::new(),
                 guest_cid: 0,
                 uds_path: String::new(),
+                peer_allowlist: None,
             }),
             VmmActionError::OperationNotSupportedPostBoot,
         );
         check_runtime_request_err(
-            VmmAction::SetMmdsConfiguration(MmdsConfig { ipv4_address: None }),
+            VmmAction::SetMmdsConfiguration(MmdsConfig {
+                version: MmdsVersion::V1,
+                ipv4_address: None,
+                ipv6_address: None,
+            }),
             VmmActionError::OperationNotSupportedPostBoot,
         );
         check_runtime_request_err(
@@ -1601,12 +2830,24 @@ mod tests {
         check_runtime_request_err(
             VmmAction::LoadSnapshot(LoadSnapshotParams {
                 snapshot_path: PathBuf::new(),
+                snapshot_fd: None,
                 mem_file_path: PathBuf::new(),
+                mem_file_fd: None,
                 enable_diff_snapshots: false,
                 resume_vm: false,
+                rebase_clock: false,
+                require_tsc_scaling: false,
+                verify_mem_checksum: false,
+                drive_paths: HashMap::new(),
+                vcpu_count: None,
+                mem_mapping: MemoryMapping::Private,
             }),
             VmmActionError::OperationNotSupportedPostBoot,
         );
+        check_runtime_request_err(
+            VmmAction::ValidateConfiguration,
+            VmmActionError::OperationNotSupportedPostBoot,
+        );
     }
 
     fn verify_load_snap_disallowed_after_boot_resources(res: VmmAction, res_name: &str) {
@@ -1620,9 +2861,17 @@ mod tests {
         // Load snapshot should no longer be allowed.
         let req = VmmAction::LoadSnapshot(LoadSnapshotParams {
             snapshot_path: PathBuf::new(),
+            snapshot_fd: None,
             mem_file_path: PathBuf::new(),
+            mem_file_fd: None,
             enable_diff_snapshots: false,
             resume_vm: false,
+            rebase_clock: false,
+            require_tsc_scaling: false,
+            verify_mem_checksum: false,
+            drive_paths: HashMap::new(),
+            vcpu_count: None,
+            mem_mapping: MemoryMapping::Private,
         });
         let err = preboot.handle_preboot_request(req);
         assert_eq!(
@@ -1639,17 +2888,33 @@ mod tests {
         let req = VmmAction::ConfigureBootSource(BootSourceConfig::default());
         verify_load_snap_disallowed_after_boot_resources(req, "ConfigureBootSource");
 
+        let req = VmmAction::UpdateBootSource(BootSourceUpdateConfig::default());
+        verify_load_snap_disallowed_after_boot_resources(req, "UpdateBootSource");
+
         let req = VmmAction::InsertBlockDevice(BlockDeviceConfig {
             path_on_host: String::new(),
+            backing: DiskBacking::File,
             is_root_device: false,
             partuuid: None,
+            boot_index: None,
             cache_type: CacheType::Unsafe,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::default(),
+            interrupt_coalescing_us: 0,
             is_read_only: false,
             drive_id: String::new(),
             rate_limiter: None,
         });
         verify_load_snap_disallowed_after_boot_resources(req, "InsertBlockDevice");
 
+        let req = VmmAction::InsertFsDevice(FsDeviceConfig {
+            fs_id: String::new(),
+            shared_dir: String::new(),
+            tag: String::new(),
+            is_read_only: false,
+        });
+        verify_load_snap_disallowed_after_boot_resources(req, "InsertFsDevice");
+
         let req = VmmAction::InsertNetworkDevice(NetworkInterfaceConfig {
             iface_id: String::new(),
             host_dev_name: String::new(),
@@ -1657,6 +2922,11 @@ mod tests {
             rx_rate_limiter: None,
             tx_rate_limiter: None,
             allow_mmds_requests: false,
+            mmds_allowed_sources: Vec::new(),
+            enable_offload: true,
+            mtu: None,
+            pcap_path: None,
+            interrupt_coalescing_us: 0,
         });
         verify_load_snap_disallowed_after_boot_resources(req, "InsertNetworkDevice");
 
@@ -1667,13 +2937,18 @@ mod tests {
             vsock_id: String::new(),
             guest_cid: 0,
             uds_path: String::new(),
+            peer_allowlist: None,
         });
         verify_load_snap_disallowed_after_boot_resources(req, "SetVsockDevice");
 
         let req = VmmAction::SetVmConfiguration(VmConfig::default());
         verify_load_snap_disallowed_after_boot_resources(req, "SetVmConfiguration");
 
-        let req = VmmAction::SetMmdsConfiguration(MmdsConfig { ipv4_address: None });
+        let req = VmmAction::SetMmdsConfiguration(MmdsConfig {
+            version: MmdsVersion::V1,
+            ipv4_address: None,
+            ipv6_address: None,
+        });
         verify_load_snap_disallowed_after_boot_resources(req, "SetMmdsConfiguration");
     }
 }
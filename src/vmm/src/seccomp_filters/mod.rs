@@ -1,13 +1,23 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
-use seccompiler::{deserialize_binary, BpfThreadMap, DeserializationError, InstallationError};
+use logger::{error, IncMetric, METRICS};
+use seccompiler::{
+    deserialize_binary, sock_filter, BpfProgram, BpfProgramRef, BpfThreadMap, DeserializationError,
+    InstallationError,
+};
 
 use std::fmt;
 use std::fs::File;
 use std::io::{BufReader, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
-const THREAD_CATEGORIES: [&str; 3] = ["vmm", "api", "vcpu"];
+const THREAD_CATEGORIES: [&str; 4] = ["vmm", "api", "vcpu", "fs"];
+
+// The device thread categories which may be left out of a filter file, along with the category
+// whose filter they get instead. Their devices used to be served on that thread, so the filter
+// files written before the category existed keep working.
+const OPTIONAL_THREAD_CATEGORIES: [(&str, &str); 1] = [("fs", "vmm")];
 
 // This byte limit is passed to `bincode` to guard against a potential memory
 // allocation DOS caused by binary filters that are too large.
@@ -15,6 +25,22 @@ const THREAD_CATEGORIES: [&str; 3] = ["vmm", "api", "vcpu"];
 // filter is 4096 instructions and Firecracker has a finite number of threads.
 const DESERIALIZATION_BYTES_LIMIT: Option<u64> = Some(100_000);
 
+// The maximum seccomp-BPF program length allowed by the linux kernel.
+const BPF_MAX_LEN: usize = 4096;
+
+// BPF instruction fields, see /usr/include/linux/bpf_common.h .
+const BPF_JMP: u16 = 0x05;
+const BPF_JA: u16 = 0x00;
+const BPF_RET: u16 = 0x06;
+const BPF_K: u16 = 0x00;
+
+// See /usr/include/linux/seccomp.h .
+const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+
+// Whether the threads keep running without filtering when their filter can't be loaded. Only
+// set through `--seccomp-allow-load-failure`.
+static ALLOW_LOAD_FAILURE: AtomicBool = AtomicBool::new(false);
+
 /// Error retrieving seccomp filters.
 #[derive(fmt::Debug)]
 pub enum FilterError {
@@ -64,6 +90,9 @@ pub enum SeccompConfig {
     Advanced,
     /// Custom, user-provided filters.
     Custom(Box<dyn std::io::Read>),
+    /// Default, advanced filters, supplemented with user-provided filters.
+    /// A system call is allowed if either of the filters allows it.
+    Supplemented(Box<dyn std::io::Read>),
 }
 
 impl SeccompConfig {
@@ -72,9 +101,11 @@ impl SeccompConfig {
         seccomp_level: Option<&String>,
         no_seccomp: bool,
         seccomp_filter: Option<&String>,
+        seccomp_supplement: Option<&String>,
     ) -> Result<Self, FilterError> {
         // The argument parser is configured to forbid usages of `--seccomp-filter` or `--no-seccomp`
-        // together with `--seccomp-level`, so we do not have to check for it.
+        // together with `--seccomp-level`, and usages of `--seccomp-supplement` together with any
+        // of the other seccomp parameters, so we do not have to check for it.
         match seccomp_level {
             Some(value) => match &value[..] {
                 "0" => Ok(SeccompConfig::None),
@@ -90,7 +121,12 @@ impl SeccompConfig {
                     Some(path) => Ok(SeccompConfig::Custom(Box::new(
                         File::open(&path).map_err(FilterError::FileOpen)?,
                     ))),
-                    None => Ok(SeccompConfig::Advanced),
+                    None => match seccomp_supplement {
+                        Some(path) => Ok(SeccompConfig::Supplemented(Box::new(
+                            File::open(&path).map_err(FilterError::FileOpen)?,
+                        ))),
+                        None => Ok(SeccompConfig::Advanced),
+                    },
                 },
             },
         }
@@ -104,6 +140,31 @@ pub fn get_filters(config: SeccompConfig) -> Result<BpfThreadMap, FilterError> {
         SeccompConfig::Basic => get_default_filters(true),
         SeccompConfig::Advanced => get_default_filters(false),
         SeccompConfig::Custom(reader) => get_custom_filters(reader),
+        SeccompConfig::Supplemented(reader) => get_supplemented_filters(reader),
+    }
+}
+
+/// Lets the threads run without filtering when their filter can't be loaded, instead of failing.
+pub fn set_allow_load_failure(allow: bool) {
+    ALLOW_LOAD_FAILURE.store(allow, Ordering::SeqCst);
+}
+
+/// Loads `filter` on the calling thread.
+///
+/// A failure is returned to the caller, which doesn't start the thread or the microVM. Only if
+/// the failure was explicitly allowed through `set_allow_load_failure()`, it's logged and the
+/// thread keeps running without filtering.
+pub fn apply_filter(filter: BpfProgramRef) -> Result<(), InstallationError> {
+    match seccompiler::apply_filter(filter) {
+        Err(e) if ALLOW_LOAD_FAILURE.load(Ordering::SeqCst) => {
+            error!(
+                "Failed to load the seccomp filter, the thread runs without filtering: {}",
+                e
+            );
+            METRICS.seccomp.load_fails.inc();
+            Ok(())
+        }
+        result => result,
     }
 }
 
@@ -126,6 +187,7 @@ fn get_empty_filters() -> BpfThreadMap {
     map.insert("vmm".to_string(), Arc::new(vec![]));
     map.insert("api".to_string(), Arc::new(vec![]));
     map.insert("vcpu".to_string(), Arc::new(vec![]));
+    map.insert("fs".to_string(), Arc::new(vec![]));
     map
 }
 
@@ -136,9 +198,59 @@ fn get_custom_filters<R: Read>(reader: R) -> Result<BpfThreadMap, FilterError> {
     filter_thread_categories(map)
 }
 
+/// Retrieve the default, advanced filters, supplemented with the custom filters.
+fn get_supplemented_filters<R: Read>(reader: R) -> Result<BpfThreadMap, FilterError> {
+    let supplements = get_custom_filters(reader)?;
+    get_default_filters(false)?
+        .into_iter()
+        .map(|(category, filter)| {
+            // Both maps contain all the thread categories, as checked when retrieving them.
+            let merged = merge_filters(&filter, &supplements[&category])?;
+            Ok((category, Arc::new(merged)))
+        })
+        .collect()
+}
+
+/// Merge two BPF programs into one which allows the system calls allowed by either of them.
+///
+/// Every return of an action other than allow in `filter` is turned into a jump to the start
+/// of `supplement`, which then decides the action. An empty program doesn't filter anything,
+/// so merging it either way leaves `filter` unchanged.
+fn merge_filters(
+    filter: &[sock_filter],
+    supplement: &[sock_filter],
+) -> Result<BpfProgram, FilterError> {
+    if filter.is_empty() || supplement.is_empty() {
+        return Ok(filter.to_vec());
+    }
+    if filter.len() + supplement.len() > BPF_MAX_LEN {
+        return Err(FilterError::Install(InstallationError::FilterTooLarge));
+    }
+
+    let mut merged: BpfProgram = filter
+        .iter()
+        .enumerate()
+        .map(|(pc, instruction)| {
+            if instruction.code == BPF_RET | BPF_K && instruction.k != SECCOMP_RET_ALLOW {
+                // Jumps are relative to the next instruction.
+                sock_filter {
+                    code: BPF_JMP | BPF_JA,
+                    jt: 0,
+                    jf: 0,
+                    k: (filter.len() - pc - 1) as u32,
+                }
+            } else {
+                instruction.clone()
+            }
+        })
+        .collect();
+    merged.extend_from_slice(supplement);
+    Ok(merged)
+}
+
 /// Return an error if the BpfThreadMap contains invalid thread categories.
 fn filter_thread_categories(map: BpfThreadMap) -> Result<BpfThreadMap, FilterError> {
-    let (filters, invalid_filters): (BpfThreadMap, BpfThreadMap) = map
+    let (mut filters, invalid_filters): (BpfThreadMap, BpfThreadMap) = map
         .into_iter()
         .partition(|(k, _)| THREAD_CATEGORIES.contains(&k.as_str()));
     if !invalid_filters.is_empty() {
@@ -155,6 +267,14 @@ fn filter_thread_categories(map: BpfThreadMap) -> Result<BpfThreadMap, FilterErr
         return Err(FilterError::ThreadCategories(thread_categories_string));
     }
 
+    for &(category, fallback) in OPTIONAL_THREAD_CATEGORIES.iter() {
+        if !filters.contains_key(category) {
+            if let Some(filter) = filters.get(fallback).cloned() {
+                filters.insert(category.to_string(), filter);
+            }
+        }
+    }
+
     for &category in THREAD_CATEGORIES.iter() {
         let category_string = category.to_string();
         if !filters.contains_key(&category_string) {
@@ -171,29 +291,108 @@ mod tests {
     use seccompiler::BpfThreadMap;
     use utils::tempfile::TempFile;
 
+    #[test]
+    fn test_apply_filter_load_failure() {
+        // The filter is too large to be loaded.
+        let filter = vec![
+            sock_filter {
+                code: BPF_RET | BPF_K,
+                jt: 0,
+                jf: 0,
+                k: SECCOMP_RET_ALLOW,
+            };
+            BPF_MAX_LEN + 1
+        ];
+        assert!(matches!(
+            apply_filter(&filter),
+            Err(InstallationError::FilterTooLarge)
+        ));
+
+        let load_fails = METRICS.seccomp.load_fails.count();
+        set_allow_load_failure(true);
+        let result = apply_filter(&filter);
+        set_allow_load_failure(false);
+        assert!(result.is_ok());
+        assert_eq!(METRICS.seccomp.load_fails.count(), load_fails + 1);
+    }
+
     #[test]
     fn test_get_filters() {
         let mut filters = get_filters(SeccompConfig::Basic).unwrap();
-        assert_eq!(filters.len(), 3);
+        assert_eq!(filters.len(), 4);
         assert!(filters.remove("vmm").is_some());
         assert!(filters.remove("api").is_some());
         assert!(filters.remove("vcpu").is_some());
+        assert!(filters.remove("fs").is_some());
 
         let mut filters = get_filters(SeccompConfig::Advanced).unwrap();
-        assert_eq!(filters.len(), 3);
+        assert_eq!(filters.len(), 4);
         assert!(filters.remove("vmm").is_some());
         assert!(filters.remove("api").is_some());
         assert!(filters.remove("vcpu").is_some());
+        assert!(filters.remove("fs").is_some());
 
         let mut filters = get_filters(SeccompConfig::None).unwrap();
-        assert_eq!(filters.len(), 3);
+        assert_eq!(filters.len(), 4);
         assert_eq!(filters.remove("vmm").unwrap().len(), 0);
         assert_eq!(filters.remove("api").unwrap().len(), 0);
         assert_eq!(filters.remove("vcpu").unwrap().len(), 0);
+        assert_eq!(filters.remove("fs").unwrap().len(), 0);
 
         let file = TempFile::new().unwrap().into_file();
 
         assert!(get_filters(SeccompConfig::Custom(Box::new(file))).is_err());
+
+        // A malformed supplement fails instead of falling back to the default filters.
+        let file = TempFile::new().unwrap().into_file();
+
+        assert!(get_filters(SeccompConfig::Supplemented(Box::new(file))).is_err());
+    }
+
+    #[test]
+    fn test_merge_filters() {
+        let ret = |k| sock_filter {
+            code: BPF_RET | BPF_K,
+            jt: 0,
+            jf: 0,
+            k,
+        };
+        let jeq = |k, jt, jf| sock_filter {
+            code: BPF_JMP | 0x10 | BPF_K,
+            jt,
+            jf,
+            k,
+        };
+        // Allows system call 1, traps on anything else.
+        let filter = vec![jeq(1, 0, 1), ret(SECCOMP_RET_ALLOW), ret(0x0003_0000)];
+        // Allows system call 2, kills the process on anything else.
+        let supplement = vec![jeq(2, 0, 1), ret(SECCOMP_RET_ALLOW), ret(0x8000_0000)];
+
+        let merged = merge_filters(&filter, &supplement).unwrap();
+        assert_eq!(merged.len(), 6);
+        assert_eq!(merged[..2], filter[..2]);
+        // The trap is replaced by a jump to the supplement.
+        assert_eq!(
+            merged[2],
+            sock_filter {
+                code: BPF_JMP | BPF_JA,
+                jt: 0,
+                jf: 0,
+                k: 0,
+            }
+        );
+        assert_eq!(merged[3..], supplement[..]);
+
+        // Empty programs are not merged.
+        assert_eq!(merge_filters(&filter, &[]).unwrap(), filter);
+        assert!(merge_filters(&[], &supplement).unwrap().is_empty());
+
+        // The merged program can't exceed the maximum length.
+        let supplement = vec![ret(SECCOMP_RET_ALLOW); BPF_MAX_LEN];
+        assert!(matches!(
+            merge_filters(&filter, &supplement),
+            Err(FilterError::Install(InstallationError::FilterTooLarge))
+        ));
     }
 
     #[test]
@@ -203,8 +402,25 @@ mod tests {
         map.insert("vcpu".to_string(), Arc::new(vec![]));
         map.insert("vmm".to_string(), Arc::new(vec![]));
         map.insert("api".to_string(), Arc::new(vec![]));
+        map.insert("fs".to_string(), Arc::new(vec![]));
 
-        assert_eq!(filter_thread_categories(map).unwrap().len(), 3);
+        assert_eq!(filter_thread_categories(map).unwrap().len(), 4);
+
+        // the fs threads get the VMM thread filter when their category is left out
+        let vmm_filter = Arc::new(vec![sock_filter {
+            code: BPF_RET | BPF_K,
+            jt: 0,
+            jf: 0,
+            k: SECCOMP_RET_ALLOW,
+        }]);
+        let mut map = BpfThreadMap::new();
+        map.insert("vcpu".to_string(), Arc::new(vec![]));
+        map.insert("vmm".to_string(), vmm_filter.clone());
+        map.insert("api".to_string(), Arc::new(vec![]));
+
+        let filters = filter_thread_categories(map).unwrap();
+        assert_eq!(filters.len(), 4);
+        assert_eq!(filters["fs"], vmm_filter);
 
         // invalid categories
         let mut map = BpfThreadMap::new();
@@ -235,44 +451,54 @@ mod tests {
     fn test_seccomp_config() {
         // test deprecated seccomp-level config.
         assert!(matches!(
-            SeccompConfig::from_args(Some(&"0".to_string()), false, None),
+            SeccompConfig::from_args(Some(&"0".to_string()), false, None, None),
             Ok(SeccompConfig::None)
         ));
 
         assert!(matches!(
-            SeccompConfig::from_args(Some(&"1".to_string()), false, None),
+            SeccompConfig::from_args(Some(&"1".to_string()), false, None, None),
             Ok(SeccompConfig::Basic)
         ));
 
         assert!(matches!(
-            SeccompConfig::from_args(Some(&"2".to_string()), false, None),
+            SeccompConfig::from_args(Some(&"2".to_string()), false, None, None),
             Ok(SeccompConfig::Advanced)
         ));
 
         assert!(matches!(
-            SeccompConfig::from_args(Some(&"3".to_string()), false, None),
+            SeccompConfig::from_args(Some(&"3".to_string()), false, None, None),
             Err(FilterError::SeccompConfig(_))
         ));
 
         // test new seccomp parameters config.
         assert!(matches!(
-            SeccompConfig::from_args(None, true, None),
+            SeccompConfig::from_args(None, true, None, None),
             Ok(SeccompConfig::None)
         ));
 
         assert!(matches!(
-            SeccompConfig::from_args(None, false, Some(&"/dev/null".to_string())),
+            SeccompConfig::from_args(None, false, Some(&"/dev/null".to_string()), None),
             Ok(SeccompConfig::Custom(_))
         ));
 
         assert!(matches!(
-            SeccompConfig::from_args(None, false, Some(&"invalid_path".to_string())),
+            SeccompConfig::from_args(None, false, Some(&"invalid_path".to_string()), None),
+            Err(FilterError::FileOpen(_))
+        ));
+
+        assert!(matches!(
+            SeccompConfig::from_args(None, false, None, Some(&"/dev/null".to_string())),
+            Ok(SeccompConfig::Supplemented(_))
+        ));
+
+        assert!(matches!(
+            SeccompConfig::from_args(None, false, None, Some(&"invalid_path".to_string())),
             Err(FilterError::FileOpen(_))
         ));
 
         // test the default case, no parametes -> default advanced.
         assert!(matches!(
-            SeccompConfig::from_args(None, false, None),
+            SeccompConfig::from_args(None, false, None, None),
             Ok(SeccompConfig::Advanced)
         ));
     }
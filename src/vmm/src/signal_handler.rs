@@ -1,10 +1,14 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::sync::atomic::Ordering;
+
 use libc::{
-    c_int, c_void, siginfo_t, SIGBUS, SIGHUP, SIGILL, SIGPIPE, SIGSEGV, SIGSYS, SIGXCPU, SIGXFSZ,
+    c_int, c_void, siginfo_t, SIGBUS, SIGHUP, SIGILL, SIGPIPE, SIGSEGV, SIGSYS, SIGUSR1, SIGXCPU,
+    SIGXFSZ,
 };
 
+use crate::persist::SNAPSHOT_CANCEL_REQUESTED;
 use crate::{ExitCode, FC_EXIT_CODE_UNEXPECTED_ERROR};
 use logger::{error, IncMetric, METRICS};
 use utils::signal::register_signal_handler;
@@ -16,6 +20,19 @@ use utils::signal::register_signal_handler;
 // See https://github.com/rust-lang/libc/issues/716 for why the offset is different in Rust.
 const SI_OFF_SYSCALL: isize = 6;
 
+// The offsets of the registers holding the arguments of the offending syscall within the
+// `ucontext` structure passed to the signal handler, expressed as an `u64`.
+// They follow the kernel definitions of `struct ucontext` and `struct sigcontext`, found in
+// arch/<arch>/include/uapi/asm/, rather than the `mcontext_t` of the libc crate, which differs
+// between the gnu and musl targets.
+// On x86_64, `uc_mcontext` is at offset 40 and the arguments are in rdi, rsi, rdx, r10, r8, r9.
+#[cfg(target_arch = "x86_64")]
+const UC_OFF_SYSCALL_ARGS: [isize; 6] = [13, 14, 17, 7, 5, 6];
+// On aarch64, `uc_mcontext` is at offset 176 and the arguments are in x0 to x5, right after
+// the `fault_address` field.
+#[cfg(target_arch = "aarch64")]
+const UC_OFF_SYSCALL_ARGS: [isize; 6] = [23, 24, 25, 26, 27, 28];
+
 const SYS_SECCOMP_CODE: i32 = 1;
 
 #[inline]
@@ -31,7 +48,7 @@ fn exit_with_code(exit_code: ExitCode) {
 macro_rules! generate_handler {
     ($fn_name:ident ,$signal_name:ident, $exit_code:ident, $signal_metric:expr, $body:ident) => {
         #[inline(always)]
-        extern "C" fn $fn_name(num: c_int, info: *mut siginfo_t, _unused: *mut c_void) {
+        extern "C" fn $fn_name(num: c_int, info: *mut siginfo_t, context: *mut c_void) {
             // Safe because we're just reading some fields from a supposedly valid argument.
             let si_signo = unsafe { (*info).si_signo };
             let si_code = unsafe { (*info).si_code };
@@ -46,7 +63,7 @@ macro_rules! generate_handler {
                 si_signo, si_code
             );
 
-            $body(si_code, info);
+            $body(si_code, info, context);
 
             #[cfg(not(test))]
             match si_signo {
@@ -57,7 +74,7 @@ macro_rules! generate_handler {
     };
 }
 
-fn log_sigsys_err(si_code: c_int, info: *mut siginfo_t) {
+fn log_sigsys_err(si_code: c_int, info: *mut siginfo_t, context: *mut c_void) {
     if si_code != SYS_SECCOMP_CODE as i32 {
         // We received a SIGSYS for a reason other than `bad syscall`.
         exit_with_code(FC_EXIT_CODE_UNEXPECTED_ERROR);
@@ -66,13 +83,27 @@ fn log_sigsys_err(si_code: c_int, info: *mut siginfo_t) {
     // Other signals which might do async unsafe things incompatible with the rest of this
     // function are blocked due to the sa_mask used when registering the signal handler.
     let syscall = unsafe { *(info as *const i32).offset(SI_OFF_SYSCALL) as usize };
+    // Safe because the kernel passes a valid `ucontext` to the handlers registered
+    // with `SA_SIGINFO`.
+    let args = unsafe { syscall_args(context) };
     error!(
-        "Shutting down VM after intercepting a bad syscall ({}).",
-        syscall
+        "Shutting down VM after intercepting a bad syscall ({}), with the arguments \
+         {:#x}, {:#x}, {:#x}, {:#x}, {:#x}, {:#x}.",
+        syscall, args[0], args[1], args[2], args[3], args[4], args[5]
     );
 }
 
-fn empty_fn(_si_code: c_int, _info: *mut siginfo_t) {}
+// Reads the arguments of the syscall which raised a `SIGSYS` from the registers saved in the
+// `ucontext` of the signal handler.
+unsafe fn syscall_args(context: *const c_void) -> [u64; 6] {
+    let mut args = [0u64; 6];
+    for (arg, offset) in args.iter_mut().zip(UC_OFF_SYSCALL_ARGS.iter()) {
+        *arg = *(context as *const u64).offset(*offset);
+    }
+    args
+}
+
+fn empty_fn(_si_code: c_int, _info: *mut siginfo_t, _context: *mut c_void) {}
 
 generate_handler!(
     sigxfsz_handler,
@@ -148,10 +179,27 @@ extern "C" fn sigpipe_handler(num: c_int, info: *mut siginfo_t, _unused: *mut c_
     error!("Received signal {}, code {}.", si_signo, si_code);
 }
 
+#[inline(always)]
+extern "C" fn sigusr1_handler(num: c_int, info: *mut siginfo_t, _unused: *mut c_void) {
+    // Request the cancellation of the snapshot being created, if any. The snapshot
+    // creation path takes care of the cleanup and of resuming the microVM.
+
+    // Safe because we're just reading some fields from a supposedly valid argument.
+    let si_signo = unsafe { (*info).si_signo };
+    let si_code = unsafe { (*info).si_code };
+
+    if num != si_signo || num != SIGUSR1 {
+        error!("Received invalid signal {}, code {}.", si_signo, si_code);
+        return;
+    }
+
+    SNAPSHOT_CANCEL_REQUESTED.store(true, Ordering::SeqCst);
+}
+
 /// Registers all the required signal handlers.
 ///
 /// Custom handlers are installed for: `SIGBUS`, `SIGSEGV`, `SIGSYS`
-/// `SIGXFSZ` `SIGXCPU` `SIGPIPE` `SIGHUP` and `SIGILL`.
+/// `SIGXFSZ` `SIGXCPU` `SIGPIPE` `SIGHUP` `SIGILL` and `SIGUSR1`.
 pub fn register_signal_handlers() -> utils::errno::Result<()> {
     // Call to unsafe register_signal_handler which is considered unsafe because it will
     // register a signal handler which will be called in the current thread and will interrupt
@@ -165,6 +213,7 @@ pub fn register_signal_handlers() -> utils::errno::Result<()> {
     register_signal_handler(SIGPIPE, sigpipe_handler)?;
     register_signal_handler(SIGHUP, sighup_handler)?;
     register_signal_handler(SIGILL, sigill_handler)?;
+    register_signal_handler(SIGUSR1, sigusr1_handler)?;
     Ok(())
 }
 
@@ -281,6 +330,20 @@ mod tests {
         assert!(METRICS.signals.sigill.count() >= 1);
     }
 
+    #[test]
+    fn test_syscall_args() {
+        // A buffer large enough for the registers of any architecture, each holding its index.
+        let mut context = [0u64; 64];
+        for (i, reg) in context.iter_mut().enumerate() {
+            *reg = i as u64;
+        }
+
+        let args = unsafe { syscall_args(context.as_ptr() as *const c_void) };
+        for (arg, offset) in args.iter().zip(UC_OFF_SYSCALL_ARGS.iter()) {
+            assert_eq!(*arg, *offset as u64);
+        }
+    }
+
     fn make_test_seccomp_bpf_filter() -> Vec<sock_filter> {
         // Create seccomp filter that allows all syscalls, except for `SYS_mkdirat`.
         // For some reason, directly calling `SYS_kill` with SIGSYS, like we do with the
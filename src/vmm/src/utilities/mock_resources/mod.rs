@@ -6,7 +6,7 @@ use std::path::PathBuf;
 
 use crate::resources::VmResources;
 use crate::vmm_config::boot_source::BootSourceConfig;
-use crate::vmm_config::machine_config::VmConfig;
+use crate::vmm_config::machine_config::{RebootAction, VmConfig};
 
 pub const DEFAULT_BOOT_ARGS: &str = "reboot=k panic=1 pci=off";
 #[cfg(target_arch = "x86_64")]
@@ -43,6 +43,7 @@ impl MockBootSourceConfig {
             kernel_image_path: kernel_image_path(None),
             initrd_path: None,
             boot_args: None,
+            rng_seed: None,
         })
     }
 
@@ -95,6 +96,11 @@ impl MockVmConfig {
         self.0.track_dirty_pages = true;
         self
     }
+
+    pub fn with_restart_on_reboot(mut self) -> Self {
+        self.0.on_reboot = Some(RebootAction::Restart);
+        self
+    }
 }
 
 generate_from!(MockBootSourceConfig, BootSourceConfig);
@@ -6,9 +6,15 @@
 use std::collections::HashMap;
 
 use crate::device_manager::persist::DeviceStates;
+use crate::persist::VmInfo;
 #[cfg(target_arch = "x86_64")]
 use crate::vstate::vcpu::VcpuState;
+#[cfg(target_arch = "x86_64")]
+use crate::vstate::vm::VmState;
 use devices::virtio::block::persist::BlockState;
+use devices::virtio::net::persist::{NetConfigSpaceState, NetState};
+use devices::virtio::vsock::persist::VsockUdsState;
+use mmds::persist::MmdsNetworkStackState;
 
 use lazy_static::lazy_static;
 use versionize::VersionMap;
@@ -28,6 +34,14 @@ lazy_static! {
         version_map.new_version().set_type_version(BlockState::type_id(), 2);
         #[cfg(target_arch = "x86_64")]
         version_map.set_type_version(VcpuState::type_id(), 2);
+        #[cfg(target_arch = "x86_64")]
+        version_map.set_type_version(VmState::type_id(), 2);
+        version_map.set_type_version(MmdsNetworkStackState::type_id(), 2);
+        version_map.set_type_version(DeviceStates::type_id(), 3);
+        version_map.set_type_version(NetState::type_id(), 2);
+        version_map.set_type_version(NetConfigSpaceState::type_id(), 2);
+        version_map.set_type_version(VsockUdsState::type_id(), 2);
+        version_map.set_type_version(VmInfo::type_id(), 2);
 
         version_map
     };
@@ -83,6 +83,9 @@ pub struct BalloonDeviceConfig {
     /// Interval in seconds between refreshing statistics.
     #[serde(default)]
     pub stats_polling_interval_s: u16,
+    /// Option to free the guest memory which the driver reports as unused.
+    #[serde(default)]
+    pub free_page_reporting: bool,
 }
 
 impl From<BalloonConfig> for BalloonDeviceConfig {
@@ -91,6 +94,7 @@ impl From<BalloonConfig> for BalloonDeviceConfig {
             amount_mib: state.amount_mib,
             deflate_on_oom: state.deflate_on_oom,
             stats_polling_interval_s: state.stats_polling_interval_s,
+            free_page_reporting: state.free_page_reporting,
         }
     }
 }
@@ -115,6 +119,48 @@ pub struct BalloonUpdateStatsConfig {
     pub stats_polling_interval_s: u16,
 }
 
+/// The memory usage of the guest, as last reported in the balloon device statistics. All the
+/// figures are in bytes, those the guest did not report are left out.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct GuestMemoryStats {
+    /// The amount of memory available to the guest.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_bytes: Option<u64>,
+    /// The amount of memory the guest doesn't use at all.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub free_bytes: Option<u64>,
+    /// The amount of memory the guest uses, besides its disk caches.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used_bytes: Option<u64>,
+    /// The amount of memory the guest uses for its disk caches, which it can reclaim.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cached_bytes: Option<u64>,
+    /// An estimate of the memory the guest can use for new processes, without swapping.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub available_bytes: Option<u64>,
+}
+
+impl From<&BalloonStats> for GuestMemoryStats {
+    fn from(stats: &BalloonStats) -> Self {
+        let used_bytes = match (stats.total_memory, stats.free_memory) {
+            (Some(total), Some(free)) => Some(
+                total
+                    .saturating_sub(free)
+                    .saturating_sub(stats.disk_caches.unwrap_or(0)),
+            ),
+            _ => None,
+        };
+        GuestMemoryStats {
+            total_bytes: stats.total_memory,
+            free_bytes: stats.free_memory,
+            used_bytes,
+            cached_bytes: stats.disk_caches,
+            available_bytes: stats.available_memory,
+        }
+    }
+}
+
 /// A builder for `Balloon` devices from 'BalloonDeviceConfig'.
 pub struct BalloonBuilder {
     inner: Option<MutexBalloon>,
@@ -141,6 +187,7 @@ impl BalloonBuilder {
                 cfg.amount_mib,
                 cfg.deflate_on_oom,
                 cfg.stats_polling_interval_s,
+                cfg.free_page_reporting,
                 // `restored` flag is false because this code path
                 // is never called by snapshot restore functionality.
                 false,
@@ -174,6 +221,7 @@ pub(crate) mod tests {
             amount_mib: 0,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            free_page_reporting: false,
         }
     }
 
@@ -192,6 +240,7 @@ pub(crate) mod tests {
             amount_mib: 0,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            free_page_reporting: false,
         };
         assert_eq!(default_balloon_config, balloon_config);
         let mut builder = BalloonBuilder::new();
@@ -213,17 +262,53 @@ pub(crate) mod tests {
             amount_mib: 5,
             deflate_on_oom: false,
             stats_polling_interval_s: 3,
+            free_page_reporting: false,
         };
 
         let actual_balloon_config = BalloonDeviceConfig::from(BalloonConfig {
             amount_mib: 5,
             deflate_on_oom: false,
             stats_polling_interval_s: 3,
+            free_page_reporting: false,
         });
 
         assert_eq!(expected_balloon_config, actual_balloon_config);
     }
 
+    #[test]
+    fn test_guest_memory_stats() {
+        // Nothing is reported before the guest reports its statistics.
+        let stats = BalloonStats::default();
+        assert_eq!(GuestMemoryStats::from(&stats), GuestMemoryStats::default());
+
+        let mut stats = BalloonStats {
+            total_memory: Some(1000),
+            free_memory: Some(300),
+            available_memory: Some(600),
+            ..Default::default()
+        };
+        let memory_stats = GuestMemoryStats::from(&stats);
+        assert_eq!(memory_stats.total_bytes, Some(1000));
+        assert_eq!(memory_stats.free_bytes, Some(300));
+        assert_eq!(memory_stats.used_bytes, Some(700));
+        assert_eq!(memory_stats.cached_bytes, None);
+        assert_eq!(memory_stats.available_bytes, Some(600));
+
+        // The disk caches are not counted as used.
+        stats.disk_caches = Some(200);
+        let memory_stats = GuestMemoryStats::from(&stats);
+        assert_eq!(memory_stats.used_bytes, Some(500));
+        assert_eq!(memory_stats.cached_bytes, Some(200));
+        assert_eq!(
+            serde_json::to_string(&memory_stats).unwrap(),
+            r#"{"total_bytes":1000,"free_bytes":300,"used_bytes":500,"cached_bytes":200,"available_bytes":600}"#
+        );
+
+        // The used memory can't be computed without the free memory.
+        stats.free_memory = None;
+        assert_eq!(GuestMemoryStats::from(&stats).used_bytes, None);
+    }
+
     #[test]
     fn test_error_messages() {
         use super::BalloonConfigError::*;
@@ -20,6 +20,9 @@ use serde::{Deserialize, Serialize};
 pub const DEFAULT_KERNEL_CMDLINE: &str = "reboot=k panic=1 pci=off nomodules 8250.nr_uarts=0 \
                                           i8042.noaux i8042.nomux i8042.nopnp i8042.dumbkbd";
 
+/// Size, in bytes, of the random seed given to the guest kernel at boot.
+pub const RNG_SEED_SIZE: usize = 32;
+
 /// Strongly typed data structure used to configure the boot source of the
 /// microvm.
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
@@ -33,6 +36,22 @@ pub struct BootSourceConfig {
     /// kernel command line is used: `reboot=k panic=1 pci=off nomodules 8250.nr_uarts=0`.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub boot_args: Option<String>,
+    /// Whether the guest kernel is given a random seed from the host CSPRNG at boot, to seed its
+    /// CRNG before any entropy source is available. The seed is generated anew for every boot.
+    /// If this field is uninitialized, the seed is given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rng_seed: Option<bool>,
+}
+
+/// Strongly typed data structure used to update the boot source of the microvm, before boot.
+/// The fields which are not specified keep their current values.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BootSourceUpdateConfig {
+    /// New path of the kernel image.
+    pub kernel_image_path: Option<String>,
+    /// New boot arguments to pass to the kernel.
+    pub boot_args: Option<String>,
 }
 
 impl From<&BootConfig> for BootSourceConfig {
@@ -50,6 +69,8 @@ pub enum BootSourceConfigError {
     InvalidInitrdPath(io::Error),
     /// The kernel command line is invalid.
     InvalidKernelCommandLine(String),
+    /// The boot source cannot be updated before being configured.
+    MissingBootSource,
 }
 
 impl Display for BootSourceConfigError {
@@ -66,6 +87,10 @@ impl Display for BootSourceConfigError {
             InvalidKernelCommandLine(ref e) => {
                 write!(f, "The kernel command line is invalid: {}", e.as_str())
             }
+            MissingBootSource => write!(
+                f,
+                "The boot source must be configured before it can be updated."
+            ),
         }
     }
 }
@@ -109,7 +134,7 @@ impl BootConfig {
             cmdline,
             kernel_file,
             initrd_file,
-            // We can simply store original config since it doesn't support updates.
+            // Updates build a new `BootConfig`, so the original config can simply be stored.
             description: cfg,
         })
     }
@@ -129,6 +154,7 @@ pub(crate) mod tests {
             boot_args: None,
             initrd_path: None,
             kernel_image_path: kernel_path,
+            rng_seed: None,
         };
 
         let boot_cfg = BootConfig::new(boot_src_cfg.clone()).unwrap();
@@ -0,0 +1,105 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::{Display, Formatter};
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+/// Serializable struct that describes a device attached to the MMIO bus of the microVM.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MmioDeviceDescription {
+    /// The type of the device, e.g. `net` or `block` for virtio devices.
+    pub device_type: String,
+    /// The ID of the device.
+    pub id: String,
+    /// The guest physical address at which the device registers are mapped.
+    pub mmio_base: u64,
+    /// The IRQ line of the device, if it uses one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub irq: Option<u32>,
+    /// The feature bits offered by a virtio device to the guest driver.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avail_features: Option<u64>,
+    /// The feature bits of a virtio device acked by the guest driver, known once the driver
+    /// has activated the device.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub acked_features: Option<u64>,
+}
+
+/// The virtio devices which can be reset through the API.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResettableDeviceType {
+    /// A virtio block device.
+    Block,
+    /// A virtio net device.
+    Net,
+}
+
+/// Identifies the device whose guest driver is asked to reset it.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceResetConfig {
+    /// The type of the device.
+    pub device_type: ResettableDeviceType,
+    /// The ID of the device.
+    pub id: String,
+}
+
+/// Errors associated with resetting a device.
+#[derive(Debug)]
+pub enum DeviceResetError {
+    /// The device isn't activated by the guest driver, so there is nothing to reset.
+    DeviceNotActivated(String),
+    /// No device of the requested type has the given ID.
+    DeviceNotFound(String),
+    /// Cannot notify the guest driver.
+    SignalDevice(io::Error),
+}
+
+impl Display for DeviceResetError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        use self::DeviceResetError::*;
+        match self {
+            DeviceNotActivated(id) => {
+                write!(f, "The device {} is not activated by the guest driver.", id)
+            }
+            DeviceNotFound(id) => write!(f, "Cannot find the device {}.", id),
+            SignalDevice(err) => write!(f, "Cannot notify the guest driver: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_device_reset_config() {
+        let config: DeviceResetConfig =
+            serde_json::from_str(r#"{"device_type": "net", "id": "eth0"}"#).unwrap();
+        assert_eq!(
+            config,
+            DeviceResetConfig {
+                device_type: ResettableDeviceType::Net,
+                id: "eth0".to_string(),
+            }
+        );
+        assert!(serde_json::from_str::<DeviceResetConfig>(
+            r#"{"device_type": "vsock", "id": "vsock0"}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_error_messages() {
+        let err = DeviceResetError::DeviceNotActivated("rootfs".to_string());
+        assert_eq!(
+            err.to_string(),
+            "The device rootfs is not activated by the guest driver."
+        );
+        let err = DeviceResetError::DeviceNotFound("rootfs".to_string());
+        assert_eq!(err.to_string(), "Cannot find the device rootfs.");
+    }
+}
@@ -9,11 +9,13 @@ use std::path::PathBuf;
 use std::result;
 use std::sync::{Arc, Mutex};
 
-use super::RateLimiterConfig;
+use super::{RateLimiterConfig, MAX_DEVICE_IRQS};
 use crate::Error as VmmError;
+use devices::virtio::block::SECTOR_SIZE;
 use devices::virtio::Block;
 
-pub use devices::virtio::CacheType;
+pub use devices::virtio::block::device::{MAX_OPEN_RETRIES, MAX_OPEN_RETRY_BACKOFF_MS};
+pub use devices::virtio::{AccessPattern, CacheType, DiskBacking, OpenRetryConfig};
 
 use serde::{Deserialize, Serialize};
 use std::ops::Deref;
@@ -25,6 +27,10 @@ type Result<T> = result::Result<T, DriveError>;
 pub enum DriveError {
     /// Cannot update the block device.
     BlockDeviceUpdateFailed(io::Error),
+    /// Another root block device has the same boot index.
+    BootIndexAlreadyUsed(u32),
+    /// A boot index was set on a block device which is not a root device.
+    BootIndexWithoutRootDevice,
     /// Unable to seek the block device backing file due to invalid permissions or
     /// the file was corrupted.
     CreateBlockDevice(io::Error),
@@ -32,12 +38,20 @@ pub enum DriveError {
     CreateRateLimiter(io::Error),
     /// Error during drive update (patch).
     DeviceUpdate(VmmError),
+    /// There is no drive with the given ID.
+    DriveNotFound(String),
     /// The block device path is invalid.
     InvalidBlockDevicePath,
+    /// The size of a memory backed drive is not a non-zero multiple of the sector size.
+    InvalidMemoryBackingSize(u64),
+    /// The retry configuration for opening the backing file is out of bounds.
+    InvalidOpenRetryConfig,
     /// Cannot open block device due to invalid permissions or path.
     OpenBlockDevice(io::Error),
     /// A root block device was already added.
     RootBlockDeviceAlreadyAdded,
+    /// There is no IRQ left for another device.
+    TooManyDevices,
 }
 
 impl Display for DriveError {
@@ -51,15 +65,41 @@ impl Display for DriveError {
                 e
             ),
             BlockDeviceUpdateFailed(e) => write!(f, "The update operation failed: {}", e),
+            BootIndexAlreadyUsed(index) => write!(
+                f,
+                "A root block device with the boot index {} already exists!",
+                index
+            ),
+            BootIndexWithoutRootDevice => {
+                write!(f, "Only root block devices can have a boot index!")
+            }
             CreateRateLimiter(e) => write!(f, "Cannot create RateLimiter: {}", e),
             DeviceUpdate(e) => write!(f, "Error during drive update (patch): {}", e),
+            DriveNotFound(drive_id) => write!(f, "No drive with the ID {} exists!", drive_id),
             InvalidBlockDevicePath => write!(f, "Invalid block device path!"),
+            InvalidMemoryBackingSize(size) => write!(
+                f,
+                "Invalid size {} of the memory backed drive. It must be a non-zero multiple of \
+                 the {} bytes sector size.",
+                size, SECTOR_SIZE
+            ),
+            InvalidOpenRetryConfig => write!(
+                f,
+                "Invalid backing file open retry configuration. The number of retries cannot \
+                 exceed {} and the backoff cannot exceed {} ms.",
+                MAX_OPEN_RETRIES, MAX_OPEN_RETRY_BACKOFF_MS
+            ),
             OpenBlockDevice(e) => write!(
                 f,
                 "Cannot open block device. Invalid permission/path: {}",
                 e
             ),
             RootBlockDeviceAlreadyAdded => write!(f, "A root block device already exists!"),
+            TooManyDevices => write!(
+                f,
+                "Cannot attach another device: all the {} IRQs available to the devices are in use.",
+                MAX_DEVICE_IRQS
+            ),
         }
     }
 }
@@ -70,8 +110,13 @@ impl Display for DriveError {
 pub struct BlockDeviceConfig {
     /// Unique identifier of the drive.
     pub drive_id: String,
-    /// Path of the drive.
+    /// Path of the drive. It is not used by memory backed drives.
+    #[serde(default)]
     pub path_on_host: String,
+    /// Storage backing the drive: the host file found at `path_on_host`, or an anonymous
+    /// memory region of the given size in bytes, discarded when the microVM stops.
+    #[serde(default)]
+    pub backing: DiskBacking,
     /// If set to true, it makes the current device the root block device.
     /// Setting this flag to true will mount the block device in the
     /// guest under /dev/vda unless the partuuid is present.
@@ -79,6 +124,10 @@ pub struct BlockDeviceConfig {
     /// Part-UUID. Represents the unique id of the boot partition of this device. It is
     /// optional and it will be used only if the `is_root_device` field is true.
     pub partuuid: Option<String>,
+    /// Boot order of the root block devices. It is required when there are several root block
+    /// devices, in which case the one with the lowest boot index is used as the guest root.
+    #[serde(default)]
+    pub boot_index: Option<u32>,
     /// If set to true, the drive is opened in read-only mode. Otherwise, the
     /// drive is opened as read-write.
     pub is_read_only: bool,
@@ -86,6 +135,18 @@ pub struct BlockDeviceConfig {
     /// the guest driver.
     #[serde(default = "CacheType::default")]
     pub cache_type: CacheType,
+    /// Retries performed when opening the backing file fails with a transient error.
+    #[serde(default = "OpenRetryConfig::default")]
+    pub open_retry: OpenRetryConfig,
+    /// Hint about how the guest accesses the drive, tuning the host read-ahead of the backing
+    /// file. No hint is given by default.
+    #[serde(default)]
+    pub access_pattern: AccessPattern,
+    /// Minimum time between two used queue interrupts, in microseconds. The interrupts
+    /// requested sooner are merged into one, raised at the end of the interval. Every
+    /// interrupt is raised right away by default.
+    #[serde(default)]
+    pub interrupt_coalescing_us: u64,
     /// Rate Limiter for I/O operations.
     pub rate_limiter: Option<RateLimiterConfig>,
 }
@@ -96,10 +157,15 @@ impl From<&Block> for BlockDeviceConfig {
         BlockDeviceConfig {
             drive_id: block.id().clone(),
             path_on_host: block.file_path().clone(),
+            backing: block.backing(),
             is_root_device: block.is_root_device(),
             partuuid: block.partuuid().cloned(),
+            boot_index: block.boot_index(),
             is_read_only: block.is_read_only(),
             cache_type: block.cache_type(),
+            open_retry: block.open_retry(),
+            access_pattern: block.access_pattern(),
+            interrupt_coalescing_us: block.interrupt_coalescing_us(),
             rate_limiter: rl.into_option(),
         }
     }
@@ -122,7 +188,8 @@ pub struct BlockDeviceUpdateConfig {
 #[derive(Default)]
 pub struct BlockBuilder {
     /// The list of block devices.
-    /// There can be at most one root block device and it would be the first in the list.
+    /// The root block devices are the first in the list, ordered by their boot index. There can
+    /// be several of them only if they all have distinct boot indices.
     // Root Device should be the first in the list whether or not PARTUUID is
     // specified in order to avoid bugs in case of switching from partuuid boot
     // scenarios to /dev/vda boot type.
@@ -156,49 +223,70 @@ impl BlockBuilder {
 
     /// Inserts a `Block` in the block devices list using the specified configuration.
     /// If a block with the same id already exists, it will overwrite it.
-    /// Inserting a secondary root block device will fail.
+    /// Inserting a secondary root block device will fail, unless all the root block devices
+    /// have distinct boot indices.
     pub fn insert(&mut self, config: BlockDeviceConfig) -> Result<()> {
-        let is_root_device = config.is_root_device;
         let position = self.get_index_of_drive_id(&config.drive_id);
-        let has_root_block = self.has_root_device();
 
-        // Don't allow adding a second root block device.
-        // If the new device cfg is root and not an update to the existing root, fail fast.
-        if is_root_device && has_root_block && position != Some(0) {
-            return Err(DriveError::RootBlockDeviceAlreadyAdded);
+        if config.boot_index.is_some() && !config.is_root_device {
+            return Err(DriveError::BootIndexWithoutRootDevice);
         }
 
-        let block_dev = Arc::new(Mutex::new(Self::create_block(config)?));
-        // If the id of the drive already exists in the list, the operation is update/overwrite.
-        match position {
-            // New block device.
-            None => {
-                if is_root_device {
-                    self.list.push_front(block_dev);
-                } else {
-                    self.list.push_back(block_dev);
+        if config.is_root_device && self.has_root_device() {
+            // Check the new root device against the other root devices, skipping the one it
+            // may overwrite.
+            for block in self.list.iter() {
+                let block = block.lock().expect("Poisoned lock");
+                if !block.is_root_device() || block.id() == &config.drive_id {
+                    continue;
                 }
-            }
-            // Update existing block device.
-            Some(index) => {
-                // Update the slot with the new block.
-                self.list[index] = block_dev;
-                // Check if the root block device is being updated.
-                if index != 0 && is_root_device {
-                    // Make sure the root device is on the first position.
-                    self.list.swap(0, index);
+                match (block.boot_index(), config.boot_index) {
+                    (Some(index), Some(new_index)) if index == new_index => {
+                        return Err(DriveError::BootIndexAlreadyUsed(new_index));
+                    }
+                    (Some(_), Some(_)) => (),
+                    _ => return Err(DriveError::RootBlockDeviceAlreadyAdded),
                 }
             }
         }
+
+        let block_dev = Arc::new(Mutex::new(Self::create_block(config)?));
+        // If the id of the drive already exists in the list, the operation is update/overwrite.
+        match position {
+            None => self.list.push_back(block_dev),
+            Some(index) => self.list[index] = block_dev,
+        }
+
+        // Move the root devices on the first positions, in boot order. The sort is stable, so
+        // the other devices keep the order in which they were added.
+        self.list.make_contiguous().sort_by_key(|block| {
+            let block = block.lock().expect("Poisoned lock");
+            (!block.is_root_device(), block.boot_index())
+        });
         Ok(())
     }
 
     /// Creates a Block device from a BlockDeviceConfig.
     pub fn create_block(block_device_config: BlockDeviceConfig) -> Result<Block> {
-        // check if the path exists
-        let path_on_host = PathBuf::from(&block_device_config.path_on_host);
-        if !path_on_host.exists() {
-            return Err(DriveError::InvalidBlockDevicePath);
+        match block_device_config.backing {
+            DiskBacking::File => {
+                // check if the path exists
+                let path_on_host = PathBuf::from(&block_device_config.path_on_host);
+                if !path_on_host.exists() {
+                    return Err(DriveError::InvalidBlockDevicePath);
+                }
+            }
+            DiskBacking::Memory(size) => {
+                if size == 0 || size % SECTOR_SIZE != 0 {
+                    return Err(DriveError::InvalidMemoryBackingSize(size));
+                }
+            }
+        }
+
+        if block_device_config.open_retry.max_retries > MAX_OPEN_RETRIES
+            || block_device_config.open_retry.initial_backoff_ms > MAX_OPEN_RETRY_BACKOFF_MS
+        {
+            return Err(DriveError::InvalidOpenRetryConfig);
         }
 
         let rate_limiter = block_device_config
@@ -208,16 +296,22 @@ impl BlockBuilder {
             .map_err(DriveError::CreateRateLimiter)?;
 
         // Create and return the Block device
-        devices::virtio::Block::new(
+        let mut block = devices::virtio::Block::new(
             block_device_config.drive_id,
             block_device_config.partuuid,
             block_device_config.cache_type,
             block_device_config.path_on_host,
+            block_device_config.backing,
             block_device_config.is_read_only,
             block_device_config.is_root_device,
+            block_device_config.boot_index,
             rate_limiter.unwrap_or_default(),
+            block_device_config.open_retry,
+            block_device_config.access_pattern,
         )
-        .map_err(DriveError::CreateBlockDevice)
+        .map_err(DriveError::CreateBlockDevice)?;
+        block.set_interrupt_coalescing_us(block_device_config.interrupt_coalescing_us);
+        Ok(block)
     }
 
     /// Returns a vec with the structures used to configure the devices.
@@ -248,9 +342,14 @@ mod tests {
         fn clone(&self) -> Self {
             BlockDeviceConfig {
                 path_on_host: self.path_on_host.clone(),
+                backing: self.backing,
                 is_root_device: self.is_root_device,
                 partuuid: self.partuuid.clone(),
+                boot_index: self.boot_index,
                 cache_type: self.cache_type,
+                open_retry: self.open_retry,
+                access_pattern: self.access_pattern,
+                interrupt_coalescing_us: self.interrupt_coalescing_us,
                 is_read_only: self.is_read_only,
                 drive_id: self.drive_id.clone(),
                 rate_limiter: None,
@@ -271,9 +370,14 @@ mod tests {
         let dummy_id = String::from("1");
         let dummy_block_device = BlockDeviceConfig {
             path_on_host: dummy_path,
+            backing: DiskBacking::File,
             is_root_device: false,
             partuuid: None,
+            boot_index: None,
             cache_type: CacheType::Writeback,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::default(),
+            interrupt_coalescing_us: 0,
             is_read_only: false,
             drive_id: dummy_id.clone(),
             rate_limiter: None,
@@ -294,6 +398,46 @@ mod tests {
         assert_eq!(block_devs.get_index_of_drive_id(&dummy_id), Some(0));
     }
 
+    #[test]
+    fn test_add_memory_backed_block_device() {
+        let mut block_device = BlockDeviceConfig {
+            path_on_host: String::new(),
+            backing: DiskBacking::Memory(0x10_0000),
+            is_root_device: false,
+            partuuid: None,
+            boot_index: None,
+            cache_type: CacheType::Unsafe,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::default(),
+            interrupt_coalescing_us: 0,
+            is_read_only: false,
+            drive_id: String::from("scratch"),
+            rate_limiter: None,
+        };
+
+        let mut block_devs = BlockBuilder::new();
+        assert!(block_devs.insert(block_device.clone()).is_ok());
+        assert_eq!(block_devs.configs(), vec![block_device.clone()]);
+
+        block_device.backing = DiskBacking::Memory(0);
+        assert_eq!(
+            block_devs.insert(block_device.clone()),
+            Err(DriveError::InvalidMemoryBackingSize(0))
+        );
+        block_device.backing = DiskBacking::Memory(SECTOR_SIZE + 1);
+        assert_eq!(
+            block_devs.insert(block_device.clone()),
+            Err(DriveError::InvalidMemoryBackingSize(SECTOR_SIZE + 1))
+        );
+
+        // The path is only required for file backed drives.
+        block_device.backing = DiskBacking::File;
+        assert_eq!(
+            block_devs.insert(block_device),
+            Err(DriveError::InvalidBlockDevicePath)
+        );
+    }
+
     #[test]
     fn test_add_one_root_block_device() {
         let dummy_file = TempFile::new().unwrap();
@@ -301,9 +445,14 @@ mod tests {
 
         let dummy_block_device = BlockDeviceConfig {
             path_on_host: dummy_path,
+            backing: DiskBacking::File,
             is_root_device: true,
             partuuid: None,
+            boot_index: None,
             cache_type: CacheType::Unsafe,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::default(),
+            interrupt_coalescing_us: 0,
             is_read_only: true,
             drive_id: String::from("1"),
             rate_limiter: None,
@@ -328,9 +477,14 @@ mod tests {
         let dummy_path_1 = dummy_file_1.as_path().to_str().unwrap().to_string();
         let root_block_device_1 = BlockDeviceConfig {
             path_on_host: dummy_path_1,
+            backing: DiskBacking::File,
             is_root_device: true,
             partuuid: None,
+            boot_index: None,
             cache_type: CacheType::Unsafe,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::default(),
+            interrupt_coalescing_us: 0,
             is_read_only: false,
             drive_id: String::from("1"),
             rate_limiter: None,
@@ -340,9 +494,14 @@ mod tests {
         let dummy_path_2 = dummy_file_2.as_path().to_str().unwrap().to_string();
         let root_block_device_2 = BlockDeviceConfig {
             path_on_host: dummy_path_2,
+            backing: DiskBacking::File,
             is_root_device: true,
             partuuid: None,
+            boot_index: None,
             cache_type: CacheType::Unsafe,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::default(),
+            interrupt_coalescing_us: 0,
             is_read_only: false,
             drive_id: String::from("2"),
             rate_limiter: None,
@@ -356,6 +515,78 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_add_root_block_devs_with_boot_index() {
+        let dummy_files: Vec<TempFile> = (0..4).map(|_| TempFile::new().unwrap()).collect();
+        let block_device =
+            |drive_id: &str, index: usize, is_root_device, boot_index| BlockDeviceConfig {
+                path_on_host: dummy_files[index].as_path().to_str().unwrap().to_string(),
+                backing: DiskBacking::File,
+                is_root_device,
+                partuuid: None,
+                boot_index,
+                cache_type: CacheType::Unsafe,
+                open_retry: OpenRetryConfig::default(),
+                access_pattern: AccessPattern::default(),
+                interrupt_coalescing_us: 0,
+                is_read_only: false,
+                drive_id: String::from(drive_id),
+                rate_limiter: None,
+            };
+
+        let mut block_devs = BlockBuilder::new();
+        assert!(block_devs.insert(block_device("1", 0, false, None)).is_ok());
+        assert!(block_devs
+            .insert(block_device("2", 1, true, Some(2)))
+            .is_ok());
+        assert!(block_devs
+            .insert(block_device("3", 2, true, Some(1)))
+            .is_ok());
+
+        // The root devices are first, in boot order.
+        assert!(block_devs.has_root_device());
+        let ids: Vec<String> = block_devs
+            .list
+            .iter()
+            .map(|block| block.lock().unwrap().id().clone())
+            .collect();
+        assert_eq!(ids, vec!["3", "2", "1"]);
+
+        // Two root devices cannot share a boot index.
+        assert_eq!(
+            block_devs
+                .insert(block_device("4", 3, true, Some(2)))
+                .unwrap_err(),
+            DriveError::BootIndexAlreadyUsed(2)
+        );
+        // Several root devices must all have a boot index.
+        assert_eq!(
+            block_devs
+                .insert(block_device("4", 3, true, None))
+                .unwrap_err(),
+            DriveError::RootBlockDeviceAlreadyAdded
+        );
+        // Only root devices can have a boot index.
+        assert_eq!(
+            block_devs
+                .insert(block_device("4", 3, false, Some(3)))
+                .unwrap_err(),
+            DriveError::BootIndexWithoutRootDevice
+        );
+        assert_eq!(block_devs.list.len(), 3);
+
+        // Updating a root device can change its position in the boot order.
+        assert!(block_devs
+            .insert(block_device("2", 1, true, Some(0)))
+            .is_ok());
+        assert_eq!(block_devs.list[0].lock().unwrap().id(), "2");
+        assert_eq!(block_devs.list[1].lock().unwrap().id(), "3");
+
+        let configs = block_devs.configs();
+        assert_eq!(configs[0].boot_index, Some(0));
+        assert_eq!(configs[2].boot_index, None);
+    }
+
     #[test]
     // Test BlockDevicesConfigs::add when you first add the root device and then the other devices.
     fn test_add_root_block_device_first() {
@@ -363,9 +594,14 @@ mod tests {
         let dummy_path_1 = dummy_file_1.as_path().to_str().unwrap().to_string();
         let root_block_device = BlockDeviceConfig {
             path_on_host: dummy_path_1,
+            backing: DiskBacking::File,
             is_root_device: true,
             partuuid: None,
+            boot_index: None,
             cache_type: CacheType::Unsafe,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::default(),
+            interrupt_coalescing_us: 0,
             is_read_only: false,
             drive_id: String::from("1"),
             rate_limiter: None,
@@ -375,9 +611,14 @@ mod tests {
         let dummy_path_2 = dummy_file_2.as_path().to_str().unwrap().to_string();
         let dummy_block_dev_2 = BlockDeviceConfig {
             path_on_host: dummy_path_2,
+            backing: DiskBacking::File,
             is_root_device: false,
             partuuid: None,
+            boot_index: None,
             cache_type: CacheType::Unsafe,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::default(),
+            interrupt_coalescing_us: 0,
             is_read_only: false,
             drive_id: String::from("2"),
             rate_limiter: None,
@@ -387,9 +628,14 @@ mod tests {
         let dummy_path_3 = dummy_file_3.as_path().to_str().unwrap().to_string();
         let dummy_block_dev_3 = BlockDeviceConfig {
             path_on_host: dummy_path_3,
+            backing: DiskBacking::File,
             is_root_device: false,
             partuuid: None,
+            boot_index: None,
             cache_type: CacheType::Unsafe,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::default(),
+            interrupt_coalescing_us: 0,
             is_read_only: false,
             drive_id: String::from("3"),
             rate_limiter: None,
@@ -424,9 +670,14 @@ mod tests {
         let dummy_path_1 = dummy_file_1.as_path().to_str().unwrap().to_string();
         let root_block_device = BlockDeviceConfig {
             path_on_host: dummy_path_1,
+            backing: DiskBacking::File,
             is_root_device: true,
             partuuid: None,
+            boot_index: None,
             cache_type: CacheType::Unsafe,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::default(),
+            interrupt_coalescing_us: 0,
             is_read_only: false,
             drive_id: String::from("1"),
             rate_limiter: None,
@@ -436,9 +687,14 @@ mod tests {
         let dummy_path_2 = dummy_file_2.as_path().to_str().unwrap().to_string();
         let dummy_block_dev_2 = BlockDeviceConfig {
             path_on_host: dummy_path_2,
+            backing: DiskBacking::File,
             is_root_device: false,
             partuuid: None,
+            boot_index: None,
             cache_type: CacheType::Unsafe,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::default(),
+            interrupt_coalescing_us: 0,
             is_read_only: false,
             drive_id: String::from("2"),
             rate_limiter: None,
@@ -448,9 +704,14 @@ mod tests {
         let dummy_path_3 = dummy_file_3.as_path().to_str().unwrap().to_string();
         let dummy_block_dev_3 = BlockDeviceConfig {
             path_on_host: dummy_path_3,
+            backing: DiskBacking::File,
             is_root_device: false,
             partuuid: None,
+            boot_index: None,
             cache_type: CacheType::Unsafe,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::default(),
+            interrupt_coalescing_us: 0,
             is_read_only: false,
             drive_id: String::from("3"),
             rate_limiter: None,
@@ -486,9 +747,14 @@ mod tests {
         let dummy_path_1 = dummy_file_1.as_path().to_str().unwrap().to_string();
         let root_block_device = BlockDeviceConfig {
             path_on_host: dummy_path_1.clone(),
+            backing: DiskBacking::File,
             is_root_device: true,
             partuuid: None,
+            boot_index: None,
             cache_type: CacheType::Unsafe,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::default(),
+            interrupt_coalescing_us: 0,
             is_read_only: false,
             drive_id: String::from("1"),
             rate_limiter: None,
@@ -498,9 +764,14 @@ mod tests {
         let dummy_path_2 = dummy_file_2.as_path().to_str().unwrap().to_string();
         let mut dummy_block_device_2 = BlockDeviceConfig {
             path_on_host: dummy_path_2.clone(),
+            backing: DiskBacking::File,
             is_root_device: false,
             partuuid: None,
+            boot_index: None,
             cache_type: CacheType::Unsafe,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::default(),
+            interrupt_coalescing_us: 0,
             is_read_only: false,
             drive_id: String::from("2"),
             rate_limiter: None,
@@ -547,8 +818,24 @@ mod tests {
             Err(DriveError::InvalidBlockDevicePath)
         );
 
-        // Update with 2 root block devices.
+        // Update with an out of bounds open retry configuration.
         dummy_block_device_2.path_on_host = dummy_path_2.clone();
+        dummy_block_device_2.open_retry.max_retries = MAX_OPEN_RETRIES + 1;
+        assert_eq!(
+            block_devs.insert(dummy_block_device_2.clone()),
+            Err(DriveError::InvalidOpenRetryConfig)
+        );
+        dummy_block_device_2.open_retry = OpenRetryConfig {
+            max_retries: MAX_OPEN_RETRIES,
+            initial_backoff_ms: MAX_OPEN_RETRY_BACKOFF_MS + 1,
+        };
+        assert_eq!(
+            block_devs.insert(dummy_block_device_2.clone()),
+            Err(DriveError::InvalidOpenRetryConfig)
+        );
+        dummy_block_device_2.open_retry = OpenRetryConfig::default();
+
+        // Update with 2 root block devices.
         dummy_block_device_2.is_root_device = true;
         assert_eq!(
             block_devs.insert(dummy_block_device_2),
@@ -557,9 +844,14 @@ mod tests {
 
         let root_block_device = BlockDeviceConfig {
             path_on_host: dummy_path_1,
+            backing: DiskBacking::File,
             is_root_device: true,
             partuuid: None,
+            boot_index: None,
             cache_type: CacheType::Unsafe,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::default(),
+            interrupt_coalescing_us: 0,
             is_read_only: false,
             drive_id: String::from("1"),
             rate_limiter: None,
@@ -569,9 +861,14 @@ mod tests {
         root_block_device_old.is_root_device = false;
         let root_block_device_new = BlockDeviceConfig {
             path_on_host: dummy_path_2,
+            backing: DiskBacking::File,
             is_root_device: true,
             partuuid: Some("0eaa91a0-01".to_string()),
+            boot_index: None,
             cache_type: CacheType::Unsafe,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::default(),
+            interrupt_coalescing_us: 0,
             is_read_only: false,
             drive_id: String::from("2"),
             rate_limiter: None,
@@ -590,9 +887,14 @@ mod tests {
 
         let dummy_block_device = BlockDeviceConfig {
             path_on_host: dummy_file.as_path().to_str().unwrap().to_string(),
+            backing: DiskBacking::File,
             is_root_device: true,
             partuuid: None,
+            boot_index: None,
             cache_type: CacheType::Unsafe,
+            open_retry: OpenRetryConfig::default(),
+            access_pattern: AccessPattern::Sequential,
+            interrupt_coalescing_us: 0,
             is_read_only: true,
             drive_id: String::from("1"),
             rate_limiter: None,
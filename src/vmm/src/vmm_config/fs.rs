@@ -0,0 +1,215 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::{Display, Formatter};
+use std::ops::Deref;
+use std::path::Path;
+use std::result;
+use std::sync::{Arc, Mutex};
+
+use super::MAX_DEVICE_IRQS;
+use devices::virtio::fs::Error as FsError;
+use devices::virtio::{Fs, FS_TAG_MAX_LEN};
+
+use serde::{Deserialize, Serialize};
+
+type Result<T> = result::Result<T, FsConfigError>;
+
+/// Errors associated with the configuration of the virtio-fs devices.
+#[derive(Debug)]
+pub enum FsConfigError {
+    /// Cannot create the virtio-fs device.
+    CreateFsDevice(FsError),
+    /// The shared directory is not a directory.
+    InvalidSharedDir(String),
+    /// The tag is empty or too long.
+    InvalidTag(String),
+    /// Another virtio-fs device has the same tag.
+    TagAlreadyUsed(String),
+    /// There is no IRQ left for another device.
+    TooManyDevices,
+}
+
+impl Display for FsConfigError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        use self::FsConfigError::*;
+        match self {
+            CreateFsDevice(e) => write!(f, "Cannot create the virtio-fs device: {:?}", e),
+            InvalidSharedDir(path) => write!(f, "{} is not a directory.", path),
+            InvalidTag(tag) => write!(
+                f,
+                "Invalid tag {:?}: it must have between 1 and {} bytes.",
+                tag, FS_TAG_MAX_LEN
+            ),
+            TagAlreadyUsed(tag) => write!(f, "A virtio-fs device with the tag {} exists.", tag),
+            TooManyDevices => write!(
+                f,
+                "Cannot attach another device: all the {} IRQs available to the devices are in use.",
+                MAX_DEVICE_IRQS
+            ),
+        }
+    }
+}
+
+/// Use this structure to set up a virtio-fs device before booting the kernel.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FsDeviceConfig {
+    /// Unique identifier of the device.
+    pub fs_id: String,
+    /// Path of the host directory shared with the guest. The guest can't access anything
+    /// outside of it.
+    pub shared_dir: String,
+    /// Name through which the guest mounts the file system, at most 36 bytes long.
+    pub tag: String,
+    /// If set to true, the guest can't change anything in the shared directory.
+    #[serde(default)]
+    pub is_read_only: bool,
+}
+
+impl From<&Fs> for FsDeviceConfig {
+    fn from(fs: &Fs) -> Self {
+        FsDeviceConfig {
+            fs_id: fs.id().to_string(),
+            shared_dir: fs.shared_dir().to_string_lossy().into_owned(),
+            tag: fs.tag(),
+            is_read_only: fs.is_read_only(),
+        }
+    }
+}
+
+/// Wrapper for the collection that holds all the virtio-fs devices.
+#[derive(Default)]
+pub struct FsBuilder {
+    /// The list of virtio-fs devices, in the order in which they were added.
+    pub list: Vec<Arc<Mutex<Fs>>>,
+}
+
+impl FsBuilder {
+    /// Creates an empty list of virtio-fs devices.
+    pub fn new() -> Self {
+        Self { list: Vec::new() }
+    }
+
+    /// Inserts a virtio-fs device in the list using the specified configuration.
+    /// If a device with the same id already exists, it will overwrite it.
+    pub fn insert(&mut self, config: FsDeviceConfig) -> Result<()> {
+        let position = self
+            .list
+            .iter()
+            .position(|fs| fs.lock().expect("Poisoned lock").id() == config.fs_id);
+
+        // The guest tells the file systems apart by their tag.
+        let tag_used = self.list.iter().any(|fs| {
+            let fs = fs.lock().expect("Poisoned lock");
+            fs.id() != config.fs_id && fs.tag() == config.tag
+        });
+        if tag_used {
+            return Err(FsConfigError::TagAlreadyUsed(config.tag));
+        }
+
+        let fs = Arc::new(Mutex::new(Self::create_fs(config)?));
+        match position {
+            None => self.list.push(fs),
+            Some(index) => self.list[index] = fs,
+        }
+        Ok(())
+    }
+
+    /// Creates a virtio-fs device from a `FsDeviceConfig`.
+    pub fn create_fs(config: FsDeviceConfig) -> Result<Fs> {
+        if config.tag.is_empty() || config.tag.len() > FS_TAG_MAX_LEN {
+            return Err(FsConfigError::InvalidTag(config.tag));
+        }
+        let shared_dir = Path::new(&config.shared_dir);
+        if !shared_dir.is_dir() {
+            return Err(FsConfigError::InvalidSharedDir(config.shared_dir));
+        }
+
+        Fs::new(config.fs_id, shared_dir, &config.tag, config.is_read_only)
+            .map_err(FsConfigError::CreateFsDevice)
+    }
+
+    /// Returns a vec with the structures used to configure the devices.
+    pub fn configs(&self) -> Vec<FsDeviceConfig> {
+        self.list
+            .iter()
+            .map(|fs| FsDeviceConfig::from(fs.lock().unwrap().deref()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::tempdir::TempDir;
+
+    fn default_config(fs_id: &str, dir: &TempDir, tag: &str) -> FsDeviceConfig {
+        FsDeviceConfig {
+            fs_id: fs_id.to_string(),
+            shared_dir: dir.as_path().to_str().unwrap().to_string(),
+            tag: tag.to_string(),
+            is_read_only: false,
+        }
+    }
+
+    #[test]
+    fn test_insert() {
+        let dir = TempDir::new().unwrap();
+        let mut builder = FsBuilder::new();
+
+        let config = default_config("fs0", &dir, "shared");
+        builder.insert(config.clone()).unwrap();
+        builder
+            .insert(default_config("fs1", &dir, "other"))
+            .unwrap();
+        assert_eq!(builder.list.len(), 2);
+        assert_eq!(builder.configs()[0], config);
+
+        // A device is overwritten through its ID, but the tags must stay distinct.
+        let mut config = default_config("fs0", &dir, "shared");
+        config.is_read_only = true;
+        builder.insert(config.clone()).unwrap();
+        assert_eq!(builder.list.len(), 2);
+        assert_eq!(builder.configs()[0], config);
+        match builder.insert(default_config("fs2", &dir, "shared")) {
+            Err(FsConfigError::TagAlreadyUsed(tag)) => assert_eq!(tag, "shared"),
+            _ => panic!("The tag should be in use"),
+        }
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let dir = TempDir::new().unwrap();
+        let mut builder = FsBuilder::new();
+
+        match builder.insert(default_config("fs0", &dir, "")) {
+            Err(FsConfigError::InvalidTag(_)) => (),
+            _ => panic!("The tag should be invalid"),
+        }
+        let long_tag = "t".repeat(FS_TAG_MAX_LEN + 1);
+        match builder.insert(default_config("fs0", &dir, &long_tag)) {
+            Err(FsConfigError::InvalidTag(_)) => (),
+            _ => panic!("The tag should be invalid"),
+        }
+
+        let mut config = default_config("fs0", &dir, "shared");
+        config.shared_dir = "/nonexistent".to_string();
+        match builder.insert(config) {
+            Err(FsConfigError::InvalidSharedDir(path)) => assert_eq!(path, "/nonexistent"),
+            _ => panic!("The shared directory should be invalid"),
+        }
+        assert!(builder.list.is_empty());
+    }
+
+    #[test]
+    fn test_error_messages() {
+        let err = FsConfigError::InvalidTag(String::new());
+        assert!(err.to_string().starts_with("Invalid tag"));
+        let err = FsConfigError::TagAlreadyUsed("shared".to_string());
+        assert_eq!(
+            err.to_string(),
+            "A virtio-fs device with the tag shared exists."
+        );
+    }
+}
@@ -1,6 +1,6 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
-use serde::{ser, Serialize};
+use serde::{ser, Deserialize, Serialize};
 use std::fmt::{self, Display, Formatter};
 
 /// Enumerates microVM runtime states.
@@ -51,3 +51,19 @@ pub struct InstanceInfo {
     /// The name of the application that runs the microVM.
     pub app_name: String,
 }
+
+/// The microVM instance information which can be changed before the microVM is started.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct InstanceInfoUpdate {
+    /// The ID of the microVM, used by the logger and the snapshots.
+    pub id: String,
+}
+
+/// The options of starting a microVM.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct StartMicroVmConfig {
+    /// When set to true, the vCPUs are left paused at the kernel entry point, until the
+    /// microVM is explicitly resumed.
+    pub start_paused: bool,
+}
@@ -5,10 +5,17 @@
 use serde::{de, Deserialize, Deserializer, Serialize};
 use std::fmt::{Display, Formatter};
 use std::path::PathBuf;
+use std::sync::Mutex;
 
 use super::{open_file_nonblock, FcLineWriter};
 use crate::vmm_config::instance_info::InstanceInfo;
-use logger::{LevelFilter, LOGGER};
+use lazy_static::lazy_static;
+use logger::{LevelFilter, LOGGER, METRICS};
+
+lazy_static! {
+    // Path of the logs destination, recorded upon initialization so that it can be reopened.
+    static ref LOG_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
 
 /// Enum used for setting the log level.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -114,6 +121,8 @@ impl LoggerConfig {
 pub enum LoggerConfigError {
     /// Cannot initialize the logger due to bad user input.
     InitializationFailure(String),
+    /// Cannot reopen the logs destination.
+    ReopenFailure(String),
 }
 
 impl Display for LoggerConfigError {
@@ -121,6 +130,11 @@ impl Display for LoggerConfigError {
         use self::LoggerConfigError::*;
         match *self {
             InitializationFailure(ref err_msg) => write!(f, "{}", err_msg.replace("\"", "")),
+            ReopenFailure(ref err_msg) => write!(
+                f,
+                "Cannot reopen the logs destination: {}",
+                err_msg.replace("\"", "")
+            ),
         }
     }
 }
@@ -138,6 +152,7 @@ pub fn init_logger(
     let writer = FcLineWriter::new(
         open_file_nonblock(&logger_cfg.log_path)
             .map_err(|e| LoggerConfigError::InitializationFailure(e.to_string()))?,
+        &METRICS.logger.missed_log_count,
     );
     LOGGER
         .init(
@@ -147,7 +162,28 @@ pub fn init_logger(
             ),
             Box::new(writer),
         )
-        .map_err(|e| LoggerConfigError::InitializationFailure(e.to_string()))
+        .map_err(|e| LoggerConfigError::InitializationFailure(e.to_string()))?;
+
+    *LOG_PATH.lock().expect("Poisoned lock") = Some(logger_cfg.log_path);
+    Ok(())
+}
+
+/// Closes and reopens the logs destination at the path provided upon initialization, e.g. after
+/// it was rotated. It does nothing if the logger was not initialized.
+pub fn reopen_logger() -> std::result::Result<(), LoggerConfigError> {
+    let log_path = match LOG_PATH.lock().expect("Poisoned lock").clone() {
+        Some(log_path) => log_path,
+        None => return Ok(()),
+    };
+
+    let writer = FcLineWriter::new(
+        open_file_nonblock(&log_path)
+            .map_err(|e| LoggerConfigError::ReopenFailure(e.to_string()))?,
+        &METRICS.logger.missed_log_count,
+    );
+    LOGGER
+        .reopen(Box::new(writer))
+        .map_err(|e| LoggerConfigError::ReopenFailure(e.to_string()))
 }
 
 #[cfg(test)]
@@ -202,6 +238,21 @@ mod tests {
             }
         }
 
+        // Reopening the logs destination keeps logging to the same path.
+        assert!(reopen_logger().is_ok());
+        warn!("this is a test after reopen");
+
+        let mut line = String::new();
+        loop {
+            if line.contains("this is a test after reopen") {
+                break;
+            }
+            if reader.read_line(&mut line).unwrap() == 0 {
+                // If it ever gets here, this assert will fail.
+                assert!(line.contains("this is a test after reopen"));
+            }
+        }
+
         // Validate logging the boot time works.
         let mut boot_timer = BootTimer::new(TimestampUs::default());
         boot_timer.write(0, &[123]);
@@ -229,6 +280,13 @@ mod tests {
             ),
             "Failed to initialize logger"
         );
+        assert_eq!(
+            format!(
+                "{}",
+                LoggerConfigError::ReopenFailure(String::from("No such file or directory"))
+            ),
+            "Cannot reopen the logs destination: No such file or directory"
+        );
     }
 
     #[test]
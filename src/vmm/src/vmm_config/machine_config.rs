@@ -2,27 +2,63 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use serde::{de, Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
+use std::path::PathBuf;
+
+use vm_memory::GuestAddress;
 
 /// The default memory size of the VM, in MiB.
 pub const DEFAULT_MEM_SIZE_MIB: usize = 128;
+/// The default time the vCPUs are given to stop when the microVM shuts down, in milliseconds.
+pub const DEFAULT_SHUTDOWN_GRACE_PERIOD_MS: u64 = 1000;
 /// Firecracker aims to support small scale workloads only, so limit the maximum
 /// vCPUs supported.
 pub const MAX_SUPPORTED_VCPUS: u8 = 32;
+/// Host memory required on top of the guest memory size, in percent of the latter, which is
+/// left for the VMM itself and for the rest of the host.
+pub const HOST_MEM_HEADROOM_PERCENT: usize = 10;
+/// The maximum length of the SMBIOS strings, in bytes.
+pub const MAX_SMBIOS_STRING_LEN: usize = 64;
 
 /// Errors associated with configuring the microVM.
 #[derive(Debug, PartialEq)]
 pub enum VmConfigError {
     /// The memory size is smaller than the target size set in the balloon device configuration.
     IncompatibleBalloonSize,
+    /// The host doesn't have enough available memory for the memory size. Holds the required
+    /// and the available host memory, in MiB.
+    InsufficientHostMemory(usize, usize),
     /// The memory size is invalid. The memory can only be an unsigned integer.
     InvalidMemorySize,
+    /// The guest memory layout is invalid. Its regions can't overlap, have to add up to the
+    /// memory size and have to be valid guest memory on this platform.
+    InvalidMemoryLayout,
     /// The vcpu count is invalid. When hyperthreading is enabled, the `cpu_count` must be either
     /// 1 or an even number.
     InvalidVcpuCount,
+    /// The vcpu affinity is invalid. It can only refer to existing vcpus and host CPUs, and each
+    /// vcpu must be allowed to run on at least one host CPU.
+    InvalidVcpuAffinity,
+    /// The memory regions can't be assigned NUMA nodes on the host architecture.
+    UnsupportedNumaNode,
     /// Could not get the config of the balloon device from the VM resources, even though a
     /// balloon device was previously installed.
     InvalidVmState,
+    /// The guest has no RTC device on the host architecture, so it has no base time to set.
+    UnsupportedRtcBaseTime,
+    /// The maximum number of descriptor chains processed per event can't be 0.
+    InvalidMaxDescriptorsPerEvent,
+    /// The maximum number of devices sharing an IRQ line can't be 0.
+    InvalidMaxDevicesPerIrq,
+    /// The interrupt controller doesn't exist on the host architecture.
+    InvalidInterruptController(InterruptController),
+    /// The SMBIOS configuration is invalid. The strings are too long or hold a NUL byte, or the
+    /// UUID is malformed.
+    InvalidSmbiosConfig,
+    /// The vcpu count or the memory size differ from the ones of the running microVM, which
+    /// can't change once its vcpus are started, either by booting or by resuming a snapshot.
+    UpdateNotAllowedPostBoot,
 }
 
 impl fmt::Display for VmConfigError {
@@ -34,17 +70,70 @@ impl fmt::Display for VmConfigError {
                 "The memory size (MiB) is smaller than the previously \
                  set balloon device target size.",
             ),
+            InsufficientHostMemory(required_mib, available_mib) => write!(
+                f,
+                "The host does not have enough available memory for the memory size! \
+                 {} MiB are required, including headroom, but only {} MiB are available. \
+                 Set allow_mem_overcommit to skip this check.",
+                required_mib, available_mib
+            ),
             InvalidMemorySize => write!(f, "The memory size (MiB) is invalid.",),
+            InvalidMemoryLayout => write!(
+                f,
+                "The guest memory layout is invalid! The memory regions must be \
+                 page aligned, must not overlap, must add up to the memory size \
+                 and must be valid guest memory on this platform.",
+            ),
             InvalidVcpuCount => write!(
                 f,
                 "The vCPU number is invalid! The vCPU number can only \
                  be 1 or an even number when hyperthreading is enabled.",
             ),
+            InvalidVcpuAffinity => write!(
+                f,
+                "The vCPU affinity is invalid! Each vCPU id must be lower than \
+                 the vCPU number and each vCPU must be assigned a non-empty \
+                 set of existing host CPUs.",
+            ),
+            InvalidMaxDescriptorsPerEvent => write!(
+                f,
+                "The maximum number of descriptor chains processed per event must be at least 1.",
+            ),
+            InvalidMaxDevicesPerIrq => write!(
+                f,
+                "The maximum number of devices sharing an IRQ line must be at least 1.",
+            ),
+            InvalidInterruptController(interrupt_controller) => write!(
+                f,
+                "The {} interrupt controller is not available on this architecture.",
+                interrupt_controller
+            ),
+            InvalidSmbiosConfig => write!(
+                f,
+                "The SMBIOS configuration is invalid! The strings can have at most {} \
+                 bytes and no NUL byte, and the UUID must be formatted as \
+                 xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx, in hexadecimal digits.",
+                MAX_SMBIOS_STRING_LEN
+            ),
+            UnsupportedNumaNode => write!(
+                f,
+                "The memory regions can only be assigned NUMA nodes on aarch64.",
+            ),
+            UnsupportedRtcBaseTime => write!(
+                f,
+                "The RTC base time can only be set on aarch64, where the guest has an RTC.",
+            ),
             InvalidVmState => write!(
                 f,
                 "Could not get the configuration of the previously \
                  installed balloon device to validate the memory size.",
             ),
+            UpdateNotAllowedPostBoot => write!(
+                f,
+                "The vCPU number and the memory size cannot be changed once the \
+                 vCPUs are started, either by booting the microVM or by resuming \
+                 it from a snapshot, whose vCPU number and memory size are kept.",
+            ),
         }
     }
 }
@@ -73,6 +162,80 @@ pub struct VmConfig {
     /// Enables or disables dirty page tracking. Enabling allows incremental snapshots.
     #[serde(default)]
     pub track_dirty_pages: bool,
+    /// The host CPUs on which each vcpu thread is allowed to run, indexed by vcpu id.
+    /// The vcpus which are missing from the map can run on any host CPU.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vcpu_affinity: Option<BTreeMap<u8, Vec<usize>>>,
+    /// Explicit layout of the guest memory, used instead of the default single region (or two
+    /// regions around the MMIO gap on x86_64).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mem_regions: Option<Vec<MemoryRegionConfig>>,
+    /// Allows a memory size larger than the memory available on the host, which is otherwise
+    /// rejected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_mem_overcommit: Option<bool>,
+    /// File backing the guest memory, which is anonymous memory by default. The file has to be
+    /// as large as the guest memory and is shared with the guest, e.g. to observe its memory
+    /// from another process.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mem_backing_file: Option<PathBuf>,
+    /// What to do when the guest reboots. The microVM is shut down by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_reboot: Option<RebootAction>,
+    /// How the microVM stops when the guest reboots by resetting the i8042 controller, which
+    /// tells the guest reboot apart from the guest powering off. Only applies on x86_64.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reset_action: Option<ResetAction>,
+    /// What to do when the guest kernel panics, which the guest reports through the pvpanic
+    /// device. The device is only attached when the panics are reported.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub panic_action: Option<PanicAction>,
+    /// Time, in seconds since the Unix epoch, which the guest RTC reads when the microVM boots.
+    /// The RTC follows the host time by default. Only aarch64 guests have an RTC device, it
+    /// can't be set on x86_64.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rtc_base_time: Option<u32>,
+    /// The maximum number of descriptor chains the block and net devices process from a queue
+    /// for one event, before handling the other pending events.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_descriptors_per_event: Option<u16>,
+    /// The maximum number of virtio devices sharing an IRQ line, once every line is used. Each
+    /// virtio device has an IRQ line of its own by default, which limits their number.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_devices_per_irq: Option<u32>,
+    /// The version of the interrupt controller exposed to the guest. By default, the newest
+    /// version supported by the host is used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interrupt_controller: Option<InterruptController>,
+    /// Identity of the microVM exposed to the guest, through the SMBIOS tables on x86_64 and
+    /// through the FDT on aarch64. The Firecracker defaults are exposed if it's not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smbios: Option<SmbiosConfig>,
+    /// How long, in milliseconds, the vCPUs are given to stop when the microVM shuts down. The
+    /// vCPUs still running past it are left behind, to be killed when Firecracker exits.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shutdown_grace_period_ms: Option<u64>,
+    /// Where the snapshots asked for by the guest are written. The snapshot trigger device,
+    /// through which the guest asks for them, is only attached when it's set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guest_snapshot: Option<GuestSnapshotConfig>,
+    /// Whether the guest memory is zeroed when the microVM shuts down, so that the guest data
+    /// doesn't linger in the host memory. The memory backed by a file is left untouched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zero_mem_on_shutdown: Option<bool>,
+}
+
+/// A region of an explicit guest memory layout.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryRegionConfig {
+    /// Guest physical address where the region starts.
+    pub guest_addr: u64,
+    /// The region size in MiB.
+    pub size_mib: usize,
+    /// The NUMA node the region belongs to.
+    #[serde(default)]
+    pub numa_node: u32,
 }
 
 impl Default for VmConfig {
@@ -83,6 +246,21 @@ impl Default for VmConfig {
             ht_enabled: Some(false),
             cpu_template: None,
             track_dirty_pages: false,
+            vcpu_affinity: None,
+            mem_regions: None,
+            allow_mem_overcommit: None,
+            mem_backing_file: None,
+            on_reboot: None,
+            reset_action: None,
+            panic_action: None,
+            rtc_base_time: None,
+            max_descriptors_per_event: None,
+            max_devices_per_irq: None,
+            interrupt_controller: None,
+            smbios: None,
+            shutdown_grace_period_ms: None,
+            guest_snapshot: None,
+            zero_mem_on_shutdown: None,
         }
     }
 }
@@ -120,6 +298,202 @@ where
     Ok(val)
 }
 
+/// Checks that `vcpu_affinity` only refers to the first `vcpu_count` vcpus and to existing
+/// host CPUs, and that it leaves at least one host CPU to each vcpu.
+pub fn validate_vcpu_affinity(
+    vcpu_affinity: &BTreeMap<u8, Vec<usize>>,
+    vcpu_count: u8,
+) -> std::result::Result<(), VmConfigError> {
+    // Safe because sysconf doesn't have side effects.
+    let host_cpu_count = match unsafe { libc::sysconf(libc::_SC_NPROCESSORS_CONF) } {
+        -1 => return Err(VmConfigError::InvalidVcpuAffinity),
+        count => std::cmp::min(count as usize, libc::CPU_SETSIZE as usize),
+    };
+
+    for (vcpu_id, host_cpus) in vcpu_affinity.iter() {
+        if *vcpu_id >= vcpu_count
+            || host_cpus.is_empty()
+            || host_cpus.iter().any(|cpu| *cpu >= host_cpu_count)
+        {
+            return Err(VmConfigError::InvalidVcpuAffinity);
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the host has enough available memory for a guest with `mem_size_mib` MiB of
+/// memory, plus the headroom. The check is advisory: it is skipped if the available memory
+/// can't be read, and the memory available when the guest touches its memory may differ.
+pub fn validate_host_memory(mem_size_mib: usize) -> std::result::Result<(), VmConfigError> {
+    check_host_memory(mem_size_mib, host_mem_available_mib())
+}
+
+fn check_host_memory(
+    mem_size_mib: usize,
+    mem_available_mib: Option<usize>,
+) -> std::result::Result<(), VmConfigError> {
+    let required_mib =
+        mem_size_mib.saturating_add(mem_size_mib.saturating_mul(HOST_MEM_HEADROOM_PERCENT) / 100);
+    match mem_available_mib {
+        Some(available_mib) if available_mib < required_mib => Err(
+            VmConfigError::InsufficientHostMemory(required_mib, available_mib),
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the memory available on the host, in MiB, as reported by `/proc/meminfo`.
+pub(crate) fn host_mem_available_mib() -> Option<usize> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    parse_mem_available_mib(&meminfo)
+}
+
+fn parse_mem_available_mib(meminfo: &str) -> Option<usize> {
+    meminfo.lines().find_map(|line| {
+        let value = line.strip_prefix("MemAvailable:")?.trim();
+        let kib = value.strip_suffix("kB")?.trim().parse::<usize>().ok()?;
+        Some(kib / 1024)
+    })
+}
+
+/// Returns the regions of an explicit guest memory layout, sorted by address.
+pub fn sorted_mem_regions(mem_regions: &[MemoryRegionConfig]) -> Vec<&MemoryRegionConfig> {
+    let mut sorted_regions: Vec<&MemoryRegionConfig> = mem_regions.iter().collect();
+    sorted_regions.sort_by_key(|region| region.guest_addr);
+    sorted_regions
+}
+
+/// Checks that the regions of an explicit guest memory layout are page aligned, don't overlap,
+/// add up to `mem_size_mib` and are valid guest memory on this platform. The NUMA nodes are
+/// only described to aarch64 guests, so the regions all belong to node 0 on x86_64.
+pub fn validate_mem_regions(
+    mem_regions: &[MemoryRegionConfig],
+    mem_size_mib: usize,
+) -> std::result::Result<(), VmConfigError> {
+    let mut ranges = Vec::with_capacity(mem_regions.len());
+    let mut total_size_mib: usize = 0;
+    for region in sorted_mem_regions(mem_regions) {
+        if cfg!(target_arch = "x86_64") && region.numa_node != 0 {
+            return Err(VmConfigError::UnsupportedNumaNode);
+        }
+        if region.size_mib == 0 || region.guest_addr % arch::PAGE_SIZE as u64 != 0 {
+            return Err(VmConfigError::InvalidMemoryLayout);
+        }
+        let size = region
+            .size_mib
+            .checked_mul(1 << 20)
+            .ok_or(VmConfigError::InvalidMemoryLayout)?;
+        region
+            .guest_addr
+            .checked_add(size as u64)
+            .ok_or(VmConfigError::InvalidMemoryLayout)?;
+        if let Some((GuestAddress(prev_start), prev_size)) = ranges.last() {
+            if *prev_start + *prev_size as u64 > region.guest_addr {
+                return Err(VmConfigError::InvalidMemoryLayout);
+            }
+        }
+        total_size_mib = total_size_mib
+            .checked_add(region.size_mib)
+            .ok_or(VmConfigError::InvalidMemoryLayout)?;
+        ranges.push((GuestAddress(region.guest_addr), size));
+    }
+
+    if total_size_mib != mem_size_mib || !arch::is_valid_memory_layout(&ranges) {
+        return Err(VmConfigError::InvalidMemoryLayout);
+    }
+    Ok(())
+}
+
+/// Identity of the microVM exposed to the guest, e.g. for the DMI of a Linux guest. The fields
+/// which are left out keep their defaults.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SmbiosConfig {
+    /// Manufacturer of the system, "Firecracker" by default.
+    #[serde(default = "default_smbios_manufacturer")]
+    pub manufacturer: String,
+    /// Product name of the system, "microVM" by default.
+    #[serde(default = "default_smbios_product_name")]
+    pub product_name: String,
+    /// Serial number of the system, none by default.
+    #[serde(default)]
+    pub serial_number: String,
+    /// UUID of the system, formatted as xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx. Only exposed on
+    /// x86_64, none by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<String>,
+}
+
+fn default_smbios_manufacturer() -> String {
+    "Firecracker".to_string()
+}
+
+fn default_smbios_product_name() -> String {
+    "microVM".to_string()
+}
+
+impl Default for SmbiosConfig {
+    fn default() -> Self {
+        SmbiosConfig {
+            manufacturer: default_smbios_manufacturer(),
+            product_name: default_smbios_product_name(),
+            serial_number: String::new(),
+            uuid: None,
+        }
+    }
+}
+
+impl SmbiosConfig {
+    /// Checks that the strings fit the SMBIOS tables and that the UUID is well formed.
+    pub fn validate(&self) -> std::result::Result<(), VmConfigError> {
+        let valid_string =
+            |s: &String| s.len() <= MAX_SMBIOS_STRING_LEN && !s.as_bytes().contains(&0);
+        if !valid_string(&self.manufacturer)
+            || !valid_string(&self.product_name)
+            || !valid_string(&self.serial_number)
+            || self
+                .uuid
+                .as_deref()
+                .map_or(false, |uuid| parse_uuid(uuid).is_none())
+        {
+            return Err(VmConfigError::InvalidSmbiosConfig);
+        }
+        Ok(())
+    }
+
+    /// The identity of the system written in guest memory, for a validated configuration.
+    pub fn system_info(&self) -> arch::SystemInfo {
+        arch::SystemInfo {
+            manufacturer: self.manufacturer.clone(),
+            product_name: self.product_name.clone(),
+            serial_number: self.serial_number.clone(),
+            uuid: self
+                .uuid
+                .as_deref()
+                .and_then(parse_uuid)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+// Parses a UUID formatted as xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx.
+fn parse_uuid(uuid: &str) -> Option<[u8; 16]> {
+    let groups: Vec<&str> = uuid.split('-').collect();
+    let group_lens: Vec<usize> = groups.iter().map(|group| group.len()).collect();
+    if group_lens != [8, 4, 4, 4, 12] {
+        return None;
+    }
+    let digits = groups.concat();
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut bytes = [0u8; 16];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&digits[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
 /// Template types available for configuring the CPU features that map
 /// to EC2 instances.
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
@@ -130,6 +504,100 @@ pub enum CpuFeaturesTemplate {
     T2,
 }
 
+/// Action taken when the guest reboots.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum RebootAction {
+    /// Shut the microVM down, ending the Firecracker process.
+    Shutdown,
+    /// Boot the microVM again, with the same configuration.
+    Restart,
+}
+
+impl Default for RebootAction {
+    fn default() -> Self {
+        RebootAction::Shutdown
+    }
+}
+
+/// How the microVM stops when the guest resets the i8042 controller to reboot.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum ResetAction {
+    /// Stop the microVM with the success exit code, like when the guest powers off.
+    Shutdown,
+    /// Stop the microVM with the `FC_EXIT_CODE_GUEST_REBOOT` exit code.
+    ReportReboot,
+}
+
+impl Default for ResetAction {
+    fn default() -> Self {
+        ResetAction::Shutdown
+    }
+}
+
+/// What happens when the guest kernel panics.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum PanicAction {
+    /// Leave the guest to handle its panic, e.g. by rebooting as configured by `panic=`.
+    Ignore,
+    /// Stop the microVM with the `FC_EXIT_CODE_GUEST_PANIC` exit code.
+    ReportPanic,
+}
+
+impl Default for PanicAction {
+    fn default() -> Self {
+        PanicAction::Ignore
+    }
+}
+
+/// The paths of the full snapshot created when the guest asks for one. The guest can't choose
+/// them, each of its requests overwrites the previous snapshot.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct GuestSnapshotConfig {
+    /// Path to the file that will contain the microVM state.
+    pub snapshot_path: PathBuf,
+    /// Path to the file that will contain the guest memory.
+    pub mem_file_path: PathBuf,
+}
+
+/// The interrupt controller exposed to the guest.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum InterruptController {
+    /// The local APIC, only in xAPIC mode. Only available on x86_64.
+    XApic,
+    /// The local APIC, which the guest can switch to x2APIC mode. Only available on x86_64.
+    X2Apic,
+    /// GICv2. Only available on aarch64.
+    GicV2,
+    /// GICv3, without ITS. Only available on aarch64.
+    GicV3,
+}
+
+impl InterruptController {
+    /// Whether the interrupt controller exists on the host architecture.
+    pub fn is_available(self) -> bool {
+        match self {
+            InterruptController::XApic | InterruptController::X2Apic => {
+                cfg!(target_arch = "x86_64")
+            }
+            InterruptController::GicV2 | InterruptController::GicV3 => {
+                cfg!(target_arch = "aarch64")
+            }
+        }
+    }
+}
+
+impl fmt::Display for InterruptController {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InterruptController::XApic => write!(f, "xAPIC"),
+            InterruptController::X2Apic => write!(f, "x2APIC"),
+            InterruptController::GicV2 => write!(f, "GICv2"),
+            InterruptController::GicV3 => write!(f, "GICv3"),
+        }
+    }
+}
+
 impl fmt::Display for CpuFeaturesTemplate {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -149,6 +617,75 @@ mod tests {
         assert_eq!(CpuFeaturesTemplate::T2.to_string(), "T2".to_string());
     }
 
+    #[test]
+    fn test_interrupt_controller() {
+        assert_eq!(InterruptController::X2Apic.to_string(), "x2APIC");
+        assert_eq!(InterruptController::GicV3.to_string(), "GICv3");
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            assert!(InterruptController::XApic.is_available());
+            assert!(InterruptController::X2Apic.is_available());
+            assert!(!InterruptController::GicV2.is_available());
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            assert!(InterruptController::GicV2.is_available());
+            assert!(InterruptController::GicV3.is_available());
+            assert!(!InterruptController::XApic.is_available());
+        }
+    }
+
+    #[test]
+    fn test_smbios_config() {
+        let config: SmbiosConfig = serde_json::from_str(r#"{"serial_number": "fc-0123"}"#).unwrap();
+        assert_eq!(config.manufacturer, "Firecracker");
+        assert_eq!(config.product_name, "microVM");
+        config.validate().unwrap();
+        let system_info = config.system_info();
+        assert_eq!(system_info.serial_number, "fc-0123");
+        assert_eq!(system_info.uuid, [0; 16]);
+
+        let config = SmbiosConfig {
+            uuid: Some("0123ABCD-4567-89ab-cdef-0123456789AB".to_string()),
+            ..Default::default()
+        };
+        config.validate().unwrap();
+        assert_eq!(
+            config.system_info().uuid,
+            [
+                0x01, 0x23, 0xab, 0xcd, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x01, 0x23, 0x45, 0x67,
+                0x89, 0xab
+            ]
+        );
+
+        for uuid in &[
+            "",
+            "0123abcd456789abcdef0123456789ab",
+            "0123abcd-4567-89ab-cdef-0123456789a",
+            "0123abcd-4567-89ab-cdef-0123456789ag",
+            "+123abcd-4567-89ab-cdef-0123456789ab",
+            "0123abcd-4567-89ab-cdef-0123456789ab-",
+        ] {
+            let config = SmbiosConfig {
+                uuid: Some(uuid.to_string()),
+                ..Default::default()
+            };
+            assert_eq!(config.validate(), Err(VmConfigError::InvalidSmbiosConfig));
+        }
+
+        let config = SmbiosConfig {
+            serial_number: "x".repeat(MAX_SMBIOS_STRING_LEN + 1),
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(VmConfigError::InvalidSmbiosConfig));
+        let config = SmbiosConfig {
+            product_name: "micro\0VM".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(VmConfigError::InvalidSmbiosConfig));
+    }
+
     #[test]
     fn test_display_vm_config_error() {
         let expected_str = "The vCPU number is invalid! The vCPU number can only \
@@ -157,5 +694,129 @@ mod tests {
 
         let expected_str = "The memory size (MiB) is invalid.";
         assert_eq!(VmConfigError::InvalidMemorySize.to_string(), expected_str);
+
+        let expected_str = "The guest memory layout is invalid! The memory regions must be \
+                            page aligned, must not overlap, must add up to the memory size \
+                            and must be valid guest memory on this platform.";
+        assert_eq!(VmConfigError::InvalidMemoryLayout.to_string(), expected_str);
+
+        let expected_str = "The host does not have enough available memory for the memory \
+                            size! 1100 MiB are required, including headroom, but only 512 \
+                            MiB are available. Set allow_mem_overcommit to skip this check.";
+        assert_eq!(
+            VmConfigError::InsufficientHostMemory(1100, 512).to_string(),
+            expected_str
+        );
+
+        let expected_str = "The vCPU number and the memory size cannot be changed once the \
+                            vCPUs are started, either by booting the microVM or by resuming \
+                            it from a snapshot, whose vCPU number and memory size are kept.";
+        assert_eq!(
+            VmConfigError::UpdateNotAllowedPostBoot.to_string(),
+            expected_str
+        );
+    }
+
+    #[test]
+    fn test_check_host_memory() {
+        // The guest memory plus the headroom has to fit in the available memory.
+        assert!(check_host_memory(1000, Some(1100)).is_ok());
+        assert_eq!(
+            check_host_memory(1000, Some(1099)),
+            Err(VmConfigError::InsufficientHostMemory(1100, 1099))
+        );
+        // The check is skipped when the available memory is unknown.
+        assert!(check_host_memory(1000, None).is_ok());
+        // Huge sizes don't overflow.
+        assert!(check_host_memory(usize::MAX, Some(1024)).is_err());
+    }
+
+    #[test]
+    fn test_parse_mem_available_mib() {
+        let meminfo = "MemTotal:       16318140 kB\n\
+                       MemFree:         8155068 kB\n\
+                       MemAvailable:   12582912 kB\n\
+                       Buffers:          450040 kB\n";
+        assert_eq!(parse_mem_available_mib(meminfo), Some(12288));
+        assert_eq!(parse_mem_available_mib("MemTotal: 16318140 kB\n"), None);
+        assert_eq!(parse_mem_available_mib("MemAvailable: lots kB\n"), None);
+        // The host this runs on reports its available memory.
+        assert!(host_mem_available_mib().is_some());
+    }
+
+    #[test]
+    fn test_validate_mem_regions() {
+        #[cfg(target_arch = "x86_64")]
+        let mem_start = 0;
+        #[cfg(target_arch = "aarch64")]
+        let mem_start = arch::aarch64::layout::DRAM_MEM_START;
+        let region = |guest_addr: u64, size_mib: usize, numa_node: u32| MemoryRegionConfig {
+            guest_addr: mem_start + guest_addr,
+            size_mib,
+            numa_node,
+        };
+
+        // The NUMA nodes can only be assigned on aarch64.
+        #[cfg(target_arch = "x86_64")]
+        let numa_node = 0;
+        #[cfg(target_arch = "aarch64")]
+        let numa_node = 1;
+
+        // The regions don't need to be sorted.
+        let mem_regions = vec![region(1 << 30, 64, numa_node), region(0, 64, 0)];
+        assert!(validate_mem_regions(&mem_regions, 128).is_ok());
+        assert_eq!(
+            sorted_mem_regions(&mem_regions),
+            vec![&mem_regions[1], &mem_regions[0]]
+        );
+
+        // The regions must add up to the memory size.
+        assert_eq!(
+            validate_mem_regions(&mem_regions, 256),
+            Err(VmConfigError::InvalidMemoryLayout)
+        );
+        // There must be at least a region.
+        assert_eq!(
+            validate_mem_regions(&[], 0),
+            Err(VmConfigError::InvalidMemoryLayout)
+        );
+        // Empty regions are not allowed.
+        assert_eq!(
+            validate_mem_regions(&[region(0, 128, 0), region(1 << 30, 0, 0)], 128),
+            Err(VmConfigError::InvalidMemoryLayout)
+        );
+        // The regions must be page aligned.
+        assert_eq!(
+            validate_mem_regions(&[region(0, 64, 0), region((1 << 30) + 1, 64, 0)], 128),
+            Err(VmConfigError::InvalidMemoryLayout)
+        );
+        // The regions can't overlap.
+        assert_eq!(
+            validate_mem_regions(&[region(0, 64, 0), region(32 << 20, 64, 0)], 128),
+            Err(VmConfigError::InvalidMemoryLayout)
+        );
+        // The layout must be valid on this platform, i.e. start at the beginning of the
+        // guest memory.
+        assert_eq!(
+            validate_mem_regions(&[region(1 << 30, 128, 0)], 128),
+            Err(VmConfigError::InvalidMemoryLayout)
+        );
+        // Overflows are caught.
+        assert_eq!(
+            validate_mem_regions(
+                &[
+                    region(0, 64, 0),
+                    region((u64::MAX - mem_start) & !0xfff, 64, 0)
+                ],
+                128
+            ),
+            Err(VmConfigError::InvalidMemoryLayout)
+        );
+
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(
+            validate_mem_regions(&[region(0, 64, 0), region(1 << 30, 64, 1)], 128),
+            Err(VmConfigError::UnsupportedNumaNode)
+        );
     }
 }
@@ -0,0 +1,20 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Configurations used for dumping the guest memory.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// Stores the configuration used for dumping the guest memory of a running microVM.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DumpGuestMemoryParams {
+    /// Path to the file that will contain the guest memory dump.
+    pub mem_file_path: PathBuf,
+    /// When set to true, the vCPUs are paused while the guest memory is dumped, so that the
+    /// dump is consistent, and resumed afterwards.
+    #[serde(default)]
+    pub pause_vm: bool,
+}
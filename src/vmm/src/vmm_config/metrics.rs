@@ -4,17 +4,27 @@
 //! Auxiliary module for configuring the metrics system.
 use std::fmt::{Display, Formatter};
 use std::path::PathBuf;
+use std::sync::Mutex;
 
 use super::{open_file_nonblock, FcLineWriter};
-use logger::METRICS;
+use lazy_static::lazy_static;
+use logger::{MetricsFormat, METRICS};
 
 use serde::{Deserialize, Serialize};
 
+lazy_static! {
+    // Path of the metrics destination, recorded upon initialization so that it can be reopened.
+    static ref METRICS_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
 /// Strongly typed structure used to describe the metrics system.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct MetricsConfig {
     /// Named pipe or file used as output for metrics.
     pub metrics_path: PathBuf,
+    /// Format in which the metrics are written, JSON being the default.
+    #[serde(default)]
+    pub format: MetricsFormat,
 }
 
 /// Errors associated with actions on the `MetricsConfig`.
@@ -22,6 +32,8 @@ pub struct MetricsConfig {
 pub enum MetricsConfigError {
     /// Cannot initialize the metrics system due to bad user input.
     InitializationFailure(String),
+    /// Cannot reopen the metrics destination.
+    ReopenFailure(String),
 }
 
 impl Display for MetricsConfigError {
@@ -29,6 +41,11 @@ impl Display for MetricsConfigError {
         use self::MetricsConfigError::*;
         match *self {
             InitializationFailure(ref err_msg) => write!(f, "{}", err_msg.replace("\"", "")),
+            ReopenFailure(ref err_msg) => write!(
+                f,
+                "Cannot reopen the metrics destination: {}",
+                err_msg.replace("\"", "")
+            ),
         }
     }
 }
@@ -38,10 +55,33 @@ pub fn init_metrics(metrics_cfg: MetricsConfig) -> std::result::Result<(), Metri
     let writer = FcLineWriter::new(
         open_file_nonblock(&metrics_cfg.metrics_path)
             .map_err(|e| MetricsConfigError::InitializationFailure(e.to_string()))?,
+        &METRICS.logger.missed_metrics_count,
     );
     METRICS
         .init(Box::new(writer))
-        .map_err(|e| MetricsConfigError::InitializationFailure(e.to_string()))
+        .map_err(|e| MetricsConfigError::InitializationFailure(e.to_string()))?;
+    METRICS.set_format(metrics_cfg.format);
+
+    *METRICS_PATH.lock().expect("Poisoned lock") = Some(metrics_cfg.metrics_path);
+    Ok(())
+}
+
+/// Closes and reopens the metrics destination at the path provided upon initialization, e.g.
+/// after it was rotated. It does nothing if the metrics system was not initialized.
+pub fn reopen_metrics() -> std::result::Result<(), MetricsConfigError> {
+    let metrics_path = match METRICS_PATH.lock().expect("Poisoned lock").clone() {
+        Some(metrics_path) => metrics_path,
+        None => return Ok(()),
+    };
+
+    let writer = FcLineWriter::new(
+        open_file_nonblock(&metrics_path)
+            .map_err(|e| MetricsConfigError::ReopenFailure(e.to_string()))?,
+        &METRICS.logger.missed_metrics_count,
+    );
+    METRICS
+        .reopen(Box::new(writer))
+        .map_err(|e| MetricsConfigError::ReopenFailure(e.to_string()))
 }
 
 #[cfg(test)]
@@ -54,6 +94,7 @@ mod tests {
         // Error case: initializing metrics with invalid pipe returns error.
         let desc = MetricsConfig {
             metrics_path: PathBuf::from("not_found_file_metrics"),
+            format: MetricsFormat::Json,
         };
         assert!(init_metrics(desc).is_err());
 
@@ -61,10 +102,17 @@ mod tests {
         let metrics_file = TempFile::new().unwrap();
         let desc = MetricsConfig {
             metrics_path: metrics_file.as_path().to_path_buf(),
+            format: MetricsFormat::OpenMetrics,
         };
 
         assert!(init_metrics(desc.clone()).is_ok());
         assert!(init_metrics(desc).is_err());
+
+        // Reopening the metrics destination keeps writing to the same path.
+        assert!(reopen_metrics().is_ok());
+        assert!(METRICS.write().unwrap());
+        let text = std::fs::read_to_string(metrics_file.as_path()).unwrap();
+        assert!(text.ends_with("# EOF\n"));
     }
 
     #[test]
@@ -78,5 +126,12 @@ mod tests {
             ),
             "Failed to initialize metrics"
         );
+        assert_eq!(
+            format!(
+                "{}",
+                MetricsConfigError::ReopenFailure(String::from("No such file or directory"))
+            ),
+            "Cannot reopen the metrics destination: No such file or directory"
+        );
     }
 }
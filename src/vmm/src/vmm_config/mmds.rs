@@ -1,24 +1,43 @@
 // Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use mmds::data_store::MmdsVersion;
+use mmds::token::Error as TokenError;
 use serde::{export::Formatter, Deserialize, Serialize};
 use std::fmt::{Display, Result};
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 
 /// Keeps the MMDS configuration.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct MmdsConfig {
+    /// MMDS version. Version 2 requires guest applications to obtain a session token first.
+    #[serde(default)]
+    pub version: MmdsVersion,
     /// MMDS IPv4 configured address.
     pub ipv4_address: Option<Ipv4Addr>,
+    /// MMDS IPv6 configured address. MMDS is not reachable over IPv6 unless this is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ipv6_address: Option<Ipv6Addr>,
 }
 
 impl MmdsConfig {
+    /// Returns the MMDS version.
+    pub fn version(&self) -> MmdsVersion {
+        self.version
+    }
+
     /// Returns the MMDS IPv4 address if one was configured.
     /// Otherwise returns None.
     pub fn ipv4_addr(&self) -> Option<Ipv4Addr> {
         self.ipv4_address
     }
+
+    /// Returns the MMDS IPv6 address if one was configured.
+    /// Otherwise returns None.
+    pub fn ipv6_addr(&self) -> Option<Ipv6Addr> {
+        self.ipv6_address
+    }
 }
 
 /// MMDS configuration related errors.
@@ -26,6 +45,10 @@ impl MmdsConfig {
 pub enum MmdsConfigError {
     /// The provided IPv4 address is not link-local valid.
     InvalidIpv4Addr,
+    /// The provided IPv6 address is not link-local valid.
+    InvalidIpv6Addr,
+    /// Failed to set the MMDS version.
+    MmdsVersion(MmdsVersion, TokenError),
 }
 
 impl Display for MmdsConfigError {
@@ -34,6 +57,12 @@ impl Display for MmdsConfigError {
             MmdsConfigError::InvalidIpv4Addr => {
                 write!(f, "The MMDS IPv4 address is not link local.")
             }
+            MmdsConfigError::InvalidIpv6Addr => {
+                write!(f, "The MMDS IPv6 address is not link local.")
+            }
+            MmdsConfigError::MmdsVersion(version, err) => {
+                write!(f, "Failed to set MMDS version to {:?}: {}", version, err)
+            }
         }
     }
 }
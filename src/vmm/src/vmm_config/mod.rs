@@ -1,35 +1,50 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::collections::VecDeque;
 use std::convert::{From, TryInto};
 use std::fs::{File, OpenOptions};
-use std::io;
+use std::io::{self, Write};
 use std::os::unix::fs::OpenOptionsExt;
 use std::path::Path;
 
 use libc::O_NONBLOCK;
+use logger::{IncMetric, SharedIncMetric};
 use serde::{Deserialize, Serialize};
 
-use rate_limiter::{BucketUpdate, RateLimiter, TokenBucket};
+use rate_limiter::{BucketUpdate, RateLimiter, TokenBucket, MAX_REFILL_TIME_MS};
+
+/// Number of IRQs available to the devices attached on the MMIO bus.
+pub const MAX_DEVICE_IRQS: usize = (arch::IRQ_MAX - arch::IRQ_BASE + 1) as usize;
 
 /// Wrapper for configuring the balloon device.
 pub mod balloon;
 /// Wrapper for configuring the microVM boot source.
 pub mod boot_source;
+/// Wrapper for describing the devices attached to the microVM.
+pub mod devices;
 /// Wrapper for configuring the block devices.
 pub mod drive;
+/// Wrapper for configuring the virtio-fs devices.
+pub mod fs;
 /// Wrapper over the microVM general information attached to the microVM.
 pub mod instance_info;
 /// Wrapper for configuring the logger.
 pub mod logger;
 /// Wrapper for configuring the memory and CPU of the microVM.
 pub mod machine_config;
+/// Wrapper for configuring the guest memory dumps.
+pub mod memory_dump;
 /// Wrapper for configuring the metrics.
 pub mod metrics;
 /// Wrapper for configuring the MMDS.
 pub mod mmds;
 /// Wrapper for configuring the network devices attached to the microVM.
 pub mod net;
+/// Wrapper for configuring the resource limits of the Firecracker process.
+pub mod resource_limits;
+/// Wrapper for configuring the serial console.
+pub mod serial;
 /// Wrapper for configuring microVM snapshots and the microVM state.
 pub mod snapshot;
 /// Wrapper for configuring the vsock devices attached to the microVM.
@@ -70,6 +85,29 @@ impl From<&TokenBucket> for TokenBucketConfig {
     }
 }
 
+impl TokenBucketConfig {
+    /// Checks that the bucket is either disabled, through a zero `size`, or refills in a
+    /// non-zero `refill_time` of at most `MAX_REFILL_TIME_MS` milliseconds.
+    pub fn validate(&self) -> Result<()> {
+        if self.size != 0 && self.refill_time == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The refill_time of a token bucket with a non-zero size must not be zero.",
+            ));
+        }
+        if self.refill_time > MAX_REFILL_TIME_MS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "The refill_time of a token bucket must not exceed {} ms.",
+                    MAX_REFILL_TIME_MS
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
 /// A public-facing, stateless structure, holding all the data we need to create a RateLimiter
 /// (live) object.
 #[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
@@ -129,6 +167,7 @@ impl TryInto<RateLimiter> for RateLimiterConfig {
     type Error = io::Error;
 
     fn try_into(self) -> std::result::Result<RateLimiter, Self::Error> {
+        self.validate()?;
         let bw = self.bandwidth.unwrap_or_default();
         let ops = self.ops.unwrap_or_default();
         RateLimiter::new(
@@ -152,6 +191,14 @@ impl From<&RateLimiter> for RateLimiterConfig {
 }
 
 impl RateLimiterConfig {
+    /// Checks the configurations of both token buckets.
+    pub fn validate(&self) -> Result<()> {
+        self.bandwidth
+            .iter()
+            .chain(self.ops.iter())
+            .try_for_each(TokenBucketConfig::validate)
+    }
+
     // Option<T> already implements From<T> so we have to use a custom one.
     fn into_option(self) -> Option<RateLimiterConfig> {
         if self.bandwidth.is_some() || self.ops.is_some() {
@@ -167,7 +214,8 @@ type Result<T> = std::result::Result<T, std::io::Error>;
 /// Create and opens a File for writing to it.
 /// In case we open a FIFO, in order to not block the instance if nobody is consuming the message
 /// that is flushed to the two pipes, we are opening it with `O_NONBLOCK` flag.
-/// In this case, writing to a pipe will start failing when reaching 64K of unconsumed content.
+/// In this case, writing to a pipe will start failing when reaching 64K of unconsumed content,
+/// so the writes are buffered by `FcLineWriter`.
 fn open_file_nonblock(path: &Path) -> Result<File> {
     OpenOptions::new()
         .custom_flags(O_NONBLOCK)
@@ -176,12 +224,102 @@ fn open_file_nonblock(path: &Path) -> Result<File> {
         .open(&path)
 }
 
-type FcLineWriter = io::LineWriter<File>;
+// The maximum size of the lines kept in memory while the destination doesn't take them in.
+const FC_LINE_WRITER_BUFFER_SIZE: usize = 1 << 20;
+
+/// Writes complete lines to a file opened with `O_NONBLOCK`, without ever blocking the caller.
+/// The lines which the file doesn't take in, e.g. because nobody reads the other end of a FIFO,
+/// are buffered in memory and written out on the next writes. When the buffer is full, the
+/// oldest lines are dropped and counted in `dropped_lines`.
+struct FcLineWriter {
+    file: File,
+    dropped_lines: &'static SharedIncMetric,
+    // The last line written, until its newline.
+    partial_line: Vec<u8>,
+    // The complete lines which were not written out yet.
+    pending_lines: VecDeque<Vec<u8>>,
+    pending_len: usize,
+    // How much of the first pending line was written out.
+    front_offset: usize,
+}
+
+impl FcLineWriter {
+    fn new(file: File, dropped_lines: &'static SharedIncMetric) -> Self {
+        FcLineWriter {
+            file,
+            dropped_lines,
+            partial_line: Vec::new(),
+            pending_lines: VecDeque::new(),
+            pending_len: 0,
+            front_offset: 0,
+        }
+    }
+
+    // Writes out the pending lines until the file stops taking them in.
+    fn write_pending_lines(&mut self) -> io::Result<()> {
+        while let Some(line) = self.pending_lines.front() {
+            match self.file.write(&line[self.front_offset..]) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+                Ok(count) => {
+                    self.front_offset += count;
+                    if self.front_offset == line.len() {
+                        self.pending_len -= line.len();
+                        self.front_offset = 0;
+                        self.pending_lines.pop_front();
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    // Drops the oldest pending lines until the buffer is within its limit. A line which was
+    // partially written out is kept, so that no torn line reaches the destination.
+    fn drop_oldest_lines(&mut self) {
+        while self.pending_len > FC_LINE_WRITER_BUFFER_SIZE {
+            let index = if self.front_offset > 0 { 1 } else { 0 };
+            match self.pending_lines.remove(index) {
+                Some(line) => {
+                    self.pending_len -= line.len();
+                    self.dropped_lines.inc();
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+impl Write for FcLineWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for chunk in buf.split_inclusive(|&byte| byte == b'\n') {
+            self.partial_line.extend_from_slice(chunk);
+            if chunk.ends_with(b"\n") {
+                let line = std::mem::take(&mut self.partial_line);
+                self.pending_len += line.len();
+                self.pending_lines.push_back(line);
+            }
+        }
+
+        self.write_pending_lines()?;
+        self.drop_oldest_lines();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_pending_lines()?;
+        self.file.flush()
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use std::io::Write;
+    use std::io::Read;
+    use std::os::unix::io::FromRawFd;
 
+    use logger::METRICS;
     use utils::tempfile::TempFile;
 
     use super::*;
@@ -234,6 +372,45 @@ mod tests {
         assert_eq!(generated_rl_conf.into_option(), Some(rl_conf));
     }
 
+    #[test]
+    fn test_validate_rate_limiter_config() {
+        let mut tb_cfg = TokenBucketConfig {
+            size: SIZE,
+            one_time_burst: Some(ONE_TIME_BURST),
+            refill_time: REFILL_TIME,
+        };
+        assert!(tb_cfg.validate().is_ok());
+
+        // A zero size disables the bucket, whatever its refill time.
+        tb_cfg.size = 0;
+        tb_cfg.refill_time = 0;
+        assert!(tb_cfg.validate().is_ok());
+
+        tb_cfg.size = SIZE;
+        let err = tb_cfg.validate().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        tb_cfg.refill_time = MAX_REFILL_TIME_MS + 1;
+        let err = tb_cfg.validate().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        tb_cfg.refill_time = MAX_REFILL_TIME_MS;
+        assert!(tb_cfg.validate().is_ok());
+
+        // Both buckets are checked when creating the rate limiter.
+        let rl_conf = RateLimiterConfig {
+            bandwidth: None,
+            ops: Some(TokenBucketConfig {
+                size: SIZE,
+                one_time_burst: None,
+                refill_time: 0,
+            }),
+        };
+        assert!(rl_conf.validate().is_err());
+        let res: std::result::Result<RateLimiter, _> = rl_conf.try_into();
+        assert!(res.is_err());
+    }
+
     #[test]
     fn test_fifo_line_writer() {
         let log_file_temp =
@@ -241,10 +418,60 @@ mod tests {
         let good_file = log_file_temp.as_path().to_path_buf();
         let maybe_fifo = open_file_nonblock(&good_file);
         assert!(maybe_fifo.is_ok());
-        let mut fw = FcLineWriter::new(maybe_fifo.unwrap());
+        let mut fw = FcLineWriter::new(maybe_fifo.unwrap(), &METRICS.logger.missed_log_count);
 
         let msg = String::from("some message");
         assert!(fw.write(&msg.as_bytes()).is_ok());
         assert!(fw.flush().is_ok());
+        // The line is written out only once it's complete.
+        assert!(std::fs::read(&good_file).unwrap().is_empty());
+        assert!(fw.write(b" and its end\nnext").is_ok());
+        assert_eq!(
+            std::fs::read(&good_file).unwrap(),
+            b"some message and its end\n"
+        );
+    }
+
+    #[test]
+    fn test_fifo_line_writer_full_fifo() {
+        let mut fds = [0; 2];
+        // Safe because we check the return value and own the created file descriptors.
+        assert_eq!(
+            unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK) },
+            0
+        );
+        // Safe because the file descriptors are valid and not owned by anything else.
+        let mut reader = unsafe { File::from_raw_fd(fds[0]) };
+        let writer = unsafe { File::from_raw_fd(fds[1]) };
+        let dropped_lines: &'static SharedIncMetric = Box::leak(Box::new(Default::default()));
+        let mut fw = FcLineWriter::new(writer, dropped_lines);
+
+        // Nobody reads the pipe, so the writes fill the pipe, then the buffer, without blocking.
+        let line = vec![b'a'; 1023];
+        let mut written = 0;
+        while dropped_lines.count() == 0 {
+            fw.write_all(&line).unwrap();
+            fw.write_all(b"\n").unwrap();
+            written += 1;
+        }
+        assert!(fw.pending_len <= FC_LINE_WRITER_BUFFER_SIZE);
+
+        // Once the pipe is read, the buffered lines are written out, whole.
+        let mut pipe_content = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            fw.flush().unwrap();
+            match reader.read(&mut buf) {
+                Ok(count) => pipe_content.extend_from_slice(&buf[..count]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => panic!("{}", e),
+            }
+        }
+        assert!(fw.pending_lines.is_empty());
+        assert_eq!(pipe_content.len() % 1024, 0);
+        assert_eq!(pipe_content.len() / 1024, written - dropped_lines.count());
+        assert!(pipe_content
+            .chunks(1024)
+            .all(|chunk| chunk[..1023] == line[..] && chunk[1023] == b'\n'));
     }
 }
@@ -6,10 +6,11 @@ use std::fmt;
 use std::result;
 use std::sync::{Arc, Mutex};
 
-use super::RateLimiterConfig;
+use super::{RateLimiterConfig, MAX_DEVICE_IRQS};
 use crate::Error as VmmError;
 use devices::virtio::net::TapError;
 use devices::virtio::Net;
+use utils::net::ip_prefix::IpPrefix;
 use utils::net::mac::MacAddr;
 
 use serde::{Deserialize, Serialize};
@@ -37,6 +38,27 @@ pub struct NetworkInterfaceConfig {
     /// same address are intercepted by the device model, and do not reach
     /// the associated TAP device.
     pub allow_mmds_requests: bool,
+    #[serde(default)]
+    /// The guest addresses or prefixes (`10.0.0.2`, `10.0.0.0/24`, `fd00::/8`) from which the
+    /// MMDS can be reached via this interface. The requests of the other addresses are dropped.
+    /// Any address can reach the MMDS when this is empty.
+    pub mmds_allowed_sources: Vec<IpPrefix>,
+    #[serde(default = "default_enable_offload")]
+    /// If this field is set, checksum and segmentation offloading are negotiated with the
+    /// guest and enabled on the associated TAP device. Disabling it can help when debugging
+    /// networking issues, at the expense of throughput.
+    pub enable_offload: bool,
+    /// MTU set on the associated TAP device and advertised to the guest. If this field is not
+    /// set, the MTU of the TAP device is left unchanged and the guest defaults to 1500.
+    pub mtu: Option<u16>,
+    /// If this field is set, every frame received or transmitted by the guest is also written
+    /// to a pcap file created at this path.
+    pub pcap_path: Option<String>,
+    /// Minimum time between two used queue interrupts, in microseconds. The interrupts
+    /// requested sooner are merged into one, raised at the end of the interval. Every
+    /// interrupt is raised right away by default.
+    #[serde(default)]
+    pub interrupt_coalescing_us: u64,
 }
 
 impl From<&Net> for NetworkInterfaceConfig {
@@ -50,6 +72,11 @@ impl From<&Net> for NetworkInterfaceConfig {
             rx_rate_limiter: rx_rl.into_option(),
             tx_rate_limiter: tx_rl.into_option(),
             allow_mmds_requests: net.mmds_enabled(),
+            mmds_allowed_sources: net.mmds_allowed_sources(),
+            enable_offload: net.offload_enabled(),
+            mtu: net.mtu(),
+            pcap_path: net.pcap_path(),
+            interrupt_coalescing_us: net.interrupt_coalescing_us(),
         }
     }
 }
@@ -61,6 +88,13 @@ fn default_allow_mmds_requests() -> bool {
     false
 }
 
+fn default_enable_offload() -> bool {
+    true
+}
+
+/// The smallest MTU which a network interface can be configured with.
+pub const MIN_MTU: u16 = 576;
+
 /// The data fed into a network iface update request. Currently, only the RX and TX rate limiters
 /// can be updated.
 #[derive(Debug, Deserialize, PartialEq, Clone)]
@@ -74,6 +108,8 @@ pub struct NetworkInterfaceUpdateConfig {
     /// New TX rate limiter config. Only provided data will be updated. I.e. if any optional data
     /// is missing, it will not be nullified, but left unchanged.
     pub tx_rate_limiter: Option<RateLimiterConfig>,
+    /// New path of the packet capture file. An empty path stops the packet capture.
+    pub pcap_path: Option<String>,
 }
 
 /// Errors associated with `NetworkInterfaceConfig`.
@@ -85,10 +121,16 @@ pub enum NetworkInterfaceError {
     CreateRateLimiter(std::io::Error),
     /// The MAC address is already in use.
     GuestMacAddressInUse(String),
+    /// The MTU is too small.
+    InvalidMtu(u16),
+    /// The MMDS allowed sources are set on an interface which doesn't reach the MMDS.
+    MmdsAllowedSourcesWithoutMmds,
     /// Error during interface update (patch).
     DeviceUpdate(VmmError),
     /// Cannot open/create tap device.
     OpenTap(TapError),
+    /// There is no IRQ left for another device.
+    TooManyDevices,
 }
 
 impl fmt::Display for NetworkInterfaceError {
@@ -102,6 +144,17 @@ impl fmt::Display for NetworkInterfaceError {
                 "{}",
                 format!("The guest MAC address {} is already in use.", mac_addr)
             ),
+            InvalidMtu(mtu) => write!(
+                f,
+                "Invalid MTU {}: the MTU must be between {} and {}.",
+                mtu,
+                MIN_MTU,
+                u16::MAX
+            ),
+            MmdsAllowedSourcesWithoutMmds => write!(
+                f,
+                "The MMDS allowed sources can only be set when the MMDS requests are allowed."
+            ),
             DeviceUpdate(e) => write!(f, "Error during interface update (patch): {}", e),
             OpenTap(e) => {
                 // We are propagating the Tap Error. This error can contain
@@ -116,6 +169,11 @@ impl fmt::Display for NetworkInterfaceError {
                     tap_err
                 )
             }
+            TooManyDevices => write!(
+                f,
+                "Cannot attach another device: all the {} IRQs available to the devices are in use.",
+                MAX_DEVICE_IRQS
+            ),
         }
     }
 }
@@ -165,6 +223,14 @@ impl NetBuilder {
                 netif_config.guest_mac.unwrap().to_string(),
             ));
         }
+        if let Some(mtu) = netif_config.mtu {
+            if mtu < MIN_MTU {
+                return Err(NetworkInterfaceError::InvalidMtu(mtu));
+            }
+        }
+        if !netif_config.allow_mmds_requests && !netif_config.mmds_allowed_sources.is_empty() {
+            return Err(NetworkInterfaceError::MmdsAllowedSourcesWithoutMmds);
+        }
 
         // If this is an update, just remove the old one.
         if let Some(index) = self
@@ -196,15 +262,22 @@ impl NetBuilder {
             .map_err(NetworkInterfaceError::CreateRateLimiter)?;
 
         // Create and return the Net device
-        devices::virtio::net::Net::new_with_tap(
+        let mut net = devices::virtio::net::Net::new_with_tap(
             cfg.iface_id,
             cfg.host_dev_name.clone(),
             cfg.guest_mac.as_ref(),
             rx_rate_limiter.unwrap_or_default(),
             tx_rate_limiter.unwrap_or_default(),
             cfg.allow_mmds_requests,
+            cfg.enable_offload,
+            cfg.mtu,
         )
-        .map_err(NetworkInterfaceError::CreateNetworkDevice)
+        .map_err(NetworkInterfaceError::CreateNetworkDevice)?;
+        net.set_mmds_allowed_sources(cfg.mmds_allowed_sources);
+        net.set_pcap_path(cfg.pcap_path)
+            .map_err(NetworkInterfaceError::CreateNetworkDevice)?;
+        net.set_interrupt_coalescing_us(cfg.interrupt_coalescing_us);
+        Ok(net)
     }
 
     /// Returns a vec with the structures used to configure the net devices.
@@ -241,6 +314,11 @@ mod tests {
             rx_rate_limiter: RateLimiterConfig::default().into_option(),
             tx_rate_limiter: RateLimiterConfig::default().into_option(),
             allow_mmds_requests: false,
+            mmds_allowed_sources: Vec::new(),
+            enable_offload: true,
+            mtu: None,
+            pcap_path: None,
+            interrupt_coalescing_us: 0,
         }
     }
 
@@ -253,6 +331,11 @@ mod tests {
                 rx_rate_limiter: None,
                 tx_rate_limiter: None,
                 allow_mmds_requests: self.allow_mmds_requests,
+                mmds_allowed_sources: self.mmds_allowed_sources.clone(),
+                enable_offload: self.enable_offload,
+                mtu: self.mtu,
+                pcap_path: self.pcap_path.clone(),
+                interrupt_coalescing_us: self.interrupt_coalescing_us,
             }
         }
     }
@@ -370,6 +453,14 @@ mod tests {
             NetworkInterfaceError::OpenTap(TapError::InvalidIfname),
             NetworkInterfaceError::OpenTap(TapError::InvalidIfname)
         );
+        assert_eq!(
+            NetworkInterfaceError::InvalidMtu(68).to_string(),
+            "Invalid MTU 68: the MTU must be between 576 and 65535."
+        );
+        assert_eq!(
+            NetworkInterfaceError::MmdsAllowedSourcesWithoutMmds.to_string(),
+            "The MMDS allowed sources can only be set when the MMDS requests are allowed."
+        );
     }
 
     #[test]
@@ -393,4 +484,63 @@ mod tests {
         assert_eq!(configs.len(), 1);
         assert_eq!(configs.first().unwrap(), &net_if_cfg);
     }
+
+    #[test]
+    fn test_net_pcap_config() {
+        let tmp_file = utils::tempfile::TempFile::new().unwrap();
+        let pcap_path = tmp_file.as_path().to_str().unwrap().to_string();
+
+        let mut net_if_cfg = create_netif("id", "dev5", "01:23:45:67:89:0c");
+        net_if_cfg.pcap_path = Some(pcap_path);
+        let mut net_builder = NetBuilder::new();
+        assert!(net_builder.build(net_if_cfg.clone()).is_ok());
+        assert_eq!(net_builder.configs().first().unwrap(), &net_if_cfg);
+
+        // The capture file must be creatable.
+        net_if_cfg.pcap_path = Some("/invalid/path/capture.pcap".to_string());
+        match net_builder.build(net_if_cfg) {
+            Err(NetworkInterfaceError::CreateNetworkDevice(
+                devices::virtio::net::Error::PcapOpen(_),
+            )) => (),
+            _ => panic!("Expected a pcap open error."),
+        }
+    }
+
+    #[test]
+    fn test_net_mtu_config() {
+        let mut net_if_cfg = create_netif("id", "dev6", "01:23:45:67:89:0d");
+        net_if_cfg.mtu = Some(9000);
+        let mut net_builder = NetBuilder::new();
+        assert!(net_builder.build(net_if_cfg.clone()).is_ok());
+        assert_eq!(net_builder.configs().first().unwrap(), &net_if_cfg);
+
+        // Error Case: the MTU is too small. The existing device is kept.
+        net_if_cfg.mtu = Some(MIN_MTU - 1);
+        match net_builder.build(net_if_cfg) {
+            Err(NetworkInterfaceError::InvalidMtu(mtu)) => assert_eq!(mtu, MIN_MTU - 1),
+            _ => panic!("Expected an invalid MTU error."),
+        }
+        assert_eq!(net_builder.configs().first().unwrap().mtu, Some(9000));
+    }
+
+    #[test]
+    fn test_net_mmds_allowed_sources_config() {
+        let mut net_if_cfg = create_netif("id", "dev7", "01:23:45:67:89:0e");
+        net_if_cfg.allow_mmds_requests = true;
+        net_if_cfg.mmds_allowed_sources = vec!["10.0.0.0/24".parse().unwrap()];
+        let mut net_builder = NetBuilder::new();
+        assert!(net_builder.build(net_if_cfg.clone()).is_ok());
+        assert_eq!(net_builder.configs().first().unwrap(), &net_if_cfg);
+
+        // Error Case: the allowed sources only apply to the interfaces which reach the MMDS.
+        // The existing device is kept.
+        net_if_cfg.allow_mmds_requests = false;
+        match net_builder.build(net_if_cfg) {
+            Err(NetworkInterfaceError::MmdsAllowedSourcesWithoutMmds) => (),
+            _ => panic!("Expected an MMDS allowed sources error."),
+        }
+        let config = net_builder.configs().pop().unwrap();
+        assert!(config.allow_mmds_requests);
+        assert_eq!(config.mmds_allowed_sources.len(), 1);
+    }
 }
@@ -0,0 +1,124 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::{Display, Formatter};
+use std::io;
+
+/// Errors associated with applying the resource limits of the Firecracker process.
+#[derive(Debug)]
+pub enum ResourceLimitsError {
+    /// Cannot set the resource limit with the given name.
+    SetLimit(&'static str, io::Error),
+}
+
+impl Display for ResourceLimitsError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        use self::ResourceLimitsError::*;
+        match self {
+            SetLimit(resource, err) => {
+                write!(f, "Cannot set the {} resource limit: {}", resource, err)
+            }
+        }
+    }
+}
+
+/// Resource limits of the Firecracker process, provided through command line parameters.
+///
+/// The limits are applied once the microVM is built, after all the long-lived file
+/// descriptors (KVM, epoll, devices) are opened and before the seccomp filter of the VMM
+/// thread is loaded. A limit of 0 is not set, the one inherited from the parent process is
+/// kept instead, which is the default for all of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ResourceLimits {
+    /// Maximum number of open file descriptors (`RLIMIT_NOFILE`).
+    pub nofile: u64,
+    /// Maximum size of a file created or extended by the process, in bytes (`RLIMIT_FSIZE`).
+    /// It has to fit the snapshot and the guest memory dump files.
+    pub fsize: u64,
+    /// Maximum number of threads of the user running the process (`RLIMIT_NPROC`).
+    pub nproc: u64,
+}
+
+impl ResourceLimits {
+    /// Sets the configured resource limits on the Firecracker process. Both the soft and the
+    /// hard limit are set, so that they can't be raised afterwards.
+    pub fn apply(&self) -> Result<(), ResourceLimitsError> {
+        set_limit(libc::RLIMIT_NOFILE, "RLIMIT_NOFILE", self.nofile)?;
+        set_limit(libc::RLIMIT_FSIZE, "RLIMIT_FSIZE", self.fsize)?;
+        set_limit(libc::RLIMIT_NPROC, "RLIMIT_NPROC", self.nproc)
+    }
+}
+
+#[cfg(target_env = "musl")]
+type Resource = libc::c_int;
+#[cfg(not(target_env = "musl"))]
+type Resource = libc::__rlimit_resource_t;
+
+fn set_limit(
+    resource: Resource,
+    name: &'static str,
+    limit: u64,
+) -> Result<(), ResourceLimitsError> {
+    if limit == 0 {
+        return Ok(());
+    }
+    let rlim = libc::rlimit {
+        rlim_cur: limit,
+        rlim_max: limit,
+    };
+    // Safe because the kernel only reads the limits from the `rlimit` struct and we check the
+    // return value.
+    if unsafe { libc::setrlimit(resource, &rlim) } < 0 {
+        return Err(ResourceLimitsError::SetLimit(
+            name,
+            io::Error::last_os_error(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_limit(resource: Resource) -> libc::rlimit {
+        let mut rlim = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        assert_eq!(unsafe { libc::getrlimit(resource, &mut rlim) }, 0);
+        rlim
+    }
+
+    #[test]
+    fn test_apply_resource_limits() {
+        let nofile = get_limit(libc::RLIMIT_NOFILE);
+        let fsize = get_limit(libc::RLIMIT_FSIZE);
+
+        // Nothing is set by default.
+        ResourceLimits::default().apply().unwrap();
+        assert_eq!(get_limit(libc::RLIMIT_NOFILE).rlim_cur, nofile.rlim_cur);
+        assert_eq!(get_limit(libc::RLIMIT_FSIZE).rlim_cur, fsize.rlim_cur);
+
+        // Setting the current limit keeps the tests able to open files.
+        let limits = ResourceLimits {
+            nofile: nofile.rlim_cur,
+            ..Default::default()
+        };
+        limits.apply().unwrap();
+        assert_eq!(get_limit(libc::RLIMIT_NOFILE).rlim_cur, nofile.rlim_cur);
+        assert_eq!(get_limit(libc::RLIMIT_NOFILE).rlim_max, nofile.rlim_cur);
+        assert_eq!(get_limit(libc::RLIMIT_FSIZE).rlim_cur, fsize.rlim_cur);
+    }
+
+    #[test]
+    fn test_error_messages() {
+        let err = ResourceLimitsError::SetLimit(
+            "RLIMIT_NPROC",
+            io::Error::from_raw_os_error(libc::EPERM),
+        );
+        assert!(err
+            .to_string()
+            .starts_with("Cannot set the RLIMIT_NPROC resource limit"));
+    }
+}
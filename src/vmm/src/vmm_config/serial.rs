@@ -0,0 +1,102 @@
+// Copyright 2021 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Default size of the in-memory log of the guest serial output, in bytes.
+pub const DEFAULT_SERIAL_LOG_SIZE: usize = 16 * 1024;
+
+/// Device backing the guest console, which is connected to the Firecracker stdin and stdout.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConsoleType {
+    /// The legacy 8250 serial port (ttyS0 in the guest).
+    Serial,
+    /// A virtio console device (hvc0), which is made the primary console of the guest.
+    Virtio,
+}
+
+impl ConsoleType {
+    /// Name of the guest device backing the console, as given to the `console=` parameter of
+    /// the kernel command line.
+    pub fn guest_device(self) -> &'static str {
+        match self {
+            ConsoleType::Serial => "ttyS0",
+            ConsoleType::Virtio => "hvc0",
+        }
+    }
+}
+
+impl Default for ConsoleType {
+    fn default() -> Self {
+        ConsoleType::Serial
+    }
+}
+
+impl FromStr for ConsoleType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "serial" => Ok(ConsoleType::Serial),
+            "virtio" => Ok(ConsoleType::Virtio),
+            _ => Err(format!("Invalid console type: {}", s)),
+        }
+    }
+}
+
+/// Configuration of the serial console, provided through command line parameters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SerialConfig {
+    /// If set to true, the serial console is output-only.
+    pub disable_input: bool,
+    /// Size of the in-memory log of the guest serial output, in bytes. A size of 0 disables
+    /// the log.
+    pub log_size: usize,
+    /// Host file or FIFO receiving the output of the second serial port (ttyS1 in the guest).
+    /// Without it, the output of the second serial port is discarded. Only used on x86_64.
+    pub second_output_path: Option<PathBuf>,
+    /// Device backing the guest console. With the virtio console, the legacy serial port is
+    /// not connected to the host.
+    pub console_type: ConsoleType,
+    /// If set to true, the `console=` parameter matching the console type is added to the
+    /// kernel command line when the command line doesn't select a console already.
+    pub auto_console: bool,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        SerialConfig {
+            disable_input: false,
+            log_size: DEFAULT_SERIAL_LOG_SIZE,
+            second_output_path: None,
+            console_type: ConsoleType::default(),
+            auto_console: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_console_type_from_str() {
+        assert_eq!(ConsoleType::default(), ConsoleType::Serial);
+        assert_eq!(
+            "serial".parse::<ConsoleType>().unwrap(),
+            ConsoleType::Serial
+        );
+        assert_eq!(
+            "virtio".parse::<ConsoleType>().unwrap(),
+            ConsoleType::Virtio
+        );
+        assert!("hvc".parse::<ConsoleType>().is_err());
+    }
+
+    #[test]
+    fn test_console_type_guest_device() {
+        assert_eq!(ConsoleType::Serial.guest_device(), "ttyS0");
+        assert_eq!(ConsoleType::Virtio.guest_device(), "hvc0");
+    }
+}
@@ -3,6 +3,8 @@
 
 //! Configurations used in the snapshotting context.
 
+use std::collections::HashMap;
+use std::os::unix::io::RawFd;
 use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
@@ -23,6 +25,21 @@ impl Default for SnapshotType {
     }
 }
 
+/// The options for mapping the memory file of a snapshot into the guest memory.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum MemoryMapping {
+    /// Copy-on-write mapping, the memory file is never written to.
+    Private,
+    /// Shared mapping, the guest writes are carried through to the memory file.
+    Shared,
+}
+
+impl Default for MemoryMapping {
+    fn default() -> MemoryMapping {
+        MemoryMapping::Private
+    }
+}
+
 /// Stores the configuration that will be used for creating a snapshot.
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
@@ -32,12 +49,26 @@ pub struct CreateSnapshotParams {
     #[serde(default = "SnapshotType::default")]
     pub snapshot_type: SnapshotType,
     /// Path to the file that will contain the microVM state.
+    #[serde(default)]
     pub snapshot_path: PathBuf,
+    /// Inherited file descriptor of the file that will contain the microVM state, used
+    /// instead of `snapshot_path`. Firecracker takes ownership of the descriptor.
+    #[serde(default)]
+    pub snapshot_fd: Option<RawFd>,
     /// Path to the file that will contain the guest memory.
+    #[serde(default)]
     pub mem_file_path: PathBuf,
+    /// Inherited file descriptor of the file that will contain the guest memory, used
+    /// instead of `mem_file_path`. Firecracker takes ownership of the descriptor.
+    #[serde(default)]
+    pub mem_file_fd: Option<RawFd>,
     /// Optional field for the microVM version. The default
     /// value is the current version.
     pub version: Option<String>,
+    /// When set to true, the guest memory is copied while the microVM keeps running, which is
+    /// then only paused for the final copy of the pages it dirtied, and resumed.
+    #[serde(default)]
+    pub live: bool,
 }
 
 /// Stores the configuration that will be used for loading a snapshot.
@@ -45,9 +76,19 @@ pub struct CreateSnapshotParams {
 #[serde(deny_unknown_fields)]
 pub struct LoadSnapshotParams {
     /// Path to the file that contains the microVM state to be loaded.
+    #[serde(default)]
     pub snapshot_path: PathBuf,
+    /// Inherited file descriptor of the file that contains the microVM state to be loaded,
+    /// used instead of `snapshot_path`. Firecracker takes ownership of the descriptor.
+    #[serde(default)]
+    pub snapshot_fd: Option<RawFd>,
     /// Path to the file that contains the guest memory to be loaded.
+    #[serde(default)]
     pub mem_file_path: PathBuf,
+    /// Inherited file descriptor of the file that contains the guest memory to be loaded,
+    /// used instead of `mem_file_path`. Firecracker takes ownership of the descriptor.
+    #[serde(default)]
+    pub mem_file_fd: Option<RawFd>,
     /// Setting this flag will enable KVM dirty page tracking and will
     /// allow taking subsequent incremental snapshots.
     #[serde(default)]
@@ -56,6 +97,66 @@ pub struct LoadSnapshotParams {
     /// is successful.
     #[serde(default)]
     pub resume_vm: bool,
+    /// When set to true, the guest clock is advanced by the time elapsed since the snapshot
+    /// was created, and the guest is told that its vCPUs were stopped.
+    #[serde(default)]
+    pub rebase_clock: bool,
+    /// When set to true, the load fails if the TSC frequency saved in the snapshot can't be
+    /// presented to the guest, instead of only logging a warning. This happens when the host
+    /// has another TSC frequency and can't scale the TSC of the guest.
+    #[serde(default)]
+    pub require_tsc_scaling: bool,
+    /// When set to true, the memory file is checked against the checksum saved in the
+    /// microVM state before it is loaded. This reads the whole memory file.
+    #[serde(default)]
+    pub verify_mem_checksum: bool,
+    /// The new paths of the backing files of the drives, by drive ID, for the drives
+    /// which were moved since the snapshot was created.
+    #[serde(default)]
+    pub drive_paths: HashMap<String, String>,
+    /// The number of vCPUs to run, when fewer than in the snapshot. The other vCPUs are
+    /// restored but never resumed, so the guest must have taken them offline before the
+    /// snapshot was created.
+    #[serde(default)]
+    pub vcpu_count: Option<u8>,
+    /// How the memory file is mapped into the guest memory. The default value is `Private`,
+    /// which leaves the memory file untouched.
+    #[serde(default = "MemoryMapping::default")]
+    pub mem_mapping: MemoryMapping,
+}
+
+/// Stores the configuration that will be used for inspecting a snapshot.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct InspectSnapshotParams {
+    /// Path to the file that contains the microVM state to be inspected.
+    pub snapshot_path: PathBuf,
+}
+
+/// Switches the dirty page tracking of a running microVM on or off.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DirtyPageTrackingConfig {
+    /// Whether the pages dirtied by the guest are tracked, as needed by the diff and live
+    /// snapshots.
+    pub enabled: bool,
+}
+
+/// Describes the resources needed by the microVM saved in a snapshot.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SnapshotInfo {
+    /// Number of vCPUs.
+    pub vcpu_count: usize,
+    /// Guest memory size, in MiB.
+    pub mem_size_mib: u64,
+    /// IDs of the block devices.
+    pub block_devices: Vec<String>,
+    /// IDs of the network interfaces.
+    pub net_devices: Vec<String>,
+    /// ID of the vsock device, if there is one.
+    pub vsock_device: Option<String>,
+    /// Whether there is a balloon device.
+    pub balloon_device: bool,
 }
 
 /// The microVM state options.
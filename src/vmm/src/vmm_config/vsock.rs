@@ -5,12 +5,19 @@ use std::convert::TryFrom;
 use std::fmt;
 use std::sync::{Arc, Mutex};
 
-use devices::virtio::{Vsock, VsockError, VsockUnixBackend, VsockUnixBackendError};
+use super::MAX_DEVICE_IRQS;
+use devices::virtio::{
+    Vsock, VsockError, VsockPeerAllowlist, VsockUnixBackend, VsockUnixBackendError,
+};
 
 use serde::{Deserialize, Serialize};
 
 type MutexVsockUnix = Arc<Mutex<Vsock<VsockUnixBackend>>>;
 
+// The CIDs below this one are reserved: 0 for the hypervisor, 1 for the local loopback and 2
+// for the host.
+const MIN_GUEST_CID: u32 = 3;
+
 /// Errors associated with `NetworkInterfaceConfig`.
 #[derive(Debug)]
 pub enum VsockConfigError {
@@ -18,6 +25,10 @@ pub enum VsockConfigError {
     CreateVsockBackend(VsockUnixBackendError),
     /// Failed to create the vsock device.
     CreateVsockDevice(VsockError),
+    /// The guest CID is one of the reserved CIDs.
+    InvalidGuestCid(u32),
+    /// There is no IRQ left for another device.
+    TooManyDevices,
 }
 
 impl fmt::Display for VsockConfigError {
@@ -28,6 +39,16 @@ impl fmt::Display for VsockConfigError {
                 write!(f, "Cannot create backend for vsock device: {:?}", e)
             }
             CreateVsockDevice(ref e) => write!(f, "Cannot create vsock device: {:?}", e),
+            InvalidGuestCid(cid) => write!(
+                f,
+                "Invalid guest CID {}: the CIDs below {} are reserved.",
+                cid, MIN_GUEST_CID
+            ),
+            TooManyDevices => write!(
+                f,
+                "Cannot attach another device: all the {} IRQs available to the devices are in use.",
+                MAX_DEVICE_IRQS
+            ),
         }
     }
 }
@@ -45,11 +66,16 @@ pub struct VsockDeviceConfig {
     pub guest_cid: u32,
     /// Path to local unix socket.
     pub uds_path: String,
+    /// The host-side processes allowed to initiate connections to the guest, by the
+    /// credentials of their end of the unix socket. All of them are allowed if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_allowlist: Option<VsockPeerAllowlist>,
 }
 
 struct VsockAndUnixPath {
     vsock: MutexVsockUnix,
     uds_path: String,
+    peer_allowlist: Option<VsockPeerAllowlist>,
 }
 
 impl From<&VsockAndUnixPath> for VsockDeviceConfig {
@@ -59,6 +85,7 @@ impl From<&VsockAndUnixPath> for VsockDeviceConfig {
             vsock_id: vsock_lock.id().to_string(),
             guest_cid: u32::try_from(vsock_lock.cid()).unwrap(),
             uds_path: vsock.uds_path.clone(),
+            peer_allowlist: vsock.peer_allowlist.clone(),
         }
     }
 }
@@ -78,6 +105,9 @@ impl VsockBuilder {
     /// Inserts a Unix backend Vsock in the store.
     /// If an entry already exists, it will overwrite it.
     pub fn insert(&mut self, cfg: VsockDeviceConfig) -> Result<()> {
+        if cfg.guest_cid < MIN_GUEST_CID {
+            return Err(VsockConfigError::InvalidGuestCid(cfg.guest_cid));
+        }
         // Make sure to drop the old one and remove the socket before creating a new one.
         if let Some(existing) = self.inner.take() {
             std::fs::remove_file(existing.uds_path)
@@ -86,6 +116,7 @@ impl VsockBuilder {
         }
         self.inner = Some(VsockAndUnixPath {
             uds_path: cfg.uds_path.clone(),
+            peer_allowlist: cfg.peer_allowlist.clone(),
             vsock: Arc::new(Mutex::new(Self::create_unixsock_vsock(cfg)?)),
         });
         Ok(())
@@ -98,8 +129,9 @@ impl VsockBuilder {
 
     /// Creates a Vsock device from a VsockDeviceConfig.
     pub fn create_unixsock_vsock(cfg: VsockDeviceConfig) -> Result<Vsock<VsockUnixBackend>> {
-        let backend = VsockUnixBackend::new(u64::from(cfg.guest_cid), cfg.uds_path)
-            .map_err(VsockConfigError::CreateVsockBackend)?;
+        let backend =
+            VsockUnixBackend::new(u64::from(cfg.guest_cid), cfg.uds_path, cfg.peer_allowlist)
+                .map_err(VsockConfigError::CreateVsockBackend)?;
 
         Vsock::new(u64::from(cfg.guest_cid), backend).map_err(VsockConfigError::CreateVsockDevice)
     }
@@ -120,6 +152,7 @@ pub(crate) mod tests {
             vsock_id: "vsock".to_string(),
             guest_cid: 3,
             uds_path: tmp_sock_file.as_path().to_str().unwrap().to_string(),
+            peer_allowlist: None,
         }
     }
 
@@ -149,6 +182,25 @@ pub(crate) mod tests {
         assert_eq!(vsock.lock().unwrap().cid(), new_cid as u64);
     }
 
+    #[test]
+    fn test_vsock_insert_reserved_cid() {
+        let mut store = VsockBuilder::new();
+        let mut tmp_sock_file = TempFile::new().unwrap();
+        tmp_sock_file.remove().unwrap();
+        let mut vsock_config = default_config(&tmp_sock_file);
+        store.insert(vsock_config.clone()).unwrap();
+
+        for cid in 0..MIN_GUEST_CID {
+            vsock_config.guest_cid = cid;
+            match store.insert(vsock_config.clone()) {
+                Err(VsockConfigError::InvalidGuestCid(err_cid)) => assert_eq!(err_cid, cid),
+                _ => panic!("The reserved CID {} should be rejected.", cid),
+            }
+        }
+        // The device configured before is kept.
+        assert_eq!(store.config().unwrap().guest_cid, MIN_GUEST_CID);
+    }
+
     #[test]
     fn test_vsock_config() {
         let mut vsock_builder = VsockBuilder::new();
@@ -160,6 +212,14 @@ pub(crate) mod tests {
         let config = vsock_builder.config();
         assert!(config.is_some());
         assert_eq!(config.unwrap(), vsock_config);
+
+        let mut vsock_config = default_config(&tmp_sock_file);
+        vsock_config.peer_allowlist = Some(VsockPeerAllowlist {
+            uids: vec![1000],
+            gids: vec![100],
+        });
+        vsock_builder.insert(vsock_config.clone()).unwrap();
+        assert_eq!(vsock_builder.config().unwrap(), vsock_config);
     }
 
     #[test]
@@ -175,5 +235,11 @@ pub(crate) mod tests {
             io::Error::from_raw_os_error(0),
         ));
         let _ = format!("{}{:?}", err, err);
+
+        let err = InvalidGuestCid(2);
+        assert_eq!(
+            err.to_string(),
+            "Invalid guest CID 2: the CIDs below 3 are reserved."
+        );
     }
 }
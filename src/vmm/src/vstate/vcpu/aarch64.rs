@@ -13,10 +13,14 @@ use std::{
 use crate::vstate::{vcpu::VcpuEmulation, vm::Vm};
 use kvm_ioctls::*;
 use logger::{error, IncMetric, METRICS};
+use serde::Serialize;
 use versionize::{VersionMap, Versionize, VersionizeResult};
 use versionize_derive::Versionize;
 use vm_memory::{Address, GuestAddress, GuestMemoryMmap};
 
+// Number of general purpose registers, x0-x30.
+const NR_GP_REGS: usize = 31;
+
 /// Errors associated with the wrappers over KVM ioctls.
 #[derive(Debug)]
 pub enum Error {
@@ -181,6 +185,38 @@ pub struct VcpuState {
     pub mpidr: u64,
 }
 
+impl VcpuState {
+    /// Provides the registers of the saved state which are reported for debugging.
+    pub fn registers(&self) -> VcpuRegisters {
+        // The core registers are saved first, in the order of `user_pt_regs`: x0-x30, sp, pc and
+        // pstate.
+        let core_reg = |index: usize| self.regs.get(index).map_or(0, |reg| reg.addr);
+        VcpuRegisters {
+            x: (0..NR_GP_REGS).map(core_reg).collect(),
+            sp: core_reg(NR_GP_REGS),
+            pc: core_reg(NR_GP_REGS + 1),
+            pstate: core_reg(NR_GP_REGS + 2),
+        }
+    }
+
+    /// Whether the guest had taken the vCPU offline when its state was saved, through the
+    /// PSCI `CPU_OFF` call which stops it.
+    pub fn is_offline(&self) -> bool {
+        self.mp_state.mp_state == kvm_bindings::KVM_MP_STATE_STOPPED
+    }
+}
+
+/// General purpose registers, stack pointer, program counter and processor state of a vCPU,
+/// reported to debug a guest.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct VcpuRegisters {
+    /// The general purpose registers x0-x30.
+    pub x: Vec<u64>,
+    pub sp: u64,
+    pub pc: u64,
+    pub pstate: u64,
+}
+
 #[cfg(test)]
 mod tests {
     use std::os::unix::io::AsRawFd;
@@ -194,7 +230,7 @@ mod tests {
         let (mut vm, vm_mem) = setup_vm(mem_size);
         let vcpu = KvmVcpu::new(0, &vm).unwrap();
         vcpu.init(vm.fd()).unwrap();
-        vm.setup_irqchip(1).unwrap();
+        vm.setup_irqchip(1, None).unwrap();
 
         (vm, vcpu, vm_mem)
     }
@@ -266,7 +302,7 @@ mod tests {
     fn test_vcpu_save_restore_state() {
         let (mut vm, _vm_mem) = setup_vm(0x1000);
         let vcpu = KvmVcpu::new(0, &vm).unwrap();
-        vm.setup_irqchip(1).unwrap();
+        vm.setup_irqchip(1, None).unwrap();
 
         // Calling KVM_GET_REGLIST before KVM_VCPU_INIT will result in error.
         let res = vcpu.save_state();
@@ -302,6 +338,11 @@ mod tests {
             id: 0x6030_0000_0010_003E,
             addr
         }));
+
+        // The reported registers are picked from the saved core registers.
+        let registers = state.registers();
+        assert_eq!(registers.x.len(), NR_GP_REGS);
+        assert_eq!(registers.sp, addr);
     }
 
     #[test]
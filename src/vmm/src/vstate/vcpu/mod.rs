@@ -12,15 +12,20 @@ use std::sync::{Arc, Barrier};
 use std::{
     cell::Cell,
     fmt::{Display, Formatter},
-    io, result,
+    io, mem,
+    os::unix::thread::JoinHandleExt,
+    result,
     sync::atomic::{fence, Ordering},
-    sync::mpsc::{channel, Receiver, Sender, TryRecvError},
+    sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError},
     thread,
+    time::Duration,
 };
 
 use crate::{
-    vmm_config::machine_config::CpuFeaturesTemplate, vstate::vm::Vm, FC_EXIT_CODE_GENERIC_ERROR,
-    FC_EXIT_CODE_OK,
+    seccomp_filters,
+    vmm_config::machine_config::{CpuFeaturesTemplate, InterruptController},
+    vstate::vm::Vm,
+    FC_EXIT_CODE_GENERIC_ERROR, FC_EXIT_CODE_OK,
 };
 use kvm_bindings::{KVM_SYSTEM_EVENT_RESET, KVM_SYSTEM_EVENT_SHUTDOWN};
 use kvm_ioctls::VcpuExit;
@@ -58,6 +63,8 @@ pub enum Error {
     UnhandledKvmExit(String),
     /// Wrapper over error triggered by some vcpu action.
     VcpuResponse(VcpuError),
+    /// Cannot set the host CPU affinity of the vCPU thread.
+    VcpuAffinity(io::Error),
     /// Cannot spawn a new vCPU thread.
     VcpuSpawn(io::Error),
     /// Cannot cleanly initialize vcpu TLS.
@@ -75,6 +82,7 @@ impl Display for Error {
             SignalVcpu(e) => write!(f, "Failed to signal vcpu: {}", e),
             UnhandledKvmExit(ref e) => write!(f, "Unexpected kvm exit received: {}", e),
             VcpuResponse(e) => write!(f, "Failed to run action on vcpu: {}", e),
+            VcpuAffinity(e) => write!(f, "Cannot set the vCPU thread affinity: {}", e),
             VcpuSpawn(e) => write!(f, "Cannot spawn a new vCPU thread: {}", e),
             VcpuTlsInit => write!(f, "Cannot clean init vcpu TLS"),
             VcpuTlsNotPresent => write!(f, "Vcpu not present in TLS"),
@@ -93,6 +101,8 @@ pub struct VcpuConfig {
     pub ht_enabled: bool,
     /// CPUID template to use.
     pub cpu_template: Option<CpuFeaturesTemplate>,
+    /// The interrupt controller exposed to the guest, the host default if not set.
+    pub interrupt_controller: Option<InterruptController>,
 }
 
 // Using this for easier explicit type-casting to help IDEs interpret the code.
@@ -241,9 +251,12 @@ impl Vcpu {
     ) -> Result<VcpuHandle> {
         let event_sender = self.event_sender.take().expect("vCPU already started");
         let response_receiver = self.response_receiver.take().unwrap();
+        let (finished_sender, finished_receiver) = channel::<()>();
         let vcpu_thread = thread::Builder::new()
             .name(format!("fc_vcpu {}", self.kvm_vcpu.index))
             .spawn(move || {
+                // Dropped when the thread ends, even if it panics, which disconnects the channel.
+                let _finished_sender = finished_sender;
                 let filter = &*seccomp_filter;
                 self.init_thread_local_data()
                     .expect("Cannot cleanly initialize vcpu TLS.");
@@ -256,6 +269,7 @@ impl Vcpu {
         Ok(VcpuHandle::new(
             event_sender,
             response_receiver,
+            finished_receiver,
             vcpu_thread,
         ))
     }
@@ -269,7 +283,7 @@ impl Vcpu {
         // Load seccomp filters for this vCPU thread.
         // Execution panics if filters cannot be loaded, use --no-seccomp if skipping filters
         // altogether is the desired behaviour.
-        if let Err(e) = seccompiler::apply_filter(seccomp_filter) {
+        if let Err(e) = seccomp_filters::apply_filter(seccomp_filter) {
             panic!(
                 "Failed to set the requested seccomp filters on vCPU {}: Error: {}",
                 self.kvm_vcpu.index, e
@@ -593,8 +607,11 @@ pub enum VcpuResponse {
 pub struct VcpuHandle {
     event_sender: Sender<VcpuEvent>,
     response_receiver: Receiver<VcpuResponse>,
+    // Disconnected once the vCPU thread has finished.
+    finished_receiver: Receiver<()>,
     // Rust JoinHandles have to be wrapped in Option if you ever plan on 'join()'ing them.
-    // We want to be able to join these threads in tests.
+    // We want to be able to join these threads in tests. It is only `None` when the handle is
+    // dropped after detaching the thread.
     vcpu_thread: Option<thread::JoinHandle<()>>,
 }
 
@@ -602,15 +619,32 @@ impl VcpuHandle {
     pub fn new(
         event_sender: Sender<VcpuEvent>,
         response_receiver: Receiver<VcpuResponse>,
+        finished_receiver: Receiver<()>,
         vcpu_thread: thread::JoinHandle<()>,
     ) -> Self {
         Self {
             event_sender,
             response_receiver,
+            finished_receiver,
             vcpu_thread: Some(vcpu_thread),
         }
     }
 
+    /// Waits up to `timeout` for the vCPU thread to finish, which it does after handling
+    /// `VcpuEvent::Finish`. Returns `false` if the thread is still running.
+    pub fn wait_finished(&self, timeout: Duration) -> bool {
+        matches!(
+            self.finished_receiver.recv_timeout(timeout),
+            Err(RecvTimeoutError::Disconnected)
+        )
+    }
+
+    /// Drops the handle without joining the vCPU thread, which keeps running until the process
+    /// exits.
+    pub fn detach(mut self) {
+        self.vcpu_thread = None;
+    }
+
     pub fn send_event(&self, event: VcpuEvent) -> Result<()> {
         // Use expect() to crash if the other thread closed this channel.
         self.event_sender
@@ -629,6 +663,30 @@ impl VcpuHandle {
     pub fn response_receiver(&self) -> &Receiver<VcpuResponse> {
         &self.response_receiver
     }
+
+    /// Restricts the vCPU thread to run only on the `host_cpus`.
+    pub fn set_affinity(&self, host_cpus: &[usize]) -> Result<()> {
+        // Safe because an all zeroes `cpu_set_t` is a valid empty set.
+        let mut cpu_set: libc::cpu_set_t = unsafe { mem::zeroed() };
+        for cpu in host_cpus {
+            // Safe because `cpu_set` is a valid set and out of range CPUs are ignored.
+            unsafe { libc::CPU_SET(*cpu, &mut cpu_set) };
+        }
+        let vcpu_thread = self
+            .vcpu_thread
+            .as_ref()
+            // Safe to unwrap since constructor make this 'Some'.
+            .unwrap()
+            .as_pthread_t();
+        // Safe because the thread is alive as long as its handle and we pass the right size.
+        let ret = unsafe {
+            libc::pthread_setaffinity_np(vcpu_thread, mem::size_of::<libc::cpu_set_t>(), &cpu_set)
+        };
+        if ret != 0 {
+            return Err(Error::VcpuAffinity(io::Error::from_raw_os_error(ret)));
+        }
+        Ok(())
+    }
 }
 
 // Wait for the Vcpu thread to finish execution
@@ -641,7 +699,9 @@ impl Drop for VcpuHandle {
         //
         // If the code hangs at this point, that means that a Finish event was not
         // sent by Vmm.
-        self.vcpu_thread.take().unwrap().join().unwrap();
+        if let Some(vcpu_thread) = self.vcpu_thread.take() {
+            vcpu_thread.join().unwrap();
+        }
     }
 }
 
@@ -854,7 +914,7 @@ mod tests {
         {
             vcpu = Vcpu::new(1, &vm, exit_evt).unwrap();
             vcpu.kvm_vcpu.init(vm.fd()).unwrap();
-            vm.setup_irqchip(1).unwrap();
+            vm.setup_irqchip(1, None).unwrap();
         }
         #[cfg(target_arch = "x86_64")]
         {
@@ -905,6 +965,7 @@ mod tests {
                 vcpu_count: 1,
                 ht_enabled: false,
                 cpu_template: None,
+                interrupt_controller: None,
             };
             vcpu.kvm_vcpu
                 .configure(
@@ -1103,6 +1164,24 @@ mod tests {
         vcpu_handle.send_event(VcpuEvent::Finish).unwrap();
     }
 
+    #[test]
+    fn test_vcpu_wait_finished() {
+        let (vcpu_handle, _vcpu_exit_evt) = vcpu_configured_for_boot();
+
+        // The paused vCPU keeps running until it is told to finish.
+        assert!(!vcpu_handle.wait_finished(Duration::from_millis(10)));
+        vcpu_handle.send_event(VcpuEvent::Finish).unwrap();
+        assert!(vcpu_handle.wait_finished(Duration::from_secs(1)));
+
+        // The thread has finished, so dropping the handle joins it right away.
+        drop(vcpu_handle);
+
+        // A detached thread isn't joined.
+        let (vcpu_handle, _vcpu_exit_evt) = vcpu_configured_for_boot();
+        vcpu_handle.send_event(VcpuEvent::Finish).unwrap();
+        vcpu_handle.detach();
+    }
+
     #[test]
     fn test_vcpu_rtsig_offset() {
         assert!(validate_signal_num(sigrtmin() + VCPU_RTSIG_OFFSET).is_ok());
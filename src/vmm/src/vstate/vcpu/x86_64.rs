@@ -10,18 +10,21 @@ use std::{
     result,
 };
 
-use crate::vmm_config::machine_config::CpuFeaturesTemplate;
+use crate::vmm_config::machine_config::{CpuFeaturesTemplate, InterruptController};
 use crate::vstate::{
     vcpu::{VcpuConfig, VcpuEmulation},
     vm::Vm,
 };
-use cpuid::{c3, filter_cpuid, t2, VmSpec};
+use cpuid::{c3, filter_cpuid, set_x2apic, t2, VmSpec};
 use kvm_bindings::{
     kvm_debugregs, kvm_lapic_state, kvm_mp_state, kvm_regs, kvm_sregs, kvm_vcpu_events, kvm_xcrs,
-    kvm_xsave, CpuId, MsrList, Msrs,
+    kvm_xsave, CpuId, MsrList, Msrs, KVMIO, KVM_MP_STATE_HALTED,
 };
 use kvm_ioctls::{VcpuExit, VcpuFd};
 use logger::{error, warn, IncMetric, METRICS};
+use serde::Serialize;
+use utils::ioctl::ioctl;
+use utils::{ioctl_expr, ioctl_io_nr, ioctl_ioc_nr};
 use versionize::{VersionMap, Versionize, VersionizeResult};
 use versionize_derive::Versionize;
 use vm_memory::{Address, GuestAddress, GuestMemoryMmap};
@@ -32,6 +35,12 @@ use vm_memory::{Address, GuestAddress, GuestMemoryMmap};
 // https://bugzilla.redhat.com/show_bug.cgi?id=1839095
 const TSC_KHZ_TOL: f64 = 250.0 / 1_000_000.0;
 
+// The interrupt enable flag of RFLAGS.
+const X86_EFLAGS_IF: u64 = 1 << 9;
+
+// Not exposed by `kvm_ioctls`.
+ioctl_io_nr!(KVM_KVMCLOCK_CTRL, KVMIO, 0xad);
+
 /// Errors associated with the wrappers over KVM ioctls.
 #[derive(Debug)]
 pub enum Error {
@@ -162,6 +171,8 @@ pub struct KvmVcpu {
     pub mmio_bus: Option<devices::Bus>,
 
     msr_list: MsrList,
+    /// Whether to tell the guest, after restoring the vCPU state, that the vCPU was stopped.
+    pub(crate) pvclock_stopped_hint: bool,
 }
 
 impl KvmVcpu {
@@ -180,6 +191,7 @@ impl KvmVcpu {
             pio_bus: None,
             mmio_bus: None,
             msr_list: vm.supported_msrs().clone(),
+            pvclock_stopped_hint: false,
         })
     }
 
@@ -221,6 +233,16 @@ impl KvmVcpu {
             }
         }
 
+        match vcpu_config.interrupt_controller {
+            Some(InterruptController::XApic) => {
+                set_x2apic(&mut cpuid, false).map_err(Error::CpuId)?
+            }
+            Some(InterruptController::X2Apic) => {
+                set_x2apic(&mut cpuid, true).map_err(Error::CpuId)?
+            }
+            _ => (),
+        }
+
         self.fd.set_cpuid2(&cpuid).map_err(Error::VcpuSetCpuid)?;
 
         arch::x86_64::msr::setup_msrs(&self.fd).map_err(Error::MSRSConfiguration)?;
@@ -388,9 +410,29 @@ impl KvmVcpu {
         self.fd
             .set_vcpu_events(&state.vcpu_events)
             .map_err(Error::VcpuSetVcpuEvents)?;
+        if self.pvclock_stopped_hint {
+            self.notify_guest_stopped();
+        }
         Ok(())
     }
 
+    /// Lets the guest know, through the kvmclock, that the vCPU was stopped, so that it
+    /// doesn't take the missing time for a soft lockup and resyncs its clocks faster.
+    ///
+    /// This is only a hint, so failures are logged without being reported.
+    fn notify_guest_stopped(&self) {
+        // Safe because we know that our file is a vCPU fd and we verify the return result.
+        let ret = unsafe { ioctl(&self.fd, KVM_KVMCLOCK_CTRL()) };
+        if ret < 0 {
+            // Fails with `EINVAL` if the guest doesn't use the kvmclock.
+            warn!(
+                "Failed to notify the guest that vcpu {} was stopped: {}",
+                self.index,
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+
     /// Runs the vCPU in KVM context and handles the kvm exit reason.
     ///
     /// Returns error or enum specifying whether emulation was handled or interrupted.
@@ -460,6 +502,70 @@ impl VcpuState {
 
         Ok(())
     }
+
+    /// Provides the registers of the saved state which are reported for debugging.
+    pub fn registers(&self) -> VcpuRegisters {
+        VcpuRegisters {
+            rax: self.regs.rax,
+            rbx: self.regs.rbx,
+            rcx: self.regs.rcx,
+            rdx: self.regs.rdx,
+            rsi: self.regs.rsi,
+            rdi: self.regs.rdi,
+            rsp: self.regs.rsp,
+            rbp: self.regs.rbp,
+            r8: self.regs.r8,
+            r9: self.regs.r9,
+            r10: self.regs.r10,
+            r11: self.regs.r11,
+            r12: self.regs.r12,
+            r13: self.regs.r13,
+            r14: self.regs.r14,
+            r15: self.regs.r15,
+            rip: self.regs.rip,
+            rflags: self.regs.rflags,
+            cr0: self.sregs.cr0,
+            cr2: self.sregs.cr2,
+            cr3: self.sregs.cr3,
+            cr4: self.sregs.cr4,
+            efer: self.sregs.efer,
+        }
+    }
+
+    /// Whether the guest had taken the vCPU offline when its state was saved. An offline vCPU
+    /// halts with its interrupts disabled, while an idle one halts with them enabled.
+    pub fn is_offline(&self) -> bool {
+        self.mp_state.mp_state == KVM_MP_STATE_HALTED && self.regs.rflags & X86_EFLAGS_IF == 0
+    }
+}
+
+/// General purpose, instruction pointer, flags and control registers of a vCPU, reported to
+/// debug a guest.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct VcpuRegisters {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rsp: u64,
+    pub rbp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rip: u64,
+    pub rflags: u64,
+    pub cr0: u64,
+    pub cr2: u64,
+    pub cr3: u64,
+    pub cr4: u64,
+    pub efer: u64,
 }
 
 #[cfg(test)]
@@ -506,6 +612,7 @@ mod tests {
             vcpu_count: 1,
             ht_enabled: false,
             cpu_template: None,
+            interrupt_controller: None,
         };
 
         assert!(vcpu
@@ -563,6 +670,46 @@ mod tests {
         assert!(vcpu.save_state().unwrap().cpuid.as_slice()[0].eax == 0x1234_5678);
     }
 
+    #[test]
+    fn test_vcpu_registers() {
+        let (vm, mut vcpu, vm_mem) = setup_vcpu(0x10000);
+        let vcpu_config = VcpuConfig {
+            vcpu_count: 1,
+            ht_enabled: false,
+            cpu_template: None,
+            interrupt_controller: None,
+        };
+        vcpu.configure(
+            &vm_mem,
+            GuestAddress(0x1000),
+            &vcpu_config,
+            vm.supported_cpuid().clone(),
+        )
+        .unwrap();
+
+        let state = vcpu.save_state().unwrap();
+        let registers = state.registers();
+        assert_eq!(registers.rip, 0x1000);
+        assert_eq!(registers.rsp, state.regs.rsp);
+        assert_eq!(registers.rflags, state.regs.rflags);
+        assert_eq!(registers.cr0, state.sregs.cr0);
+        assert_eq!(registers.efer, state.sregs.efer);
+    }
+
+    #[test]
+    fn test_is_offline() {
+        let mut state = VcpuState::default();
+        assert!(!state.is_offline());
+
+        // An idle vCPU, waiting for interrupts.
+        state.mp_state.mp_state = KVM_MP_STATE_HALTED;
+        state.regs.rflags = X86_EFLAGS_IF;
+        assert!(!state.is_offline());
+
+        state.regs.rflags = 0;
+        assert!(state.is_offline());
+    }
+
     #[test]
     fn test_is_tsc_scaling_required() {
         // Test `is_tsc_scaling_required` as if it were on the same
@@ -13,6 +13,8 @@ use std::{
 #[cfg(target_arch = "aarch64")]
 use arch::aarch64::gic::GICDevice;
 #[cfg(target_arch = "aarch64")]
+use arch::aarch64::gic::GICVersion;
+#[cfg(target_arch = "aarch64")]
 use arch::aarch64::gic::GicState;
 #[cfg(target_arch = "x86_64")]
 use kvm_bindings::{
@@ -22,6 +24,10 @@ use kvm_bindings::{
 };
 use kvm_bindings::{kvm_userspace_memory_region, KVM_MEM_LOG_DIRTY_PAGES};
 use kvm_ioctls::{Kvm, VmFd};
+#[cfg(target_arch = "x86_64")]
+use logger::warn;
+#[cfg(target_arch = "x86_64")]
+use utils::time::{get_time_ns, ClockType};
 use versionize::{VersionMap, Versionize, VersionizeResult};
 use versionize_derive::Versionize;
 use vm_memory::{Address, GuestMemory, GuestMemoryMmap, GuestMemoryRegion};
@@ -190,11 +196,12 @@ impl Vm {
         self.fd.create_pit2(pit_config).map_err(Error::VmSetup)
     }
 
-    /// Creates the GIC (Global Interrupt Controller).
+    /// Creates the GIC (Global Interrupt Controller) of the given version, or of the newest
+    /// version supported by the host if not specified.
     #[cfg(target_arch = "aarch64")]
-    pub fn setup_irqchip(&mut self, vcpu_count: u8) -> Result<()> {
+    pub fn setup_irqchip(&mut self, vcpu_count: u8, version: Option<GICVersion>) -> Result<()> {
         self.irqchip_handle = Some(
-            arch::aarch64::gic::create_gic(&self.fd, vcpu_count.into(), None)
+            arch::aarch64::gic::create_gic(&self.fd, vcpu_count.into(), version)
                 .map_err(Error::VmCreateGIC)?,
         );
         Ok(())
@@ -253,6 +260,7 @@ impl Vm {
             pic_master,
             pic_slave,
             ioapic,
+            realtime_ns: get_time_ns(ClockType::Real),
         })
     }
 
@@ -275,6 +283,20 @@ impl Vm {
         Ok(())
     }
 
+    #[cfg(target_arch = "x86_64")]
+    /// Sets the KVM clock to the one in `state`, advanced by the host time elapsed since `state`
+    /// was saved.
+    pub fn rebase_clock(&self, state: &VmState) -> Result<()> {
+        if state.realtime_ns == 0 {
+            warn!("The VM state does not record when it was saved. The clock is not rebased.");
+            return Ok(());
+        }
+
+        let mut clock = state.clock;
+        clock.clock += get_time_ns(ClockType::Real).saturating_sub(state.realtime_ns);
+        self.fd.set_clock(&clock).map_err(Error::VmSetClock)
+    }
+
     #[cfg(target_arch = "aarch64")]
     pub fn save_state(&self, mpidrs: &[u64]) -> Result<VmState> {
         Ok(VmState {
@@ -332,6 +354,15 @@ pub struct VmState {
     // TODO: rename this field to adopt inclusive language once Linux updates it, too.
     pic_slave: kvm_irqchip,
     ioapic: kvm_irqchip,
+    #[version(start = 2, default_fn = "default_realtime_ns")]
+    realtime_ns: u64,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl VmState {
+    fn default_realtime_ns(_source_version: u16) -> u64 {
+        0
+    }
 }
 
 /// Structure holding an general specific VM state.
@@ -425,6 +456,29 @@ pub(crate) mod tests {
         assert!(vm.restore_state(&vm_state).is_ok());
     }
 
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_vm_rebase_clock() {
+        let (vm, _mem) = setup_vm(0x1000);
+        vm.setup_irqchip().unwrap();
+        let mut vm_state = vm.save_state().unwrap();
+
+        // Pretend the state was saved 10 seconds ago.
+        let offset_ns = 10_000_000_000;
+        vm_state.realtime_ns -= offset_ns;
+
+        let (vm, _mem) = setup_vm(0x1000);
+        vm.setup_irqchip().unwrap();
+        vm.restore_state(&vm_state).unwrap();
+        vm.rebase_clock(&vm_state).unwrap();
+        assert!(vm.fd().get_clock().unwrap().clock >= vm_state.clock.clock + offset_ns);
+
+        // Without the saving time, the clock is left unchanged.
+        vm_state.realtime_ns = 0;
+        vm.rebase_clock(&vm_state).unwrap();
+        assert!(vm.fd().get_clock().unwrap().clock < vm_state.clock.clock + offset_ns);
+    }
+
     #[test]
     fn test_set_kvm_memory_regions() {
         let kvm_context = KvmContext::new().unwrap();
@@ -1,14 +1,21 @@
 // Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
+use std::fs::OpenOptions;
 use std::io;
 use std::io::{Seek, SeekFrom};
+use std::os::unix::io::IntoRawFd;
+use std::path::PathBuf;
 use std::thread;
 use std::time::Duration;
 
+use seccompiler::BpfThreadMap;
 use snapshot::Snapshot;
 use utils::tempfile::TempFile;
 use vmm::builder::{build_microvm_for_boot, build_microvm_from_snapshot, setup_serial_device};
-use vmm::persist::{self, snapshot_state_sanity_check, LoadSnapshotError, MicrovmState};
+use vmm::persist::{
+    self, snapshot_state_sanity_check, CreateSnapshotError, LoadSnapshotError, MicrovmState,
+};
+use vmm::recent_errors::RecentErrors;
 use vmm::resources::VmResources;
 use vmm::seccomp_filters::{get_filters, SeccompConfig};
 use vmm::version_map::VERSION_MAP;
@@ -16,11 +23,15 @@ use vmm::vmm_config::snapshot::{CreateSnapshotParams, SnapshotType};
 use vmm::{EventManager, FC_EXIT_CODE_OK};
 
 use vmm::utilities::mock_devices::MockSerialInput;
-use vmm::utilities::mock_resources::{MockVmResources, NOISY_KERNEL_IMAGE};
+use vmm::utilities::mock_resources::{
+    MockBootSourceConfig, MockVmConfig, MockVmResources, NOISY_KERNEL_IMAGE,
+};
 #[cfg(target_arch = "x86_64")]
 use vmm::utilities::test_utils::dirty_tracking_vmm;
 use vmm::utilities::test_utils::{create_vmm, default_vmm};
-use vmm::vmm_config::instance_info::InstanceInfo;
+use vmm::vmm_config::instance_info::{InstanceInfo, VmState};
+use vmm::vmm_config::resource_limits::ResourceLimits;
+use vmm::vmm_config::serial::SerialConfig;
 
 #[test]
 fn test_setup_serial_device() {
@@ -30,10 +41,13 @@ fn test_setup_serial_device() {
 
     assert!(setup_serial_device(
         &mut event_manager,
-        Box::new(read_handle),
+        Some(Box::new(read_handle)),
         Box::new(io::stdout()),
     )
     .is_ok());
+
+    // Output-only serial console.
+    assert!(setup_serial_device(&mut event_manager, None, Box::new(io::stdout())).is_ok());
 }
 
 #[test]
@@ -53,6 +67,27 @@ fn test_build_microvm() {
         assert_eq!(format!("{:?}", vmm_ret.err()), "Some(MissingKernelConfig)");
     }
 
+    // Error case: restart on guest reboot with the default seccomp filters.
+    {
+        let resources: VmResources = MockVmResources::new()
+            .with_boot_source(MockBootSourceConfig::new().with_default_boot_args().into())
+            .with_vm_config(MockVmConfig::new().with_restart_on_reboot().into())
+            .into();
+        let mut event_manager = EventManager::new().unwrap();
+        let seccomp_filters = get_filters(SeccompConfig::Advanced).unwrap();
+
+        let vmm_ret = build_microvm_for_boot(
+            &InstanceInfo::default(),
+            &resources,
+            &mut event_manager,
+            &seccomp_filters,
+        );
+        assert_eq!(
+            format!("{:?}", vmm_ret.err()),
+            "Some(RestartWithSeccompFilter)"
+        );
+    }
+
     // Success case.
     let (vmm, mut _evmgr) = default_vmm(None);
 
@@ -84,6 +119,30 @@ fn test_pause_resume_microvm() {
     vmm.lock().unwrap().stop(FC_EXIT_CODE_OK);
 }
 
+#[test]
+fn test_start_paused_microvm() {
+    let mut event_manager = EventManager::new().unwrap();
+    let empty_seccomp_filters = get_filters(SeccompConfig::None).unwrap();
+    let mut resources: VmResources = MockVmResources::new()
+        .with_boot_source(MockBootSourceConfig::new().with_default_boot_args().into())
+        .into();
+    resources.start_paused = true;
+
+    let vmm = build_microvm_for_boot(
+        &InstanceInfo::default(),
+        &resources,
+        &mut event_manager,
+        &empty_seccomp_filters,
+    )
+    .unwrap();
+    // The vCPUs are left paused until the microVM is resumed.
+    assert_eq!(vmm.lock().unwrap().instance_info().state, VmState::Paused);
+
+    vmm.lock().unwrap().resume_vm().unwrap();
+    assert_eq!(vmm.lock().unwrap().instance_info().state, VmState::Running);
+    vmm.lock().unwrap().stop(FC_EXIT_CODE_OK);
+}
+
 #[test]
 fn test_dirty_bitmap_error() {
     // Error case: dirty tracking disabled.
@@ -141,6 +200,39 @@ fn test_disallow_snapshots_without_pausing() {
     vmm.lock().unwrap().stop(FC_EXIT_CODE_OK);
 }
 
+#[test]
+fn test_create_snapshot_error_keeps_files() {
+    let snapshot_file = TempFile::new().unwrap();
+    let memory_file = TempFile::new().unwrap();
+    memory_file.as_file().set_len(4096).unwrap();
+
+    let (vmm, _) = default_vmm(Some(NOISY_KERNEL_IMAGE));
+
+    // The state of a running microVM can't be saved, so the snapshot creation fails before the
+    // memory file is opened.
+    let snapshot_params = CreateSnapshotParams {
+        snapshot_type: SnapshotType::Full,
+        snapshot_path: snapshot_file.as_path().to_path_buf(),
+        snapshot_fd: None,
+        mem_file_path: memory_file.as_path().to_path_buf(),
+        mem_file_fd: None,
+        version: None,
+        live: false,
+    };
+    {
+        let mut locked_vmm = vmm.lock().unwrap();
+        match persist::create_snapshot(&mut locked_vmm, &snapshot_params, VERSION_MAP.clone()) {
+            Err(CreateSnapshotError::MicrovmState(_)) => (),
+            _ => panic!("Should not be allowed."),
+        }
+    }
+    vmm.lock().unwrap().stop(FC_EXIT_CODE_OK);
+
+    // The files already at the snapshot paths are left untouched.
+    assert!(snapshot_file.as_path().exists());
+    assert_eq!(memory_file.as_file().metadata().unwrap().len(), 4096);
+}
+
 fn verify_create_snapshot(is_diff: bool) -> (TempFile, TempFile) {
     let snapshot_file = TempFile::new().unwrap();
     let memory_file = TempFile::new().unwrap();
@@ -161,8 +253,11 @@ fn verify_create_snapshot(is_diff: bool) -> (TempFile, TempFile) {
     let snapshot_params = CreateSnapshotParams {
         snapshot_type,
         snapshot_path: snapshot_file.as_path().to_path_buf(),
+        snapshot_fd: None,
         mem_file_path: memory_file.as_path().to_path_buf(),
+        mem_file_fd: None,
         version: Some(String::from("0.24.0")),
+        live: false,
     };
 
     {
@@ -201,11 +296,20 @@ fn verify_create_snapshot(is_diff: bool) -> (TempFile, TempFile) {
 }
 
 fn verify_load_snapshot(snapshot_file: TempFile, memory_file: TempFile) {
+    let empty_seccomp_filters = get_filters(SeccompConfig::None).unwrap();
+    load_snapshot(snapshot_file, memory_file, false, &empty_seccomp_filters);
+}
+
+fn load_snapshot(
+    snapshot_file: TempFile,
+    memory_file: TempFile,
+    rebase_clock: bool,
+    seccomp_filters: &BpfThreadMap,
+) {
     use vm_memory::GuestMemoryMmap;
     use vmm::memory_snapshot::SnapshotMemory;
 
     let mut event_manager = EventManager::new().unwrap();
-    let mut empty_seccomp_filters = get_filters(SeccompConfig::None).unwrap();
 
     // Deserialize microVM state.
     let snapshot_file_metadata = snapshot_file.as_file().metadata().unwrap();
@@ -217,8 +321,13 @@ fn verify_load_snapshot(snapshot_file: TempFile, memory_file: TempFile) {
         VERSION_MAP.clone(),
     )
     .unwrap();
-    let mem = GuestMemoryMmap::restore(memory_file.as_file(), &microvm_state.memory_state, false)
-        .unwrap();
+    let mem = GuestMemoryMmap::restore(
+        memory_file.as_file(),
+        &microvm_state.memory_state,
+        false,
+        false,
+    )
+    .unwrap();
 
     // Build microVM from state.
     let vmm = build_microvm_from_snapshot(
@@ -227,7 +336,12 @@ fn verify_load_snapshot(snapshot_file: TempFile, memory_file: TempFile) {
         microvm_state,
         mem,
         false,
-        &mut empty_seccomp_filters,
+        rebase_clock,
+        false,
+        seccomp_filters,
+        SerialConfig::default(),
+        ResourceLimits::default(),
+        RecentErrors::default(),
     )
     .unwrap();
     // For now we're happy we got this far, we don't test what the guest is actually doing.
@@ -253,6 +367,114 @@ fn test_create_and_load_snapshot() {
     verify_load_snapshot(snapshot_file, memory_file);
 }
 
+#[test]
+fn test_create_snapshot_to_file_descriptors() {
+    let snapshot_file = TempFile::new().unwrap();
+    let memory_file = TempFile::new().unwrap();
+    // The files passed as descriptors are truncated.
+    snapshot_file.as_file().set_len(1 << 30).unwrap();
+    let open_fd = |file: &TempFile| {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(file.as_path())
+            .unwrap()
+            .into_raw_fd()
+    };
+
+    let (vmm, _) = create_vmm(Some(NOISY_KERNEL_IMAGE), false);
+    vmm.lock().unwrap().pause_vm().unwrap();
+
+    let snapshot_params = CreateSnapshotParams {
+        snapshot_type: SnapshotType::Full,
+        snapshot_path: PathBuf::new(),
+        snapshot_fd: Some(open_fd(&snapshot_file)),
+        mem_file_path: PathBuf::new(),
+        mem_file_fd: Some(open_fd(&memory_file)),
+        version: None,
+        live: false,
+    };
+    {
+        let mut locked_vmm = vmm.lock().unwrap();
+        persist::create_snapshot(&mut locked_vmm, &snapshot_params, VERSION_MAP.clone()).unwrap();
+    }
+    vmm.lock().unwrap().stop(FC_EXIT_CODE_OK);
+
+    assert!(snapshot_file.as_file().metadata().unwrap().len() < 1 << 30);
+    verify_load_snapshot(snapshot_file, memory_file);
+}
+
+#[test]
+fn test_create_live_snapshot() {
+    let snapshot_file = TempFile::new().unwrap();
+    let memory_file = TempFile::new().unwrap();
+
+    // Live snapshots need the dirty page tracking.
+    let (vmm, _) = create_vmm(Some(NOISY_KERNEL_IMAGE), true);
+
+    // Be sure that the microVM is running.
+    thread::sleep(Duration::from_millis(200));
+
+    // Create the snapshot without pausing the microVM.
+    let snapshot_params = CreateSnapshotParams {
+        snapshot_type: SnapshotType::Full,
+        snapshot_path: snapshot_file.as_path().to_path_buf(),
+        snapshot_fd: None,
+        mem_file_path: memory_file.as_path().to_path_buf(),
+        mem_file_fd: None,
+        version: None,
+        live: true,
+    };
+    {
+        let mut locked_vmm = vmm.lock().unwrap();
+        persist::create_snapshot(&mut locked_vmm, &snapshot_params, VERSION_MAP.clone()).unwrap();
+        // The microVM is resumed once the snapshot is created.
+        assert_eq!(locked_vmm.instance_info().state, VmState::Running);
+    }
+    vmm.lock().unwrap().stop(FC_EXIT_CODE_OK);
+
+    let memory_file_size_mib = memory_file.as_file().metadata().unwrap().len() >> 20;
+    let snapshot_len = snapshot_file.as_file().metadata().unwrap().len() as usize;
+    let microvm_state: MicrovmState = Snapshot::load(
+        &mut snapshot_file.as_file(),
+        snapshot_len,
+        VERSION_MAP.clone(),
+    )
+    .unwrap();
+    assert_eq!(microvm_state.vm_info.mem_size_mib, memory_file_size_mib);
+    // The memory changes while it's copied, so it has no checksum.
+    assert!(microvm_state.vm_info.mem_checksum.is_none());
+
+    verify_load_snapshot(snapshot_file, memory_file);
+}
+
+#[test]
+fn test_create_live_snapshot_of_paused_microvm() {
+    let snapshot_file = TempFile::new().unwrap();
+    let memory_file = TempFile::new().unwrap();
+
+    let (vmm, _) = create_vmm(Some(NOISY_KERNEL_IMAGE), true);
+    thread::sleep(Duration::from_millis(200));
+    vmm.lock().unwrap().pause_vm().unwrap();
+
+    let snapshot_params = CreateSnapshotParams {
+        snapshot_type: SnapshotType::Full,
+        snapshot_path: snapshot_file.as_path().to_path_buf(),
+        snapshot_fd: None,
+        mem_file_path: memory_file.as_path().to_path_buf(),
+        mem_file_fd: None,
+        version: None,
+        live: true,
+    };
+    {
+        let mut locked_vmm = vmm.lock().unwrap();
+        persist::create_snapshot(&mut locked_vmm, &snapshot_params, VERSION_MAP.clone()).unwrap();
+        // A microVM paused before the snapshot stays paused.
+        assert_eq!(locked_vmm.instance_info().state, VmState::Paused);
+    }
+    vmm.lock().unwrap().stop(FC_EXIT_CODE_OK);
+}
+
 #[test]
 fn test_snapshot_load_sanity_checks() {
     use vmm::vmm_config::machine_config::MAX_SUPPORTED_VCPUS;
@@ -445,3 +667,69 @@ fn test_snapshot_cpu_vendor_mismatch() {
     }
     assert!(validate_cpu_manufacturer_id(&microvm_state).is_err());
 }
+
+#[test]
+fn test_net_offload_with_default_seccomp_filters() {
+    use devices::virtio::net::Net;
+    use devices::virtio::VirtioDevice;
+    use rate_limiter::RateLimiter;
+    use vm_memory::{GuestAddress, GuestMemoryMmap};
+
+    let filters = get_filters(SeccompConfig::Advanced).unwrap();
+    let mut net = Net::new_with_tap(
+        "net-seccomp".to_string(),
+        "net-seccomp".to_string(),
+        None,
+        RateLimiter::default(),
+        RateLimiter::default(),
+        false,
+        true,
+    )
+    .unwrap();
+    let features = net.avail_features();
+    net.ack_features_by_page(0, features as u32);
+    net.ack_features_by_page(1, (features >> 32) as u32);
+    let mem = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), 0x10000)]).unwrap();
+
+    // The device is activated on a vcpu thread, when the guest driver sets the DRIVER_OK status
+    // bit. Setting the tap offload flags must not break the vcpu seccomp filter.
+    let vcpu_thread = thread::spawn(move || {
+        seccompiler::apply_filter(filters.get("vcpu").unwrap()).unwrap();
+        net.activate(mem).is_ok()
+    });
+    assert!(vcpu_thread.join().unwrap());
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn test_load_snapshot_rebase_clock_with_default_seccomp_filters() {
+    let (snapshot_file, memory_file) = verify_create_snapshot(false);
+    let filters = get_filters(SeccompConfig::Advanced).unwrap();
+
+    // Restoring the vcpu state issues KVM_KVMCLOCK_CTRL on the vcpu threads, which run under the
+    // default vcpu filter. The VMM filter gets installed on the calling thread, so keep it off the
+    // test harness thread.
+    let vmm_thread = thread::spawn(move || {
+        load_snapshot(snapshot_file, memory_file, true, &filters);
+    });
+    vmm_thread.join().unwrap();
+}
+
+#[test]
+fn test_set_dirty_page_tracking_with_default_seccomp_filters() {
+    let (vmm, _) = create_vmm(Some(NOISY_KERNEL_IMAGE), true);
+    let filters = get_filters(SeccompConfig::Advanced).unwrap();
+
+    // The dirty page tracking is toggled on the VMM thread, which runs under the default VMM
+    // filter.
+    let vmm_clone = vmm.clone();
+    let vmm_thread = thread::spawn(move || {
+        seccompiler::apply_filter(filters.get("vmm").unwrap()).unwrap();
+        let mut locked_vmm = vmm_clone.lock().unwrap();
+        locked_vmm.set_dirty_page_tracking(false).is_ok()
+            && locked_vmm.set_dirty_page_tracking(true).is_ok()
+    });
+    assert!(vmm_thread.join().unwrap());
+
+    vmm.lock().unwrap().stop(FC_EXIT_CODE_OK);
+}